@@ -0,0 +1,21 @@
+//! Generates `awwasm.h` in `OUT_DIR` from the `extern "C"` surface.
+
+use std::env;
+use std::path::PathBuf;
+
+fn main() {
+    let crate_dir = PathBuf::from(env::var("CARGO_MANIFEST_DIR").unwrap());
+    let out_dir = PathBuf::from(env::var("OUT_DIR").unwrap());
+    println!("cargo:rerun-if-changed=src/lib.rs");
+    println!("cargo:rerun-if-changed=cbindgen.toml");
+
+    let config = cbindgen::Config::from_file(crate_dir.join("cbindgen.toml")).expect("valid cbindgen.toml");
+    match cbindgen::generate_with_config(&crate_dir, config) {
+        Ok(bindings) => {
+            bindings.write_to_file(out_dir.join("awwasm.h"));
+        }
+        // A half-edited lib.rs should fail in rustc with a real error,
+        // not here.
+        Err(e) => println!("cargo:warning=awwasm.h not regenerated: {}", e),
+    }
+}
@@ -0,0 +1,482 @@
+//! C API for the AwWasm runtime.
+//!
+//! A store is an opaque `AwwasmCStore *` owning everything instantiated
+//! into it, including a copy of each module's bytes. Modules are named by
+//! the `uint32_t` address `awwasm_store_instantiate` returns. Host
+//! functions are imported by id and all run through one callback per
+//! store.
+//!
+//! Fallible functions return `0` on success or the runtime's stable error
+//! code (`AwwasmInstantiationError::code`, `AwwasmRuntimeError::code`,
+//! `AwwasmTrap::code`); `awwasm_store_last_error` has the message. The
+//! build script generates the header as `awwasm.h` in Cargo's `OUT_DIR`.
+//!
+//! The runtime itself has no `unsafe`; this crate is the one place raw
+//! pointers from C become references, and each `unsafe` block names
+//! what the caller promised.
+
+use core::ffi::{c_char, c_void, CStr};
+use core::fmt::Display;
+use core::ptr;
+use core::slice;
+
+use awwasm_runtime::func::AwwasmFuncInst;
+use awwasm_runtime::memory::AwwasmMemInst;
+use awwasm_runtime::values::{AwwasmModuleAddr, AwwasmValueType};
+use awwasm_runtime::{AwwasmHost, AwwasmImports, AwwasmRuntimeError, AwwasmStore, AwwasmTrap, AwwasmValue};
+
+/// Error code for arguments the C caller got wrong (null pointers,
+/// invalid UTF-8, unsupported value kinds).
+pub const AWWASM_ERR_INVALID_ARGUMENT: u32 = 1;
+
+/// Most results a host function may return.
+pub const AWWASM_MAX_HOST_RESULTS: usize = 16;
+
+/// Kind tag of an `AwwasmCValue`, stored in its `kind` byte.
+#[repr(u8)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AwwasmCValueKind {
+    I32 = 0,
+    I64 = 1,
+    F32 = 2,
+    F64 = 3,
+}
+
+impl TryFrom<u8> for AwwasmCValueKind {
+    /// The unknown kind byte.
+    type Error = u8;
+
+    fn try_from(kind: u8) -> Result<Self, u8> {
+        match kind {
+            0 => Ok(AwwasmCValueKind::I32),
+            1 => Ok(AwwasmCValueKind::I64),
+            2 => Ok(AwwasmCValueKind::F32),
+            3 => Ok(AwwasmCValueKind::F64),
+            _ => Err(kind),
+        }
+    }
+}
+
+/// Payload of an `AwwasmCValue`, selected by its `kind`.
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub union AwwasmCValuePayload {
+    pub i32: i32,
+    pub i64: i64,
+    pub f32: f32,
+    pub f64: f64,
+}
+
+/// A WebAssembly number value.
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub struct AwwasmCValue {
+    /// An `AwwasmCValueKind`. A plain byte, since C may store any value
+    /// here; unknown kinds are rejected when the value is read.
+    pub kind: u8,
+    pub of: AwwasmCValuePayload,
+}
+
+impl AwwasmCValue {
+    /// The value, or the kind byte if it names no `AwwasmCValueKind`.
+    fn to_value(self) -> Result<AwwasmValue, u8> {
+        let kind = AwwasmCValueKind::try_from(self.kind)?;
+        // SAFETY: `kind` says which payload field the C side wrote.
+        unsafe {
+            Ok(match kind {
+                AwwasmCValueKind::I32 => AwwasmValue::I32(self.of.i32),
+                AwwasmCValueKind::I64 => AwwasmValue::I64(self.of.i64),
+                AwwasmCValueKind::F32 => AwwasmValue::F32(self.of.f32),
+                AwwasmCValueKind::F64 => AwwasmValue::F64(self.of.f64),
+            })
+        }
+    }
+
+    fn from_value(value: AwwasmValue) -> Option<Self> {
+        let (kind, of) = match value {
+            AwwasmValue::I32(v) => (AwwasmCValueKind::I32, AwwasmCValuePayload { i32: v }),
+            AwwasmValue::I64(v) => (AwwasmCValueKind::I64, AwwasmCValuePayload { i64: v }),
+            AwwasmValue::F32(v) => (AwwasmCValueKind::F32, AwwasmCValuePayload { f32: v }),
+            AwwasmValue::F64(v) => (AwwasmCValueKind::F64, AwwasmCValuePayload { f64: v }),
+            _ => return None,
+        };
+        Some(Self { kind: kind as u8, of })
+    }
+}
+
+/// Host function callback.
+///
+/// Called with the store's `env`, the id the function was defined with,
+/// its arguments and the caller's default memory (null and 0 if it has
+/// none, or it is shared). Writes up to `AWWASM_MAX_HOST_RESULTS` values to `results` and
+/// returns how many, or a negative number to trap.
+pub type AwwasmCHostFunc = extern "C" fn(
+    env: *mut c_void,
+    host_func_id: u32,
+    args: *const AwwasmCValue,
+    nargs: usize,
+    results: *mut AwwasmCValue,
+    memory: *mut u8,
+    memory_len: usize,
+) -> i32;
+
+/// Runs host functions through the registered C callback.
+struct CHost {
+    callback: Option<AwwasmCHostFunc>,
+    env: *mut c_void,
+}
+
+impl AwwasmHost for CHost {
+    fn call(
+        &mut self,
+        host_func_id: u32,
+        args: &[AwwasmValue],
+        mem: Option<&mut AwwasmMemInst>,
+    ) -> Result<Vec<AwwasmValue>, AwwasmTrap> {
+        let callback = self.callback.ok_or(AwwasmTrap::InvalidHostCall { host_func_id })?;
+        let args: Vec<AwwasmCValue> = args
+            .iter()
+            .map(|&v| AwwasmCValue::from_value(v))
+            .collect::<Option<_>>()
+            .ok_or(AwwasmTrap::InvalidHostCall { host_func_id })?;
+        let (memory, memory_len) = match mem.and_then(|mem| mem.data.as_mut_slice()) {
+            Some(bytes) => (bytes.as_mut_ptr(), bytes.len()),
+            None => (ptr::null_mut(), 0),
+        };
+        let zero = AwwasmCValue { kind: AwwasmCValueKind::I32 as u8, of: AwwasmCValuePayload { i64: 0 } };
+        let mut results = [zero; AWWASM_MAX_HOST_RESULTS];
+        let n = callback(self.env, host_func_id, args.as_ptr(), args.len(), results.as_mut_ptr(), memory, memory_len);
+        if n < 0 || n as usize > AWWASM_MAX_HOST_RESULTS {
+            return Err(AwwasmTrap::host("host function trapped"));
+        }
+        results[..n as usize]
+            .iter()
+            .map(|v| v.to_value())
+            .collect::<Result<_, _>>()
+            .map_err(|kind| AwwasmTrap::host(format!("host function returned a value of unknown kind {}", kind)))
+    }
+}
+
+/// A store plus what it needs to stay valid from C.
+pub struct AwwasmCStore {
+    store: AwwasmStore<'static>,
+    /// Imports defined for the next instantiation.
+    imports: AwwasmImports<'static>,
+    host: CHost,
+    last_error: String,
+    /// Module bytes and import names `store` and `imports` borrow.
+    /// Declared after them so it is dropped last.
+    owned: OwnedBytes,
+}
+
+/// Byte buffers leaked from their boxes so references into them stay
+/// valid while the list grows; freed on drop.
+#[derive(Default)]
+struct OwnedBytes(Vec<*mut [u8]>);
+
+impl Drop for OwnedBytes {
+    fn drop(&mut self) {
+        for &bytes in &self.0 {
+            // SAFETY: each pointer came from `Box::into_raw` in `own` and
+            // is freed only here, once.
+            drop(unsafe { Box::from_raw(bytes) });
+        }
+    }
+}
+
+impl AwwasmCStore {
+    /// Keep a copy of `bytes` alive as long as the store.
+    fn own(&mut self, bytes: &[u8]) -> &'static [u8] {
+        let bytes = Box::into_raw(Box::<[u8]>::from(bytes));
+        self.owned.0.push(bytes);
+        // SAFETY: the allocation is freed only when `owned` drops, after
+        // `store` and `imports`, the only holders of the reference.
+        unsafe { &*bytes }
+    }
+
+    /// Record `error` for `awwasm_store_last_error` and return `code`.
+    fn fail(&mut self, code: u32, error: impl Display) -> u32 {
+        self.last_error = error.to_string();
+        code
+    }
+}
+
+/// Borrow a NUL-terminated UTF-8 string from C.
+///
+/// # Safety
+///
+/// `s` must be null or point to a NUL-terminated string that outlives
+/// the returned reference.
+unsafe fn c_str<'s>(s: *const c_char) -> Option<&'s str> {
+    if s.is_null() {
+        return None;
+    }
+    CStr::from_ptr(s).to_str().ok()
+}
+
+/// Borrow `len` elements at `data`; null is fine when `len` is zero.
+///
+/// # Safety
+///
+/// Unless `len` is zero, `data` must point to `len` initialized elements
+/// that outlive the returned slice.
+unsafe fn c_slice<'s, T>(data: *const T, len: usize) -> Option<&'s [T]> {
+    match (data.is_null(), len) {
+        (_, 0) => Some(&[]),
+        (true, _) => None,
+        (false, _) => Some(slice::from_raw_parts(data, len)),
+    }
+}
+
+/// Create an empty store. Free it with `awwasm_store_delete`.
+#[no_mangle]
+pub extern "C" fn awwasm_store_new() -> *mut AwwasmCStore {
+    Box::into_raw(Box::new(AwwasmCStore {
+        store: AwwasmStore::new(),
+        imports: AwwasmImports::new(),
+        host: CHost { callback: None, env: ptr::null_mut() },
+        last_error: String::new(),
+        owned: OwnedBytes::default(),
+    }))
+}
+
+/// Free a store and everything instantiated into it.
+///
+/// # Safety
+///
+/// `store` must be null or come from `awwasm_store_new` and not be used
+/// afterwards.
+#[no_mangle]
+pub unsafe extern "C" fn awwasm_store_delete(store: *mut AwwasmCStore) {
+    if !store.is_null() {
+        drop(Box::from_raw(store));
+    }
+}
+
+/// Run host functions imported by this store's modules through
+/// `callback`, which receives `env` unchanged.
+///
+/// # Safety
+///
+/// `store` must be a live store.
+#[no_mangle]
+pub unsafe extern "C" fn awwasm_store_set_host(store: *mut AwwasmCStore, callback: AwwasmCHostFunc, env: *mut c_void) {
+    let store = &mut *store;
+    store.host = CHost { callback: Some(callback), env };
+}
+
+/// Provide the import `module`.`name` to the next instantiation as the
+/// host function `host_func_id`.
+///
+/// # Safety
+///
+/// `store` must be a live store; `module` and `name` NUL-terminated
+/// strings.
+#[no_mangle]
+pub unsafe extern "C" fn awwasm_store_define_func(
+    store: *mut AwwasmCStore,
+    module: *const c_char,
+    name: *const c_char,
+    host_func_id: u32,
+) -> u32 {
+    let store = &mut *store;
+    let (Some(module), Some(name)) = (c_str(module), c_str(name)) else {
+        return store.fail(AWWASM_ERR_INVALID_ARGUMENT, "import names must be UTF-8 strings");
+    };
+    let (module, name) = (store.own(module.as_bytes()), store.own(name.as_bytes()));
+    store.imports.add_func(module, name, AwwasmFuncInst::host(0, host_func_id));
+    0
+}
+
+/// Instantiate the module in `bytes[..len]`, consuming the imports
+/// defined so far, and write its address to `out_module`.
+///
+/// The bytes are copied; the caller's buffer can be freed right away.
+///
+/// # Safety
+///
+/// `store` must be a live store, `bytes` point to `len` bytes and
+/// `out_module` be writable.
+#[no_mangle]
+pub unsafe extern "C" fn awwasm_store_instantiate(
+    store: *mut AwwasmCStore,
+    bytes: *const u8,
+    len: usize,
+    out_module: *mut u32,
+) -> u32 {
+    let store = &mut *store;
+    let Some(bytes) = c_slice(bytes, len) else {
+        return store.fail(AWWASM_ERR_INVALID_ARGUMENT, "module bytes are null");
+    };
+    let bytes = store.own(bytes);
+    let mut imports = core::mem::take(&mut store.imports);
+    match store.store.instantiate_bytes(bytes, &mut imports) {
+        Ok(addr) => {
+            *out_module = addr.0;
+            0
+        }
+        Err(e) => store.fail(e.code(), e),
+    }
+}
+
+/// Call the function `module` exports as `name`.
+///
+/// Its results are written to `results`, and their count to
+/// `out_nresults`. Fails without running the function if it has more
+/// than `results_len` results or any that are not numbers.
+///
+/// # Safety
+///
+/// `store` must be a live store, `name` a NUL-terminated string, `args`
+/// point to `nargs` values, `results` to room for `results_len` values
+/// and `out_nresults` be writable.
+#[no_mangle]
+pub unsafe extern "C" fn awwasm_invoke(
+    store: *mut AwwasmCStore,
+    module: u32,
+    name: *const c_char,
+    args: *const AwwasmCValue,
+    nargs: usize,
+    results: *mut AwwasmCValue,
+    results_len: usize,
+    out_nresults: *mut usize,
+) -> u32 {
+    let store = &mut *store;
+    let (Some(name), Some(args)) = (c_str(name), c_slice(args, nargs)) else {
+        return store.fail(AWWASM_ERR_INVALID_ARGUMENT, "invalid export name or arguments");
+    };
+    let args: Vec<AwwasmValue> = match args.iter().map(|v| v.to_value()).collect() {
+        Ok(args) => args,
+        Err(kind) => return store.fail(AWWASM_ERR_INVALID_ARGUMENT, format_args!("unknown value kind {}", kind)),
+    };
+    let Some(func) = store.store.module(AwwasmModuleAddr(module)).and_then(|m| m.get_func(name)) else {
+        let e = AwwasmRuntimeError::ExportNotFound(name.into());
+        return store.fail(e.code(), e);
+    };
+    let result_types = match store.store.func_type(func) {
+        Ok(ty) => ty.results.clone(),
+        Err(e) => return store.fail(e.code(), e),
+    };
+    if result_types.len() > results_len || (results.is_null() && !result_types.is_empty()) {
+        return store.fail(AWWASM_ERR_INVALID_ARGUMENT, "result buffer too small");
+    }
+    if let Some(ty) = result_types.iter().find(|ty| !matches!(ty, AwwasmValueType::I32 | AwwasmValueType::I64 | AwwasmValueType::F32 | AwwasmValueType::F64)) {
+        let e = AwwasmRuntimeError::TypeMismatch { expected: "number".into(), got: ty.name().into() };
+        return store.fail(e.code(), e);
+    }
+    let values = match store.store.invoke(func, &args, &mut store.host) {
+        Ok(values) => values,
+        Err(e) => return store.fail(e.code(), e),
+    };
+    for (i, value) in values.iter().enumerate() {
+        let Some(value) = AwwasmCValue::from_value(*value) else {
+            let e = AwwasmRuntimeError::TypeMismatch { expected: "number".into(), got: value.value_type().name().into() };
+            return store.fail(e.code(), e);
+        };
+        *results.add(i) = value;
+    }
+    *out_nresults = values.len();
+    0
+}
+
+/// Contents of the memory `module` exports as `name`, with its size in
+/// bytes written to `out_len`; null if there is no such memory, or it
+/// is shared: other threads may change a shared memory's bytes at any
+/// time.
+///
+/// The pointer is invalidated by anything that can grow the memory,
+/// including calls into the module.
+///
+/// # Safety
+///
+/// `store` must be a live store, `name` a NUL-terminated string and
+/// `out_len` writable.
+#[no_mangle]
+pub unsafe extern "C" fn awwasm_memory_data(
+    store: *mut AwwasmCStore,
+    module: u32,
+    name: *const c_char,
+    out_len: *mut usize,
+) -> *mut u8 {
+    let store = &mut *store;
+    let Some(name) = c_str(name) else {
+        return ptr::null_mut();
+    };
+    let Some(addr) = store.store.module(AwwasmModuleAddr(module)).and_then(|m| m.get_memory(name)) else {
+        return ptr::null_mut();
+    };
+    match store.store.mem_mut(addr).ok().and_then(|mem| mem.data.as_mut_slice()) {
+        Some(bytes) => {
+            *out_len = bytes.len();
+            bytes.as_mut_ptr()
+        }
+        None => ptr::null_mut(),
+    }
+}
+
+/// Copy the message of the last failed call into `buf` (at most `len`
+/// bytes including the NUL terminator) and return the full message
+/// length, so a too-small buffer can be retried.
+///
+/// # Safety
+///
+/// `store` must be a live store and `buf` writable for `len` bytes (or
+/// null with `len` zero).
+#[no_mangle]
+pub unsafe extern "C" fn awwasm_store_last_error(store: *const AwwasmCStore, buf: *mut c_char, len: usize) -> usize {
+    let message = (*store).last_error.as_bytes();
+    if !buf.is_null() && len > 0 {
+        let n = message.len().min(len - 1);
+        ptr::copy_nonoverlapping(message.as_ptr(), buf.cast::<u8>(), n);
+        *buf.add(n) = 0;
+    }
+    message.len()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// `(module (func (export "add") (param i32 i32) (result i32)
+    /// local.get 0 local.get 1 i32.add))`
+    const ADD: &[u8] = &[
+        0x00, 0x61, 0x73, 0x6d, 0x01, 0x00, 0x00, 0x00, 0x01, 0x07, 0x01, 0x60, 0x02, 0x7f, 0x7f, 0x01, 0x7f, 0x03,
+        0x02, 0x01, 0x00, 0x07, 0x07, 0x01, 0x03, 0x61, 0x64, 0x64, 0x00, 0x00, 0x0a, 0x09, 0x01, 0x07, 0x00, 0x20,
+        0x00, 0x20, 0x01, 0x6a, 0x0b,
+    ];
+
+    fn i32(v: i32) -> AwwasmCValue {
+        AwwasmCValue { kind: AwwasmCValueKind::I32 as u8, of: AwwasmCValuePayload { i32: v } }
+    }
+
+    #[test]
+    fn test_instantiate_and_invoke() {
+        unsafe {
+            let store = awwasm_store_new();
+            let mut module = u32::MAX;
+            assert_eq!(awwasm_store_instantiate(store, ADD.as_ptr(), ADD.len(), &mut module), 0);
+
+            let args = [i32(2), i32(40)];
+            let mut results = [i32(0)];
+            let mut n = 0;
+            let code = awwasm_invoke(store, module, c"add".as_ptr(), args.as_ptr(), 2, results.as_mut_ptr(), 1, &mut n);
+            assert_eq!(code, 0);
+            assert_eq!(n, 1);
+            assert_eq!(results[0].to_value(), Ok(AwwasmValue::I32(42)));
+
+            let bad = [i32(2), AwwasmCValue { kind: 9, ..i32(40) }];
+            let code = awwasm_invoke(store, module, c"add".as_ptr(), bad.as_ptr(), 2, results.as_mut_ptr(), 1, &mut n);
+            assert_eq!(code, AWWASM_ERR_INVALID_ARGUMENT);
+
+            let code = awwasm_invoke(store, module, c"add".as_ptr(), args.as_ptr(), 2, results.as_mut_ptr(), 0, &mut n);
+            assert_eq!(code, AWWASM_ERR_INVALID_ARGUMENT);
+
+            let code = awwasm_invoke(store, module, c"nope".as_ptr(), ptr::null(), 0, ptr::null_mut(), 0, &mut n);
+            assert_eq!(code, 3010);
+            let mut buf = [0 as c_char; 64];
+            let len = awwasm_store_last_error(store, buf.as_mut_ptr(), buf.len());
+            assert_eq!(CStr::from_ptr(buf.as_ptr()).to_str().unwrap().len(), len);
+            awwasm_store_delete(store);
+        }
+    }
+}
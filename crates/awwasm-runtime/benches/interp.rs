@@ -0,0 +1,88 @@
+//! Interpreter throughput and instantiation benchmarks.
+//!
+//! Run with `cargo bench -p awwasm-runtime`.
+
+use awwasm_parser::components::module::AwwasmModule;
+use awwasm_runtime::memory::AwwasmMemInst;
+use awwasm_runtime::{AwwasmExternAddr, AwwasmHost, AwwasmImports, AwwasmStore, AwwasmTrap, AwwasmValue};
+use criterion::{criterion_group, criterion_main, Criterion};
+
+const WAT: &str = r#"
+    (module
+        (func $fib (export "fib") (param i32) (result i32)
+            local.get 0 i32.const 2 i32.lt_u
+            if (result i32) local.get 0
+            else
+                local.get 0 i32.const 1 i32.sub call $fib
+                local.get 0 i32.const 2 i32.sub call $fib
+                i32.add
+            end)
+        (func (export "sum") (param i32) (result i32) (local i32 i32)
+            block
+                loop
+                    local.get 2 local.get 0 i32.ge_u br_if 1
+                    local.get 1 local.get 2 i32.add local.set 1
+                    local.get 2 i32.const 1 i32.add local.set 2
+                    br 0
+                end
+            end
+            local.get 1)
+        (memory 1)
+        (func (export "memsum") (param i32) (result i32) (local i32 i32)
+            block
+                loop
+                    local.get 2 local.get 0 i32.ge_u br_if 1
+                    local.get 2 local.get 2 i32.store
+                    local.get 1 local.get 2 i32.load i32.add local.set 1
+                    local.get 2 i32.const 4 i32.add local.set 2
+                    br 0
+                end
+            end
+            local.get 1)
+    )
+"#;
+
+struct NoHost;
+
+impl AwwasmHost for NoHost {
+    fn call(
+        &mut self,
+        host_func_id: u32,
+        _args: &[AwwasmValue],
+        _mem: Option<&mut AwwasmMemInst>,
+    ) -> Result<Vec<AwwasmValue>, AwwasmTrap> {
+        Err(AwwasmTrap::InvalidHostCall { host_func_id })
+    }
+}
+
+fn interp(c: &mut Criterion) {
+    let wasm = wat::parse_str(WAT).unwrap();
+    let mut module = AwwasmModule::new(&wasm).unwrap();
+    module.resolve_all_sections().unwrap();
+    let mut store = AwwasmStore::new();
+    let addr = store.store_init(&module, &mut AwwasmImports::new()).unwrap();
+    let func = |store: &AwwasmStore, name: &str| match store.module(addr).unwrap().export_by_str(name).unwrap().addr {
+        AwwasmExternAddr::Func(f) => f,
+        other => panic!("expected function export, got {:?}", other),
+    };
+
+    for (name, arg) in [("fib", 20), ("sum", 100_000), ("memsum", 60_000)] {
+        let f = func(&store, name);
+        c.bench_function(&format!("{}({})", name, arg), |b| {
+            b.iter(|| store.invoke(f, &[AwwasmValue::I32(arg)], &mut NoHost).unwrap())
+        });
+    }
+}
+
+fn instantiate(c: &mut Criterion) {
+    // 16 MiB of memory with a small data segment: dominated by allocation.
+    let wasm = wat::parse_str(r#"(module (memory 256) (data (i32.const 4096) "hello"))"#).unwrap();
+    let mut module = AwwasmModule::new(&wasm).unwrap();
+    module.resolve_all_sections().unwrap();
+    c.bench_function("instantiate(256 pages)", |b| {
+        b.iter(|| AwwasmStore::new().store_init(&module, &mut AwwasmImports::new()).unwrap())
+    });
+}
+
+criterion_group!(benches, interp, instantiate);
+criterion_main!(benches);
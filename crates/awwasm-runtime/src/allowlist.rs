@@ -0,0 +1,209 @@
+//! Refusing to instantiate unapproved modules.
+//!
+//! With an `AwwasmModuleGate` set (`AwwasmStore::set_module_gate`), every
+//! module is hashed with SHA-256 before it is parsed, and instantiation
+//! fails with `ModuleNotPermitted` unless the gate approves the hash.
+//! `AwwasmAllowList` approves a fixed set of hashes;
+//! `AwwasmModuleVerifier` hands the decision, and the module bytes, to a
+//! closure, e.g. to check a signature.
+//!
+//! Only `instantiate_bytes` (and what builds on it) sees the bytes it
+//! hashes. A gated store therefore refuses `store_init` of an
+//! already-parsed module outright.
+
+use alloc::boxed::Box;
+use alloc::collections::BTreeSet;
+use core::fmt;
+
+/// The SHA-256 digest of a module's binary.
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct AwwasmModuleHash(pub [u8; 32]);
+
+impl AwwasmModuleHash {
+    /// Hash the module binary `bytes`.
+    pub fn of(bytes: &[u8]) -> Self {
+        Self(sha256(bytes))
+    }
+
+    /// Parse 64 hex digits, as printed by `Display` and `sha256sum`.
+    pub fn from_hex(hex: &str) -> Option<Self> {
+        let hex = hex.as_bytes();
+        if hex.len() != 64 {
+            return None;
+        }
+        let digit = |c: u8| (c as char).to_digit(16).map(|d| d as u8);
+        let mut digest = [0u8; 32];
+        for (byte, pair) in digest.iter_mut().zip(hex.chunks_exact(2)) {
+            *byte = (digit(pair[0])? << 4) | digit(pair[1])?;
+        }
+        Some(Self(digest))
+    }
+}
+
+impl fmt::Display for AwwasmModuleHash {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.0.iter().try_for_each(|byte| write!(f, "{:02x}", byte))
+    }
+}
+
+impl fmt::Debug for AwwasmModuleHash {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "AwwasmModuleHash({})", self)
+    }
+}
+
+/// Decides which modules a store may instantiate.
+pub trait AwwasmModuleGate: Send + Sync + fmt::Debug {
+    /// Whether the module `bytes`, whose SHA-256 is `hash`, may be
+    /// instantiated.
+    fn permits(&self, hash: &AwwasmModuleHash, bytes: &[u8]) -> bool;
+}
+
+/// An `AwwasmModuleGate` approving a fixed set of module hashes.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct AwwasmAllowList {
+    hashes: BTreeSet<AwwasmModuleHash>,
+}
+
+impl AwwasmAllowList {
+    /// An allow-list approving nothing.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Approve the module whose SHA-256 is `hash`.
+    pub fn insert(&mut self, hash: AwwasmModuleHash) -> &mut Self {
+        self.hashes.insert(hash);
+        self
+    }
+
+    /// Approve the module binary `bytes`.
+    pub fn insert_module(&mut self, bytes: &[u8]) -> &mut Self {
+        self.insert(AwwasmModuleHash::of(bytes))
+    }
+
+    /// Whether `hash` is approved.
+    pub fn contains(&self, hash: &AwwasmModuleHash) -> bool {
+        self.hashes.contains(hash)
+    }
+
+    /// Number of approved hashes.
+    pub fn len(&self) -> usize {
+        self.hashes.len()
+    }
+
+    /// Whether nothing is approved.
+    pub fn is_empty(&self) -> bool {
+        self.hashes.is_empty()
+    }
+}
+
+impl FromIterator<AwwasmModuleHash> for AwwasmAllowList {
+    fn from_iter<I: IntoIterator<Item = AwwasmModuleHash>>(iter: I) -> Self {
+        Self { hashes: iter.into_iter().collect() }
+    }
+}
+
+impl AwwasmModuleGate for AwwasmAllowList {
+    fn permits(&self, hash: &AwwasmModuleHash, _bytes: &[u8]) -> bool {
+        self.contains(hash)
+    }
+}
+
+/// An `AwwasmModuleGate` that asks a closure.
+pub struct AwwasmModuleVerifier {
+    verify: Box<dyn Fn(&AwwasmModuleHash, &[u8]) -> bool + Send + Sync>,
+}
+
+impl AwwasmModuleVerifier {
+    /// Approve the modules `verify` returns `true` for.
+    pub fn new(verify: impl Fn(&AwwasmModuleHash, &[u8]) -> bool + Send + Sync + 'static) -> Self {
+        Self { verify: Box::new(verify) }
+    }
+}
+
+impl fmt::Debug for AwwasmModuleVerifier {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("AwwasmModuleVerifier").finish_non_exhaustive()
+    }
+}
+
+impl AwwasmModuleGate for AwwasmModuleVerifier {
+    fn permits(&self, hash: &AwwasmModuleHash, bytes: &[u8]) -> bool {
+        (self.verify)(hash, bytes)
+    }
+}
+
+/// Round constants: the first 32 bits of the fractional parts of the cube
+/// roots of the first 64 primes (FIPS 180-4, 4.2.2).
+const K: [u32; 64] = [
+    0x428a2f98, 0x71374491, 0xb5c0fbcf, 0xe9b5dba5, 0x3956c25b, 0x59f111f1, 0x923f82a4, 0xab1c5ed5,
+    0xd807aa98, 0x12835b01, 0x243185be, 0x550c7dc3, 0x72be5d74, 0x80deb1fe, 0x9bdc06a7, 0xc19bf174,
+    0xe49b69c1, 0xefbe4786, 0x0fc19dc6, 0x240ca1cc, 0x2de92c6f, 0x4a7484aa, 0x5cb0a9dc, 0x76f988da,
+    0x983e5152, 0xa831c66d, 0xb00327c8, 0xbf597fc7, 0xc6e00bf3, 0xd5a79147, 0x06ca6351, 0x14292967,
+    0x27b70a85, 0x2e1b2138, 0x4d2c6dfc, 0x53380d13, 0x650a7354, 0x766a0abb, 0x81c2c92e, 0x92722c85,
+    0xa2bfe8a1, 0xa81a664b, 0xc24b8b70, 0xc76c51a3, 0xd192e819, 0xd6990624, 0xf40e3585, 0x106aa070,
+    0x19a4c116, 0x1e376c08, 0x2748774c, 0x34b0bcb5, 0x391c0cb3, 0x4ed8aa4a, 0x5b9cca4f, 0x682e6ff3,
+    0x748f82ee, 0x78a5636f, 0x84c87814, 0x8cc70208, 0x90befffa, 0xa4506ceb, 0xbef9a3f7, 0xc67178f2,
+];
+
+/// SHA-256 of `bytes` (FIPS 180-4).
+fn sha256(bytes: &[u8]) -> [u8; 32] {
+    let mut state: [u32; 8] = [
+        0x6a09e667, 0xbb67ae85, 0x3c6ef372, 0xa54ff53a, 0x510e527f, 0x9b05688c, 0x1f83d9ab, 0x5be0cd19,
+    ];
+    let mut blocks = bytes.chunks_exact(64);
+    for block in &mut blocks {
+        compress(&mut state, block);
+    }
+    // Pad with 0x80, zeros and the bit length, into one or two blocks.
+    let rest = blocks.remainder();
+    let mut tail = [0u8; 128];
+    tail[..rest.len()].copy_from_slice(rest);
+    tail[rest.len()] = 0x80;
+    let len = if rest.len() < 56 { 64 } else { 128 };
+    tail[len - 8..len].copy_from_slice(&(bytes.len() as u64).wrapping_mul(8).to_be_bytes());
+    for block in tail[..len].chunks_exact(64) {
+        compress(&mut state, block);
+    }
+
+    let mut digest = [0u8; 32];
+    for (out, word) in digest.chunks_exact_mut(4).zip(state) {
+        out.copy_from_slice(&word.to_be_bytes());
+    }
+    digest
+}
+
+/// Fold one 64-byte block into `state`.
+fn compress(state: &mut [u32; 8], block: &[u8]) {
+    let mut w = [0u32; 64];
+    for (word, bytes) in w.iter_mut().zip(block.chunks_exact(4)) {
+        *word = u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]);
+    }
+    for i in 16..64 {
+        let s0 = w[i - 15].rotate_right(7) ^ w[i - 15].rotate_right(18) ^ (w[i - 15] >> 3);
+        let s1 = w[i - 2].rotate_right(17) ^ w[i - 2].rotate_right(19) ^ (w[i - 2] >> 10);
+        w[i] = w[i - 16].wrapping_add(s0).wrapping_add(w[i - 7]).wrapping_add(s1);
+    }
+
+    let [mut a, mut b, mut c, mut d, mut e, mut f, mut g, mut h] = *state;
+    for (k, w) in K.iter().zip(w) {
+        let s1 = e.rotate_right(6) ^ e.rotate_right(11) ^ e.rotate_right(25);
+        let ch = (e & f) ^ (!e & g);
+        let t1 = h.wrapping_add(s1).wrapping_add(ch).wrapping_add(*k).wrapping_add(w);
+        let s0 = a.rotate_right(2) ^ a.rotate_right(13) ^ a.rotate_right(22);
+        let maj = (a & b) ^ (a & c) ^ (b & c);
+        let t2 = s0.wrapping_add(maj);
+        h = g;
+        g = f;
+        f = e;
+        e = d.wrapping_add(t1);
+        d = c;
+        c = b;
+        b = a;
+        a = t1.wrapping_add(t2);
+    }
+    for (word, v) in state.iter_mut().zip([a, b, c, d, e, f, g, h]) {
+        *word = word.wrapping_add(v);
+    }
+}
@@ -0,0 +1,261 @@
+//! High-level API in the shape most engines share.
+//!
+//! `Engine`, `Module`, `Store`, `Instance`, `Func` and `Memory` wrap the
+//! address-based types so code written against other runtimes ports with
+//! little change:
+//!
+//! ```ignore
+//! let engine = Engine::default();
+//! let module = Module::new(&engine, &wasm)?;
+//! let mut store = Store::new(&engine, host);
+//! let instance = Instance::new(&mut store, &module, &mut AwwasmImports::new())?;
+//! let add = instance.get_func(&store, "add").unwrap();
+//! let sum = add.call(&mut store, &[AwwasmValue::I32(1), AwwasmValue::I32(2)])?;
+//! ```
+//!
+//! The handles are plain addresses into the store that made them, as in
+//! the low-level API, which stays available through `Store::as_raw`.
+
+use alloc::vec::Vec;
+
+use awwasm_parser::components::module::AwwasmModule;
+
+use crate::async_call::AwwasmAsyncCall;
+use crate::error::{AwwasmInstantiationError, AwwasmRuntimeError};
+use crate::exec::AwwasmStackLimits;
+use crate::func::{AwwasmFuncType, AwwasmHost};
+use crate::imports::AwwasmImports;
+use crate::info::AwwasmModuleInfo;
+use crate::store::AwwasmStore;
+use crate::type_convert;
+use crate::values::{AwwasmFuncAddr, AwwasmMemAddr, AwwasmModuleAddr, AwwasmValue};
+
+/// Shared configuration for the stores created from it.
+#[derive(Debug, Clone, Default)]
+pub struct Engine {
+    stack_limits: AwwasmStackLimits,
+    code_cache_limit: Option<usize>,
+}
+
+impl Engine {
+    /// Bound the interpreter stacks of every store created afterwards
+    /// (see `AwwasmStore::set_stack_limits`).
+    pub fn stack_limits(mut self, limits: AwwasmStackLimits) -> Self {
+        self.stack_limits = limits;
+        self
+    }
+
+    /// Cap the lowered-code cache of every store created afterwards (see
+    /// `AwwasmStore::set_code_cache_limit`).
+    pub fn code_cache_limit(mut self, limit: Option<usize>) -> Self {
+        self.code_cache_limit = limit;
+        self
+    }
+}
+
+/// A validated module, ready to be instantiated any number of times.
+#[derive(Debug, Clone)]
+pub struct Module<'a> {
+    bytes: &'a [u8],
+    info: AwwasmModuleInfo<'a>,
+}
+
+impl<'a> Module<'a> {
+    /// Parse and check the binary module in `bytes`.
+    ///
+    /// Modules do not depend on the engine; it is taken so the call
+    /// reads like other runtimes'.
+    pub fn new(_engine: &Engine, bytes: &'a [u8]) -> Result<Self, AwwasmInstantiationError> {
+        let invalid = |e| AwwasmInstantiationError::InvalidModule(type_convert::parse_error(e));
+        let mut module = AwwasmModule::new(bytes).map_err(invalid)?;
+        if module.sections.is_some() {
+            module.resolve_all_sections().map_err(invalid)?;
+        }
+        let info = AwwasmModuleInfo::new(&module)?;
+        Ok(Self { bytes, info })
+    }
+
+    /// The module's binary encoding.
+    pub fn bytes(&self) -> &'a [u8] {
+        self.bytes
+    }
+
+    /// What the module imports and exports.
+    pub fn info(&self) -> &AwwasmModuleInfo<'a> {
+        &self.info
+    }
+}
+
+/// Instances and the host data their host functions run against.
+///
+/// `T` receives every host function call, like the low-level `host`
+/// argument of `AwwasmStore::invoke`. The store can move to another
+/// thread when `T` can: with `closures::AwwasmHostFuncs` as `T`, its
+/// thread mode decides.
+#[derive(Debug)]
+pub struct Store<'a, T> {
+    inner: AwwasmStore<'a>,
+    data: T,
+}
+
+impl<'a, T: AwwasmHost> Store<'a, T> {
+    /// An empty store configured by `engine`.
+    pub fn new(engine: &Engine, data: T) -> Self {
+        let mut inner = AwwasmStore::new();
+        // A new store has no arena, so any limits fit.
+        let _ = inner.set_stack_limits(engine.stack_limits);
+        inner.set_code_cache_limit(engine.code_cache_limit);
+        Self { inner, data }
+    }
+
+    /// The host data.
+    pub fn data(&self) -> &T {
+        &self.data
+    }
+
+    /// The host data, mutably.
+    pub fn data_mut(&mut self) -> &mut T {
+        &mut self.data
+    }
+
+    /// Give up the store and keep the host data.
+    pub fn into_data(self) -> T {
+        self.data
+    }
+
+    /// The low-level store.
+    pub fn as_raw(&self) -> &AwwasmStore<'a> {
+        &self.inner
+    }
+
+    /// The low-level store, mutably.
+    pub fn as_raw_mut(&mut self) -> &mut AwwasmStore<'a> {
+        &mut self.inner
+    }
+}
+
+/// An instantiated module.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Instance {
+    addr: AwwasmModuleAddr,
+}
+
+impl Instance {
+    /// Instantiate `module` into `store`, taking its imports from
+    /// `imports`.
+    pub fn new<'a, T: AwwasmHost>(
+        store: &mut Store<'a, T>,
+        module: &Module<'a>,
+        imports: &mut AwwasmImports<'a>,
+    ) -> Result<Self, AwwasmInstantiationError> {
+        let addr = store.inner.instantiate_bytes(module.bytes, imports)?;
+        Ok(Self { addr })
+    }
+
+    /// Wrap an instance made with the low-level API.
+    pub fn from_raw(addr: AwwasmModuleAddr) -> Self {
+        Self { addr }
+    }
+
+    /// The instance's address in its store.
+    pub fn as_raw(&self) -> AwwasmModuleAddr {
+        self.addr
+    }
+
+    /// The function exported as `name`.
+    pub fn get_func<T>(&self, store: &Store<'_, T>, name: &str) -> Option<Func> {
+        store.inner.module(self.addr)?.get_func(name).map(|addr| Func { addr })
+    }
+
+    /// The memory exported as `name`.
+    pub fn get_memory<T>(&self, store: &Store<'_, T>, name: &str) -> Option<Memory> {
+        store.inner.module(self.addr)?.get_memory(name).map(|addr| Memory { addr })
+    }
+}
+
+/// A function in a store.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Func {
+    addr: AwwasmFuncAddr,
+}
+
+impl Func {
+    /// Call the function with `args`, which are checked against its
+    /// signature.
+    pub fn call<T: AwwasmHost>(
+        &self,
+        store: &mut Store<'_, T>,
+        args: &[AwwasmValue],
+    ) -> Result<Vec<AwwasmValue>, AwwasmRuntimeError> {
+        store.inner.invoke(self.addr, args, &mut store.data)
+    }
+
+    /// Call the function as a future that yields to its executor every
+    /// `yield_every` units of fuel; see `async_call`.
+    pub fn call_async<'s, 'a, T: AwwasmHost>(
+        &self,
+        store: &'s mut Store<'a, T>,
+        args: &[AwwasmValue],
+        yield_every: u64,
+    ) -> AwwasmAsyncCall<'s, 'a, T> {
+        store.inner.call_async(self.addr, args, &mut store.data, yield_every)
+    }
+
+    /// The function's signature.
+    pub fn ty<'s, T>(&self, store: &'s Store<'_, T>) -> Result<&'s AwwasmFuncType, AwwasmRuntimeError> {
+        store.inner.func_type(self.addr)
+    }
+
+    /// The function's address in its store.
+    pub fn as_raw(&self) -> AwwasmFuncAddr {
+        self.addr
+    }
+}
+
+/// A linear memory in a store.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Memory {
+    addr: AwwasmMemAddr,
+}
+
+impl Memory {
+    /// The memory's contents; `None` for a shared memory, whose
+    /// contents only `read` and `write` reach.
+    pub fn data<'s, T>(&self, store: &'s Store<'_, T>) -> Result<Option<&'s [u8]>, AwwasmRuntimeError> {
+        Ok(store.inner.mem(self.addr)?.data.as_slice())
+    }
+
+    /// The memory's contents, mutably; `None` for a shared memory.
+    pub fn data_mut<'s, T>(&self, store: &'s mut Store<'_, T>) -> Result<Option<&'s mut [u8]>, AwwasmRuntimeError> {
+        Ok(store.inner.mem_mut(self.addr)?.data.as_mut_slice())
+    }
+
+    /// Current size in pages.
+    pub fn size<T>(&self, store: &Store<'_, T>) -> Result<u32, AwwasmRuntimeError> {
+        Ok(store.inner.mem(self.addr)?.size_pages())
+    }
+
+    /// Grow by `delta` pages; the previous size, or `None` if the memory
+    /// cannot grow that far.
+    pub fn grow<T>(&self, store: &mut Store<'_, T>, delta: u32) -> Result<Option<u32>, AwwasmRuntimeError> {
+        Ok(store.inner.mem_mut(self.addr)?.grow(delta))
+    }
+
+    /// Copy `buf.len()` bytes starting at `offset` into `buf`.
+    pub fn read<T>(&self, store: &Store<'_, T>, offset: u32, buf: &mut [u8]) -> Result<(), AwwasmRuntimeError> {
+        let len = u32::try_from(buf.len()).unwrap_or(u32::MAX);
+        let bytes = store.inner.mem(self.addr)?.read(offset, len)?;
+        buf.copy_from_slice(&bytes);
+        Ok(())
+    }
+
+    /// Copy `data` into the memory starting at `offset`.
+    pub fn write<T>(&self, store: &mut Store<'_, T>, offset: u32, data: &[u8]) -> Result<(), AwwasmRuntimeError> {
+        Ok(store.inner.mem_mut(self.addr)?.write(offset, data)?)
+    }
+
+    /// The memory's address in its store.
+    pub fn as_raw(&self) -> AwwasmMemAddr {
+        self.addr
+    }
+}
@@ -0,0 +1,485 @@
+//! Precompiled artifacts: lowered function bodies serialized for reuse.
+//!
+//! `AwwasmStore::precompile` writes, and `AwwasmStore::load_precompiled`
+//! reads, this layout (integers are LEB128 as in the wasm binary format):
+//!
+//! - magic `\0awc`, `FORMAT_VERSION`, the runtime's crate version;
+//! - the metering the bodies were lowered with: `0` for none, or `1`
+//!   and the `AwwasmGasTable::hash` (8 bytes, little endian) of the table
+//!   they were instrumented with;
+//! - the number of functions, then for each function the module defines,
+//!   in index order: an FNV-1a hash of its instruction bytes, its locals,
+//!   its ops, its `br_table` depths and its `call_indirect` site count.
+//!
+//! The IR is target independent, so an artifact loads on any host running
+//! the same runtime version. Loading checks the structure the interpreter
+//! relies on: block nesting, branch targets and side-table indices. Ops
+//! are not type checked against the module, so operand types and stack
+//! heights are only checked as the code runs; a corrupt artifact fails
+//! with an error rather than breaking the interpreter, but should only be
+//! loaded from a source trusted as much as the module itself.
+
+#[cfg(feature = "alloc")]
+use alloc::vec::Vec;
+#[cfg(feature = "alloc")]
+use alloc::string::String;
+#[cfg(feature = "alloc")]
+use alloc::format;
+
+use crate::decode::{self, AwwasmReader};
+use crate::error::AwwasmRuntimeError;
+use crate::func::AwwasmLocalDecl;
+use crate::exec::MAX_FUNCTION_LOCALS;
+use crate::fuel::AwwasmGasTable;
+use crate::ir::{AwwasmCompiledCode, CallCache, Op, MAX_BLOCK_NESTING};
+use crate::values::AwwasmValueType;
+
+const MAGIC: &[u8; 4] = b"\0awc";
+
+/// Bumped whenever the layout or `Op` changes.
+const FORMAT_VERSION: u32 = 3;
+
+/// One function's entry in an artifact.
+pub(crate) struct AwwasmArtifactFunc {
+    /// `body_hash` of the instruction bytes the code was lowered from.
+    pub(crate) hash: u64,
+    pub(crate) code: AwwasmCompiledCode,
+}
+
+fn invalid(why: &str) -> AwwasmRuntimeError {
+    AwwasmRuntimeError::InvalidArtifact(String::from(why))
+}
+
+/// FNV-1a hash identifying the instruction bytes of a function body.
+pub(crate) fn body_hash(code: &[u8]) -> u64 {
+    code.iter()
+        .fold(0xcbf2_9ce4_8422_2325, |h, &b| (h ^ u64::from(b)).wrapping_mul(0x0100_0000_01b3))
+}
+
+pub(crate) fn write_u32(out: &mut Vec<u8>, mut v: u32) {
+    loop {
+        let byte = (v & 0x7f) as u8;
+        v >>= 7;
+        if v == 0 {
+            out.push(byte);
+            return;
+        }
+        out.push(byte | 0x80);
+    }
+}
+
+pub(crate) fn write_i64(out: &mut Vec<u8>, mut v: i64) {
+    loop {
+        let byte = (v & 0x7f) as u8;
+        v >>= 7;
+        if (v == 0 && byte & 0x40 == 0) || (v == -1 && byte & 0x40 != 0) {
+            out.push(byte);
+            return;
+        }
+        out.push(byte | 0x80);
+    }
+}
+
+fn value_type_byte(ty: AwwasmValueType) -> u8 {
+    match ty {
+        AwwasmValueType::I32 => 0x7f,
+        AwwasmValueType::I64 => 0x7e,
+        AwwasmValueType::F32 => 0x7d,
+        AwwasmValueType::F64 => 0x7c,
+        AwwasmValueType::V128 => 0x7b,
+        AwwasmValueType::FuncRef => 0x70,
+        AwwasmValueType::ExternRef => 0x6f,
+    }
+}
+
+/// Serialize lowered bodies, one `(hash, code)` per defined function,
+/// instrumented with `metering` if it is set.
+pub(crate) fn write(funcs: &[(u64, &AwwasmCompiledCode)], metering: Option<&AwwasmGasTable>) -> Vec<u8> {
+    let mut out = Vec::new();
+    out.extend_from_slice(MAGIC);
+    write_u32(&mut out, FORMAT_VERSION);
+    let version = env!("CARGO_PKG_VERSION");
+    write_u32(&mut out, version.len() as u32);
+    out.extend_from_slice(version.as_bytes());
+    match metering {
+        Some(table) => {
+            out.push(1);
+            out.extend_from_slice(&table.hash().to_le_bytes());
+        }
+        None => out.push(0),
+    }
+
+    write_u32(&mut out, funcs.len() as u32);
+    for (hash, code) in funcs {
+        out.extend_from_slice(&hash.to_le_bytes());
+        write_u32(&mut out, code.locals.len() as u32);
+        for decl in &code.locals {
+            write_u32(&mut out, decl.count);
+            out.push(value_type_byte(decl.type_));
+        }
+        write_u32(&mut out, code.ops.len() as u32);
+        for op in &code.ops {
+            write_op(&mut out, op);
+        }
+        write_u32(&mut out, code.br_tables.len() as u32);
+        for &depth in &code.br_tables {
+            write_u32(&mut out, depth);
+        }
+        write_u32(&mut out, code.call_caches.len() as u32);
+    }
+    out
+}
+
+fn write_op(out: &mut Vec<u8>, op: &Op) {
+    match *op {
+        Op::Unreachable => out.push(0),
+        Op::Nop => out.push(1),
+        Op::Block { params, results, end } => {
+            out.push(2);
+            for v in [params, results, end] {
+                write_u32(out, v);
+            }
+        }
+        Op::Loop { params, results } => {
+            out.push(3);
+            write_u32(out, params);
+            write_u32(out, results);
+        }
+        Op::If { params, results, else_, end } => {
+            out.push(4);
+            for v in [params, results, else_, end] {
+                write_u32(out, v);
+            }
+        }
+        Op::Else { end } => {
+            out.push(5);
+            write_u32(out, end);
+        }
+        Op::End => out.push(6),
+        Op::Br(depth) => {
+            out.push(7);
+            write_u32(out, depth);
+        }
+        Op::BrIf(depth) => {
+            out.push(8);
+            write_u32(out, depth);
+        }
+        Op::BrTable { start, len } => {
+            out.push(9);
+            write_u32(out, start);
+            write_u32(out, len);
+        }
+        Op::Return => out.push(10),
+        Op::Call(idx) => {
+            out.push(11);
+            write_u32(out, idx);
+        }
+        Op::CallIndirect { type_idx, table_idx, cache } => {
+            out.push(12);
+            for v in [type_idx, table_idx, cache] {
+                write_u32(out, v);
+            }
+        }
+        Op::Drop => out.push(13),
+        Op::Select => out.push(14),
+        Op::LocalGet(idx) => {
+            out.push(15);
+            write_u32(out, idx);
+        }
+        Op::LocalSet(idx) => {
+            out.push(16);
+            write_u32(out, idx);
+        }
+        Op::LocalTee(idx) => {
+            out.push(17);
+            write_u32(out, idx);
+        }
+        Op::GlobalGet(idx) => {
+            out.push(18);
+            write_u32(out, idx);
+        }
+        Op::GlobalSet(idx) => {
+            out.push(19);
+            write_u32(out, idx);
+        }
+        Op::Load { opcode, offset } => {
+            out.push(20);
+            out.push(opcode);
+            write_u32(out, offset);
+        }
+        Op::Store { opcode, offset } => {
+            out.push(21);
+            out.push(opcode);
+            write_u32(out, offset);
+        }
+        Op::MemorySize => out.push(22),
+        Op::MemoryGrow => out.push(23),
+        Op::I32Const(v) => {
+            out.push(24);
+            write_i64(out, i64::from(v));
+        }
+        Op::I64Const(v) => {
+            out.push(25);
+            write_i64(out, v);
+        }
+        Op::F32Const(bits) => {
+            out.push(26);
+            write_u32(out, bits);
+        }
+        Op::F64Const(bits) => {
+            out.push(27);
+            write_i64(out, bits as i64);
+        }
+        Op::Num(opcode) => {
+            out.push(28);
+            out.push(opcode);
+        }
+        Op::TruncSat(sub) => {
+            out.push(29);
+            out.push(sub);
+        }
+        Op::MemoryInit(idx) => {
+            out.push(30);
+            write_u32(out, idx);
+        }
+        Op::DataDrop(idx) => {
+            out.push(31);
+            write_u32(out, idx);
+        }
+        Op::MemoryCopy => out.push(32),
+        Op::MemoryFill => out.push(33),
+        Op::I32AddLocals { lhs, rhs, dst } => {
+            out.push(34);
+            for v in [lhs, rhs, dst] {
+                write_u32(out, v);
+            }
+        }
+        Op::I32AddImm(v) => {
+            out.push(35);
+            write_i64(out, i64::from(v));
+        }
+        Op::I32CmpBrIf { cmp, depth } => {
+            out.push(36);
+            out.push(cmp);
+            write_u32(out, depth);
+        }
+        Op::RefNull(ty) => {
+            out.push(37);
+            out.push(value_type_byte(ty));
+        }
+        Op::RefIsNull => out.push(38),
+        Op::RefFunc(idx) => {
+            out.push(39);
+            write_u32(out, idx);
+        }
+        Op::ConsumeFuel(cost) => {
+            out.push(40);
+            write_i64(out, cost as i64);
+        }
+        Op::Atomic { sub, offset } => {
+            out.push(41);
+            out.push(sub);
+            write_u32(out, offset);
+        }
+    }
+}
+
+/// Parse and check an artifact written by `write`, which must have been
+/// given the same `metering` as the loading store uses.
+pub(crate) fn read(
+    bytes: &[u8],
+    metering: Option<&AwwasmGasTable>,
+) -> Result<Vec<AwwasmArtifactFunc>, AwwasmRuntimeError> {
+    let mut r = AwwasmReader::new(bytes);
+    let truncated = |_| invalid("truncated");
+    if r.bytes(MAGIC.len()).map_err(truncated)? != MAGIC {
+        return Err(invalid("not a precompiled artifact"));
+    }
+    let format = r.u32().map_err(truncated)?;
+    let version = r.name().map_err(truncated)?;
+    if format != FORMAT_VERSION || version != env!("CARGO_PKG_VERSION").as_bytes() {
+        return Err(AwwasmRuntimeError::InvalidArtifact(format!(
+            "made by runtime {} (format {}), this is {} (format {})",
+            String::from_utf8_lossy(version),
+            format,
+            env!("CARGO_PKG_VERSION"),
+            FORMAT_VERSION
+        )));
+    }
+    let table_hash = match r.u8().map_err(truncated)? {
+        0 => None,
+        1 => {
+            let hash = r.bytes(8).map_err(truncated)?;
+            Some(u64::from_le_bytes([hash[0], hash[1], hash[2], hash[3], hash[4], hash[5], hash[6], hash[7]]))
+        }
+        _ => return Err(invalid("bad metering mode")),
+    };
+    // The store decides how code is metered, never the artifact.
+    if table_hash != metering.map(AwwasmGasTable::hash) {
+        return Err(invalid("metered differently from this store"));
+    }
+    let metered = metering.is_some();
+
+    let count = r.u32().map_err(truncated)?;
+    let mut funcs = Vec::new();
+    for _ in 0..count {
+        let hash = r.bytes(8).map_err(truncated)?;
+        let hash = u64::from_le_bytes([hash[0], hash[1], hash[2], hash[3], hash[4], hash[5], hash[6], hash[7]]);
+        let mut locals = Vec::new();
+        for _ in 0..r.u32().map_err(truncated)? {
+            let count = r.u32().map_err(truncated)?;
+            let type_ = decode::value_type(r.u8().map_err(truncated)?).ok_or_else(|| invalid("bad value type"))?;
+            locals.push(AwwasmLocalDecl { count, type_ });
+        }
+        let mut ops = Vec::new();
+        for _ in 0..r.u32().map_err(truncated)? {
+            ops.push(read_op(&mut r)?);
+        }
+        let mut br_tables = Vec::new();
+        for _ in 0..r.u32().map_err(truncated)? {
+            br_tables.push(r.u32().map_err(truncated)?);
+        }
+        // One cache per `call_indirect` site, so never more than there are ops
+        let sites = r.u32().map_err(truncated)? as usize;
+        if sites > ops.len() {
+            return Err(invalid("more call_indirect sites than ops"));
+        }
+        let mut call_caches = Vec::new();
+        call_caches.resize_with(sites, CallCache::default);
+
+        if !metered && ops.iter().any(|op| matches!(op, Op::ConsumeFuel(_))) {
+            return Err(invalid("fuel charged by uninstrumented code"));
+        }
+        let code = AwwasmCompiledCode { locals, ops, br_tables, call_caches, metered };
+        check(&code)?;
+        funcs.push(AwwasmArtifactFunc { hash, code });
+    }
+    if !r.is_empty() {
+        return Err(invalid("trailing bytes"));
+    }
+    Ok(funcs)
+}
+
+fn read_op(r: &mut AwwasmReader<'_>) -> Result<Op, AwwasmRuntimeError> {
+    let truncated = |_| invalid("truncated");
+    let op = match r.u8().map_err(truncated)? {
+        0 => Op::Unreachable,
+        1 => Op::Nop,
+        2 => Op::Block { params: r.u32().map_err(truncated)?, results: r.u32().map_err(truncated)?, end: r.u32().map_err(truncated)? },
+        3 => Op::Loop { params: r.u32().map_err(truncated)?, results: r.u32().map_err(truncated)? },
+        4 => Op::If {
+            params: r.u32().map_err(truncated)?,
+            results: r.u32().map_err(truncated)?,
+            else_: r.u32().map_err(truncated)?,
+            end: r.u32().map_err(truncated)?,
+        },
+        5 => Op::Else { end: r.u32().map_err(truncated)? },
+        6 => Op::End,
+        7 => Op::Br(r.u32().map_err(truncated)?),
+        8 => Op::BrIf(r.u32().map_err(truncated)?),
+        9 => Op::BrTable { start: r.u32().map_err(truncated)?, len: r.u32().map_err(truncated)? },
+        10 => Op::Return,
+        11 => Op::Call(r.u32().map_err(truncated)?),
+        12 => Op::CallIndirect {
+            type_idx: r.u32().map_err(truncated)?,
+            table_idx: r.u32().map_err(truncated)?,
+            cache: r.u32().map_err(truncated)?,
+        },
+        13 => Op::Drop,
+        14 => Op::Select,
+        15 => Op::LocalGet(r.u32().map_err(truncated)?),
+        16 => Op::LocalSet(r.u32().map_err(truncated)?),
+        17 => Op::LocalTee(r.u32().map_err(truncated)?),
+        18 => Op::GlobalGet(r.u32().map_err(truncated)?),
+        19 => Op::GlobalSet(r.u32().map_err(truncated)?),
+        20 => Op::Load { opcode: r.u8().map_err(truncated)?, offset: r.u32().map_err(truncated)? },
+        21 => Op::Store { opcode: r.u8().map_err(truncated)?, offset: r.u32().map_err(truncated)? },
+        22 => Op::MemorySize,
+        23 => Op::MemoryGrow,
+        24 => Op::I32Const(r.i32().map_err(truncated)?),
+        25 => Op::I64Const(r.i64().map_err(truncated)?),
+        26 => Op::F32Const(r.u32().map_err(truncated)?),
+        27 => Op::F64Const(r.i64().map_err(truncated)? as u64),
+        28 => Op::Num(r.u8().map_err(truncated)?),
+        29 => Op::TruncSat(r.u8().map_err(truncated)?),
+        30 => Op::MemoryInit(r.u32().map_err(truncated)?),
+        31 => Op::DataDrop(r.u32().map_err(truncated)?),
+        32 => Op::MemoryCopy,
+        33 => Op::MemoryFill,
+        34 => Op::I32AddLocals { lhs: r.u32().map_err(truncated)?, rhs: r.u32().map_err(truncated)?, dst: r.u32().map_err(truncated)? },
+        35 => Op::I32AddImm(r.i32().map_err(truncated)?),
+        36 => Op::I32CmpBrIf { cmp: r.u8().map_err(truncated)?, depth: r.u32().map_err(truncated)? },
+        37 => Op::RefNull(decode::value_type(r.u8().map_err(truncated)?).ok_or_else(|| invalid("bad value type"))?),
+        38 => Op::RefIsNull,
+        39 => Op::RefFunc(r.u32().map_err(truncated)?),
+        40 => Op::ConsumeFuel(r.i64().map_err(truncated)? as u64),
+        41 => Op::Atomic { sub: r.u8().map_err(truncated)?, offset: r.u32().map_err(truncated)? },
+        _ => return Err(invalid("unknown op")),
+    };
+    Ok(op)
+}
+
+/// Check the structural invariants `ir::lower` guarantees and the
+/// interpreter relies on: known opcodes, in-range side tables, block
+/// targets that match the nesting of `block`/`loop`/`if`/`else`/`end`,
+/// and branch depths naming an enclosing label. Operand stack heights are
+/// not checked here; the interpreter checks them as it runs.
+fn check(code: &AwwasmCompiledCode) -> Result<(), AwwasmRuntimeError> {
+    if code.locals.iter().map(|decl| u64::from(decl.count)).sum::<u64>() > u64::from(MAX_FUNCTION_LOCALS) {
+        return Err(invalid("too many locals"));
+    }
+    let mut control: Vec<(usize, Option<usize>)> = Vec::new();
+    for (i, op) in code.ops.iter().enumerate() {
+        let ok = match *op {
+            Op::Block { .. } | Op::Loop { .. } | Op::If { .. } => {
+                control.push((i, None));
+                control.len() <= MAX_BLOCK_NESTING as usize
+            }
+            Op::Else { .. } => match control.last_mut() {
+                Some(open) if matches!(code.ops[open.0], Op::If { .. }) && open.1.is_none() => {
+                    open.1 = Some(i);
+                    true
+                }
+                _ => false,
+            },
+            Op::End => match control.pop() {
+                Some((open, else_pos)) => {
+                    let end = i as u32 + 1;
+                    let targets_ok = match code.ops[open] {
+                        Op::Block { end: e, .. } => e == end,
+                        Op::If { else_, end: e, .. } => e == end && else_ == else_pos.map_or(i as u32, |pos| pos as u32 + 1),
+                        _ => true,
+                    };
+                    let else_ok = match else_pos.map(|pos| code.ops[pos]) {
+                        Some(Op::Else { end: e }) => e == end,
+                        _ => true,
+                    };
+                    targets_ok && else_ok
+                }
+                None => true,
+            },
+            // Depth `control.len()` is the function body's own label
+            Op::Br(depth) | Op::BrIf(depth) | Op::I32CmpBrIf { depth, .. } if depth as usize > control.len() => false,
+            Op::BrTable { start, len } => code
+                .br_tables
+                .get(start as usize..=start as usize + len as usize)
+                .is_some_and(|depths| depths.iter().all(|&depth| depth as usize <= control.len())),
+            Op::CallIndirect { cache, .. } => (cache as usize) < code.call_caches.len(),
+            Op::Load { opcode, .. } => (0x28..=0x35).contains(&opcode),
+            Op::Store { opcode, .. } => (0x36..=0x3e).contains(&opcode),
+            Op::Num(opcode) => (0x45..=0xc4).contains(&opcode),
+            Op::TruncSat(sub) => sub <= 7,
+            Op::Atomic { sub, .. } => sub <= 2,
+            Op::I32CmpBrIf { cmp, .. } => (0x45..=0x4f).contains(&cmp),
+            Op::RefNull(ty) => ty.is_ref(),
+            _ => true,
+        };
+        if !ok {
+            return Err(AwwasmRuntimeError::InvalidArtifact(format!("malformed op {} ({:?})", i, op)));
+        }
+    }
+    if !control.is_empty() {
+        return Err(invalid("unterminated block"));
+    }
+    Ok(())
+}
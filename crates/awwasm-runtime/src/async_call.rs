@@ -0,0 +1,103 @@
+//! Guest calls as futures that yield to their executor.
+//!
+//! `AwwasmStore::call_async` (and `api::Func::call_async`) runs a call in
+//! slices. Once a slice has spent
+//! `yield_every` units of fuel, or executed that many instructions on a
+//! store without fuel, the call pauses between two instructions, wakes
+//! its task and returns `Poll::Pending`, so the executor polls its other
+//! tasks before resuming it. No threads are involved: hundreds of guest
+//! calls multiplex fairly on one executor thread, tokio's included.
+//!
+//! A host function runs to completion inside its slice; one that blocks
+//! stalls the executor thread like any other blocking code.
+
+use alloc::vec::Vec;
+use core::future::Future;
+use core::pin::Pin;
+use core::task::{Context, Poll};
+
+use crate::error::AwwasmRuntimeError;
+use crate::exec::{self, AwwasmPausedCall, AwwasmProgress};
+use crate::func::AwwasmHost;
+use crate::store::AwwasmStore;
+use crate::values::{AwwasmFuncAddr, AwwasmValue};
+
+#[derive(Debug)]
+enum AwwasmCallState {
+    /// Not polled yet.
+    Start { addr: AwwasmFuncAddr, args: Vec<AwwasmValue> },
+    /// Waiting to be resumed.
+    Paused(AwwasmPausedCall),
+    /// Returned its output.
+    Finished,
+}
+
+/// A guest call that yields every `yield_every` units of fuel; see the
+/// module docs. Resolves to what `AwwasmStore::invoke` returns.
+///
+/// Dropping the future abandons the call and gives the store back any
+/// arena it borrowed.
+#[derive(Debug)]
+#[must_use = "futures do nothing unless polled"]
+pub struct AwwasmAsyncCall<'s, 'a, H> {
+    store: &'s mut AwwasmStore<'a>,
+    host: &'s mut H,
+    yield_every: u64,
+    yields: u64,
+    state: AwwasmCallState,
+}
+
+impl<'s, 'a, H: AwwasmHost> AwwasmAsyncCall<'s, 'a, H> {
+    pub(crate) fn new(
+        store: &'s mut AwwasmStore<'a>,
+        addr: AwwasmFuncAddr,
+        args: &[AwwasmValue],
+        host: &'s mut H,
+        yield_every: u64,
+    ) -> Self {
+        Self {
+            store,
+            host,
+            yield_every: yield_every.max(1),
+            yields: 0,
+            state: AwwasmCallState::Start { addr, args: args.to_vec() },
+        }
+    }
+
+    /// How many times the call has yielded so far.
+    pub fn yields(&self) -> u64 {
+        self.yields
+    }
+}
+
+impl<H> Drop for AwwasmAsyncCall<'_, '_, H> {
+    fn drop(&mut self) {
+        if let AwwasmCallState::Paused(paused) = core::mem::replace(&mut self.state, AwwasmCallState::Finished) {
+            exec::abandon(self.store, paused);
+        }
+    }
+}
+
+impl<H: AwwasmHost> Future for AwwasmAsyncCall<'_, '_, H> {
+    type Output = Result<Vec<AwwasmValue>, AwwasmRuntimeError>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = self.get_mut();
+        let budget = Some(this.yield_every);
+        let progress = match core::mem::replace(&mut this.state, AwwasmCallState::Finished) {
+            AwwasmCallState::Start { addr, args } => exec::start(this.store, addr, &args, this.host, budget),
+            AwwasmCallState::Paused(paused) => exec::resume(this.store, paused, this.host, budget),
+            AwwasmCallState::Finished => panic!("`AwwasmAsyncCall` polled after completion"),
+        };
+        match progress {
+            Ok(AwwasmProgress::Paused(paused)) => {
+                this.state = AwwasmCallState::Paused(paused);
+                this.yields += 1;
+                cx.waker().wake_by_ref();
+                Poll::Pending
+            }
+            Ok(AwwasmProgress::Done(results)) => Poll::Ready(Ok(results)),
+            Err(e) => Poll::Ready(Err(e)),
+        }
+    }
+}
@@ -0,0 +1,154 @@
+//! Auditing host-side access to guest memory.
+//!
+//! With an `AwwasmMemoryAuditor` set (`AwwasmStore::set_memory_auditor`,
+//! or `AwwasmMemInst::set_auditor` for a single memory), every read and
+//! write made through the `AwwasmMemInst` accessors (`read`, `write`,
+//! the typed `read_*`/`write_*`, `fill` and `copy_within`) is reported
+//! with who made it: a host function, identified by its `host_func_id`
+//! and calling module, or the embedder outside any host call. Failed
+//! accesses are reported too.
+//!
+//! The guest's own loads and stores are not reported; they cannot
+//! reach outside its memory. Neither is direct access to
+//! `AwwasmMemInst::data`, which bypasses the accessors.
+//!
+//! `AwwasmAuditLog` keeps the most recent accesses, up to a fixed
+//! number, for later review;
+//! `AwwasmAuditCallback` streams them to a closure.
+
+use alloc::boxed::Box;
+use core::fmt;
+
+#[cfg(feature = "std")]
+use std::collections::VecDeque;
+#[cfg(feature = "std")]
+use std::sync::{Mutex, MutexGuard};
+#[cfg(feature = "std")]
+use std::vec::Vec;
+
+use crate::values::AwwasmModuleAddr;
+
+/// Who accessed a memory.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum AwwasmMemAccessor {
+    /// Embedder code, outside any host function call.
+    Embedder,
+    /// A host function, while serving a call from `module` (`None` when
+    /// the embedder invoked it directly).
+    HostFunc { host_func_id: u32, module: Option<AwwasmModuleAddr> },
+}
+
+/// Whether an access read or wrote.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum AwwasmMemAccessKind {
+    Read,
+    Write,
+}
+
+/// One host-side memory access.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AwwasmMemAccess {
+    pub accessor: AwwasmMemAccessor,
+    pub kind: AwwasmMemAccessKind,
+    pub offset: u32,
+    pub size: u32,
+    /// `false` if the access was out of bounds and trapped.
+    pub ok: bool,
+}
+
+/// Receives every audited memory access.
+///
+/// Called on the thread making the access, before the accessor returns.
+pub trait AwwasmMemoryAuditor: Send + Sync + fmt::Debug {
+    /// `access` was just made.
+    fn record(&self, access: &AwwasmMemAccess);
+}
+
+/// An `AwwasmMemoryAuditor` that keeps the last `capacity` accesses.
+#[cfg(feature = "std")]
+#[derive(Debug)]
+pub struct AwwasmAuditLog {
+    capacity: usize,
+    entries: Mutex<AwwasmAuditBuf>,
+}
+
+#[cfg(feature = "std")]
+#[derive(Debug, Default)]
+struct AwwasmAuditBuf {
+    entries: VecDeque<AwwasmMemAccess>,
+    /// Accesses pushed out by newer ones.
+    dropped: u64,
+}
+
+#[cfg(feature = "std")]
+impl AwwasmAuditLog {
+    /// An empty log keeping at most `capacity` accesses; older accesses
+    /// are discarded first.
+    pub fn new(capacity: usize) -> Self {
+        Self { capacity, entries: Mutex::new(AwwasmAuditBuf::default()) }
+    }
+
+    /// The most accesses kept.
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    /// The accesses kept, oldest first.
+    pub fn entries(&self) -> Vec<AwwasmMemAccess> {
+        self.lock().entries.iter().copied().collect()
+    }
+
+    /// Remove and return the accesses kept, oldest first.
+    pub fn take(&self) -> Vec<AwwasmMemAccess> {
+        core::mem::take(&mut self.lock().entries).into()
+    }
+
+    /// How many accesses were discarded to make room for newer ones.
+    pub fn dropped(&self) -> u64 {
+        self.lock().dropped
+    }
+
+    fn lock(&self) -> MutexGuard<'_, AwwasmAuditBuf> {
+        self.entries.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+#[cfg(feature = "std")]
+impl AwwasmMemoryAuditor for AwwasmAuditLog {
+    fn record(&self, access: &AwwasmMemAccess) {
+        let mut buf = self.lock();
+        if self.capacity == 0 {
+            buf.dropped += 1;
+            return;
+        }
+        if buf.entries.len() == self.capacity {
+            buf.entries.pop_front();
+            buf.dropped += 1;
+        }
+        buf.entries.push_back(*access);
+    }
+}
+
+/// An `AwwasmMemoryAuditor` that passes every access to a closure.
+pub struct AwwasmAuditCallback {
+    callback: Box<dyn Fn(&AwwasmMemAccess) + Send + Sync>,
+}
+
+impl AwwasmAuditCallback {
+    /// Stream accesses to `callback`.
+    pub fn new(callback: impl Fn(&AwwasmMemAccess) + Send + Sync + 'static) -> Self {
+        Self { callback: Box::new(callback) }
+    }
+}
+
+impl fmt::Debug for AwwasmAuditCallback {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("AwwasmAuditCallback").finish_non_exhaustive()
+    }
+}
+
+impl AwwasmMemoryAuditor for AwwasmAuditCallback {
+    fn record(&self, access: &AwwasmMemAccess) {
+        (self.callback)(access)
+    }
+}
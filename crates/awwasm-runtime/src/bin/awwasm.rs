@@ -0,0 +1,210 @@
+//! `awwasm`: run a WebAssembly module from the command line.
+//!
+//! ```text
+//! awwasm [OPTIONS] <FILE> [ARGS...]
+//! ```
+//!
+//! `FILE` is a binary module or WebAssembly text. By default the module
+//! gets WASI imports and its `_start` export runs with `ARGS` as argv.
+//! With `--invoke NAME`, the export `NAME` is called instead with `ARGS`
+//! parsed by its parameter types (see `AwwasmValue::parse`), and its
+//! results are printed one per line. With `--repl`, commands are read
+//! from stdin instead (see `AwwasmRepl`). `--trace FILE` records every
+//! call and return as a Chrome trace (see `AwwasmTraceRecorder`).
+//!
+//! Built with `cargo run --features cli --bin awwasm`.
+
+use std::process::ExitCode;
+use std::sync::Arc;
+use std::{env, fs};
+
+use awwasm_runtime::func::AwwasmHost;
+use awwasm_runtime::values::AwwasmModuleAddr;
+use awwasm_runtime::wasi::AwwasmWasiCtx;
+use awwasm_runtime::{
+    AwwasmImports, AwwasmRepl, AwwasmRuntimeError, AwwasmSpectest, AwwasmStdPlatform, AwwasmStore,
+    AwwasmTraceRecorder, AwwasmTrap, AwwasmValue, TRAP_EXIT_CODE,
+};
+
+const USAGE: &str = "\
+usage: awwasm [OPTIONS] <FILE> [ARGS...]
+
+Run a WebAssembly module (binary or text).
+
+options:
+  --invoke <NAME>       call export NAME with ARGS as its parameters
+                        instead of running _start
+  --repl                explore the instance interactively
+  --spectest            link the spectest module instead of WASI
+  --dir <HOST[::GUEST]> give the guest access to a host directory
+  --env <KEY=VALUE>     set a guest environment variable
+  --trace <FILE>        write a Chrome trace of every call to FILE
+  -h, --help            print this help
+";
+
+/// Command-line options.
+#[derive(Debug, Default)]
+struct Options {
+    invoke: Option<String>,
+    repl: bool,
+    spectest: bool,
+    dirs: Vec<(String, String)>,
+    envs: Vec<(String, String)>,
+    trace: Option<String>,
+    file: String,
+    args: Vec<String>,
+}
+
+fn parse_options() -> Result<Options, String> {
+    let mut options = Options::default();
+    let mut args = env::args().skip(1);
+    let value = |args: &mut dyn Iterator<Item = String>, flag: &str| {
+        args.next().ok_or_else(|| format!("{} needs a value", flag))
+    };
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "-h" | "--help" => {
+                print!("{}", USAGE);
+                std::process::exit(0);
+            }
+            "--invoke" => options.invoke = Some(value(&mut args, "--invoke")?),
+            "--repl" => options.repl = true,
+            "--trace" => options.trace = Some(value(&mut args, "--trace")?),
+            "--spectest" => options.spectest = true,
+            "--dir" => {
+                let dir = value(&mut args, "--dir")?;
+                let (host, guest) = dir.split_once("::").unwrap_or((&dir, &dir));
+                options.dirs.push((host.to_string(), guest.to_string()));
+            }
+            "--env" => {
+                let var = value(&mut args, "--env")?;
+                let (key, val) = var.split_once('=').ok_or_else(|| format!("--env {}: expected KEY=VALUE", var))?;
+                options.envs.push((key.to_string(), val.to_string()));
+            }
+            "--" => {
+                options.file = args.next().ok_or("missing FILE")?;
+                break;
+            }
+            flag if flag.starts_with('-') && flag.len() > 1 => return Err(format!("unknown option {}", flag)),
+            _ => {
+                options.file = arg;
+                break;
+            }
+        }
+    }
+    if options.file.is_empty() {
+        return Err(String::from("missing FILE"));
+    }
+    options.args = args.collect();
+    Ok(options)
+}
+
+fn main() -> ExitCode {
+    let options = match parse_options() {
+        Ok(options) => options,
+        Err(e) => {
+            eprint!("error: {}\n\n{}", e, USAGE);
+            return ExitCode::from(2);
+        }
+    };
+    match run(&options) {
+        Ok(code) => code,
+        Err(e) => {
+            eprintln!("error: {}", e);
+            ExitCode::FAILURE
+        }
+    }
+}
+
+fn run(options: &Options) -> Result<ExitCode, String> {
+    let bytes = fs::read(&options.file).map_err(|e| format!("cannot read {}: {}", options.file, e))?;
+    // Text is compiled; binary modules pass through untouched.
+    let wasm = wat::parse_bytes(&bytes).map_err(|e| format!("{}: {}", options.file, e))?.into_owned();
+
+    let mut store = AwwasmStore::new();
+    let mut imports = AwwasmImports::new();
+    let mut host: Box<dyn AwwasmHost> = if options.spectest {
+        let spectest = AwwasmSpectest::new().with_echo(true);
+        spectest.add_to_imports(&mut store, &mut imports);
+        Box::new(spectest)
+    } else {
+        let mut builder = AwwasmWasiCtx::builder().arg(options.file.clone()).args(options.args.iter().cloned());
+        for (key, value) in &options.envs {
+            builder = builder.env(key.clone(), value.clone());
+        }
+        for (host_path, guest_path) in &options.dirs {
+            builder = builder.preopen_dir(host_path.clone(), guest_path.clone());
+        }
+        let wasi = builder.build().map_err(|e| e.to_string())?;
+        wasi.add_to_imports(&mut imports);
+        Box::new(wasi)
+    };
+    let module = store.instantiate_bytes(&wasm, &mut imports).map_err(|e| format!("instantiation failed: {}", e))?;
+
+    let Some(path) = &options.trace else {
+        return execute(options, &mut store, module, host.as_mut());
+    };
+    let recorder = Arc::new(AwwasmTraceRecorder::new(Arc::new(AwwasmStdPlatform::new())));
+    store.set_profiler(Some(recorder.clone()));
+    let code = execute(options, &mut store, module, host.as_mut());
+    let file = fs::File::create(path).map_err(|e| format!("cannot create {}: {}", path, e))?;
+    recorder
+        .write_chrome_trace(&store, std::io::BufWriter::new(file))
+        .map_err(|e| format!("cannot write {}: {}", path, e))?;
+    code
+}
+
+/// Run the instantiated module as `options` ask.
+fn execute(
+    options: &Options,
+    store: &mut AwwasmStore<'_>,
+    module: AwwasmModuleAddr,
+    host: &mut dyn AwwasmHost,
+) -> Result<ExitCode, String> {
+    if options.repl {
+        let stdin = std::io::stdin();
+        AwwasmRepl::new(store, module, host)
+            .run(stdin.lock(), std::io::stdout())
+            .map_err(|e| e.to_string())?;
+        return Ok(ExitCode::SUCCESS);
+    }
+    let Some(name) = &options.invoke else {
+        return match store.invoke_export(module, "_start", &[], host) {
+            Ok(_) => Ok(ExitCode::SUCCESS),
+            Err(e) => finish(e),
+        };
+    };
+    let func = store
+        .module(module)
+        .and_then(|m| m.get_func(name))
+        .ok_or_else(|| format!("no function export {:?}", name))?;
+    let ty = store.func_type(func).map_err(|e| e.to_string())?;
+    if ty.params.len() != options.args.len() {
+        return Err(format!("{} takes {} arguments, got {}", name, ty.params.len(), options.args.len()));
+    }
+    let args = ty
+        .params
+        .iter()
+        .zip(&options.args)
+        .map(|(&ty, arg)| AwwasmValue::parse(ty, arg).ok_or_else(|| format!("{:?} is not a valid {}", arg, ty)))
+        .collect::<Result<Vec<_>, _>>()?;
+    match store.invoke(func, &args, host) {
+        Ok(results) => {
+            for value in results {
+                println!("{}", value);
+            }
+            Ok(ExitCode::SUCCESS)
+        }
+        Err(e) => finish(e),
+    }
+}
+
+/// Turn a failed call into the process outcome: a WASI exit keeps its
+/// status, anything else is reported and exits like an abort.
+fn finish(e: AwwasmRuntimeError) -> Result<ExitCode, String> {
+    if let Some(AwwasmTrap::Exit(status)) = e.trap() {
+        return Ok(ExitCode::from(status.code() as u8));
+    }
+    eprintln!("error: {}", e);
+    Ok(ExitCode::from(TRAP_EXIT_CODE as u8))
+}
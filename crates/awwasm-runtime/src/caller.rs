@@ -0,0 +1,112 @@
+//! Store access from inside host functions.
+//!
+//! While a host function runs, the interpreter that called it holds no
+//! reference into the store: its operand stack and frames live in the
+//! interpreter itself. So instead of just the caller's memory it can lend
+//! the host the whole store, as an `AwwasmCaller`, through
+//! `AwwasmHost::call_with_caller`. A host function can then read any
+//! instance, or call another guest export on the same store, which runs
+//! on a fresh interpreter and returns before the outer call continues.
+//! The borrow checker keeps the store exclusive to whoever runs at the
+//! moment; nothing is aliased.
+//!
+//! Each re-entry stacks native frames, so nesting is limited to
+//! `MAX_REENTRY_DEPTH`; deeper calls trap with `CallStackExhausted`.
+
+use alloc::vec::Vec;
+
+use crate::error::{AwwasmRuntimeError, AwwasmTrap};
+use crate::func::AwwasmHost;
+use crate::memory::AwwasmMemInst;
+use crate::store::AwwasmStore;
+use crate::values::{AwwasmFuncAddr, AwwasmMemAddr, AwwasmModuleAddr, AwwasmValue};
+
+/// How many host functions may call back into guest code inside each
+/// other at once.
+pub const MAX_REENTRY_DEPTH: usize = 64;
+
+/// The store a host function was called from; see the module docs.
+#[derive(Debug)]
+pub struct AwwasmCaller<'c, 'a> {
+    store: &'c mut AwwasmStore<'a>,
+    module: Option<AwwasmModuleAddr>,
+    mem: Option<AwwasmMemAddr>,
+}
+
+impl<'c, 'a> AwwasmCaller<'c, 'a> {
+    /// A caller for a host function called from `module`, whose default
+    /// memory is `mem`. Both are `None` when the embedder invoked the host
+    /// function directly.
+    pub fn new(store: &'c mut AwwasmStore<'a>, module: Option<AwwasmModuleAddr>, mem: Option<AwwasmMemAddr>) -> Self {
+        Self { store, module, mem }
+    }
+
+    /// The calling module instance, if a guest made the call.
+    pub fn module(&self) -> Option<AwwasmModuleAddr> {
+        self.module
+    }
+
+    /// The store the host function was called from.
+    pub fn store(&self) -> &AwwasmStore<'a> {
+        self.store
+    }
+
+    /// The store, mutably.
+    pub fn store_mut(&mut self) -> &mut AwwasmStore<'a> {
+        self.store
+    }
+
+    /// The calling module's default memory: what `AwwasmHost::call` gets.
+    pub fn memory(&self) -> Option<&AwwasmMemInst> {
+        self.store.mems.get(self.mem?.0 as usize)
+    }
+
+    /// The calling module's default memory, mutably.
+    pub fn memory_mut(&mut self) -> Option<&mut AwwasmMemInst> {
+        self.store.mems.get_mut(self.mem?.0 as usize)
+    }
+
+    /// Call the function at `addr` on this store, running its host
+    /// functions through `host` (usually the host function's own `self`).
+    ///
+    /// A trap in the nested call is returned as is, so `proc_exit` and
+    /// interrupts unwind the outer call too; other errors become host
+    /// traps.
+    pub fn invoke(
+        &mut self,
+        addr: AwwasmFuncAddr,
+        args: &[AwwasmValue],
+        host: &mut dyn AwwasmHost,
+    ) -> Result<Vec<AwwasmValue>, AwwasmTrap> {
+        if self.store.reentry_depth >= MAX_REENTRY_DEPTH {
+            return Err(AwwasmTrap::CallStackExhausted);
+        }
+        self.store.reentry_depth += 1;
+        let result = self.store.invoke(addr, args, host);
+        self.store.reentry_depth -= 1;
+        result.map_err(into_trap)
+    }
+
+    /// Call the function export `name` of the calling module.
+    pub fn invoke_export(
+        &mut self,
+        name: &str,
+        args: &[AwwasmValue],
+        host: &mut dyn AwwasmHost,
+    ) -> Result<Vec<AwwasmValue>, AwwasmTrap> {
+        let addr = self
+            .module
+            .and_then(|module| self.store.module(module))
+            .and_then(|inst| inst.get_func(name))
+            .ok_or_else(|| into_trap(AwwasmRuntimeError::ExportNotFound(name.into())))?;
+        self.invoke(addr, args, host)
+    }
+}
+
+/// Surface a nested call's error to the host function that made it.
+fn into_trap(error: AwwasmRuntimeError) -> AwwasmTrap {
+    match error.trap() {
+        Some(trap) => trap.clone(),
+        None => AwwasmTrap::host(error),
+    }
+}
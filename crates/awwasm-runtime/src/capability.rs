@@ -0,0 +1,112 @@
+//! Capability policies restricting what a module may import.
+//!
+//! One host function registry, such as an `AwwasmWasiCtx` added to every
+//! import set, often serves plugins of different trust levels. An
+//! `AwwasmCapabilityPolicy` set on a plugin's `AwwasmImports` limits which
+//! of the offered imports that instantiation may bind: a module importing
+//! anything the policy denies fails with `ImportDenied`, even though the
+//! import set provides it.
+//!
+//! A policy is a default plus a list of rules, each matching a module and
+//! a field name. Patterns are exact names, `prefix*`, or `*` for
+//! anything. The last matching rule wins, so general rules come first:
+//!
+//! ```
+//! use awwasm_runtime::capability::AwwasmCapabilityPolicy;
+//!
+//! let policy = AwwasmCapabilityPolicy::allow_all()
+//!     .deny("wasi_snapshot_preview1", "*")
+//!     .allow("wasi_snapshot_preview1", "fd_write")
+//!     .deny("env", "network_*");
+//! assert!(policy.permits(b"wasi_snapshot_preview1", b"fd_write"));
+//! assert!(!policy.permits(b"wasi_snapshot_preview1", b"path_open"));
+//! assert!(!policy.permits(b"env", b"network_connect"));
+//! assert!(policy.permits(b"env", b"log"));
+//! ```
+
+use alloc::string::String;
+use alloc::vec::Vec;
+
+/// One rule of an `AwwasmCapabilityPolicy`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AwwasmCapabilityRule {
+    /// Pattern for the import's module name.
+    pub module: String,
+    /// Pattern for the import's field name.
+    pub name: String,
+    /// Whether matching imports may be bound.
+    pub allow: bool,
+}
+
+impl AwwasmCapabilityRule {
+    /// Whether this rule applies to the import `module`.`name`.
+    pub fn matches(&self, module: &[u8], name: &[u8]) -> bool {
+        pattern_matches(&self.module, module) && pattern_matches(&self.name, name)
+    }
+}
+
+/// Which imports an instantiation may bind; see the module docs.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AwwasmCapabilityPolicy {
+    /// What happens to imports no rule matches.
+    default_allow: bool,
+    rules: Vec<AwwasmCapabilityRule>,
+}
+
+impl AwwasmCapabilityPolicy {
+    /// A policy permitting every import not denied by a later rule.
+    pub fn allow_all() -> Self {
+        Self { default_allow: true, rules: Vec::new() }
+    }
+
+    /// A policy denying every import not allowed by a later rule.
+    pub fn deny_all() -> Self {
+        Self { default_allow: false, rules: Vec::new() }
+    }
+
+    /// Permit imports matching `module` and `name`.
+    pub fn allow(mut self, module: &str, name: &str) -> Self {
+        self.push(module, name, true);
+        self
+    }
+
+    /// Refuse imports matching `module` and `name`.
+    pub fn deny(mut self, module: &str, name: &str) -> Self {
+        self.push(module, name, false);
+        self
+    }
+
+    /// Append a rule; it takes precedence over every earlier one.
+    pub fn push(&mut self, module: &str, name: &str, allow: bool) {
+        self.rules.push(AwwasmCapabilityRule { module: module.into(), name: name.into(), allow });
+    }
+
+    /// The rules, in the order they were added.
+    pub fn rules(&self) -> &[AwwasmCapabilityRule] {
+        &self.rules
+    }
+
+    /// Whether the import `module`.`name` may be bound.
+    pub fn permits(&self, module: &[u8], name: &[u8]) -> bool {
+        self.rules
+            .iter()
+            .rev()
+            .find(|rule| rule.matches(module, name))
+            .map_or(self.default_allow, |rule| rule.allow)
+    }
+}
+
+impl Default for AwwasmCapabilityPolicy {
+    fn default() -> Self {
+        Self::allow_all()
+    }
+}
+
+/// `*` matches anything, `prefix*` anything starting with `prefix`, and
+/// any other pattern only itself.
+fn pattern_matches(pattern: &str, name: &[u8]) -> bool {
+    match pattern.strip_suffix('*') {
+        Some(prefix) => name.starts_with(prefix.as_bytes()),
+        None => name == pattern.as_bytes(),
+    }
+}
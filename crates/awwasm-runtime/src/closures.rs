@@ -0,0 +1,215 @@
+//! Host functions as Rust closures.
+//!
+//! `AwwasmHostFuncs` turns closures into host function instances and
+//! serves their calls as an `AwwasmHost`. Its thread mode, a marker
+//! type, decides what the closures may capture:
+//!
+//! - `AwwasmThreadSafe` (the default, `AwwasmHostFuncs::new`): closures
+//!   must be `Send + Sync`, so the set, and an `api::Store` holding it,
+//!   can move to another thread.
+//! - `AwwasmSingleThread` (`AwwasmHostFuncs::single_threaded`): closures
+//!   may capture `Rc`, `RefCell` and other thread-bound state; the set,
+//!   and anything holding it, is then neither `Send` nor `Sync`.
+//!
+//! Both are checked at compile time:
+//!
+//! ```compile_fail
+//! use std::rc::Rc;
+//! use awwasm_runtime::closures::AwwasmHostFuncs;
+//!
+//! let calls = Rc::new(std::cell::Cell::new(0));
+//! let mut funcs = AwwasmHostFuncs::new();
+//! // `Rc` is not `Send`: a thread-safe set rejects the closure.
+//! funcs.wrap(move |_, _| {
+//!     calls.set(calls.get() + 1);
+//!     Ok(Vec::new())
+//! });
+//! ```
+
+use alloc::boxed::Box;
+use alloc::vec::Vec;
+use core::fmt;
+use core::marker::PhantomData;
+
+use crate::error::{AwwasmRuntimeError, AwwasmTrap};
+use crate::func::{AwwasmFuncInst, AwwasmHost};
+use crate::memory::AwwasmMemInst;
+use crate::values::AwwasmValue;
+
+/// Default first `host_func_id` handed out to closures.
+pub const CLOSURE_HOST_FUNC_BASE: u32 = 0x4846_0000;
+
+mod sealed {
+    pub trait Sealed {}
+}
+
+/// Whether the closures of an `AwwasmHostFuncs` must be thread-safe.
+///
+/// Implemented only by `AwwasmThreadSafe` and `AwwasmSingleThread`.
+pub trait AwwasmThreadMode: sealed::Sealed + 'static {
+    /// How a closure is stored in this mode.
+    type Func: ?Sized;
+
+    /// Call a stored closure.
+    fn call(
+        func: &mut Self::Func,
+        args: &[AwwasmValue],
+        mem: Option<&mut AwwasmMemInst>,
+    ) -> Result<Vec<AwwasmValue>, AwwasmTrap>;
+}
+
+/// Closures must be `Send + Sync`; the set is too.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AwwasmThreadSafe {}
+
+/// Closures may capture thread-bound state; the set stays on its thread.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AwwasmSingleThread {}
+
+impl sealed::Sealed for AwwasmThreadSafe {}
+impl sealed::Sealed for AwwasmSingleThread {}
+
+impl AwwasmThreadMode for AwwasmThreadSafe {
+    type Func = dyn FnMut(&[AwwasmValue], Option<&mut AwwasmMemInst>) -> Result<Vec<AwwasmValue>, AwwasmTrap>
+        + Send
+        + Sync;
+
+    fn call(
+        func: &mut Self::Func,
+        args: &[AwwasmValue],
+        mem: Option<&mut AwwasmMemInst>,
+    ) -> Result<Vec<AwwasmValue>, AwwasmTrap> {
+        func(args, mem)
+    }
+}
+
+impl AwwasmThreadMode for AwwasmSingleThread {
+    type Func = dyn FnMut(&[AwwasmValue], Option<&mut AwwasmMemInst>) -> Result<Vec<AwwasmValue>, AwwasmTrap>;
+
+    fn call(
+        func: &mut Self::Func,
+        args: &[AwwasmValue],
+        mem: Option<&mut AwwasmMemInst>,
+    ) -> Result<Vec<AwwasmValue>, AwwasmTrap> {
+        func(args, mem)
+    }
+}
+
+/// A closure that may be registered in thread mode `M`.
+pub trait AwwasmHostClosure<M: AwwasmThreadMode> {
+    /// Box the closure as mode `M` stores it.
+    fn boxed(self) -> Box<M::Func>;
+}
+
+impl<F> AwwasmHostClosure<AwwasmThreadSafe> for F
+where
+    F: FnMut(&[AwwasmValue], Option<&mut AwwasmMemInst>) -> Result<Vec<AwwasmValue>, AwwasmTrap>
+        + Send
+        + Sync
+        + 'static,
+{
+    fn boxed(self) -> Box<<AwwasmThreadSafe as AwwasmThreadMode>::Func> {
+        Box::new(self)
+    }
+}
+
+impl<F> AwwasmHostClosure<AwwasmSingleThread> for F
+where
+    F: FnMut(&[AwwasmValue], Option<&mut AwwasmMemInst>) -> Result<Vec<AwwasmValue>, AwwasmTrap> + 'static,
+{
+    fn boxed(self) -> Box<<AwwasmSingleThread as AwwasmThreadMode>::Func> {
+        Box::new(self)
+    }
+}
+
+/// Closures serving host function calls, in thread mode `M`.
+pub struct AwwasmHostFuncs<M: AwwasmThreadMode = AwwasmThreadSafe> {
+    base_id: u32,
+    funcs: Vec<Box<M::Func>>,
+    mode: PhantomData<M>,
+}
+
+impl AwwasmHostFuncs<AwwasmThreadSafe> {
+    /// An empty set whose closures must be `Send + Sync`.
+    pub fn new() -> Self {
+        Self::with_mode()
+    }
+}
+
+impl AwwasmHostFuncs<AwwasmSingleThread> {
+    /// An empty set whose closures may capture thread-bound state.
+    pub fn single_threaded() -> Self {
+        Self::with_mode()
+    }
+}
+
+impl<M: AwwasmThreadMode> AwwasmHostFuncs<M> {
+    fn with_mode() -> Self {
+        Self { base_id: CLOSURE_HOST_FUNC_BASE, funcs: Vec::new(), mode: PhantomData }
+    }
+
+    /// Use a different first `host_func_id`, to avoid clashing with
+    /// other host functions registered by the embedder. Call it before
+    /// wrapping any closure.
+    pub fn with_base_id(mut self, base_id: u32) -> Self {
+        self.base_id = base_id;
+        self
+    }
+
+    /// Register `f` and return a host function instance that calls it,
+    /// for `AwwasmImports::add_func`. Fails once the ids from the base
+    /// up to `u32::MAX` are used up.
+    pub fn wrap<'a, F>(&mut self, f: F) -> Result<AwwasmFuncInst<'a>, AwwasmRuntimeError>
+    where
+        F: FnMut(&[AwwasmValue], Option<&mut AwwasmMemInst>) -> Result<Vec<AwwasmValue>, AwwasmTrap>
+            + AwwasmHostClosure<M>
+            + 'static,
+    {
+        let id = u32::try_from(self.funcs.len())
+            .ok()
+            .and_then(|n| self.base_id.checked_add(n))
+            .ok_or(AwwasmRuntimeError::HostFuncIdsExhausted { base_id: self.base_id })?;
+        self.funcs.push(f.boxed());
+        Ok(AwwasmFuncInst::host(0, id))
+    }
+
+    /// Number of registered closures.
+    pub fn len(&self) -> usize {
+        self.funcs.len()
+    }
+
+    /// Whether no closure is registered.
+    pub fn is_empty(&self) -> bool {
+        self.funcs.is_empty()
+    }
+}
+
+impl Default for AwwasmHostFuncs<AwwasmThreadSafe> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<M: AwwasmThreadMode> fmt::Debug for AwwasmHostFuncs<M> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("AwwasmHostFuncs")
+            .field("base_id", &self.base_id)
+            .field("funcs", &self.funcs.len())
+            .finish()
+    }
+}
+
+impl<M: AwwasmThreadMode> AwwasmHost for AwwasmHostFuncs<M> {
+    fn call(
+        &mut self,
+        host_func_id: u32,
+        args: &[AwwasmValue],
+        mem: Option<&mut AwwasmMemInst>,
+    ) -> Result<Vec<AwwasmValue>, AwwasmTrap> {
+        let func = host_func_id
+            .checked_sub(self.base_id)
+            .and_then(|idx| self.funcs.get_mut(idx as usize))
+            .ok_or(AwwasmTrap::InvalidHostCall { host_func_id })?;
+        M::call(&mut **func, args, mem)
+    }
+}
@@ -0,0 +1,565 @@
+//! Raw binary decoding for the parts of a module the runtime reads itself.
+//!
+//! The parser exposes typed items for the sections `store_init` has always
+//! consumed. Everything else the runtime needs (full value types in the
+//! type section, function import signatures, tables, globals, element
+//! segments, the start function) is decoded here straight from
+//! `AwwasmModule::sections`.
+
+#[cfg(feature = "alloc")]
+use alloc::vec::Vec;
+#[cfg(feature = "alloc")]
+use alloc::string::String;
+
+use awwasm_parser::components::module::AwwasmModule;
+
+use crate::error::AwwasmInstantiationError;
+use crate::info::AwwasmCustomSection;
+use crate::func::AwwasmFuncType;
+use crate::global::AwwasmGlobalType;
+use crate::memory::AwwasmMemoryType;
+use crate::table::AwwasmTableType;
+use crate::values::{AwwasmFuncAddr, AwwasmValue, AwwasmValueType};
+
+/// Section ids, as they appear in the binary format.
+pub(crate) const SECTION_CUSTOM: u8 = 0;
+pub(crate) const SECTION_TYPE: u8 = 1;
+pub(crate) const SECTION_IMPORT: u8 = 2;
+pub(crate) const SECTION_TABLE: u8 = 4;
+pub(crate) const SECTION_MEMORY: u8 = 5;
+pub(crate) const SECTION_GLOBAL: u8 = 6;
+pub(crate) const SECTION_START: u8 = 8;
+pub(crate) const SECTION_ELEMENT: u8 = 9;
+
+/// Cursor over a byte slice with LEB128 helpers.
+#[derive(Debug, Clone)]
+pub(crate) struct AwwasmReader<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+}
+
+/// Ran off the end of the input or hit a malformed encoding.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct DecodeError;
+
+impl<'a> AwwasmReader<'a> {
+    pub(crate) fn new(bytes: &'a [u8]) -> Self {
+        Self { bytes, pos: 0 }
+    }
+
+    #[inline]
+    pub(crate) fn pos(&self) -> usize {
+        self.pos
+    }
+
+    #[inline]
+    pub(crate) fn is_empty(&self) -> bool {
+        self.pos >= self.bytes.len()
+    }
+
+    #[inline]
+    pub(crate) fn u8(&mut self) -> Result<u8, DecodeError> {
+        let byte = *self.bytes.get(self.pos).ok_or(DecodeError)?;
+        self.pos += 1;
+        Ok(byte)
+    }
+
+    pub(crate) fn bytes(&mut self, len: usize) -> Result<&'a [u8], DecodeError> {
+        let end = self.pos.checked_add(len).ok_or(DecodeError)?;
+        let slice = self.bytes.get(self.pos..end).ok_or(DecodeError)?;
+        self.pos = end;
+        Ok(slice)
+    }
+
+    pub(crate) fn u32(&mut self) -> Result<u32, DecodeError> {
+        let mut result: u64 = 0;
+        let mut shift = 0;
+        loop {
+            let byte = self.u8()?;
+            result |= u64::from(byte & 0x7f) << shift;
+            if byte & 0x80 == 0 {
+                break;
+            }
+            shift += 7;
+            if shift > 28 {
+                return Err(DecodeError);
+            }
+        }
+        u32::try_from(result).map_err(|_| DecodeError)
+    }
+
+    fn signed(&mut self, bits: u32) -> Result<i64, DecodeError> {
+        let mut result: i64 = 0;
+        let mut shift = 0;
+        loop {
+            let byte = self.u8()?;
+            result |= i64::from(byte & 0x7f) << shift;
+            shift += 7;
+            if byte & 0x80 == 0 {
+                if shift < 64 && byte & 0x40 != 0 {
+                    result |= -1i64 << shift;
+                }
+                return Ok(result);
+            }
+            if shift >= bits + 7 {
+                return Err(DecodeError);
+            }
+        }
+    }
+
+    pub(crate) fn i32(&mut self) -> Result<i32, DecodeError> {
+        Ok(self.signed(32)? as i32)
+    }
+
+    pub(crate) fn i64(&mut self) -> Result<i64, DecodeError> {
+        self.signed(64)
+    }
+
+    /// Signed 33-bit integer, used for block types.
+    pub(crate) fn s33(&mut self) -> Result<i64, DecodeError> {
+        self.signed(33)
+    }
+
+    pub(crate) fn f32(&mut self) -> Result<f32, DecodeError> {
+        let b = self.bytes(4)?;
+        Ok(f32::from_bits(u32::from_le_bytes([b[0], b[1], b[2], b[3]])))
+    }
+
+    pub(crate) fn f64(&mut self) -> Result<f64, DecodeError> {
+        let b = self.bytes(8)?;
+        let mut raw = [0u8; 8];
+        raw.copy_from_slice(b);
+        Ok(f64::from_bits(u64::from_le_bytes(raw)))
+    }
+
+    pub(crate) fn name(&mut self) -> Result<&'a [u8], DecodeError> {
+        let len = self.u32()? as usize;
+        self.bytes(len)
+    }
+
+    pub(crate) fn value_type(&mut self) -> Result<AwwasmValueType, DecodeError> {
+        value_type(self.u8()?).ok_or(DecodeError)
+    }
+
+    /// Limits: `(min, max)`.
+    pub(crate) fn limits(&mut self) -> Result<(u32, Option<u32>), DecodeError> {
+        let flags = self.u8()?;
+        let min = self.u32()?;
+        let max = if flags & 1 != 0 { Some(self.u32()?) } else { None };
+        Ok((min, max))
+    }
+
+    /// Read a memory type: limits, then the page size exponent when flag
+    /// bit 3 (custom-page-sizes) is set. Bit 1 marks a shared memory,
+    /// which must have a maximum.
+    pub(crate) fn memory_type(&mut self) -> Result<AwwasmMemoryType, DecodeError> {
+        let flags = self.u8()?;
+        let min = self.u32()?;
+        let max = if flags & 1 != 0 { Some(self.u32()?) } else { None };
+        let mut ty = AwwasmMemoryType::new(min, max).with_shared(flags & 0x02 != 0);
+        if ty.shared && max.is_none() {
+            return Err(DecodeError);
+        }
+        if flags & 0x08 != 0 {
+            ty = ty.with_page_size_log2(self.u32()?);
+            if !ty.valid_page_size() {
+                return Err(DecodeError);
+            }
+        }
+        Ok(ty)
+    }
+
+    /// Consume a constant expression including its `end` and return its
+    /// bytes (without the terminator).
+    pub(crate) fn const_expr(&mut self) -> Result<&'a [u8], DecodeError> {
+        let start = self.pos;
+        loop {
+            match self.u8()? {
+                0x0b => return Ok(&self.bytes[start..self.pos - 1]),
+                0x41 => {
+                    self.i32()?;
+                }
+                0x42 => {
+                    self.i64()?;
+                }
+                0x43 => {
+                    self.bytes(4)?;
+                }
+                0x44 => {
+                    self.bytes(8)?;
+                }
+                0x23 | 0xd2 => {
+                    self.u32()?;
+                }
+                0xd0 => {
+                    self.u8()?;
+                }
+                // v128.const
+                0xfd if self.u32()? == 12 => {
+                    self.bytes(16)?;
+                }
+                _ => return Err(DecodeError),
+            }
+        }
+    }
+}
+
+/// Map a value type byte to the runtime type.
+pub(crate) fn value_type(byte: u8) -> Option<AwwasmValueType> {
+    match byte {
+        0x7f => Some(AwwasmValueType::I32),
+        0x7e => Some(AwwasmValueType::I64),
+        0x7d => Some(AwwasmValueType::F32),
+        0x7c => Some(AwwasmValueType::F64),
+        0x7b => Some(AwwasmValueType::V128),
+        0x70 => Some(AwwasmValueType::FuncRef),
+        0x6f => Some(AwwasmValueType::ExternRef),
+        _ => None,
+    }
+}
+
+/// Body of the first section with id `id`, if present.
+pub(crate) fn section<'a>(module: &AwwasmModule<'a>, id: u8) -> Option<&'a [u8]> {
+    module
+        .sections
+        .as_ref()?
+        .iter()
+        .find(|s| s.header.section_type as u8 == id)
+        .map(|s| s.section_body)
+}
+
+/// Every custom section, in module order.
+pub(crate) fn custom_sections<'a>(module: &AwwasmModule<'a>) -> Result<Vec<AwwasmCustomSection<'a>>, AwwasmInstantiationError> {
+    let Some(sections) = module.sections.as_ref() else {
+        return Ok(Vec::new());
+    };
+    let mut customs = Vec::new();
+    for section in sections.iter().filter(|s| s.header.section_type as u8 == SECTION_CUSTOM) {
+        let mut r = AwwasmReader::new(section.section_body);
+        let name = r.name().map_err(|_| malformed("custom"))?;
+        customs.push(AwwasmCustomSection { name, data: &section.section_body[r.pos()..] });
+    }
+    Ok(customs)
+}
+
+fn malformed(section: &str) -> AwwasmInstantiationError {
+    AwwasmInstantiationError::UnsupportedType {
+        description: String::from("malformed or unsupported ") + section + " section",
+    }
+}
+
+/// Decode the type section into function signatures.
+pub(crate) fn func_types(module: &AwwasmModule<'_>) -> Result<Vec<AwwasmFuncType>, AwwasmInstantiationError> {
+    let Some(body) = section(module, SECTION_TYPE) else {
+        return Ok(Vec::new());
+    };
+    let decode = || -> Result<Vec<AwwasmFuncType>, DecodeError> {
+        let mut r = AwwasmReader::new(body);
+        let count = r.u32()?;
+        let mut types = Vec::new();
+        for _ in 0..count {
+            if r.u8()? != 0x60 {
+                return Err(DecodeError);
+            }
+            let params = (0..r.u32()?).map(|_| r.value_type()).collect::<Result<_, _>>()?;
+            let results = (0..r.u32()?).map(|_| r.value_type()).collect::<Result<_, _>>()?;
+            types.push(AwwasmFuncType::new(params, results));
+        }
+        Ok(types)
+    };
+    decode().map_err(|_| malformed("type"))
+}
+
+/// What an import asks for, as declared in the import section.
+#[derive(Debug, Clone, Copy)]
+pub(crate) enum AwwasmImportDesc {
+    /// A function with this type index.
+    Func(u32),
+    Table(AwwasmTableType),
+    Memory(AwwasmMemoryType),
+    Global(AwwasmGlobalType),
+}
+
+/// One entry of the import section.
+pub(crate) struct AwwasmImportDecl<'a> {
+    pub module: &'a [u8],
+    pub name: &'a [u8],
+    pub desc: AwwasmImportDesc,
+}
+
+/// Decode the import section.
+pub(crate) fn imports<'a>(module: &AwwasmModule<'a>) -> Result<Vec<AwwasmImportDecl<'a>>, AwwasmInstantiationError> {
+    let Some(body) = section(module, SECTION_IMPORT) else {
+        return Ok(Vec::new());
+    };
+    let decode = || -> Result<Vec<AwwasmImportDecl<'a>>, DecodeError> {
+        let mut r = AwwasmReader::new(body);
+        let count = r.u32()?;
+        let mut imports = Vec::new();
+        for _ in 0..count {
+            let module = r.name()?;
+            let name = r.name()?;
+            let desc = match r.u8()? {
+                0x00 => AwwasmImportDesc::Func(r.u32()?),
+                0x01 => {
+                    if r.u8()? != 0x70 {
+                        return Err(DecodeError);
+                    }
+                    let (min, max) = r.limits()?;
+                    AwwasmImportDesc::Table(AwwasmTableType::funcref(min, max))
+                }
+                0x02 => AwwasmImportDesc::Memory(r.memory_type()?),
+                0x03 => {
+                    let value_type = r.value_type()?;
+                    let mutable = r.u8()? != 0;
+                    AwwasmImportDesc::Global(AwwasmGlobalType { mutable, value_type })
+                }
+                _ => return Err(DecodeError),
+            };
+            imports.push(AwwasmImportDecl { module, name, desc });
+        }
+        Ok(imports)
+    };
+    decode().map_err(|_| malformed("import"))
+}
+
+/// Type indices declared by the function imports, in import order.
+pub(crate) fn import_func_type_idxs(module: &AwwasmModule<'_>) -> Result<Vec<u32>, AwwasmInstantiationError> {
+    Ok(imports(module)?
+        .into_iter()
+        .filter_map(|import| match import.desc {
+            AwwasmImportDesc::Func(idx) => Some(idx),
+            _ => None,
+        })
+        .collect())
+}
+
+/// Types declared by the global imports, in import order.
+pub(crate) fn import_global_types(module: &AwwasmModule<'_>) -> Result<Vec<AwwasmGlobalType>, AwwasmInstantiationError> {
+    Ok(imports(module)?
+        .into_iter()
+        .filter_map(|import| match import.desc {
+            AwwasmImportDesc::Global(ty) => Some(ty),
+            _ => None,
+        })
+        .collect())
+}
+
+/// Types declared by the table imports, in import order.
+pub(crate) fn import_table_types(module: &AwwasmModule<'_>) -> Result<Vec<AwwasmTableType>, AwwasmInstantiationError> {
+    Ok(imports(module)?
+        .into_iter()
+        .filter_map(|import| match import.desc {
+            AwwasmImportDesc::Table(ty) => Some(ty),
+            _ => None,
+        })
+        .collect())
+}
+
+/// Memory types declared by the memory imports, in import order.
+pub(crate) fn import_memory_types(module: &AwwasmModule<'_>) -> Result<Vec<AwwasmMemoryType>, AwwasmInstantiationError> {
+    Ok(imports(module)?
+        .into_iter()
+        .filter_map(|import| match import.desc {
+            AwwasmImportDesc::Memory(ty) => Some(ty),
+            _ => None,
+        })
+        .collect())
+}
+
+/// Decode the memory section.
+pub(crate) fn memories(module: &AwwasmModule<'_>) -> Result<Vec<AwwasmMemoryType>, AwwasmInstantiationError> {
+    let Some(body) = section(module, SECTION_MEMORY) else {
+        return Ok(Vec::new());
+    };
+    let decode = || -> Result<Vec<AwwasmMemoryType>, DecodeError> {
+        let mut r = AwwasmReader::new(body);
+        let count = r.u32()?;
+        let mut memories = Vec::new();
+        for _ in 0..count {
+            memories.push(r.memory_type()?);
+        }
+        Ok(memories)
+    };
+    decode().map_err(|_| malformed("memory"))
+}
+
+/// Decode the start section into its function index.
+pub(crate) fn start(module: &AwwasmModule<'_>) -> Result<Option<u32>, AwwasmInstantiationError> {
+    let Some(body) = section(module, SECTION_START) else {
+        return Ok(None);
+    };
+    let mut r = AwwasmReader::new(body);
+    match r.u32() {
+        Ok(idx) if r.is_empty() => Ok(Some(idx)),
+        _ => Err(malformed("start")),
+    }
+}
+
+/// Decode the table section.
+pub(crate) fn tables(module: &AwwasmModule<'_>) -> Result<Vec<AwwasmTableType>, AwwasmInstantiationError> {
+    let Some(body) = section(module, SECTION_TABLE) else {
+        return Ok(Vec::new());
+    };
+    let decode = || -> Result<Vec<AwwasmTableType>, DecodeError> {
+        let mut r = AwwasmReader::new(body);
+        let count = r.u32()?;
+        let mut tables = Vec::new();
+        for _ in 0..count {
+            if r.u8()? != 0x70 {
+                return Err(DecodeError);
+            }
+            let (min, max) = r.limits()?;
+            tables.push(AwwasmTableType::funcref(min, max));
+        }
+        Ok(tables)
+    };
+    decode().map_err(|_| malformed("table"))
+}
+
+/// Decode the global section into types and initializer expressions.
+pub(crate) fn globals<'a>(
+    module: &AwwasmModule<'a>,
+) -> Result<Vec<(AwwasmGlobalType, &'a [u8])>, AwwasmInstantiationError> {
+    let Some(body) = section(module, SECTION_GLOBAL) else {
+        return Ok(Vec::new());
+    };
+    let decode = || -> Result<Vec<(AwwasmGlobalType, &'a [u8])>, DecodeError> {
+        let mut r = AwwasmReader::new(body);
+        let count = r.u32()?;
+        let mut globals = Vec::new();
+        for _ in 0..count {
+            let value_type = r.value_type()?;
+            let type_ = match r.u8()? {
+                0 => AwwasmGlobalType::immutable(value_type),
+                1 => AwwasmGlobalType::mutable(value_type),
+                _ => return Err(DecodeError),
+            };
+            globals.push((type_, r.const_expr()?));
+        }
+        Ok(globals)
+    };
+    decode().map_err(|_| malformed("global"))
+}
+
+/// How an element segment is used at instantiation.
+pub(crate) enum AwwasmElemMode<'a> {
+    /// Copied into `table` at the offset the expression evaluates to.
+    Active { table: u32, offset: &'a [u8] },
+    /// Only declares its functions as referenceable by `ref.func`.
+    Declarative,
+}
+
+/// An element segment: its mode and the function indices it lists.
+pub(crate) struct AwwasmElemSegment<'a> {
+    pub(crate) mode: AwwasmElemMode<'a>,
+    pub(crate) funcs: Vec<u32>,
+}
+
+/// Decode the element section.
+///
+/// Understood are the active function-index encodings (flags 0 and 2
+/// with elemkind 0x00) and declarative segments in either form (flags 3
+/// and 7). A declarative expression list may only hold `ref.func` and
+/// `ref.null func`; null entries declare nothing and are left out.
+/// Other segment kinds are reported as unsupported.
+pub(crate) fn elems<'a>(module: &AwwasmModule<'a>) -> Result<Vec<AwwasmElemSegment<'a>>, AwwasmInstantiationError> {
+    let Some(body) = section(module, SECTION_ELEMENT) else {
+        return Ok(Vec::new());
+    };
+    let decode = || -> Result<Vec<AwwasmElemSegment<'a>>, DecodeError> {
+        let mut r = AwwasmReader::new(body);
+        let count = r.u32()?;
+        let mut elems = Vec::new();
+        for _ in 0..count {
+            let flags = r.u32()?;
+            let mode = match flags {
+                0 => AwwasmElemMode::Active { table: 0, offset: r.const_expr()? },
+                2 => {
+                    let table = r.u32()?;
+                    let offset = r.const_expr()?;
+                    AwwasmElemMode::Active { table, offset }
+                }
+                3 | 7 => AwwasmElemMode::Declarative,
+                _ => return Err(DecodeError),
+            };
+            // elemkind 0x00 for index lists, reftype funcref for expressions
+            let kind = if flags == 0 { 0x00 } else { r.u8()? };
+            let funcs = match (flags, kind) {
+                (7, 0x70) => {
+                    let mut funcs = Vec::new();
+                    for _ in 0..r.u32()? {
+                        let expr = r.const_expr()?;
+                        match const_ref_func(expr) {
+                            Some(idx) => funcs.push(idx),
+                            None if expr == [0xd0, 0x70] => {}
+                            None => return Err(DecodeError),
+                        }
+                    }
+                    funcs
+                }
+                (0 | 2 | 3, 0x00) => (0..r.u32()?).map(|_| r.u32()).collect::<Result<_, _>>()?,
+                _ => return Err(DecodeError),
+            };
+            elems.push(AwwasmElemSegment { mode, funcs });
+        }
+        Ok(elems)
+    };
+    decode().map_err(|_| malformed("element"))
+}
+
+/// Evaluate a constant expression to a value.
+///
+/// `globals` are the values of the globals visible to the expression
+/// (the imported ones, per the spec) and `funcs` the module's function
+/// addresses, for `ref.func`.
+pub(crate) fn eval_const_value(
+    code: &[u8],
+    globals: &[AwwasmValue],
+    funcs: &[AwwasmFuncAddr],
+) -> Result<AwwasmValue, AwwasmInstantiationError> {
+    let invalid = || AwwasmInstantiationError::InvalidConstExpr {
+        description: String::from("unsupported constant expression"),
+        source: None,
+    };
+    let mut r = AwwasmReader::new(code);
+    let value = match r.u8().map_err(|_| invalid())? {
+        0x41 => AwwasmValue::I32(r.i32().map_err(|_| invalid())?),
+        0x42 => AwwasmValue::I64(r.i64().map_err(|_| invalid())?),
+        0x43 => AwwasmValue::F32(r.f32().map_err(|_| invalid())?),
+        0x44 => AwwasmValue::F64(r.f64().map_err(|_| invalid())?),
+        0xfd if r.u32().map_err(|_| invalid())? == 12 => {
+            let mut raw = [0u8; 16];
+            raw.copy_from_slice(r.bytes(16).map_err(|_| invalid())?);
+            AwwasmValue::V128(u128::from_le_bytes(raw))
+        }
+        0x23 => {
+            let idx = r.u32().map_err(|_| invalid())?;
+            *globals.get(idx as usize).ok_or_else(invalid)?
+        }
+        0xd0 => match value_type(r.u8().map_err(|_| invalid())?) {
+            Some(ty) if ty.is_ref() => AwwasmValue::default_for_type(ty),
+            _ => return Err(invalid()),
+        },
+        0xd2 => {
+            let idx = r.u32().map_err(|_| invalid())?;
+            AwwasmValue::FuncRef(Some(*funcs.get(idx as usize).ok_or_else(invalid)?))
+        }
+        _ => return Err(invalid()),
+    };
+    if !r.is_empty() {
+        return Err(invalid());
+    }
+    Ok(value)
+}
+
+/// The function index of a constant expression that is a single
+/// `ref.func`.
+pub(crate) fn const_ref_func(code: &[u8]) -> Option<u32> {
+    let mut r = AwwasmReader::new(code);
+    if r.u8().ok()? != 0xd2 {
+        return None;
+    }
+    let idx = r.u32().ok()?;
+    r.is_empty().then_some(idx)
+}
@@ -0,0 +1,320 @@
+//! Differential execution against a reference interpreter.
+//!
+//! `AwwasmDiffHarness` instantiates one module in both AwWasm and
+//! [wasmi](https://docs.rs/wasmi), runs the same calls in each and
+//! compares what comes back: result values, whether and how a call
+//! trapped, and afterwards every exported memory and global. A
+//! difference is reported as an `AwwasmDivergence`.
+//!
+//! Meant for fuzz targets and tests, behind the `differential` feature:
+//!
+//! ```ignore
+//! fuzz_target!(|module: wasm_smith::Module| {
+//!     if let Err(divergence) = diff_exports(&module.to_bytes()) {
+//!         panic!("{}", divergence);
+//!     }
+//! });
+//! ```
+//!
+//! Some differences are not bugs, so they are skipped rather than
+//! reported:
+//!
+//! - NaN payloads, which the spec leaves nondeterministic (any NaN equals
+//!   any other);
+//! - exhausting the call stack, whose depth differs between engines
+//!   (such a call ends the comparison);
+//! - modules either engine rejects, or that have imports or a start
+//!   function, and values neither side can represent (`v128`, non-null
+//!   references).
+
+use std::fmt;
+use std::string::{String, ToString};
+use std::vec::Vec;
+
+use wasmi::core::{TrapCode, F32, F64};
+use wasmi::{ExternRef, FuncRef, Val};
+
+use crate::error::{AwwasmTrap, TrapKind};
+use crate::func::AwwasmHost;
+use crate::imports::AwwasmImports;
+use crate::memory::AwwasmMemInst;
+use crate::store::AwwasmStore;
+use crate::values::{AwwasmExternAddr, AwwasmModuleAddr, AwwasmValue, AwwasmValueType};
+
+/// Reason given for calls that ran out of stack in either engine.
+const STACK_EXHAUSTED: &str = "call stack exhausted";
+
+/// How a call ended, in terms both engines share.
+#[derive(Debug, Clone, PartialEq)]
+pub enum AwwasmDiffOutcome {
+    /// Both engines returned these values.
+    Returned(Vec<AwwasmValue>),
+    /// Both engines trapped this way.
+    Trapped(TrapKind),
+    /// The call could not be compared; the reason says why.
+    Skipped(String),
+}
+
+/// The engines disagreed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AwwasmDivergence {
+    /// What was being compared, e.g. the call or the memory export.
+    pub context: String,
+    /// What AwWasm did.
+    pub awwasm: String,
+    /// What the reference engine did.
+    pub reference: String,
+}
+
+impl fmt::Display for AwwasmDivergence {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: awwasm {}, reference {}", self.context, self.awwasm, self.reference)
+    }
+}
+
+impl std::error::Error for AwwasmDivergence {}
+
+/// One module instantiated in both engines.
+pub struct AwwasmDiffHarness<'a> {
+    store: AwwasmStore<'a>,
+    instance: AwwasmModuleAddr,
+    reference: wasmi::Store<()>,
+    reference_instance: wasmi::Instance,
+}
+
+impl<'a> AwwasmDiffHarness<'a> {
+    /// Instantiate `wasm` in both engines.
+    ///
+    /// `Err` carries the reason the module cannot be compared.
+    pub fn new(wasm: &'a [u8]) -> Result<Self, String> {
+        let engine = wasmi::Engine::default();
+        let module = wasmi::Module::new(&engine, wasm).map_err(|e| format!("reference rejects the module: {}", e))?;
+        if module.imports().next().is_some() {
+            return Err(String::from("the module has imports"));
+        }
+        let mut reference = wasmi::Store::new(&engine, ());
+        let reference_instance = wasmi::Linker::<()>::new(&engine)
+            .instantiate(&mut reference, &module)
+            .and_then(|pre| pre.ensure_no_start(&mut reference).map_err(Into::into))
+            .map_err(|e| format!("reference cannot instantiate the module: {}", e))?;
+
+        let mut store = AwwasmStore::new();
+        let instance = store
+            .instantiate_bytes(wasm, &mut AwwasmImports::new())
+            .map_err(|e| format!("awwasm cannot instantiate the module: {}", e))?;
+        Ok(Self { store, instance, reference, reference_instance })
+    }
+
+    /// Call the export `name` with `args` in both engines and compare.
+    pub fn call(&mut self, name: &str, args: &[AwwasmValue]) -> Result<AwwasmDiffOutcome, AwwasmDivergence> {
+        let context = || format!("call {}({:?})", name, args);
+        let Some(func) = self.reference_instance.get_func(&self.reference, name) else {
+            return Ok(AwwasmDiffOutcome::Skipped(format!("no function export {}", name)));
+        };
+        let Some(inputs) = args.iter().map(to_reference).collect::<Option<Vec<_>>>() else {
+            return Ok(AwwasmDiffOutcome::Skipped(String::from("arguments the reference cannot take")));
+        };
+        let mut outputs: Vec<Val> = func.ty(&self.reference).results().iter().map(|&ty| Val::default(ty)).collect();
+        let expected = func
+            .call(&mut self.reference, &inputs, &mut outputs)
+            .map(|()| outputs.iter().map(from_reference).collect::<Option<Vec<_>>>());
+        let actual = self.store.invoke_export(self.instance, name, args, &mut NoHost);
+
+        match (actual, expected) {
+            (Ok(_), Ok(None)) => Ok(AwwasmDiffOutcome::Skipped(String::from("results awwasm cannot represent"))),
+            (Ok(actual), Ok(Some(expected))) => {
+                let same = actual.len() == expected.len() && actual.iter().zip(&expected).all(|(a, b)| same_value(a, b));
+                if !same {
+                    return Err(AwwasmDivergence {
+                        context: context(),
+                        awwasm: format!("returned {:?}", actual),
+                        reference: format!("returned {:?}", expected),
+                    });
+                }
+                Ok(AwwasmDiffOutcome::Returned(actual))
+            }
+            (Err(actual), expected) => {
+                let kind = actual.trap().map(|t| t.kind());
+                let expected_kind = expected.as_ref().err().and_then(|e| e.as_trap_code()).and_then(trap_kind);
+                if exhausted(kind) || exhausted(expected_kind) {
+                    return Ok(AwwasmDiffOutcome::Skipped(String::from(STACK_EXHAUSTED)));
+                }
+                match kind {
+                    Some(kind) if Some(kind) == expected_kind => Ok(AwwasmDiffOutcome::Trapped(kind)),
+                    // Not a trap: an instruction AwWasm does not support.
+                    None => Ok(AwwasmDiffOutcome::Skipped(format!("awwasm cannot run the call: {}", actual))),
+                    Some(_) => Err(AwwasmDivergence {
+                        context: context(),
+                        awwasm: format!("failed with {}", actual),
+                        reference: match expected {
+                            Ok(values) => format!("returned {:?}", values),
+                            Err(e) => format!("failed with {}", e),
+                        },
+                    }),
+                }
+            }
+            (Ok(actual), Err(expected)) => {
+                if exhausted(expected.as_trap_code().and_then(trap_kind)) {
+                    return Ok(AwwasmDiffOutcome::Skipped(String::from(STACK_EXHAUSTED)));
+                }
+                Err(AwwasmDivergence {
+                    context: context(),
+                    awwasm: format!("returned {:?}", actual),
+                    reference: format!("failed with {}", expected),
+                })
+            }
+        }
+    }
+
+    /// Compare every exported memory and global.
+    pub fn compare_state(&self) -> Result<(), AwwasmDivergence> {
+        let inst = self.store.module(self.instance).expect("harness instance");
+        for export in &inst.exports {
+            let name = String::from_utf8_lossy(export.name).to_string();
+            match export.addr {
+                AwwasmExternAddr::Mem(addr) => {
+                    let actual = &self.store.mems[addr.0 as usize].data.to_vec()[..];
+                    let Some(memory) = self.reference_instance.get_memory(&self.reference, &name) else {
+                        continue;
+                    };
+                    let expected = memory.data(&self.reference);
+                    if actual != expected {
+                        let at = actual.iter().zip(expected).position(|(a, b)| a != b);
+                        return Err(AwwasmDivergence {
+                            context: format!("memory {}", name),
+                            awwasm: describe_memory(actual, at),
+                            reference: describe_memory(expected, at),
+                        });
+                    }
+                }
+                AwwasmExternAddr::Global(addr) => {
+                    let actual = self.store.globals[addr.0 as usize].value;
+                    let Some(global) = self.reference_instance.get_global(&self.reference, &name) else {
+                        continue;
+                    };
+                    let Some(expected) = from_reference(&global.get(&self.reference)) else {
+                        continue;
+                    };
+                    if !same_value(&actual, &expected) {
+                        return Err(AwwasmDivergence {
+                            context: format!("global {}", name),
+                            awwasm: format!("{:?}", actual),
+                            reference: format!("{:?}", expected),
+                        });
+                    }
+                }
+                _ => {}
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Instantiate `wasm` in both engines, call every exported function once
+/// with zero arguments, in export order, and compare the results and the
+/// state after each call.
+///
+/// Modules that cannot be compared pass.
+pub fn diff_exports(wasm: &[u8]) -> Result<Vec<AwwasmDiffOutcome>, AwwasmDivergence> {
+    let Ok(mut harness) = AwwasmDiffHarness::new(wasm) else {
+        return Ok(Vec::new());
+    };
+    let inst = harness.store.module(harness.instance).expect("harness instance");
+    let mut calls = Vec::new();
+    for export in &inst.exports {
+        if let AwwasmExternAddr::Func(addr) = export.addr {
+            let Ok(ty) = harness.store.func_type(addr) else {
+                continue;
+            };
+            let args: Vec<AwwasmValue> = ty.params.iter().map(|&t| AwwasmValue::default_for_type(t)).collect();
+            calls.push((String::from_utf8_lossy(export.name).to_string(), args));
+        }
+    }
+    let mut outcomes = Vec::new();
+    for (name, args) in calls {
+        let outcome = harness.call(&name, &args)?;
+        // The engines unwound at different depths, so their states differ.
+        let stop = matches!(&outcome, AwwasmDiffOutcome::Skipped(why) if why == STACK_EXHAUSTED);
+        outcomes.push(outcome);
+        if stop {
+            break;
+        }
+        harness.compare_state()?;
+    }
+    Ok(outcomes)
+}
+
+/// Host for modules without imports.
+struct NoHost;
+
+impl AwwasmHost for NoHost {
+    fn call(
+        &mut self,
+        host_func_id: u32,
+        _args: &[AwwasmValue],
+        _mem: Option<&mut AwwasmMemInst>,
+    ) -> Result<Vec<AwwasmValue>, AwwasmTrap> {
+        Err(AwwasmTrap::InvalidHostCall { host_func_id })
+    }
+}
+
+fn to_reference(value: &AwwasmValue) -> Option<Val> {
+    Some(match *value {
+        AwwasmValue::I32(v) => Val::I32(v),
+        AwwasmValue::I64(v) => Val::I64(v),
+        AwwasmValue::F32(v) => Val::F32(F32::from_bits(v.to_bits())),
+        AwwasmValue::F64(v) => Val::F64(F64::from_bits(v.to_bits())),
+        AwwasmValue::FuncRef(None) => Val::FuncRef(FuncRef::null()),
+        AwwasmValue::ExternRef(None) => Val::ExternRef(ExternRef::null()),
+        _ => return None,
+    })
+}
+
+fn from_reference(value: &Val) -> Option<AwwasmValue> {
+    Some(match value {
+        Val::I32(v) => AwwasmValue::I32(*v),
+        Val::I64(v) => AwwasmValue::I64(*v),
+        Val::F32(v) => AwwasmValue::F32(f32::from_bits(v.to_bits())),
+        Val::F64(v) => AwwasmValue::F64(f64::from_bits(v.to_bits())),
+        Val::FuncRef(f) if f.is_null() => AwwasmValue::default_for_type(AwwasmValueType::FuncRef),
+        Val::ExternRef(e) if e.is_null() => AwwasmValue::default_for_type(AwwasmValueType::ExternRef),
+        _ => return None,
+    })
+}
+
+/// Bitwise equality, except that all NaNs are equal.
+fn same_value(a: &AwwasmValue, b: &AwwasmValue) -> bool {
+    match (a, b) {
+        (AwwasmValue::F32(a), AwwasmValue::F32(b)) => a.to_bits() == b.to_bits() || (a.is_nan() && b.is_nan()),
+        (AwwasmValue::F64(a), AwwasmValue::F64(b)) => a.to_bits() == b.to_bits() || (a.is_nan() && b.is_nan()),
+        // A non-null reference never reaches here; see `from_reference`.
+        (AwwasmValue::FuncRef(a), AwwasmValue::FuncRef(b)) => a.is_none() && b.is_none(),
+        _ => a == b,
+    }
+}
+
+fn exhausted(kind: Option<TrapKind>) -> bool {
+    matches!(kind, Some(TrapKind::StackOverflow | TrapKind::CallStackExhausted))
+}
+
+fn trap_kind(code: TrapCode) -> Option<TrapKind> {
+    Some(match code {
+        TrapCode::UnreachableCodeReached => TrapKind::Unreachable,
+        TrapCode::MemoryOutOfBounds => TrapKind::MemoryOutOfBounds,
+        TrapCode::TableOutOfBounds => TrapKind::TableOutOfBounds,
+        TrapCode::IndirectCallToNull => TrapKind::IndirectCallToNull,
+        TrapCode::IntegerDivisionByZero => TrapKind::DivisionByZero,
+        TrapCode::IntegerOverflow => TrapKind::IntegerOverflow,
+        TrapCode::BadConversionToInteger => TrapKind::InvalidConversionToInteger,
+        TrapCode::StackOverflow => TrapKind::StackOverflow,
+        TrapCode::BadSignature => TrapKind::IndirectCallTypeMismatch,
+        _ => return None,
+    })
+}
+
+fn describe_memory(data: &[u8], first_difference: Option<usize>) -> String {
+    match first_difference {
+        Some(at) => format!("has {:#04x} at {:#x}", data[at], at),
+        None => format!("is {} bytes", data.len()),
+    }
+}
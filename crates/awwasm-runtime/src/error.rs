@@ -2,9 +2,18 @@
 
 #[cfg(feature = "alloc")]
 use alloc::string::String;
+#[cfg(feature = "alloc")]
+use alloc::boxed::Box;
+#[cfg(feature = "alloc")]
+use alloc::sync::Arc;
+
+use core::fmt;
+
+use crate::values::{AwwasmExternKind, AwwasmValueType};
 
 /// Errors that can occur during module instantiation.
 #[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum AwwasmInstantiationError {
     /// Import not found in provided imports
     MissingImport {
@@ -22,6 +31,10 @@ pub enum AwwasmInstantiationError {
     MemoryAllocationFailed {
         requested_pages: u32,
     },
+    /// Table allocation failed
+    TableAllocationFailed {
+        requested_elems: u32,
+    },
     /// Data segment out of bounds
     DataSegmentOutOfBounds {
         segment_idx: u32,
@@ -45,16 +58,203 @@ pub enum AwwasmInstantiationError {
     /// Invalid constant initializer expression
     InvalidConstExpr {
         description: String,
+        /// The parser's error, when the parser rejected the expression
+        source: Option<AwwasmParseError>,
     },
     /// Function/code section count mismatch
     FuncCodeMismatch {
         func_count: u32,
         code_count: u32,
     },
+    /// An export refers to an index past the end of its index space
+    ExportIndexOutOfBounds {
+        kind: AwwasmExternKind,
+        name: String,
+        index: u32,
+        count: u32,
+    },
+    /// The parser rejected the module bytes
+    InvalidModule(AwwasmParseError),
+    /// A module instantiated in parallel imports something that lives in
+    /// the destination store, which its shard cannot see
+    ShardImport {
+        module: String,
+        name: String,
+    },
+    /// The module is larger than the store's module limits allow
+    ModuleLimitExceeded {
+        limit: AwwasmModuleLimit,
+        max: u32,
+        actual: u64,
+    },
+    /// The store's module gate refused the module. `hash` is its SHA-256
+    /// in hex, or `None` for a parsed module, which a gated store cannot
+    /// hash
+    ModuleNotPermitted {
+        hash: Option<String>,
+    },
+    /// The import set's capability policy forbids binding this import
+    ImportDenied {
+        module: String,
+        name: String,
+    },
+    /// Initializing the module takes more work than the store's
+    /// instantiation limits allow
+    InstantiationCostExceeded {
+        cost: AwwasmInstantiationCost,
+        max: u64,
+        actual: u64,
+    },
+    /// An import or export name is not UTF-8, and the store's module
+    /// limits require it to be. `index` counts imports or exports
+    InvalidName {
+        position: AwwasmNamePosition,
+        index: u32,
+    },
+    /// The start section names a function past the end of the function
+    /// index space
+    StartIndexOutOfBounds {
+        index: u32,
+        count: u32,
+    },
+}
+
+/// Which of the store's `AwwasmModuleLimits` a module exceeded.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum AwwasmModuleLimit {
+    /// Functions, imports included.
+    Funcs,
+    /// Declared locals of one function.
+    Locals,
+    /// Memories, imports included.
+    Memories,
+    /// Tables, imports included.
+    Tables,
+    /// Globals, imports included.
+    Globals,
+    /// Bytes of one data segment.
+    DataSegmentBytes,
+    /// Bytes of one export name.
+    ExportNameBytes,
+    /// Bytes of one import module or field name.
+    ImportNameBytes,
+    /// Declared maximum pages of one shared memory.
+    SharedMemoryPages,
+}
+
+impl fmt::Display for AwwasmModuleLimit {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            AwwasmModuleLimit::Funcs => "functions",
+            AwwasmModuleLimit::Locals => "locals in a function",
+            AwwasmModuleLimit::Memories => "memories",
+            AwwasmModuleLimit::Tables => "tables",
+            AwwasmModuleLimit::Globals => "globals",
+            AwwasmModuleLimit::DataSegmentBytes => "bytes in a data segment",
+            AwwasmModuleLimit::ExportNameBytes => "bytes in an export name",
+            AwwasmModuleLimit::ImportNameBytes => "bytes in an import name",
+            AwwasmModuleLimit::SharedMemoryPages => "maximum pages in a shared memory",
+        })
+    }
+}
+
+/// Where an `InvalidName` was found.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum AwwasmNamePosition {
+    /// The module name of an import.
+    ImportModule,
+    /// The field name of an import.
+    ImportField,
+    /// An export name.
+    Export,
+}
+
+impl fmt::Display for AwwasmNamePosition {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            AwwasmNamePosition::ImportModule => "module name of import",
+            AwwasmNamePosition::ImportField => "field name of import",
+            AwwasmNamePosition::Export => "name of export",
+        })
+    }
+}
+
+/// Which of the store's `AwwasmInstantiationLimits` a module exceeded.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum AwwasmInstantiationCost {
+    /// Bytes copied into memory by active data segments.
+    DataBytes,
+    /// Constant expressions evaluated.
+    ConstExprs,
+    /// Entries of all element segments.
+    Elements,
+}
+
+impl fmt::Display for AwwasmInstantiationCost {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            AwwasmInstantiationCost::DataBytes => "bytes of active data segments",
+            AwwasmInstantiationCost::ConstExprs => "constant expressions",
+            AwwasmInstantiationCost::Elements => "element segment entries",
+        })
+    }
+}
+
+/// An error reported by `awwasm-parser`.
+///
+/// The parser's error chain is captured as text so the runtime error
+/// types stay `Clone + Eq`; `source()` walks the same chain.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct AwwasmParseError {
+    message: String,
+    source: Option<Box<AwwasmParseError>>,
+}
+
+impl AwwasmParseError {
+    /// Create an error without a cause.
+    pub fn new(message: impl Into<String>) -> Self {
+        Self { message: message.into(), source: None }
+    }
+
+    /// Build an error from a chain of messages, outermost first.
+    ///
+    /// Returns `None` for an empty chain.
+    pub fn from_chain<I>(chain: I) -> Option<Self>
+    where
+        I: IntoIterator,
+        I::Item: Into<String>,
+        I::IntoIter: DoubleEndedIterator,
+    {
+        chain.into_iter().rev().fold(None, |source, message| {
+            Some(Self { message: message.into(), source: source.map(Box::new) })
+        })
+    }
+
+    /// The message of this error alone, without its causes.
+    pub fn message(&self) -> &str {
+        &self.message
+    }
+}
+
+impl fmt::Display for AwwasmParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.message)
+    }
+}
+
+impl core::error::Error for AwwasmParseError {
+    fn source(&self) -> Option<&(dyn core::error::Error + 'static)> {
+        self.source.as_deref().map(|e| e as _)
+    }
 }
 
 /// Runtime trap - an unrecoverable error during execution.
 #[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum AwwasmTrap {
     /// Division by zero
     DivisionByZero,
@@ -73,10 +273,11 @@ pub enum AwwasmTrap {
         index: u32,
         table_size: u32,
     },
-    /// Indirect call type mismatch
+    /// Indirect call type mismatch: the signature the call site expects
+    /// and the callee's, in text format
     IndirectCallTypeMismatch {
-        expected_type: u32,
-        actual_type: u32,
+        expected: String,
+        got: String,
     },
     /// Indirect call to null reference
     IndirectCallToNull,
@@ -86,10 +287,327 @@ pub enum AwwasmTrap {
     StackOverflow,
     /// Call stack exhausted
     CallStackExhausted,
+    /// Host function called with an unknown id or mismatched arguments
+    InvalidHostCall {
+        host_func_id: u32,
+    },
+    /// The guest asked to terminate (e.g. WASI `proc_exit`).
+    ///
+    /// Not a fault: it only uses the trap path to unwind execution.
+    Exit(ExitStatus),
+    /// Execution was stopped through an `AwwasmInterruptHandle`
+    Interrupted,
+    /// A host function aborted execution with its own error
+    #[cfg(feature = "alloc")]
+    Host(AwwasmHostError),
+    /// The store's fuel does not cover the next instruction
+    OutOfFuel,
+    /// An atomic access to an address that is not a multiple of its size
+    UnalignedAtomic {
+        offset: u32,
+    },
+    /// `memory.atomic.wait` on a memory that is not shared
+    ExpectedSharedMemory,
+}
+
+/// Error raised by a host function, carried by `AwwasmTrap::Host`.
+///
+/// The payload is shared, so traps stay cheap to clone. Two host errors
+/// compare equal only if they are the same payload.
+#[cfg(feature = "alloc")]
+#[derive(Debug, Clone)]
+pub struct AwwasmHostError(Arc<dyn core::error::Error + Send + Sync>);
+
+#[cfg(feature = "alloc")]
+impl AwwasmHostError {
+    /// Wrap a host error.
+    pub fn new(error: impl Into<Box<dyn core::error::Error + Send + Sync>>) -> Self {
+        Self(Arc::from(error.into()))
+    }
+
+    /// The host's error.
+    pub fn get(&self) -> &(dyn core::error::Error + Send + Sync + 'static) {
+        &*self.0
+    }
+
+    /// The host's error, if it is a `T`.
+    pub fn downcast_ref<T: core::error::Error + 'static>(&self) -> Option<&T> {
+        self.0.downcast_ref::<T>()
+    }
+
+    /// Whether the host's error is a `T`.
+    pub fn is<T: core::error::Error + 'static>(&self) -> bool {
+        self.0.is::<T>()
+    }
+}
+
+#[cfg(feature = "alloc")]
+impl PartialEq for AwwasmHostError {
+    fn eq(&self, other: &Self) -> bool {
+        Arc::ptr_eq(&self.0, &other.0)
+    }
+}
+
+#[cfg(feature = "alloc")]
+impl Eq for AwwasmHostError {}
+
+/// A host function panicked; carried by `AwwasmTrap::Host`.
+#[cfg(feature = "std")]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AwwasmHostPanic {
+    /// The panic message, if the payload was a string.
+    pub message: Option<String>,
+}
+
+#[cfg(feature = "std")]
+impl AwwasmHostPanic {
+    pub(crate) fn from_payload(payload: Box<dyn core::any::Any + Send>) -> Self {
+        let message = match payload.downcast::<String>() {
+            Ok(message) => Some(*message),
+            Err(payload) => payload.downcast_ref::<&str>().map(|s| String::from(*s)),
+        };
+        Self { message }
+    }
+}
+
+#[cfg(feature = "std")]
+impl fmt::Display for AwwasmHostPanic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.message {
+            Some(message) => write!(f, "host function panicked: {}", message),
+            None => write!(f, "host function panicked"),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl core::error::Error for AwwasmHostPanic {}
+
+/// Host errors serialize as their message and deserialize into an
+/// opaque error carrying it, so the concrete type is lost in transit.
+#[cfg(feature = "serde")]
+impl serde::Serialize for AwwasmHostError {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(&self.0)
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de> serde::Deserialize<'de> for AwwasmHostError {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        String::deserialize(deserializer).map(AwwasmHostError::new)
+    }
+}
+
+#[cfg(feature = "alloc")]
+impl fmt::Display for AwwasmHostError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Display::fmt(&self.0, f)
+    }
+}
+
+/// The kind of an `AwwasmTrap`, without its details.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum TrapKind {
+    DivisionByZero,
+    IntegerOverflow,
+    InvalidConversionToInteger,
+    MemoryOutOfBounds,
+    TableOutOfBounds,
+    IndirectCallTypeMismatch,
+    IndirectCallToNull,
+    Unreachable,
+    StackOverflow,
+    CallStackExhausted,
+    InvalidHostCall,
+    Exit,
+    Interrupted,
+    Host,
+    OutOfFuel,
+    UnalignedAtomic,
+    ExpectedSharedMemory,
+}
+
+/// Process exit code for a guest that trapped, matching wasmtime's CLI:
+/// the code of an abort (`128 + SIGABRT`) on Unix-like systems and `3`
+/// (what `abort()` exits with) on Windows.
+#[cfg(not(windows))]
+pub const TRAP_EXIT_CODE: i32 = 128 + 6;
+/// Process exit code for a guest that trapped, matching wasmtime's CLI:
+/// the code of an abort (`128 + SIGABRT`) on Unix-like systems and `3`
+/// (what `abort()` exits with) on Windows.
+#[cfg(windows)]
+pub const TRAP_EXIT_CODE: i32 = 3;
+
+/// Exit status reported by a guest that terminated itself.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ExitStatus(pub u32);
+
+impl ExitStatus {
+    /// The exit code.
+    pub fn code(&self) -> u32 {
+        self.0
+    }
+
+    /// Whether the guest exited with code 0.
+    pub fn is_success(&self) -> bool {
+        self.0 == 0
+    }
+}
+
+impl AwwasmTrap {
+    /// Stable numeric code for this trap, in the 2000 range.
+    ///
+    /// | code | variant |
+    /// |------|---------|
+    /// | 2001 | `DivisionByZero` |
+    /// | 2002 | `IntegerOverflow` |
+    /// | 2003 | `InvalidConversionToInteger` |
+    /// | 2004 | `MemoryOutOfBounds` |
+    /// | 2005 | `TableOutOfBounds` |
+    /// | 2006 | `IndirectCallTypeMismatch` |
+    /// | 2007 | `IndirectCallToNull` |
+    /// | 2008 | `Unreachable` |
+    /// | 2009 | `StackOverflow` |
+    /// | 2010 | `CallStackExhausted` |
+    /// | 2011 | `InvalidHostCall` |
+    /// | 2012 | `Exit` |
+    /// | 2013 | `Interrupted` |
+    /// | 2014 | `Host` |
+    /// | 2015 | `OutOfFuel` |
+    /// | 2016 | `UnalignedAtomic` |
+    /// | 2017 | `ExpectedSharedMemory` |
+    ///
+    /// Codes are never reused or renumbered; new variants get new codes.
+    pub fn code(&self) -> u32 {
+        match self {
+            AwwasmTrap::DivisionByZero => 2001,
+            AwwasmTrap::IntegerOverflow => 2002,
+            AwwasmTrap::InvalidConversionToInteger => 2003,
+            AwwasmTrap::MemoryOutOfBounds { .. } => 2004,
+            AwwasmTrap::TableOutOfBounds { .. } => 2005,
+            AwwasmTrap::IndirectCallTypeMismatch { .. } => 2006,
+            AwwasmTrap::IndirectCallToNull => 2007,
+            AwwasmTrap::Unreachable => 2008,
+            AwwasmTrap::StackOverflow => 2009,
+            AwwasmTrap::CallStackExhausted => 2010,
+            AwwasmTrap::InvalidHostCall { .. } => 2011,
+            AwwasmTrap::Exit(_) => 2012,
+            AwwasmTrap::Interrupted => 2013,
+            #[cfg(feature = "alloc")]
+            AwwasmTrap::Host(_) => 2014,
+            AwwasmTrap::OutOfFuel => 2015,
+            AwwasmTrap::UnalignedAtomic { .. } => 2016,
+            AwwasmTrap::ExpectedSharedMemory => 2017,
+        }
+    }
+
+    /// A trap carrying a host function's own error.
+    #[cfg(feature = "alloc")]
+    pub fn host(error: impl Into<Box<dyn core::error::Error + Send + Sync>>) -> Self {
+        AwwasmTrap::Host(AwwasmHostError::new(error))
+    }
+
+    /// The host error behind this trap, if it is a `T`.
+    #[cfg(feature = "alloc")]
+    pub fn downcast_ref<T: core::error::Error + 'static>(&self) -> Option<&T> {
+        match self {
+            AwwasmTrap::Host(error) => error.downcast_ref::<T>(),
+            _ => None,
+        }
+    }
+
+    /// The kind of this trap.
+    pub fn kind(&self) -> TrapKind {
+        match self {
+            AwwasmTrap::DivisionByZero => TrapKind::DivisionByZero,
+            AwwasmTrap::IntegerOverflow => TrapKind::IntegerOverflow,
+            AwwasmTrap::InvalidConversionToInteger => TrapKind::InvalidConversionToInteger,
+            AwwasmTrap::MemoryOutOfBounds { .. } => TrapKind::MemoryOutOfBounds,
+            AwwasmTrap::TableOutOfBounds { .. } => TrapKind::TableOutOfBounds,
+            AwwasmTrap::IndirectCallTypeMismatch { .. } => TrapKind::IndirectCallTypeMismatch,
+            AwwasmTrap::IndirectCallToNull => TrapKind::IndirectCallToNull,
+            AwwasmTrap::Unreachable => TrapKind::Unreachable,
+            AwwasmTrap::StackOverflow => TrapKind::StackOverflow,
+            AwwasmTrap::CallStackExhausted => TrapKind::CallStackExhausted,
+            AwwasmTrap::InvalidHostCall { .. } => TrapKind::InvalidHostCall,
+            AwwasmTrap::Exit(_) => TrapKind::Exit,
+            AwwasmTrap::Interrupted => TrapKind::Interrupted,
+            #[cfg(feature = "alloc")]
+            AwwasmTrap::Host(_) => TrapKind::Host,
+            AwwasmTrap::OutOfFuel => TrapKind::OutOfFuel,
+            AwwasmTrap::UnalignedAtomic { .. } => TrapKind::UnalignedAtomic,
+            AwwasmTrap::ExpectedSharedMemory => TrapKind::ExpectedSharedMemory,
+        }
+    }
+
+    /// The process exit code a command-line embedder should use.
+    ///
+    /// A requested exit keeps the guest's status (truncated to `i32` as
+    /// `std::process::exit` takes it); every other trap maps to
+    /// `TRAP_EXIT_CODE`.
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            AwwasmTrap::Exit(status) => status.0 as i32,
+            _ => TRAP_EXIT_CODE,
+        }
+    }
+
+    /// The exit status if this trap is a requested exit rather than a
+    /// runtime fault.
+    pub fn exit_status(&self) -> Option<ExitStatus> {
+        match self {
+            AwwasmTrap::Exit(status) => Some(*status),
+            _ => None,
+        }
+    }
+}
+
+/// Why `AwwasmGlobalInst::set` refused a value.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GlobalError {
+    /// The global is immutable
+    Immutable,
+    /// The value is not of the global's declared type
+    TypeMismatch {
+        expected: AwwasmValueType,
+        got: AwwasmValueType,
+    },
+}
+
+impl GlobalError {
+    /// The runtime error for this failure on global `idx`.
+    pub(crate) fn at(self, idx: u32) -> AwwasmRuntimeError {
+        match self {
+            GlobalError::Immutable => AwwasmRuntimeError::ImmutableGlobal(idx),
+            GlobalError::TypeMismatch { expected, got } => AwwasmRuntimeError::TypeMismatch {
+                expected: String::from(expected.name()),
+                got: String::from(got.name()),
+            },
+        }
+    }
+}
+
+/// Why a table write was refused.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TableError {
+    /// The index is past the end of the table
+    OutOfBounds {
+        index: u32,
+        table_size: u32,
+    },
+    /// The reference is not of the table's element type
+    TypeMismatch {
+        expected: AwwasmValueType,
+        got: AwwasmValueType,
+    },
 }
 
 /// Errors that can occur during runtime execution.
 #[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum AwwasmRuntimeError {
     /// A trap occurred
     Trap(AwwasmTrap),
@@ -114,6 +632,193 @@ pub enum AwwasmRuntimeError {
     },
     /// Global is immutable
     ImmutableGlobal(u32),
+    /// No function export with this name
+    ExportNotFound(String),
+    /// A precompiled artifact is malformed, from another runtime version,
+    /// or was made for a different module
+    InvalidArtifact(String),
+    /// A host function that no module imported has no signature
+    UntypedFunction(u32),
+    /// The allocator could not provide `requested_bytes` more bytes
+    OutOfMemory {
+        requested_bytes: usize,
+    },
+    /// A store snapshot was taken from a store with other modules or
+    /// state that does not fit this one
+    SnapshotMismatch(String),
+    /// Bytes passed to `AwwasmStoreSnapshot::from_bytes` are not a
+    /// snapshot in this version's format, or are corrupt
+    InvalidSnapshot(String),
+    /// An error raised while executing a guest function, with the
+    /// function it happened in.
+    InFunction {
+        /// Module instance the function belongs to.
+        module: u32,
+        /// Module-local function index.
+        func_idx: u32,
+        /// What went wrong.
+        source: Box<AwwasmRuntimeError>,
+    },
+    /// A function body exceeds one of the runtime's fixed limits
+    /// (`exec::MAX_FUNCTION_LOCALS`, `ir::MAX_BLOCK_NESTING`), found
+    /// while decoding it
+    BodyLimitExceeded {
+        limit: AwwasmBodyLimit,
+        max: u32,
+        actual: u64,
+    },
+    /// An `AwwasmHostFuncs` has handed out every `host_func_id` from its
+    /// base up to `u32::MAX`
+    HostFuncIdsExhausted {
+        base_id: u32,
+    },
+    /// Invalid module instance address
+    InvalidModuleAddr(u32),
+    /// `set_stack_limits` asked for more than the store's execution
+    /// arena was reserved for
+    StackLimitsExceedArena,
+}
+
+/// Which fixed limit a function body exceeded.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum AwwasmBodyLimit {
+    /// Declared locals, parameters excluded.
+    Locals,
+    /// `block`, `loop` and `if` open at once.
+    BlockNesting,
+}
+
+impl fmt::Display for AwwasmBodyLimit {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            AwwasmBodyLimit::Locals => "locals",
+            AwwasmBodyLimit::BlockNesting => "nested blocks",
+        })
+    }
+}
+
+impl AwwasmRuntimeError {
+    /// Stable numeric code for this error, in the 3000 range.
+    ///
+    /// A `Trap` reports the trap's own code (see `AwwasmTrap::code`) and
+    /// `InFunction` reports the code of the error it wraps.
+    ///
+    /// | code | variant |
+    /// |------|---------|
+    /// | 3001 | `InvalidFuncAddr` |
+    /// | 3002 | `InvalidMemAddr` |
+    /// | 3003 | `InvalidTableAddr` |
+    /// | 3004 | `InvalidGlobalAddr` |
+    /// | 3005 | `HostFunctionNotExecutable` |
+    /// | 3006 | `FunctionNotParsed` |
+    /// | 3007 | `InstructionParseError` |
+    /// | 3008 | `TypeMismatch` |
+    /// | 3009 | `ImmutableGlobal` |
+    /// | 3010 | `ExportNotFound` |
+    /// | 3011 | `InvalidArtifact` |
+    /// | 3012 | `UntypedFunction` |
+    /// | 3013 | `OutOfMemory` |
+    /// | 3014 | `SnapshotMismatch` |
+    /// | 3015 | `InvalidSnapshot` |
+    /// | 3016 | `BodyLimitExceeded` |
+    /// | 3017 | `HostFuncIdsExhausted` |
+    /// | 3018 | `InvalidModuleAddr` |
+    /// | 3019 | `StackLimitsExceedArena` |
+    ///
+    /// Codes are never reused or renumbered; new variants get new codes.
+    pub fn code(&self) -> u32 {
+        match self {
+            AwwasmRuntimeError::Trap(trap) => trap.code(),
+            AwwasmRuntimeError::InvalidFuncAddr(_) => 3001,
+            AwwasmRuntimeError::InvalidMemAddr(_) => 3002,
+            AwwasmRuntimeError::InvalidTableAddr(_) => 3003,
+            AwwasmRuntimeError::InvalidGlobalAddr(_) => 3004,
+            AwwasmRuntimeError::HostFunctionNotExecutable => 3005,
+            AwwasmRuntimeError::FunctionNotParsed => 3006,
+            AwwasmRuntimeError::InstructionParseError(_) => 3007,
+            AwwasmRuntimeError::TypeMismatch { .. } => 3008,
+            AwwasmRuntimeError::ImmutableGlobal(_) => 3009,
+            AwwasmRuntimeError::ExportNotFound(_) => 3010,
+            AwwasmRuntimeError::InvalidArtifact(_) => 3011,
+            AwwasmRuntimeError::UntypedFunction(_) => 3012,
+            AwwasmRuntimeError::OutOfMemory { .. } => 3013,
+            AwwasmRuntimeError::SnapshotMismatch(_) => 3014,
+            AwwasmRuntimeError::InvalidSnapshot(_) => 3015,
+            AwwasmRuntimeError::InFunction { source, .. } => source.code(),
+            AwwasmRuntimeError::BodyLimitExceeded { .. } => 3016,
+            AwwasmRuntimeError::HostFuncIdsExhausted { .. } => 3017,
+            AwwasmRuntimeError::InvalidModuleAddr(_) => 3018,
+            AwwasmRuntimeError::StackLimitsExceedArena => 3019,
+        }
+    }
+
+    /// The innermost error, with any function context removed.
+    pub fn root(&self) -> &AwwasmRuntimeError {
+        match self {
+            AwwasmRuntimeError::InFunction { source, .. } => source.root(),
+            other => other,
+        }
+    }
+
+    /// The trap behind this error, if execution trapped.
+    pub fn trap(&self) -> Option<&AwwasmTrap> {
+        match self.root() {
+            AwwasmRuntimeError::Trap(trap) => Some(trap),
+            _ => None,
+        }
+    }
+}
+
+impl AwwasmInstantiationError {
+    /// Stable numeric code for this error, in the 1000 range.
+    ///
+    /// | code | variant |
+    /// |------|---------|
+    /// | 1001 | `MissingImport` |
+    /// | 1002 | `ImportTypeMismatch` |
+    /// | 1003 | `MemoryAllocationFailed` |
+    /// | 1004 | `DataSegmentOutOfBounds` |
+    /// | 1005 | `ElementSegmentOutOfBounds` |
+    /// | 1006 | `StartFunctionTrapped` |
+    /// | 1007 | `UnsupportedType` |
+    /// | 1008 | `InvalidConstExpr` |
+    /// | 1009 | `FuncCodeMismatch` |
+    /// | 1010 | `ExportIndexOutOfBounds` |
+    /// | 1011 | `TableAllocationFailed` |
+    /// | 1012 | `InvalidModule` |
+    /// | 1014 | `ShardImport` |
+    /// | 1015 | `ModuleLimitExceeded` |
+    /// | 1016 | `ModuleNotPermitted` |
+    /// | 1017 | `ImportDenied` |
+    /// | 1018 | `InstantiationCostExceeded` |
+    /// | 1019 | `InvalidName` |
+    /// | 1020 | `StartIndexOutOfBounds` |
+    ///
+    /// Codes are never reused or renumbered; new variants get new codes.
+    pub fn code(&self) -> u32 {
+        match self {
+            AwwasmInstantiationError::MissingImport { .. } => 1001,
+            AwwasmInstantiationError::ImportTypeMismatch { .. } => 1002,
+            AwwasmInstantiationError::MemoryAllocationFailed { .. } => 1003,
+            AwwasmInstantiationError::DataSegmentOutOfBounds { .. } => 1004,
+            AwwasmInstantiationError::ElementSegmentOutOfBounds { .. } => 1005,
+            AwwasmInstantiationError::StartFunctionTrapped(_) => 1006,
+            AwwasmInstantiationError::UnsupportedType { .. } => 1007,
+            AwwasmInstantiationError::InvalidConstExpr { .. } => 1008,
+            AwwasmInstantiationError::FuncCodeMismatch { .. } => 1009,
+            AwwasmInstantiationError::ExportIndexOutOfBounds { .. } => 1010,
+            AwwasmInstantiationError::TableAllocationFailed { .. } => 1011,
+            AwwasmInstantiationError::InvalidModule(_) => 1012,
+            AwwasmInstantiationError::ShardImport { .. } => 1014,
+            AwwasmInstantiationError::ModuleLimitExceeded { .. } => 1015,
+            AwwasmInstantiationError::ModuleNotPermitted { .. } => 1016,
+            AwwasmInstantiationError::ImportDenied { .. } => 1017,
+            AwwasmInstantiationError::InstantiationCostExceeded { .. } => 1018,
+            AwwasmInstantiationError::InvalidName { .. } => 1019,
+            AwwasmInstantiationError::StartIndexOutOfBounds { .. } => 1020,
+        }
+    }
 }
 
 impl From<AwwasmTrap> for AwwasmRuntimeError {
@@ -122,9 +827,94 @@ impl From<AwwasmTrap> for AwwasmRuntimeError {
     }
 }
 
-#[cfg(feature = "std")]
-impl std::fmt::Display for AwwasmTrap {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+impl fmt::Display for AwwasmInstantiationError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AwwasmInstantiationError::MissingImport { module, name } => {
+                write!(f, "missing import {}.{}", module, name)
+            }
+            AwwasmInstantiationError::ImportTypeMismatch { module, name, expected, got } => {
+                write!(f, "import {}.{} type mismatch: expected {}, got {}", module, name, expected, got)
+            }
+            AwwasmInstantiationError::MemoryAllocationFailed { requested_pages } => {
+                write!(f, "failed to allocate memory of {} pages", requested_pages)
+            }
+            AwwasmInstantiationError::TableAllocationFailed { requested_elems } => {
+                write!(f, "failed to allocate table of {} elements", requested_elems)
+            }
+            AwwasmInstantiationError::DataSegmentOutOfBounds { segment_idx, offset, size, memory_size } => write!(
+                f,
+                "data segment {} out of bounds: offset={}, size={}, memory_size={}",
+                segment_idx, offset, size, memory_size
+            ),
+            AwwasmInstantiationError::ElementSegmentOutOfBounds { segment_idx, offset, size, table_size } => write!(
+                f,
+                "element segment {} out of bounds: offset={}, size={}, table_size={}",
+                segment_idx, offset, size, table_size
+            ),
+            AwwasmInstantiationError::StartFunctionTrapped(trap) => write!(f, "start function trapped: {}", trap),
+            AwwasmInstantiationError::UnsupportedType { description } => write!(f, "unsupported type: {}", description),
+            AwwasmInstantiationError::InvalidConstExpr { description, .. } => {
+                write!(f, "invalid constant expression: {}", description)
+            }
+            AwwasmInstantiationError::FuncCodeMismatch { func_count, code_count } => write!(
+                f,
+                "function and code section counts differ: {} functions, {} bodies",
+                func_count, code_count
+            ),
+            AwwasmInstantiationError::ExportIndexOutOfBounds { kind, name, index, count } => write!(
+                f,
+                "export {:?} refers to {} {}, but the module has {}",
+                name, kind, index, count
+            ),
+            AwwasmInstantiationError::InvalidModule(e) => write!(f, "invalid module: {}", e),
+            AwwasmInstantiationError::ShardImport { module, name } => {
+                write!(f, "import {}.{} refers to the destination store and cannot be instantiated in parallel", module, name)
+            }
+            AwwasmInstantiationError::ModuleLimitExceeded { limit, max, actual } => {
+                write!(f, "module has {} {}, at most {} allowed", actual, limit, max)
+            }
+            AwwasmInstantiationError::ModuleNotPermitted { hash: Some(hash) } => {
+                write!(f, "module {} is not permitted in this store", hash)
+            }
+            AwwasmInstantiationError::ModuleNotPermitted { hash: None } => {
+                write!(f, "store has a module gate; instantiate from bytes so the module can be hashed")
+            }
+            AwwasmInstantiationError::ImportDenied { module, name } => {
+                write!(f, "import {}.{} is denied by the capability policy", module, name)
+            }
+            AwwasmInstantiationError::InstantiationCostExceeded { cost, max, actual } => {
+                write!(f, "instantiating the module takes {} {}, at most {} allowed", actual, cost, max)
+            }
+            AwwasmInstantiationError::InvalidName { position, index } => {
+                write!(f, "{} {} is not valid UTF-8", position, index)
+            }
+            AwwasmInstantiationError::StartIndexOutOfBounds { index, count } => {
+                write!(f, "start function {} is out of range, the module has {} functions", index, count)
+            }
+        }
+    }
+}
+
+impl core::error::Error for AwwasmInstantiationError {
+    fn source(&self) -> Option<&(dyn core::error::Error + 'static)> {
+        match self {
+            AwwasmInstantiationError::InvalidConstExpr { source: Some(source), .. } => Some(source),
+            AwwasmInstantiationError::StartFunctionTrapped(trap) => trap.source(),
+            AwwasmInstantiationError::InvalidModule(e) => e.source(),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for ExitStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "exit status {}", self.0)
+    }
+}
+
+impl fmt::Display for AwwasmTrap {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
             AwwasmTrap::DivisionByZero => write!(f, "division by zero"),
             AwwasmTrap::IntegerOverflow => write!(f, "integer overflow"),
@@ -135,23 +925,67 @@ impl std::fmt::Display for AwwasmTrap {
             AwwasmTrap::TableOutOfBounds { index, table_size } => {
                 write!(f, "table out of bounds: index={}, table_size={}", index, table_size)
             }
-            AwwasmTrap::IndirectCallTypeMismatch { expected_type, actual_type } => {
-                write!(f, "indirect call type mismatch: expected={}, actual={}", expected_type, actual_type)
+            AwwasmTrap::IndirectCallTypeMismatch { expected, got } => {
+                write!(f, "indirect call type mismatch: expected {}, got {}", expected, got)
             }
             AwwasmTrap::IndirectCallToNull => write!(f, "indirect call to null"),
             AwwasmTrap::Unreachable => write!(f, "unreachable"),
             AwwasmTrap::StackOverflow => write!(f, "stack overflow"),
             AwwasmTrap::CallStackExhausted => write!(f, "call stack exhausted"),
+            AwwasmTrap::InvalidHostCall { host_func_id } => {
+                write!(f, "invalid call to host function {}", host_func_id)
+            }
+            AwwasmTrap::Exit(status) => write!(f, "exited with status {}", status.0),
+            AwwasmTrap::Interrupted => write!(f, "interrupted"),
+            #[cfg(feature = "alloc")]
+            AwwasmTrap::Host(error) => write!(f, "host error: {}", error),
+            AwwasmTrap::OutOfFuel => write!(f, "out of fuel"),
+            AwwasmTrap::UnalignedAtomic { offset } => write!(f, "unaligned atomic: offset={}", offset),
+            AwwasmTrap::ExpectedSharedMemory => write!(f, "expected shared memory"),
         }
     }
 }
 
-#[cfg(feature = "std")]
-impl std::error::Error for AwwasmTrap {}
+impl core::error::Error for AwwasmTrap {
+    fn source(&self) -> Option<&(dyn core::error::Error + 'static)> {
+        match self {
+            #[cfg(feature = "alloc")]
+            AwwasmTrap::Host(error) => Some(error.get()),
+            _ => None,
+        }
+    }
+}
 
-#[cfg(feature = "std")]
-impl std::fmt::Display for AwwasmRuntimeError {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+impl fmt::Display for GlobalError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            GlobalError::Immutable => write!(f, "global is immutable"),
+            GlobalError::TypeMismatch { expected, got } => {
+                write!(f, "global type mismatch: expected {}, got {}", expected, got)
+            }
+        }
+    }
+}
+
+impl core::error::Error for GlobalError {}
+
+impl fmt::Display for TableError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TableError::OutOfBounds { index, table_size } => {
+                write!(f, "table out of bounds: index={}, table_size={}", index, table_size)
+            }
+            TableError::TypeMismatch { expected, got } => {
+                write!(f, "table element type mismatch: expected {}, got {}", expected, got)
+            }
+        }
+    }
+}
+
+impl core::error::Error for TableError {}
+
+impl fmt::Display for AwwasmRuntimeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
             AwwasmRuntimeError::Trap(trap) => write!(f, "trap: {}", trap),
             AwwasmRuntimeError::InvalidFuncAddr(addr) => write!(f, "invalid function address: {}", addr),
@@ -165,9 +999,41 @@ impl std::fmt::Display for AwwasmRuntimeError {
                 write!(f, "type mismatch: expected {}, got {}", expected, got)
             }
             AwwasmRuntimeError::ImmutableGlobal(idx) => write!(f, "global {} is immutable", idx),
+            AwwasmRuntimeError::ExportNotFound(name) => write!(f, "no function export named {:?}", name),
+            AwwasmRuntimeError::InvalidArtifact(why) => write!(f, "invalid precompiled artifact: {}", why),
+            AwwasmRuntimeError::UntypedFunction(addr) => {
+                write!(f, "function {} has no signature: it is a host function no module imported", addr)
+            }
+            AwwasmRuntimeError::OutOfMemory { requested_bytes } => {
+                write!(f, "out of memory allocating {} bytes", requested_bytes)
+            }
+            AwwasmRuntimeError::SnapshotMismatch(why) => write!(f, "snapshot does not match the store: {}", why),
+            AwwasmRuntimeError::InvalidSnapshot(why) => write!(f, "invalid snapshot: {}", why),
+            AwwasmRuntimeError::InFunction { module, func_idx, source } => {
+                write!(f, "{} (in function {} of module {})", source, func_idx, module)
+            }
+            AwwasmRuntimeError::BodyLimitExceeded { limit, max, actual } => {
+                write!(f, "function body has {} {}, at most {} allowed", actual, limit, max)
+            }
+            AwwasmRuntimeError::HostFuncIdsExhausted { base_id } => {
+                write!(f, "no host function ids left above base {}", base_id)
+            }
+            AwwasmRuntimeError::InvalidModuleAddr(addr) => write!(f, "invalid module address: {}", addr),
+            AwwasmRuntimeError::StackLimitsExceedArena => {
+                f.write_str("stack limits exceed those the execution arena was reserved for")
+            }
         }
     }
 }
 
-#[cfg(feature = "std")]
-impl std::error::Error for AwwasmRuntimeError {}
+impl core::error::Error for AwwasmRuntimeError {
+    fn source(&self) -> Option<&(dyn core::error::Error + 'static)> {
+        match self {
+            // The wrapped error's message is already part of ours, so
+            // continue the chain with its cause.
+            AwwasmRuntimeError::InFunction { source, .. } => source.source(),
+            AwwasmRuntimeError::Trap(trap) => trap.source(),
+            _ => None,
+        }
+    }
+}
@@ -0,0 +1,1620 @@
+//! Interpreter for WebAssembly function bodies.
+//!
+//! Function bodies are lowered to the internal `Op` representation (see
+//! `ir`) on their first call and interpreted from there.
+//! Calls push an explicit frame instead of recursing on the native stack,
+//! so guest recursion depth is bounded by `MAX_CALL_DEPTH` rather than
+//! by the host thread's stack size.
+//!
+//! Native stack use per `invoke` is therefore fixed: the `Executor`, one
+//! `step` and whatever the host function being called needs. Lowering is
+//! a loop over the body with an explicit control stack, so deep nesting
+//! costs heap, not stack. Frames, labels and operands live on the heap,
+//! bounded by the store's `AwwasmStackLimits`. The only way to stack
+//! native frames is a host function that invokes guest code again
+//! through its `AwwasmCaller`, at most `caller::MAX_REENTRY_DEPTH` deep.
+//!
+//! `step` dispatches on the lowered `Op`, whose variants the compiler
+//! turns into a jump table. Numeric instructions, most of what a typical
+//! body executes, then go through `Executor::NUMERIC`, a table of handler
+//! functions indexed by opcode, rather than a second `match` on the
+//! opcode byte. Handlers return to the loop instead of tail-calling the
+//! next one, since stable Rust has no guaranteed tail calls. Changes here
+//! should be checked against `benches/interp.rs`.
+
+#[cfg(feature = "alloc")]
+use alloc::vec::Vec;
+#[cfg(feature = "alloc")]
+use alloc::string::String;
+#[cfg(feature = "alloc")]
+use alloc::format;
+#[cfg(feature = "alloc")]
+use alloc::boxed::Box;
+#[cfg(feature = "alloc")]
+use alloc::sync::Arc;
+#[cfg(feature = "alloc")]
+use alloc::collections::BTreeSet;
+
+use crate::audit::AwwasmMemAccessor;
+use crate::caller::AwwasmCaller;
+use crate::host_trail::AwwasmHostCall;
+use crate::decode::AwwasmReader;
+use crate::error::{AwwasmBodyLimit, AwwasmRuntimeError, AwwasmTrap, GlobalError};
+#[cfg(feature = "std")]
+use crate::error::AwwasmHostPanic;
+use crate::memory::AwwasmMemInst;
+use crate::func::{AwwasmFuncInst, AwwasmFuncType, AwwasmHost, AwwasmLocalDecl, AwwasmWasmFuncInst, LazyResolvedCodeRef};
+use crate::ir::{self, AwwasmCompiledCode, Op};
+use crate::fuel::{self, AwwasmGasTable};
+use crate::store::AwwasmStore;
+use crate::values::{AwwasmFuncAddr, AwwasmMemAddr, AwwasmModuleAddr, AwwasmValue};
+
+#[cfg(feature = "softfloat")]
+use crate::softfloat as fp;
+
+/// Float operations the target may round or NaN differently; the
+/// `softfloat` feature swaps in `crate::softfloat`.
+#[cfg(not(feature = "softfloat"))]
+mod fp {
+    pub(super) fn f32_add(a: f32, b: f32) -> f32 {
+        a + b
+    }
+
+    pub(super) fn f32_sub(a: f32, b: f32) -> f32 {
+        a - b
+    }
+
+    pub(super) fn f32_mul(a: f32, b: f32) -> f32 {
+        a * b
+    }
+
+    pub(super) fn f32_div(a: f32, b: f32) -> f32 {
+        a / b
+    }
+
+    pub(super) fn f32_sqrt(a: f32) -> f32 {
+        libm::sqrtf(a)
+    }
+
+    pub(super) fn f32_ceil(a: f32) -> f32 {
+        libm::ceilf(a)
+    }
+
+    pub(super) fn f32_floor(a: f32) -> f32 {
+        libm::floorf(a)
+    }
+
+    pub(super) fn f32_trunc(a: f32) -> f32 {
+        libm::truncf(a)
+    }
+
+    pub(super) fn f32_nearest(a: f32) -> f32 {
+        libm::rintf(a)
+    }
+
+    pub(super) fn f64_add(a: f64, b: f64) -> f64 {
+        a + b
+    }
+
+    pub(super) fn f64_sub(a: f64, b: f64) -> f64 {
+        a - b
+    }
+
+    pub(super) fn f64_mul(a: f64, b: f64) -> f64 {
+        a * b
+    }
+
+    pub(super) fn f64_div(a: f64, b: f64) -> f64 {
+        a / b
+    }
+
+    pub(super) fn f64_sqrt(a: f64) -> f64 {
+        libm::sqrt(a)
+    }
+
+    pub(super) fn f64_ceil(a: f64) -> f64 {
+        libm::ceil(a)
+    }
+
+    pub(super) fn f64_floor(a: f64) -> f64 {
+        libm::floor(a)
+    }
+
+    pub(super) fn f64_trunc(a: f64) -> f64 {
+        libm::trunc(a)
+    }
+
+    pub(super) fn f64_nearest(a: f64) -> f64 {
+        libm::rint(a)
+    }
+
+    pub(super) fn f64_to_f32(a: f64) -> f32 {
+        a as f32
+    }
+
+    pub(super) fn f32_to_f64(a: f32) -> f64 {
+        a as f64
+    }
+
+    pub(super) fn i64_to_f32(a: i64) -> f32 {
+        a as f32
+    }
+
+    pub(super) fn u64_to_f32(a: u64) -> f32 {
+        a as f32
+    }
+
+    pub(super) fn i64_to_f64(a: i64) -> f64 {
+        a as f64
+    }
+
+    pub(super) fn u64_to_f64(a: u64) -> f64 {
+        a as f64
+    }
+}
+
+/// Maximum number of nested guest calls before `CallStackExhausted`.
+pub const MAX_CALL_DEPTH: usize = 1024;
+
+/// Maximum number of values on the operand stack before `StackOverflow`.
+pub const MAX_VALUE_STACK: usize = 1024 * 1024;
+
+/// Maximum number of blocks open at once, across all frames, before
+/// `CallStackExhausted`.
+pub const MAX_LABEL_DEPTH: usize = 64 * 1024;
+
+/// Maximum number of locals one function may declare, parameters
+/// excluded. Bodies declaring more fail to decode with
+/// `BodyLimitExceeded` rather than filling the operand stack on every
+/// call.
+pub const MAX_FUNCTION_LOCALS: u32 = 50_000;
+
+
+/// A structured control label.
+#[derive(Debug, Clone, Copy)]
+struct Label {
+    /// `true` for `loop`, whose branch target is its start.
+    is_loop: bool,
+    /// Index a branch to this label continues at.
+    target: usize,
+    /// Operand stack height below the block's parameters.
+    height: usize,
+    /// Number of values a branch to this label carries.
+    arity: usize,
+}
+
+/// An active guest function call.
+#[derive(Debug, Clone)]
+struct Frame {
+    func: AwwasmFuncAddr,
+    module: AwwasmModuleAddr,
+    mem: Option<AwwasmMemAddr>,
+    code: Arc<AwwasmCompiledCode>,
+    /// Index of the next instruction in `code`.
+    pc: usize,
+    /// Index of local 0 on the operand stack.
+    locals: usize,
+    /// Index of the frame's outermost label on the label stack.
+    labels: usize,
+    /// Number of results the function returns.
+    arity: usize,
+}
+
+fn parse_error(what: &str) -> AwwasmRuntimeError {
+    AwwasmRuntimeError::InstructionParseError(String::from(what))
+}
+
+/// Call into the host. With `std`, a panicking host becomes an
+/// `AwwasmHostPanic` trap instead of unwinding through the interpreter.
+fn guard_host<T>(f: impl FnOnce() -> Result<T, AwwasmTrap>) -> Result<T, AwwasmTrap> {
+    #[cfg(feature = "std")]
+    {
+        use std::panic::{catch_unwind, AssertUnwindSafe};
+
+        catch_unwind(AssertUnwindSafe(f))
+            .unwrap_or_else(|payload| Err(AwwasmTrap::host(AwwasmHostPanic::from_payload(payload))))
+    }
+    #[cfg(not(feature = "std"))]
+    {
+        f()
+    }
+}
+
+fn call_host(
+    host: &mut dyn AwwasmHost,
+    host_func_id: u32,
+    args: &[AwwasmValue],
+    caller: &mut AwwasmCaller<'_, '_>,
+) -> Result<Vec<AwwasmValue>, AwwasmTrap> {
+    guard_host(|| host.call_with_caller(host_func_id, args, caller))
+}
+
+/// Bounds on the heap-allocated interpreter stacks of one `invoke`, set
+/// per store with `AwwasmStore::set_stack_limits`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AwwasmStackLimits {
+    /// Maximum number of nested guest calls before `CallStackExhausted`.
+    pub call_depth: usize,
+    /// Maximum number of operand stack values before `StackOverflow`.
+    pub value_stack: usize,
+    /// Maximum number of `block`, `loop` and `if` open at once, summed
+    /// over every frame, before `CallStackExhausted`.
+    pub label_depth: usize,
+}
+
+impl AwwasmStackLimits {
+    /// Whether every bound of `self` is within `other`'s.
+    pub fn fits_within(&self, other: &AwwasmStackLimits) -> bool {
+        self.call_depth <= other.call_depth
+            && self.value_stack <= other.value_stack
+            && self.label_depth <= other.label_depth
+    }
+}
+
+impl Default for AwwasmStackLimits {
+    fn default() -> Self {
+        Self { call_depth: MAX_CALL_DEPTH, value_stack: MAX_VALUE_STACK, label_depth: MAX_LABEL_DEPTH }
+    }
+}
+
+/// What one `invoke` did, collected when the store asks for it with
+/// `AwwasmStore::set_collect_metrics`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct AwwasmExecutionMetrics {
+    /// WebAssembly instructions executed, whether or not the interpreter
+    /// fused them.
+    pub instructions: u64,
+    /// Calls into host functions.
+    pub host_calls: u64,
+    /// Most values on the operand stack at once, locals included.
+    pub max_stack_depth: usize,
+    /// Pages added by successful `memory.grow` instructions.
+    pub pages_grown: u64,
+}
+
+/// Interpreter stacks reserved once by the embedder and reused by every
+/// call of the store they are handed to with `AwwasmStore::with_arena`.
+///
+/// For devices that must not allocate or fragment their heap while
+/// guest code runs: the operand, label and frame stacks are sized up
+/// front from `AwwasmStackLimits` and only cleared between calls.
+#[derive(Debug)]
+pub struct AwwasmExecArena {
+    stack: Vec<AwwasmValue>,
+    labels: Vec<Label>,
+    frames: Vec<Frame>,
+    limits: AwwasmStackLimits,
+}
+
+impl AwwasmExecArena {
+    /// Reserve room for `limits.value_stack` operands,
+    /// `limits.label_depth` labels and `limits.call_depth` frames. Fails
+    /// with `OutOfMemory` if the allocator cannot provide it.
+    pub fn new(limits: AwwasmStackLimits) -> Result<Self, AwwasmRuntimeError> {
+        let mut arena = Self { stack: Vec::new(), labels: Vec::new(), frames: Vec::new(), limits };
+        crate::ir::reserve(&mut arena.stack, limits.value_stack)?;
+        crate::ir::reserve(&mut arena.labels, limits.label_depth)?;
+        crate::ir::reserve(&mut arena.frames, limits.call_depth)?;
+        Ok(arena)
+    }
+
+    /// The limits the arena was sized for.
+    pub fn limits(&self) -> AwwasmStackLimits {
+        self.limits
+    }
+
+    /// Operands the arena holds without growing.
+    pub fn value_capacity(&self) -> usize {
+        self.stack.capacity()
+    }
+
+    /// Suspended calls the arena holds without growing.
+    pub fn frame_capacity(&self) -> usize {
+        self.frames.capacity()
+    }
+
+    /// Open blocks the arena holds without growing.
+    pub fn label_capacity(&self) -> usize {
+        self.labels.capacity()
+    }
+
+    /// Clear the stacks a call borrowed and give them back to `store`.
+    fn restore(
+        store: &mut AwwasmStore<'_>,
+        mut stack: Vec<AwwasmValue>,
+        mut labels: Vec<Label>,
+        mut frames: Vec<Frame>,
+        limits: AwwasmStackLimits,
+    ) {
+        stack.clear();
+        labels.clear();
+        frames.clear();
+        store.restore_arena(Self { stack, labels, frames, limits });
+    }
+}
+
+/// Execution state of one top-level `invoke`.
+struct Executor<'s, 'a, 'h> {
+    store: &'s mut AwwasmStore<'a>,
+    host: &'h mut dyn AwwasmHost,
+    limits: AwwasmStackLimits,
+    stack: Vec<AwwasmValue>,
+    labels: Vec<Label>,
+    /// Suspended callers of the frame being executed.
+    frames: Vec<Frame>,
+    /// Counters of this call, if the store collects them.
+    metrics: Option<AwwasmExecutionMetrics>,
+    /// The limits of the store's arena, if the stacks were borrowed
+    /// from it.
+    arena: Option<AwwasmStackLimits>,
+}
+
+/// Call the function at `addr` with `args`, running host functions
+/// through `host`.
+pub(crate) fn invoke<'a>(
+    store: &mut AwwasmStore<'a>,
+    addr: AwwasmFuncAddr,
+    args: &[AwwasmValue],
+    host: &mut dyn AwwasmHost,
+) -> Result<Vec<AwwasmValue>, AwwasmRuntimeError> {
+    match start(store, addr, args, host, None)? {
+        AwwasmProgress::Done(results) => Ok(results),
+        AwwasmProgress::Paused(_) => Err(parse_error("call paused without a budget")),
+    }
+}
+
+/// Interpreter state of a call paused between two instructions.
+#[derive(Debug)]
+pub(crate) struct AwwasmPausedCall {
+    stack: Vec<AwwasmValue>,
+    labels: Vec<Label>,
+    frames: Vec<Frame>,
+    frame: Frame,
+    metrics: Option<AwwasmExecutionMetrics>,
+    arena: Option<AwwasmStackLimits>,
+}
+
+/// How far a call got within its budget.
+#[derive(Debug)]
+pub(crate) enum AwwasmProgress {
+    /// The call returned these results.
+    Done(Vec<AwwasmValue>),
+    /// The budget ran out; `resume` continues the call.
+    Paused(AwwasmPausedCall),
+}
+
+/// Start a call like `invoke`, pausing once `budget` is spent: units of
+/// fuel on a store with fuel, instructions otherwise. `None` runs the
+/// call to completion.
+pub(crate) fn start<'a>(
+    store: &mut AwwasmStore<'a>,
+    addr: AwwasmFuncAddr,
+    args: &[AwwasmValue],
+    host: &mut dyn AwwasmHost,
+    budget: Option<u64>,
+) -> Result<AwwasmProgress, AwwasmRuntimeError> {
+    let func = store.func(addr)?;
+    if let AwwasmFuncInst::Wasm(f) = func {
+        let ty = store
+            .module(f.module)
+            .and_then(|m| m.types.get(f.type_idx as usize))
+            .ok_or_else(|| parse_error("function type index out of range"))?;
+        let matches = ty.params.len() == args.len()
+            && ty.params.iter().zip(args).all(|(p, a)| *p == a.value_type());
+        if !matches {
+            return Err(AwwasmRuntimeError::TypeMismatch {
+                expected: format!("{:?}", ty.params),
+                got: format!("{:?}", args.iter().map(AwwasmValue::value_type).collect::<Vec<_>>()),
+            });
+        }
+    }
+
+    let limits = store.stack_limits();
+    let store_metrics = store.collect_metrics();
+    // A call made while another one holds the arena, such as a host
+    // function re-entering the guest, gets stacks of its own.
+    let (stack, labels, frames, arena) = match store.take_arena() {
+        Some(arena) => (arena.stack, arena.labels, arena.frames, Some(arena.limits)),
+        None => (Vec::new(), Vec::new(), Vec::new(), None),
+    };
+    let mut exec = Executor {
+        store,
+        host,
+        limits,
+        stack,
+        labels,
+        frames,
+        metrics: store_metrics.then(AwwasmExecutionMetrics::default),
+        arena,
+    };
+    exec.stack.extend_from_slice(args);
+    let outcome = match exec.call(addr, None) {
+        Ok(Some(frame)) => exec.run(frame, budget),
+        Ok(None) => Ok(None),
+        Err(e) => Err(e),
+    };
+    exec.finish(outcome)
+}
+
+/// Give up a paused call, returning the stacks it borrowed to the store's
+/// arena.
+pub(crate) fn abandon(store: &mut AwwasmStore<'_>, paused: AwwasmPausedCall) {
+    let AwwasmPausedCall { stack, labels, frames, arena, .. } = paused;
+    if let Some(limits) = arena {
+        AwwasmExecArena::restore(store, stack, labels, frames, limits);
+    }
+}
+
+/// Continue a paused call for another `budget`.
+pub(crate) fn resume<'a>(
+    store: &mut AwwasmStore<'a>,
+    paused: AwwasmPausedCall,
+    host: &mut dyn AwwasmHost,
+    budget: Option<u64>,
+) -> Result<AwwasmProgress, AwwasmRuntimeError> {
+    let AwwasmPausedCall { stack, labels, frames, frame, metrics, arena } = paused;
+    let limits = store.stack_limits();
+    let mut exec = Executor { store, host, limits, stack, labels, frames, metrics, arena };
+    let outcome = exec.run(frame, budget);
+    exec.finish(outcome)
+}
+
+/// Lower the body of the guest function at `addr` unless that already
+/// happened, and cache the result on the function instance if the
+/// store's code cache has room.
+pub(crate) fn compile(store: &mut AwwasmStore<'_>, addr: AwwasmFuncAddr) -> Result<Arc<AwwasmCompiledCode>, AwwasmRuntimeError> {
+    let AwwasmFuncInst::Wasm(f) = store.func(addr)? else {
+        return Err(AwwasmRuntimeError::HostFunctionNotExecutable);
+    };
+    let bytes = match &f.code {
+        LazyResolvedCodeRef::Resolved { compiled, .. } => return Ok(compiled.clone()),
+        LazyResolvedCodeRef::Unparsed { bytes } => *bytes,
+    };
+    let module = store.module(f.module).ok_or_else(|| parse_error("unknown module"))?;
+    let compiled = Arc::new(lower_body(bytes, &module.types, &module.refs, store.instrumentation().as_deref())?);
+    if store.code_cache_fits(compiled.heap_bytes()) {
+        install(store, addr, compiled.clone())?;
+    }
+    Ok(compiled)
+}
+
+/// Lower a raw function body (locals and instructions) of a module with
+/// type section `types` and declared function references `refs`, and
+/// instrument it for fuel priced by `meter` if given.
+pub(crate) fn lower_body(
+    bytes: &[u8],
+    types: &[AwwasmFuncType],
+    refs: &BTreeSet<u32>,
+    meter: Option<&AwwasmGasTable>,
+) -> Result<AwwasmCompiledCode, AwwasmRuntimeError> {
+    let (locals, code) = resolve_body(bytes)?;
+    let code = ir::lower(locals, code, types, refs)?;
+    match meter {
+        Some(table) => fuel::instrument(code, table),
+        None => Ok(code),
+    }
+}
+
+/// Cache `compiled` as the lowered body of the guest function at `addr`.
+pub(crate) fn install(
+    store: &mut AwwasmStore<'_>,
+    addr: AwwasmFuncAddr,
+    compiled: Arc<AwwasmCompiledCode>,
+) -> Result<(), AwwasmRuntimeError> {
+    let AwwasmFuncInst::Wasm(f) = store.func_mut(addr)? else {
+        return Err(AwwasmRuntimeError::HostFunctionNotExecutable);
+    };
+    let (locals, code) = body(f)?;
+    let bytes = compiled.heap_bytes();
+    f.code = LazyResolvedCodeRef::Resolved { locals, code, compiled };
+    store.add_code_cache_bytes(bytes);
+    Ok(())
+}
+
+/// Locals declarations and instruction bytes of a guest function.
+pub(crate) fn body<'a>(f: &AwwasmWasmFuncInst<'a>) -> Result<(Vec<AwwasmLocalDecl>, &'a [u8]), AwwasmRuntimeError> {
+    match &f.code {
+        LazyResolvedCodeRef::Unparsed { bytes } => resolve_body(bytes),
+        LazyResolvedCodeRef::Resolved { locals, code, .. } => Ok((locals.clone(), *code)),
+    }
+}
+
+/// Decode the locals declarations at the start of a function body.
+pub(crate) fn resolve_body(bytes: &[u8]) -> Result<(Vec<AwwasmLocalDecl>, &[u8]), AwwasmRuntimeError> {
+    let mut r = AwwasmReader::new(bytes);
+    let malformed = |_| parse_error("malformed locals declaration");
+    let groups = r.u32().map_err(malformed)?;
+    let mut locals = Vec::new();
+    for _ in 0..groups {
+        let count = r.u32().map_err(malformed)?;
+        let type_ = r.value_type().map_err(malformed)?;
+        locals.push(AwwasmLocalDecl { count, type_ });
+    }
+    // Each group takes at least two bytes, so `locals` is bounded by the
+    // body size; the slots they declare are not.
+    let total = locals.iter().map(|decl| u64::from(decl.count)).sum::<u64>();
+    if total > u64::from(MAX_FUNCTION_LOCALS) {
+        return Err(AwwasmRuntimeError::BodyLimitExceeded {
+            limit: AwwasmBodyLimit::Locals,
+            max: MAX_FUNCTION_LOCALS,
+            actual: total,
+        });
+    }
+    Ok((locals, &bytes[r.pos()..]))
+}
+
+fn trap(trap: AwwasmTrap) -> AwwasmRuntimeError {
+    AwwasmRuntimeError::Trap(trap)
+}
+
+/// Float → integer truncation bounds check shared by the trapping
+/// conversions. `lo`/`hi` are exclusive bounds on the truncated value.
+fn trunc_check(x: f64, lo: f64, hi: f64) -> Result<f64, AwwasmRuntimeError> {
+    if x.is_nan() {
+        return Err(trap(AwwasmTrap::InvalidConversionToInteger));
+    }
+    if !(x > lo && x < hi) {
+        return Err(trap(AwwasmTrap::IntegerOverflow));
+    }
+    Ok(x)
+}
+
+/// `min` with wasm semantics: NaN propagates and -0 < +0.
+macro_rules! wasm_min {
+    ($t:ty, $a:expr, $b:expr) => {{
+        let (a, b) = ($a, $b);
+        if a.is_nan() || b.is_nan() {
+            <$t>::NAN
+        } else if a == b {
+            <$t>::from_bits(a.to_bits() | b.to_bits())
+        } else {
+            a.min(b)
+        }
+    }};
+}
+
+/// `max` with wasm semantics: NaN propagates and +0 > -0.
+macro_rules! wasm_max {
+    ($t:ty, $a:expr, $b:expr) => {{
+        let (a, b) = ($a, $b);
+        if a.is_nan() || b.is_nan() {
+            <$t>::NAN
+        } else if a == b {
+            <$t>::from_bits(a.to_bits() & b.to_bits())
+        } else {
+            a.max(b)
+        }
+    }};
+}
+
+/// First opcode in `Executor::NUMERIC`.
+const NUMERIC_BASE: u8 = 0x45;
+/// Number of opcodes in `Executor::NUMERIC`, through `i64.extend32_s`.
+const NUMERIC_LEN: usize = 0xc5 - NUMERIC_BASE as usize;
+
+impl<'s, 'a, 'h> Executor<'s, 'a, 'h> {
+    // ------------------------------------------------------------------
+    // Operand stack
+    // ------------------------------------------------------------------
+
+    #[inline]
+    fn push(&mut self, value: AwwasmValue) -> Result<(), AwwasmRuntimeError> {
+        if self.stack.len() >= self.limits.value_stack {
+            return Err(trap(AwwasmTrap::StackOverflow));
+        }
+        self.stack.push(value);
+        Ok(())
+    }
+
+    #[inline]
+    fn pop(&mut self) -> Result<AwwasmValue, AwwasmRuntimeError> {
+        self.stack.pop().ok_or_else(|| parse_error("operand stack underflow"))
+    }
+
+    fn mismatch(expected: &str, got: AwwasmValue) -> AwwasmRuntimeError {
+        AwwasmRuntimeError::TypeMismatch {
+            expected: String::from(expected),
+            got: String::from(got.value_type().name()),
+        }
+    }
+
+    #[inline]
+    fn pop_i32(&mut self) -> Result<i32, AwwasmRuntimeError> {
+        match self.pop()? {
+            AwwasmValue::I32(v) => Ok(v),
+            other => Err(Self::mismatch("i32", other)),
+        }
+    }
+
+    #[inline]
+    fn pop_i64(&mut self) -> Result<i64, AwwasmRuntimeError> {
+        match self.pop()? {
+            AwwasmValue::I64(v) => Ok(v),
+            other => Err(Self::mismatch("i64", other)),
+        }
+    }
+
+    #[inline]
+    fn pop_f32(&mut self) -> Result<f32, AwwasmRuntimeError> {
+        match self.pop()? {
+            AwwasmValue::F32(v) => Ok(v),
+            other => Err(Self::mismatch("f32", other)),
+        }
+    }
+
+    #[inline]
+    fn pop_f64(&mut self) -> Result<f64, AwwasmRuntimeError> {
+        match self.pop()? {
+            AwwasmValue::F64(v) => Ok(v),
+            other => Err(Self::mismatch("f64", other)),
+        }
+    }
+
+    /// Keep the top `arity` values and drop everything down to `height`.
+    fn unwind(&mut self, height: usize, arity: usize) -> Result<(), AwwasmRuntimeError> {
+        let top = self
+            .stack
+            .len()
+            .checked_sub(arity)
+            .filter(|&top| top >= height)
+            .ok_or_else(|| parse_error("operand stack underflow"))?;
+        self.stack.drain(height..top);
+        Ok(())
+    }
+
+    // ------------------------------------------------------------------
+    // Calls
+    // ------------------------------------------------------------------
+
+    /// Enter the function at `addr`. Its arguments are on top of the
+    /// operand stack. Host functions run to completion immediately;
+    /// for guest functions the new frame is returned for `run` to enter.
+    fn call(&mut self, addr: AwwasmFuncAddr, caller: Option<&Frame>) -> Result<Option<Frame>, AwwasmRuntimeError> {
+        #[cfg(feature = "alloc")]
+        if self.store.safepoint() {
+            return Err(trap(AwwasmTrap::Interrupted));
+        }
+
+        let (params, results) = match self.store.func_type(addr).ok() {
+            Some(ty) => (ty.params.len(), ty.results.len()),
+            // A host function invoked directly by the embedder: hand it
+            // every argument.
+            None => (self.stack.len(), 0),
+        };
+        if self.stack.len() < params {
+            return Err(parse_error("operand stack underflow"));
+        }
+
+        let (module, code) = match self.store.func(addr)? {
+            AwwasmFuncInst::Host(f) => {
+                let host_func_id = f.host_func_id;
+                let args: Vec<AwwasmValue> = self.stack.split_off(self.stack.len() - params);
+                if let Some(metrics) = &mut self.metrics {
+                    metrics.host_calls += 1;
+                }
+                self.profile_enter(addr);
+                let module = caller.map(|f| f.module);
+                let accessor = self.store.set_memory_accessor(AwwasmMemAccessor::HostFunc { host_func_id, module });
+                // The interpreter's own state is not in the store, so the
+                // host may have all of it, even to run guest code.
+                let started = self.host_call_clock();
+                let mut host_caller = AwwasmCaller::new(&mut *self.store, module, caller.and_then(|f| f.mem));
+                let values = call_host(&mut *self.host, host_func_id, &args, &mut host_caller);
+                self.store.set_memory_accessor(accessor);
+                if let Some(auditor) = self.store.host_call_auditor() {
+                    let (import_module, import_name) = self.store.host_import(addr).unwrap_or((b"", b""));
+                    let nanos = started.zip(self.host_call_clock()).map(|(start, end)| end.saturating_sub(start));
+                    auditor.record(&AwwasmHostCall {
+                        host_func_id,
+                        import_module: String::from_utf8_lossy(import_module).into_owned(),
+                        import_name: String::from_utf8_lossy(import_name).into_owned(),
+                        module,
+                        args,
+                        result: values.clone(),
+                        nanos,
+                    });
+                }
+                self.profile_exit(addr);
+                for value in values.map_err(trap)? {
+                    self.push(value)?;
+                }
+                return Ok(None);
+            }
+            AwwasmFuncInst::Wasm(f) => match &f.code {
+                LazyResolvedCodeRef::Resolved { compiled, .. } => (f.module, compiled.clone()),
+                LazyResolvedCodeRef::Unparsed { .. } => (f.module, compile(self.store, addr)?),
+            },
+        };
+
+        if self.frames.len() + usize::from(caller.is_some()) >= self.limits.call_depth {
+            return Err(trap(AwwasmTrap::CallStackExhausted));
+        }
+        let base = self.stack.len() - params;
+        for decl in &code.locals {
+            for _ in 0..decl.count {
+                self.push(AwwasmValue::default_for_type(decl.type_))?;
+            }
+        }
+        let mem = self
+            .store
+            .module(module)
+            .ok_or_else(|| parse_error("unknown module"))?
+            .memaddrs
+            .first()
+            .copied();
+        self.profile_enter(addr);
+        Ok(Some(Frame {
+            func: addr,
+            module,
+            mem,
+            code,
+            pc: 0,
+            locals: base,
+            labels: self.labels.len(),
+            arity: results,
+        }))
+    }
+
+    /// Return from `frame`, keeping its results.
+    fn ret(&mut self, frame: &Frame) -> Result<(), AwwasmRuntimeError> {
+        self.unwind(frame.locals, frame.arity)?;
+        self.labels.truncate(frame.labels);
+        self.profile_exit(frame.func);
+        Ok(())
+    }
+
+    /// Platform monotonic time, if host calls are audited and there is a
+    /// platform to tell it.
+    #[inline]
+    fn host_call_clock(&self) -> Option<u64> {
+        self.store.host_call_auditor()?;
+        #[cfg(feature = "alloc")]
+        return self.store.platform().map(|platform| platform.monotonic_nanos());
+        #[cfg(not(feature = "alloc"))]
+        None
+    }
+
+    /// Tell the store's profiler, if any, that `addr` is entered.
+    #[inline]
+    fn profile_enter(&self, addr: AwwasmFuncAddr) {
+        #[cfg(feature = "alloc")]
+        if let Some(profiler) = self.store.profiler() {
+            profiler.enter(addr);
+        }
+    }
+
+    /// Tell the store's profiler, if any, that `addr` is left.
+    #[inline]
+    fn profile_exit(&self, addr: AwwasmFuncAddr) {
+        #[cfg(feature = "alloc")]
+        if let Some(profiler) = self.store.profiler() {
+            profiler.exit(addr);
+        }
+    }
+
+    /// Branch to the label `depth` levels out.
+    fn branch(&mut self, frame: &mut Frame, depth: u32) -> Result<(), AwwasmRuntimeError> {
+        let Some(idx) = (self.labels.len() - frame.labels).checked_sub(depth as usize + 1) else {
+            // Branching to the function body's own label is a return.
+            if depth as usize == self.labels.len() - frame.labels {
+                frame.pc = frame.code.ops.len();
+                return Ok(());
+            }
+            return Err(parse_error("branch depth out of range"));
+        };
+        let label = self.labels[frame.labels + idx];
+        self.unwind(label.height, label.arity)?;
+        frame.pc = label.target;
+        if label.is_loop {
+            self.labels.truncate(frame.labels + idx + 1);
+            #[cfg(feature = "alloc")]
+            if self.store.safepoint() {
+                return Err(trap(AwwasmTrap::Interrupted));
+            }
+        } else {
+            self.labels.truncate(frame.labels + idx);
+        }
+        Ok(())
+    }
+
+    /// Push the label for a block-like instruction; branches to it
+    /// continue at `target`.
+    fn enter_block(&mut self, is_loop: bool, target: usize, params: u32, results: u32) -> Result<(), AwwasmRuntimeError> {
+        let height = self
+            .stack
+            .len()
+            .checked_sub(params as usize)
+            .ok_or_else(|| parse_error("operand stack underflow"))?;
+        if self.labels.len() >= self.limits.label_depth {
+            return Err(trap(AwwasmTrap::CallStackExhausted));
+        }
+        self.labels.push(Label {
+            is_loop,
+            target,
+            height,
+            arity: if is_loop { params } else { results } as usize,
+        });
+        Ok(())
+    }
+
+    fn mem_addr(&self, frame: &Frame) -> Result<AwwasmMemAddr, AwwasmRuntimeError> {
+        frame.mem.ok_or_else(|| parse_error("memory instruction without a memory"))
+    }
+
+    /// Read `N` bytes at `base + offset` of the frame's memory.
+    fn load<const N: usize>(&mut self, frame: &Frame, offset: u32) -> Result<[u8; N], AwwasmRuntimeError> {
+        let base = self.pop_i32()? as u32;
+        let strategy = self.store.bounds_check();
+        let mem = self.store.mem(self.mem_addr(frame)?)?;
+        mem.guest_load(strategy, base, offset).map_err(trap)
+    }
+
+    /// Write `bytes` at `base + offset`; the value has already been popped.
+    fn store_bytes(&mut self, frame: &Frame, offset: u32, bytes: &[u8]) -> Result<(), AwwasmRuntimeError> {
+        let base = self.pop_i32()? as u32;
+        let addr = self.mem_addr(frame)?;
+        let strategy = self.store.bounds_check();
+        let mem = self.store.mem_mut(addr)?;
+        mem.guest_store(strategy, base, offset, bytes).map_err(trap)
+    }
+
+    // ------------------------------------------------------------------
+    // Main loop
+    // ------------------------------------------------------------------
+
+    /// Execute until the outermost frame returns, or until `budget` is
+    /// spent; then return the frame to resume in. Pauses fall between
+    /// two instructions, so no instruction is ever half done.
+    fn run(&mut self, mut frame: Frame, budget: Option<u64>) -> Result<Option<Frame>, AwwasmRuntimeError> {
+        let fuel_at_start = self.store.fuel();
+        let mut steps = 0u64;
+        loop {
+            let outcome = match self.step(&mut frame) {
+                Ok(StepOutcome::Returned) => self.ret(&frame).map(|()| StepOutcome::Returned),
+                outcome => outcome,
+            };
+            if let Some(metrics) = &mut self.metrics {
+                metrics.max_stack_depth = metrics.max_stack_depth.max(self.stack.len());
+            }
+            match outcome {
+                Ok(StepOutcome::Continue) => {}
+                Ok(StepOutcome::Called(callee)) => {
+                    let caller = core::mem::replace(&mut frame, callee);
+                    self.frames.push(caller);
+                }
+                Ok(StepOutcome::Returned) => match self.frames.pop() {
+                    Some(caller) => frame = caller,
+                    None => return Ok(None),
+                },
+                Err(e) => {
+                    // A trap leaves every active call.
+                    self.profile_exit(frame.func);
+                    for caller in self.frames.iter().rev() {
+                        self.profile_exit(caller.func);
+                    }
+                    return Err(self.in_function(&frame, e));
+                }
+            }
+            if let Some(budget) = budget {
+                steps += 1;
+                let spent = match (fuel_at_start, self.store.fuel()) {
+                    (Some(start), Some(left)) => start.saturating_sub(left),
+                    _ => steps,
+                };
+                if spent >= budget {
+                    return Ok(Some(frame));
+                }
+            }
+        }
+    }
+
+    /// Package what `run` returned: the results, or the state to resume.
+    fn finish(self, outcome: Result<Option<Frame>, AwwasmRuntimeError>) -> Result<AwwasmProgress, AwwasmRuntimeError> {
+        let Executor { store, stack, labels, frames, metrics, arena, .. } = self;
+        match outcome {
+            Ok(Some(frame)) => Ok(AwwasmProgress::Paused(AwwasmPausedCall { stack, labels, frames, frame, metrics, arena })),
+            outcome => {
+                if let Some(mut metrics) = metrics {
+                    metrics.max_stack_depth = metrics.max_stack_depth.max(stack.len());
+                    store.set_last_metrics(metrics);
+                }
+                let Some(limits) = arena else {
+                    return outcome.map(|_| AwwasmProgress::Done(stack));
+                };
+                // Only the results leave the arena.
+                let progress = outcome.map(|_| AwwasmProgress::Done(stack.to_vec()));
+                AwwasmExecArena::restore(store, stack, labels, frames, limits);
+                progress
+            }
+        }
+    }
+
+    /// Charge the store's fuel for executing `op`.
+    fn charge(&mut self, op: &Op) -> Result<(), AwwasmRuntimeError> {
+        #[cfg(feature = "alloc")]
+        let cost = self.store.gas_table().map_or(1, |table| table.cost(op));
+        #[cfg(not(feature = "alloc"))]
+        let cost = 1;
+        self.store.charge_fuel(cost).map_err(trap)
+    }
+
+    /// Attach the function `frame` is executing to an error.
+    fn in_function(&self, frame: &Frame, error: AwwasmRuntimeError) -> AwwasmRuntimeError {
+        let func_idx = self
+            .store
+            .module(frame.module)
+            .and_then(|m| m.funcaddrs.iter().position(|&a| a == frame.func))
+            .unwrap_or(frame.func.0 as usize);
+        AwwasmRuntimeError::InFunction {
+            module: frame.module.0,
+            func_idx: func_idx as u32,
+            source: Box::new(error),
+        }
+    }
+
+    /// Execute one instruction of `frame`.
+    fn step(&mut self, frame: &mut Frame) -> Result<StepOutcome, AwwasmRuntimeError> {
+        let Some(&op) = frame.code.ops.get(frame.pc) else {
+            return Ok(StepOutcome::Returned);
+        };
+        frame.pc += 1;
+        if self.store.fuel().is_some() && !frame.code.metered {
+            self.charge(&op)?;
+        }
+        if let Some(metrics) = &mut self.metrics {
+            metrics.instructions += op.width();
+        }
+
+        match op {
+            // ---------------- control ----------------
+            Op::Unreachable => return Err(trap(AwwasmTrap::Unreachable)),
+            Op::Nop => {}
+            Op::Block { params, results, end } => self.enter_block(false, end as usize, params, results)?,
+            Op::Loop { params, results } => self.enter_block(true, frame.pc, params, results)?,
+            Op::If { params, results, else_, end } => {
+                let cond = self.pop_i32()?;
+                self.enter_block(false, end as usize, params, results)?;
+                if cond == 0 {
+                    // Either the `else` arm or, without one, the `end`
+                    // that pops the label.
+                    frame.pc = else_ as usize;
+                }
+            }
+            Op::Else { end } => {
+                // End of the taken `then` arm: skip the `else` arm.
+                self.labels.pop().ok_or_else(|| parse_error("else without if"))?;
+                frame.pc = end as usize;
+            }
+            Op::End => {
+                if self.labels.len() > frame.labels {
+                    self.labels.pop();
+                } else {
+                    return Ok(StepOutcome::Returned);
+                }
+            }
+            Op::Br(depth) => self.branch(frame, depth)?,
+            Op::BrIf(depth) => {
+                if self.pop_i32()? != 0 {
+                    self.branch(frame, depth)?;
+                }
+            }
+            Op::BrTable { start, len } => {
+                let idx = self.pop_i32()? as u32;
+                let slot = start + idx.min(len);
+                let depth = *frame
+                    .code
+                    .br_tables
+                    .get(slot as usize)
+                    .ok_or_else(|| parse_error("malformed br_table"))?;
+                self.branch(frame, depth)?;
+            }
+            Op::Return => return Ok(StepOutcome::Returned),
+            Op::Call(idx) => {
+                let addr = self
+                    .store
+                    .module(frame.module)
+                    .and_then(|m| m.func(idx))
+                    .ok_or_else(|| parse_error("function index out of range"))?;
+                if let Some(callee) = self.call(addr, Some(frame))? {
+                    return Ok(StepOutcome::Called(callee));
+                }
+            }
+            Op::CallIndirect { type_idx, table_idx, cache } => {
+                let elem = self.pop_i32()? as u32;
+                let module = self.store.module(frame.module).ok_or_else(|| parse_error("unknown module"))?;
+                let expected = *module
+                    .type_ids
+                    .get(type_idx as usize)
+                    .ok_or_else(|| parse_error("type index out of range"))?;
+                let table = module.table(table_idx).ok_or_else(|| parse_error("table index out of range"))?;
+                let addr = self
+                    .store
+                    .table(table)?
+                    .get(elem)
+                    .map_err(trap)?
+                    .ok_or_else(|| trap(AwwasmTrap::IndirectCallToNull))?;
+                let cache = &frame.code.call_caches[cache as usize];
+                if !cache.hit(addr.0) {
+                    if self.store.func_type_id(addr).ok() != Some(expected) {
+                        // The callee may come from another module, so
+                        // report signatures rather than type indices.
+                        let expected = module
+                            .types
+                            .get(type_idx as usize)
+                            .map(|ty| format!("{}", ty))
+                            .ok_or_else(|| parse_error("type index out of range"))?;
+                        let got = match self.store.func_type(addr) {
+                            Ok(ty) => format!("{}", ty),
+                            Err(_) => String::from("an untyped host function"),
+                        };
+                        return Err(trap(AwwasmTrap::IndirectCallTypeMismatch { expected, got }));
+                    }
+                    cache.set(addr.0);
+                }
+                if let Some(callee) = self.call(addr, Some(frame))? {
+                    return Ok(StepOutcome::Called(callee));
+                }
+            }
+
+            // ---------------- parametric ----------------
+            Op::Drop => {
+                self.pop()?;
+            }
+            Op::Select => {
+                let cond = self.pop_i32()?;
+                let b = self.pop()?;
+                let a = self.pop()?;
+                self.push(if cond != 0 { a } else { b })?;
+            }
+
+            // ---------------- references ----------------
+            Op::RefNull(ty) => self.push(AwwasmValue::default_for_type(ty))?,
+            Op::RefIsNull => {
+                let value = self.pop()?;
+                let null = value.is_null().ok_or_else(|| Self::mismatch("reference", value))?;
+                self.push(AwwasmValue::I32(null as i32))?;
+            }
+            Op::RefFunc(idx) => {
+                let addr = self
+                    .store
+                    .module(frame.module)
+                    .and_then(|m| m.func(idx))
+                    .ok_or_else(|| parse_error("function index out of range"))?;
+                self.push(AwwasmValue::FuncRef(Some(addr)))?;
+            }
+
+            // ---------------- variables ----------------
+            Op::LocalGet(idx) => {
+                let value = *self
+                    .stack
+                    .get(frame.locals + idx as usize)
+                    .ok_or_else(|| parse_error("local index out of range"))?;
+                self.push(value)?;
+            }
+            Op::LocalSet(idx) | Op::LocalTee(idx) => {
+                let value = if let Op::LocalTee(_) = op {
+                    *self.stack.last().ok_or_else(|| parse_error("operand stack underflow"))?
+                } else {
+                    self.pop()?
+                };
+                let slot = self
+                    .stack
+                    .get_mut(frame.locals + idx as usize)
+                    .ok_or_else(|| parse_error("local index out of range"))?;
+                *slot = value;
+            }
+            Op::GlobalGet(idx) => {
+                let addr = self
+                    .store
+                    .module(frame.module)
+                    .and_then(|m| m.global(idx))
+                    .ok_or_else(|| parse_error("global index out of range"))?;
+                let global = self.store.global(addr)?;
+                let value = match global.host_global_id {
+                    Some(id) => {
+                        let ty = global.type_.value_type;
+                        let value = guard_host(|| self.host.get_global(id, ty)).map_err(trap)?;
+                        if value.value_type() != ty {
+                            return Err(GlobalError::TypeMismatch { expected: ty, got: value.value_type() }.at(idx));
+                        }
+                        value
+                    }
+                    None => global.get(),
+                };
+                self.push(value)?;
+            }
+            Op::GlobalSet(idx) => {
+                let addr = self
+                    .store
+                    .module(frame.module)
+                    .and_then(|m| m.global(idx))
+                    .ok_or_else(|| parse_error("global index out of range"))?;
+                let value = self.pop()?;
+                let global = self.store.global_mut(addr)?;
+                match global.host_global_id {
+                    Some(id) => {
+                        global.check_set(value).map_err(|e| e.at(idx))?;
+                        guard_host(|| self.host.set_global(id, value)).map_err(trap)?;
+                    }
+                    None => global.set(value).map_err(|e| e.at(idx))?,
+                }
+            }
+
+            // ---------------- memory ----------------
+            Op::Load { opcode, offset } => self.load_op(frame, opcode, offset)?,
+            Op::Store { opcode, offset } => self.store_op(frame, opcode, offset)?,
+            Op::Atomic { sub, offset } => self.atomic_op(frame, sub, offset)?,
+            Op::MemorySize => {
+                let pages = self.store.mem(self.mem_addr(frame)?)?.size_pages();
+                self.push(AwwasmValue::I32(pages as i32))?;
+            }
+            Op::MemoryGrow => {
+                let delta = self.pop_i32()? as u32;
+                let addr = self.mem_addr(frame)?;
+                #[cfg(feature = "alloc")]
+                if let Some(table) = self.store.gas_table() {
+                    let cost = table.memory_grow_page().saturating_mul(u64::from(delta));
+                    self.store.charge_fuel(cost).map_err(trap)?;
+                }
+                let old = self.store.mem_mut(addr)?.grow(delta).map_or(-1, |old| old as i32);
+                if old >= 0 {
+                    if let Some(metrics) = &mut self.metrics {
+                        metrics.pages_grown += u64::from(delta);
+                    }
+                }
+                self.push(AwwasmValue::I32(old))?;
+            }
+            Op::MemoryInit(data_idx) => {
+                let n = self.pop_i32()? as u32;
+                let src = self.pop_i32()? as u32;
+                let dst = self.pop_i32()? as u32;
+                let data_addr = self
+                    .store
+                    .module(frame.module)
+                    .and_then(|m| m.data(data_idx))
+                    .ok_or_else(|| parse_error("data index out of range"))?;
+                let bytes = self.store.data(data_addr).and_then(|d| d.bytes()).unwrap_or(&[]);
+                let end = src.checked_add(n).filter(|&end| end as usize <= bytes.len()).ok_or_else(|| {
+                    trap(AwwasmTrap::MemoryOutOfBounds { offset: src, size: n, memory_size: bytes.len() as u32 })
+                })?;
+                let bytes = &bytes[src as usize..end as usize];
+                let mem_addr = self.mem_addr(frame)?;
+                self.store.mem_mut(mem_addr)?.guest_write(dst, bytes).map_err(trap)?;
+            }
+            Op::DataDrop(data_idx) => {
+                if let Some(addr) = self.store.module(frame.module).and_then(|m| m.data(data_idx)) {
+                    if let Some(data) = self.store.data_mut(addr) {
+                        data.drop_data();
+                    }
+                }
+            }
+            Op::MemoryCopy => {
+                let n = self.pop_i32()? as u32;
+                let src = self.pop_i32()? as u32;
+                let dst = self.pop_i32()? as u32;
+                let addr = self.mem_addr(frame)?;
+                self.store.mem_mut(addr)?.guest_copy_within(dst, src, n).map_err(trap)?;
+            }
+            Op::MemoryFill => {
+                let n = self.pop_i32()? as u32;
+                let value = self.pop_i32()? as u8;
+                let dst = self.pop_i32()? as u32;
+                let addr = self.mem_addr(frame)?;
+                self.store.mem_mut(addr)?.guest_fill(dst, value, n).map_err(trap)?;
+            }
+
+            // ---------------- constants ----------------
+            Op::I32Const(v) => self.push(AwwasmValue::I32(v))?,
+            Op::I64Const(v) => self.push(AwwasmValue::I64(v))?,
+            Op::F32Const(bits) => self.push(AwwasmValue::F32(f32::from_bits(bits)))?,
+            Op::F64Const(bits) => self.push(AwwasmValue::F64(f64::from_bits(bits)))?,
+
+            // ---------------- numeric ----------------
+            Op::Num(opcode) => self.numeric(opcode)?,
+            Op::TruncSat(sub) => self.trunc_sat(sub)?,
+
+            // ---------------- superinstructions ----------------
+            Op::I32AddLocals { lhs, rhs, dst } => {
+                let local = |idx: u32| match self.stack.get(frame.locals + idx as usize) {
+                    Some(AwwasmValue::I32(v)) => Ok(*v),
+                    Some(other) => Err(Self::mismatch("i32", *other)),
+                    None => Err(parse_error("local index out of range")),
+                };
+                let sum = local(lhs)?.wrapping_add(local(rhs)?);
+                let slot = self
+                    .stack
+                    .get_mut(frame.locals + dst as usize)
+                    .ok_or_else(|| parse_error("local index out of range"))?;
+                *slot = AwwasmValue::I32(sum);
+            }
+            Op::I32AddImm(imm) => {
+                let a = self.pop_i32()?;
+                self.push(AwwasmValue::I32(a.wrapping_add(imm)))?;
+            }
+            Op::I32CmpBrIf { cmp, depth } => {
+                self.numeric(cmp)?;
+                if self.pop_i32()? != 0 {
+                    self.branch(frame, depth)?;
+                }
+            }
+            Op::ConsumeFuel(cost) => self.store.charge_fuel(cost).map_err(trap)?,
+        }
+
+        Ok(StepOutcome::Continue)
+    }
+
+    fn load_op(&mut self, frame: &Frame, opcode: u8, offset: u32) -> Result<(), AwwasmRuntimeError> {
+        let value = match opcode {
+            0x28 => AwwasmValue::I32(i32::from_le_bytes(self.load::<4>(frame, offset)?)),
+            0x29 => AwwasmValue::I64(i64::from_le_bytes(self.load::<8>(frame, offset)?)),
+            0x2a => AwwasmValue::F32(f32::from_le_bytes(self.load::<4>(frame, offset)?)),
+            0x2b => AwwasmValue::F64(f64::from_le_bytes(self.load::<8>(frame, offset)?)),
+            0x2c => AwwasmValue::I32(self.load::<1>(frame, offset)?[0] as i8 as i32),
+            0x2d => AwwasmValue::I32(self.load::<1>(frame, offset)?[0] as i32),
+            0x2e => AwwasmValue::I32(i16::from_le_bytes(self.load::<2>(frame, offset)?) as i32),
+            0x2f => AwwasmValue::I32(u16::from_le_bytes(self.load::<2>(frame, offset)?) as i32),
+            0x30 => AwwasmValue::I64(self.load::<1>(frame, offset)?[0] as i8 as i64),
+            0x31 => AwwasmValue::I64(self.load::<1>(frame, offset)?[0] as i64),
+            0x32 => AwwasmValue::I64(i16::from_le_bytes(self.load::<2>(frame, offset)?) as i64),
+            0x33 => AwwasmValue::I64(u16::from_le_bytes(self.load::<2>(frame, offset)?) as i64),
+            0x34 => AwwasmValue::I64(i32::from_le_bytes(self.load::<4>(frame, offset)?) as i64),
+            0x35 => AwwasmValue::I64(u32::from_le_bytes(self.load::<4>(frame, offset)?) as i64),
+            _ => return Err(parse_error("unknown load opcode")),
+        };
+        self.push(value)
+    }
+
+    /// `memory.atomic.notify` (0), `wait32` (1) and `wait64` (2). A wait
+    /// blocks this thread until another one, sharing the memory through
+    /// its own store, notifies it.
+    fn atomic_op(&mut self, frame: &Frame, sub: u8, offset: u32) -> Result<(), AwwasmRuntimeError> {
+        let result = match sub {
+            0 => {
+                let count = self.pop_i32()? as u32;
+                let (mem, ea) = self.atomic_addr(frame, offset, 4)?;
+                mem.atomic_notify(ea, count).map_err(trap)? as i32
+            }
+            1 => {
+                let timeout = self.pop_i64()?;
+                let expected = self.pop_i32()?;
+                let (mem, ea) = self.atomic_addr(frame, offset, 4)?;
+                mem.atomic_wait32(ea, expected, timeout).map_err(trap)? as i32
+            }
+            2 => {
+                let timeout = self.pop_i64()?;
+                let expected = self.pop_i64()?;
+                let (mem, ea) = self.atomic_addr(frame, offset, 8)?;
+                mem.atomic_wait64(ea, expected, timeout).map_err(trap)? as i32
+            }
+            _ => return Err(parse_error("unknown atomic opcode")),
+        };
+        self.push(AwwasmValue::I32(result))
+    }
+
+    /// Pop the base address of an atomic access and resolve it against
+    /// the frame's memory.
+    fn atomic_addr(&mut self, frame: &Frame, offset: u32, size: u32) -> Result<(&AwwasmMemInst, u32), AwwasmRuntimeError> {
+        let base = self.pop_i32()? as u32;
+        let mem = self.store.mem(self.mem_addr(frame)?)?;
+        let ea = u32::try_from(u64::from(base) + u64::from(offset)).map_err(|_| {
+            trap(AwwasmTrap::MemoryOutOfBounds { offset: u32::MAX, size, memory_size: mem.data.len() as u32 })
+        })?;
+        Ok((mem, ea))
+    }
+
+    fn store_op(&mut self, frame: &Frame, opcode: u8, offset: u32) -> Result<(), AwwasmRuntimeError> {
+        match opcode {
+            0x36 => {
+                let v = self.pop_i32()?;
+                self.store_bytes(frame, offset, &v.to_le_bytes())
+            }
+            0x37 => {
+                let v = self.pop_i64()?;
+                self.store_bytes(frame, offset, &v.to_le_bytes())
+            }
+            0x38 => {
+                let v = self.pop_f32()?;
+                self.store_bytes(frame, offset, &v.to_le_bytes())
+            }
+            0x39 => {
+                let v = self.pop_f64()?;
+                self.store_bytes(frame, offset, &v.to_le_bytes())
+            }
+            0x3a => {
+                let v = self.pop_i32()?;
+                self.store_bytes(frame, offset, &[v as u8])
+            }
+            0x3b => {
+                let v = self.pop_i32()?;
+                self.store_bytes(frame, offset, &(v as u16).to_le_bytes())
+            }
+            0x3c => {
+                let v = self.pop_i64()?;
+                self.store_bytes(frame, offset, &[v as u8])
+            }
+            0x3d => {
+                let v = self.pop_i64()?;
+                self.store_bytes(frame, offset, &(v as u16).to_le_bytes())
+            }
+            0x3e => {
+                let v = self.pop_i64()?;
+                self.store_bytes(frame, offset, &(v as u32).to_le_bytes())
+            }
+            _ => Err(parse_error("unknown store opcode")),
+        }
+    }
+
+    /// Handlers for the numeric instructions without immediates
+    /// (`0x45..=0xc4`), indexed by opcode from `NUMERIC_BASE`. `numeric`
+    /// jumps straight to the handler instead of going through a second
+    /// `match` on the opcode byte.
+    const NUMERIC: [Option<fn(&mut Self) -> Result<(), AwwasmRuntimeError>>; NUMERIC_LEN] = {
+        macro_rules! un {
+            ($pop:ident, $ctor:ident, |$a:ident| $e:expr) => {
+                |ex: &mut Self| -> Result<(), AwwasmRuntimeError> {
+                    let $a = ex.$pop()?;
+                    ex.push(AwwasmValue::$ctor($e))
+                }
+            };
+        }
+        macro_rules! bin {
+            ($pop:ident, $ctor:ident, |$a:ident, $b:ident| $e:expr) => {
+                |ex: &mut Self| -> Result<(), AwwasmRuntimeError> {
+                    let $b = ex.$pop()?;
+                    let $a = ex.$pop()?;
+                    ex.push(AwwasmValue::$ctor($e))
+                }
+            };
+        }
+        macro_rules! test {
+            ($pop:ident, |$a:ident| $e:expr) => {
+                |ex: &mut Self| -> Result<(), AwwasmRuntimeError> {
+                    let $a = ex.$pop()?;
+                    ex.push(AwwasmValue::I32(($e) as i32))
+                }
+            };
+        }
+        macro_rules! cmp {
+            ($pop:ident, |$a:ident, $b:ident| $e:expr) => {
+                |ex: &mut Self| -> Result<(), AwwasmRuntimeError> {
+                    let $b = ex.$pop()?;
+                    let $a = ex.$pop()?;
+                    ex.push(AwwasmValue::I32(($e) as i32))
+                }
+            };
+        }
+        macro_rules! handlers {
+            ($($op:literal => $handler:expr,)*) => {{
+                let mut table: [Option<fn(&mut Self) -> Result<(), AwwasmRuntimeError>>; NUMERIC_LEN] =
+                    [None; NUMERIC_LEN];
+                $(table[$op - NUMERIC_BASE as usize] =
+                    Some($handler as fn(&mut Self) -> Result<(), AwwasmRuntimeError>);)*
+                table
+            }};
+        }
+
+        handlers! {
+            // ---------------- i32 comparisons ----------------
+            0x45 => test!(pop_i32, |a| a == 0),
+            0x46 => cmp!(pop_i32, |a, b| a == b),
+            0x47 => cmp!(pop_i32, |a, b| a != b),
+            0x48 => cmp!(pop_i32, |a, b| a < b),
+            0x49 => cmp!(pop_i32, |a, b| (a as u32) < (b as u32)),
+            0x4a => cmp!(pop_i32, |a, b| a > b),
+            0x4b => cmp!(pop_i32, |a, b| (a as u32) > (b as u32)),
+            0x4c => cmp!(pop_i32, |a, b| a <= b),
+            0x4d => cmp!(pop_i32, |a, b| (a as u32) <= (b as u32)),
+            0x4e => cmp!(pop_i32, |a, b| a >= b),
+            0x4f => cmp!(pop_i32, |a, b| (a as u32) >= (b as u32)),
+
+            // ---------------- i64 comparisons ----------------
+            0x50 => test!(pop_i64, |a| a == 0),
+            0x51 => cmp!(pop_i64, |a, b| a == b),
+            0x52 => cmp!(pop_i64, |a, b| a != b),
+            0x53 => cmp!(pop_i64, |a, b| a < b),
+            0x54 => cmp!(pop_i64, |a, b| (a as u64) < (b as u64)),
+            0x55 => cmp!(pop_i64, |a, b| a > b),
+            0x56 => cmp!(pop_i64, |a, b| (a as u64) > (b as u64)),
+            0x57 => cmp!(pop_i64, |a, b| a <= b),
+            0x58 => cmp!(pop_i64, |a, b| (a as u64) <= (b as u64)),
+            0x59 => cmp!(pop_i64, |a, b| a >= b),
+            0x5a => cmp!(pop_i64, |a, b| (a as u64) >= (b as u64)),
+
+            // ---------------- float comparisons ----------------
+            0x5b => cmp!(pop_f32, |a, b| a == b),
+            0x5c => cmp!(pop_f32, |a, b| a != b),
+            0x5d => cmp!(pop_f32, |a, b| a < b),
+            0x5e => cmp!(pop_f32, |a, b| a > b),
+            0x5f => cmp!(pop_f32, |a, b| a <= b),
+            0x60 => cmp!(pop_f32, |a, b| a >= b),
+            0x61 => cmp!(pop_f64, |a, b| a == b),
+            0x62 => cmp!(pop_f64, |a, b| a != b),
+            0x63 => cmp!(pop_f64, |a, b| a < b),
+            0x64 => cmp!(pop_f64, |a, b| a > b),
+            0x65 => cmp!(pop_f64, |a, b| a <= b),
+            0x66 => cmp!(pop_f64, |a, b| a >= b),
+
+            // ---------------- i32 arithmetic ----------------
+            0x67 => un!(pop_i32, I32, |a| a.leading_zeros() as i32),
+            0x68 => un!(pop_i32, I32, |a| a.trailing_zeros() as i32),
+            0x69 => un!(pop_i32, I32, |a| a.count_ones() as i32),
+            0x6a => bin!(pop_i32, I32, |a, b| a.wrapping_add(b)),
+            0x6b => bin!(pop_i32, I32, |a, b| a.wrapping_sub(b)),
+            0x6c => bin!(pop_i32, I32, |a, b| a.wrapping_mul(b)),
+            0x6d => bin!(pop_i32, I32, |a, b| {
+                if b == 0 {
+                    return Err(trap(AwwasmTrap::DivisionByZero));
+                }
+                a.checked_div(b).ok_or_else(|| trap(AwwasmTrap::IntegerOverflow))?
+            }),
+            0x6e => bin!(pop_i32, I32, |a, b| {
+                (a as u32).checked_div(b as u32).ok_or_else(|| trap(AwwasmTrap::DivisionByZero))? as i32
+            }),
+            0x6f => bin!(pop_i32, I32, |a, b| {
+                if b == 0 {
+                    return Err(trap(AwwasmTrap::DivisionByZero));
+                }
+                a.wrapping_rem(b)
+            }),
+            0x70 => bin!(pop_i32, I32, |a, b| {
+                (a as u32).checked_rem(b as u32).ok_or_else(|| trap(AwwasmTrap::DivisionByZero))? as i32
+            }),
+            0x71 => bin!(pop_i32, I32, |a, b| a & b),
+            0x72 => bin!(pop_i32, I32, |a, b| a | b),
+            0x73 => bin!(pop_i32, I32, |a, b| a ^ b),
+            0x74 => bin!(pop_i32, I32, |a, b| a.wrapping_shl(b as u32)),
+            0x75 => bin!(pop_i32, I32, |a, b| a.wrapping_shr(b as u32)),
+            0x76 => bin!(pop_i32, I32, |a, b| (a as u32).wrapping_shr(b as u32) as i32),
+            0x77 => bin!(pop_i32, I32, |a, b| a.rotate_left(b as u32 % 32)),
+            0x78 => bin!(pop_i32, I32, |a, b| a.rotate_right(b as u32 % 32)),
+
+            // ---------------- i64 arithmetic ----------------
+            0x79 => un!(pop_i64, I64, |a| a.leading_zeros() as i64),
+            0x7a => un!(pop_i64, I64, |a| a.trailing_zeros() as i64),
+            0x7b => un!(pop_i64, I64, |a| a.count_ones() as i64),
+            0x7c => bin!(pop_i64, I64, |a, b| a.wrapping_add(b)),
+            0x7d => bin!(pop_i64, I64, |a, b| a.wrapping_sub(b)),
+            0x7e => bin!(pop_i64, I64, |a, b| a.wrapping_mul(b)),
+            0x7f => bin!(pop_i64, I64, |a, b| {
+                if b == 0 {
+                    return Err(trap(AwwasmTrap::DivisionByZero));
+                }
+                a.checked_div(b).ok_or_else(|| trap(AwwasmTrap::IntegerOverflow))?
+            }),
+            0x80 => bin!(pop_i64, I64, |a, b| {
+                (a as u64).checked_div(b as u64).ok_or_else(|| trap(AwwasmTrap::DivisionByZero))? as i64
+            }),
+            0x81 => bin!(pop_i64, I64, |a, b| {
+                if b == 0 {
+                    return Err(trap(AwwasmTrap::DivisionByZero));
+                }
+                a.wrapping_rem(b)
+            }),
+            0x82 => bin!(pop_i64, I64, |a, b| {
+                (a as u64).checked_rem(b as u64).ok_or_else(|| trap(AwwasmTrap::DivisionByZero))? as i64
+            }),
+            0x83 => bin!(pop_i64, I64, |a, b| a & b),
+            0x84 => bin!(pop_i64, I64, |a, b| a | b),
+            0x85 => bin!(pop_i64, I64, |a, b| a ^ b),
+            0x86 => bin!(pop_i64, I64, |a, b| a.wrapping_shl(b as u32)),
+            0x87 => bin!(pop_i64, I64, |a, b| a.wrapping_shr(b as u32)),
+            0x88 => bin!(pop_i64, I64, |a, b| (a as u64).wrapping_shr(b as u32) as i64),
+            0x89 => bin!(pop_i64, I64, |a, b| a.rotate_left((b as u64 % 64) as u32)),
+            0x8a => bin!(pop_i64, I64, |a, b| a.rotate_right((b as u64 % 64) as u32)),
+
+            // ---------------- f32 arithmetic ----------------
+            0x8b => un!(pop_f32, F32, |a| f32::from_bits(a.to_bits() & 0x7fff_ffff)),
+            0x8c => un!(pop_f32, F32, |a| f32::from_bits(a.to_bits() ^ 0x8000_0000)),
+            0x8d => un!(pop_f32, F32, |a| fp::f32_ceil(a)),
+            0x8e => un!(pop_f32, F32, |a| fp::f32_floor(a)),
+            0x8f => un!(pop_f32, F32, |a| fp::f32_trunc(a)),
+            0x90 => un!(pop_f32, F32, |a| fp::f32_nearest(a)),
+            0x91 => un!(pop_f32, F32, |a| fp::f32_sqrt(a)),
+            0x92 => bin!(pop_f32, F32, |a, b| fp::f32_add(a, b)),
+            0x93 => bin!(pop_f32, F32, |a, b| fp::f32_sub(a, b)),
+            0x94 => bin!(pop_f32, F32, |a, b| fp::f32_mul(a, b)),
+            0x95 => bin!(pop_f32, F32, |a, b| fp::f32_div(a, b)),
+            0x96 => bin!(pop_f32, F32, |a, b| wasm_min!(f32, a, b)),
+            0x97 => bin!(pop_f32, F32, |a, b| wasm_max!(f32, a, b)),
+            0x98 => bin!(pop_f32, F32, |a, b| {
+                f32::from_bits((a.to_bits() & 0x7fff_ffff) | (b.to_bits() & 0x8000_0000))
+            }),
+
+            // ---------------- f64 arithmetic ----------------
+            0x99 => un!(pop_f64, F64, |a| f64::from_bits(a.to_bits() & 0x7fff_ffff_ffff_ffff)),
+            0x9a => un!(pop_f64, F64, |a| f64::from_bits(a.to_bits() ^ 0x8000_0000_0000_0000)),
+            0x9b => un!(pop_f64, F64, |a| fp::f64_ceil(a)),
+            0x9c => un!(pop_f64, F64, |a| fp::f64_floor(a)),
+            0x9d => un!(pop_f64, F64, |a| fp::f64_trunc(a)),
+            0x9e => un!(pop_f64, F64, |a| fp::f64_nearest(a)),
+            0x9f => un!(pop_f64, F64, |a| fp::f64_sqrt(a)),
+            0xa0 => bin!(pop_f64, F64, |a, b| fp::f64_add(a, b)),
+            0xa1 => bin!(pop_f64, F64, |a, b| fp::f64_sub(a, b)),
+            0xa2 => bin!(pop_f64, F64, |a, b| fp::f64_mul(a, b)),
+            0xa3 => bin!(pop_f64, F64, |a, b| fp::f64_div(a, b)),
+            0xa4 => bin!(pop_f64, F64, |a, b| wasm_min!(f64, a, b)),
+            0xa5 => bin!(pop_f64, F64, |a, b| wasm_max!(f64, a, b)),
+            0xa6 => bin!(pop_f64, F64, |a, b| {
+                f64::from_bits((a.to_bits() & 0x7fff_ffff_ffff_ffff) | (b.to_bits() & 0x8000_0000_0000_0000))
+            }),
+
+            // ---------------- conversions ----------------
+            0xa7 => un!(pop_i64, I32, |a| a as i32),
+            0xa8 => un!(pop_f32, I32, |a| trunc_check(a as f64, -2147483649.0, 2147483648.0)? as i32),
+            0xa9 => un!(pop_f32, I32, |a| trunc_check(a as f64, -1.0, 4294967296.0)? as u32 as i32),
+            0xaa => un!(pop_f64, I32, |a| trunc_check(a, -2147483649.0, 2147483648.0)? as i32),
+            0xab => un!(pop_f64, I32, |a| trunc_check(a, -1.0, 4294967296.0)? as u32 as i32),
+            0xac => un!(pop_i32, I64, |a| a as i64),
+            0xad => un!(pop_i32, I64, |a| a as u32 as i64),
+            0xae => un!(pop_f32, I64, |a| trunc_check(a as f64, -9223372036854777856.0, 9223372036854775808.0)? as i64),
+            0xaf => un!(pop_f32, I64, |a| trunc_check(a as f64, -1.0, 18446744073709551616.0)? as u64 as i64),
+            0xb0 => un!(pop_f64, I64, |a| trunc_check(a, -9223372036854777856.0, 9223372036854775808.0)? as i64),
+            0xb1 => un!(pop_f64, I64, |a| trunc_check(a, -1.0, 18446744073709551616.0)? as u64 as i64),
+            0xb2 => un!(pop_i32, F32, |a| fp::i64_to_f32(a as i64)),
+            0xb3 => un!(pop_i32, F32, |a| fp::u64_to_f32(a as u32 as u64)),
+            0xb4 => un!(pop_i64, F32, |a| fp::i64_to_f32(a)),
+            0xb5 => un!(pop_i64, F32, |a| fp::u64_to_f32(a as u64)),
+            0xb6 => un!(pop_f64, F32, |a| fp::f64_to_f32(a)),
+            0xb7 => un!(pop_i32, F64, |a| fp::i64_to_f64(a as i64)),
+            0xb8 => un!(pop_i32, F64, |a| fp::u64_to_f64(a as u32 as u64)),
+            0xb9 => un!(pop_i64, F64, |a| fp::i64_to_f64(a)),
+            0xba => un!(pop_i64, F64, |a| fp::u64_to_f64(a as u64)),
+            0xbb => un!(pop_f32, F64, |a| fp::f32_to_f64(a)),
+            0xbc => un!(pop_f32, I32, |a| a.to_bits() as i32),
+            0xbd => un!(pop_f64, I64, |a| a.to_bits() as i64),
+            0xbe => un!(pop_i32, F32, |a| f32::from_bits(a as u32)),
+            0xbf => un!(pop_i64, F64, |a| f64::from_bits(a as u64)),
+
+            // ---------------- sign extension ----------------
+            0xc0 => un!(pop_i32, I32, |a| a as i8 as i32),
+            0xc1 => un!(pop_i32, I32, |a| a as i16 as i32),
+            0xc2 => un!(pop_i64, I64, |a| a as i8 as i64),
+            0xc3 => un!(pop_i64, I64, |a| a as i16 as i64),
+            0xc4 => un!(pop_i64, I64, |a| a as i32 as i64),
+        }
+    };
+
+    /// Execute a numeric instruction without immediates.
+    #[inline]
+    fn numeric(&mut self, op: u8) -> Result<(), AwwasmRuntimeError> {
+        let handler = op.checked_sub(NUMERIC_BASE).and_then(|i| Self::NUMERIC.get(i as usize).copied().flatten());
+        match handler {
+            Some(handler) => handler(self),
+            None => Err(AwwasmRuntimeError::InstructionParseError(format!("unsupported opcode 0x{:02x}", op))),
+        }
+    }
+
+    /// Saturating float → integer truncation (`0xfc 0..=7`). Rust's `as`
+    /// already saturates and maps NaN to zero.
+    fn trunc_sat(&mut self, sub: u8) -> Result<(), AwwasmRuntimeError> {
+        macro_rules! un {
+            ($pop:ident, $ctor:ident, |$a:ident| $e:expr) => {{
+                let $a = self.$pop()?;
+                self.push(AwwasmValue::$ctor($e))?;
+            }};
+        }
+
+        match sub {
+            0 => un!(pop_f32, I32, |a| a as i32),
+            1 => un!(pop_f32, I32, |a| a as u32 as i32),
+            2 => un!(pop_f64, I32, |a| a as i32),
+            3 => un!(pop_f64, I32, |a| a as u32 as i32),
+            4 => un!(pop_f32, I64, |a| a as i64),
+            5 => un!(pop_f32, I64, |a| a as u64 as i64),
+            6 => un!(pop_f64, I64, |a| a as i64),
+            7 => un!(pop_f64, I64, |a| a as u64 as i64),
+            _ => return Err(parse_error("unknown saturating truncation")),
+        }
+        Ok(())
+    }
+}
+
+/// What `step` did to the frame stack.
+enum StepOutcome {
+    /// Still in the same frame.
+    Continue,
+    /// A guest function was called; execution continues in its frame.
+    Called(Frame),
+    /// The current frame returned.
+    Returned,
+}
@@ -0,0 +1,240 @@
+//! Fuel metering and gas cost tables.
+//!
+//! A store with fuel (`AwwasmStore::set_fuel`) charges every instruction
+//! the interpreter executes against it and traps with
+//! `AwwasmTrap::OutOfFuel` once the next instruction costs more than is
+//! left. By default every instruction costs 1; an `AwwasmGasTable` set
+//! with `AwwasmStore::set_gas_table` assigns each opcode its own weight
+//! and prices the pages `memory.grow` asks for, so a deployment can
+//! follow its protocol's gas schedule.
+//!
+//! With `AwwasmMetering::Instrumented`, bodies are instead rewritten when
+//! they are lowered: `instrument` puts one `ConsumeFuel` instruction at
+//! the start of every straight-line run of code (function entry, block
+//! entries, loop back-edge targets, and after every branch), charging
+//! the whole run at once. Metering then lives in the code itself rather
+//! than in the interpreter loop, so any other engine that executes the
+//! same lowered code meters it identically.
+
+#[cfg(feature = "alloc")]
+use alloc::vec::Vec;
+
+use crate::artifact;
+use crate::error::AwwasmRuntimeError;
+use crate::ir::{self, AwwasmCompiledCode, Op};
+
+/// Number of `0xfc`-prefixed opcodes a table prices
+/// (`i32.trunc_sat_f32_s` through `table.fill`).
+const PREFIXED_OPS: usize = 18;
+
+/// Per-opcode fuel costs.
+///
+/// Costs are keyed by binary opcode: `set_op(0x6a, 3)` prices `i32.add`,
+/// `set_prefixed_op(10, 50)` prices `memory.copy` (`0xfc 10`). The
+/// interpreter's fused instructions cost the sum of the instructions
+/// they replace, so results do not depend on how a body was lowered.
+/// The `0xfe`-prefixed wait and notify all cost `set_op(0xfe, ..)`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AwwasmGasTable {
+    ops: [u32; 256],
+    prefixed: [u32; PREFIXED_OPS],
+    grow_page: u64,
+}
+
+impl AwwasmGasTable {
+    /// A table charging `cost` for every instruction and nothing for
+    /// grown pages.
+    pub fn uniform(cost: u32) -> Self {
+        Self { ops: [cost; 256], prefixed: [cost; PREFIXED_OPS], grow_page: 0 }
+    }
+
+    /// Price the single-byte opcode `opcode`.
+    pub fn set_op(&mut self, opcode: u8, cost: u32) -> &mut Self {
+        self.ops[usize::from(opcode)] = cost;
+        self
+    }
+
+    /// Price the `0xfc`-prefixed opcode `sub`. Returns `None` for
+    /// opcodes the table does not know.
+    pub fn set_prefixed_op(&mut self, sub: u32, cost: u32) -> Option<&mut Self> {
+        *self.prefixed.get_mut(sub as usize)? = cost;
+        Some(self)
+    }
+
+    /// Charge `cost` per 64 KiB page `memory.grow` requests, on top of
+    /// the instruction itself. Charged before growing, so a failed grow
+    /// still pays.
+    pub fn set_memory_grow_page(&mut self, cost: u64) -> &mut Self {
+        self.grow_page = cost;
+        self
+    }
+
+    /// The cost of the single-byte opcode `opcode`.
+    pub fn op(&self, opcode: u8) -> u32 {
+        self.ops[usize::from(opcode)]
+    }
+
+    /// The cost of the `0xfc`-prefixed opcode `sub`, if the table knows it.
+    pub fn prefixed_op(&self, sub: u32) -> Option<u32> {
+        self.prefixed.get(sub as usize).copied()
+    }
+
+    /// The cost of each page `memory.grow` requests.
+    pub fn memory_grow_page(&self) -> u64 {
+        self.grow_page
+    }
+
+    /// FNV-1a hash of every price in the table, identifying it in
+    /// precompiled artifacts.
+    pub(crate) fn hash(&self) -> u64 {
+        let mut bytes = Vec::new();
+        for cost in self.ops.iter().chain(&self.prefixed) {
+            bytes.extend_from_slice(&cost.to_le_bytes());
+        }
+        bytes.extend_from_slice(&self.grow_page.to_le_bytes());
+        artifact::body_hash(&bytes)
+    }
+
+    /// The cost of executing `op`.
+    pub(crate) fn cost(&self, op: &Op) -> u64 {
+        let code = |opcode: u8| u64::from(self.ops[usize::from(opcode)]);
+        let prefixed = |sub: usize| u64::from(self.prefixed[sub]);
+        match *op {
+            Op::Unreachable => code(0x00),
+            Op::Nop => code(0x01),
+            Op::Block { .. } => code(0x02),
+            Op::Loop { .. } => code(0x03),
+            Op::If { .. } => code(0x04),
+            Op::Else { .. } => code(0x05),
+            Op::End => code(0x0b),
+            Op::Br(_) => code(0x0c),
+            Op::BrIf(_) => code(0x0d),
+            Op::BrTable { .. } => code(0x0e),
+            Op::Return => code(0x0f),
+            Op::Call(_) => code(0x10),
+            Op::CallIndirect { .. } => code(0x11),
+            Op::Drop => code(0x1a),
+            Op::Select => code(0x1b),
+            Op::LocalGet(_) => code(0x20),
+            Op::LocalSet(_) => code(0x21),
+            Op::LocalTee(_) => code(0x22),
+            Op::GlobalGet(_) => code(0x23),
+            Op::GlobalSet(_) => code(0x24),
+            Op::Load { opcode, .. } | Op::Store { opcode, .. } => code(opcode),
+            Op::MemorySize => code(0x3f),
+            Op::MemoryGrow => code(0x40),
+            Op::I32Const(_) => code(0x41),
+            Op::I64Const(_) => code(0x42),
+            Op::F32Const(_) => code(0x43),
+            Op::F64Const(_) => code(0x44),
+            Op::Num(opcode) => code(opcode),
+            Op::TruncSat(sub) => prefixed(usize::from(sub)),
+            Op::RefNull(_) => code(0xd0),
+            Op::RefIsNull => code(0xd1),
+            Op::RefFunc(_) => code(0xd2),
+            Op::MemoryInit(_) => prefixed(8),
+            Op::DataDrop(_) => prefixed(9),
+            Op::MemoryCopy => prefixed(10),
+            Op::MemoryFill => prefixed(11),
+            Op::Atomic { .. } => code(0xfe),
+            Op::I32AddLocals { .. } => 2 * code(0x20) + code(0x6a) + code(0x21),
+            Op::I32AddImm(_) => code(0x41) + code(0x6a),
+            Op::I32CmpBrIf { cmp, .. } => code(cmp) + code(0x0d),
+            Op::ConsumeFuel(_) => 0,
+        }
+    }
+}
+
+impl Default for AwwasmGasTable {
+    /// Every instruction costs 1.
+    fn default() -> Self {
+        Self::uniform(1)
+    }
+}
+
+/// Where a metered store charges fuel; see `AwwasmStore::set_metering`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "fuzz", derive(arbitrary::Arbitrary))]
+pub enum AwwasmMetering {
+    /// The interpreter charges every instruction as it executes it.
+    #[default]
+    PerInstruction,
+    /// Bodies are rewritten by `instrument` when lowered and charge a
+    /// whole straight-line run on entering it. A trap part-way through a
+    /// run has already paid for all of it.
+    Instrumented,
+}
+
+/// Insert a `ConsumeFuel` charging each straight-line run of `code` as
+/// priced by `table`, at the start of the run.
+///
+/// A run starts at the function entry, at every branch target and after
+/// every instruction that may branch; control only enters a run at its
+/// start, so each executed run pays exactly once.
+pub(crate) fn instrument(code: AwwasmCompiledCode, table: &AwwasmGasTable) -> Result<AwwasmCompiledCode, AwwasmRuntimeError> {
+    let ops = &code.ops;
+    // `leader[i]`: op `i` starts a run; `leader[ops.len()]` closes the last.
+    let mut leader = Vec::new();
+    ir::reserve(&mut leader, ops.len() + 1)?;
+    leader.resize(ops.len() + 1, false);
+    leader[0] = true;
+    for (i, op) in ops.iter().enumerate() {
+        match *op {
+            // Without an `else` arm, a false condition lands on the `end`.
+            Op::If { else_, .. } => {
+                leader[i + 1] = true;
+                leader[else_ as usize] = true;
+            }
+            // A loop's label targets the op after it.
+            Op::Block { .. }
+            | Op::Loop { .. }
+            | Op::Else { .. }
+            | Op::End
+            | Op::Br(_)
+            | Op::BrIf(_)
+            | Op::BrTable { .. }
+            | Op::Return
+            | Op::Unreachable
+            | Op::I32CmpBrIf { .. } => leader[i + 1] = true,
+            _ => {}
+        }
+    }
+    leader[ops.len()] = true;
+
+    let mut out = Vec::new();
+    ir::reserve(&mut out, ops.len() + leader.iter().filter(|&&l| l).count())?;
+    // `remap[i]` is the new index of old op `i`, or of the `ConsumeFuel`
+    // before it, so branches to a run pay for it.
+    let mut remap = Vec::new();
+    ir::reserve(&mut remap, ops.len() + 1)?;
+    let mut start = 0;
+    while start < ops.len() {
+        let end = start + 1 + leader[start + 1..].iter().position(|&l| l).unwrap_or(0);
+        let run = &ops[start..end];
+        let cost = run.iter().map(|op| table.cost(op)).fold(0, u64::saturating_add);
+        remap.push(out.len() as u32);
+        if cost > 0 {
+            out.push(Op::ConsumeFuel(cost));
+        }
+        out.push(run[0]);
+        for &op in &run[1..] {
+            remap.push(out.len() as u32);
+            out.push(op);
+        }
+        start = end;
+    }
+    remap.push(out.len() as u32);
+
+    let at = |idx: u32| remap[idx as usize];
+    for op in &mut out {
+        match op {
+            Op::Block { end, .. } | Op::Else { end } => *end = at(*end),
+            Op::If { else_, end, .. } => {
+                *else_ = at(*else_);
+                *end = at(*end);
+            }
+            _ => {}
+        }
+    }
+    Ok(AwwasmCompiledCode { ops: out, metered: true, ..code })
+}
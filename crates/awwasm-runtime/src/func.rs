@@ -5,10 +5,15 @@
 
 #[cfg(feature = "alloc")]
 use alloc::vec::Vec;
+#[cfg(feature = "alloc")]
+use alloc::sync::Arc;
 
 use core::marker::PhantomData;
 
-use crate::values::AwwasmModuleAddr;
+use crate::values::{AwwasmModuleAddr, AwwasmValue, AwwasmValueType};
+use crate::memory::AwwasmMemInst;
+use crate::error::AwwasmTrap;
+use crate::caller::AwwasmCaller;
 
 /// Function type signature.
 #[derive(Debug, Clone, PartialEq, Eq)]
@@ -26,11 +31,28 @@ impl AwwasmFuncType {
     }
 }
 
+/// Text-format signature, e.g. `(func (param i32 i32) (result i32))`.
+impl core::fmt::Display for AwwasmFuncType {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.write_str("(func")?;
+        for (keyword, types) in [("param", &self.params), ("result", &self.results)] {
+            if !types.is_empty() {
+                write!(f, " ({}", keyword)?;
+                for ty in types {
+                    write!(f, " {}", ty)?;
+                }
+                f.write_str(")")?;
+            }
+        }
+        f.write_str(")")
+    }
+}
+
 /// Lazy code representation for function bodies.
 ///
 /// This enum enables lazy parsing: function bodies are stored as raw
 /// bytes until they are actually executed, at which point they are
-/// parsed into instructions.
+/// lowered to the interpreter's internal representation.
 #[derive(Debug, Clone)]
 pub enum LazyResolvedCodeRef<'a> {
     /// Raw bytes, not yet parsed.
@@ -39,14 +61,14 @@ pub enum LazyResolvedCodeRef<'a> {
         /// Raw function body bytes (from parsed module).
         bytes: &'a [u8],
     },
-    /// Parsed locals and code bytes ready for interpretation.
-    /// Note: We keep code as bytes for the interpreter to use
-    /// with InstructionIterator for streaming execution.
+    /// Parsed locals and the lowered body ready for interpretation.
     Resolved {
         /// Local variable declarations (count, type).
         locals: Vec<AwwasmLocalDecl>,
         /// Code bytes (instruction sequence).
         code: &'a [u8],
+        /// The body with immediates pre-decoded, shared by every call.
+        compiled: Arc<crate::ir::AwwasmCompiledCode>,
     },
 }
 
@@ -95,6 +117,57 @@ pub struct AwwasmHostFuncInst {
     pub host_func_id: u32,
 }
 
+/// Embedder-side dispatcher for host function calls.
+///
+/// Host function instances only carry a `host_func_id`. When one is
+/// called, the id, the arguments and the caller's default memory are
+/// handed to this trait, which performs the actual work. A host function
+/// that needs the rest of the store, e.g. to call back into guest code,
+/// overrides `call_with_caller` instead.
+///
+/// Host-backed globals (`AwwasmGlobalInst::host`) are read and written
+/// through `get_global` and `set_global` the same way.
+pub trait AwwasmHost {
+    /// Execute the host function identified by `host_func_id`.
+    fn call(
+        &mut self,
+        host_func_id: u32,
+        args: &[AwwasmValue],
+        mem: Option<&mut AwwasmMemInst>,
+    ) -> Result<Vec<AwwasmValue>, AwwasmTrap>;
+
+    /// Execute the host function identified by `host_func_id` with the
+    /// calling store at hand; this is what the interpreter calls.
+    ///
+    /// The default passes the caller's default memory on to `call`.
+    fn call_with_caller(
+        &mut self,
+        host_func_id: u32,
+        args: &[AwwasmValue],
+        caller: &mut AwwasmCaller<'_, '_>,
+    ) -> Result<Vec<AwwasmValue>, AwwasmTrap> {
+        self.call(host_func_id, args, caller.memory_mut())
+    }
+
+    /// Read the host-backed global identified by `host_global_id`.
+    ///
+    /// The value must be of type `value_type`. The default traps with
+    /// `InvalidHostCall`.
+    fn get_global(&mut self, host_global_id: u32, value_type: AwwasmValueType) -> Result<AwwasmValue, AwwasmTrap> {
+        let _ = value_type;
+        Err(AwwasmTrap::InvalidHostCall { host_func_id: host_global_id })
+    }
+
+    /// Write the host-backed global identified by `host_global_id`.
+    ///
+    /// Only called for mutable globals, with a value of the global's
+    /// type. The default traps with `InvalidHostCall`.
+    fn set_global(&mut self, host_global_id: u32, value: AwwasmValue) -> Result<(), AwwasmTrap> {
+        let _ = value;
+        Err(AwwasmTrap::InvalidHostCall { host_func_id: host_global_id })
+    }
+}
+
 impl<'a> AwwasmFuncInst<'a> {
     /// Create a new WebAssembly function instance.
     pub fn wasm(type_idx: u32, module: AwwasmModuleAddr, code_bytes: &'a [u8]) -> Self {
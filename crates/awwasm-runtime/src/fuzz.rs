@@ -0,0 +1,243 @@
+//! Fuzzing entry points and `arbitrary::Arbitrary` implementations.
+//!
+//! Each target takes the raw fuzzer input and never panics on its own;
+//! a panic, abort or hang is a runtime bug. Wire them into `cargo fuzz`
+//! (or OSS-Fuzz) with one line per target:
+//!
+//! ```ignore
+//! // fuzz/fuzz_targets/execute.rs
+//! #![no_main]
+//! libfuzzer_sys::fuzz_target!(|data: &[u8]| awwasm_runtime::fuzz::execute(data));
+//! ```
+//!
+//! - `instantiate`: the input is a module, instantiated without imports.
+//! - `execute`: an `AwwasmFuzzConfig`, a module and the answers of its
+//!   imported functions; every function export is called with arbitrary
+//!   arguments under a fuel limit.
+//! - `memory_ops`: a sequence of `testing::AwwasmMemoryOp`s, checked
+//!   against `testing::AwwasmMemoryModel`.
+//!
+//! The `Arbitrary` impls keep sizes small (memories of at most
+//! `MAX_FUZZ_PAGES`, bounded fuel) so the fuzzer spends its time in the
+//! runtime rather than allocating.
+
+use std::vec::Vec;
+
+use arbitrary::{Arbitrary, Result, Unstructured};
+
+use awwasm_parser::components::module::AwwasmModule;
+
+use crate::error::AwwasmTrap;
+use crate::exec::{AwwasmStackLimits, MAX_CALL_DEPTH, MAX_LABEL_DEPTH, MAX_VALUE_STACK};
+use crate::fuel::{AwwasmGasTable, AwwasmMetering};
+use crate::func::{AwwasmFuncInst, AwwasmHost};
+use crate::global::AwwasmGlobalInst;
+use crate::imports::AwwasmImports;
+use crate::info::{AwwasmExternType, AwwasmModuleInfo};
+use crate::memory::{AwwasmMemInst, AwwasmMemoryType, DEFAULT_PAGE_SIZE_LOG2};
+use crate::store::AwwasmStore;
+use crate::table::AwwasmTableInst;
+use crate::testing::{check_memory_ops, AwwasmMemoryOp};
+use crate::values::{AwwasmValue, AwwasmValueType};
+
+/// Largest memory, in 64 KiB pages, the fuzz targets allocate.
+pub const MAX_FUZZ_PAGES: u32 = 16;
+
+/// Largest table the fuzz targets allocate for an import.
+const MAX_FUZZ_TABLE: u32 = 10_000;
+
+/// Most fuel one call of `execute` may burn.
+const MAX_FUZZ_FUEL: u64 = 1_000_000;
+
+/// Store settings for one `execute` run.
+#[derive(Debug, Clone)]
+pub struct AwwasmFuzzConfig {
+    pub stack_limits: AwwasmStackLimits,
+    /// Fuel for each export call; always set, so guests terminate.
+    pub fuel: u64,
+    pub metering: AwwasmMetering,
+    pub gas_table: Option<AwwasmGasTable>,
+    pub code_cache_limit: Option<usize>,
+}
+
+impl AwwasmFuzzConfig {
+    /// Configure `store` with these settings.
+    pub fn apply(&self, store: &mut AwwasmStore<'_>) {
+        // Fuzzed stores run without an arena, so any limits fit.
+        let _ = store.set_stack_limits(self.stack_limits);
+        store.set_metering(self.metering);
+        if let Some(table) = &self.gas_table {
+            store.set_gas_table(std::sync::Arc::new(table.clone()));
+        }
+        store.set_code_cache_limit(self.code_cache_limit);
+        store.set_fuel(Some(self.fuel));
+    }
+}
+
+impl<'a> Arbitrary<'a> for AwwasmFuzzConfig {
+    fn arbitrary(u: &mut Unstructured<'a>) -> Result<Self> {
+        Ok(Self {
+            stack_limits: u.arbitrary()?,
+            fuel: u.int_in_range(0..=MAX_FUZZ_FUEL)?,
+            metering: u.arbitrary()?,
+            gas_table: u.arbitrary()?,
+            code_cache_limit: if u.arbitrary()? { Some(u.int_in_range(0..=1 << 20)?) } else { None },
+        })
+    }
+}
+
+impl<'a> Arbitrary<'a> for AwwasmStackLimits {
+    fn arbitrary(u: &mut Unstructured<'a>) -> Result<Self> {
+        Ok(Self {
+            call_depth: u.int_in_range(1..=MAX_CALL_DEPTH)?,
+            value_stack: u.int_in_range(1..=MAX_VALUE_STACK)?,
+            label_depth: u.int_in_range(1..=MAX_LABEL_DEPTH)?,
+        })
+    }
+}
+
+impl<'a> Arbitrary<'a> for AwwasmMemoryType {
+    fn arbitrary(u: &mut Unstructured<'a>) -> Result<Self> {
+        let min = u.int_in_range(0..=MAX_FUZZ_PAGES)?;
+        let max = if u.arbitrary()? { Some(u.int_in_range(min..=MAX_FUZZ_PAGES)?) } else { None };
+        let page_size_log2 = if u.ratio(1, 8)? { 0 } else { DEFAULT_PAGE_SIZE_LOG2 };
+        Ok(Self { min, max, page_size_log2, shared: false })
+    }
+}
+
+impl<'a> Arbitrary<'a> for AwwasmGasTable {
+    fn arbitrary(u: &mut Unstructured<'a>) -> Result<Self> {
+        let mut table = AwwasmGasTable::uniform(u.int_in_range(0..=4)?);
+        for entry in u.arbitrary_iter::<(u8, u8)>()? {
+            let (opcode, cost) = entry?;
+            table.set_op(opcode, u32::from(cost));
+        }
+        table.set_memory_grow_page(u.int_in_range(0..=1_000)?);
+        Ok(table)
+    }
+}
+
+/// Instantiate `data` as a module without imports.
+pub fn instantiate(data: &[u8]) {
+    let mut store = AwwasmStore::new();
+    let _ = store.instantiate_bytes(data, &mut AwwasmImports::new());
+}
+
+/// Instantiate a module with arbitrary imports and call all of its
+/// function exports with arbitrary arguments.
+pub fn execute(data: &[u8]) {
+    let mut u = Unstructured::new(data);
+    let (Ok(config), Ok(wasm)) = (AwwasmFuzzConfig::arbitrary(&mut u), <&[u8]>::arbitrary(&mut u)) else {
+        return;
+    };
+    let Ok(mut module) = AwwasmModule::new(wasm) else { return };
+    if module.sections.is_some() && module.resolve_all_sections().is_err() {
+        return;
+    }
+    let Ok(info) = AwwasmModuleInfo::new(&module) else { return };
+
+    let mut store = AwwasmStore::new();
+    config.apply(&mut store);
+    let mut host = AwwasmFuzzHost { u, results: Vec::new() };
+    let mut imports = AwwasmImports::new();
+    for import in &info.imports {
+        match &import.ty {
+            AwwasmExternType::Func(ty) => {
+                let func = AwwasmFuncInst::host(0, host.results.len() as u32);
+                host.results.push(ty.results.clone());
+                imports.add_func(import.module, import.name, func);
+            }
+            AwwasmExternType::Memory(ty) if ty.min <= MAX_FUZZ_PAGES => {
+                if let Ok(mem) = AwwasmMemInst::try_new(*ty) {
+                    imports.add_memory(import.module, import.name, mem);
+                }
+            }
+            AwwasmExternType::Table(ty) if ty.min <= MAX_FUZZ_TABLE => {
+                if let Ok(table) = AwwasmTableInst::try_new(*ty) {
+                    let addr = store.alloc_table(table);
+                    imports.add_table(import.module, import.name, addr);
+                }
+            }
+            AwwasmExternType::Global(ty) => {
+                let value = arbitrary_value(&mut host.u, ty.value_type);
+                imports.add_global(import.module, import.name, AwwasmGlobalInst::new(*ty, value));
+            }
+            // Too big to bother; instantiation fails on the missing import.
+            AwwasmExternType::Memory(_) | AwwasmExternType::Table(_) => {}
+        }
+    }
+    let Ok(addr) = store.store_init(&module, &mut imports) else { return };
+
+    let funcs: Vec<_> = match store.module(addr) {
+        Some(inst) => inst.func_exports().map(|(_, func)| func).collect(),
+        None => return,
+    };
+    for func in funcs {
+        let Ok(params) = store.func_type(func).map(|ty| ty.params.clone()) else { continue };
+        let args: Vec<_> = params.iter().map(|&ty| arbitrary_value(&mut host.u, ty)).collect();
+        store.set_fuel(Some(config.fuel));
+        let _ = store.invoke(func, &args, &mut host);
+    }
+}
+
+/// Run arbitrary operations on an arbitrary memory and check each
+/// against `testing::AwwasmMemoryModel`.
+///
+/// # Panics
+///
+/// Panics if the memory disagrees with the model.
+pub fn memory_ops(data: &[u8]) {
+    let mut u = Unstructured::new(data);
+    let (Ok(ty), Ok(ops)) = (AwwasmMemoryType::arbitrary(&mut u), Vec::<AwwasmMemoryOp>::arbitrary(&mut u)) else {
+        return;
+    };
+    let Ok(mut mem) = AwwasmMemInst::try_new(ty) else { return };
+    // Growing past the fuzzing budget is not interesting.
+    if let Err(e) = check_memory_ops(&mut mem, ty, MAX_FUZZ_PAGES, &ops) {
+        panic!("{}", e);
+    }
+}
+
+/// Host functions of `execute`: results and occasional traps drawn from
+/// the rest of the fuzzer input.
+struct AwwasmFuzzHost<'d> {
+    u: Unstructured<'d>,
+    /// Result types, by host function id.
+    results: Vec<Vec<AwwasmValueType>>,
+}
+
+impl AwwasmHost for AwwasmFuzzHost<'_> {
+    fn call(
+        &mut self,
+        host_func_id: u32,
+        _args: &[AwwasmValue],
+        mem: Option<&mut AwwasmMemInst>,
+    ) -> core::result::Result<Vec<AwwasmValue>, AwwasmTrap> {
+        let results = self.results.get(host_func_id as usize).ok_or(AwwasmTrap::InvalidHostCall { host_func_id })?;
+        if self.u.ratio(1, 16).unwrap_or(false) {
+            return Err(AwwasmTrap::host("fuzz host error"));
+        }
+        // Scribble on the caller's memory now and then, as WASI calls do.
+        if let (Some(mem), Ok(true)) = (mem, self.u.ratio(1, 4)) {
+            if let (Ok(offset), Ok(bytes)) = (self.u.arbitrary::<u32>(), self.u.arbitrary::<&[u8]>()) {
+                let _ = mem.write(offset % (mem.size_bytes() as u32).max(1), bytes);
+            }
+        }
+        Ok(results.iter().map(|&ty| arbitrary_value(&mut self.u, ty)).collect())
+    }
+}
+
+/// An arbitrary value of type `ty`; null for function references, which
+/// must name functions of the store. Zero once the input runs out.
+fn arbitrary_value(u: &mut Unstructured<'_>, ty: AwwasmValueType) -> AwwasmValue {
+    let value = match ty {
+        AwwasmValueType::I32 => u.arbitrary().map(AwwasmValue::I32),
+        AwwasmValueType::I64 => u.arbitrary().map(AwwasmValue::I64),
+        AwwasmValueType::F32 => u.arbitrary().map(AwwasmValue::F32),
+        AwwasmValueType::F64 => u.arbitrary().map(AwwasmValue::F64),
+        AwwasmValueType::V128 => u.arbitrary().map(AwwasmValue::V128),
+        AwwasmValueType::FuncRef => Ok(AwwasmValue::FuncRef(None)),
+        AwwasmValueType::ExternRef => u.arbitrary().map(AwwasmValue::ExternRef),
+    };
+    value.unwrap_or_else(|_| AwwasmValue::default_for_type(ty))
+}
@@ -3,6 +3,7 @@
 //! A global instance is the runtime representation of a global variable.
 
 use crate::values::AwwasmValue;
+use crate::error::GlobalError;
 
 /// Global type - describes the mutability and value type of a global.
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
@@ -31,6 +32,17 @@ impl AwwasmGlobalType {
     }
 }
 
+/// Text-format type, e.g. `(global (mut i32))`.
+impl core::fmt::Display for AwwasmGlobalType {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        if self.mutable {
+            write!(f, "(global (mut {}))", self.value_type)
+        } else {
+            write!(f, "(global {})", self.value_type)
+        }
+    }
+}
+
 /// Global instance - runtime representation of a global variable.
 #[derive(Debug, Clone)]
 pub struct AwwasmGlobalInst {
@@ -38,12 +50,30 @@ pub struct AwwasmGlobalInst {
     pub type_: AwwasmGlobalType,
     /// The current value of the global.
     pub value: AwwasmValue,
+    /// Embedder-defined id for a host-backed global; `None` for an
+    /// ordinary one.
+    pub host_global_id: Option<u32>,
 }
 
 impl AwwasmGlobalInst {
     /// Create a new global instance.
     pub fn new(type_: AwwasmGlobalType, value: AwwasmValue) -> Self {
-        Self { type_, value }
+        Self { type_, value, host_global_id: None }
+    }
+
+    /// Create a host-backed global.
+    ///
+    /// While executing, `global.get` and `global.set` on it are handed to
+    /// `AwwasmHost::get_global` / `AwwasmHost::set_global` with
+    /// `host_global_id`. Constant expressions at instantiation and the
+    /// store's own accessors only see `value`, which starts at the type's
+    /// default.
+    pub fn host(type_: AwwasmGlobalType, host_global_id: u32) -> Self {
+        Self {
+            type_,
+            value: AwwasmValue::default_for_type(type_.value_type),
+            host_global_id: Some(host_global_id),
+        }
     }
 
     /// Get the current value.
@@ -54,13 +84,23 @@ impl AwwasmGlobalInst {
 
     /// Set the value (only if mutable).
     ///
-    /// Returns an error if the global is immutable.
+    /// Returns an error if the global is immutable or `value` is not of
+    /// the global's value type.
     #[inline]
-    pub fn set(&mut self, value: AwwasmValue) -> Result<(), ()> {
+    pub fn set(&mut self, value: AwwasmValue) -> Result<(), GlobalError> {
+        self.check_set(value)?;
+        self.value = value;
+        Ok(())
+    }
+
+    /// Whether `set(value)` would succeed.
+    pub(crate) fn check_set(&self, value: AwwasmValue) -> Result<(), GlobalError> {
         if !self.type_.mutable {
-            return Err(());
+            return Err(GlobalError::Immutable);
+        }
+        if value.value_type() != self.type_.value_type {
+            return Err(GlobalError::TypeMismatch { expected: self.type_.value_type, got: value.value_type() });
         }
-        self.value = value;
         Ok(())
     }
 
@@ -69,5 +109,11 @@ impl AwwasmGlobalInst {
     pub fn is_mutable(&self) -> bool {
         self.type_.mutable
     }
+
+    /// Check if reads and writes of this global go to the host.
+    #[inline]
+    pub fn is_host(&self) -> bool {
+        self.host_global_id.is_some()
+    }
 }
 
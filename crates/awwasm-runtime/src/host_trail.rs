@@ -0,0 +1,118 @@
+//! Recording the calls a guest makes to host functions.
+//!
+//! With an `AwwasmHostCallAuditor` set (`AwwasmStore::set_host_call_auditor`),
+//! every call into a host function is reported once it returns: the
+//! import it was linked as, the calling module, the arguments, the
+//! results or trap, and how long it took by the store's platform clock
+//! (`AwwasmStore::set_platform`).
+//!
+//! `AwwasmHostCallTrail` keeps the most recent calls in a ring buffer of
+//! fixed size, so a misbehaving plugin can be investigated after the
+//! fact without the record growing with its run time.
+
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::fmt;
+
+#[cfg(feature = "std")]
+use alloc::collections::VecDeque;
+#[cfg(feature = "std")]
+use std::sync::{Mutex, MutexGuard};
+
+use crate::error::AwwasmTrap;
+use crate::values::{AwwasmModuleAddr, AwwasmValue};
+
+/// One completed host function call.
+#[derive(Debug, Clone, PartialEq)]
+pub struct AwwasmHostCall {
+    /// The called host function's `host_func_id`.
+    pub host_func_id: u32,
+    /// Module name of the import the function was first linked as; empty
+    /// when it was never imported.
+    pub import_module: String,
+    /// Field name of that import.
+    pub import_name: String,
+    /// The calling module, `None` when the embedder invoked the function
+    /// directly.
+    pub module: Option<AwwasmModuleAddr>,
+    pub args: Vec<AwwasmValue>,
+    /// What the call returned, or the trap it raised.
+    pub result: Result<Vec<AwwasmValue>, AwwasmTrap>,
+    /// Platform monotonic time the call took, in nanoseconds; `None`
+    /// without a platform.
+    pub nanos: Option<u64>,
+}
+
+/// Receives every completed host function call.
+///
+/// Called on the thread running the guest, right after the host function
+/// returns.
+pub trait AwwasmHostCallAuditor: Send + Sync + fmt::Debug {
+    /// `call` just returned.
+    fn record(&self, call: &AwwasmHostCall);
+}
+
+/// An `AwwasmHostCallAuditor` keeping the last `capacity` calls.
+#[cfg(feature = "std")]
+#[derive(Debug)]
+pub struct AwwasmHostCallTrail {
+    capacity: usize,
+    calls: Mutex<AwwasmTrailBuf>,
+}
+
+#[cfg(feature = "std")]
+#[derive(Debug, Default)]
+struct AwwasmTrailBuf {
+    calls: VecDeque<AwwasmHostCall>,
+    /// Calls pushed out by newer ones.
+    dropped: u64,
+}
+
+#[cfg(feature = "std")]
+impl AwwasmHostCallTrail {
+    /// An empty trail keeping at most `capacity` calls; older calls are
+    /// discarded first.
+    pub fn new(capacity: usize) -> Self {
+        Self { capacity, calls: Mutex::new(AwwasmTrailBuf::default()) }
+    }
+
+    /// The most calls kept.
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    /// The calls kept, oldest first.
+    pub fn calls(&self) -> Vec<AwwasmHostCall> {
+        self.lock().calls.iter().cloned().collect()
+    }
+
+    /// Remove and return the calls kept, oldest first.
+    pub fn take(&self) -> Vec<AwwasmHostCall> {
+        core::mem::take(&mut self.lock().calls).into()
+    }
+
+    /// How many calls were discarded to make room for newer ones.
+    pub fn dropped(&self) -> u64 {
+        self.lock().dropped
+    }
+
+    fn lock(&self) -> MutexGuard<'_, AwwasmTrailBuf> {
+        self.calls.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+#[cfg(feature = "std")]
+impl AwwasmHostCallAuditor for AwwasmHostCallTrail {
+    fn record(&self, call: &AwwasmHostCall) {
+        let mut buf = self.lock();
+        if self.capacity == 0 {
+            buf.dropped += 1;
+            return;
+        }
+        if buf.calls.len() == self.capacity {
+            buf.calls.pop_front();
+            buf.dropped += 1;
+        }
+        buf.calls.push_back(call.clone());
+    }
+}
@@ -5,18 +5,32 @@
 
 #[cfg(feature = "alloc")]
 use alloc::vec::Vec;
+#[cfg(feature = "alloc")]
+use alloc::collections::BTreeMap;
 
+use crate::capability::AwwasmCapabilityPolicy;
 use crate::func::AwwasmFuncInst;
 use crate::memory::AwwasmMemInst;
 use crate::global::AwwasmGlobalInst;
+use crate::values::AwwasmTableAddr;
 
 /// Host-provided imports for module instantiation.
 ///
 /// Imports are matched by (module, name) pairs against the module's
-/// import section. The order does not matter.
+/// import section. The order does not matter; adding the same pair twice
+/// replaces the earlier value.
+///
+/// A capability policy (`set_policy`) further limits which of the
+/// provided imports the module may bind.
 #[derive(Debug)]
 pub struct AwwasmImports<'a> {
-    entries: Vec<AwwasmImportEntry<'a>>,
+    /// Entries in insertion order; `take` leaves `None` behind.
+    entries: Vec<Option<AwwasmImportEntry<'a>>>,
+    /// (module, name) → index into `entries`, so lookups stay logarithmic
+    /// for modules with many imports.
+    index: BTreeMap<(&'a [u8], &'a [u8]), usize>,
+    /// Which entries the importing module may bind; `None` allows all.
+    policy: Option<AwwasmCapabilityPolicy>,
 }
 
 /// A single import entry keyed by (module, name).
@@ -39,6 +53,8 @@ pub enum AwwasmImportValue<'a> {
     Memory(AwwasmMemInst),
     /// An imported global instance.
     Global(AwwasmGlobalInst),
+    /// A table already in the store, shared with the importing module.
+    Table(AwwasmTableAddr),
 }
 
 impl<'a> AwwasmImports<'a> {
@@ -46,45 +62,193 @@ impl<'a> AwwasmImports<'a> {
     pub fn new() -> Self {
         Self {
             entries: Vec::new(),
+            index: BTreeMap::new(),
+            policy: None,
         }
     }
 
+    /// Start building an import set with chained calls.
+    pub fn builder() -> AwwasmImportsBuilder<'a> {
+        AwwasmImportsBuilder { imports: Self::new() }
+    }
+
     /// Add a function import.
-    pub fn add_func(&mut self, module: &'a [u8], name: &'a [u8], func: AwwasmFuncInst<'a>) {
-        self.entries.push(AwwasmImportEntry {
-            module,
-            name,
+    ///
+    /// Names may be byte strings or `&str`.
+    pub fn add_func<M, N>(&mut self, module: &'a M, name: &'a N, func: AwwasmFuncInst<'a>)
+    where
+        M: AsRef<[u8]> + ?Sized,
+        N: AsRef<[u8]> + ?Sized,
+    {
+        self.insert(AwwasmImportEntry {
+            module: module.as_ref(),
+            name: name.as_ref(),
             value: AwwasmImportValue::Func(func),
         });
     }
 
+    /// Add the same function import under several field names.
+    pub fn add_func_names<M, N>(&mut self, module: &'a M, names: &[&'a N], func: AwwasmFuncInst<'a>)
+    where
+        M: AsRef<[u8]> + ?Sized,
+        N: AsRef<[u8]> + ?Sized,
+    {
+        for name in names {
+            self.add_func(module, *name, func.clone());
+        }
+    }
+
     /// Add a memory import.
-    pub fn add_memory(&mut self, module: &'a [u8], name: &'a [u8], mem: AwwasmMemInst) {
-        self.entries.push(AwwasmImportEntry {
-            module,
-            name,
+    pub fn add_memory<M, N>(&mut self, module: &'a M, name: &'a N, mem: AwwasmMemInst)
+    where
+        M: AsRef<[u8]> + ?Sized,
+        N: AsRef<[u8]> + ?Sized,
+    {
+        self.insert(AwwasmImportEntry {
+            module: module.as_ref(),
+            name: name.as_ref(),
             value: AwwasmImportValue::Memory(mem),
         });
     }
 
     /// Add a global import.
-    pub fn add_global(&mut self, module: &'a [u8], name: &'a [u8], global: AwwasmGlobalInst) {
-        self.entries.push(AwwasmImportEntry {
-            module,
-            name,
+    pub fn add_global<M, N>(&mut self, module: &'a M, name: &'a N, global: AwwasmGlobalInst)
+    where
+        M: AsRef<[u8]> + ?Sized,
+        N: AsRef<[u8]> + ?Sized,
+    {
+        self.insert(AwwasmImportEntry {
+            module: module.as_ref(),
+            name: name.as_ref(),
             value: AwwasmImportValue::Global(global),
         });
     }
 
+    /// Add a table import: the table at `table` in the store the module
+    /// is instantiated into, shared rather than copied.
+    pub fn add_table<M, N>(&mut self, module: &'a M, name: &'a N, table: AwwasmTableAddr)
+    where
+        M: AsRef<[u8]> + ?Sized,
+        N: AsRef<[u8]> + ?Sized,
+    {
+        self.insert(AwwasmImportEntry {
+            module: module.as_ref(),
+            name: name.as_ref(),
+            value: AwwasmImportValue::Table(table),
+        });
+    }
+
+    fn insert(&mut self, entry: AwwasmImportEntry<'a>) {
+        match self.index.get(&(entry.module, entry.name)) {
+            Some(&pos) => self.entries[pos] = Some(entry),
+            None => {
+                self.index.insert((entry.module, entry.name), self.entries.len());
+                self.entries.push(Some(entry));
+            }
+        }
+    }
+
     /// Find an import by (module, name).
     pub fn find(&self, module: &[u8], name: &[u8]) -> Option<&AwwasmImportEntry<'a>> {
-        self.entries.iter().find(|e| e.module == module && e.name == name)
+        let &pos = self.index.get(&(module, name))?;
+        self.entries[pos].as_ref()
+    }
+
+    /// Every import still present, in insertion order.
+    pub fn iter(&self) -> impl Iterator<Item = &AwwasmImportEntry<'a>> {
+        self.entries.iter().flatten()
+    }
+
+    /// Restrict the imports a module instantiated with this set may bind;
+    /// `None` lifts the restriction. See the `capability` module.
+    pub fn set_policy(&mut self, policy: Option<AwwasmCapabilityPolicy>) {
+        self.policy = policy;
+    }
+
+    /// The capability policy, if any.
+    pub fn policy(&self) -> Option<&AwwasmCapabilityPolicy> {
+        self.policy.as_ref()
+    }
+
+    /// Whether the policy lets a module bind (module, name).
+    pub fn permits(&self, module: &[u8], name: &[u8]) -> bool {
+        self.policy.as_ref().is_none_or(|policy| policy.permits(module, name))
     }
 
     /// Remove and return an import by (module, name).
     pub fn take(&mut self, module: &[u8], name: &[u8]) -> Option<AwwasmImportEntry<'a>> {
-        let pos = self.entries.iter().position(|e| e.module == module && e.name == name)?;
-        Some(self.entries.swap_remove(pos))
+        let &pos = self.index.get(&(module, name))?;
+        self.entries[pos].take()
+    }
+}
+
+/// Chained construction of an `AwwasmImports`, e.g.
+/// `AwwasmImports::builder().memory("env", "memory", mem).build()`.
+#[derive(Debug)]
+pub struct AwwasmImportsBuilder<'a> {
+    imports: AwwasmImports<'a>,
+}
+
+impl<'a> AwwasmImportsBuilder<'a> {
+    /// Add a function import.
+    pub fn func<M, N>(mut self, module: &'a M, name: &'a N, func: AwwasmFuncInst<'a>) -> Self
+    where
+        M: AsRef<[u8]> + ?Sized,
+        N: AsRef<[u8]> + ?Sized,
+    {
+        self.imports.add_func(module, name, func);
+        self
+    }
+
+    /// Add the same function import under several field names.
+    pub fn func_names<M, N>(mut self, module: &'a M, names: &[&'a N], func: AwwasmFuncInst<'a>) -> Self
+    where
+        M: AsRef<[u8]> + ?Sized,
+        N: AsRef<[u8]> + ?Sized,
+    {
+        self.imports.add_func_names(module, names, func);
+        self
+    }
+
+    /// Add a memory import.
+    pub fn memory<M, N>(mut self, module: &'a M, name: &'a N, mem: AwwasmMemInst) -> Self
+    where
+        M: AsRef<[u8]> + ?Sized,
+        N: AsRef<[u8]> + ?Sized,
+    {
+        self.imports.add_memory(module, name, mem);
+        self
+    }
+
+    /// Add a global import.
+    pub fn global<M, N>(mut self, module: &'a M, name: &'a N, global: AwwasmGlobalInst) -> Self
+    where
+        M: AsRef<[u8]> + ?Sized,
+        N: AsRef<[u8]> + ?Sized,
+    {
+        self.imports.add_global(module, name, global);
+        self
+    }
+
+    /// Add a table import of a table already in the store.
+    pub fn table<M, N>(mut self, module: &'a M, name: &'a N, table: AwwasmTableAddr) -> Self
+    where
+        M: AsRef<[u8]> + ?Sized,
+        N: AsRef<[u8]> + ?Sized,
+    {
+        self.imports.add_table(module, name, table);
+        self
+    }
+
+    /// Restrict what the importing module may bind.
+    pub fn policy(mut self, policy: AwwasmCapabilityPolicy) -> Self {
+        self.imports.set_policy(Some(policy));
+        self
+    }
+
+    /// Finish building.
+    pub fn build(self) -> AwwasmImports<'a> {
+        self.imports
     }
 }
 
@@ -0,0 +1,170 @@
+//! Static description of a module's imports and exports.
+//!
+//! `AwwasmModuleInfo` is read from a parsed module without instantiating
+//! it, so a host can check what a plugin needs and provides up front.
+
+#[cfg(feature = "alloc")]
+use alloc::vec::Vec;
+#[cfg(feature = "alloc")]
+use alloc::format;
+
+use awwasm_parser::components::module::AwwasmModule;
+use awwasm_parser::components::types::AwwasmExportKind;
+
+use crate::decode::{self, AwwasmImportDesc};
+use crate::error::AwwasmInstantiationError;
+use crate::func::AwwasmFuncType;
+use crate::global::AwwasmGlobalType;
+use crate::memory::AwwasmMemoryType;
+use crate::table::AwwasmTableType;
+use crate::type_convert;
+use crate::values::AwwasmExternKind;
+
+/// The type of an importable/exportable entity.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum AwwasmExternType {
+    Func(AwwasmFuncType),
+    Table(AwwasmTableType),
+    Memory(AwwasmMemoryType),
+    Global(AwwasmGlobalType),
+}
+
+impl AwwasmExternType {
+    /// What kind of entity this type describes.
+    pub fn kind(&self) -> AwwasmExternKind {
+        match self {
+            AwwasmExternType::Func(_) => AwwasmExternKind::Func,
+            AwwasmExternType::Table(_) => AwwasmExternKind::Table,
+            AwwasmExternType::Memory(_) => AwwasmExternKind::Mem,
+            AwwasmExternType::Global(_) => AwwasmExternKind::Global,
+        }
+    }
+}
+
+/// An import the module declares.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AwwasmImportType<'a> {
+    /// Module name (e.g. "env").
+    pub module: &'a [u8],
+    /// Field name (e.g. "memory").
+    pub name: &'a [u8],
+    /// What the import must be.
+    pub ty: AwwasmExternType,
+}
+
+/// An export the module provides.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AwwasmExportType<'a> {
+    /// The export name.
+    pub name: &'a [u8],
+    /// What the export is.
+    pub ty: AwwasmExternType,
+}
+
+/// A custom section: metadata the runtime itself ignores, such as the
+/// `name` and `producers` sections or an embedder's own manifest.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AwwasmCustomSection<'a> {
+    /// The section name.
+    pub name: &'a [u8],
+    /// The section payload after the name, borrowed from the module bytes.
+    pub data: &'a [u8],
+}
+
+/// Payload of the first section in `sections` named `name`.
+pub(crate) fn find_custom_section<'a>(sections: &[AwwasmCustomSection<'a>], name: &str) -> Option<&'a [u8]> {
+    sections.iter().find(|s| s.name == name.as_bytes()).map(|s| s.data)
+}
+
+/// Imports, exports and custom sections of a parsed module, in module
+/// order.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AwwasmModuleInfo<'a> {
+    /// What the module needs from the host.
+    pub imports: Vec<AwwasmImportType<'a>>,
+    /// What the module provides.
+    pub exports: Vec<AwwasmExportType<'a>>,
+    /// Custom sections. A name may occur more than once.
+    pub custom_sections: Vec<AwwasmCustomSection<'a>>,
+}
+
+impl<'a> AwwasmModuleInfo<'a> {
+    /// Describe `module`, which must have had its sections resolved.
+    ///
+    /// Fails on the same malformed sections and out-of-range exports
+    /// that `store_init` would reject.
+    pub fn new(module: &AwwasmModule<'a>) -> Result<Self, AwwasmInstantiationError> {
+        let types = decode::func_types(module)?;
+        let func_type = |idx: u32| {
+            types.get(idx as usize).cloned().ok_or_else(|| AwwasmInstantiationError::UnsupportedType {
+                description: format!("function type index {} out of range", idx),
+            })
+        };
+
+        // Index spaces: imports first, then the module's own definitions.
+        let mut funcs = Vec::new();
+        let mut tables = Vec::new();
+        let mut mems = Vec::new();
+        let mut globals = Vec::new();
+        let mut imports = Vec::new();
+        for import in decode::imports(module)? {
+            let ty = match import.desc {
+                AwwasmImportDesc::Func(idx) => AwwasmExternType::Func(func_type(idx)?),
+                AwwasmImportDesc::Table(ty) => AwwasmExternType::Table(ty),
+                AwwasmImportDesc::Memory(ty) => AwwasmExternType::Memory(ty),
+                AwwasmImportDesc::Global(ty) => AwwasmExternType::Global(ty),
+            };
+            match &ty {
+                AwwasmExternType::Func(f) => funcs.push(f.clone()),
+                AwwasmExternType::Table(t) => tables.push(*t),
+                AwwasmExternType::Memory(m) => mems.push(*m),
+                AwwasmExternType::Global(g) => globals.push(*g),
+            }
+            imports.push(AwwasmImportType { module: import.module, name: import.name, ty });
+        }
+        for func in module.funcs.iter().flatten() {
+            funcs.push(func_type(func.type_idx)?);
+        }
+        tables.extend(decode::tables(module)?);
+        mems.extend(module.memories.iter().flatten().map(|m| type_convert::memory_params_to_type(&m.limits)));
+        globals.extend(decode::globals(module)?.into_iter().map(|(ty, _)| ty));
+
+        let mut exports = Vec::new();
+        for export in module.exports.iter().flatten() {
+            let index = export.index as usize;
+            let (kind, count, ty) = match export.kind {
+                AwwasmExportKind::Function => {
+                    (AwwasmExternKind::Func, funcs.len(), funcs.get(index).cloned().map(AwwasmExternType::Func))
+                }
+                AwwasmExportKind::Table => {
+                    (AwwasmExternKind::Table, tables.len(), tables.get(index).copied().map(AwwasmExternType::Table))
+                }
+                AwwasmExportKind::Memory => {
+                    (AwwasmExternKind::Mem, mems.len(), mems.get(index).copied().map(AwwasmExternType::Memory))
+                }
+                AwwasmExportKind::Global => {
+                    (AwwasmExternKind::Global, globals.len(), globals.get(index).copied().map(AwwasmExternType::Global))
+                }
+            };
+            let ty = ty.ok_or_else(|| AwwasmInstantiationError::ExportIndexOutOfBounds {
+                kind,
+                name: core::str::from_utf8(export.name.bytes).unwrap_or("<invalid>").into(),
+                index: export.index,
+                count: count as u32,
+            })?;
+            exports.push(AwwasmExportType { name: export.name.bytes, ty });
+        }
+
+        Ok(Self { imports, exports, custom_sections: decode::custom_sections(module)? })
+    }
+
+    /// Find an export by string name.
+    pub fn export(&self, name: &str) -> Option<&AwwasmExternType> {
+        self.exports.iter().find(|e| e.name == name.as_bytes()).map(|e| &e.ty)
+    }
+
+    /// Payload of the first custom section named `name`.
+    pub fn custom_section(&self, name: &str) -> Option<&'a [u8]> {
+        find_custom_section(&self.custom_sections, name)
+    }
+}
@@ -0,0 +1,112 @@
+//! Reading a running guest's memories from another thread.
+//!
+//! The interpreter owns its store's memories while it runs, so another
+//! thread cannot read them directly. An `AwwasmInspector` installed with
+//! `AwwasmStore::set_inspector` queues read requests instead: the
+//! interpreter runs them at its next safepoint (a call or a loop
+//! back-edge, the points where it also checks for interrupts), while the
+//! guest is paused between instructions, and sends the result back.
+//!
+//! A guest blocked in a host call reaches no safepoint until the call
+//! returns; `inspect` gives up after its timeout in that case.
+
+use std::collections::VecDeque;
+use std::fmt;
+use std::ops::Range;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use crate::error::AwwasmTrap;
+use crate::memory::AwwasmMemInst;
+use crate::values::AwwasmMemAddr;
+
+type AwwasmInspection = Box<dyn FnOnce(&[AwwasmMemInst]) + Send>;
+
+#[derive(Default)]
+struct InspectorState {
+    /// Set while `queue` is non-empty; the interpreter checks only this.
+    pending: AtomicBool,
+    queue: Mutex<VecDeque<AwwasmInspection>>,
+}
+
+/// Shared handle through which other threads read a store's memories.
+#[derive(Clone, Default)]
+pub struct AwwasmInspector {
+    state: Arc<InspectorState>,
+}
+
+impl fmt::Debug for AwwasmInspector {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("AwwasmInspector")
+            .field("pending", &self.state.pending.load(Ordering::Relaxed))
+            .finish()
+    }
+}
+
+impl AwwasmInspector {
+    /// Create an inspector with no requests queued.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Run `f` over the store's memories, indexed by `AwwasmMemAddr`, at
+    /// the guest's next safepoint, and return what it returns.
+    ///
+    /// Returns `None` if no safepoint is reached within `timeout`; the
+    /// request then still runs at the next one, and its result is
+    /// dropped.
+    pub fn inspect<R, F>(&self, f: F, timeout: Duration) -> Option<R>
+    where
+        R: Send + 'static,
+        F: FnOnce(&[AwwasmMemInst]) -> R + Send + 'static,
+    {
+        let (tx, rx) = mpsc::channel();
+        let job: AwwasmInspection = Box::new(move |mems| {
+            let _ = tx.send(f(mems));
+        });
+        self.state.queue.lock().unwrap_or_else(|e| e.into_inner()).push_back(job);
+        self.state.pending.store(true, Ordering::Release);
+        rx.recv_timeout(timeout).ok()
+    }
+
+    /// Copy `range` out of memory `addr` at the next safepoint.
+    ///
+    /// `None` on timeout, as for `inspect`; an unknown address or an
+    /// out-of-bounds range is a `MemoryOutOfBounds` trap.
+    pub fn read_memory(
+        &self,
+        addr: AwwasmMemAddr,
+        range: Range<u32>,
+        timeout: Duration,
+    ) -> Option<Result<Vec<u8>, AwwasmTrap>> {
+        self.inspect(
+            move |mems| {
+                let len = range.end.saturating_sub(range.start);
+                match mems.get(addr.0 as usize) {
+                    Some(mem) => mem.read(range.start, len).map(|bytes| bytes.into_owned()),
+                    None => Err(AwwasmTrap::MemoryOutOfBounds { offset: range.start, size: len, memory_size: 0 }),
+                }
+            },
+            timeout,
+        )
+    }
+
+    /// Run every queued request over `mems`. Called by the interpreter at
+    /// safepoints.
+    #[inline]
+    pub(crate) fn serve(&self, mems: &[AwwasmMemInst]) {
+        if !self.state.pending.load(Ordering::Acquire) {
+            return;
+        }
+        let jobs: VecDeque<AwwasmInspection> = {
+            let mut queue = self.state.queue.lock().unwrap_or_else(|e| e.into_inner());
+            self.state.pending.store(false, Ordering::Release);
+            core::mem::take(&mut *queue)
+        };
+        for job in jobs {
+            job(mems);
+        }
+    }
+}
@@ -4,8 +4,15 @@
 
 #[cfg(feature = "alloc")]
 use alloc::vec::Vec;
+#[cfg(feature = "alloc")]
+use alloc::collections::{BTreeMap, BTreeSet};
 
-use crate::values::{AwwasmFuncAddr, AwwasmTableAddr, AwwasmMemAddr, AwwasmGlobalAddr, AwwasmElemAddr, AwwasmDataAddr, AwwasmExternAddr};
+use crate::func::AwwasmFuncType;
+use crate::info::{self, AwwasmCustomSection, AwwasmExternType};
+use crate::memory::AwwasmMemoryType;
+use crate::store::AwwasmStore;
+use crate::table::AwwasmTableType;
+use crate::values::{AwwasmFuncAddr, AwwasmTableAddr, AwwasmMemAddr, AwwasmGlobalAddr, AwwasmElemAddr, AwwasmDataAddr, AwwasmExternAddr, AwwasmModuleAddr};
 
 /// Export instance - runtime representation of an export.
 #[derive(Debug, Clone)]
@@ -28,12 +35,42 @@ impl<'a> AwwasmExportInst<'a> {
     }
 }
 
+/// One export of a facade instance (`AwwasmStore::alias_exports`): the
+/// export `export` of `module`, offered as `name`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AwwasmExportAlias<'a> {
+    /// The name the facade exports it as.
+    pub name: &'a [u8],
+    /// The instance actually providing it.
+    pub module: AwwasmModuleAddr,
+    /// Its export name in `module`.
+    pub export: &'a [u8],
+}
+
+impl<'a> AwwasmExportAlias<'a> {
+    /// Offer `module`'s export `export` as `name`.
+    ///
+    /// Names may be byte strings or `&str`.
+    pub fn new<N, E>(name: &'a N, module: AwwasmModuleAddr, export: &'a E) -> Self
+    where
+        N: AsRef<[u8]> + ?Sized,
+        E: AsRef<[u8]> + ?Sized,
+    {
+        Self { name: name.as_ref(), module, export: export.as_ref() }
+    }
+}
+
 /// Module instance - runtime representation of an instantiated module.
 ///
 /// This holds all the addresses that map module-local indices to
 /// Store addresses, plus the exports.
 #[derive(Debug, Clone)]
 pub struct AwwasmModuleInst<'a> {
+    /// Function signatures (indexed by typeidx).
+    pub types: Vec<AwwasmFuncType>,
+    /// Ids of `types` in the store's signature registry (indexed by
+    /// typeidx); equal ids mean equal signatures.
+    pub type_ids: Vec<u32>,
     /// Function addresses (indexed by funcidx).
     pub funcaddrs: Vec<AwwasmFuncAddr>,
     /// Table addresses (indexed by tableidx).
@@ -46,16 +83,27 @@ pub struct AwwasmModuleInst<'a> {
     pub elemaddrs: Vec<AwwasmElemAddr>,
     /// Data addresses (indexed by dataidx).
     pub dataaddrs: Vec<AwwasmDataAddr>,
-    /// Exports.
+    /// Exports, in module order. Add through `push_export` so name
+    /// lookups see them.
     pub exports: Vec<AwwasmExportInst<'a>>,
+    /// Export name → index into `exports`.
+    export_index: BTreeMap<&'a [u8], usize>,
     /// Start function (if any).
     pub start: Option<AwwasmFuncAddr>,
+    /// Function indices declared outside function bodies (exports,
+    /// element segments, global initializers), the only ones `ref.func`
+    /// may name.
+    pub refs: BTreeSet<u32>,
+    /// Custom sections of the module, borrowed from its bytes.
+    pub custom_sections: Vec<AwwasmCustomSection<'a>>,
 }
 
 impl<'a> AwwasmModuleInst<'a> {
     /// Create a new empty module instance.
     pub fn new() -> Self {
         Self {
+            types: Vec::new(),
+            type_ids: Vec::new(),
             funcaddrs: Vec::new(),
             tableaddrs: Vec::new(),
             memaddrs: Vec::new(),
@@ -63,7 +111,10 @@ impl<'a> AwwasmModuleInst<'a> {
             elemaddrs: Vec::new(),
             dataaddrs: Vec::new(),
             exports: Vec::new(),
+            export_index: BTreeMap::new(),
             start: None,
+            refs: BTreeSet::new(),
+            custom_sections: Vec::new(),
         }
     }
 
@@ -97,9 +148,15 @@ impl<'a> AwwasmModuleInst<'a> {
         self.dataaddrs.get(idx as usize).copied()
     }
 
+    /// Add an export. The first export of a given name wins.
+    pub fn push_export(&mut self, export: AwwasmExportInst<'a>) {
+        self.export_index.entry(export.name).or_insert(self.exports.len());
+        self.exports.push(export);
+    }
+
     /// Find an export by name.
     pub fn export(&self, name: &[u8]) -> Option<&AwwasmExportInst<'a>> {
-        self.exports.iter().find(|e| e.name == name)
+        self.exports.get(*self.export_index.get(name)?)
     }
 
     /// Find an export by string name.
@@ -107,6 +164,38 @@ impl<'a> AwwasmModuleInst<'a> {
         self.export(name.as_bytes())
     }
 
+    /// Get the function exported as `name`.
+    pub fn get_func(&self, name: &str) -> Option<AwwasmFuncAddr> {
+        match self.export_by_str(name)?.addr {
+            AwwasmExternAddr::Func(addr) => Some(addr),
+            _ => None,
+        }
+    }
+
+    /// Get the table exported as `name`.
+    pub fn get_table(&self, name: &str) -> Option<AwwasmTableAddr> {
+        match self.export_by_str(name)?.addr {
+            AwwasmExternAddr::Table(addr) => Some(addr),
+            _ => None,
+        }
+    }
+
+    /// Get the memory exported as `name`.
+    pub fn get_memory(&self, name: &str) -> Option<AwwasmMemAddr> {
+        match self.export_by_str(name)?.addr {
+            AwwasmExternAddr::Mem(addr) => Some(addr),
+            _ => None,
+        }
+    }
+
+    /// Get the global exported as `name`.
+    pub fn get_global(&self, name: &str) -> Option<AwwasmGlobalAddr> {
+        match self.export_by_str(name)?.addr {
+            AwwasmExternAddr::Global(addr) => Some(addr),
+            _ => None,
+        }
+    }
+
     /// Get all function exports.
     pub fn func_exports(&self) -> impl Iterator<Item = (&'a [u8], AwwasmFuncAddr)> + '_ {
         self.exports.iter().filter_map(|e| {
@@ -126,6 +215,40 @@ impl<'a> AwwasmModuleInst<'a> {
             }
         })
     }
+
+    /// Exports with their types, in module order: function signatures,
+    /// table and memory limits, global types. `store` is the store this
+    /// instance lives in; table and memory minimums are their current
+    /// sizes there, as import matching sees them.
+    ///
+    /// Exports `store` does not hold, because the instance belongs to
+    /// another store, are skipped.
+    pub fn exports_with_types<'s>(
+        &'s self,
+        store: &'s AwwasmStore<'_>,
+    ) -> impl Iterator<Item = (&'a [u8], AwwasmExternType)> + 's {
+        self.exports.iter().filter_map(move |e| {
+            let ty = match e.addr {
+                AwwasmExternAddr::Func(addr) => AwwasmExternType::Func(store.func_type(addr).ok()?.clone()),
+                AwwasmExternAddr::Table(addr) => {
+                    let table = store.table(addr).ok()?;
+                    AwwasmExternType::Table(AwwasmTableType { min: table.size(), ..table.type_ })
+                }
+                AwwasmExternAddr::Mem(addr) => {
+                    let mem = store.mem(addr).ok()?;
+                    AwwasmExternType::Memory(AwwasmMemoryType { min: mem.size_pages(), ..mem.type_ })
+                }
+                AwwasmExternAddr::Global(addr) => AwwasmExternType::Global(store.global(addr).ok()?.type_),
+            };
+            Some((e.name, ty))
+        })
+    }
+
+    /// Payload of the first custom section named `name`, e.g. a plugin
+    /// manifest the host reads before calling in.
+    pub fn custom_section(&self, name: &str) -> Option<&'a [u8]> {
+        info::find_custom_section(&self.custom_sections, name)
+    }
 }
 
 impl<'a> Default for AwwasmModuleInst<'a> {
@@ -0,0 +1,87 @@
+//! Cross-thread interruption of guest execution.
+//!
+//! An `AwwasmInterruptHandle` is a cheap, cloneable flag. The embedder
+//! keeps one clone and calls `interrupt()` from any thread; code running
+//! on behalf of the guest polls it at safe points (and blocking host
+//! calls wait on it) and unwinds with `AwwasmTrap::Interrupted`.
+
+use alloc::sync::Arc;
+
+use core::sync::atomic::{AtomicBool, Ordering};
+
+#[cfg(feature = "std")]
+use std::sync::{Condvar, Mutex};
+#[cfg(feature = "std")]
+use std::time::{Duration, Instant};
+
+#[derive(Debug, Default)]
+struct InterruptState {
+    flag: AtomicBool,
+    #[cfg(feature = "std")]
+    lock: Mutex<()>,
+    #[cfg(feature = "std")]
+    wakeup: Condvar,
+}
+
+/// Shared handle used to interrupt a running or sleeping guest.
+#[derive(Debug, Clone, Default)]
+pub struct AwwasmInterruptHandle {
+    state: Arc<InterruptState>,
+}
+
+impl AwwasmInterruptHandle {
+    /// Create a new, un-triggered handle.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Request that the guest stop as soon as possible.
+    pub fn interrupt(&self) {
+        self.state.flag.store(true, Ordering::SeqCst);
+        #[cfg(feature = "std")]
+        {
+            let _guard = self.state.lock.lock().unwrap_or_else(|e| e.into_inner());
+            self.state.wakeup.notify_all();
+        }
+    }
+
+    /// Whether an interrupt has been requested.
+    #[inline]
+    pub fn is_interrupted(&self) -> bool {
+        self.state.flag.load(Ordering::Relaxed)
+    }
+
+    /// Reset the flag so the handle can be reused for the next call.
+    pub fn clear(&self) {
+        self.state.flag.store(false, Ordering::SeqCst);
+    }
+
+    /// Block the current thread for `duration` without spinning.
+    ///
+    /// Returns `false` if the sleep was cut short by `interrupt()`. A
+    /// duration too long to represent as a deadline sleeps until
+    /// interrupted.
+    #[cfg(feature = "std")]
+    pub fn sleep(&self, duration: Duration) -> bool {
+        let deadline = Instant::now().checked_add(duration);
+        let mut guard = self.state.lock.lock().unwrap_or_else(|e| e.into_inner());
+        loop {
+            if self.is_interrupted() {
+                return false;
+            }
+            guard = match deadline {
+                Some(deadline) => {
+                    let now = Instant::now();
+                    if now >= deadline {
+                        return true;
+                    }
+                    match self.state.wakeup.wait_timeout(guard, deadline - now) {
+                        Ok((guard, _)) => guard,
+                        Err(e) => e.into_inner().0,
+                    }
+                }
+                None => self.state.wakeup.wait(guard).unwrap_or_else(|e| e.into_inner()),
+            };
+        }
+    }
+}
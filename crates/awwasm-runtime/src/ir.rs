@@ -0,0 +1,431 @@
+//! Internal instruction representation.
+//!
+//! The first call to a function lowers its body into a flat array of
+//! `Op`s with every immediate (LEB128 indices, memargs, constants,
+//! block types) already decoded. The interpreter then walks that array by
+//! index instead of re-reading the binary encoding on every execution.
+//!
+//! Lowering also resolves structured control flow: every `block`, `if`
+//! and `else` records where its `else` arm and `end` are, so branches and
+//! untaken arms are single jumps rather than forward scans.
+//!
+//! A final peephole pass (`fuse`) folds a few hot sequences into
+//! superinstructions so loops spend fewer iterations in the dispatch loop.
+
+#[cfg(feature = "alloc")]
+use alloc::vec::Vec;
+#[cfg(feature = "alloc")]
+use alloc::format;
+#[cfg(feature = "alloc")]
+use alloc::collections::BTreeSet;
+
+use core::sync::atomic::{AtomicU32, Ordering};
+
+use crate::decode::AwwasmReader;
+use crate::error::{AwwasmBodyLimit, AwwasmRuntimeError};
+use crate::func::{AwwasmFuncType, AwwasmLocalDecl};
+use crate::values::AwwasmValueType;
+
+/// One lowered instruction.
+///
+/// Stack-only numeric instructions keep their binary opcode in `Num` /
+/// `TruncSat`, since they have no immediates to decode.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum Op {
+    Unreachable,
+    Nop,
+    /// `end` is the index just past the matching `end`.
+    Block { params: u32, results: u32, end: u32 },
+    Loop { params: u32, results: u32 },
+    /// `else_` is the index just past the matching `else`, or of the
+    /// matching `end` itself when there is no `else` arm.
+    If { params: u32, results: u32, else_: u32, end: u32 },
+    /// `end` is the index just past the matching `end`.
+    Else { end: u32 },
+    End,
+    Br(u32),
+    BrIf(u32),
+    /// Depths are `br_tables[start..start + len]`, followed by the default.
+    BrTable { start: u32, len: u32 },
+    Return,
+    Call(u32),
+    /// `cache` indexes the site's entry in `AwwasmCompiledCode::call_caches`.
+    CallIndirect { type_idx: u32, table_idx: u32, cache: u32 },
+    Drop,
+    Select,
+    LocalGet(u32),
+    LocalSet(u32),
+    LocalTee(u32),
+    GlobalGet(u32),
+    GlobalSet(u32),
+    /// Any load; `opcode` is the binary opcode (0x28..=0x35).
+    Load { opcode: u8, offset: u32 },
+    /// Any store; `opcode` is the binary opcode (0x36..=0x3e).
+    Store { opcode: u8, offset: u32 },
+    MemorySize,
+    MemoryGrow,
+    I32Const(i32),
+    I64Const(i64),
+    F32Const(u32),
+    F64Const(u64),
+    /// Numeric instruction without immediates (0x45..=0xc4).
+    Num(u8),
+    /// Saturating truncation, `0xfc 0..=7`.
+    TruncSat(u8),
+    /// `ref.null` of a reference type.
+    RefNull(AwwasmValueType),
+    RefIsNull,
+    RefFunc(u32),
+    MemoryInit(u32),
+    DataDrop(u32),
+    MemoryCopy,
+    MemoryFill,
+    /// `memory.atomic.notify` / `wait32` / `wait64`, `0xfe 0..=2`.
+    Atomic { sub: u8, offset: u32 },
+    /// `local.get lhs; local.get rhs; i32.add; local.set dst`.
+    I32AddLocals { lhs: u32, rhs: u32, dst: u32 },
+    /// `i32.const imm; i32.add`.
+    I32AddImm(i32),
+    /// An i32 test or comparison (0x45..=0x4f) followed by `br_if depth`.
+    I32CmpBrIf { cmp: u8, depth: u32 },
+    /// Charge this much fuel for the straight-line run of instructions
+    /// it starts; inserted by `fuel::instrument`.
+    ConsumeFuel(u64),
+}
+
+impl Op {
+    /// Number of WebAssembly instructions this op stands for.
+    pub(crate) fn width(&self) -> u64 {
+        match self {
+            Op::I32AddLocals { .. } => 4,
+            Op::I32AddImm(_) | Op::I32CmpBrIf { .. } => 2,
+            Op::ConsumeFuel(_) => 0,
+            _ => 1,
+        }
+    }
+}
+
+/// A function body lowered to `Op`s, cached on its function instance.
+#[derive(Debug)]
+pub struct AwwasmCompiledCode {
+    pub(crate) locals: Vec<AwwasmLocalDecl>,
+    pub(crate) ops: Vec<Op>,
+    pub(crate) br_tables: Vec<u32>,
+    /// One inline cache per `call_indirect` site.
+    pub(crate) call_caches: Vec<CallCache>,
+    /// Whether the body charges its own fuel with `ConsumeFuel`.
+    pub(crate) metered: bool,
+}
+
+/// Inline cache of a `call_indirect` site: the last callee that passed
+/// the site's signature check.
+///
+/// The table slot is still read on every call, so a table update simply
+/// misses the cache. Atomics keep the shared compiled code `Sync`.
+#[derive(Debug, Default)]
+pub(crate) struct CallCache(AtomicU32);
+
+impl CallCache {
+    /// Whether `func_addr` is known to match the site's signature.
+    #[inline]
+    pub(crate) fn hit(&self, func_addr: u32) -> bool {
+        self.0.load(Ordering::Relaxed) == func_addr.wrapping_add(1)
+    }
+
+    /// Remember `func_addr` as matching the site's signature.
+    #[inline]
+    pub(crate) fn set(&self, func_addr: u32) {
+        self.0.store(func_addr.wrapping_add(1), Ordering::Relaxed);
+    }
+}
+
+impl AwwasmCompiledCode {
+    /// Number of lowered instructions.
+    pub fn len(&self) -> usize {
+        self.ops.len()
+    }
+
+    /// Whether the body has no instructions at all.
+    pub fn is_empty(&self) -> bool {
+        self.ops.is_empty()
+    }
+
+    /// Heap bytes held by the lowered body.
+    pub fn heap_bytes(&self) -> usize {
+        fn bytes<T>(v: &Vec<T>) -> usize {
+            v.capacity() * core::mem::size_of::<T>()
+        }
+        bytes(&self.locals) + bytes(&self.ops) + bytes(&self.br_tables) + bytes(&self.call_caches)
+    }
+}
+
+/// Maximum number of `block`, `loop` and `if` open at once in one
+/// function body. Deeper bodies fail to lower with `BodyLimitExceeded`.
+pub const MAX_BLOCK_NESTING: u32 = 16_384;
+
+fn parse_error(what: &str) -> AwwasmRuntimeError {
+    AwwasmRuntimeError::InstructionParseError(what.into())
+}
+
+/// `(params, results)` of a block type immediate.
+fn block_type(r: &mut AwwasmReader<'_>, types: &[AwwasmFuncType]) -> Result<(u32, u32), AwwasmRuntimeError> {
+    let bt = r.s33().map_err(|_| parse_error("malformed block type"))?;
+    match bt {
+        -64 => Ok((0, 0)),
+        idx if idx >= 0 => {
+            let ty = types.get(idx as usize).ok_or_else(|| parse_error("block type index out of range"))?;
+            Ok((ty.params.len() as u32, ty.results.len() as u32))
+        }
+        _ => Ok((0, 1)),
+    }
+}
+
+/// Lower the instruction sequence `code` of a body declaring `locals`.
+///
+/// `types` is the owning module's type section, used to resolve block
+/// types up front, and `refs` the function indices `ref.func` may name
+/// (`AwwasmModuleInst::refs`).
+pub(crate) fn lower(
+    locals: Vec<AwwasmLocalDecl>,
+    code: &[u8],
+    types: &[AwwasmFuncType],
+    refs: &BTreeSet<u32>) -> Result<AwwasmCompiledCode, AwwasmRuntimeError> {
+    let mut r = AwwasmReader::new(code);
+    // Every instruction takes at least one byte, so this is the only
+    // allocation `ops` needs.
+    let mut ops = Vec::new();
+    reserve(&mut ops, code.len())?;
+    let mut br_tables = Vec::new();
+    let mut call_caches = Vec::new();
+    let imm = |_| parse_error("malformed immediate");
+    // Open `block`/`loop`/`if`s, with the `else` of an `if` once seen.
+    let mut control: Vec<(usize, Option<usize>)> = Vec::new();
+
+    while !r.is_empty() {
+        let opcode = r.u8().map_err(imm)?;
+        let op = match opcode {
+            0x00 => Op::Unreachable,
+            0x01 => Op::Nop,
+            0x02 => {
+                let (params, results) = block_type(&mut r, types)?;
+                control.push((ops.len(), None));
+                Op::Block { params, results, end: 0 }
+            }
+            0x03 => {
+                let (params, results) = block_type(&mut r, types)?;
+                control.push((ops.len(), None));
+                Op::Loop { params, results }
+            }
+            0x04 => {
+                let (params, results) = block_type(&mut r, types)?;
+                control.push((ops.len(), None));
+                Op::If { params, results, else_: 0, end: 0 }
+            }
+            0x05 => {
+                let open = control.last_mut().ok_or_else(|| parse_error("else without if"))?;
+                if !matches!(ops[open.0], Op::If { .. }) || open.1.is_some() {
+                    return Err(parse_error("else without if"));
+                }
+                open.1 = Some(ops.len());
+                Op::Else { end: 0 }
+            }
+            0x0b => {
+                // The function body's own `end` has no opener.
+                if let Some((open, else_pos)) = control.pop() {
+                    let here = ops.len() as u32;
+                    match &mut ops[open] {
+                        Op::Block { end, .. } => *end = here + 1,
+                        Op::If { else_, end, .. } => {
+                            *else_ = else_pos.map_or(here, |pos| pos as u32 + 1);
+                            *end = here + 1;
+                        }
+                        _ => {}
+                    }
+                    if let Some(pos) = else_pos {
+                        ops[pos] = Op::Else { end: here + 1 };
+                    }
+                }
+                Op::End
+            }
+            0x0c => Op::Br(r.u32().map_err(imm)?),
+            0x0d => Op::BrIf(r.u32().map_err(imm)?),
+            0x0e => {
+                let len = r.u32().map_err(imm)?;
+                let start = br_tables.len() as u32;
+                for _ in 0..=len {
+                    br_tables.push(r.u32().map_err(imm)?);
+                }
+                Op::BrTable { start, len }
+            }
+            0x0f => Op::Return,
+            0x10 => Op::Call(r.u32().map_err(imm)?),
+            0x11 => {
+                let type_idx = r.u32().map_err(imm)?;
+                let table_idx = r.u32().map_err(imm)?;
+                let cache = call_caches.len() as u32;
+                call_caches.push(CallCache::default());
+                Op::CallIndirect { type_idx, table_idx, cache }
+            }
+            0x1a => Op::Drop,
+            0x1b => Op::Select,
+            0x1c => {
+                let n = r.u32().map_err(imm)?;
+                r.bytes(n as usize).map_err(imm)?;
+                Op::Select
+            }
+            0x20 => Op::LocalGet(r.u32().map_err(imm)?),
+            0x21 => Op::LocalSet(r.u32().map_err(imm)?),
+            0x22 => Op::LocalTee(r.u32().map_err(imm)?),
+            0x23 => Op::GlobalGet(r.u32().map_err(imm)?),
+            0x24 => Op::GlobalSet(r.u32().map_err(imm)?),
+            0x28..=0x3e => {
+                r.u32().map_err(|_| parse_error("malformed memarg"))?;
+                let offset = r.u32().map_err(|_| parse_error("malformed memarg"))?;
+                if opcode <= 0x35 {
+                    Op::Load { opcode, offset }
+                } else {
+                    Op::Store { opcode, offset }
+                }
+            }
+            0x3f => {
+                r.u8().map_err(imm)?;
+                Op::MemorySize
+            }
+            0x40 => {
+                r.u8().map_err(imm)?;
+                Op::MemoryGrow
+            }
+            0x41 => Op::I32Const(r.i32().map_err(imm)?),
+            0x42 => Op::I64Const(r.i64().map_err(imm)?),
+            0x43 => Op::F32Const(r.f32().map_err(imm)?.to_bits()),
+            0x44 => Op::F64Const(r.f64().map_err(imm)?.to_bits()),
+            0x45..=0xc4 => Op::Num(opcode),
+            0xd0 => match crate::decode::value_type(r.u8().map_err(imm)?) {
+                Some(ty) if ty.is_ref() => Op::RefNull(ty),
+                _ => return Err(parse_error("malformed heap type")),
+            },
+            0xd1 => Op::RefIsNull,
+            0xd2 => {
+                let idx = r.u32().map_err(imm)?;
+                if !refs.contains(&idx) {
+                    return Err(AwwasmRuntimeError::InstructionParseError(format!(
+                        "ref.func {} names an undeclared function",
+                        idx
+                    )));
+                }
+                Op::RefFunc(idx)
+            }
+            0xfc => match r.u32().map_err(imm)? {
+                sub @ 0..=7 => Op::TruncSat(sub as u8),
+                8 => {
+                    let data_idx = r.u32().map_err(imm)?;
+                    r.u8().map_err(imm)?;
+                    Op::MemoryInit(data_idx)
+                }
+                9 => Op::DataDrop(r.u32().map_err(imm)?),
+                10 => {
+                    r.u8().map_err(imm)?;
+                    r.u8().map_err(imm)?;
+                    Op::MemoryCopy
+                }
+                11 => {
+                    r.u8().map_err(imm)?;
+                    Op::MemoryFill
+                }
+                sub => {
+                    return Err(AwwasmRuntimeError::InstructionParseError(format!(
+                        "unsupported opcode 0xfc {}",
+                        sub
+                    )))
+                }
+            },
+            0xfe => match r.u32().map_err(imm)? {
+                sub @ 0..=2 => {
+                    r.u32().map_err(|_| parse_error("malformed memarg"))?;
+                    let offset = r.u32().map_err(|_| parse_error("malformed memarg"))?;
+                    Op::Atomic { sub: sub as u8, offset }
+                }
+                // `atomic.fence`: every access here is already sequentially
+                // consistent.
+                3 => {
+                    r.u8().map_err(imm)?;
+                    Op::Nop
+                }
+                sub => {
+                    return Err(AwwasmRuntimeError::InstructionParseError(format!(
+                        "unsupported opcode 0xfe {}",
+                        sub
+                    )))
+                }
+            },
+            _ => {
+                return Err(AwwasmRuntimeError::InstructionParseError(format!(
+                    "unsupported opcode 0x{:02x}",
+                    opcode
+                )))
+            }
+        };
+        if control.len() > MAX_BLOCK_NESTING as usize {
+            return Err(AwwasmRuntimeError::BodyLimitExceeded {
+                limit: AwwasmBodyLimit::BlockNesting,
+                max: MAX_BLOCK_NESTING,
+                actual: control.len() as u64,
+            });
+        }
+        ops.push(op);
+    }
+
+    if !control.is_empty() {
+        return Err(parse_error("unterminated block"));
+    }
+    Ok(AwwasmCompiledCode { locals, ops: fuse(ops)?, br_tables, call_caches, metered: false })
+}
+
+/// Reserve room for `additional` more elements of `v`, reporting
+/// allocation failure as `OutOfMemory` instead of aborting.
+pub(crate) fn reserve<T>(v: &mut Vec<T>, additional: usize) -> Result<(), AwwasmRuntimeError> {
+    v.try_reserve_exact(additional).map_err(|_| AwwasmRuntimeError::OutOfMemory {
+        requested_bytes: additional.saturating_mul(core::mem::size_of::<T>()),
+    })
+}
+
+/// Fold known instruction sequences into superinstructions.
+///
+/// Fused sequences are straight-line code, so no branch can land inside
+/// one; block targets only need their indices shifted.
+fn fuse(ops: Vec<Op>) -> Result<Vec<Op>, AwwasmRuntimeError> {
+    let mut out = Vec::new();
+    reserve(&mut out, ops.len())?;
+    // `remap[i]` is the new index of old op `i`; one extra slot covers
+    // targets just past the last op.
+    let mut remap = Vec::new();
+    reserve(&mut remap, ops.len() + 1)?;
+    let mut i = 0;
+    while i < ops.len() {
+        let (op, len) = match ops[i..] {
+            [Op::LocalGet(lhs), Op::LocalGet(rhs), Op::Num(0x6a), Op::LocalSet(dst), ..] => {
+                (Op::I32AddLocals { lhs, rhs, dst }, 4)
+            }
+            [Op::I32Const(imm), Op::Num(0x6a), ..] => (Op::I32AddImm(imm), 2),
+            [Op::Num(cmp @ 0x45..=0x4f), Op::BrIf(depth), ..] => (Op::I32CmpBrIf { cmp, depth }, 2),
+            _ => (ops[i], 1),
+        };
+        remap.extend(core::iter::repeat_n(out.len() as u32, len));
+        out.push(op);
+        i += len;
+    }
+    remap.push(out.len() as u32);
+
+    let at = |idx: u32| remap[idx as usize];
+    for op in &mut out {
+        match op {
+            Op::Block { end, .. } | Op::Else { end } => *end = at(*end),
+            Op::If { else_, end, .. } => {
+                *else_ = at(*else_);
+                *end = at(*end);
+            }
+            _ => {}
+        }
+    }
+    Ok(out)
+}
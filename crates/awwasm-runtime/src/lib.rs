@@ -5,15 +5,45 @@
 //!
 //! # Features
 //!
-//! - `std` (default): Enable standard library support
-//! - `alloc`: Enable heap allocation without full std
-//! - `parallel`: Enable Rayon-based parallel parsing (future)
+//! - `std` (default): Enable standard library support, including
+//!   `pool::AwwasmStorePool` for running instances across threads
+//! - `alloc`: Enable heap allocation without full std. Parsing,
+//!   instantiation and execution all work on `no_std` + `alloc`; only host
+//!   panic capture, interrupt deadlines, `parallel` and `wasi` need `std`
+//!   (`cargo test --no-default-features --features alloc` covers it)
+//! - `parallel`: Lower function bodies on the Rayon thread pool
+//!   (`AwwasmStore::resolve_all_functions_parallel`) and instantiate
+//!   independent modules concurrently (`AwwasmStore::instantiate_parallel`)
+//! - `wasi`: Enable the WASI snapshot_preview1 host implementation
+//! - `serde`: Serialize errors, traps and store snapshots
+//!   (`AwwasmStore::snapshot`; the binary `AwwasmStoreSnapshot::to_bytes`
+//!   needs no feature)
+//! - `wat`: Instantiate modules from WebAssembly text
+//!   (`AwwasmStore::instantiate_wat`)
+//! - `softfloat`: Run float arithmetic in software, bit-identical on
+//!   every target and with canonical NaNs
+//! - `spectest`: Run `.wast` spec-test scripts
+//!   (`spectest::AwwasmWastRunner`)
+//! - `differential`: Compare execution against the wasmi interpreter,
+//!   for fuzzing (`differential::AwwasmDiffHarness`)
+//! - `cli`: Build the `awwasm` command-line runner
+//! - `fuzz`: `arbitrary::Arbitrary` for values, limits and store
+//!   settings, and fuzz targets (`fuzz::instantiate`, `fuzz::execute`,
+//!   `fuzz::memory_ops`)
+//! - `proptest`: Model-based checkers for memory and table operations
+//!   and `proptest` strategies to drive them (`testing::check_memory_ops`,
+//!   `testing::check_table_ops`), for validating other implementations
+//!   against the reference semantics
 
 #![cfg_attr(not(feature = "std"), no_std)]
+#![forbid(unsafe_code)]
 
 #[cfg(feature = "alloc")]
 extern crate alloc;
 
+#[cfg(not(feature = "alloc"))]
+compile_error!("awwasm-runtime needs the `alloc` feature (or `std`, which implies it)");
+
 pub mod error;
 pub mod values;
 pub mod memory;
@@ -24,13 +54,98 @@ pub mod store;
 pub mod instance;
 pub mod type_convert;
 pub mod imports;
+pub mod info;
+pub mod exec;
+pub mod ir;
+pub mod runner;
+#[cfg(feature = "std")]
+pub mod repl;
+pub mod preinit;
+pub mod api;
+mod decode;
+mod artifact;
+pub mod snapshot;
+#[cfg(feature = "softfloat")]
+mod softfloat;
+#[cfg(feature = "alloc")]
+pub mod interrupt;
+pub mod platform;
+pub mod profile;
+pub mod observer;
+pub mod closures;
+pub mod async_call;
+pub mod scheduler;
+pub mod caller;
+pub mod audit;
+pub mod host_trail;
+pub mod allowlist;
+pub mod capability;
+#[cfg(feature = "std")]
+pub mod pool;
+#[cfg(feature = "std")]
+pub mod inspect;
+pub mod fuel;
+#[cfg(feature = "wasi")]
+pub mod wasi;
+pub mod spectest;
+#[cfg(feature = "differential")]
+pub mod differential;
+#[cfg(feature = "fuzz")]
+pub mod fuzz;
+#[cfg(any(feature = "fuzz", feature = "proptest"))]
+pub mod testing;
 
 // Re-export key types
-pub use error::{AwwasmRuntimeError, AwwasmInstantiationError, AwwasmTrap};
-pub use values::{AwwasmValue, AwwasmFuncAddr, AwwasmTableAddr, AwwasmMemAddr, AwwasmGlobalAddr, AwwasmElemAddr, AwwasmDataAddr, AwwasmExternAddr};
-pub use store::AwwasmStore;
-pub use instance::AwwasmModuleInst;
+pub use error::{AwwasmRuntimeError, AwwasmBodyLimit, AwwasmInstantiationCost, AwwasmInstantiationError, AwwasmModuleLimit, AwwasmNamePosition, AwwasmParseError, AwwasmTrap, ExitStatus, GlobalError, TableError, TrapKind, TRAP_EXIT_CODE};
+#[cfg(feature = "alloc")]
+pub use error::AwwasmHostError;
+#[cfg(feature = "std")]
+pub use error::AwwasmHostPanic;
+pub use values::{AwwasmValue, AwwasmFuncAddr, AwwasmTableAddr, AwwasmMemAddr, AwwasmGlobalAddr, AwwasmElemAddr, AwwasmDataAddr, AwwasmExternAddr, AwwasmExternKind};
+pub use store::{AwwasmStore, AwwasmModuleLimits, AwwasmInstantiationLimits, AwwasmHeapUsage};
+pub use snapshot::{AwwasmInstanceSnapshot, AwwasmStoreSnapshot};
+pub use instance::{AwwasmExportAlias, AwwasmModuleInst};
 pub use imports::AwwasmImports;
+pub use capability::AwwasmCapabilityPolicy;
+pub use info::{AwwasmCustomSection, AwwasmModuleInfo};
+pub use runner::{AwwasmReactor, AwwasmRunError};
+#[cfg(feature = "wasi")]
+pub use runner::run_command;
+pub use preinit::pre_initialize;
+#[cfg(feature = "std")]
+pub use repl::AwwasmRepl;
+pub use func::AwwasmHost;
+pub use caller::AwwasmCaller;
+pub use audit::{AwwasmAuditCallback, AwwasmMemAccess, AwwasmMemAccessKind, AwwasmMemAccessor, AwwasmMemoryAuditor};
+#[cfg(feature = "std")]
+pub use audit::AwwasmAuditLog;
+pub use host_trail::{AwwasmHostCall, AwwasmHostCallAuditor};
+#[cfg(feature = "std")]
+pub use host_trail::AwwasmHostCallTrail;
+pub use allowlist::{AwwasmAllowList, AwwasmModuleGate, AwwasmModuleHash, AwwasmModuleVerifier};
+pub use platform::AwwasmHostPlatform;
+#[cfg(feature = "std")]
+pub use platform::AwwasmStdPlatform;
+pub use profile::AwwasmProfiler;
+pub use observer::{AwwasmInstantiationObserver, AwwasmInstantiationPhase};
+pub use closures::{AwwasmHostFuncs, AwwasmSingleThread, AwwasmThreadSafe};
+pub use async_call::AwwasmAsyncCall;
+pub use scheduler::{AwwasmScheduler, AwwasmTaskId};
+#[cfg(feature = "std")]
+pub use pool::{AwwasmPoolCall, AwwasmStorePool};
+#[cfg(feature = "std")]
+pub use inspect::AwwasmInspector;
+pub use fuel::{AwwasmGasTable, AwwasmMetering};
+#[cfg(feature = "std")]
+pub use profile::AwwasmTraceRecorder;
+pub use exec::{AwwasmExecArena, AwwasmExecutionMetrics, AwwasmStackLimits};
+#[cfg(feature = "alloc")]
+pub use interrupt::AwwasmInterruptHandle;
+#[cfg(feature = "wasi")]
+pub use wasi::{AwwasmWasiCtx, AwwasmWasiThreads};
+pub use spectest::AwwasmSpectest;
+#[cfg(feature = "spectest")]
+pub use spectest::{AwwasmWastRunner, AwwasmWastReport, AwwasmWastError};
 
 #[cfg(test)]
 mod tests {
@@ -40,6 +155,9 @@ mod tests {
     use global::{AwwasmGlobalInst, AwwasmGlobalType};
     use func::{AwwasmFuncInst, AwwasmDataInst};
     use values::{AwwasmValueType, AwwasmModuleAddr};
+    use interrupt::AwwasmInterruptHandle;
+    #[cfg(not(feature = "std"))]
+    use alloc::{boxed::Box, format, vec, vec::Vec};
 
     #[test]
     fn test_store_creation() {
@@ -74,6 +192,52 @@ mod tests {
         assert!(mem.grow(1).is_none());
     }
 
+    #[test]
+    fn test_static_memory() {
+        let buf: &'static mut [u8] = Box::leak(vec![0xaa; memory::PAGE_SIZE + 1].into_boxed_slice());
+        let mut mem = AwwasmMemInst::from_static(buf, AwwasmMemoryType::new(1, Some(4))).unwrap();
+        assert!(mem.is_static());
+        assert_eq!(mem.size_bytes(), memory::PAGE_SIZE);
+        assert_eq!(mem.read_u8(0).unwrap(), 0);
+        mem.write_i32(8, 7).unwrap();
+        assert_eq!(mem.read_i32(8).unwrap(), 7);
+        assert_eq!(mem.grow(0), Some(1));
+        assert_eq!(mem.grow(1), None);
+
+        let copy = mem.clone();
+        assert!(!copy.is_static());
+        assert_eq!(copy.read_i32(8).unwrap(), 7);
+
+        let small: &'static mut [u8] = Box::leak(vec![0; 16].into_boxed_slice());
+        assert_eq!(
+            AwwasmMemInst::from_static(small, AwwasmMemoryType::new(1, None)).unwrap_err(),
+            AwwasmInstantiationError::MemoryAllocationFailed { requested_pages: 1 }
+        );
+    }
+
+    #[test]
+    fn test_custom_page_size() {
+        use alloc::string::ToString;
+
+        // flags 0x09: max present, custom page size; min 2, max 100, 2^0 bytes
+        let mut r = decode::AwwasmReader::new(&[0x09, 2, 100, 0]);
+        let ty = r.memory_type().unwrap();
+        assert_eq!(ty, AwwasmMemoryType::new(2, Some(100)).with_page_size_log2(0));
+        assert_eq!(ty.to_string(), "(memory 2 100 (pagesize 1))");
+        assert!(decode::AwwasmReader::new(&[0x08, 1, 12]).memory_type().is_err());
+
+        let mut mem = AwwasmMemInst::new(ty);
+        assert_eq!(mem.size_bytes(), 2);
+        assert_eq!(mem.grow(3), Some(2));
+        assert_eq!(mem.size_pages(), 5);
+        assert_eq!(mem.size_bytes(), 5);
+        mem.write_i32(1, -1).unwrap();
+        assert!(mem.write_i32(2, 0).is_err());
+        assert_eq!(mem.grow(96), None);
+        assert_eq!(ty.max_pages(), u32::MAX);
+        assert_eq!(AwwasmMemoryType::new(0, None).max_pages(), memory::MAX_PAGES);
+    }
+
     #[test]
     fn test_memory_bounds_checking() {
         let mem = AwwasmMemInst::new(AwwasmMemoryType::new(1, None));
@@ -86,6 +250,53 @@ mod tests {
         assert!(mem.read(65536, 1).is_err());
     }
 
+    #[test]
+    fn test_memory_allocation_failure() {
+        assert_eq!(
+            AwwasmMemInst::try_new(AwwasmMemoryType::new(memory::MAX_PAGES + 1, None)).unwrap_err(),
+            AwwasmInstantiationError::MemoryAllocationFailed { requested_pages: memory::MAX_PAGES + 1 }
+        );
+
+        // Growth past the 4 GiB limit fails without touching the memory.
+        let mut mem = AwwasmMemInst::try_new(AwwasmMemoryType::new(1, None)).unwrap();
+        assert_eq!(mem.grow(memory::MAX_PAGES), None);
+        assert_eq!(mem.size_pages(), 1);
+        assert_eq!(mem.grow(1), Some(1));
+
+        let mut table = AwwasmTableInst::try_new(AwwasmTableType::funcref(2, None)).unwrap();
+        assert_eq!(table.grow(3, None), Some(2));
+        assert_eq!(table.size(), 5);
+    }
+
+    #[test]
+    fn test_memory_hexdump() {
+        let mut mem = AwwasmMemInst::new(AwwasmMemoryType::new(1, None));
+        mem.write(16, b"hello").unwrap();
+
+        let dump = mem.hexdump(16..21).unwrap();
+        let padding = " ".repeat(11 * 3);
+        assert_eq!(dump, format!("00000010  68 65 6c 6c 6f{}  |hello|\n", padding));
+
+        assert_eq!(mem.hexdump(0..32).unwrap().lines().count(), 2);
+        assert!(mem.hexdump(65530..65540).is_err());
+    }
+
+    #[test]
+    fn test_memory_diff() {
+        let before = AwwasmMemInst::new(AwwasmMemoryType::new(1, None));
+        let mut after = before.clone();
+        after.write_u8(3, 0xaa).unwrap();
+        after.write_u8(100, 0x01).unwrap();
+
+        assert_eq!(before.diff(&after), vec![(3, 0x00, 0xaa), (100, 0x00, 0x01)]);
+        assert!(before.diff(&before).is_empty());
+
+        // Grown pages read as zero on the smaller side
+        after.grow(1).unwrap();
+        after.write_u8(65536, 7).unwrap();
+        assert_eq!(before.diff(&after).last(), Some(&(65536, 0, 7)));
+    }
+
     #[test]
     fn test_table_allocation_and_access() {
         let mut store: AwwasmStore = AwwasmStore::new();
@@ -112,6 +323,51 @@ mod tests {
         assert!(table.get(10).is_err());
     }
 
+    #[test]
+    fn test_table_element_types() {
+        let mut funcs = AwwasmTableInst::new(AwwasmTableType::funcref(2, None));
+        funcs.set_value(0, AwwasmValue::FuncRef(Some(AwwasmFuncAddr(3)))).unwrap();
+        assert_eq!(funcs.get(0).unwrap(), Some(AwwasmFuncAddr(3)));
+        assert_eq!(
+            funcs.set_value(1, AwwasmValue::I32(3)),
+            Err(TableError::TypeMismatch { expected: AwwasmValueType::FuncRef, got: AwwasmValueType::I32 })
+        );
+        assert_eq!(funcs.set(2, None), Err(TableError::OutOfBounds { index: 2, table_size: 2 }));
+
+        let externs_type = AwwasmTableType { elem_type: table::AwwasmElemType::ExternRef, ..AwwasmTableType::funcref(2, None) };
+        let mut externs = AwwasmTableInst::new(externs_type);
+        let mismatch = TableError::TypeMismatch { expected: AwwasmValueType::ExternRef, got: AwwasmValueType::FuncRef };
+        assert_eq!(externs.set(0, Some(AwwasmFuncAddr(3))), Err(mismatch));
+        assert_eq!(externs.set_value(0, AwwasmValue::FuncRef(None)), Err(mismatch));
+        assert_eq!(externs.fill(0, Some(AwwasmFuncAddr(3)), 2), Err(mismatch));
+        assert_eq!(externs.grow(1, Some(AwwasmFuncAddr(3))), None);
+        externs.set(0, None).unwrap();
+        assert_eq!(externs.get(0).unwrap(), None);
+    }
+
+    #[test]
+    fn test_table_growth_strategy() {
+        let mut exact = AwwasmTableInst::new(AwwasmTableType::funcref(0, None));
+        exact.grow(1, None).unwrap();
+        assert_eq!(exact.capacity(), 1);
+        exact.reserve_exact(7).unwrap();
+        assert!(exact.capacity() >= 8);
+        let reserved = exact.capacity();
+        for _ in 0..7 {
+            exact.grow(1, None).unwrap();
+        }
+        assert_eq!(exact.capacity(), reserved);
+
+        let mut geometric = AwwasmTableInst::new(AwwasmTableType::funcref(4, Some(10)))
+            .with_growth(table::AwwasmTableGrowth::Geometric);
+        geometric.grow(1, None).unwrap();
+        assert_eq!(geometric.size(), 5);
+        assert!(geometric.capacity() >= 8);
+        geometric.grow(5, None).unwrap();
+        assert_eq!(geometric.capacity(), 10);
+        assert_eq!(geometric.grow(1, None), None);
+    }
+
     #[test]
     fn test_global_allocation() {
         let mut store: AwwasmStore = AwwasmStore::new();
@@ -139,7 +395,79 @@ mod tests {
         
         assert_eq!(global.get(), AwwasmValue::I64(42));
         assert!(!global.is_mutable());
-        assert!(global.set(AwwasmValue::I64(0)).is_err());
+        assert_eq!(global.set(AwwasmValue::I64(0)), Err(GlobalError::Immutable));
+    }
+
+    #[test]
+    fn test_v128_globals() {
+        let wasm = wat::parse_str(r#"
+            (module
+                (import "env" "base" (global $base v128))
+                (global (export "lanes") v128 (v128.const i32x4 1 2 3 4))
+                (global (export "copy") (mut v128) (global.get $base))
+            )
+        "#).unwrap();
+        let mut store = AwwasmStore::new();
+        let mut imports = AwwasmImports::builder()
+            .global("env", "base", AwwasmGlobalInst::new(AwwasmGlobalType::immutable(AwwasmValueType::V128), AwwasmValue::V128(u128::MAX)))
+            .build();
+        let addr = store.instantiate_bytes(&wasm, &mut imports).unwrap();
+        let inst = store.module(addr).unwrap();
+        let lanes = inst.get_global("lanes").unwrap();
+        let copy = inst.get_global("copy").unwrap();
+
+        assert_eq!(store.get_global_v128(lanes), Ok(1 | 2 << 32 | 3 << 64 | 4 << 96));
+        assert_eq!(store.get_global(copy), Ok(AwwasmValue::V128(u128::MAX)));
+        store.set_global(copy, 7u128).unwrap();
+        assert_eq!(store.get_global_v128(copy), Ok(7));
+        assert_eq!(
+            store.set_global(copy, 7i64),
+            Err(AwwasmRuntimeError::TypeMismatch { expected: "v128".into(), got: "i64".into() })
+        );
+    }
+
+    #[test]
+    fn test_global_set_type_mismatch() {
+        let mut global = AwwasmGlobalInst::new(
+            AwwasmGlobalType::mutable(AwwasmValueType::I32),
+            AwwasmValue::I32(1),
+        );
+
+        assert_eq!(
+            global.set(AwwasmValue::I64(2)),
+            Err(GlobalError::TypeMismatch { expected: AwwasmValueType::I32, got: AwwasmValueType::I64 })
+        );
+        assert_eq!(global.get(), AwwasmValue::I32(1));
+    }
+
+    #[test]
+    fn test_typed_global_access() {
+        let mut store: AwwasmStore = AwwasmStore::new();
+        let counter = store.alloc_global(AwwasmGlobalInst::new(
+            AwwasmGlobalType::mutable(AwwasmValueType::I32),
+            AwwasmValue::I32(1),
+        ));
+        let pi = store.alloc_global(AwwasmGlobalInst::new(
+            AwwasmGlobalType::immutable(AwwasmValueType::F64),
+            AwwasmValue::F64(3.5),
+        ));
+
+        store.set_global(counter, 7).unwrap();
+        assert_eq!(store.get_global_i32(counter), Ok(7));
+        assert_eq!(store.get_global(pi), Ok(AwwasmValue::F64(3.5)));
+        assert_eq!(store.get_global_f64(pi), Ok(3.5));
+
+        assert_eq!(
+            store.get_global_i64(counter),
+            Err(AwwasmRuntimeError::TypeMismatch { expected: "i64".into(), got: "i32".into() })
+        );
+        assert_eq!(
+            store.set_global(counter, 7i64),
+            Err(AwwasmRuntimeError::TypeMismatch { expected: "i32".into(), got: "i64".into() })
+        );
+        assert_eq!(store.set_global(pi, 1.0), Err(AwwasmRuntimeError::ImmutableGlobal(pi.0)));
+        assert_eq!(store.get_global_i32(AwwasmGlobalAddr(9)), Err(AwwasmRuntimeError::InvalidGlobalAddr(9)));
+        assert_eq!(store.get_global_i32(counter), Ok(7));
     }
 
     #[test]
@@ -197,6 +525,128 @@ mod tests {
         
         assert_eq!(AwwasmValue::default_for_type(AwwasmValueType::I32), AwwasmValue::I32(0));
         assert_eq!(AwwasmValue::default_for_type(AwwasmValueType::F64), AwwasmValue::F64(0.0));
+
+        assert_eq!(AwwasmValue::from(7i64), AwwasmValue::I64(7));
+        let args: Vec<AwwasmValue> = vec![1i32.into(), 2.5f32.into()];
+        assert_eq!(args, [AwwasmValue::I32(1), AwwasmValue::F32(2.5)]);
+        assert_eq!(args[0], 1);
+        assert_ne!(args[0], 1i64);
+        assert_eq!(args[1], 2.5f32);
+        assert_eq!(args[0].expect_i32(), 1);
+        assert_eq!(AwwasmValue::F64(0.5).expect_f64(), 0.5);
+    }
+
+    #[test]
+    #[should_panic(expected = "expected i64, got I32(1)")]
+    fn test_value_expect_wrong_type() {
+        AwwasmValue::I32(1).expect_i64();
+    }
+
+    #[test]
+    fn test_error_display() {
+        use alloc::string::ToString;
+
+        let err = AwwasmInstantiationError::MissingImport { module: "env".into(), name: "memory".into() };
+        assert_eq!(err.to_string(), "missing import env.memory");
+        let err = AwwasmInstantiationError::StartFunctionTrapped(AwwasmTrap::Unreachable);
+        assert_eq!(err.to_string(), "start function trapped: unreachable");
+        let err = AwwasmRuntimeError::Trap(AwwasmTrap::DivisionByZero);
+        assert_eq!(err.to_string(), "trap: division by zero");
+
+        // Usable through the core error trait.
+        let boxed: alloc::boxed::Box<dyn core::error::Error> = alloc::boxed::Box::new(err);
+        assert_eq!(boxed.to_string(), "trap: division by zero");
+    }
+
+    #[test]
+    fn test_error_source_chain() {
+        use alloc::string::ToString;
+        use core::error::Error;
+
+        let parse = AwwasmParseError::from_chain(["bad init expr", "unexpected opcode 0x20"]).unwrap();
+        let err = AwwasmInstantiationError::InvalidConstExpr {
+            description: parse.to_string(),
+            source: Some(parse),
+        };
+        let source = err.source().unwrap();
+        assert_eq!(source.to_string(), "bad init expr");
+        assert_eq!(source.source().unwrap().to_string(), "unexpected opcode 0x20");
+        assert!(source.source().unwrap().source().is_none());
+        assert!(AwwasmParseError::from_chain(core::iter::empty::<&str>()).is_none());
+
+        let err = AwwasmRuntimeError::InFunction {
+            module: 0,
+            func_idx: 3,
+            source: alloc::boxed::Box::new(AwwasmRuntimeError::Trap(AwwasmTrap::Unreachable)),
+        };
+        assert_eq!(err.to_string(), "trap: unreachable (in function 3 of module 0)");
+        assert_eq!(err.trap(), Some(&AwwasmTrap::Unreachable));
+        assert_eq!(err.root(), &AwwasmRuntimeError::Trap(AwwasmTrap::Unreachable));
+    }
+
+    #[test]
+    fn test_error_codes() {
+        assert_eq!(AwwasmInstantiationError::MissingImport { module: "env".into(), name: "f".into() }.code(), 1001);
+        assert_eq!(AwwasmInstantiationError::StartFunctionTrapped(AwwasmTrap::Unreachable).code(), 1006);
+        assert_eq!(AwwasmTrap::DivisionByZero.code(), 2001);
+        assert_eq!(AwwasmTrap::host("quota exceeded").code(), 2014);
+        assert_eq!(AwwasmRuntimeError::ExportNotFound("f".into()).code(), 3010);
+        assert_eq!(AwwasmRuntimeError::InvalidArtifact("truncated".into()).code(), 3011);
+        assert_eq!(AwwasmRuntimeError::UntypedFunction(0).code(), 3012);
+
+        // Traps and function context keep the underlying code.
+        let err = AwwasmRuntimeError::InFunction {
+            module: 0,
+            func_idx: 0,
+            source: alloc::boxed::Box::new(AwwasmRuntimeError::Trap(AwwasmTrap::Interrupted)),
+        };
+        assert_eq!(err.code(), 2013);
+        assert_eq!(AwwasmRunError::from(err).code(), 2013);
+    }
+
+    #[test]
+    fn test_trap_kind_and_exit_code() {
+        assert_eq!(AwwasmTrap::Exit(ExitStatus(3)).exit_code(), 3);
+        assert_eq!(AwwasmTrap::Exit(ExitStatus(3)).kind(), TrapKind::Exit);
+        let oob = AwwasmTrap::MemoryOutOfBounds { offset: 0, size: 4, memory_size: 0 };
+        assert_eq!(oob.kind(), TrapKind::MemoryOutOfBounds);
+        assert_eq!(oob.exit_code(), TRAP_EXIT_CODE);
+        assert_eq!(AwwasmTrap::Interrupted.exit_code(), TRAP_EXIT_CODE);
+        #[cfg(unix)]
+        assert_eq!(TRAP_EXIT_CODE, 134);
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_error_serde_round_trip() {
+        use alloc::string::ToString;
+
+        let err = AwwasmRuntimeError::InFunction {
+            module: 1,
+            func_idx: 4,
+            source: alloc::boxed::Box::new(AwwasmRuntimeError::Trap(AwwasmTrap::MemoryOutOfBounds {
+                offset: 65536,
+                size: 4,
+                memory_size: 65536,
+            })),
+        };
+        let json = serde_json::to_string(&err).unwrap();
+        assert_eq!(serde_json::from_str::<AwwasmRuntimeError>(&json).unwrap(), err);
+
+        let err = AwwasmInstantiationError::ExportIndexOutOfBounds {
+            kind: AwwasmExternKind::Global,
+            name: "g".into(),
+            index: 2,
+            count: 1,
+        };
+        let json = serde_json::to_string(&err).unwrap();
+        assert_eq!(serde_json::from_str::<AwwasmInstantiationError>(&json).unwrap(), err);
+
+        // Host payloads travel as their message.
+        let json = serde_json::to_string(&AwwasmTrap::host("quota exceeded")).unwrap();
+        assert_eq!(json, r#"{"Host":"quota exceeded"}"#);
+        let trap: AwwasmTrap = serde_json::from_str(&json).unwrap();
+        assert_eq!(trap.to_string(), "host error: quota exceeded");
     }
 
     // store_init() integration tests
@@ -225,6 +675,240 @@ mod tests {
         assert_eq!(inst.memaddrs.len(), 0);
     }
 
+    #[test]
+    fn test_instantiate_bytes() {
+        let empty = wat::parse_str("(module)").unwrap();
+        let wasm = wat::parse_str(r#"
+            (module
+                (memory (export "mem") 1)
+                (func (export "answer") (result i32) i32.const 42)
+            )
+        "#).unwrap();
+
+        let mut store = AwwasmStore::new();
+        let empty_addr = store.instantiate_bytes(&empty, &mut AwwasmImports::new()).unwrap();
+        assert!(store.module(empty_addr).unwrap().exports.is_empty());
+
+        let addr = store.instantiate_bytes(&wasm, &mut AwwasmImports::new()).unwrap();
+        let answer = export_func(&store, addr, "answer");
+        assert_eq!(store.invoke(answer, &[], &mut NoHost).unwrap(), vec![AwwasmValue::I32(42)]);
+
+        let err = store.instantiate_bytes(b"not wasm", &mut AwwasmImports::new()).unwrap_err();
+        assert!(matches!(err, AwwasmInstantiationError::InvalidModule(_)));
+        assert_eq!(err.code(), 1012);
+        assert!(err.to_string().starts_with("invalid module: "));
+        assert_eq!(store.module_count(), 2);
+    }
+
+    #[test]
+    fn test_func_type_registry() {
+        let wasm = wat::parse_str(r#"
+            (module
+                (type $unary (func (param i32) (result i32)))
+                (import "env" "double" (func (type $unary)))
+                (func (export "inc") (type $unary) local.get 0 i32.const 1 i32.add)
+                (func (export "nop"))
+            )
+        "#).unwrap();
+        let other = wat::parse_str(r#"(module (func (export "dec") (param i32) (result i32) local.get 0))"#).unwrap();
+
+        let mut store = AwwasmStore::new();
+        let mut imports = AwwasmImports::builder().func("env", "double", AwwasmFuncInst::host(0, 1)).build();
+        let addr = store.instantiate_bytes(&wasm, &mut imports).unwrap();
+        let other_addr = store.instantiate_bytes(&other, &mut AwwasmImports::new()).unwrap();
+        let inst = store.module(addr).unwrap();
+
+        let unary = func::AwwasmFuncType::new(vec![AwwasmValueType::I32], vec![AwwasmValueType::I32]);
+        assert_eq!(store.func_type(inst.funcaddrs[0]), Ok(&unary));
+        assert_eq!(store.func_type(inst.get_func("inc").unwrap()), Ok(&unary));
+        assert_eq!(store.func_type(inst.get_func("nop").unwrap()), Ok(&func::AwwasmFuncType::new(vec![], vec![])));
+
+        // Equal signatures share one registry entry across modules.
+        let dec = store.module(other_addr).unwrap().get_func("dec").unwrap();
+        assert!(core::ptr::eq(store.func_type(dec).unwrap(), store.func_type(inst.funcaddrs[0]).unwrap()));
+
+        let loose = store.alloc_func(AwwasmFuncInst::host(0, 2));
+        assert_eq!(store.func_type(loose), Err(AwwasmRuntimeError::UntypedFunction(loose.0)));
+        assert_eq!(store.func_type(AwwasmFuncAddr(99)), Err(AwwasmRuntimeError::InvalidFuncAddr(99)));
+    }
+
+    #[test]
+    fn test_reference_instructions() {
+        let wasm = wat::parse_str(r#"
+            (module
+                (func $exported (export "exported"))
+                (func $in_global)
+                (func $undeclared)
+                (global funcref (ref.func $in_global))
+                (func (export "null_is_null") (result i32) ref.null func ref.is_null)
+                (func (export "extern_is_null") (result i32) ref.null extern ref.is_null)
+                (func (export "ref_exported") (result funcref) ref.func $exported)
+                (func (export "ref_in_global") (result i32) ref.func $in_global ref.is_null)
+                (func (export "ref_undeclared") (result funcref) ref.func $undeclared)
+            )
+        "#).unwrap();
+        let mut store = AwwasmStore::new();
+        let addr = store.instantiate_bytes(&wasm, &mut AwwasmImports::new()).unwrap();
+        let exported = store.module(addr).unwrap().get_func("exported").unwrap();
+
+        assert_eq!(store.invoke_export(addr, "null_is_null", &[], &mut NoHost).unwrap(), vec![AwwasmValue::I32(1)]);
+        assert_eq!(store.invoke_export(addr, "extern_is_null", &[], &mut NoHost).unwrap(), vec![AwwasmValue::I32(1)]);
+        assert_eq!(
+            store.invoke_export(addr, "ref_exported", &[], &mut NoHost).unwrap(),
+            vec![AwwasmValue::FuncRef(Some(exported))]
+        );
+        assert_eq!(store.invoke_export(addr, "ref_in_global", &[], &mut NoHost).unwrap(), vec![AwwasmValue::I32(0)]);
+        assert!(matches!(
+            store.invoke_export(addr, "ref_undeclared", &[], &mut NoHost).unwrap_err().root(),
+            AwwasmRuntimeError::InstructionParseError(msg) if msg.contains("undeclared")
+        ));
+    }
+
+    #[test]
+    fn test_declarative_element_segments() {
+        let wasm = wat::parse_str(r#"
+            (module
+                (table (export "t") 2 funcref)
+                (func $by_index)
+                (func $by_expr)
+                (elem declare func $by_index)
+                (elem declare funcref (ref.func $by_expr) (ref.null func))
+                (func (export "refs") (result funcref funcref)
+                    ref.func $by_index ref.func $by_expr)
+            )
+        "#).unwrap();
+        let mut store = AwwasmStore::new();
+        let addr = store.instantiate_bytes(&wasm, &mut AwwasmImports::new()).unwrap();
+        let inst = store.module(addr).unwrap();
+        let (by_index, by_expr) = (inst.funcaddrs[0], inst.funcaddrs[1]);
+        let table = inst.get_table("t").unwrap();
+
+        assert_eq!(
+            store.invoke_export(addr, "refs", &[], &mut NoHost).unwrap(),
+            vec![AwwasmValue::FuncRef(Some(by_index)), AwwasmValue::FuncRef(Some(by_expr))]
+        );
+        // Declaring functions leaves tables alone.
+        let table = store.table(table).unwrap();
+        assert_eq!((table.get(0).unwrap(), table.get(1).unwrap()), (None, None));
+    }
+
+    #[test]
+    fn test_element_offset_from_imported_global() {
+        let wasm = wat::parse_str(r#"
+            (module
+                (import "env" "table_base" (global $base i32))
+                (table (export "t") 4 funcref)
+                (func $f)
+                (func $g)
+                (elem (global.get $base) $f $g)
+            )
+        "#).unwrap();
+        let base = |value: i32| {
+            AwwasmImports::builder()
+                .global("env", "table_base", AwwasmGlobalInst::new(AwwasmGlobalType::immutable(AwwasmValueType::I32), AwwasmValue::I32(value)))
+                .build()
+        };
+
+        let mut store = AwwasmStore::new();
+        let addr = store.instantiate_bytes(&wasm, &mut base(2)).unwrap();
+        let inst = store.module(addr).unwrap();
+        let (f, g) = (inst.funcaddrs[0], inst.funcaddrs[1]);
+        let table = store.table(inst.get_table("t").unwrap()).unwrap();
+        assert_eq!(table.get(1).unwrap(), None);
+        assert_eq!((table.get(2).unwrap(), table.get(3).unwrap()), (Some(f), Some(g)));
+
+        assert_eq!(
+            store.instantiate_bytes(&wasm, &mut base(3)),
+            Err(AwwasmInstantiationError::ElementSegmentOutOfBounds { segment_idx: 0, offset: 3, size: 2, table_size: 4 })
+        );
+    }
+
+    #[test]
+    fn test_shared_table_import() {
+        let main = wat::parse_str(r#"
+            (module
+                (table (export "__indirect_function_table") 2 funcref)
+                (type $unary (func (param i32) (result i32)))
+                (func $double (param i32) (result i32) local.get 0 i32.const 2 i32.mul)
+                (elem (i32.const 0) $double)
+                (func (export "dispatch") (param i32 i32) (result i32)
+                    local.get 1 local.get 0 call_indirect (type $unary))
+            )
+        "#).unwrap();
+        let side = wat::parse_str(r#"
+            (module
+                (import "env" "__indirect_function_table" (table 2 funcref))
+                (func $square (param i32) (result i32) local.get 0 local.get 0 i32.mul)
+                (elem (i32.const 1) $square)
+            )
+        "#).unwrap();
+        let mut store = AwwasmStore::new();
+        let main_addr = store.instantiate_bytes(&main, &mut AwwasmImports::new()).unwrap();
+        let table = store.module(main_addr).unwrap().get_table("__indirect_function_table").unwrap();
+        let mut imports = AwwasmImports::builder().table("env", "__indirect_function_table", table).build();
+        let side_addr = store.instantiate_bytes(&side, &mut imports).unwrap();
+
+        assert_eq!(store.module(side_addr).unwrap().table(0), Some(table));
+        assert_eq!(store.table_count(), 1);
+        assert_eq!(store.invoke_export(main_addr, "dispatch", &[0.into(), 7.into()], &mut NoHost).unwrap(), vec![AwwasmValue::I32(14)]);
+        assert_eq!(store.invoke_export(main_addr, "dispatch", &[1.into(), 7.into()], &mut NoHost).unwrap(), vec![AwwasmValue::I32(49)]);
+
+        // The imported table must satisfy the declared limits.
+        let small = store.alloc_table(AwwasmTableInst::new(AwwasmTableType::funcref(1, None)));
+        let mut imports = AwwasmImports::builder().table("env", "__indirect_function_table", small).build();
+        assert_eq!(
+            store.instantiate_bytes(&side, &mut imports),
+            Err(AwwasmInstantiationError::ImportTypeMismatch {
+                module: "env".into(),
+                name: "__indirect_function_table".into(),
+                expected: "(table 2 funcref)".into(),
+                got: "(table 1 funcref)".into(),
+            })
+        );
+    }
+
+    #[test]
+    fn test_invoke_export() {
+        let wasm = wat::parse_str(r#"
+            (module
+                (memory (export "mem") 1)
+                (func (export "add") (param i32 i32) (result i32)
+                    local.get 0 local.get 1 i32.add)
+            )
+        "#).unwrap();
+        let mut store = AwwasmStore::new();
+        let addr = store.instantiate_bytes(&wasm, &mut AwwasmImports::new()).unwrap();
+
+        let sum = store.invoke_export(addr, "add", &[1.into(), 2.into()], &mut NoHost).unwrap();
+        assert_eq!(sum[0], 3);
+        assert!(matches!(
+            store.invoke_export(addr, "add", &[1.into()], &mut NoHost),
+            Err(AwwasmRuntimeError::TypeMismatch { .. })
+        ));
+        assert_eq!(
+            store.invoke_export(addr, "mem", &[], &mut NoHost),
+            Err(AwwasmRuntimeError::ExportNotFound("mem".into()))
+        );
+        assert_eq!(
+            store.invoke_export(AwwasmModuleAddr(9), "add", &[], &mut NoHost),
+            Err(AwwasmRuntimeError::ExportNotFound("add".into()))
+        );
+    }
+
+    #[cfg(feature = "wat")]
+    #[test]
+    fn test_instantiate_wat() {
+        let mut store = AwwasmStore::new();
+        let addr = store
+            .instantiate_wat(r#"(module (func (export "answer") (result i32) i32.const 42))"#, &mut AwwasmImports::new())
+            .unwrap();
+        let answer = export_func(&store, addr, "answer");
+        assert_eq!(store.invoke(answer, &[], &mut NoHost).unwrap(), vec![AwwasmValue::I32(42)]);
+
+        let err = store.instantiate_wat("(module (func (result i32) i32.bogus))", &mut AwwasmImports::new()).unwrap_err();
+        assert_eq!(err.code(), 1012);
+    }
+
     #[test]
     fn test_instantiate_with_memory() {
         let wasm = wat::parse_str("(module (memory 1 4))").unwrap();
@@ -283,9 +967,10 @@ mod tests {
         let inst = store.module(addr).unwrap();
         let mem = store.mem(inst.memaddrs[0]).unwrap();
 
-        assert_eq!(&mem.data[16..21], b"hello");
-        assert_eq!(mem.data[15], 0);
-        assert_eq!(mem.data[21], 0);
+        let data = mem.data.to_vec();
+        assert_eq!(&data[16..21], b"hello");
+        assert_eq!(data[15], 0);
+        assert_eq!(data[21], 0);
     }
 
     #[test]
@@ -311,6 +996,69 @@ mod tests {
 
         let func_export = inst.exports.iter().find(|e| e.name == b"add").unwrap();
         assert!(matches!(func_export.addr, AwwasmExternAddr::Func(_)));
+
+        // Typed getters match both the name and the kind.
+        assert_eq!(inst.get_memory("memory"), Some(inst.memaddrs[0]));
+        assert_eq!(inst.get_func("add"), Some(inst.funcaddrs[0]));
+        assert_eq!(inst.get_func("memory"), None);
+        assert_eq!(inst.get_global("add"), None);
+        assert_eq!(inst.get_table("missing"), None);
+    }
+
+    #[test]
+    fn test_module_info() {
+        use info::AwwasmExternType;
+
+        let wasm = wat::parse_str(r#"
+            (module
+                (import "env" "log" (func (param i32)))
+                (import "env" "memory" (memory 1 4))
+                (import "env" "base" (global i64))
+                (table (export "table") 2 funcref)
+                (global (export "counter") (mut i32) (i32.const 0))
+                (func (export "add") (param i32 i32) (result i32)
+                    local.get 0 local.get 1 i32.add)
+                (export "log" (func 0))
+                (export "memory" (memory 0))
+            )
+        "#).unwrap();
+        let mut module = AwwasmModule::new(&wasm).unwrap();
+        module.resolve_all_sections().unwrap();
+        let info = AwwasmModuleInfo::new(&module).unwrap();
+
+        let imports: Vec<_> = info.imports.iter().map(|i| (i.module, i.name, i.ty.clone())).collect();
+        assert_eq!(imports, [
+            (&b"env"[..], &b"log"[..], AwwasmExternType::Func(func::AwwasmFuncType::new(vec![AwwasmValueType::I32], vec![]))),
+            (b"env", b"memory", AwwasmExternType::Memory(AwwasmMemoryType::new(1, Some(4)))),
+            (b"env", b"base", AwwasmExternType::Global(AwwasmGlobalType::immutable(AwwasmValueType::I64))),
+        ]);
+
+        assert_eq!(info.exports.len(), 5);
+        assert_eq!(info.export("table"), Some(&AwwasmExternType::Table(AwwasmTableType::funcref(2, None))));
+        assert_eq!(info.export("counter"), Some(&AwwasmExternType::Global(AwwasmGlobalType::mutable(AwwasmValueType::I32))));
+        assert_eq!(info.export("add"), Some(&AwwasmExternType::Func(func::AwwasmFuncType::new(
+            vec![AwwasmValueType::I32, AwwasmValueType::I32],
+            vec![AwwasmValueType::I32],
+        ))));
+        // Imported entities come first in their index space.
+        assert_eq!(info.export("log"), Some(&info.imports[0].ty));
+        assert_eq!(info.export("memory").map(|ty| ty.kind()), Some(AwwasmExternKind::Mem));
+        assert!(info.export("missing").is_none());
+    }
+
+    #[test]
+    fn test_export_lookup() {
+        let names: Vec<Vec<u8>> = (0..100).map(|i| format!("e{}", i).into_bytes()).collect();
+        let mut inst = AwwasmModuleInst::new();
+        for (i, name) in names.iter().enumerate() {
+            inst.push_export(instance::AwwasmExportInst::new(name, AwwasmExternAddr::Func(AwwasmFuncAddr(i as u32))));
+        }
+        inst.push_export(instance::AwwasmExportInst::new(b"e7", AwwasmExternAddr::Mem(AwwasmMemAddr(0))));
+
+        assert_eq!(inst.get_func("e42"), Some(AwwasmFuncAddr(42)));
+        assert_eq!(inst.get_func("e7"), Some(AwwasmFuncAddr(7)));
+        assert!(inst.export(b"e100").is_none());
+        assert_eq!(inst.exports.len(), 101);
     }
 
     #[test]
@@ -356,5 +1104,2877 @@ mod tests {
             other => panic!("expected MissingImport, got {:?}", other),
         }
     }
-}
 
+    #[test]
+    fn test_import_lookup() {
+        let names: Vec<Vec<u8>> = (0..256).map(|i| format!("f{}", i).into_bytes()).collect();
+        let mut imports = AwwasmImports::new();
+        for (i, name) in names.iter().enumerate() {
+            imports.add_func(b"env", name, AwwasmFuncInst::host(i as u32, 0));
+        }
+        imports.add_global(b"env", b"g", AwwasmGlobalInst::new(AwwasmGlobalType::immutable(AwwasmValueType::I32), AwwasmValue::I32(1)));
+        imports.add_global(b"env", b"g", AwwasmGlobalInst::new(AwwasmGlobalType::immutable(AwwasmValueType::I32), AwwasmValue::I32(2)));
+
+        assert!(imports.find(b"env", b"f200").is_some());
+        assert!(imports.find(b"other", b"f200").is_none());
+        // A later add replaces the earlier one.
+        match imports.take(b"env", b"g").map(|e| e.value) {
+            Some(imports::AwwasmImportValue::Global(g)) => assert_eq!(g.get(), AwwasmValue::I32(2)),
+            other => panic!("expected global import, got {:?}", other),
+        }
+        assert!(imports.take(b"env", b"g").is_none());
+        assert!(imports.take(b"env", b"f17").is_some());
+        assert!(imports.find(b"env", b"f17").is_none());
+        assert!(imports.find(b"env", b"f18").is_some());
+    }
+
+    #[test]
+    fn test_imports_builder() {
+        let wasm = wat::parse_str(r#"
+            (module
+                (import "env" "memory" (memory 1))
+                (import "env" "print" (func (param i32)))
+                (import "env" "log" (func (param i32)))
+            )
+        "#).unwrap();
+        let mut imports = AwwasmImports::builder()
+            .memory("env", "memory", AwwasmMemInst::new(AwwasmMemoryType::new(1, None)))
+            .func_names("env", &["print", "log"], AwwasmFuncInst::host(0, 7))
+            .build();
+        assert!(imports.find(b"env", b"log").is_some());
+
+        let mut store = AwwasmStore::new();
+        let addr = store.instantiate_bytes(&wasm, &mut imports).unwrap();
+        let inst = store.module(addr).unwrap();
+        assert_eq!(inst.memaddrs.len(), 1);
+        for &func in &inst.funcaddrs {
+            match store.func(func).unwrap() {
+                AwwasmFuncInst::Host(host) => assert_eq!(host.host_func_id, 7),
+                _ => panic!("expected host function"),
+            }
+        }
+        assert_eq!(inst.funcaddrs.len(), 2);
+    }
+
+    // Execution tests
+
+    /// Host that rejects every call, for modules without host imports.
+    struct NoHost;
+
+    impl AwwasmHost for NoHost {
+        fn call(
+            &mut self,
+            host_func_id: u32,
+            _args: &[AwwasmValue],
+            _mem: Option<&mut AwwasmMemInst>,
+        ) -> Result<Vec<AwwasmValue>, AwwasmTrap> {
+            Err(AwwasmTrap::InvalidHostCall { host_func_id })
+        }
+    }
+
+    fn export_func(store: &AwwasmStore, addr: AwwasmModuleAddr, name: &str) -> AwwasmFuncAddr {
+        match store.module(addr).unwrap().export_by_str(name).unwrap().addr {
+            AwwasmExternAddr::Func(f) => f,
+            other => panic!("expected function export, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_invoke_arithmetic_and_control_flow() {
+        let wasm = wat::parse_str(r#"
+            (module
+                (func (export "add") (param i32 i32) (result i32)
+                    local.get 0 local.get 1 i32.add)
+                (func $fac (export "fac") (param i64) (result i64)
+                    local.get 0 i64.eqz
+                    if (result i64) i64.const 1
+                    else local.get 0 local.get 0 i64.const 1 i64.sub call $fac i64.mul end)
+                (func (export "sum_to") (param i32) (result i32) (local i32)
+                    block
+                        loop
+                            local.get 0 i32.eqz br_if 1
+                            local.get 1 local.get 0 i32.add local.set 1
+                            local.get 0 i32.const 1 i32.sub local.set 0
+                            br 0
+                        end
+                    end
+                    local.get 1)
+                (func (export "classify") (param i32) (result i32)
+                    block block block
+                        local.get 0 br_table 0 1 2
+                    end i32.const 10 return
+                    end i32.const 20 return
+                    end i32.const 30)
+                (func (export "fmin") (param f64 f64) (result f64)
+                    local.get 0 local.get 1 f64.min)
+            )
+        "#).unwrap();
+        let mut module = AwwasmModule::new(&wasm).unwrap();
+        module.resolve_all_sections().unwrap();
+        let mut store = AwwasmStore::new();
+        let addr = store.store_init(&module, &mut AwwasmImports::new()).unwrap();
+
+        let add = export_func(&store, addr, "add");
+        assert_eq!(store.invoke(add, &[AwwasmValue::I32(40), AwwasmValue::I32(2)], &mut NoHost).unwrap(), vec![AwwasmValue::I32(42)]);
+        let fac = export_func(&store, addr, "fac");
+        assert_eq!(store.invoke(fac, &[AwwasmValue::I64(20)], &mut NoHost).unwrap(), vec![AwwasmValue::I64(2432902008176640000)]);
+        let sum_to = export_func(&store, addr, "sum_to");
+        assert_eq!(store.invoke(sum_to, &[AwwasmValue::I32(100)], &mut NoHost).unwrap(), vec![AwwasmValue::I32(5050)]);
+        let classify = export_func(&store, addr, "classify");
+        for (arg, expected) in [(0, 10), (1, 20), (2, 30), (7, 30)] {
+            assert_eq!(store.invoke(classify, &[AwwasmValue::I32(arg)], &mut NoHost).unwrap(), vec![AwwasmValue::I32(expected)]);
+        }
+        let fmin = export_func(&store, addr, "fmin");
+        let zero = store.invoke(fmin, &[AwwasmValue::F64(0.0), AwwasmValue::F64(-0.0)], &mut NoHost).unwrap();
+        assert_eq!(zero[0].as_f64().unwrap().to_bits(), (-0.0f64).to_bits());
+
+        // Bodies are lowered on first call and cached.
+        assert!(matches!(
+            store.func(add).unwrap(),
+            crate::func::AwwasmFuncInst::Wasm(f)
+                if matches!(&f.code, LazyResolvedCodeRef::Resolved { compiled, .. } if !compiled.is_empty())
+        ));
+        assert!(matches!(
+            store.invoke(add, &[AwwasmValue::I32(1)], &mut NoHost),
+            Err(AwwasmRuntimeError::TypeMismatch { .. })
+        ));
+    }
+
+    #[test]
+    fn test_lowering_resolves_block_targets() {
+        use crate::ir::{lower, Op};
+
+        // block
+        //   if (result i32) i32.const 1 else i32.const 2 end
+        //   drop
+        //   if nop end
+        // end
+        // end
+        let code = [
+            0x02, 0x40, 0x04, 0x7f, 0x41, 0x01, 0x05, 0x41, 0x02, 0x0b, 0x1a, 0x04, 0x40, 0x01, 0x0b, 0x0b, 0x0b,
+        ];
+        let compiled = lower(Vec::new(), &code, &[], &Default::default()).unwrap();
+        assert_eq!(compiled.ops[0], Op::Block { params: 0, results: 0, end: 11 });
+        assert_eq!(compiled.ops[1], Op::If { params: 0, results: 1, else_: 4, end: 6 });
+        assert_eq!(compiled.ops[3], Op::Else { end: 6 });
+        // Without an `else`, a false condition lands on the `end`.
+        assert_eq!(compiled.ops[7], Op::If { params: 0, results: 0, else_: 9, end: 10 });
+
+        assert!(lower(Vec::new(), &[0x02, 0x40, 0x01], &[], &Default::default()).is_err());
+        assert!(lower(Vec::new(), &[0x05, 0x0b], &[], &Default::default()).is_err());
+    }
+
+    #[test]
+    fn test_lowering_fuses_superinstructions() {
+        use crate::ir::{lower, Op};
+
+        // block
+        //   loop
+        //     local.get 0 local.get 1 i32.add local.set 1
+        //     local.get 0 i32.const 1 i32.add local.tee 0
+        //     i32.const 10 i32.lt_s br_if 0
+        //   end
+        // end
+        // end
+        let code = [
+            0x02, 0x40, 0x03, 0x40, 0x20, 0x00, 0x20, 0x01, 0x6a, 0x21, 0x01, 0x20, 0x00, 0x41, 0x01, 0x6a, 0x22,
+            0x00, 0x41, 0x0a, 0x48, 0x0d, 0x00, 0x0b, 0x0b, 0x0b,
+        ];
+        let compiled = lower(Vec::new(), &code, &[], &Default::default()).unwrap();
+        assert_eq!(
+            compiled.ops,
+            vec![
+                Op::Block { params: 0, results: 0, end: 10 },
+                Op::Loop { params: 0, results: 0 },
+                Op::I32AddLocals { lhs: 0, rhs: 1, dst: 1 },
+                Op::LocalGet(0),
+                Op::I32AddImm(1),
+                Op::LocalTee(0),
+                Op::I32Const(10),
+                Op::I32CmpBrIf { cmp: 0x48, depth: 0 },
+                Op::End,
+                Op::End,
+                Op::End,
+            ]
+        );
+    }
+
+    #[test]
+    fn test_precompiled_artifact_round_trip() {
+        let wasm = wat::parse_str(r#"
+            (module
+                (type $unary (func (param i32) (result i32)))
+                (table 1 funcref)
+                (elem (i32.const 0) $double)
+                (func $double (param i32) (result i32) local.get 0 i32.const 2 i32.mul)
+                (func (export "run") (param i32) (result i32) (local i64 f64)
+                    f64.const 1.5 local.set 2
+                    block (result i32)
+                        local.get 0 i32.const 0 call_indirect (type $unary)
+                        local.get 0 i32.const 3 i32.gt_s br_if 0
+                        drop i32.const -1
+                    end))
+        "#).unwrap();
+        let mut module = AwwasmModule::new(&wasm).unwrap();
+        module.resolve_all_sections().unwrap();
+
+        let mut store = AwwasmStore::new();
+        let addr = store.store_init(&module, &mut AwwasmImports::new()).unwrap();
+        let artifact = store.precompile(addr).unwrap();
+
+        let mut fresh = AwwasmStore::new();
+        let fresh_addr = fresh.store_init(&module, &mut AwwasmImports::new()).unwrap();
+        fresh.load_precompiled(fresh_addr, &artifact).unwrap();
+        let run = export_func(&fresh, fresh_addr, "run");
+        assert!(matches!(fresh.func(run).unwrap(), AwwasmFuncInst::Wasm(f) if matches!(f.code, LazyResolvedCodeRef::Resolved { .. })));
+        assert_eq!(fresh.invoke(run, &[AwwasmValue::I32(5)], &mut NoHost).unwrap(), vec![AwwasmValue::I32(10)]);
+        assert_eq!(fresh.invoke(run, &[AwwasmValue::I32(1)], &mut NoHost).unwrap(), vec![AwwasmValue::I32(-1)]);
+
+        // Corrupt, truncated and mismatched artifacts are all rejected.
+        let mut corrupt = artifact.clone();
+        *corrupt.last_mut().unwrap() ^= 0xff;
+        for bad in [&corrupt[..], &artifact[..artifact.len() - 1], b"junk"] {
+            assert!(matches!(fresh.load_precompiled(fresh_addr, bad), Err(AwwasmRuntimeError::InvalidArtifact(_))));
+        }
+        let other = wat::parse_str(r#"(module (func (export "f")) (func))"#).unwrap();
+        let mut other = AwwasmModule::new(&other).unwrap();
+        other.resolve_all_sections().unwrap();
+        let other_addr = fresh.store_init(&other, &mut AwwasmImports::new()).unwrap();
+        assert!(matches!(fresh.load_precompiled(other_addr, &artifact), Err(AwwasmRuntimeError::InvalidArtifact(_))));
+
+        // The loading store's metering must match the artifact's.
+        let metered = |cost| {
+            let mut store = AwwasmStore::new();
+            let addr = store.store_init(&module, &mut AwwasmImports::new()).unwrap();
+            store.set_metering(AwwasmMetering::Instrumented);
+            store.set_gas_table(alloc::sync::Arc::new(AwwasmGasTable::uniform(cost)));
+            (store, addr)
+        };
+        let (mut store, addr) = metered(2);
+        assert!(matches!(store.load_precompiled(addr, &artifact), Err(AwwasmRuntimeError::InvalidArtifact(_))));
+        let instrumented = store.precompile(addr).unwrap();
+        assert!(matches!(fresh.load_precompiled(fresh_addr, &instrumented), Err(AwwasmRuntimeError::InvalidArtifact(_))));
+        let (mut other_table, other_table_addr) = metered(3);
+        assert!(matches!(
+            other_table.load_precompiled(other_table_addr, &instrumented),
+            Err(AwwasmRuntimeError::InvalidArtifact(_))
+        ));
+        let (mut same, same_addr) = metered(2);
+        same.load_precompiled(same_addr, &instrumented).unwrap();
+        same.set_fuel(Some(1_000));
+        let run = export_func(&same, same_addr, "run");
+        assert_eq!(same.invoke(run, &[AwwasmValue::I32(5)], &mut NoHost).unwrap(), vec![AwwasmValue::I32(10)]);
+        assert!(same.fuel().unwrap() < 1_000);
+    }
+
+    #[test]
+    fn test_precompiled_artifact_bounds() {
+        let wasm = wat::parse_str(r#"(module (func (export "f") (param i32) block local.get 0 br_if 0 end))"#).unwrap();
+        let mut module = AwwasmModule::new(&wasm).unwrap();
+        module.resolve_all_sections().unwrap();
+        let mut store = AwwasmStore::new();
+        let addr = store.store_init(&module, &mut AwwasmImports::new()).unwrap();
+        let artifact = store.precompile(addr).unwrap();
+        store.load_precompiled(addr, &artifact).unwrap();
+
+        // The last byte is the function's call_indirect site count.
+        let mut sites = artifact.clone();
+        assert_eq!(sites.pop(), Some(0));
+        sites.extend_from_slice(&[0xff, 0xff, 0xff, 0xff, 0x0f]);
+        assert!(matches!(store.load_precompiled(addr, &sites), Err(AwwasmRuntimeError::InvalidArtifact(_))));
+
+        // `br_if 0` retargeted past the function body.
+        let mut branch = artifact.clone();
+        let at = branch.windows(2).rposition(|w| w == [8, 0]).unwrap();
+        branch[at + 1] = 5;
+        assert!(matches!(store.load_precompiled(addr, &branch), Err(AwwasmRuntimeError::InvalidArtifact(_))));
+    }
+
+    #[test]
+    fn test_resolve_all_functions() {
+        let wasm = wat::parse_str(r#"
+            (module
+                (import "env" "f" (func))
+                (func (export "a") (result i32) i32.const 1)
+                (func (export "b") (result i32) i32.const 2))
+        "#).unwrap();
+        let mut module = AwwasmModule::new(&wasm).unwrap();
+        module.resolve_all_sections().unwrap();
+        let is_resolved = |store: &AwwasmStore, addr| {
+            let f = export_func(store, addr, "b");
+            matches!(store.func(f).unwrap(), AwwasmFuncInst::Wasm(f) if matches!(f.code, LazyResolvedCodeRef::Resolved { .. }))
+        };
+
+        for parallel in [false, cfg!(feature = "parallel")] {
+            let mut store = AwwasmStore::new();
+            let mut imports = AwwasmImports::new();
+            imports.add_func(b"env", b"f", AwwasmFuncInst::host(0, 0));
+            let addr = store.store_init(&module, &mut imports).unwrap();
+            assert!(!is_resolved(&store, addr));
+            #[cfg(feature = "parallel")]
+            if parallel {
+                store.resolve_all_functions_parallel(addr).unwrap();
+            }
+            if !parallel {
+                store.resolve_all_functions(addr).unwrap();
+            }
+            assert!(is_resolved(&store, addr));
+        }
+    }
+
+    #[test]
+    fn test_invoke_memory_globals_and_tables() {
+        let wasm = wat::parse_str(r#"
+            (module
+                (memory 1)
+                (global $counter (mut i32) (i32.const 5))
+                (table 2 funcref)
+                (elem (i32.const 0) $double $square)
+                (type $unary (func (param i32) (result i32)))
+                (func $double (param i32) (result i32) local.get 0 i32.const 2 i32.mul)
+                (func $square (param i32) (result i32) local.get 0 local.get 0 i32.mul)
+                (func (export "dispatch") (param i32 i32) (result i32)
+                    local.get 1 local.get 0 call_indirect (type $unary))
+                (func (export "bump") (result i32)
+                    global.get $counter i32.const 1 i32.add global.set $counter
+                    global.get $counter)
+                (func (export "store_load") (param i32 i64) (result i64)
+                    local.get 0 local.get 1 i64.store offset=8
+                    local.get 0 i64.load offset=8)
+            )
+        "#).unwrap();
+        let mut module = AwwasmModule::new(&wasm).unwrap();
+        module.resolve_all_sections().unwrap();
+        let mut store = AwwasmStore::new();
+        let addr = store.store_init(&module, &mut AwwasmImports::new()).unwrap();
+
+        let dispatch = export_func(&store, addr, "dispatch");
+        assert_eq!(store.invoke(dispatch, &[AwwasmValue::I32(0), AwwasmValue::I32(7)], &mut NoHost).unwrap(), vec![AwwasmValue::I32(14)]);
+        assert_eq!(store.invoke(dispatch, &[AwwasmValue::I32(1), AwwasmValue::I32(7)], &mut NoHost).unwrap(), vec![AwwasmValue::I32(49)]);
+        assert_eq!(store.invoke(dispatch, &[AwwasmValue::I32(5), AwwasmValue::I32(7)], &mut NoHost).unwrap_err().trap(), Some(&AwwasmTrap::TableOutOfBounds { index: 5, table_size: 2 }));
+
+        // The call site caches the last callee that passed its signature
+        // check; replacing the table slot must still be type-checked.
+        let bump = export_func(&store, addr, "bump");
+        let table = store.module(addr).unwrap().table(0).unwrap();
+        let square = store.table(table).unwrap().get(1).unwrap();
+        store.table_mut(table).unwrap().set(1, Some(bump)).unwrap();
+        let err = store.invoke(dispatch, &[AwwasmValue::I32(1), AwwasmValue::I32(7)], &mut NoHost).unwrap_err();
+        assert_eq!(
+            err.trap(),
+            Some(&AwwasmTrap::IndirectCallTypeMismatch {
+                expected: "(func (param i32) (result i32))".into(),
+                got: "(func (result i32))".into(),
+            })
+        );
+        store.table_mut(table).unwrap().set(1, square).unwrap();
+        assert_eq!(store.invoke(dispatch, &[AwwasmValue::I32(1), AwwasmValue::I32(7)], &mut NoHost).unwrap(), vec![AwwasmValue::I32(49)]);
+
+        store.invoke(bump, &[], &mut NoHost).unwrap();
+        assert_eq!(store.invoke(bump, &[], &mut NoHost).unwrap(), vec![AwwasmValue::I32(7)]);
+
+        let store_load = export_func(&store, addr, "store_load");
+        assert_eq!(store.invoke(store_load, &[AwwasmValue::I32(16), AwwasmValue::I64(-3)], &mut NoHost).unwrap(), vec![AwwasmValue::I64(-3)]);
+        assert!(matches!(
+            store.invoke(store_load, &[AwwasmValue::I32(65530), AwwasmValue::I64(1)], &mut NoHost).unwrap_err().trap(),
+            Some(AwwasmTrap::MemoryOutOfBounds { .. })
+        ));
+    }
+
+    #[test]
+    fn test_invoke_traps() {
+        use alloc::string::ToString;
+
+        let wasm = wat::parse_str(r#"
+            (module
+                (func (export "div") (param i32 i32) (result i32) local.get 0 local.get 1 i32.div_s)
+                (func (export "boom") unreachable)
+                (func $forever (export "recurse") call $forever)
+                (func (export "spin") loop br 0 end)
+            )
+        "#).unwrap();
+        let mut module = AwwasmModule::new(&wasm).unwrap();
+        module.resolve_all_sections().unwrap();
+        let mut store = AwwasmStore::new();
+        let addr = store.store_init(&module, &mut AwwasmImports::new()).unwrap();
+
+        let div = export_func(&store, addr, "div");
+        assert_eq!(store.invoke(div, &[AwwasmValue::I32(1), AwwasmValue::I32(0)], &mut NoHost).unwrap_err().trap(), Some(&AwwasmTrap::DivisionByZero));
+        assert_eq!(store.invoke(div, &[AwwasmValue::I32(i32::MIN), AwwasmValue::I32(-1)], &mut NoHost).unwrap_err().trap(), Some(&AwwasmTrap::IntegerOverflow));
+        let boom = export_func(&store, addr, "boom");
+        assert_eq!(
+            store.invoke(boom, &[], &mut NoHost).unwrap_err().to_string(),
+            "trap: unreachable (in function 1 of module 0)"
+        );
+        assert_eq!(store.invoke(boom, &[], &mut NoHost).unwrap_err().trap(), Some(&AwwasmTrap::Unreachable));
+        let recurse = export_func(&store, addr, "recurse");
+        assert_eq!(store.invoke(recurse, &[], &mut NoHost).unwrap_err().trap(), Some(&AwwasmTrap::CallStackExhausted));
+
+        // An already-raised interrupt stops an infinite loop.
+        let handle = AwwasmInterruptHandle::new();
+        store.set_interrupt_handle(handle.clone());
+        handle.interrupt();
+        let spin = export_func(&store, addr, "spin");
+        assert_eq!(store.invoke(spin, &[], &mut NoHost).unwrap_err().trap(), Some(&AwwasmTrap::Interrupted));
+    }
+
+    #[test]
+    fn test_heap_usage() {
+        let wasm = wat::parse_str(r#"
+            (module
+                (memory 1)
+                (table 4 funcref)
+                (func (export "f") (result i32) i32.const 1)
+            )
+        "#).unwrap();
+        let mut store = AwwasmStore::new();
+        let addr = store.instantiate_bytes(&wasm, &mut AwwasmImports::new()).unwrap();
+        let before = store.heap_usage();
+        assert_eq!(before.memories, memory::PAGE_SIZE);
+        assert!(before.tables >= 4);
+        assert_eq!(before.code, 0);
+
+        store.invoke_export(addr, "f", &[], &mut NoHost).unwrap();
+        let after = store.heap_usage();
+        assert!(after.code > 0);
+        assert_eq!(after.total(), after.memories + after.tables + after.code);
+        assert_eq!(AwwasmRuntimeError::OutOfMemory { requested_bytes: 8 }.code(), 3013);
+    }
+
+    #[test]
+    fn test_stack_limits() {
+        let wasm = wat::parse_str(r#"
+            (module
+                (func $down (export "down") (param i32) (result i32)
+                    local.get 0
+                    if (result i32)
+                        local.get 0 i32.const 1 i32.sub call $down
+                    else
+                        i32.const 0
+                    end)
+                (func (export "wide")
+                    i32.const 1 i32.const 2 i32.const 3 i32.const 4 i32.const 5
+                    drop drop drop drop drop)
+            )
+        "#).unwrap();
+        let mut store = AwwasmStore::new();
+        assert_eq!(
+            store.stack_limits(),
+            AwwasmStackLimits {
+                call_depth: exec::MAX_CALL_DEPTH,
+                value_stack: exec::MAX_VALUE_STACK,
+                label_depth: exec::MAX_LABEL_DEPTH
+            }
+        );
+        let addr = store.instantiate_bytes(&wasm, &mut AwwasmImports::new()).unwrap();
+        store.set_stack_limits(AwwasmStackLimits { call_depth: 8, ..store.stack_limits() }).unwrap();
+        assert_eq!(store.invoke_export(addr, "down", &[7.into()], &mut NoHost).unwrap(), vec![AwwasmValue::I32(0)]);
+        let err = store.invoke_export(addr, "down", &[8.into()], &mut NoHost).unwrap_err();
+        assert_eq!(err.trap(), Some(&AwwasmTrap::CallStackExhausted));
+
+        store.set_stack_limits(AwwasmStackLimits { call_depth: 8, value_stack: 4, ..store.stack_limits() }).unwrap();
+        let err = store.invoke_export(addr, "wide", &[], &mut NoHost).unwrap_err();
+        assert_eq!(err.trap(), Some(&AwwasmTrap::StackOverflow));
+
+        // Open blocks count across frames: `down` opens one per call.
+        store.set_stack_limits(AwwasmStackLimits { label_depth: 4, ..AwwasmStackLimits::default() }).unwrap();
+        assert_eq!(store.invoke_export(addr, "down", &[3.into()], &mut NoHost).unwrap(), vec![AwwasmValue::I32(0)]);
+        let err = store.invoke_export(addr, "down", &[4.into()], &mut NoHost).unwrap_err();
+        assert_eq!(err.trap(), Some(&AwwasmTrap::CallStackExhausted));
+    }
+
+    #[test]
+    fn test_float_arithmetic() {
+        let wasm = wat::parse_str(r#"
+            (module
+                (func (export "f32_div") (param f32 f32) (result f32) local.get 0 local.get 1 f32.div)
+                (func (export "f64_fma") (param f64 f64 f64) (result f64)
+                    local.get 0 local.get 1 f64.mul local.get 2 f64.add)
+                (func (export "f64_sqrt") (param f64) (result f64) local.get 0 f64.sqrt)
+                (func (export "demote") (param f64) (result f32) local.get 0 f32.demote_f64)
+                (func (export "convert") (param i64) (result f32) local.get 0 f32.convert_i64_u)
+                (func (export "f32_ceil") (param f32) (result f32) local.get 0 f32.ceil)
+                (func (export "f64_nearest") (param f64) (result f64) local.get 0 f64.nearest)
+            )
+        "#).unwrap();
+        let mut store = AwwasmStore::new();
+        let addr = store.instantiate_bytes(&wasm, &mut AwwasmImports::new()).unwrap();
+        let mut call = |name: &str, args: &[AwwasmValue]| store.invoke_export(addr, name, args, &mut NoHost).unwrap()[0];
+
+        assert_eq!(call("f32_div", &[1.0f32.into(), 3.0f32.into()]), AwwasmValue::F32(1.0 / 3.0));
+        assert_eq!(call("f64_fma", &[0.1f64.into(), 3.0f64.into(), 1e-17f64.into()]), AwwasmValue::F64(0.1 * 3.0 + 1e-17));
+        assert_eq!(call("f64_sqrt", &[2.0f64.into()]), AwwasmValue::F64(core::f64::consts::SQRT_2));
+        assert_eq!(call("demote", &[1e300f64.into()]), AwwasmValue::F32(f32::INFINITY));
+        assert_eq!(call("demote", &[1e-46f64.into()]), AwwasmValue::F32(0.0));
+        assert_eq!(call("convert", &[AwwasmValue::I64(-1)]), AwwasmValue::F32(u64::MAX as f32));
+        let nan = call("f32_div", &[0.0f32.into(), 0.0f32.into()]).as_f32().unwrap();
+        assert!(nan.is_nan());
+        #[cfg(feature = "softfloat")]
+        assert_eq!(nan.to_bits(), 0x7fc0_0000);
+
+        assert_eq!(call("f32_ceil", &[1.25f32.into()]), AwwasmValue::F32(2.0));
+        assert_eq!(call("f64_nearest", &[2.5f64.into()]), AwwasmValue::F64(2.0));
+        // A NaN with a payload stays a NaN, and is canonical in software.
+        let nan = call("f32_ceil", &[f32::from_bits(0x7fa0_0001).into()]).as_f32().unwrap();
+        assert!(nan.is_nan());
+        #[cfg(feature = "softfloat")]
+        assert_eq!(nan.to_bits(), 0x7fc0_0000);
+        let nan = call("f64_nearest", &[f64::from_bits(0xfff0_0000_0000_0001).into()]).as_f64().unwrap();
+        assert!(nan.is_nan());
+        #[cfg(feature = "softfloat")]
+        assert_eq!(nan.to_bits(), 0x7ff8_0000_0000_0000);
+    }
+
+    #[test]
+    fn test_platform_deadline() {
+        use core::sync::atomic::{AtomicU64, Ordering};
+
+        /// Every reading advances the clock by one microsecond.
+        #[derive(Debug, Default)]
+        struct TickPlatform(AtomicU64);
+
+        impl AwwasmHostPlatform for TickPlatform {
+            fn monotonic_nanos(&self) -> u64 {
+                self.0.fetch_add(1_000, Ordering::SeqCst)
+            }
+
+            fn fill_entropy(&self, buf: &mut [u8]) -> bool {
+                buf.fill(4);
+                true
+            }
+        }
+
+        let wasm = wat::parse_str(r#"(module (func (export "spin") loop br 0 end))"#).unwrap();
+        let mut store = AwwasmStore::new();
+        let addr = store.instantiate_bytes(&wasm, &mut AwwasmImports::new()).unwrap();
+        let platform = alloc::sync::Arc::new(TickPlatform::default());
+        store.set_platform(platform.clone());
+        store.set_deadline(Some(50_000));
+
+        let err = store.invoke_export(addr, "spin", &[], &mut NoHost).unwrap_err();
+        assert_eq!(err.trap(), Some(&AwwasmTrap::Interrupted));
+        assert!(platform.0.load(Ordering::SeqCst) >= 50_000);
+        assert_eq!(platform.realtime_nanos(), None);
+    }
+
+    #[test]
+    fn test_code_cache_limit() {
+        let wasm: &'static [u8] = Vec::leak(wat::parse_str(r#"
+            (module
+                (func $sq (param i32) (result i32) local.get 0 local.get 0 i32.mul)
+                (func (export "f") (param i32) (result i32) local.get 0 call $sq)
+            )
+        "#).unwrap());
+        let mut store = AwwasmStore::new();
+        store.set_code_cache_limit(Some(0));
+        let addr = store.instantiate_bytes(wasm, &mut AwwasmImports::new()).unwrap();
+        for _ in 0..2 {
+            assert_eq!(store.invoke_export(addr, "f", &[7.into()], &mut NoHost).unwrap(), vec![AwwasmValue::I32(49)]);
+        }
+        assert_eq!(store.code_cache_bytes(), 0);
+        assert_eq!(store.heap_usage().code, 0);
+
+        store.set_code_cache_limit(None);
+        store.invoke_export(addr, "f", &[7.into()], &mut NoHost).unwrap();
+        assert!(store.code_cache_bytes() > 0);
+        assert_eq!(store.code_cache_bytes(), store.heap_usage().code);
+    }
+
+    #[test]
+    fn test_import_type_mismatch_diagnostics() {
+        let wasm = wat::parse_str(r#"
+            (module
+                (import "env" "f" (func (param i32) (result i32)))
+                (import "env" "g" (global i32))
+            )
+        "#).unwrap();
+        let mut module = AwwasmModule::new(&wasm).unwrap();
+        module.resolve_all_sections().unwrap();
+
+        let mut store = AwwasmStore::new();
+        let mut imports = AwwasmImports::new();
+        imports.add_memory(b"env", b"f", AwwasmMemInst::new(AwwasmMemoryType::new(1, None)));
+        assert_eq!(
+            store.store_init(&module, &mut imports),
+            Err(AwwasmInstantiationError::ImportTypeMismatch {
+                module: "env".into(),
+                name: "f".into(),
+                expected: "(func (param i32) (result i32))".into(),
+                got: "memory".into(),
+            })
+        );
+
+        let mut imports = AwwasmImports::new();
+        imports.add_func(b"env", b"f", AwwasmFuncInst::host(0, 0));
+        imports.add_global(b"env", b"g", AwwasmGlobalInst::new(AwwasmGlobalType::mutable(AwwasmValueType::I64), AwwasmValue::I64(0)));
+        assert_eq!(
+            store.store_init(&module, &mut imports),
+            Err(AwwasmInstantiationError::ImportTypeMismatch {
+                module: "env".into(),
+                name: "g".into(),
+                expected: "(global i32)".into(),
+                got: "(global (mut i64))".into(),
+            })
+        );
+    }
+
+    #[test]
+    fn test_export_index_out_of_bounds() {
+        // (export "f" (func 3)) in a module without functions
+        let wasm = [0x00, 0x61, 0x73, 0x6d, 0x01, 0x00, 0x00, 0x00, 0x07, 0x05, 0x01, 0x01, b'f', 0x00, 0x03];
+        let mut module = AwwasmModule::new(&wasm).unwrap();
+        module.resolve_all_sections().unwrap();
+        let mut store = AwwasmStore::new();
+        assert_eq!(
+            store.store_init(&module, &mut AwwasmImports::new()),
+            Err(AwwasmInstantiationError::ExportIndexOutOfBounds {
+                kind: AwwasmExternKind::Func,
+                name: "f".into(),
+                index: 3,
+                count: 0,
+            })
+        );
+    }
+
+    #[test]
+    fn test_invoke_host_function_and_reactor() {
+        struct Counter {
+            total: i32,
+        }
+
+        impl AwwasmHost for Counter {
+            fn call(
+                &mut self,
+                host_func_id: u32,
+                args: &[AwwasmValue],
+                mem: Option<&mut AwwasmMemInst>,
+            ) -> Result<Vec<AwwasmValue>, AwwasmTrap> {
+                assert_eq!(host_func_id, 7);
+                assert!(mem.is_some());
+                self.total += args[0].as_i32().unwrap();
+                Ok(vec![AwwasmValue::I32(self.total)])
+            }
+        }
+
+        let wasm = wat::parse_str(r#"
+            (module
+                (import "env" "add" (func $add (param i32) (result i32)))
+                (memory 1)
+                (global $ready (mut i32) (i32.const 0))
+                (func (export "_initialize") i32.const 1 global.set $ready)
+                (func (export "add_twice") (param i32) (result i32)
+                    local.get 0 call $add drop
+                    local.get 0 call $add
+                    global.get $ready i32.mul)
+            )
+        "#).unwrap();
+        let mut module = AwwasmModule::new(&wasm).unwrap();
+        module.resolve_all_sections().unwrap();
+        let mut store = AwwasmStore::new();
+        let mut imports = AwwasmImports::new();
+        imports.add_func(b"env", b"add", AwwasmFuncInst::host(0, 7));
+        let addr = store.store_init(&module, &mut imports).unwrap();
+
+        let mut host = Counter { total: 0 };
+        let reactor = AwwasmReactor::initialize(&mut store, addr, &mut host).unwrap();
+        assert_eq!(reactor.call(&mut store, "add_twice", &[AwwasmValue::I32(5)], &mut host).unwrap(), vec![AwwasmValue::I32(10)]);
+        assert_eq!(host.total, 10);
+        assert_eq!(
+            reactor.call(&mut store, "missing", &[], &mut host),
+            Err(AwwasmRuntimeError::ExportNotFound("missing".into()))
+        );
+    }
+
+    #[test]
+    fn test_host_backed_globals() {
+        struct Clock {
+            tick: i64,
+        }
+
+        impl AwwasmHost for Clock {
+            fn call(&mut self, _: u32, _: &[AwwasmValue], _: Option<&mut AwwasmMemInst>) -> Result<Vec<AwwasmValue>, AwwasmTrap> {
+                unreachable!()
+            }
+
+            fn get_global(&mut self, host_global_id: u32, value_type: AwwasmValueType) -> Result<AwwasmValue, AwwasmTrap> {
+                assert_eq!((host_global_id, value_type), (3, AwwasmValueType::I64));
+                Ok(AwwasmValue::I64(self.tick))
+            }
+
+            fn set_global(&mut self, host_global_id: u32, value: AwwasmValue) -> Result<(), AwwasmTrap> {
+                assert_eq!(host_global_id, 3);
+                self.tick = value.expect_i64();
+                Ok(())
+            }
+        }
+
+        let wasm = wat::parse_str(r#"
+            (module
+                (import "env" "tick" (global $tick (mut i64)))
+                (func (export "advance") (result i64)
+                    global.get $tick i64.const 10 i64.add global.set $tick
+                    global.get $tick)
+            )
+        "#).unwrap();
+        let mut store = AwwasmStore::new();
+        let mut imports = AwwasmImports::builder()
+            .global("env", "tick", AwwasmGlobalInst::host(AwwasmGlobalType::mutable(AwwasmValueType::I64), 3))
+            .build();
+        let addr = store.instantiate_bytes(&wasm, &mut imports).unwrap();
+
+        let mut clock = Clock { tick: 5 };
+        assert_eq!(store.invoke_export(addr, "advance", &[], &mut clock).unwrap(), vec![AwwasmValue::I64(15)]);
+        clock.tick = 100;
+        assert_eq!(store.invoke_export(addr, "advance", &[], &mut clock).unwrap(), vec![AwwasmValue::I64(110)]);
+        assert_eq!(clock.tick, 110);
+
+        // Hosts without global support trap on access.
+        assert!(matches!(
+            store.invoke_export(addr, "advance", &[], &mut NoHost).unwrap_err().trap(),
+            Some(AwwasmTrap::InvalidHostCall { host_func_id: 3 })
+        ));
+    }
+
+    #[test]
+    fn test_host_trap_payload() {
+        use alloc::string::ToString;
+        use core::error::Error;
+
+        #[derive(Debug, PartialEq)]
+        struct QuotaExceeded {
+            used: u32,
+        }
+
+        impl core::fmt::Display for QuotaExceeded {
+            fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+                write!(f, "quota exceeded after {} calls", self.used)
+            }
+        }
+
+        impl core::error::Error for QuotaExceeded {}
+
+        struct Quota {
+            used: u32,
+        }
+
+        impl AwwasmHost for Quota {
+            fn call(
+                &mut self,
+                _host_func_id: u32,
+                _args: &[AwwasmValue],
+                _mem: Option<&mut AwwasmMemInst>,
+            ) -> Result<Vec<AwwasmValue>, AwwasmTrap> {
+                self.used += 1;
+                if self.used > 2 {
+                    return Err(AwwasmTrap::host(QuotaExceeded { used: self.used }));
+                }
+                Ok(Vec::new())
+            }
+        }
+
+        let wasm = wat::parse_str(r#"
+            (module
+                (import "env" "tick" (func $tick))
+                (func (export "run") loop call $tick br 0 end)
+            )
+        "#).unwrap();
+        let mut module = AwwasmModule::new(&wasm).unwrap();
+        module.resolve_all_sections().unwrap();
+        let mut store = AwwasmStore::new();
+        let mut imports = AwwasmImports::new();
+        imports.add_func(b"env", b"tick", AwwasmFuncInst::host(0, 0));
+        let addr = store.store_init(&module, &mut imports).unwrap();
+
+        let run = export_func(&store, addr, "run");
+        let err = store.invoke(run, &[], &mut Quota { used: 0 }).unwrap_err();
+        let trap = err.trap().unwrap();
+        assert_eq!(trap.downcast_ref::<QuotaExceeded>(), Some(&QuotaExceeded { used: 3 }));
+        assert_eq!(trap.to_string(), "host error: quota exceeded after 3 calls");
+        assert_eq!(err.source().unwrap().to_string(), "quota exceeded after 3 calls");
+
+        // Traps sharing a payload compare equal; separate payloads do not.
+        assert_eq!(trap.clone(), *trap);
+        assert_ne!(AwwasmTrap::host("quota exceeded"), AwwasmTrap::host("quota exceeded"));
+        assert!(AwwasmTrap::host("quota exceeded").downcast_ref::<QuotaExceeded>().is_none());
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn test_host_panic_is_trapped() {
+        struct Flaky {
+            calls: u32,
+        }
+
+        impl AwwasmHost for Flaky {
+            fn call(
+                &mut self,
+                _host_func_id: u32,
+                _args: &[AwwasmValue],
+                _mem: Option<&mut AwwasmMemInst>,
+            ) -> Result<Vec<AwwasmValue>, AwwasmTrap> {
+                self.calls += 1;
+                if self.calls == 1 {
+                    panic!("callback bug");
+                }
+                Ok(vec![AwwasmValue::I32(7)])
+            }
+        }
+
+        let wasm = wat::parse_str(r#"
+            (module
+                (import "env" "get" (func $get (result i32)))
+                (func (export "run") (result i32) call $get)
+            )
+        "#).unwrap();
+        let mut module = AwwasmModule::new(&wasm).unwrap();
+        module.resolve_all_sections().unwrap();
+        let mut store = AwwasmStore::new();
+        let mut imports = AwwasmImports::new();
+        imports.add_func(b"env", b"get", AwwasmFuncInst::host(0, 0));
+        let addr = store.store_init(&module, &mut imports).unwrap();
+
+        let run = export_func(&store, addr, "run");
+        let mut host = Flaky { calls: 0 };
+        let err = store.invoke(run, &[], &mut host).unwrap_err();
+        assert_eq!(
+            err.trap().unwrap().downcast_ref::<AwwasmHostPanic>(),
+            Some(&AwwasmHostPanic { message: Some("callback bug".into()) })
+        );
+
+        // The store is still usable afterwards.
+        assert_eq!(store.invoke(run, &[], &mut host).unwrap(), vec![AwwasmValue::I32(7)]);
+    }
+
+    #[test]
+    fn test_high_level_api() {
+        use api::{Engine, Instance, Module, Store};
+
+        let wasm = wat::parse_str(r#"
+            (module
+                (memory (export "memory") 1 2)
+                (func (export "add") (param i32 i32) (result i32)
+                    local.get 0 local.get 1 i32.add)
+                (func (export "load") (param i32) (result i32)
+                    local.get 0 i32.load)
+            )
+        "#).unwrap();
+        let engine = Engine::default();
+        let module = Module::new(&engine, &wasm).unwrap();
+        assert_eq!(module.info().exports.len(), 3);
+
+        let mut store = Store::new(&engine, NoHost);
+        let instance = Instance::new(&mut store, &module, &mut AwwasmImports::new()).unwrap();
+        let add = instance.get_func(&store, "add").unwrap();
+        assert_eq!(add.ty(&store).unwrap().params.len(), 2);
+        assert_eq!(add.call(&mut store, &[AwwasmValue::I32(2), AwwasmValue::I32(40)]).unwrap(), vec![AwwasmValue::I32(42)]);
+        assert!(instance.get_func(&store, "memory").is_none());
+
+        let memory = instance.get_memory(&store, "memory").unwrap();
+        memory.write(&mut store, 8, &7i32.to_le_bytes()).unwrap();
+        let load = instance.get_func(&store, "load").unwrap();
+        assert_eq!(load.call(&mut store, &[AwwasmValue::I32(8)]).unwrap(), vec![AwwasmValue::I32(7)]);
+        let mut buf = [0; 4];
+        memory.read(&store, 8, &mut buf).unwrap();
+        assert_eq!(buf, 7i32.to_le_bytes());
+
+        assert_eq!(memory.grow(&mut store, 1).unwrap(), Some(1));
+        assert_eq!(memory.grow(&mut store, 1).unwrap(), None);
+        assert_eq!(memory.size(&store).unwrap(), 2);
+        assert_eq!(memory.data(&store).unwrap().unwrap().len(), 2 * 65536);
+
+        // The low-level API sees the same instance.
+        assert_eq!(store.as_raw().module_count(), 1);
+        assert_eq!(store.as_raw().module(instance.as_raw()).unwrap().get_func("add"), Some(add.as_raw()));
+    }
+
+    #[cfg(feature = "spectest")]
+    #[test]
+    fn test_wast_runner() {
+        let mut runner = AwwasmWastRunner::new(NoHost);
+        let report = runner.run(r#"
+            (module $math
+                (func (export "add") (param i32 i32) (result i32)
+                    local.get 0 local.get 1 i32.add)
+                (func (export "div") (param i32 i32) (result i32)
+                    local.get 0 local.get 1 i32.div_s)
+                (func (export "nan") (result f32)
+                    f32.const 0 f32.const 0 f32.div))
+            (register "math" $math)
+            (assert_return (invoke "add" (i32.const 2) (i32.const 40)) (i32.const 42))
+            (assert_return (invoke "nan") (f32.const nan:canonical))
+            (assert_trap (invoke "div" (i32.const 1) (i32.const 0)) "integer divide by zero")
+
+            (module
+                (import "math" "add" (func $add (param i32 i32) (result i32)))
+                (func (export "inc") (param i32) (result i32)
+                    local.get 0 i32.const 1 call $add))
+            (assert_return (invoke "inc" (i32.const 1)) (i32.const 2))
+            (assert_return (invoke $math "add" (i32.const 1) (i32.const 1)) (i32.const 2))
+            (assert_unlinkable (module (import "math" "missing" (func))) "unknown import")
+
+            (assert_return (invoke "inc" (i32.const 1)) (i32.const 3))
+        "#).unwrap();
+
+        assert_eq!(report.passed, 9);
+        assert_eq!(report.skipped, 0);
+        assert_eq!(report.failures.len(), 1);
+        assert_eq!(report.failures[0].line, 22);
+        assert!(!report.is_success());
+
+        assert_eq!(runner.run("(module").unwrap_err().line, 1);
+    }
+
+    #[test]
+    fn test_spectest_imports() {
+        let wasm = wat::parse_str(r#"
+            (module
+                (import "spectest" "print_i32_f32" (func $print (param i32 f32)))
+                (import "spectest" "global_i32" (global $g i32))
+                (import "spectest" "memory" (memory 1))
+                (import "spectest" "table" (table 10 funcref))
+                (func (export "run") (result i32)
+                    global.get $g f32.const 1.5 call $print
+                    i32.const 0 i32.load
+                    memory.size i32.add)
+            )
+        "#).unwrap();
+        let mut store = AwwasmStore::new();
+        let mut spectest = AwwasmSpectest::new();
+        let mut imports = AwwasmImports::new();
+        spectest.add_to_imports(&mut store, &mut imports);
+        let addr = store.instantiate_bytes(&wasm, &mut imports).unwrap();
+
+        assert_eq!(store.invoke_export(addr, "run", &[], &mut spectest).unwrap(), vec![AwwasmValue::I32(1)]);
+        assert_eq!(spectest.take_printed(), vec![vec![AwwasmValue::I32(666), AwwasmValue::F32(1.5)]]);
+        let table = store.module(addr).unwrap().table(0).unwrap();
+        assert_eq!(store.table(table).unwrap().elem.len(), 10);
+
+        // As a module of its own, its exports can be looked up by name.
+        let module = spectest.instantiate(&mut store);
+        let global = store.module(module).unwrap().get_global("global_f64").unwrap();
+        assert_eq!(store.get_global_f64(global).unwrap(), 666.6);
+        assert!(store.module(module).unwrap().get_memory("memory").is_some());
+    }
+
+    #[cfg(feature = "spectest")]
+    #[test]
+    fn test_wast_runner_spectest() {
+        let mut runner = AwwasmWastRunner::with_spectest();
+        let report = runner.run(r#"
+            (module
+                (import "spectest" "print_i64" (func $print (param i64)))
+                (import "spectest" "global_i64" (global i64))
+                (func (export "print") (param i64) local.get 0 call $print)
+                (func (export "get") (result i64) global.get 0))
+            (invoke "print" (i64.const 7))
+            (assert_return (invoke "get") (i64.const 666))
+        "#).unwrap();
+        assert!(report.is_success(), "{:?}", report.failures);
+        assert_eq!(report.passed, 3);
+        assert_eq!(runner.host_mut().take_printed(), vec![vec![AwwasmValue::I64(7)]]);
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_store_snapshot() {
+        let wasm = wat::parse_str(r#"
+            (module
+                (memory (export "memory") 1 4)
+                (global $count (mut i32) (i32.const 0))
+                (table 2 funcref)
+                (elem $e func $bump)
+                (data $d "hi")
+                (func $bump (export "bump") (result i32)
+                    global.get $count i32.const 1 i32.add global.set $count
+                    i32.const 0 global.get $count i32.store
+                    global.get $count)
+                (func (export "grow") (result i32) i32.const 1 memory.grow)
+                (func (export "drop") data.drop $d)
+            )
+        "#).unwrap();
+        let mut store = AwwasmStore::new();
+        let addr = store.instantiate_bytes(&wasm, &mut AwwasmImports::new()).unwrap();
+        store.invoke_export(addr, "bump", &[], &mut NoHost).unwrap();
+        store.invoke_export(addr, "bump", &[], &mut NoHost).unwrap();
+        store.invoke_export(addr, "grow", &[], &mut NoHost).unwrap();
+        store.invoke_export(addr, "drop", &[], &mut NoHost).unwrap();
+
+        let json = serde_json::to_string(&store.snapshot().unwrap()).unwrap();
+        let snapshot: AwwasmStoreSnapshot = serde_json::from_str(&json).unwrap();
+
+        let mut restored = AwwasmStore::new();
+        let addr = restored.instantiate_bytes(&wasm, &mut AwwasmImports::new()).unwrap();
+        restored.restore(&snapshot).unwrap();
+        let memory = restored.module(addr).unwrap().get_memory("memory").unwrap();
+        assert_eq!(restored.mem(memory).unwrap().size_pages(), 2);
+        assert_eq!(restored.mem(memory).unwrap().read_i32(0).unwrap(), 2);
+        assert!(restored.datas.iter().all(|d| d.dropped));
+        assert_eq!(restored.invoke_export(addr, "bump", &[], &mut NoHost).unwrap(), vec![AwwasmValue::I32(3)]);
+
+        // A store holding another module is rejected and left alone.
+        let other = wat::parse_str(r#"(module (memory 1))"#).unwrap();
+        let mut wrong = AwwasmStore::new();
+        wrong.instantiate_bytes(&other, &mut AwwasmImports::new()).unwrap();
+        let err = wrong.restore(&snapshot).unwrap_err();
+        assert_eq!(err.code(), 3014);
+        assert_eq!(wrong.mems[0].size_pages(), 1);
+    }
+
+    #[test]
+    fn test_binary_snapshot() {
+        let wasm = wat::parse_str(r#"
+            (module
+                (memory (export "memory") 4)
+                (global $g (mut i64) (i64.const -7))
+                (global $f (mut f64) (f64.const 1.5))
+                (table 1 funcref)
+                (elem (i32.const 0) $fill)
+                (func $fill (export "fill")
+                    i32.const 100 i32.const 0xab i32.const 5000 memory.fill
+                    i32.const 70000 i32.const 42 i32.store)
+            )
+        "#).unwrap();
+        let mut store = AwwasmStore::new();
+        let addr = store.instantiate_bytes(&wasm, &mut AwwasmImports::new()).unwrap();
+        store.invoke_export(addr, "fill", &[], &mut NoHost).unwrap();
+
+        let snapshot = store.snapshot().unwrap();
+        let bytes = snapshot.to_bytes();
+        // Four pages of mostly zeroes and one long run compress to little.
+        assert!(bytes.len() < 512, "{} bytes", bytes.len());
+        assert_eq!(AwwasmStoreSnapshot::from_bytes(&bytes).unwrap(), snapshot);
+
+        let mut restored = AwwasmStore::new();
+        let addr = restored.instantiate_bytes(&wasm, &mut AwwasmImports::new()).unwrap();
+        restored.restore(&AwwasmStoreSnapshot::from_bytes(&bytes).unwrap()).unwrap();
+        let memory = restored.module(addr).unwrap().get_memory("memory").unwrap();
+        assert_eq!(restored.mem(memory).unwrap().read_i32(70000).unwrap(), 42);
+        assert_eq!(restored.mem(memory).unwrap().read(100, 5000).unwrap(), &[0xab; 5000][..]);
+
+        // Corruption, truncation and trailing bytes are all rejected.
+        let mut corrupt = bytes.clone();
+        let last = corrupt.len() - 1;
+        corrupt[last] ^= 1;
+        assert_eq!(AwwasmStoreSnapshot::from_bytes(&corrupt).unwrap_err().code(), 3015);
+        assert_eq!(AwwasmStoreSnapshot::from_bytes(&bytes[..bytes.len() - 1]).unwrap_err().code(), 3015);
+        let mut trailing = bytes.clone();
+        trailing.push(0);
+        assert_eq!(AwwasmStoreSnapshot::from_bytes(&trailing).unwrap_err().code(), 3015);
+    }
+
+    #[test]
+    fn test_pre_initialize() {
+        let wasm = wat::parse_str(r#"
+            (module
+                (memory (export "memory") 1 4)
+                (global $ready (export "ready") (mut i32) (i32.const 0))
+                (global $table (mut i64) (i64.const 0))
+                (data "seed")
+                (data $passive "tail")
+                (func (export "wizer.initialize")
+                    (drop (memory.grow (i32.const 1)))
+                    (i32.store (i32.const 70000) (i32.const 0x01020304))
+                    (i64.store (i32.const 8) (i64.const -1))
+                    (global.set $table (i64.const 12345))
+                    (global.set $ready (i32.const 1)))
+                (func (export "check") (result i64)
+                    (i64.add (global.get $table) (i64.load (i32.const 8))))
+                (func (export "init_tail") (memory.init $passive (i32.const 100) (i32.const 0) (i32.const 4)))
+            )
+        "#).unwrap();
+        let initialized = pre_initialize(&wasm, preinit::PREINIT_EXPORT, &mut AwwasmImports::new(), &mut NoHost).unwrap();
+
+        let mut store = AwwasmStore::new();
+        let addr = store.instantiate_bytes(&initialized, &mut AwwasmImports::new()).unwrap();
+        let module = store.module(addr).unwrap();
+        let memory = module.get_memory("memory").unwrap();
+        let ready = module.get_global("ready").unwrap();
+        assert_eq!(store.get_global_i32(ready).unwrap(), 1);
+        let mem = store.mem(memory).unwrap();
+        assert_eq!(mem.size_pages(), 2);
+        assert_eq!(&*mem.read(0, 4).unwrap(), b"seed");
+        assert_eq!(mem.read_i32(70000).unwrap(), 0x01020304);
+        assert_eq!(store.invoke_export(addr, "check", &[], &mut NoHost).unwrap(), vec![AwwasmValue::I64(12344)]);
+        // Passive segments keep their indices.
+        store.invoke_export(addr, "init_tail", &[], &mut NoHost).unwrap();
+        assert_eq!(&*store.mem(memory).unwrap().read(100, 4).unwrap(), b"tail");
+        // The initializer is gone, so it cannot run twice.
+        assert!(matches!(
+            store.invoke_export(addr, preinit::PREINIT_EXPORT, &[], &mut NoHost),
+            Err(AwwasmRuntimeError::ExportNotFound(_))
+        ));
+
+        // An imported memory belongs to the embedder and is refused.
+        let imports_memory = wat::parse_str(r#"(module (import "env" "memory" (memory 1)) (func (export "init")))"#).unwrap();
+        let mut imports = AwwasmImports::new();
+        imports.add_memory(b"env", b"memory", AwwasmMemInst::new(AwwasmMemoryType::new(1, None)));
+        let err = pre_initialize(&imports_memory, "init", &mut imports, &mut NoHost).unwrap_err();
+        assert_eq!(err.code(), 1007);
+
+        // So is a 64-bit memory.
+        let memory64 = wat::parse_str(r#"(module (memory i64 1) (func (export "init")))"#).unwrap();
+        assert!(pre_initialize(&memory64, "init", &mut AwwasmImports::new(), &mut NoHost).is_err());
+    }
+
+    #[test]
+    fn test_instance_migration() {
+        let wasm = wat::parse_str(r#"
+            (module
+                (memory (export "memory") 1 4)
+                (global $count (mut i32) (i32.const 0))
+                (global $last (mut funcref) (ref.null func))
+                (table 2 funcref)
+                (elem (i32.const 1) $bump)
+                (func $bump (export "bump") (result i32)
+                    global.get $count i32.const 1 i32.add global.set $count
+                    i32.const 0 global.get $count i32.store
+                    ref.func $bump global.set $last
+                    global.get $count)
+                (func (export "grow") (result i32) i32.const 1 memory.grow)
+            )
+        "#).unwrap();
+        let other = wat::parse_str(r#"(module (memory 1) (func (export "nop")))"#).unwrap();
+
+        let mut source = AwwasmStore::new();
+        let addr = source.instantiate_bytes(&wasm, &mut AwwasmImports::new()).unwrap();
+        source.invoke_export(addr, "bump", &[], &mut NoHost).unwrap();
+        source.invoke_export(addr, "bump", &[], &mut NoHost).unwrap();
+        source.invoke_export(addr, "grow", &[], &mut NoHost).unwrap();
+        let bytes = source.extract_instance(addr).unwrap().to_bytes();
+
+        // The target store already holds something else, so every
+        // address differs from the source's.
+        let mut target = AwwasmStore::new();
+        target.instantiate_bytes(&other, &mut AwwasmImports::new()).unwrap();
+        let moved = target.instantiate_bytes(&wasm, &mut AwwasmImports::new()).unwrap();
+        target.rehydrate_instance(moved, &AwwasmInstanceSnapshot::from_bytes(&bytes).unwrap()).unwrap();
+        let memory = target.module(moved).unwrap().get_memory("memory").unwrap();
+        assert_eq!(target.mem(memory).unwrap().size_pages(), 2);
+        assert_eq!(target.mem(memory).unwrap().read_i32(0).unwrap(), 2);
+        let module = target.module(moved).unwrap();
+        let bump = module.get_func("bump").unwrap();
+        assert_eq!(target.table(module.tableaddrs[0]).unwrap().elem[1], Some(bump));
+        assert_eq!(target.get_global(module.globaladdrs[1]).unwrap(), AwwasmValue::FuncRef(Some(bump)));
+        assert_eq!(target.invoke_export(moved, "bump", &[], &mut NoHost).unwrap(), vec![AwwasmValue::I32(3)]);
+
+        // An instance of another module is rejected.
+        let mut wrong = AwwasmStore::new();
+        let addr = wrong.instantiate_bytes(&other, &mut AwwasmImports::new()).unwrap();
+        let snapshot = AwwasmInstanceSnapshot::from_bytes(&bytes).unwrap();
+        assert_eq!(wrong.rehydrate_instance(addr, &snapshot).unwrap_err().code(), 3014);
+        assert_eq!(AwwasmStoreSnapshot::from_bytes(&bytes).unwrap_err().code(), 3015);
+    }
+
+    #[cfg(feature = "differential")]
+    #[test]
+    fn test_differential_harness() {
+        use differential::{diff_exports, AwwasmDiffHarness, AwwasmDiffOutcome};
+
+        let wasm = wat::parse_str(r#"
+            (module
+                (memory (export "memory") 1)
+                (global (export "count") (mut i32) (i32.const 0))
+                (func (export "div") (param i32 i32) (result i32) local.get 0 local.get 1 i32.div_s)
+                (func (export "nan") (result f32) f32.const nan f32.const 1 f32.add)
+                (func (export "store") (i32.store (i32.const 16) (i32.const 0x11223344)))
+                (func $recurse (export "recurse") (call $recurse))
+            )
+        "#).unwrap();
+        let mut harness = AwwasmDiffHarness::new(&wasm).unwrap();
+        assert_eq!(
+            harness.call("div", &[AwwasmValue::I32(7), AwwasmValue::I32(2)]).unwrap(),
+            AwwasmDiffOutcome::Returned(vec![AwwasmValue::I32(3)])
+        );
+        assert_eq!(
+            harness.call("div", &[AwwasmValue::I32(7), AwwasmValue::I32(0)]).unwrap(),
+            AwwasmDiffOutcome::Trapped(TrapKind::DivisionByZero)
+        );
+        assert!(matches!(harness.call("nan", &[]).unwrap(), AwwasmDiffOutcome::Returned(_)));
+        harness.call("store", &[]).unwrap();
+        harness.compare_state().unwrap();
+
+        let outcomes = diff_exports(&wasm).unwrap();
+        assert!(matches!(outcomes.last(), Some(AwwasmDiffOutcome::Skipped(_))));
+    }
+
+    #[test]
+    fn test_value_parse_display() {
+        use alloc::string::ToString;
+
+        assert_eq!(AwwasmValue::parse(AwwasmValueType::I32, "-1"), Some(AwwasmValue::I32(-1)));
+        assert_eq!(AwwasmValue::parse(AwwasmValueType::I32, "4294967295"), Some(AwwasmValue::I32(-1)));
+        assert_eq!(AwwasmValue::parse(AwwasmValueType::I32, "0x7fffffff"), Some(AwwasmValue::I32(i32::MAX)));
+        assert_eq!(AwwasmValue::parse(AwwasmValueType::I32, "4294967296"), None);
+        assert_eq!(AwwasmValue::parse(AwwasmValueType::I64, "-9223372036854775808"), Some(AwwasmValue::I64(i64::MIN)));
+        assert_eq!(AwwasmValue::parse(AwwasmValueType::F64, "1.5"), Some(AwwasmValue::F64(1.5)));
+        assert!(AwwasmValue::parse(AwwasmValueType::F32, "nan").unwrap().as_f32().unwrap().is_nan());
+        assert_eq!(AwwasmValue::parse(AwwasmValueType::FuncRef, "null"), Some(AwwasmValue::FuncRef(None)));
+        assert_eq!(AwwasmValue::parse(AwwasmValueType::ExternRef, "ref.extern 7"), Some(AwwasmValue::ExternRef(Some(7))));
+        assert_eq!(AwwasmValue::parse(AwwasmValueType::I32, "seven"), None);
+
+        let values = [
+            AwwasmValue::I32(-5),
+            AwwasmValue::I64(1 << 40),
+            AwwasmValue::F32(-0.25),
+            AwwasmValue::V128(0xabc),
+            AwwasmValue::ExternRef(None),
+        ];
+        for value in values {
+            assert_eq!(AwwasmValue::parse(value.value_type(), &value.to_string()), Some(value));
+        }
+        assert_eq!(AwwasmValue::V128(1).to_string(), "0x00000000000000000000000000000001");
+    }
+
+    #[test]
+    fn test_repl() {
+        let wasm = wat::parse_str(r#"
+            (module
+                (memory (export "memory") 1 4)
+                (global (export "total") (mut i32) (i32.const 0))
+                (func (export "add") (param i32 i32) (result i32)
+                    (global.set 0 (i32.add (local.get 0) (local.get 1)))
+                    (i32.store (i32.const 0) (global.get 0))
+                    (global.get 0))
+            )
+        "#).unwrap();
+        let mut store = AwwasmStore::new();
+        let addr = store.instantiate_bytes(&wasm, &mut AwwasmImports::new()).unwrap();
+        let mut host = NoHost;
+        let mut repl = AwwasmRepl::new(&mut store, addr, &mut host);
+
+        let exports = repl.eval("exports").unwrap();
+        assert!(exports.contains(r#"(export "add" (func (param i32 i32) (result i32)))"#), "{}", exports);
+        assert!(exports.contains(r#"(export "memory" (memory 1 4)) ;; 1 pages"#), "{}", exports);
+        assert_eq!(repl.eval("add 40 2").unwrap(), "42");
+        assert_eq!(repl.eval("call add 0x10 -1").unwrap(), "15");
+        assert_eq!(repl.eval("global total").unwrap(), "15");
+        assert!(repl.eval("mem 0 4").unwrap().starts_with("00000000: 0f 00 00 00"));
+        assert!(repl.eval("mem memory 0x10 2").unwrap().starts_with("00000010: 00 00"));
+        assert!(repl.eval("add 1").unwrap_err().contains("takes 2 arguments"));
+        assert!(repl.eval("add one two").is_err());
+        assert!(repl.eval("nope").is_err());
+
+        let mut output = Vec::new();
+        repl.run(&b"add 1 1\nquit\nadd 2 2\n"[..], &mut output).unwrap();
+        assert_eq!(String::from_utf8(output).unwrap(), "> 2\n> ");
+    }
+
+    #[test]
+    fn test_trace_recorder() {
+        use core::sync::atomic::{AtomicU64, Ordering};
+        use profile::AwwasmTracePhase::{Enter, Exit};
+
+        /// Every reading advances the clock by 1.5 microseconds.
+        #[derive(Debug, Default)]
+        struct TickPlatform(AtomicU64);
+
+        impl AwwasmHostPlatform for TickPlatform {
+            fn monotonic_nanos(&self) -> u64 {
+                self.0.fetch_add(1_500, Ordering::SeqCst)
+            }
+
+            fn fill_entropy(&self, _buf: &mut [u8]) -> bool {
+                false
+            }
+        }
+
+        let wasm = wat::parse_str(r#"
+            (module
+                (func $inner (result i32) i32.const 1)
+                (func (export "outer") (result i32) call $inner call $inner i32.add)
+                (func (export "boom") call $inner drop unreachable)
+            )
+        "#).unwrap();
+        let mut store = AwwasmStore::new();
+        let addr = store.instantiate_bytes(&wasm, &mut AwwasmImports::new()).unwrap();
+        let funcs = store.modules[addr.0 as usize].funcaddrs.clone();
+        let recorder = alloc::sync::Arc::new(AwwasmTraceRecorder::new(alloc::sync::Arc::new(TickPlatform::default())));
+        store.set_profiler(Some(recorder.clone()));
+
+        assert_eq!(store.invoke_export(addr, "outer", &[], &mut NoHost).unwrap(), vec![AwwasmValue::I32(2)]);
+        let phases: Vec<_> = recorder.events().iter().map(|e| (e.func, e.phase)).collect();
+        let (inner, outer, boom) = (funcs[0], funcs[1], funcs[2]);
+        assert_eq!(phases, [(outer, Enter), (inner, Enter), (inner, Exit), (inner, Enter), (inner, Exit), (outer, Exit)]);
+
+        // A trap still closes every open call.
+        recorder.clear();
+        assert!(store.invoke_export(addr, "boom", &[], &mut NoHost).is_err());
+        let phases: Vec<_> = recorder.events().iter().map(|e| (e.func, e.phase)).collect();
+        assert_eq!(phases, [(boom, Enter), (inner, Enter), (inner, Exit), (boom, Exit)]);
+
+        let trace = recorder.to_chrome_trace(&store);
+        let json: serde_json::Value = serde_json::from_str(&trace).unwrap();
+        let events = json["traceEvents"].as_array().unwrap();
+        assert_eq!(events.len(), 4);
+        assert_eq!(events[0]["name"], "boom");
+        assert_eq!(events[0]["ph"], "B");
+        assert_eq!(events[1]["name"], format!("func[{}]", inner.0));
+        assert_eq!(events[3]["ph"], "E");
+        assert!(trace.contains(r#""ts":13.500"#), "{}", trace);
+
+        store.set_profiler(None);
+        recorder.clear();
+        store.invoke_export(addr, "outer", &[], &mut NoHost).unwrap();
+        assert!(recorder.events().is_empty());
+    }
+
+    #[test]
+    fn test_fuel_gas_table() {
+        let wasm = wat::parse_str(r#"
+            (module
+                (memory 1)
+                (func (export "add") (param i32 i32) (result i32)
+                    local.get 0 local.get 1 i32.add)
+                (func (export "grow") (param i32) (result i32)
+                    local.get 0 memory.grow)
+                (func (export "spin") loop br 0 end)
+            )
+        "#).unwrap();
+        let mut store = AwwasmStore::new();
+        let addr = store.instantiate_bytes(&wasm, &mut AwwasmImports::new()).unwrap();
+        let args = [AwwasmValue::I32(1), AwwasmValue::I32(2)];
+
+        // Unmetered by default.
+        store.invoke_export(addr, "add", &args, &mut NoHost).unwrap();
+        assert_eq!(store.fuel(), None);
+
+        // Two local.gets, i32.add and the body's end, at 1 each.
+        store.set_fuel(Some(10));
+        store.invoke_export(addr, "add", &args, &mut NoHost).unwrap();
+        assert_eq!(store.fuel(), Some(6));
+
+        let mut table = AwwasmGasTable::uniform(1);
+        table.set_op(0x6a, 3).set_memory_grow_page(100);
+        assert!(table.set_prefixed_op(10, 50).is_some());
+        assert!(table.set_prefixed_op(99, 50).is_none());
+        store.set_gas_table(alloc::sync::Arc::new(table));
+        store.set_fuel(Some(100));
+        store.invoke_export(addr, "add", &args, &mut NoHost).unwrap();
+        assert_eq!(store.fuel(), Some(94));
+
+        // local.get, memory.grow and 2 pages.
+        store.set_fuel(Some(1_000));
+        assert_eq!(store.invoke_export(addr, "grow", &[AwwasmValue::I32(2)], &mut NoHost).unwrap(), vec![AwwasmValue::I32(1)]);
+        assert_eq!(store.fuel(), Some(797));
+
+        // Running dry traps and leaves the remainder.
+        store.set_fuel(Some(4));
+        let err = store.invoke_export(addr, "add", &args, &mut NoHost).unwrap_err();
+        assert_eq!(err.trap(), Some(&AwwasmTrap::OutOfFuel));
+        assert_eq!(err.trap().unwrap().code(), 2015);
+        assert_eq!(store.fuel(), Some(2));
+
+        store.set_fuel(Some(1_000));
+        let err = store.invoke_export(addr, "spin", &[], &mut NoHost).unwrap_err();
+        assert_eq!(err.trap().map(AwwasmTrap::kind), Some(TrapKind::OutOfFuel));
+        assert_eq!(store.fuel(), Some(0));
+    }
+
+    #[test]
+    fn test_instrumented_metering() {
+        let wasm = wat::parse_str(r#"
+            (module
+                (func $double (param i32) (result i32) local.get 0 i32.const 2 i32.mul)
+                (func (export "sum") (param i32) (result i32) (local i32)
+                    block
+                        loop
+                            local.get 0
+                            i32.eqz
+                            br_if 1
+                            local.get 0
+                            i32.const 1
+                            i32.and
+                            if (result i32)
+                                local.get 0
+                                call $double
+                            else
+                                local.get 0
+                            end
+                            local.get 1
+                            i32.add
+                            local.set 1
+                            local.get 0
+                            i32.const -1
+                            i32.add
+                            local.set 0
+                            br 0
+                        end
+                    end
+                    local.get 1)
+            )
+        "#).unwrap();
+        let spent = |metering| {
+            let mut store = AwwasmStore::new();
+            let addr = store.instantiate_bytes(&wasm, &mut AwwasmImports::new()).unwrap();
+            let mut table = AwwasmGasTable::uniform(2);
+            table.set_op(0x10, 25).set_op(0x6c, 7);
+            store.set_gas_table(alloc::sync::Arc::new(table));
+            store.set_metering(metering);
+            store.set_fuel(Some(1_000_000));
+            let result = store.invoke_export(addr, "sum", &[AwwasmValue::I32(10)], &mut NoHost).unwrap();
+            assert_eq!(result, vec![AwwasmValue::I32(80)]);
+            1_000_000 - store.fuel().unwrap()
+        };
+        // Charging whole runs up front adds up to the same total.
+        let per_instruction = spent(AwwasmMetering::PerInstruction);
+        assert!(per_instruction > 0);
+        assert_eq!(spent(AwwasmMetering::Instrumented), per_instruction);
+
+        let wasm = wat::parse_str(r#"(module (func (export "spin") loop br 0 end))"#).unwrap();
+        let mut store = AwwasmStore::new();
+        let addr = store.instantiate_bytes(&wasm, &mut AwwasmImports::new()).unwrap();
+        store.set_metering(AwwasmMetering::Instrumented);
+        store.set_fuel(Some(100));
+        let err = store.invoke_export(addr, "spin", &[], &mut NoHost).unwrap_err();
+        assert_eq!(err.trap(), Some(&AwwasmTrap::OutOfFuel));
+        assert_eq!(store.fuel(), Some(0));
+    }
+
+    #[test]
+    fn test_execution_metrics() {
+        struct Echo;
+
+        impl AwwasmHost for Echo {
+            fn call(&mut self, _: u32, args: &[AwwasmValue], _: Option<&mut AwwasmMemInst>) -> Result<Vec<AwwasmValue>, AwwasmTrap> {
+                Ok(args.to_vec())
+            }
+        }
+
+        let wasm = wat::parse_str(r#"
+            (module
+                (import "env" "echo" (func $echo (param i32) (result i32)))
+                (memory 1)
+                (func (export "run") (param i32) (result i32)
+                    local.get 0
+                    call $echo
+                    i32.const 1
+                    i32.add
+                    i32.const 2
+                    memory.grow
+                    drop)
+                (func (export "fail") i32.const 0 i32.const 0 i32.div_s drop)
+            )
+        "#).unwrap();
+        let mut store = AwwasmStore::new();
+        let mut imports = AwwasmImports::new();
+        imports.add_func(b"env", b"echo", AwwasmFuncInst::host(0, 0));
+        let addr = store.instantiate_bytes(&wasm, &mut imports).unwrap();
+        let run = export_func(&store, addr, "run");
+
+        store.invoke(run, &[AwwasmValue::I32(4)], &mut Echo).unwrap();
+        assert_eq!(store.last_metrics(), None);
+
+        let (results, metrics) = store.invoke_with_metrics(run, &[AwwasmValue::I32(4)], &mut Echo).unwrap();
+        assert_eq!(results, vec![AwwasmValue::I32(5)]);
+        // The fused `i32.const 1; i32.add` still counts as two.
+        assert_eq!(
+            metrics,
+            AwwasmExecutionMetrics { instructions: 8, host_calls: 1, max_stack_depth: 3, pages_grown: 2 }
+        );
+        assert!(!store.collect_metrics());
+
+        store.set_collect_metrics(true);
+        assert!(store.invoke_export(addr, "fail", &[], &mut Echo).is_err());
+        assert_eq!(store.last_metrics().map(|m| m.instructions), Some(3));
+    }
+
+    #[cfg(feature = "fuzz")]
+    #[test]
+    fn test_fuzz_targets() {
+        let wasm = wat::parse_str(r#"
+            (module
+                (import "env" "f" (func (param i32) (result i32)))
+                (memory (export "memory") 1)
+                (func (export "g") (param i32) (result i32) local.get 0 call 0))
+        "#).unwrap();
+        fuzz::instantiate(&wasm);
+        fuzz::instantiate(&wasm[..wasm.len() / 2]);
+
+        // A cheap xorshift stands in for the fuzzer's inputs.
+        let mut state = 0x2545_f491_4f6c_dd1du64;
+        for len in [0, 1, 7, 64, 512, 4096] {
+            let data: Vec<u8> = (0..len)
+                .map(|_| {
+                    state ^= state << 13;
+                    state ^= state >> 7;
+                    state ^= state << 17;
+                    state as u8
+                })
+                .collect();
+            fuzz::memory_ops(&data);
+            fuzz::execute(&data);
+            fuzz::instantiate(&data);
+            let mut with_module = data.clone();
+            with_module.extend_from_slice(&wasm);
+            fuzz::execute(&with_module);
+        }
+    }
+
+    #[cfg(feature = "proptest")]
+    #[test]
+    fn test_model_checkers() {
+        use proptest::test_runner::{TestCaseError, TestRunner};
+        use testing::{AwwasmMemoryBackend, AwwasmMemoryOp, AwwasmTableOp};
+
+        let mut runner = TestRunner::default();
+        runner
+            .run(&(testing::memory_type(), testing::memory_ops()), |(ty, ops)| {
+                let mut mem = AwwasmMemInst::new(ty);
+                testing::check_memory_ops(&mut mem, ty, testing::MAX_MODEL_PAGES, &ops)
+                    .map_err(|e| TestCaseError::fail(e.to_string()))
+            })
+            .unwrap();
+        runner
+            .run(&(testing::table_type(), testing::table_ops()), |(ty, ops)| {
+                let mut table = AwwasmTableInst::new(ty);
+                testing::check_table_ops(&mut table, ty, testing::MAX_MODEL_TABLE, &ops)
+                    .map_err(|e| TestCaseError::fail(e.to_string()))
+            })
+            .unwrap();
+
+        // A memory whose fill stops one byte short is caught.
+        struct ShortFill(AwwasmMemInst);
+        impl AwwasmMemoryBackend for ShortFill {
+            fn size_bytes(&self) -> usize { self.0.size_bytes() }
+            fn grow(&mut self, delta: u32) -> Option<u32> { self.0.grow(delta) }
+            fn read(&self, offset: u32, len: u32) -> Option<Vec<u8>> { self.0.read(offset, len).ok().map(|b| b.to_vec()) }
+            fn write(&mut self, offset: u32, bytes: &[u8]) -> bool { self.0.write(offset, bytes).is_ok() }
+            fn fill(&mut self, offset: u32, value: u8, len: u32) -> bool {
+                self.0.fill(offset, value, len.saturating_sub(1)).is_ok()
+            }
+            fn copy_within(&mut self, dst: u32, src: u32, len: u32) -> bool { self.0.copy_within(dst, src, len).is_ok() }
+        }
+        let ty = AwwasmMemoryType::new(1, None);
+        let ops = [
+            AwwasmMemoryOp::Fill { offset: 8, value: 0xaa, len: 4 },
+            AwwasmMemoryOp::Read { offset: 8, len: 4 },
+        ];
+        let err = testing::check_memory_ops(&mut ShortFill(AwwasmMemInst::new(ty)), ty, 1, &ops).unwrap_err();
+        assert_eq!(err.step, 1);
+
+        // Filling near `u32::MAX` reports out of bounds instead of overflowing.
+        let ty = AwwasmTableType::funcref(0, None);
+        let ops = [AwwasmTableOp::Fill { offset: u32::MAX - 1, value: None, len: 8 }];
+        assert!(testing::check_table_ops(&mut AwwasmTableInst::new(ty), ty, 0, &ops).is_ok());
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn test_instantiation_observer() {
+        use core::sync::atomic::{AtomicU64, Ordering};
+        use std::sync::{Arc, Mutex};
+        use observer::AwwasmInstantiationPhase::*;
+
+        /// Every reading advances the clock by 10ns.
+        #[derive(Debug, Default)]
+        struct TickPlatform(AtomicU64);
+
+        impl AwwasmHostPlatform for TickPlatform {
+            fn monotonic_nanos(&self) -> u64 {
+                self.0.fetch_add(10, Ordering::SeqCst)
+            }
+
+            fn fill_entropy(&self, _buf: &mut [u8]) -> bool {
+                false
+            }
+        }
+
+        #[derive(Debug, Default)]
+        struct Log(Mutex<Vec<Result<AwwasmInstantiationPhase, std::string::String>>>);
+
+        impl AwwasmInstantiationObserver for Log {
+            fn phase(&self, module: AwwasmModuleAddr, phase: AwwasmInstantiationPhase, elapsed_nanos: Option<u64>) {
+                assert_eq!((module, elapsed_nanos), (AwwasmModuleAddr(0), Some(10)));
+                self.0.lock().unwrap().push(Ok(phase));
+            }
+
+            fn failed(&self, module: AwwasmModuleAddr, error: &AwwasmInstantiationError, elapsed_nanos: Option<u64>) {
+                assert_eq!((module, elapsed_nanos), (AwwasmModuleAddr(0), Some(10)));
+                self.0.lock().unwrap().push(Err(error.to_string()));
+            }
+        }
+
+        let wasm = wat::parse_str(r#"
+            (module
+                (import "env" "g" (global i32))
+                (memory (export "memory") 1)
+                (table 2 funcref)
+                (func $f)
+                (elem (i32.const 0) $f $f)
+                (data (i32.const 0) "abc")
+                (data (i32.const 8) "d"))
+        "#).unwrap();
+        let log = Arc::new(Log::default());
+        let mut store = AwwasmStore::new();
+        store.set_platform(Arc::new(TickPlatform::default()));
+        store.set_instantiation_observer(Some(log.clone()));
+
+        assert!(store.instantiate_bytes(&wasm, &mut AwwasmImports::new()).is_err());
+        assert_eq!(*log.0.lock().unwrap(), [Err(std::string::String::from("missing import env.g"))]);
+
+        log.0.lock().unwrap().clear();
+        let mut imports = AwwasmImports::new();
+        imports.add_global(b"env", b"g", AwwasmGlobalInst::new(AwwasmGlobalType::immutable(AwwasmValueType::I32), AwwasmValue::I32(0)));
+        store.instantiate_bytes(&wasm, &mut imports).unwrap();
+        assert_eq!(
+            *log.0.lock().unwrap(),
+            [
+                ImportsResolved { count: 1 },
+                FuncsAllocated { count: 1 },
+                MemoriesAllocated { count: 1, bytes: 65536 },
+                TablesAllocated { count: 1 },
+                GlobalsInitialized { count: 0 },
+                ExportsResolved { count: 1 },
+                ElemSegmentInitialized { index: 0, len: 2 },
+                DataSegmentInitialized { index: 0, bytes: 3 },
+                DataSegmentInitialized { index: 1, bytes: 1 },
+                Done,
+            ]
+            .map(Ok)
+        );
+        assert_eq!(DataSegmentInitialized { index: 1, bytes: 1 }.to_string(), "data segment 1 initialized (1 bytes)");
+    }
+
+    #[test]
+    fn test_custom_sections() {
+        let mut wasm = wat::parse_str(r#"(module (func (export "f")))"#).unwrap();
+        // Custom sections may appear anywhere; append two after the code.
+        for (name, data) in [(&b"plugin.manifest"[..], &b"{\"abi\":2}"[..]), (b"abi", b"\x02")] {
+            wasm.push(0);
+            wasm.push((1 + name.len() + data.len()) as u8);
+            wasm.push(name.len() as u8);
+            wasm.extend_from_slice(name);
+            wasm.extend_from_slice(data);
+        }
+
+        let mut module = AwwasmModule::new(&wasm).unwrap();
+        module.resolve_all_sections().unwrap();
+        let info = AwwasmModuleInfo::new(&module).unwrap();
+        let names: Vec<_> = info.custom_sections.iter().map(|s| s.name).collect();
+        assert_eq!(names, [&b"plugin.manifest"[..], b"abi"]);
+        assert_eq!(info.custom_section("abi"), Some(&b"\x02"[..]));
+        assert_eq!(info.custom_section("name"), None);
+
+        let mut store = AwwasmStore::new();
+        let addr = store.store_init(&module, &mut AwwasmImports::new()).unwrap();
+        let inst = store.module(addr).unwrap();
+        assert_eq!(inst.custom_section("plugin.manifest"), Some(&b"{\"abi\":2}"[..]));
+        assert_eq!(inst.custom_sections, info.custom_sections);
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn test_shared_memory() {
+        let ty = AwwasmMemoryType::new(1, Some(4)).with_shared(true);
+        assert_eq!(ty.to_string(), "(memory 1 4 shared)");
+        assert!(AwwasmMemInst::try_new(AwwasmMemoryType::new(1, None).with_shared(true)).is_err());
+        let mem = AwwasmMemInst::new(ty);
+        assert!(mem.is_shared());
+
+        let wasm = wat::parse_str(r#"
+            (module
+                (import "env" "mem" (memory 1 4 shared))
+                (func (export "put") (param i32 i32) local.get 0 local.get 1 i32.store)
+                (func (export "grow") (result i32) i32.const 1 memory.grow))
+        "#).unwrap();
+        std::thread::scope(|s| {
+            for offset in [0, 4] {
+                let (mem, wasm) = (mem.clone(), &wasm);
+                s.spawn(move || {
+                    let mut imports = AwwasmImports::new();
+                    imports.add_memory(b"env", b"mem", mem);
+                    let mut store = AwwasmStore::new();
+                    let addr = store.instantiate_bytes(wasm, &mut imports).unwrap();
+                    let args = [AwwasmValue::I32(offset), AwwasmValue::I32(offset + 1)];
+                    store.invoke_export(addr, "put", &args, &mut NoHost).unwrap();
+                    store.invoke_export(addr, "grow", &[], &mut NoHost).unwrap();
+                });
+            }
+        });
+        // Both stores wrote to and grew the one memory.
+        assert_eq!(mem.size_pages(), 3);
+        assert_eq!((mem.read_i32(0).unwrap(), mem.read_i32(4).unwrap()), (1, 5));
+
+        // Only a shared memory satisfies a shared import, and vice versa.
+        let mut imports = AwwasmImports::new();
+        imports.add_memory(b"env", b"mem", AwwasmMemInst::new(AwwasmMemoryType::new(1, Some(4))));
+        assert!(matches!(
+            AwwasmStore::new().instantiate_bytes(&wasm, &mut imports),
+            Err(AwwasmInstantiationError::ImportTypeMismatch { .. })
+        ));
+        let mut alias = mem.clone();
+        assert_eq!(alias.grow(1), Some(3));
+        assert_eq!(alias.grow(1), None);
+        assert_eq!(mem.size_pages(), 4);
+
+        // The bytes are only copied in and out, never borrowed; partial
+        // words keep the bytes around them.
+        assert!(alias.data.as_slice().is_none());
+        alias.write(5, b"abcdefghij").unwrap();
+        alias.copy_within(7, 5, 10).unwrap();
+        assert_eq!(&*mem.read(5, 12).unwrap(), b"ababcdefghij");
+        assert_eq!(mem.read_u8(4).unwrap(), 5);
+        assert_eq!(mem.data.to_vec().len(), 4 * 65536);
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn test_atomic_wait_notify() {
+        use memory::AwwasmWaitResult;
+
+        let mem = AwwasmMemInst::new(AwwasmMemoryType::new(1, Some(1)).with_shared(true));
+        let wasm = wat::parse_str(r#"
+            (module
+                (import "env" "mem" (memory 1 1 shared))
+                (func (export "wait") (param i32 i64) (result i32)
+                    i32.const 8 local.get 0 local.get 1 memory.atomic.wait32)
+                (func (export "wait64") (param i64 i64) (result i32)
+                    i32.const 16 local.get 0 local.get 1 memory.atomic.wait64)
+                (func (export "notify") (param i32) (result i32)
+                    i32.const 8 local.get 0 memory.atomic.notify))
+        "#).unwrap();
+        let instantiate = |mem: AwwasmMemInst| {
+            let mut imports = AwwasmImports::new();
+            imports.add_memory(b"env", b"mem", mem);
+            let mut store = AwwasmStore::new();
+            let addr = store.instantiate_bytes(&wasm, &mut imports).unwrap();
+            (store, addr)
+        };
+        let call = |store: &mut AwwasmStore, addr, name: &str, args: &[AwwasmValue]| {
+            store.invoke_export(addr, name, args, &mut NoHost).map(|r| r[0])
+        };
+
+        std::thread::scope(|s| {
+            let waiter = s.spawn(|| {
+                let (mut store, addr) = instantiate(mem.clone());
+                call(&mut store, addr, "wait", &[AwwasmValue::I32(0), AwwasmValue::I64(-1)]).unwrap()
+            });
+            // Notify until the waiter has parked and been woken.
+            let (mut store, addr) = instantiate(mem.clone());
+            while call(&mut store, addr, "notify", &[AwwasmValue::I32(1)]).unwrap() == AwwasmValue::I32(0) {
+                std::thread::yield_now();
+            }
+            assert_eq!(waiter.join().unwrap(), AwwasmValue::I32(AwwasmWaitResult::Ok as i32));
+        });
+
+        let (mut store, addr) = instantiate(mem.clone());
+        let not_equal = call(&mut store, addr, "wait", &[AwwasmValue::I32(1), AwwasmValue::I64(-1)]).unwrap();
+        assert_eq!(not_equal, AwwasmValue::I32(AwwasmWaitResult::NotEqual as i32));
+        let timed_out = call(&mut store, addr, "wait64", &[AwwasmValue::I64(0), AwwasmValue::I64(1_000_000)]).unwrap();
+        assert_eq!(timed_out, AwwasmValue::I32(AwwasmWaitResult::TimedOut as i32));
+        // A timed-out waiter left the queue: nobody is left to wake.
+        assert_eq!(mem.atomic_notify(8, u32::MAX), Ok(0));
+
+        assert_eq!(mem.atomic_wait32(2, 0, 0), Err(AwwasmTrap::UnalignedAtomic { offset: 2 }));
+        assert!(matches!(mem.atomic_wait64(65536, 0, 0), Err(AwwasmTrap::MemoryOutOfBounds { .. })));
+        let unshared = AwwasmMemInst::new(AwwasmMemoryType::new(1, Some(1)));
+        assert_eq!(unshared.atomic_notify(0, 1), Ok(0));
+        assert_eq!(unshared.atomic_wait32(0, 0, 0), Err(AwwasmTrap::ExpectedSharedMemory));
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn test_store_pool() {
+        let wasm = wat::parse_str(r#"
+            (module
+                (import "env" "worker" (func $worker (result i32)))
+                (func (export "square") (param i32) (result i32)
+                    (i32.mul (local.get 0) (local.get 0)))
+                (func (export "worker") (result i32) call $worker))
+        "#).unwrap();
+
+        /// Answers `env.worker` with the index of the worker it serves.
+        struct WorkerHost(usize);
+
+        impl AwwasmHost for WorkerHost {
+            fn call(
+                &mut self,
+                _host_func_id: u32,
+                _args: &[AwwasmValue],
+                _mem: Option<&mut AwwasmMemInst>,
+            ) -> Result<Vec<AwwasmValue>, AwwasmTrap> {
+                Ok(vec![AwwasmValue::I32(self.0 as i32)])
+            }
+        }
+
+        let pool = AwwasmStorePool::new(wasm.clone(), 4, |index, store, imports| {
+            store.set_fuel(Some(1_000));
+            imports.add_func(b"env", b"worker", AwwasmFuncInst::host(0, 0));
+            WorkerHost(index)
+        })
+        .unwrap();
+        assert_eq!(pool.workers(), 4);
+
+        let results = pool.invoke_all((0..64).map(|i| ("square", vec![AwwasmValue::I32(i)])));
+        for (i, result) in results.into_iter().enumerate() {
+            assert_eq!(result.unwrap(), vec![AwwasmValue::I32((i * i) as i32)]);
+        }
+        let worker = pool.invoke("worker", &[]).unwrap()[0].as_i32().unwrap();
+        assert!((0..4).contains(&worker));
+        assert!(matches!(pool.invoke("missing", &[]), Err(AwwasmRuntimeError::ExportNotFound(_))));
+
+        // Every worker instantiates on startup; the first failure is reported.
+        assert!(matches!(
+            AwwasmStorePool::new(wasm, 2, |_, _, _| NoHost),
+            Err(AwwasmRunError::Instantiation(AwwasmInstantiationError::MissingImport { .. }))
+        ));
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn test_store_pool_refills_fuel_and_recovers() {
+        use alloc::sync::Arc;
+        use core::sync::atomic::{AtomicBool, Ordering};
+
+        let wasm = wat::parse_str(r#"
+            (module
+                (func (export "spin") (param i32)
+                    (loop $l
+                        (local.set 0 (i32.sub (local.get 0) (i32.const 1)))
+                        (br_if $l (local.get 0)))))
+        "#).unwrap();
+
+        /// Panics on the first call it sees while armed.
+        #[derive(Debug)]
+        struct Tripwire(AtomicBool);
+
+        impl AwwasmProfiler for Tripwire {
+            fn enter(&self, _func: AwwasmFuncAddr) {
+                if self.0.swap(false, Ordering::SeqCst) {
+                    panic!("profiler bug");
+                }
+            }
+
+            fn exit(&self, _func: AwwasmFuncAddr) {}
+        }
+
+        let armed = Arc::new(Tripwire(AtomicBool::new(false)));
+        let profiler = armed.clone();
+        let pool = AwwasmStorePool::new(wasm, 1, move |_, store, _| {
+            store.set_fuel(Some(1_000));
+            store.set_profiler(Some(profiler.clone()));
+            NoHost
+        })
+        .unwrap();
+
+        // Each call gets the full budget, however much earlier ones spent.
+        for _ in 0..10 {
+            pool.invoke("spin", &[AwwasmValue::I32(100)]).unwrap();
+        }
+        let err = pool.invoke("spin", &[AwwasmValue::I32(1_000)]).unwrap_err();
+        assert_eq!(err.trap(), Some(&AwwasmTrap::OutOfFuel));
+
+        // A panic outside a host function fails the call, not the worker.
+        armed.0.store(true, Ordering::SeqCst);
+        let err = pool.invoke("spin", &[AwwasmValue::I32(1)]).unwrap_err();
+        assert_eq!(
+            err.trap().unwrap().downcast_ref::<AwwasmHostPanic>(),
+            Some(&AwwasmHostPanic { message: Some("profiler bug".into()) })
+        );
+        assert_eq!(pool.live_workers(), 1);
+        pool.invoke("spin", &[AwwasmValue::I32(100)]).unwrap();
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn test_inspector() {
+        use std::time::Duration;
+
+        let wasm = wat::parse_str(r#"
+            (module
+                (memory 1)
+                (func (export "spin")
+                    (loop $l
+                        (i32.store (i32.const 0) (i32.add (i32.load (i32.const 0)) (i32.const 1)))
+                        (br $l))))
+        "#).unwrap();
+        let mut store = AwwasmStore::new();
+        let addr = store.instantiate_bytes(&wasm, &mut AwwasmImports::new()).unwrap();
+        let (inspector, interrupt) = (AwwasmInspector::new(), AwwasmInterruptHandle::new());
+        store.set_inspector(inspector.clone());
+        store.set_interrupt_handle(interrupt.clone());
+
+        // Nothing runs, so no safepoint is reached.
+        assert_eq!(inspector.inspect(|mems| mems.len(), Duration::from_millis(10)), None);
+
+        std::thread::scope(|s| {
+            s.spawn(|| {
+                let timeout = Duration::from_secs(10);
+                let counter = |bytes: Vec<u8>| i32::from_le_bytes(bytes.try_into().unwrap());
+                let first = counter(inspector.read_memory(AwwasmMemAddr(0), 0..4, timeout).unwrap().unwrap());
+                let second = counter(inspector.read_memory(AwwasmMemAddr(0), 0..4, timeout).unwrap().unwrap());
+                assert!(second > first);
+                assert_eq!(inspector.inspect(|mems| mems.len(), timeout), Some(1));
+                assert!(matches!(
+                    inspector.read_memory(AwwasmMemAddr(0), 65535..65539, timeout),
+                    Some(Err(AwwasmTrap::MemoryOutOfBounds { .. }))
+                ));
+                interrupt.interrupt();
+            });
+            let result = store.invoke_export(addr, "spin", &[], &mut NoHost);
+            assert_eq!(result.unwrap_err().trap(), Some(&AwwasmTrap::Interrupted));
+        });
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn test_host_closures() {
+        use std::cell::Cell;
+        use std::rc::Rc;
+        use std::sync::atomic::{AtomicU32, Ordering};
+        use std::sync::Arc;
+
+        let wasm = wat::parse_str(r#"
+            (module
+                (import "env" "tick" (func $tick (param i32) (result i32)))
+                (func (export "run") (param i32) (result i32) (call $tick (local.get 0))))
+        "#).unwrap();
+
+        // Thread-safe closures: the set moves to the thread running the guest.
+        let total = Arc::new(AtomicU32::new(0));
+        let mut funcs = AwwasmHostFuncs::new();
+        let tick = {
+            let total = total.clone();
+            funcs.wrap(move |args, _| {
+                let n = args[0].as_i32().unwrap() as u32;
+                Ok(vec![AwwasmValue::I32(total.fetch_add(n, Ordering::SeqCst) as i32 + n as i32)])
+            }).unwrap()
+        };
+        let worker = std::thread::spawn(move || {
+            let mut imports = AwwasmImports::new();
+            imports.add_func(b"env", b"tick", tick);
+            let mut store = AwwasmStore::new();
+            let addr = store.instantiate_bytes(&wasm, &mut imports).unwrap();
+            store.invoke_export(addr, "run", &[AwwasmValue::I32(5)], &mut funcs).unwrap();
+            store.invoke_export(addr, "run", &[AwwasmValue::I32(2)], &mut funcs).unwrap()
+        });
+        assert_eq!(worker.join().unwrap(), vec![AwwasmValue::I32(7)]);
+        assert_eq!(total.load(Ordering::SeqCst), 7);
+
+        // Single-threaded closures may capture `Rc`.
+        let wasm = wat::parse_str(r#"
+            (module
+                (import "env" "tick" (func $tick (param i32) (result i32)))
+                (func (export "run") (param i32) (result i32) (call $tick (local.get 0))))
+        "#).unwrap();
+        let calls = Rc::new(Cell::new(0));
+        let mut funcs = AwwasmHostFuncs::single_threaded().with_base_id(100);
+        let counter = calls.clone();
+        let mut imports = AwwasmImports::new();
+        imports.add_func(b"env", b"tick", funcs.wrap(move |args, _| {
+            counter.set(counter.get() + 1);
+            Ok(args.to_vec())
+        }).unwrap());
+        let mut store = AwwasmStore::new();
+        let addr = store.instantiate_bytes(&wasm, &mut imports).unwrap();
+        assert_eq!(store.invoke_export(addr, "run", &[AwwasmValue::I32(3)], &mut funcs).unwrap(), vec![AwwasmValue::I32(3)]);
+        assert_eq!((calls.get(), funcs.len()), (1, 1));
+        assert_eq!(funcs.call(99, &[], None), Err(AwwasmTrap::InvalidHostCall { host_func_id: 99 }));
+
+        // Ids run out at `u32::MAX` instead of wrapping around.
+        let mut funcs = AwwasmHostFuncs::new().with_base_id(u32::MAX);
+        assert!(funcs.wrap(|_, _| Ok(Vec::new())).is_ok());
+        let err = funcs.wrap(|_, _| Ok(Vec::new())).unwrap_err();
+        assert_eq!(err, AwwasmRuntimeError::HostFuncIdsExhausted { base_id: u32::MAX });
+        assert_eq!(err.code(), 3017);
+    }
+
+    #[cfg(feature = "parallel")]
+    #[test]
+    fn test_instantiate_parallel() {
+        struct Double;
+        impl AwwasmHost for Double {
+            fn call(&mut self, _: u32, args: &[AwwasmValue], _: Option<&mut AwwasmMemInst>) -> Result<Vec<AwwasmValue>, AwwasmTrap> {
+                Ok(vec![AwwasmValue::I32(args[0].as_i32().unwrap() * 2)])
+            }
+        }
+
+        let base = wat::parse_str(r#"
+            (module
+                (table (export "table") 1 funcref)
+                (func (export "one") (result i32) (i32.const 1)))
+        "#).unwrap();
+        let first = wat::parse_str(r#"
+            (module
+                (import "env" "double" (func $double (param i32) (result i32)))
+                (table 2 funcref)
+                (elem (i32.const 1) $seven)
+                (global $g (mut i32) (i32.const 0))
+                (func $seven (result i32) (i32.const 7))
+                (func (export "run") (result i32)
+                    (call $double (call_indirect (result i32) (i32.const 1)))))
+        "#).unwrap();
+        let second = wat::parse_str(r#"
+            (module
+                (memory (export "mem") 1)
+                (data (i32.const 0) "\05")
+                (global $f funcref (ref.func $add))
+                (table 1 funcref)
+                (elem declare func $add)
+                (func $add (param i32 i32) (result i32) (i32.add (local.get 0) (local.get 1)))
+                (func (export "run") (result i32)
+                    (table.set (i32.const 0) (global.get $f))
+                    (call_indirect (param i32 i32) (result i32)
+                        (i32.load8_u (i32.const 0)) (i32.const 10) (i32.const 0))))
+        "#).unwrap();
+
+        let mut store = AwwasmStore::new();
+        let base_addr = store.instantiate_bytes(&base, &mut AwwasmImports::new()).unwrap();
+        let mut imports = AwwasmImports::new();
+        imports.add_func(b"env", b"double", AwwasmFuncInst::host(0, 0));
+        let addrs = store.instantiate_parallel(vec![(&first[..], imports), (&second[..], AwwasmImports::new())]).unwrap();
+        assert_eq!(addrs, vec![AwwasmModuleAddr(1), AwwasmModuleAddr(2)]);
+        assert_eq!(store.invoke_export(addrs[0], "run", &[], &mut Double).unwrap(), vec![AwwasmValue::I32(14)]);
+        assert_eq!(store.invoke_export(addrs[1], "run", &[], &mut Double).unwrap(), vec![AwwasmValue::I32(15)]);
+        assert_eq!(store.invoke_export(base_addr, "one", &[], &mut Double).unwrap(), vec![AwwasmValue::I32(1)]);
+
+        // A table of the destination store is out of a shard's reach.
+        let mut imports = AwwasmImports::new();
+        imports.add_table(b"env", b"table", AwwasmTableAddr(0));
+        let err = store.instantiate_parallel(vec![(&first[..], imports)]).unwrap_err();
+        assert_eq!(err, AwwasmInstantiationError::ShardImport { module: "env".into(), name: "table".into() });
+        assert_eq!(err.code(), 1014);
+        assert_eq!(store.modules.len(), 3);
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn test_call_async_yields() {
+        use std::future::Future;
+        use std::pin::Pin;
+        use std::sync::Arc;
+        use std::task::{Context, Poll, Wake, Waker};
+
+        struct Noop;
+        impl Wake for Noop {
+            fn wake(self: Arc<Self>) {}
+        }
+
+        let wasm = wat::parse_str(r#"
+            (module
+                (func (export "sum") (param i32) (result i32) (local i32)
+                    (block
+                        (loop
+                            (br_if 1 (i32.eqz (local.get 0)))
+                            (local.set 1 (i32.add (local.get 1) (local.get 0)))
+                            (local.set 0 (i32.sub (local.get 0) (i32.const 1)))
+                            (br 0)))
+                    (local.get 1)))
+        "#).unwrap();
+        let waker = Waker::from(Arc::new(Noop));
+        let mut cx = Context::from_waker(&waker);
+
+        // Two calls on one thread, polled in turn, both make progress.
+        let (mut a, mut b) = (AwwasmStore::new(), AwwasmStore::new());
+        let (addr_a, addr_b) = (
+            a.instantiate_bytes(&wasm, &mut AwwasmImports::new()).unwrap(),
+            b.instantiate_bytes(&wasm, &mut AwwasmImports::new()).unwrap(),
+        );
+        let (sum_a, sum_b) = (export_func(&a, addr_a, "sum"), export_func(&b, addr_b, "sum"));
+        let (mut host_a, mut host_b) = (NoHost, NoHost);
+        let mut calls = [
+            a.call_async(sum_a, &[AwwasmValue::I32(100)], &mut host_a, 50),
+            b.call_async(sum_b, &[AwwasmValue::I32(200)], &mut host_b, 50),
+        ];
+        let mut results = [None, None];
+        while results.iter().any(Option::is_none) {
+            for (call, result) in calls.iter_mut().zip(&mut results) {
+                if result.is_none() {
+                    if let Poll::Ready(r) = Pin::new(call).poll(&mut cx) {
+                        *result = Some(r.unwrap());
+                    }
+                }
+            }
+        }
+        assert_eq!(results, [Some(vec![AwwasmValue::I32(5050)]), Some(vec![AwwasmValue::I32(20100)])]);
+        assert!(calls[0].yields() > 10 && calls[1].yields() > calls[0].yields());
+        drop(calls);
+
+        // With fuel, slices are measured in fuel and the call is charged
+        // exactly as `invoke` charges it.
+        a.set_fuel(Some(1_000_000));
+        let mut call = a.call_async(sum_a, &[AwwasmValue::I32(10)], &mut host_a, 20);
+        let result = loop {
+            if let Poll::Ready(r) = Pin::new(&mut call).poll(&mut cx) {
+                break r;
+            }
+        };
+        let yields = call.yields();
+        assert_eq!(result.unwrap(), vec![AwwasmValue::I32(55)]);
+        let spent = 1_000_000 - a.fuel().unwrap();
+        assert_eq!(yields, (spent - 1) / 20);
+        a.set_fuel(Some(1_000_000));
+        a.invoke(sum_a, &[AwwasmValue::I32(10)], &mut NoHost).unwrap();
+        assert_eq!(1_000_000 - a.fuel().unwrap(), spent);
+
+        // Dropping a paused call gives the store its arena back.
+        let limits = AwwasmStackLimits { call_depth: 8, value_stack: 64, label_depth: 64 };
+        let mut store = AwwasmStore::with_arena(AwwasmExecArena::new(limits).unwrap());
+        let addr = store.instantiate_bytes(&wasm, &mut AwwasmImports::new()).unwrap();
+        let sum = export_func(&store, addr, "sum");
+        let mut host = NoHost;
+        let mut call = store.call_async(sum, &[AwwasmValue::I32(100)], &mut host, 10);
+        assert!(Pin::new(&mut call).poll(&mut cx).is_pending());
+        drop(call);
+        assert_eq!(store.take_arena().map(|arena| arena.limits()), Some(limits));
+    }
+
+    #[test]
+    fn test_scheduler_round_robin() {
+        let wasm = wat::parse_str(r#"
+            (module
+                (func (export "spin") (param i32) (result i32)
+                    (loop
+                        (local.set 0 (i32.sub (local.get 0) (i32.const 1)))
+                        (br_if 0 (local.get 0)))
+                    (i32.const 1))
+                (func (export "fail") (unreachable)))
+        "#).unwrap();
+        let mut scheduler = AwwasmScheduler::new(100);
+        let mut spawn = |name: &str, args: &[AwwasmValue]| {
+            let mut store = AwwasmStore::new();
+            let addr = store.instantiate_bytes(&wasm, &mut AwwasmImports::new()).unwrap();
+            scheduler.spawn_export(store, NoHost, addr, name, args).unwrap()
+        };
+        let long = spawn("spin", &[AwwasmValue::I32(5000)]);
+        let short = spawn("spin", &[AwwasmValue::I32(10)]);
+        let trap = spawn("fail", &[]);
+
+        // The short task and the trap finish in the first round, while
+        // the long one keeps going.
+        assert_eq!(scheduler.round(), vec![short, trap]);
+        assert_eq!(scheduler.pending(), 1);
+        assert_eq!(scheduler.run(), vec![long]);
+        assert_eq!(scheduler.result(long).unwrap().as_ref().unwrap(), &vec![AwwasmValue::I32(1)]);
+        assert_eq!(scheduler.result(trap).unwrap().as_ref().unwrap_err().trap(), Some(&AwwasmTrap::Unreachable));
+        assert_eq!(scheduler.slices(trap), Some(1));
+        assert!(scheduler.slices(long).unwrap() > scheduler.slices(short).unwrap() * 10);
+
+        let store = AwwasmStore::new();
+        let missing = scheduler.spawn_export(store, NoHost, AwwasmModuleAddr(0), "spin", &[]);
+        assert!(matches!(missing, Err(AwwasmRuntimeError::ExportNotFound(_))));
+    }
+
+    #[test]
+    fn test_host_reenters_store() {
+        use caller::{AwwasmCaller, MAX_REENTRY_DEPTH};
+
+        struct Reenter {
+            depth: usize,
+        }
+        impl AwwasmHost for Reenter {
+            fn call(&mut self, host_func_id: u32, _: &[AwwasmValue], _: Option<&mut AwwasmMemInst>) -> Result<Vec<AwwasmValue>, AwwasmTrap> {
+                Err(AwwasmTrap::InvalidHostCall { host_func_id })
+            }
+
+            fn call_with_caller(
+                &mut self,
+                host_func_id: u32,
+                args: &[AwwasmValue],
+                caller: &mut AwwasmCaller<'_, '_>,
+            ) -> Result<Vec<AwwasmValue>, AwwasmTrap> {
+                match host_func_id {
+                    // `apply(x)`: the guest's `double(x)` plus the byte at 0.
+                    0 => {
+                        let byte = caller.memory().unwrap().read(0, 1)?[0];
+                        let doubled = caller.invoke_export("double", args, self)?;
+                        Ok(vec![AwwasmValue::I32(doubled[0].as_i32().unwrap() + i32::from(byte))])
+                    }
+                    // `recurse()`: call back into `loop`, which calls us again.
+                    _ => {
+                        self.depth += 1;
+                        caller.invoke_export("loop", &[], self)
+                    }
+                }
+            }
+        }
+
+        let wasm = wat::parse_str(r#"
+            (module
+                (import "env" "apply" (func $apply (param i32) (result i32)))
+                (import "env" "recurse" (func $recurse))
+                (memory 1)
+                (data (i32.const 0) "\03")
+                (func (export "double") (param i32) (result i32) (i32.mul (local.get 0) (i32.const 2)))
+                (func (export "run") (param i32) (result i32) (call $apply (local.get 0)))
+                (func (export "loop") (call $recurse)))
+        "#).unwrap();
+        let mut imports = AwwasmImports::new();
+        imports.add_func(b"env", b"apply", AwwasmFuncInst::host(0, 0));
+        imports.add_func(b"env", b"recurse", AwwasmFuncInst::host(1, 1));
+        let mut store = AwwasmStore::new();
+        let addr = store.instantiate_bytes(&wasm, &mut imports).unwrap();
+        let mut host = Reenter { depth: 0 };
+        assert_eq!(store.invoke_export(addr, "run", &[AwwasmValue::I32(20)], &mut host).unwrap(), vec![AwwasmValue::I32(43)]);
+
+        // Unbounded re-entry stops at the limit instead of overflowing
+        // the native stack, and leaves the store usable.
+        let err = store.invoke_export(addr, "loop", &[], &mut host).unwrap_err();
+        assert_eq!(err.trap(), Some(&AwwasmTrap::CallStackExhausted));
+        assert_eq!(host.depth, MAX_REENTRY_DEPTH + 1);
+        assert_eq!(store.invoke_export(addr, "run", &[AwwasmValue::I32(1)], &mut host).unwrap(), vec![AwwasmValue::I32(5)]);
+    }
+
+    #[test]
+    fn test_module_shape_limits() {
+        use store::AwwasmModuleLimits;
+
+        let wasm = wat::parse_str(r#"
+            (module
+                (import "env" "f" (func))
+                (memory 1)
+                (global i32 (i32.const 0))
+                (global i32 (i32.const 1))
+                (data (i32.const 0) "0123456789")
+                (func (export "a_rather_long_export_name") (local i32 i64 i64))
+                (func))
+        "#).unwrap();
+        let instantiate = |limits: AwwasmModuleLimits| {
+            let mut store = AwwasmStore::new();
+            store.set_module_limits(limits);
+            let mut imports = AwwasmImports::new();
+            imports.add_func(b"env", b"f", AwwasmFuncInst::host(0, 0));
+            store.instantiate_bytes(&wasm, &mut imports).map(|_| store.funcs.len())
+        };
+        let exceeded = |limit, max, actual| Err(AwwasmInstantiationError::ModuleLimitExceeded { limit, max, actual });
+
+        let exact = AwwasmModuleLimits {
+            funcs: 3,
+            locals_per_func: 3,
+            memories: 1,
+            tables: 0,
+            globals: 2,
+            data_segment_bytes: 10,
+            export_name_bytes: 25,
+            ..AwwasmModuleLimits::default()
+        };
+        assert_eq!(instantiate(exact), Ok(3));
+        assert_eq!(instantiate(AwwasmModuleLimits::default()), Ok(3));
+        assert_eq!(instantiate(AwwasmModuleLimits { funcs: 2, ..exact }), exceeded(AwwasmModuleLimit::Funcs, 2, 3));
+        assert_eq!(instantiate(AwwasmModuleLimits { locals_per_func: 2, ..exact }), exceeded(AwwasmModuleLimit::Locals, 2, 3));
+        assert_eq!(instantiate(AwwasmModuleLimits { memories: 0, ..exact }), exceeded(AwwasmModuleLimit::Memories, 0, 1));
+        assert_eq!(instantiate(AwwasmModuleLimits { globals: 1, ..exact }), exceeded(AwwasmModuleLimit::Globals, 1, 2));
+        assert_eq!(
+            instantiate(AwwasmModuleLimits { data_segment_bytes: 9, ..exact }),
+            exceeded(AwwasmModuleLimit::DataSegmentBytes, 9, 10)
+        );
+        let err = instantiate(AwwasmModuleLimits { export_name_bytes: 8, ..exact }).unwrap_err();
+        assert_eq!(err.code(), 1015);
+        assert_eq!(err.to_string(), "module has 25 bytes in an export name, at most 8 allowed");
+
+        // A shared memory's whole maximum is allocated up front.
+        let wasm = wat::parse_str(r#"(module (memory 1 65536 shared))"#).unwrap();
+        let mut store = AwwasmStore::new();
+        store.set_module_limits(AwwasmModuleLimits { shared_memory_pages: 16, ..AwwasmModuleLimits::default() });
+        assert_eq!(
+            store.instantiate_bytes(&wasm, &mut AwwasmImports::new()).unwrap_err(),
+            AwwasmInstantiationError::ModuleLimitExceeded { limit: AwwasmModuleLimit::SharedMemoryPages, max: 16, actual: 65536 }
+        );
+        let wasm = wat::parse_str(r#"(module (memory 1 16 shared))"#).unwrap();
+        assert!(store.instantiate_bytes(&wasm, &mut AwwasmImports::new()).is_ok());
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn test_memory_audit_log() {
+        use audit::{AwwasmAuditLog, AwwasmMemAccess, AwwasmMemAccessKind::*, AwwasmMemAccessor};
+        use std::sync::Arc;
+
+        struct Peek;
+        impl AwwasmHost for Peek {
+            fn call(&mut self, _: u32, args: &[AwwasmValue], mem: Option<&mut AwwasmMemInst>) -> Result<Vec<AwwasmValue>, AwwasmTrap> {
+                let mem = mem.unwrap();
+                let value = mem.read_i32(args[0].as_i32().unwrap() as u32)?;
+                mem.fill(0, 0, 2)?;
+                Ok(vec![AwwasmValue::I32(value)])
+            }
+        }
+
+        let wasm = wat::parse_str(r#"
+            (module
+                (import "env" "peek" (func $peek (param i32) (result i32)))
+                (memory (export "mem") 1)
+                (func (export "run") (result i32)
+                    (i32.store (i32.const 8) (i32.const 42))
+                    (drop (i32.load (i32.const 8)))
+                    (call $peek (i32.const 8))))
+        "#).unwrap();
+        let mut imports = AwwasmImports::new();
+        imports.add_func(b"env", b"peek", AwwasmFuncInst::host(0, 7));
+        let mut store = AwwasmStore::new();
+        let log = Arc::new(AwwasmAuditLog::new(2));
+        store.set_memory_auditor(Some(log.clone()));
+        let addr = store.instantiate_bytes(&wasm, &mut imports).unwrap();
+
+        assert_eq!(store.invoke_export(addr, "run", &[], &mut Peek).unwrap(), vec![AwwasmValue::I32(42)]);
+        let host = AwwasmMemAccessor::HostFunc { host_func_id: 7, module: Some(addr) };
+        // The guest's own store and load are not in the log.
+        assert_eq!(
+            log.take(),
+            vec![
+                AwwasmMemAccess { accessor: host, kind: Read, offset: 8, size: 4, ok: true },
+                AwwasmMemAccess { accessor: host, kind: Write, offset: 0, size: 2, ok: true },
+            ]
+        );
+
+        // After the call, accesses are the embedder's again, failed ones
+        // included.
+        let mem = store.mem_mut(AwwasmMemAddr(0)).unwrap();
+        mem.write(16, b"hi").unwrap();
+        assert!(mem.read(65535, 2).is_err());
+        assert_eq!(
+            log.take(),
+            vec![
+                AwwasmMemAccess { accessor: AwwasmMemAccessor::Embedder, kind: Write, offset: 16, size: 2, ok: true },
+                AwwasmMemAccess { accessor: AwwasmMemAccessor::Embedder, kind: Read, offset: 65535, size: 2, ok: false },
+            ]
+        );
+
+        // Only the last `capacity` accesses are kept.
+        let mem = store.mem_mut(AwwasmMemAddr(0)).unwrap();
+        for offset in 0..3 {
+            mem.write_u8(offset, 1).unwrap();
+        }
+        assert_eq!(log.entries().iter().map(|a| a.offset).collect::<Vec<_>>(), vec![1, 2]);
+        assert_eq!((log.capacity(), log.dropped()), (2, 1));
+        log.take();
+
+        store.set_memory_auditor(None);
+        store.mem_mut(AwwasmMemAddr(0)).unwrap().write_u8(0, 1).unwrap();
+        assert!(log.entries().is_empty());
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn test_module_allow_list() {
+        use allowlist::{AwwasmAllowList, AwwasmModuleHash, AwwasmModuleVerifier};
+        use std::string::ToString;
+        use std::sync::Arc;
+
+        let abc = AwwasmModuleHash::of(b"abc");
+        assert_eq!(abc.to_string(), "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad");
+        assert_eq!(AwwasmModuleHash::from_hex(&abc.to_string()), Some(abc));
+        assert_eq!(AwwasmModuleHash::from_hex("ba78"), None);
+
+        let approved = wat::parse_str(r#"(module (func (export "f") (result i32) (i32.const 1)))"#).unwrap();
+        let other = wat::parse_str(r#"(module (func (export "f") (result i32) (i32.const 2)))"#).unwrap();
+        let mut list = AwwasmAllowList::new();
+        list.insert_module(&approved);
+        let mut store = AwwasmStore::new();
+        store.set_module_gate(Some(Arc::new(list)));
+
+        assert!(store.instantiate_bytes(&approved, &mut AwwasmImports::new()).is_ok());
+        let err = store.instantiate_bytes(&other, &mut AwwasmImports::new()).unwrap_err();
+        assert_eq!(err.code(), 1016);
+        assert_eq!(
+            err,
+            AwwasmInstantiationError::ModuleNotPermitted { hash: Some(AwwasmModuleHash::of(&other).to_string()) }
+        );
+
+        // A parsed module cannot be hashed, so a gated store refuses it.
+        let mut module = AwwasmModule::new(&approved).unwrap();
+        module.resolve_all_sections().unwrap();
+        assert_eq!(
+            store.store_init(&module, &mut AwwasmImports::new()),
+            Err(AwwasmInstantiationError::ModuleNotPermitted { hash: None })
+        );
+
+        let mut store = AwwasmStore::new();
+        store.set_module_gate(Some(Arc::new(AwwasmModuleVerifier::new(|_, bytes| bytes.len() < 64))));
+        assert!(store.instantiate_bytes(&other, &mut AwwasmImports::new()).is_ok());
+        assert!(store.instantiate_bytes(&[0; 64], &mut AwwasmImports::new()).is_err());
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn test_import_capability_policy() {
+        use capability::AwwasmCapabilityPolicy;
+
+        let wasm = wat::parse_str(r#"
+            (module
+                (import "wasi_snapshot_preview1" "fd_write" (func (param i32 i32 i32 i32) (result i32)))
+                (import "env" "network_connect" (func (param i32) (result i32))))
+        "#).unwrap();
+        // One registry offers both imports to every plugin.
+        let registry = || {
+            AwwasmImports::builder()
+                .func("wasi_snapshot_preview1", "fd_write", AwwasmFuncInst::host(0, 0))
+                .func("wasi_snapshot_preview1", "path_open", AwwasmFuncInst::host(0, 1))
+                .func("env", "network_connect", AwwasmFuncInst::host(0, 2))
+        };
+
+        let mut store = AwwasmStore::new();
+        let mut trusted = registry().build();
+        assert!(store.instantiate_bytes(&wasm, &mut trusted).is_ok());
+
+        let sandboxed = AwwasmCapabilityPolicy::allow_all()
+            .deny("wasi_snapshot_preview1", "*")
+            .allow("wasi_snapshot_preview1", "fd_write")
+            .deny("env", "network_*");
+        let mut untrusted = registry().policy(sandboxed.clone()).build();
+        assert!(!untrusted.permits(b"wasi_snapshot_preview1", b"path_open"));
+        let err = store.instantiate_bytes(&wasm, &mut untrusted).unwrap_err();
+        assert_eq!(err.code(), 1017);
+        assert_eq!(
+            err,
+            AwwasmInstantiationError::ImportDenied { module: "env".into(), name: "network_connect".into() }
+        );
+
+        // Allowing the one import back, later rules winning, admits it.
+        let mut relaxed = registry().policy(sandboxed.allow("env", "network_connect")).build();
+        assert!(store.instantiate_bytes(&wasm, &mut relaxed).is_ok());
+        let mut locked = registry().policy(AwwasmCapabilityPolicy::deny_all()).build();
+        assert!(store.instantiate_bytes(&wasm, &mut locked).is_err());
+    }
+
+    #[test]
+    fn test_instantiation_cost_limits() {
+        use store::AwwasmInstantiationLimits;
+
+        let wasm = wat::parse_str(r#"
+            (module
+                (memory 1)
+                (table 8 funcref)
+                (global i32 (i32.const 1))
+                (func $f)
+                (elem (i32.const 0) $f $f $f)
+                (elem declare func $f)
+                (data (i32.const 0) "0123456789")
+                (data "passive, never copied"))
+        "#).unwrap();
+        let exceeded = |limits| {
+            let mut store = AwwasmStore::new();
+            store.set_instantiation_limits(limits);
+            store.instantiate_bytes(&wasm, &mut AwwasmImports::new()).err()
+        };
+        let unlimited = AwwasmInstantiationLimits::default();
+
+        // 10 active data bytes, 3 constant expressions (the global and two
+        // offsets), 4 element entries.
+        let exact = AwwasmInstantiationLimits { data_bytes: 10, const_exprs: 3, elements: 4 };
+        assert_eq!(exceeded(exact), None);
+        let err = exceeded(AwwasmInstantiationLimits { data_bytes: 9, ..unlimited }).unwrap();
+        assert_eq!(err.code(), 1018);
+        assert_eq!(
+            err,
+            AwwasmInstantiationError::InstantiationCostExceeded { cost: AwwasmInstantiationCost::DataBytes, max: 9, actual: 10 }
+        );
+        assert_eq!(err.to_string(), "instantiating the module takes 10 bytes of active data segments, at most 9 allowed");
+        assert_eq!(
+            exceeded(AwwasmInstantiationLimits { const_exprs: 2, ..unlimited }),
+            Some(AwwasmInstantiationError::InstantiationCostExceeded { cost: AwwasmInstantiationCost::ConstExprs, max: 2, actual: 3 })
+        );
+        assert_eq!(
+            exceeded(AwwasmInstantiationLimits { elements: 3, ..unlimited }),
+            Some(AwwasmInstantiationError::InstantiationCostExceeded { cost: AwwasmInstantiationCost::Elements, max: 3, actual: 4 })
+        );
+    }
+
+    #[test]
+    fn test_import_export_name_limits() {
+        use store::AwwasmModuleLimits;
+
+        let wasm = wat::parse_str(r#"
+            (module
+                (import "environment" "f" (func))
+                (func (export "run")))
+        "#).unwrap();
+        let instantiate = |wasm: &[u8], limits| {
+            let mut store = AwwasmStore::new();
+            store.set_module_limits(limits);
+            let mut imports = AwwasmImports::new();
+            imports.add_func(b"environment", b"f", AwwasmFuncInst::host(0, 0));
+            imports.add_func(b"env", b"\xff", AwwasmFuncInst::host(0, 0));
+            store.instantiate_bytes(wasm, &mut imports).map(|_| ())
+        };
+        let strict = AwwasmModuleLimits { import_name_bytes: 11, export_name_bytes: 3, utf8_names: true, ..AwwasmModuleLimits::default() };
+        assert_eq!(instantiate(&wasm, strict), Ok(()));
+        assert_eq!(
+            instantiate(&wasm, AwwasmModuleLimits { import_name_bytes: 10, ..strict }),
+            Err(AwwasmInstantiationError::ModuleLimitExceeded { limit: AwwasmModuleLimit::ImportNameBytes, max: 10, actual: 11 })
+        );
+
+        // (import "env" "\ff" (func)), which the text format cannot spell.
+        let mut raw = b"\0asm\x01\0\0\0\x01\x04\x01\x60\0\0".to_vec();
+        raw.extend_from_slice(b"\x02\x09\x01\x03env\x01\xff\x00\x00");
+        assert_eq!(instantiate(&raw, AwwasmModuleLimits::default()), Ok(()));
+        let err = instantiate(&raw, strict).unwrap_err();
+        assert_eq!(err, AwwasmInstantiationError::InvalidName { position: AwwasmNamePosition::ImportField, index: 0 });
+        assert_eq!(err.code(), 1019);
+        assert_eq!(err.to_string(), "field name of import 0 is not valid UTF-8");
+    }
+
+    #[test]
+    fn test_bounds_check_strategies() {
+        use memory::AwwasmBoundsCheck;
+
+        let wasm = wat::parse_str(r#"
+            (module
+                (memory 1)
+                (func (export "load") (param i32) (result i32) (i32.load (local.get 0)))
+                (func (export "store") (param i32 i32) (i32.store offset=4 (local.get 0) (local.get 1))))
+        "#).unwrap();
+        let run = |strategy| {
+            let mut store = AwwasmStore::new();
+            store.set_bounds_check(strategy);
+            let addr = store.instantiate_bytes(&wasm, &mut AwwasmImports::new()).unwrap();
+            let mut call = |name, args: &[AwwasmValue]| {
+                store.invoke_export(addr, name, args, &mut NoHost).map_err(|e| e.trap().cloned())
+            };
+            let stored = call("store", &[AwwasmValue::I32(0), AwwasmValue::I32(7)]);
+            let wrapped = call("load", &[AwwasmValue::I32(65536 + 4)]);
+            let straddling = call("load", &[AwwasmValue::I32(65534)]);
+            (stored, wrapped, straddling)
+        };
+        let oob = |offset| Err(Some(AwwasmTrap::MemoryOutOfBounds { offset, size: 4, memory_size: 65536 }));
+
+        // Explicit and guarded checks trap identically.
+        for strategy in [AwwasmBoundsCheck::Explicit, AwwasmBoundsCheck::Guarded] {
+            assert_eq!(run(strategy), (Ok(vec![]), oob(65540), oob(65534)));
+        }
+        // Masking wraps an address past the 64 KiB window around to 4,
+        // but an access straddling the end still traps.
+        assert_eq!(run(AwwasmBoundsCheck::Masked), (Ok(vec![]), Ok(vec![AwwasmValue::I32(7)]), oob(65534)));
+        assert_eq!(AwwasmStore::new().bounds_check(), AwwasmBoundsCheck::Explicit);
+    }
+
+    #[test]
+    fn test_body_limits() {
+        use exec::MAX_FUNCTION_LOCALS;
+        use ir::MAX_BLOCK_NESTING;
+
+        let call = |body: &str| {
+            let wasm = wat::parse_str(format!(r#"(module (func (export "f") {}))"#, body)).unwrap();
+            let mut store = AwwasmStore::new();
+            let addr = store.instantiate_bytes(&wasm, &mut AwwasmImports::new()).unwrap();
+            store.invoke_export(addr, "f", &[], &mut NoHost).map_err(|e| e.root().clone())
+        };
+        let locals = |n| format!("(local{})", " i64".repeat(n as usize));
+        let nested = |n| format!("{}{}", "block ".repeat(n as usize), "end ".repeat(n as usize));
+
+        assert_eq!(call(&locals(MAX_FUNCTION_LOCALS)), Ok(vec![]));
+        let err = call(&locals(MAX_FUNCTION_LOCALS + 1)).unwrap_err();
+        assert_eq!(
+            err,
+            AwwasmRuntimeError::BodyLimitExceeded { limit: AwwasmBodyLimit::Locals, max: MAX_FUNCTION_LOCALS, actual: 50_001 }
+        );
+        assert_eq!(err.code(), 3016);
+        assert_eq!(err.to_string(), "function body has 50001 locals, at most 50000 allowed");
+
+        assert_eq!(call(&nested(MAX_BLOCK_NESTING)), Ok(vec![]));
+        assert_eq!(
+            call(&nested(MAX_BLOCK_NESTING + 1)),
+            Err(AwwasmRuntimeError::BodyLimitExceeded {
+                limit: AwwasmBodyLimit::BlockNesting,
+                max: MAX_BLOCK_NESTING,
+                actual: u64::from(MAX_BLOCK_NESTING) + 1,
+            })
+        );
+    }
+
+    #[test]
+    fn test_zeroize_on_drop() {
+        let wasm = wat::parse_str(r#"
+            (module
+                (memory (export "mem") 1)
+                (table 1 funcref)
+                (global (mut i64) (i64.const 42))
+                (func $f)
+                (elem (i32.const 0) $f)
+                (data (i32.const 8) "secret")
+                (func (export "grow") (param i32) (result i32) (memory.grow (local.get 0))))
+        "#).unwrap();
+        let mut store = AwwasmStore::new();
+        assert!(!store.zeroize_on_drop());
+        let addr = store.instantiate_bytes(&wasm, &mut AwwasmImports::new()).unwrap();
+        store.set_zeroize_on_drop(true);
+        assert!(store.zeroize_on_drop());
+
+        // Growing past the capacity moves the bytes without losing them.
+        let grown = store.invoke_export(addr, "grow", &[AwwasmValue::I32(3)], &mut NoHost).unwrap();
+        assert_eq!(grown, vec![AwwasmValue::I32(1)]);
+        assert_eq!(store.mems[0].size_pages(), 4);
+        assert_eq!(&store.mems[0].data.to_vec()[8..14], b"secret");
+
+        store.mems[0].wipe();
+        assert!(store.mems[0].data.to_vec().iter().all(|&b| b == 0));
+        drop(store);
+
+        // Off by default: wiping leaves the bytes alone.
+        let mut store = AwwasmStore::new();
+        store.instantiate_bytes(&wasm, &mut AwwasmImports::new()).unwrap();
+        store.mems[0].wipe();
+        assert_eq!(&store.mems[0].data.to_vec()[8..14], b"secret");
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn test_host_call_trail() {
+        use core::sync::atomic::{AtomicU64, Ordering};
+        use host_trail::{AwwasmHostCall, AwwasmHostCallTrail};
+
+        /// Every reading advances the clock by 10 nanoseconds.
+        #[derive(Debug, Default)]
+        struct TickPlatform(AtomicU64);
+
+        impl AwwasmHostPlatform for TickPlatform {
+            fn monotonic_nanos(&self) -> u64 {
+                self.0.fetch_add(10, Ordering::SeqCst)
+            }
+
+            fn fill_entropy(&self, _buf: &mut [u8]) -> bool {
+                false
+            }
+        }
+
+        struct Doubler;
+
+        impl AwwasmHost for Doubler {
+            fn call(&mut self, host_func_id: u32, args: &[AwwasmValue], _: Option<&mut AwwasmMemInst>) -> Result<Vec<AwwasmValue>, AwwasmTrap> {
+                match host_func_id {
+                    1 => Ok(vec![AwwasmValue::I32(args[0].as_i32().unwrap() * 2)]),
+                    _ => Err(AwwasmTrap::InvalidHostCall { host_func_id }),
+                }
+            }
+        }
+
+        let wasm = wat::parse_str(r#"
+            (module
+                (import "env" "double" (func $double (param i32) (result i32)))
+                (import "env" "fail" (func $fail))
+                (func (export "run") (param i32) (result i32) (call $double (local.get 0)))
+                (func (export "fail") (call $fail)))
+        "#).unwrap();
+        let mut imports = AwwasmImports::builder()
+            .func("env", "double", AwwasmFuncInst::host(0, 1))
+            .func("env", "fail", AwwasmFuncInst::host(0, 2))
+            .build();
+        let mut store = AwwasmStore::new();
+        let addr = store.instantiate_bytes(&wasm, &mut imports).unwrap();
+        store.set_platform(alloc::sync::Arc::new(TickPlatform::default()));
+        let trail = alloc::sync::Arc::new(AwwasmHostCallTrail::new(2));
+        store.set_host_call_auditor(Some(trail.clone()));
+
+        for arg in 1..=3 {
+            store.invoke_export(addr, "run", &[AwwasmValue::I32(arg)], &mut Doubler).unwrap();
+        }
+        // Only the last two calls are kept.
+        let calls = trail.calls();
+        assert_eq!(trail.dropped(), 1);
+        assert_eq!(
+            calls[1],
+            AwwasmHostCall {
+                host_func_id: 1,
+                import_module: "env".into(),
+                import_name: "double".into(),
+                module: Some(addr),
+                args: vec![AwwasmValue::I32(3)],
+                result: Ok(vec![AwwasmValue::I32(6)]),
+                nanos: Some(10),
+            }
+        );
+        assert_eq!(calls[0].args, [AwwasmValue::I32(2)]);
+
+        // A trapping call is recorded with its trap.
+        assert!(store.invoke_export(addr, "fail", &[], &mut Doubler).is_err());
+        let calls = trail.take();
+        assert_eq!((calls.len(), calls[1].import_name.as_str()), (2, "fail"));
+        assert_eq!(calls[1].result, Err(AwwasmTrap::InvalidHostCall { host_func_id: 2 }));
+        assert!(trail.calls().is_empty());
+    }
+
+    #[test]
+    fn test_export_aliases() {
+        let math = wat::parse_str(r#"
+            (module
+                (func (export "add") (param i32 i32) (result i32) (i32.add (local.get 0) (local.get 1)))
+                (table (export "table") 1 funcref))
+        "#).unwrap();
+        let strings = wat::parse_str(r#"
+            (module (func (export "len_v2") (result i32) (i32.const 5)))
+        "#).unwrap();
+        let mut store = AwwasmStore::new();
+        let math = store.instantiate_bytes(&math, &mut AwwasmImports::new()).unwrap();
+        let strings = store.instantiate_bytes(&strings, &mut AwwasmImports::new()).unwrap();
+
+        let facade = store
+            .alias_exports(&[
+                AwwasmExportAlias::new("sum", math, "add"),
+                AwwasmExportAlias::new("len", strings, "len_v2"),
+                AwwasmExportAlias::new("funcs", math, "table"),
+            ])
+            .unwrap();
+        let args = [AwwasmValue::I32(2), AwwasmValue::I32(3)];
+        assert_eq!(store.invoke_export(facade, "sum", &args, &mut NoHost).unwrap(), vec![AwwasmValue::I32(5)]);
+        assert_eq!(store.module(facade).unwrap().get_table("funcs"), store.module(math).unwrap().get_table("table"));
+        assert!(store.module(facade).unwrap().export_by_str("add").is_none());
+
+        // Guests import from the facade, not from the helper modules.
+        let guest = wat::parse_str(r#"
+            (module
+                (import "api" "sum" (func $sum (param i32 i32) (result i32)))
+                (import "api" "len" (func $len (result i32)))
+                (import "api" "funcs" (table 1 funcref))
+                (func (export "run") (result i32) (call $sum (call $len) (i32.const 1))))
+        "#).unwrap();
+        let mut imports = AwwasmImports::new();
+        store.add_export_imports(facade, "api", &mut imports).unwrap();
+        let guest = store.instantiate_bytes(&guest, &mut imports).unwrap();
+        assert_eq!(store.invoke_export(guest, "run", &[], &mut NoHost).unwrap(), vec![AwwasmValue::I32(6)]);
+
+        let err = store.alias_exports(&[AwwasmExportAlias::new("sub", math, "sub")]).unwrap_err();
+        assert_eq!(err, AwwasmRuntimeError::ExportNotFound("sub".into()));
+        let err = store.add_export_imports(AwwasmModuleAddr(99), "api", &mut AwwasmImports::new()).unwrap_err();
+        assert_eq!((err.clone(), err.code()), (AwwasmRuntimeError::InvalidModuleAddr(99), 3018));
+    }
+
+    #[test]
+    fn test_exports_with_types() {
+        use func::AwwasmFuncType;
+        use info::AwwasmExternType;
+        use table::AwwasmElemType;
+
+        let wasm = wat::parse_str(r#"
+            (module
+                (func (export "add") (param i32 i64) (result f32) (f32.const 0))
+                (table (export "table") 2 10 funcref)
+                (memory (export "memory") 1 4)
+                (global (export "counter") (mut i64) (i64.const 0))
+                (func (export "grow") (drop (memory.grow (i32.const 1)))))
+        "#).unwrap();
+        let mut store = AwwasmStore::new();
+        let addr = store.instantiate_bytes(&wasm, &mut AwwasmImports::new()).unwrap();
+        store.invoke_export(addr, "grow", &[], &mut NoHost).unwrap();
+
+        let inst = store.module(addr).unwrap();
+        let exports: Vec<_> = inst.exports_with_types(&store).collect();
+        let names: Vec<_> = exports.iter().map(|(name, _)| *name).collect();
+        assert_eq!(names, [&b"add"[..], b"table", b"memory", b"counter", b"grow"]);
+        assert_eq!(
+            exports[0].1,
+            AwwasmExternType::Func(AwwasmFuncType::new(
+                vec![AwwasmValueType::I32, AwwasmValueType::I64],
+                vec![AwwasmValueType::F32],
+            ))
+        );
+        assert_eq!(exports[1].1, AwwasmExternType::Table(AwwasmTableType { min: 2, max: Some(10), elem_type: AwwasmElemType::FuncRef }));
+        // Memory limits reflect the current size.
+        assert_eq!(exports[2].1, AwwasmExternType::Memory(AwwasmMemoryType::new(2, Some(4))));
+        assert_eq!(exports[3].1, AwwasmExternType::Global(AwwasmGlobalType::mutable(AwwasmValueType::I64)));
+        assert_eq!(exports[4].1.kind(), AwwasmExternKind::Func);
+    }
+
+    #[test]
+    fn test_start_function_recorded() {
+        let wasm = wat::parse_str(r#"
+            (module
+                (import "env" "log" (func))
+                (global $ran (mut i32) (i32.const 0))
+                (func $init (global.set $ran (i32.const 1)))
+                (func (export "ran") (result i32) (global.get $ran))
+                (start $init))
+        "#).unwrap();
+        let mut imports = AwwasmImports::builder().func("env", "log", AwwasmFuncInst::host(0, 0)).build();
+        let mut store = AwwasmStore::new();
+        let addr = store.instantiate_bytes(&wasm, &mut imports).unwrap();
+        // The index counts the import, and the function is not run yet.
+        let inst = store.module(addr).unwrap();
+        assert_eq!(inst.start, inst.func(1));
+        assert_eq!(store.invoke_export(addr, "ran", &[], &mut NoHost).unwrap(), vec![AwwasmValue::I32(0)]);
+        let start = store.module(addr).unwrap().start.unwrap();
+        store.invoke(start, &[], &mut NoHost).unwrap();
+        assert_eq!(store.invoke_export(addr, "ran", &[], &mut NoHost).unwrap(), vec![AwwasmValue::I32(1)]);
+
+        let plain = wat::parse_str("(module (func))").unwrap();
+        let addr = store.instantiate_bytes(&plain, &mut AwwasmImports::new()).unwrap();
+        assert_eq!(store.module(addr).unwrap().start, None);
+
+        // A start section naming function 5 of 1, placed before the
+        // code section after the header, type and function sections.
+        let mut bad = plain.clone();
+        assert_eq!(bad[18], 0x0a);
+        bad.splice(18..18, [0x08, 0x01, 0x05]);
+        let err = store.instantiate_bytes(&bad, &mut AwwasmImports::new()).unwrap_err();
+        assert_eq!(err, AwwasmInstantiationError::StartIndexOutOfBounds { index: 5, count: 1 });
+        assert_eq!(err.code(), 1020);
+    }
+
+    #[test]
+    fn test_interrupt_sleep_without_deadline() {
+        let handle = AwwasmInterruptHandle::new();
+        let waker = {
+            let handle = handle.clone();
+            std::thread::spawn(move || {
+                std::thread::sleep(std::time::Duration::from_millis(20));
+                handle.interrupt();
+            })
+        };
+        assert!(!handle.sleep(std::time::Duration::MAX));
+        waker.join().unwrap();
+    }
+
+    #[test]
+    fn test_numeric_dispatch() {
+        let wasm = wat::parse_str(r#"
+            (module
+                (func (export "first") (result i32) i32.const 0 i32.eqz)
+                (func (export "last") (result i64) i64.const 0x1_8000_0000 i64.extend32_s)
+                (func (export "div") (param i32 i32) (result i32) local.get 0 local.get 1 i32.div_s)
+                (func (export "rotl") (result i64) i64.const 1 i64.const 65 i64.rotl)
+                (func (export "copysign") (result f32) f32.const 2 f32.const -0 f32.copysign)
+            )
+        "#).unwrap();
+        let mut store = AwwasmStore::new();
+        let addr = store.instantiate_bytes(&wasm, &mut AwwasmImports::new()).unwrap();
+        let mut run = |name, args: &[AwwasmValue]| store.invoke_export(addr, name, args, &mut NoHost);
+        assert_eq!(run("first", &[]).unwrap(), vec![AwwasmValue::I32(1)]);
+        assert_eq!(run("last", &[]).unwrap(), vec![AwwasmValue::I64(i64::from(i32::MIN))]);
+        assert_eq!(run("div", &[AwwasmValue::I32(-7), AwwasmValue::I32(2)]).unwrap(), vec![AwwasmValue::I32(-3)]);
+        let err = run("div", &[AwwasmValue::I32(1), AwwasmValue::I32(0)]).unwrap_err();
+        assert_eq!(err.trap(), Some(&AwwasmTrap::DivisionByZero));
+        assert_eq!(run("rotl", &[]).unwrap(), vec![AwwasmValue::I64(2)]);
+        assert_eq!(run("copysign", &[]).unwrap(), vec![AwwasmValue::F32(-2.0)]);
+    }
+
+    #[test]
+    fn test_exec_arena() {
+        let wasm = wat::parse_str(r#"
+            (module
+                (func $fib (export "fib") (param i32) (result i32)
+                    local.get 0 i32.const 2 i32.lt_u
+                    if (result i32) local.get 0
+                    else
+                        local.get 0 i32.const 1 i32.sub call $fib
+                        local.get 0 i32.const 2 i32.sub call $fib
+                        i32.add
+                    end)
+            )
+        "#).unwrap();
+        let limits = AwwasmStackLimits { call_depth: 32, value_stack: 256, label_depth: 128 };
+        let arena = AwwasmExecArena::new(limits).unwrap();
+        assert_eq!(arena.limits(), limits);
+        let (values, frames) = (arena.value_capacity(), arena.frame_capacity());
+        assert!(values >= 256 && frames >= 32 && arena.label_capacity() >= 128);
+
+        let mut store = AwwasmStore::with_arena(arena);
+        assert_eq!(store.stack_limits(), limits);
+        // Limits can shrink within the arena but never outgrow it.
+        let err = store.set_stack_limits(AwwasmStackLimits { label_depth: 129, ..limits }).unwrap_err();
+        assert_eq!((err.code(), store.stack_limits()), (3019, limits));
+        store.set_stack_limits(AwwasmStackLimits { value_stack: 128, ..limits }).unwrap();
+        store.set_stack_limits(limits).unwrap();
+        let addr = store.instantiate_bytes(&wasm, &mut AwwasmImports::new()).unwrap();
+        for _ in 0..2 {
+            let results = store.invoke_export(addr, "fib", &[AwwasmValue::I32(15)], &mut NoHost).unwrap();
+            assert_eq!(results, vec![AwwasmValue::I32(610)]);
+        }
+        // Too deep for the arena: traps without growing it.
+        let err = store.invoke_export(addr, "fib", &[AwwasmValue::I32(40)], &mut NoHost).unwrap_err();
+        assert_eq!(err.trap(), Some(&AwwasmTrap::CallStackExhausted));
+
+        let arena = store.take_arena().unwrap();
+        assert_eq!((arena.value_capacity(), arena.frame_capacity()), (values, frames));
+        assert!(store.take_arena().is_none());
+        assert_eq!(store.invoke_export(addr, "fib", &[AwwasmValue::I32(10)], &mut NoHost).unwrap(), vec![AwwasmValue::I32(55)]);
+    }
+}
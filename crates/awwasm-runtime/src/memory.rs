@@ -5,12 +5,38 @@
 
 #[cfg(feature = "alloc")]
 use alloc::vec::Vec;
+#[cfg(feature = "alloc")]
+use alloc::string::String;
+#[cfg(feature = "alloc")]
+use alloc::sync::Arc;
+#[cfg(feature = "alloc")]
+use alloc::borrow::Cow;
+#[cfg(feature = "alloc")]
+use alloc::boxed::Box;
 
-use crate::error::AwwasmTrap;
+use core::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+#[cfg(feature = "std")]
+use std::collections::{BTreeMap, VecDeque};
+#[cfg(feature = "std")]
+use std::sync::{Condvar, Mutex, MutexGuard};
+#[cfg(feature = "std")]
+use std::time::{Duration, Instant};
+
+use core::fmt::Write;
+use core::ops::Range;
+
+use crate::audit::{AwwasmMemAccess, AwwasmMemAccessKind, AwwasmMemAccessor, AwwasmMemoryAuditor};
+use crate::error::{AwwasmInstantiationError, AwwasmTrap};
 
 /// WebAssembly page size in bytes (64 KiB).
 pub const PAGE_SIZE: usize = 65536;
 
+/// Maximum number of 64 KiB pages of a 32-bit memory (4 GiB).
+pub const MAX_PAGES: u32 = 65536;
+
+/// log2 of the default page size.
+pub const DEFAULT_PAGE_SIZE_LOG2: u32 = 16;
+
 /// Memory type - describes the limits of a memory.
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub struct AwwasmMemoryType {
@@ -18,42 +44,626 @@ pub struct AwwasmMemoryType {
     pub min: u32,
     /// Maximum number of pages (if specified).
     pub max: Option<u32>,
+    /// log2 of the page size in bytes: 16 (64 KiB) unless the module
+    /// uses the custom-page-sizes proposal, which also allows 0 (1 byte).
+    pub page_size_log2: u32,
+    /// Whether the memory is shared between threads (threads proposal).
+    /// Shared memories always have a maximum.
+    pub shared: bool,
 }
 
 impl AwwasmMemoryType {
-    /// Create a new memory type.
+    /// Create a new memory type with 64 KiB pages.
     pub fn new(min: u32, max: Option<u32>) -> Self {
-        Self { min, max }
+        Self { min, max, page_size_log2: DEFAULT_PAGE_SIZE_LOG2, shared: false }
+    }
+
+    /// Make the memory shared (or not).
+    pub fn with_shared(mut self, shared: bool) -> Self {
+        self.shared = shared;
+        self
+    }
+
+    /// Use pages of `2^log2` bytes. Only 0 and 16 are valid.
+    pub fn with_page_size_log2(mut self, log2: u32) -> Self {
+        self.page_size_log2 = log2;
+        self
+    }
+
+    /// Page size in bytes.
+    pub fn page_size(&self) -> usize {
+        1 << self.page_size_log2
+    }
+
+    /// Most pages a 32-bit memory of this page size can have (4 GiB,
+    /// or one byte less for 1-byte pages so the count fits `u32`).
+    pub fn max_pages(&self) -> u32 {
+        ((1u64 << 32) >> self.page_size_log2).min(u32::MAX as u64) as u32
+    }
+
+    /// Whether the page size is one the custom-page-sizes proposal allows.
+    pub fn valid_page_size(&self) -> bool {
+        matches!(self.page_size_log2, 0 | DEFAULT_PAGE_SIZE_LOG2)
+    }
+}
+
+impl core::fmt::Display for AwwasmMemoryType {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        write!(f, "(memory {}", self.min)?;
+        if let Some(max) = self.max {
+            write!(f, " {}", max)?;
+        }
+        if self.shared {
+            f.write_str(" shared")?;
+        }
+        if self.page_size_log2 != DEFAULT_PAGE_SIZE_LOG2 {
+            write!(f, " (pagesize {})", self.page_size())?;
+        }
+        f.write_str(")")
+    }
+}
+
+/// Backing storage of a linear memory.
+///
+/// Unshared bytes can be borrowed with `as_slice` and `as_mut_slice`.
+/// Other threads may change a shared memory's bytes at any time, so they
+/// are only ever copied in and out, one atomic access at a time.
+#[derive(Debug)]
+pub enum AwwasmMemData {
+    /// Heap-allocated bytes that grow with `memory.grow`.
+    Heap(Vec<u8>),
+    /// A caller-provided buffer, e.g. in a reserved linker section.
+    /// Never grows.
+    Static(&'static mut [u8]),
+    /// A buffer shared with every clone of the memory, possibly in
+    /// stores on other threads.
+    Shared(AwwasmSharedMemory),
+}
+
+impl AwwasmMemData {
+    /// Heap bytes held, counting unused capacity; zero for a static
+    /// buffer.
+    pub fn heap_bytes(&self) -> usize {
+        match self {
+            AwwasmMemData::Heap(v) => v.capacity(),
+            AwwasmMemData::Static(_) => 0,
+            AwwasmMemData::Shared(s) => s.capacity(),
+        }
+    }
+
+    /// Current size in bytes.
+    pub fn len(&self) -> usize {
+        match self {
+            AwwasmMemData::Heap(v) => v.len(),
+            AwwasmMemData::Static(s) => s.len(),
+            AwwasmMemData::Shared(s) => s.len(),
+        }
+    }
+
+    /// Whether the memory is empty.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// The bytes, or `None` for a shared memory.
+    pub fn as_slice(&self) -> Option<&[u8]> {
+        match self {
+            AwwasmMemData::Heap(v) => Some(v.as_slice()),
+            AwwasmMemData::Static(s) => Some(&s[..]),
+            AwwasmMemData::Shared(_) => None,
+        }
+    }
+
+    /// The bytes, mutably, or `None` for a shared memory.
+    pub fn as_mut_slice(&mut self) -> Option<&mut [u8]> {
+        match self {
+            AwwasmMemData::Heap(v) => Some(v.as_mut_slice()),
+            AwwasmMemData::Static(s) => Some(&mut s[..]),
+            AwwasmMemData::Shared(_) => None,
+        }
+    }
+
+    /// A copy of the bytes.
+    pub fn to_vec(&self) -> Vec<u8> {
+        match self {
+            AwwasmMemData::Shared(s) => {
+                let mut out = alloc::vec![0; s.len()];
+                s.read(0, &mut out);
+                out
+            }
+            _ => self.as_slice().unwrap_or_default().to_vec(),
+        }
+    }
+
+    /// The bytes in `range`, copied out of a shared memory; `None` if
+    /// out of bounds.
+    pub(crate) fn get(&self, range: Range<usize>) -> Option<Cow<'_, [u8]>> {
+        match self {
+            AwwasmMemData::Shared(s) => {
+                s.in_bounds(range.start, range.len())?;
+                let mut out = alloc::vec![0; range.len()];
+                s.read(range.start, &mut out);
+                Some(Cow::Owned(out))
+            }
+            _ => self.as_slice()?.get(range).map(Cow::Borrowed),
+        }
+    }
+
+    /// Copy the bytes at `start` into `out`; `None` if out of bounds.
+    pub(crate) fn read_into(&self, start: usize, out: &mut [u8]) -> Option<()> {
+        match self {
+            AwwasmMemData::Shared(s) => {
+                s.in_bounds(start, out.len())?;
+                s.read(start, out);
+            }
+            _ => out.copy_from_slice(self.as_slice()?.get(start..start.checked_add(out.len())?)?),
+        }
+        Some(())
+    }
+
+    /// Copy `bytes` to `start`; `None` if out of bounds.
+    pub(crate) fn write(&mut self, start: usize, bytes: &[u8]) -> Option<()> {
+        match self {
+            AwwasmMemData::Shared(s) => {
+                s.in_bounds(start, bytes.len())?;
+                s.write(start, bytes.len(), |i| bytes[i]);
+            }
+            _ => self.as_mut_slice()?.get_mut(start..start.checked_add(bytes.len())?)?.copy_from_slice(bytes),
+        }
+        Some(())
+    }
+
+    /// Set the bytes in `range` to `value`; `None` if out of bounds.
+    pub(crate) fn fill(&mut self, range: Range<usize>, value: u8) -> Option<()> {
+        match self {
+            AwwasmMemData::Shared(s) => {
+                s.in_bounds(range.start, range.len())?;
+                s.write(range.start, range.len(), |_| value);
+            }
+            _ => self.as_mut_slice()?.get_mut(range)?.fill(value),
+        }
+        Some(())
+    }
+
+    /// Copy the bytes in `src` to `dst`, which may overlap; `None` if
+    /// either is out of bounds.
+    pub(crate) fn copy_within(&mut self, src: Range<usize>, dst: usize) -> Option<()> {
+        match self {
+            AwwasmMemData::Shared(s) => {
+                s.in_bounds(src.start, src.len())?;
+                s.in_bounds(dst, src.len())?;
+                s.copy_within(src, dst);
+            }
+            _ => {
+                let data = self.as_mut_slice()?;
+                data.get(dst..dst.checked_add(src.len())?)?;
+                data.get(src.clone())?;
+                data.copy_within(src, dst);
+            }
+        }
+        Some(())
+    }
+}
+
+/// A static buffer can only be borrowed once, so its clone is an
+/// ordinary heap memory with the same contents. A shared memory's clone
+/// is another handle on the same bytes.
+impl Clone for AwwasmMemData {
+    fn clone(&self) -> Self {
+        match self {
+            AwwasmMemData::Shared(s) => AwwasmMemData::Shared(s.clone()),
+            _ => AwwasmMemData::Heap(self.to_vec()),
+        }
+    }
+}
+
+/// The bytes of a shared memory.
+///
+/// The whole maximum is allocated when the memory is created, so the
+/// bytes never move and every handle sees every grow. They are held as
+/// 64-bit atomic words: byte `i` is bits `8 * (i % 8)` and up of word
+/// `i / 8`, so a naturally aligned 4- or 8-byte value reads as its
+/// little-endian bytes. Handles on different threads access the bytes
+/// concurrently, as the threads proposal specifies: plain accesses are
+/// relaxed atomic accesses that race like they do in the guest, and only
+/// atomic instructions order them.
+#[derive(Debug, Clone)]
+pub struct AwwasmSharedMemory {
+    buf: Arc<AwwasmSharedBuf>,
+}
+
+#[derive(Debug)]
+struct AwwasmSharedBuf {
+    words: Box<[AtomicU64]>,
+    capacity: usize,
+    /// Current size in bytes; only ever grows.
+    len: AtomicUsize,
+    /// Threads blocked in `memory.atomic.wait`, by address, oldest first.
+    #[cfg(feature = "std")]
+    waiters: Mutex<BTreeMap<u32, VecDeque<Arc<AwwasmWaiter>>>>,
+}
+
+/// One thread blocked in `memory.atomic.wait`.
+#[cfg(feature = "std")]
+#[derive(Debug, Default)]
+struct AwwasmWaiter {
+    /// Set by `notify` when it takes the waiter off its queue; the only
+    /// way a wait ends with `Ok`, so spurious condvar wakeups are absorbed.
+    woken: Mutex<bool>,
+    cond: Condvar,
+}
+
+#[cfg(feature = "std")]
+fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    mutex.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+/// Outcome of `memory.atomic.wait`, as the guest sees it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AwwasmWaitResult {
+    /// Woken by `memory.atomic.notify`.
+    Ok = 0,
+    /// The memory did not hold the expected value.
+    NotEqual = 1,
+    /// The timeout passed first.
+    TimedOut = 2,
+}
+
+/// How many bytes `copy_within` moves at a time on a shared memory.
+const COPY_CHUNK: usize = 4096;
+
+impl AwwasmSharedMemory {
+    /// Whether this is the only handle on the bytes.
+    pub(crate) fn is_unique(&self) -> bool {
+        Arc::strong_count(&self.buf) == 1
+    }
+
+    /// `len` zero bytes that can grow to `capacity`, or `None` if the
+    /// allocator refuses.
+    fn new(len: usize, capacity: usize) -> Option<Self> {
+        let mut words = Vec::new();
+        words.try_reserve_exact(capacity.div_ceil(8)).ok()?;
+        words.resize_with(capacity.div_ceil(8), || AtomicU64::new(0));
+        let buf = AwwasmSharedBuf {
+            words: words.into_boxed_slice(),
+            capacity,
+            len: AtomicUsize::new(len),
+            #[cfg(feature = "std")]
+            waiters: Mutex::new(BTreeMap::new()),
+        };
+        Some(Self { buf: Arc::new(buf) })
+    }
+
+    /// Current size in bytes.
+    pub fn len(&self) -> usize {
+        self.buf.len.load(Ordering::Acquire)
+    }
+
+    /// Whether the memory is empty.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Bytes allocated, the size the memory can grow to.
+    pub fn capacity(&self) -> usize {
+        self.buf.capacity
+    }
+
+    /// Whether `other` is a handle on the same bytes.
+    pub fn same(&self, other: &AwwasmSharedMemory) -> bool {
+        Arc::ptr_eq(&self.buf, &other.buf)
+    }
+
+    /// Add `delta` bytes, returning the previous size. Other handles may
+    /// grow concurrently; each grow applies exactly once.
+    fn grow(&self, delta: usize) -> Option<usize> {
+        self.buf
+            .len
+            .fetch_update(Ordering::AcqRel, Ordering::Acquire, |len| {
+                len.checked_add(delta).filter(|&new| new <= self.buf.capacity)
+            })
+            .ok()
+    }
+
+    /// `Some` if the `len` bytes at `start` are in bounds.
+    fn in_bounds(&self, start: usize, len: usize) -> Option<()> {
+        (start.checked_add(len)? <= self.len()).then_some(())
+    }
+
+    /// Copy the bytes at `start`, which the caller has checked are in
+    /// bounds, into `out`.
+    fn read(&self, start: usize, out: &mut [u8]) {
+        let mut i = 0;
+        while i < out.len() {
+            let at = (start + i) % 8;
+            let n = (8 - at).min(out.len() - i);
+            let word = self.buf.words[(start + i) / 8].load(Ordering::Relaxed).to_le_bytes();
+            out[i..i + n].copy_from_slice(&word[at..at + n]);
+            i += n;
+        }
+    }
+
+    /// Set the `len` bytes at `start`, which the caller has checked are
+    /// in bounds, to `byte(0)`, `byte(1)`, and so on. Bytes of a word
+    /// outside the range are left as other threads write them.
+    fn write(&self, start: usize, len: usize, byte: impl Fn(usize) -> u8) {
+        let mut i = 0;
+        while i < len {
+            let at = (start + i) % 8;
+            let n = (8 - at).min(len - i);
+            let word = &self.buf.words[(start + i) / 8];
+            let merge = |old: u64| {
+                let mut bytes = old.to_le_bytes();
+                for (k, b) in bytes[at..at + n].iter_mut().enumerate() {
+                    *b = byte(i + k);
+                }
+                u64::from_le_bytes(bytes)
+            };
+            if n == 8 {
+                word.store(merge(0), Ordering::Relaxed);
+            } else {
+                let _ = word.fetch_update(Ordering::Relaxed, Ordering::Relaxed, |old| Some(merge(old)));
+            }
+            i += n;
+        }
+    }
+
+    /// Copy the bytes in `src` to `dst`, both checked to be in bounds,
+    /// a chunk at a time, in the direction that reads every source byte
+    /// before overwriting it.
+    fn copy_within(&self, src: Range<usize>, dst: usize) {
+        let mut chunk = [0u8; COPY_CHUNK];
+        let len = src.len();
+        let mut done = 0;
+        while done < len {
+            let n = COPY_CHUNK.min(len - done);
+            let at = if dst <= src.start { done } else { len - done - n };
+            self.read(src.start + at, &mut chunk[..n]);
+            self.write(dst + at, n, |i| chunk[i]);
+            done += n;
+        }
+    }
+
+    /// Atomically load the `u32` at `offset`, which the caller has
+    /// checked is in bounds and aligned.
+    fn load_u32(&self, offset: u32) -> u32 {
+        let word = self.buf.words[offset as usize / 8].load(Ordering::SeqCst);
+        (word >> (offset % 8 * 8)) as u32
+    }
+
+    /// Atomically load the `u64` at `offset`, which the caller has
+    /// checked is in bounds and aligned.
+    fn load_u64(&self, offset: u32) -> u64 {
+        self.buf.words[offset as usize / 8].load(Ordering::SeqCst)
+    }
+
+    /// Block until notified at `offset`, if `matches` (the expected-value
+    /// check) holds. A negative `timeout_nanos` waits forever.
+    ///
+    /// The check runs under the waiter table's lock, so a `notify` that
+    /// follows a store making it false cannot be missed.
+    #[cfg(feature = "std")]
+    fn wait(&self, offset: u32, matches: impl FnOnce() -> bool, timeout_nanos: i64) -> AwwasmWaitResult {
+        let mut waiters = lock(&self.buf.waiters);
+        if !matches() {
+            return AwwasmWaitResult::NotEqual;
+        }
+        let waiter = Arc::new(AwwasmWaiter::default());
+        waiters.entry(offset).or_default().push_back(waiter.clone());
+        drop(waiters);
+
+        // A deadline too far out to represent is as good as none.
+        let deadline = u64::try_from(timeout_nanos)
+            .ok()
+            .and_then(|nanos| Instant::now().checked_add(Duration::from_nanos(nanos)));
+        let mut woken = lock(&waiter.woken);
+        while !*woken {
+            match deadline {
+                None => woken = waiter.cond.wait(woken).unwrap_or_else(|e| e.into_inner()),
+                Some(deadline) => {
+                    let now = Instant::now();
+                    if now >= deadline {
+                        break;
+                    }
+                    woken = waiter.cond.wait_timeout(woken, deadline - now).unwrap_or_else(|e| e.into_inner()).0;
+                }
+            }
+        }
+        if *woken {
+            return AwwasmWaitResult::Ok;
+        }
+        drop(woken);
+
+        // Timed out: leave the queue, unless a notify took us off it
+        // (and counted us) in the meantime.
+        let mut waiters = lock(&self.buf.waiters);
+        let Some(queue) = waiters.get_mut(&offset) else {
+            return AwwasmWaitResult::Ok;
+        };
+        let Some(pos) = queue.iter().position(|w| Arc::ptr_eq(w, &waiter)) else {
+            return AwwasmWaitResult::Ok;
+        };
+        queue.remove(pos);
+        if queue.is_empty() {
+            waiters.remove(&offset);
+        }
+        AwwasmWaitResult::TimedOut
+    }
+
+    /// Without `std` there is no way to block: a matching wait times out
+    /// at once.
+    #[cfg(not(feature = "std"))]
+    fn wait(&self, _offset: u32, matches: impl FnOnce() -> bool, _timeout_nanos: i64) -> AwwasmWaitResult {
+        if matches() {
+            AwwasmWaitResult::TimedOut
+        } else {
+            AwwasmWaitResult::NotEqual
+        }
+    }
+
+    /// Wake up to `count` waiters at `offset`, oldest first; returns how
+    /// many woke.
+    #[cfg(feature = "std")]
+    fn notify(&self, offset: u32, count: u32) -> u32 {
+        let mut waiters = lock(&self.buf.waiters);
+        let Some(queue) = waiters.get_mut(&offset) else {
+            return 0;
+        };
+        let mut woken = 0;
+        while woken < count {
+            let Some(waiter) = queue.pop_front() else { break };
+            *lock(&waiter.woken) = true;
+            waiter.cond.notify_one();
+            woken += 1;
+        }
+        if queue.is_empty() {
+            waiters.remove(&offset);
+        }
+        woken
+    }
+
+    #[cfg(not(feature = "std"))]
+    fn notify(&self, _offset: u32, _count: u32) -> u32 {
+        0
     }
 }
 
+/// How guest loads and stores are kept inside their memory, chosen per
+/// store with `AwwasmStore::set_bounds_check`.
+///
+/// Applies to plain loads and stores; atomics, bulk memory instructions
+/// and host accessors always check explicitly.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum AwwasmBoundsCheck {
+    /// Compare every access against the memory size before making it,
+    /// and trap if it does not fit.
+    #[default]
+    Explicit,
+    /// Skip the comparison and let the checked slice access, which plays
+    /// the part of a guard page, catch an access past the end. Traps
+    /// exactly like `Explicit`, with one branch less per access.
+    Guarded,
+    /// Mask the effective address with the memory size rounded up to a
+    /// power of two, so no address, however computed, reaches past that
+    /// window; accesses that still do not fit trap. Addresses beyond the
+    /// window wrap instead of trapping, which the spec does not allow.
+    Masked,
+}
+
 /// Memory instance - runtime representation of linear memory.
 ///
-/// The data vector always has a size that is a multiple of PAGE_SIZE.
+/// The data always has a size that is a multiple of the page size.
 #[derive(Debug, Clone)]
 pub struct AwwasmMemInst {
     /// The memory type (limits).
     pub type_: AwwasmMemoryType,
     /// The raw bytes of memory.
-    pub data: Vec<u8>,
+    pub data: AwwasmMemData,
+    /// Where host-side accesses are reported, if anywhere.
+    audit: Option<AwwasmMemAudit>,
+    /// Whether buffers this memory lets go of are wiped first.
+    zeroize: bool,
+}
+
+/// A memory's auditor, and on whose behalf accesses are made right now.
+#[derive(Debug, Clone)]
+struct AwwasmMemAudit {
+    auditor: Arc<dyn AwwasmMemoryAuditor>,
+    accessor: AwwasmMemAccessor,
+}
+
+/// Allocate `size` zero bytes, or `None` if the allocator refuses.
+///
+/// The capacity is reserved fallibly and then filled in place, so the
+/// allocation that was checked is the one that is used.
+fn zeroed(size: usize) -> Option<Vec<u8>> {
+    let mut data = Vec::new();
+    data.try_reserve_exact(size).ok()?;
+    data.resize(size, 0);
+    Some(data)
+}
+
+/// Overwrite every slot with `zero`, as a best effort against the
+/// compiler dropping the writes because nothing reads them afterwards:
+/// `black_box` makes the slots look read, and the fence keeps the writes
+/// ahead of the deallocation. Neither is guaranteed, and copies the
+/// allocator or the OS made earlier are not reached.
+pub(crate) fn wipe<T: Copy>(slots: &mut [T], zero: T) {
+    slots.fill(zero);
+    core::hint::black_box(&*slots);
+    core::sync::atomic::compiler_fence(Ordering::SeqCst);
 }
 
 impl AwwasmMemInst {
     /// Create a new memory instance with the given type.
     ///
     /// Allocates `min` pages of zeroed memory.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the allocation fails. Use `try_new` for limits that come
+    /// from an untrusted module.
     pub fn new(type_: AwwasmMemoryType) -> Self {
-        let size = (type_.min as usize) * PAGE_SIZE;
-        Self {
-            type_,
-            data: vec![0u8; size],
+        match Self::try_new(type_) {
+            Ok(mem) => mem,
+            Err(e) => panic!("{}", e),
         }
     }
 
+    /// Create a new memory instance, reporting allocation failure
+    /// instead of aborting.
+    ///
+    /// A shared memory is allocated at its maximum size; stores bound it
+    /// with `AwwasmModuleLimits::shared_memory_pages`.
+    pub fn try_new(type_: AwwasmMemoryType) -> Result<Self, AwwasmInstantiationError> {
+        let failed = AwwasmInstantiationError::MemoryAllocationFailed { requested_pages: type_.min };
+        if !type_.valid_page_size() || type_.min > type_.max_pages() {
+            return Err(failed);
+        }
+        let size = (type_.min as usize).checked_mul(type_.page_size()).ok_or_else(|| failed.clone())?;
+        if type_.shared {
+            let max = type_.max.filter(|&max| max >= type_.min).ok_or_else(|| failed.clone())?;
+            let capacity = (max.min(type_.max_pages()) as usize).checked_mul(type_.page_size()).ok_or_else(|| failed.clone())?;
+            let shared = AwwasmSharedMemory::new(size, capacity).ok_or(failed)?;
+            return Ok(Self { type_, data: AwwasmMemData::Shared(shared), audit: None, zeroize: false });
+        }
+        let data = zeroed(size).ok_or(failed)?;
+        Ok(Self { type_, data: AwwasmMemData::Heap(data), audit: None, zeroize: false })
+    }
+
+    /// Create a memory of `type_.min` pages in `buf` instead of the heap.
+    ///
+    /// The first `min` pages of `buf` are zeroed and become the memory;
+    /// anything past them is left alone. The memory never grows:
+    /// `memory.grow` by a non-zero delta returns -1 to the guest. Fails if
+    /// `buf` is smaller than `min` pages.
+    pub fn from_static(buf: &'static mut [u8], type_: AwwasmMemoryType) -> Result<Self, AwwasmInstantiationError> {
+        let size = (type_.min as usize).checked_mul(type_.page_size());
+        let valid = type_.valid_page_size() && type_.min <= type_.max_pages();
+        let Some(size) = size.filter(|&size| valid && size <= buf.len()) else {
+            return Err(AwwasmInstantiationError::MemoryAllocationFailed { requested_pages: type_.min });
+        };
+        let buf = &mut buf[..size];
+        buf.fill(0);
+        Ok(Self { type_, data: AwwasmMemData::Static(buf), audit: None, zeroize: false })
+    }
+
+    /// Whether the memory lives in a caller-provided static buffer.
+    pub fn is_static(&self) -> bool {
+        matches!(self.data, AwwasmMemData::Static(_))
+    }
+
+    /// Whether the memory is shared. Its clones, e.g. imported into
+    /// stores on other threads, alias the same bytes.
+    pub fn is_shared(&self) -> bool {
+        matches!(self.data, AwwasmMemData::Shared(_))
+    }
+
     /// Get the current size in pages.
     #[inline]
     pub fn size_pages(&self) -> u32 {
-        (self.data.len() / PAGE_SIZE) as u32
+        (self.data.len() >> self.type_.page_size_log2) as u32
     }
 
     /// Get the current size in bytes.
@@ -65,8 +675,15 @@ impl AwwasmMemInst {
     /// Grow the memory by the given number of pages.
     ///
     /// Returns the previous size in pages on success, or None if growth
-    /// would exceed the maximum or implementation limits.
+    /// would exceed the maximum or implementation limits, or the host
+    /// cannot allocate the extra pages.
     pub fn grow(&mut self, delta: u32) -> Option<u32> {
+        // Other handles may grow a shared memory concurrently, so its
+        // size is read and raised in one step.
+        if let AwwasmMemData::Shared(shared) = &self.data {
+            let delta = (delta as usize).checked_mul(self.type_.page_size())?;
+            return shared.grow(delta).map(|old| (old >> self.type_.page_size_log2) as u32);
+        }
         let old_pages = self.size_pages();
         let new_pages = old_pages.checked_add(delta)?;
 
@@ -77,22 +694,100 @@ impl AwwasmMemInst {
             }
         }
 
-        // Check against implementation limit (spec allows up to 2^16 pages for 32-bit)
-        if new_pages > 65536 {
+        // Check against implementation limit (a 32-bit memory spans at most 4 GiB)
+        if new_pages > self.type_.max_pages() {
             return None;
         }
 
         // Extend with zeros
-        let new_size = (new_pages as usize) * PAGE_SIZE;
-        self.data.resize(new_size, 0);
+        let new_size = (new_pages as usize).checked_mul(self.type_.page_size())?;
+        let zeroize = self.zeroize;
+        match &mut self.data {
+            // Growing in place may move the bytes and free the old buffer
+            // as is; move them by hand and wipe it instead.
+            AwwasmMemData::Heap(data) if zeroize && data.capacity() < new_size => {
+                let mut grown = Vec::new();
+                grown.try_reserve_exact(new_size).ok()?;
+                grown.extend_from_slice(data);
+                grown.resize(new_size, 0);
+                wipe(data, 0);
+                *data = grown;
+            }
+            AwwasmMemData::Heap(data) => {
+                data.try_reserve_exact(new_size - data.len()).ok()?;
+                data.resize(new_size, 0);
+            }
+            AwwasmMemData::Static(data) => {
+                if new_size != data.len() {
+                    return None;
+                }
+            }
+            AwwasmMemData::Shared(_) => unreachable!("grown above"),
+        }
 
         Some(old_pages)
     }
 
-    /// Read bytes from memory.
+    /// Wipe buffers before letting go of them when growing, and on
+    /// `wipe`.
+    pub(crate) fn set_zeroize(&mut self, zeroize: bool) {
+        self.zeroize = zeroize;
+    }
+
+    /// Zero the contents if zeroizing is on. A shared memory is skipped
+    /// while other handles still use it.
+    pub(crate) fn wipe(&mut self) {
+        if !self.zeroize {
+            return;
+        }
+        match &mut self.data {
+            AwwasmMemData::Heap(data) => wipe(&mut data[..], 0),
+            AwwasmMemData::Static(data) => wipe(&mut data[..], 0),
+            AwwasmMemData::Shared(shared) if shared.is_unique() => shared.write(0, shared.len(), |_| 0),
+            AwwasmMemData::Shared(_) => {}
+        }
+    }
+
+    /// Report host-side accesses to `auditor` (`None`: stop), on behalf
+    /// of the embedder until `set_accessor` says otherwise.
+    pub(crate) fn set_auditor(&mut self, auditor: Option<Arc<dyn AwwasmMemoryAuditor>>) {
+        self.audit = auditor.map(|auditor| AwwasmMemAudit { auditor, accessor: AwwasmMemAccessor::Embedder });
+    }
+
+    /// Attribute audited accesses to `accessor` from now on.
+    pub(crate) fn set_accessor(&mut self, accessor: AwwasmMemAccessor) {
+        if let Some(audit) = &mut self.audit {
+            audit.accessor = accessor;
+        }
+    }
+
+    #[inline]
+    fn audit(&self, kind: AwwasmMemAccessKind, offset: u32, size: u32, ok: bool) {
+        if let Some(audit) = &self.audit {
+            audit.auditor.record(&AwwasmMemAccess { accessor: audit.accessor, kind, offset, size, ok });
+        }
+    }
+
+    /// Read bytes from memory: borrowed, or copied out of a shared memory.
     ///
     /// Returns a Trap if the access is out of bounds.
-    pub fn read(&self, offset: u32, size: u32) -> Result<&[u8], AwwasmTrap> {
+    pub fn read(&self, offset: u32, size: u32) -> Result<Cow<'_, [u8]>, AwwasmTrap> {
+        let result = self.guest_read(offset, size);
+        self.audit(AwwasmMemAccessKind::Read, offset, size, result.is_ok());
+        result
+    }
+
+    /// Write bytes to memory.
+    ///
+    /// Returns a AwwasmTrap if the access is out of bounds.
+    pub fn write(&mut self, offset: u32, data: &[u8]) -> Result<(), AwwasmTrap> {
+        let result = self.guest_write(offset, data);
+        self.audit(AwwasmMemAccessKind::Write, offset, data.len() as u32, result.is_ok());
+        result
+    }
+
+    /// `read` for the guest's own accesses, which are not audited.
+    pub(crate) fn guest_read(&self, offset: u32, size: u32) -> Result<Cow<'_, [u8]>, AwwasmTrap> {
         let start = offset as usize;
         let end = start.checked_add(size as usize).ok_or(AwwasmTrap::MemoryOutOfBounds {
             offset,
@@ -108,13 +803,15 @@ impl AwwasmMemInst {
             });
         }
 
-        Ok(&self.data[start..end])
+        self.data.get(start..end).ok_or(AwwasmTrap::MemoryOutOfBounds {
+            offset,
+            size,
+            memory_size: self.data.len() as u32,
+        })
     }
 
-    /// Write bytes to memory.
-    ///
-    /// Returns a AwwasmTrap if the access is out of bounds.
-    pub fn write(&mut self, offset: u32, data: &[u8]) -> Result<(), AwwasmTrap> {
+    /// `write` for the guest's own accesses.
+    pub(crate) fn guest_write(&mut self, offset: u32, data: &[u8]) -> Result<(), AwwasmTrap> {
         let start = offset as usize;
         let end = start.checked_add(data.len()).ok_or(AwwasmTrap::MemoryOutOfBounds {
             offset,
@@ -130,8 +827,66 @@ impl AwwasmMemInst {
             });
         }
 
-        self.data[start..end].copy_from_slice(data);
-        Ok(())
+        self.data.write(start, data).ok_or(AwwasmTrap::MemoryOutOfBounds {
+            offset,
+            size: data.len() as u32,
+            memory_size: self.data.len() as u32,
+        })
+    }
+
+    /// Read `N` bytes for a guest load at `base + offset`, kept in bounds
+    /// by `strategy`.
+    pub(crate) fn guest_load<const N: usize>(
+        &self,
+        strategy: AwwasmBoundsCheck,
+        base: u32,
+        offset: u32,
+    ) -> Result<[u8; N], AwwasmTrap> {
+        let (range, trap) = self.guest_range(strategy, base, offset, N);
+        let start = range.ok_or(trap.clone())?.start;
+        let mut out = [0u8; N];
+        self.data.read_into(start, &mut out).ok_or(trap)?;
+        Ok(out)
+    }
+
+    /// Write `bytes` for a guest store at `base + offset`, kept in bounds
+    /// by `strategy`.
+    pub(crate) fn guest_store(
+        &mut self,
+        strategy: AwwasmBoundsCheck,
+        base: u32,
+        offset: u32,
+        bytes: &[u8],
+    ) -> Result<(), AwwasmTrap> {
+        let (range, trap) = self.guest_range(strategy, base, offset, bytes.len());
+        self.data.write(range.ok_or(trap.clone())?.start, bytes).ok_or(trap)
+    }
+
+    /// The bytes a `size`-byte guest access at `base + offset` touches
+    /// under `strategy`, or `None` if an explicit check already failed;
+    /// and the trap for when it does not fit.
+    fn guest_range(
+        &self,
+        strategy: AwwasmBoundsCheck,
+        base: u32,
+        offset: u32,
+        size: usize,
+    ) -> (Option<Range<usize>>, AwwasmTrap) {
+        let len = self.data.len();
+        let mut ea = u64::from(base) + u64::from(offset);
+        if strategy == AwwasmBoundsCheck::Masked {
+            ea &= (len.next_power_of_two() as u64).wrapping_sub(1);
+        }
+        let trap = AwwasmTrap::MemoryOutOfBounds {
+            offset: u32::try_from(ea).unwrap_or(u32::MAX),
+            size: size as u32,
+            memory_size: len as u32,
+        };
+        if strategy == AwwasmBoundsCheck::Explicit && ea + size as u64 > len as u64 {
+            return (None, trap);
+        }
+        let start = usize::try_from(ea).unwrap_or(usize::MAX);
+        (Some(start..start.saturating_add(size)), trap)
     }
 
     /// Read a single byte from memory.
@@ -207,6 +962,21 @@ impl AwwasmMemInst {
 
     /// Fill a region of memory with a value.
     pub fn fill(&mut self, offset: u32, value: u8, size: u32) -> Result<(), AwwasmTrap> {
+        let result = self.guest_fill(offset, value, size);
+        self.audit(AwwasmMemAccessKind::Write, offset, size, result.is_ok());
+        result
+    }
+
+    /// Copy a region within memory.
+    pub fn copy_within(&mut self, dst: u32, src: u32, size: u32) -> Result<(), AwwasmTrap> {
+        let result = self.guest_copy_within(dst, src, size);
+        self.audit(AwwasmMemAccessKind::Read, src, size, result.is_ok());
+        self.audit(AwwasmMemAccessKind::Write, dst, size, result.is_ok());
+        result
+    }
+
+    /// `fill` for the guest's own accesses.
+    pub(crate) fn guest_fill(&mut self, offset: u32, value: u8, size: u32) -> Result<(), AwwasmTrap> {
         let start = offset as usize;
         let end = start.checked_add(size as usize).ok_or(AwwasmTrap::MemoryOutOfBounds {
             offset,
@@ -222,12 +992,15 @@ impl AwwasmMemInst {
             });
         }
 
-        self.data[start..end].fill(value);
-        Ok(())
+        self.data.fill(start..end, value).ok_or(AwwasmTrap::MemoryOutOfBounds {
+            offset,
+            size,
+            memory_size: self.data.len() as u32,
+        })
     }
 
-    /// Copy a region within memory.
-    pub fn copy_within(&mut self, dst: u32, src: u32, size: u32) -> Result<(), AwwasmTrap> {
+    /// `copy_within` for the guest's own accesses.
+    pub(crate) fn guest_copy_within(&mut self, dst: u32, src: u32, size: u32) -> Result<(), AwwasmTrap> {
         let mem_size = self.data.len() as u32;
         
         // Check source bounds
@@ -248,7 +1021,111 @@ impl AwwasmMemInst {
             });
         }
 
-        self.data.copy_within(src as usize..(src + size) as usize, dst as usize);
+        self.data.copy_within(src as usize..(src + size) as usize, dst as usize).ok_or(AwwasmTrap::MemoryOutOfBounds {
+            offset: src,
+            size,
+            memory_size: mem_size,
+        })
+    }
+
+    // ========================================================================
+    // Threads proposal: wait and notify
+    // ========================================================================
+
+    /// Check an atomic access of `size` bytes at `offset`: in bounds,
+    /// then naturally aligned.
+    fn check_atomic(&self, offset: u32, size: u32) -> Result<(), AwwasmTrap> {
+        let memory_size = self.data.len() as u32;
+        if offset.checked_add(size).map_or(true, |end| end > memory_size) {
+            return Err(AwwasmTrap::MemoryOutOfBounds { offset, size, memory_size });
+        }
+        if offset % size != 0 {
+            return Err(AwwasmTrap::UnalignedAtomic { offset });
+        }
         Ok(())
     }
+
+    /// `memory.atomic.notify`: wake up to `count` threads waiting at
+    /// `offset`, oldest first, and return how many woke.
+    ///
+    /// Nothing can wait on an unshared memory, so that returns 0.
+    pub fn atomic_notify(&self, offset: u32, count: u32) -> Result<u32, AwwasmTrap> {
+        self.check_atomic(offset, 4)?;
+        match &self.data {
+            AwwasmMemData::Shared(shared) => Ok(shared.notify(offset, count)),
+            _ => Ok(0),
+        }
+    }
+
+    /// `memory.atomic.wait32`: block until notified at `offset` if it
+    /// holds `expected`, for at most `timeout_nanos` (negative: forever).
+    ///
+    /// Traps on an unshared memory, which no other thread could change.
+    pub fn atomic_wait32(&self, offset: u32, expected: i32, timeout_nanos: i64) -> Result<AwwasmWaitResult, AwwasmTrap> {
+        self.check_atomic(offset, 4)?;
+        let AwwasmMemData::Shared(shared) = &self.data else {
+            return Err(AwwasmTrap::ExpectedSharedMemory);
+        };
+        Ok(shared.wait(offset, || shared.load_u32(offset) == expected as u32, timeout_nanos))
+    }
+
+    /// `memory.atomic.wait64`: as `atomic_wait32`, on an `i64`.
+    pub fn atomic_wait64(&self, offset: u32, expected: i64, timeout_nanos: i64) -> Result<AwwasmWaitResult, AwwasmTrap> {
+        self.check_atomic(offset, 8)?;
+        let AwwasmMemData::Shared(shared) = &self.data else {
+            return Err(AwwasmTrap::ExpectedSharedMemory);
+        };
+        Ok(shared.wait(offset, || shared.load_u64(offset) == expected as u64, timeout_nanos))
+    }
+
+    // ========================================================================
+    // Debug inspection helpers
+    // ========================================================================
+
+    /// Render a byte range as a classic hexdump (16 bytes per line).
+    ///
+    /// Each line holds the offset, the bytes in hex and their printable
+    /// ASCII form, e.g. `00000010  68 65 6c 6c 6f 00 ...  |hello.|`.
+    /// Returns a Trap if the range is out of bounds.
+    pub fn hexdump(&self, range: Range<u32>) -> Result<String, AwwasmTrap> {
+        let len = range.end.saturating_sub(range.start);
+        let bytes = self.read(range.start, len)?;
+        let mut out = String::new();
+
+        for (line_idx, chunk) in bytes.chunks(16).enumerate() {
+            let line_offset = range.start as usize + line_idx * 16;
+            let _ = write!(out, "{:08x} ", line_offset);
+            for col in 0..16 {
+                match chunk.get(col) {
+                    Some(b) => { let _ = write!(out, " {:02x}", b); }
+                    None => out.push_str("   "),
+                }
+            }
+            out.push_str("  |");
+            for &b in chunk {
+                out.push(if b.is_ascii_graphic() || b == b' ' { b as char } else { '.' });
+            }
+            out.push_str("|\n");
+        }
+
+        Ok(out)
+    }
+
+    /// Compare this memory against another, byte by byte.
+    ///
+    /// Returns `(offset, old, new)` for every differing byte, where `old`
+    /// comes from `self` and `new` from `other`. If the sizes differ, the
+    /// missing tail of the smaller memory reads as zero, matching the
+    /// contents of freshly grown pages.
+    pub fn diff(&self, other: &AwwasmMemInst) -> Vec<(u32, u8, u8)> {
+        let (old, new) = (self.data.to_vec(), other.data.to_vec());
+        let len = old.len().max(new.len());
+        (0..len)
+            .filter_map(|i| {
+                let old = old.get(i).copied().unwrap_or(0);
+                let new = new.get(i).copied().unwrap_or(0);
+                (old != new).then_some((i as u32, old, new))
+            })
+            .collect()
+    }
 }
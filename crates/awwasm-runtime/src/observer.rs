@@ -0,0 +1,72 @@
+//! Progress reporting for `AwwasmStore::store_init`.
+//!
+//! A store with an `AwwasmInstantiationObserver` (see
+//! `AwwasmStore::set_instantiation_observer`) reports every phase of
+//! instantiation as it finishes, and the error that stopped it, if any.
+//! With a platform set (`AwwasmStore::set_platform`), each report carries
+//! the time the phase took, so a slow instantiation of a big module shows
+//! which phase, or which segment, the time went to.
+
+use core::fmt;
+
+use crate::error::AwwasmInstantiationError;
+use crate::values::AwwasmModuleAddr;
+
+/// A finished phase of instantiation, in the order `store_init` runs them.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AwwasmInstantiationPhase {
+    /// Every import was found and type-checked.
+    ImportsResolved { count: u32 },
+    /// The module's own functions were allocated; bodies are lowered later.
+    FuncsAllocated { count: u32 },
+    /// The module's own memories were allocated, `bytes` in total.
+    MemoriesAllocated { count: u32, bytes: u64 },
+    /// The module's own tables were allocated.
+    TablesAllocated { count: u32 },
+    /// The module's own globals were allocated and initialized.
+    GlobalsInitialized { count: u32 },
+    /// Exports were resolved.
+    ExportsResolved { count: u32 },
+    /// Active element segment `index` was written into its table.
+    ElemSegmentInitialized { index: u32, len: u32 },
+    /// Active data segment `index` was copied into its memory.
+    DataSegmentInitialized { index: u32, bytes: u32 },
+    /// The instance was registered; `store_init` returns it.
+    Done,
+}
+
+impl fmt::Display for AwwasmInstantiationPhase {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            Self::ImportsResolved { count } => write!(f, "{} imports resolved", count),
+            Self::FuncsAllocated { count } => write!(f, "{} functions allocated", count),
+            Self::MemoriesAllocated { count, bytes } => write!(f, "{} memories allocated ({} bytes)", count, bytes),
+            Self::TablesAllocated { count } => write!(f, "{} tables allocated", count),
+            Self::GlobalsInitialized { count } => write!(f, "{} globals initialized", count),
+            Self::ExportsResolved { count } => write!(f, "{} exports resolved", count),
+            Self::ElemSegmentInitialized { index, len } => {
+                write!(f, "element segment {} initialized ({} elements)", index, len)
+            }
+            Self::DataSegmentInitialized { index, bytes } => {
+                write!(f, "data segment {} initialized ({} bytes)", index, bytes)
+            }
+            Self::Done => f.write_str("instantiation done"),
+        }
+    }
+}
+
+/// Receives instantiation progress.
+///
+/// `module` is the address the instance gets once registered, the same
+/// for every report of one `store_init`. `elapsed_nanos` is the time
+/// since the previous report (or since `store_init` started), measured
+/// with the store's platform clock; `None` without a platform.
+pub trait AwwasmInstantiationObserver: Send + Sync + fmt::Debug {
+    /// `phase` just finished.
+    fn phase(&self, module: AwwasmModuleAddr, phase: AwwasmInstantiationPhase, elapsed_nanos: Option<u64>);
+
+    /// Instantiation failed with `error` after the last reported phase.
+    fn failed(&self, module: AwwasmModuleAddr, error: &AwwasmInstantiationError, elapsed_nanos: Option<u64>) {
+        let _ = (module, error, elapsed_nanos);
+    }
+}
@@ -0,0 +1,92 @@
+//! Time, entropy and scheduling services of the host platform.
+//!
+//! The runtime itself never reads a clock or an entropy source directly;
+//! store deadlines and the WASI clock/entropy adapters go through an
+//! `AwwasmHostPlatform`. `std` builds get `AwwasmStdPlatform`, `no_std`
+//! embedders implement the trait over their RTOS tick counter, TRNG and
+//! task yield.
+
+use core::fmt;
+
+#[cfg(feature = "std")]
+use std::time::{Instant, SystemTime, UNIX_EPOCH};
+
+/// Platform primitives the runtime needs from its host.
+pub trait AwwasmHostPlatform: Send + Sync + fmt::Debug {
+    /// Nanoseconds on a monotonic clock with an arbitrary origin.
+    fn monotonic_nanos(&self) -> u64;
+
+    /// Nanoseconds since the Unix epoch, if the platform knows the
+    /// wall-clock time.
+    fn realtime_nanos(&self) -> Option<u64> {
+        None
+    }
+
+    /// Fill `buf` with random bytes. Returns `false` if the platform has
+    /// no entropy source or it failed.
+    fn fill_entropy(&self, buf: &mut [u8]) -> bool;
+
+    /// Let other tasks run for a moment.
+    fn yield_now(&self) {}
+
+    /// Block for about `nanos` nanoseconds. The default yields until the
+    /// monotonic clock has moved far enough.
+    fn sleep(&self, nanos: u64) {
+        let until = self.monotonic_nanos().saturating_add(nanos);
+        while self.monotonic_nanos() < until {
+            self.yield_now();
+        }
+    }
+}
+
+/// The platform of a `std` host: `Instant`, `SystemTime`, the OS
+/// scheduler and, with the `wasi` feature, OS entropy.
+#[cfg(feature = "std")]
+#[derive(Debug, Clone, Copy)]
+pub struct AwwasmStdPlatform {
+    start: Instant,
+}
+
+#[cfg(feature = "std")]
+impl AwwasmStdPlatform {
+    /// A platform whose monotonic clock starts now.
+    pub fn new() -> Self {
+        Self { start: Instant::now() }
+    }
+}
+
+#[cfg(feature = "std")]
+impl Default for AwwasmStdPlatform {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(feature = "std")]
+impl AwwasmHostPlatform for AwwasmStdPlatform {
+    fn monotonic_nanos(&self) -> u64 {
+        self.start.elapsed().as_nanos() as u64
+    }
+
+    fn realtime_nanos(&self) -> Option<u64> {
+        SystemTime::now().duration_since(UNIX_EPOCH).ok().map(|d| d.as_nanos() as u64)
+    }
+
+    fn fill_entropy(&self, buf: &mut [u8]) -> bool {
+        #[cfg(feature = "wasi")]
+        return getrandom::getrandom(buf).is_ok();
+        #[cfg(not(feature = "wasi"))]
+        {
+            let _ = buf;
+            false
+        }
+    }
+
+    fn yield_now(&self) {
+        std::thread::yield_now();
+    }
+
+    fn sleep(&self, nanos: u64) {
+        std::thread::sleep(std::time::Duration::from_nanos(nanos));
+    }
+}
@@ -0,0 +1,226 @@
+//! Running independent instances in parallel.
+//!
+//! Stores share nothing with each other. A store owns its instances,
+//! memories, tables and globals, and interprets guest code only on the
+//! thread that calls `invoke`. What it holds behind an `Arc` (the
+//! profiler, platform, observer and gas table) is `Send + Sync` by trait
+//! bound. So an `AwwasmStore` is `Send`: one store per thread, on any
+//! thread or pool (Rayon included), runs without locks. The one
+//! deliberate exception is a shared memory (`AwwasmMemoryType::with_shared`),
+//! which aliases its bytes across every store it is imported into.
+//!
+//! `AwwasmStorePool` packages that for plugin hosts: it instantiates a
+//! module once per worker thread and hands each invocation to whichever
+//! worker is free. Every worker has its own instance, so a call must not
+//! rely on guest state left behind by an earlier one.
+
+use std::panic::{catch_unwind, AssertUnwindSafe};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::sync::{Arc, Mutex};
+use std::thread::JoinHandle;
+
+use crate::error::{AwwasmHostPanic, AwwasmRuntimeError, AwwasmTrap};
+use crate::func::AwwasmHost;
+use crate::imports::AwwasmImports;
+use crate::runner::AwwasmRunError;
+use crate::store::AwwasmStore;
+use crate::values::AwwasmValue;
+
+/// Stores move between threads; the pool and Rayon users rely on it.
+const _: fn() = || {
+    fn assert_send<T: Send>() {}
+    assert_send::<AwwasmStore<'static>>();
+};
+
+type AwwasmCallResult = Result<Vec<AwwasmValue>, AwwasmRuntimeError>;
+
+/// One queued invocation.
+struct AwwasmPoolJob {
+    name: String,
+    args: Vec<AwwasmValue>,
+    reply: Sender<AwwasmCallResult>,
+}
+
+/// Worker threads, each with its own instance of one module.
+#[derive(Debug)]
+pub struct AwwasmStorePool {
+    jobs: Option<Sender<AwwasmPoolJob>>,
+    workers: Vec<JoinHandle<()>>,
+    /// Workers whose thread is still running.
+    live: Arc<AtomicUsize>,
+}
+
+/// Counts a worker as live until its thread exits, unwinding or not.
+struct AwwasmLiveWorker(Arc<AtomicUsize>);
+
+impl Drop for AwwasmLiveWorker {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+/// An invocation submitted to an `AwwasmStorePool`.
+#[derive(Debug)]
+pub struct AwwasmPoolCall {
+    reply: Receiver<AwwasmCallResult>,
+}
+
+impl AwwasmPoolCall {
+    /// Block until the call finishes and return its results.
+    pub fn wait(self) -> AwwasmCallResult {
+        self.reply.recv().unwrap_or_else(|_| Err(worker_lost()))
+    }
+}
+
+impl AwwasmStorePool {
+    /// Start `workers` threads (at least one), each instantiating `wasm`
+    /// in its own store.
+    ///
+    /// `setup` runs on each worker, with the worker's index, before
+    /// instantiation: it configures the store (fuel, limits, ...), adds
+    /// the imports and returns the host that serves the worker's calls.
+    /// Fuel it sets is a per-call budget: every call starts with that
+    /// much. Fails with the first worker's instantiation error, if any.
+    ///
+    /// A call that panics outside a host function (host panics already
+    /// trap) fails with an `AwwasmHostPanic` trap, and its worker starts
+    /// over with a fresh store from `setup`. A worker that cannot start
+    /// over stops; `live_workers` counts the ones left.
+    pub fn new<H, F>(wasm: impl Into<Arc<[u8]>>, workers: usize, setup: F) -> Result<Self, AwwasmRunError>
+    where
+        H: AwwasmHost + 'static,
+        F: Fn(usize, &mut AwwasmStore<'_>, &mut AwwasmImports<'_>) -> H + Send + Sync + 'static,
+    {
+        let wasm: Arc<[u8]> = wasm.into();
+        let setup = Arc::new(setup);
+        let (jobs, queue) = mpsc::channel::<AwwasmPoolJob>();
+        let queue = Arc::new(Mutex::new(queue));
+        let (ready_tx, ready) = mpsc::channel();
+
+        let live = Arc::new(AtomicUsize::new(0));
+        let mut pool = Self { jobs: Some(jobs), workers: Vec::new(), live: live.clone() };
+        for index in 0..workers.max(1) {
+            let (wasm, setup, queue, ready_tx) = (wasm.clone(), setup.clone(), queue.clone(), ready_tx.clone());
+            live.fetch_add(1, Ordering::SeqCst);
+            let live = AwwasmLiveWorker(live.clone());
+            let worker = std::thread::Builder::new().name(format!("awwasm-pool-{}", index)).spawn(move || {
+                let _live = live;
+                let mut ready_tx = Some(ready_tx);
+                loop {
+                    let mut store = AwwasmStore::new();
+                    let mut imports = AwwasmImports::new();
+                    let mut host = setup(index, &mut store, &mut imports);
+                    let fuel = store.fuel();
+                    let addr = match store.instantiate_bytes(&wasm, &mut imports) {
+                        Ok(addr) => addr,
+                        Err(e) => {
+                            if let Some(ready_tx) = ready_tx.take() {
+                                let _ = ready_tx.send(Err(e));
+                            }
+                            return;
+                        }
+                    };
+                    if let Some(ready_tx) = ready_tx.take() {
+                        let _ = ready_tx.send(Ok(()));
+                    }
+                    loop {
+                        // Hold the queue only while taking a job, so the
+                        // next idle worker can take the one after it.
+                        let job = match queue.lock().unwrap_or_else(|e| e.into_inner()).recv() {
+                            Ok(job) => job,
+                            Err(_) => return,
+                        };
+                        store.set_fuel(fuel);
+                        let result = catch_unwind(AssertUnwindSafe(|| {
+                            store.invoke_export(addr, &job.name, &job.args, &mut host)
+                        }));
+                        match result {
+                            Ok(result) => {
+                                let _ = job.reply.send(result);
+                            }
+                            Err(payload) => {
+                                // The store may have stopped mid-call;
+                                // start over with a fresh one.
+                                let trap = AwwasmTrap::host(AwwasmHostPanic::from_payload(payload));
+                                let _ = job.reply.send(Err(AwwasmRuntimeError::Trap(trap)));
+                                break;
+                            }
+                        }
+                    }
+                }
+            });
+            match worker {
+                Ok(handle) => pool.workers.push(handle),
+                Err(e) => {
+                    let message = Some(format!("cannot start worker: {}", e));
+                    let trap = AwwasmTrap::host(AwwasmHostPanic { message });
+                    return Err(AwwasmRuntimeError::Trap(trap).into());
+                }
+            }
+        }
+        drop(ready_tx);
+
+        for _ in 0..pool.workers.len() {
+            match ready.recv() {
+                Ok(Ok(())) => {}
+                Ok(Err(e)) => return Err(e.into()),
+                Err(_) => return Err(worker_lost().into()),
+            }
+        }
+        Ok(pool)
+    }
+
+    /// Number of worker threads.
+    pub fn workers(&self) -> usize {
+        self.workers.len()
+    }
+
+    /// Number of worker threads still serving calls; less than `workers`
+    /// once one has stopped.
+    pub fn live_workers(&self) -> usize {
+        self.live.load(Ordering::SeqCst)
+    }
+
+    /// Queue a call of the function export `name` on the next free worker.
+    pub fn submit(&self, name: impl Into<String>, args: Vec<AwwasmValue>) -> AwwasmPoolCall {
+        let (reply, result) = mpsc::channel();
+        let job = AwwasmPoolJob { name: name.into(), args, reply };
+        if let Some(jobs) = &self.jobs {
+            // A send only fails once every worker is gone; `wait` reports it.
+            let _ = jobs.send(job);
+        }
+        AwwasmPoolCall { reply: result }
+    }
+
+    /// Call the function export `name` on a free worker and wait for it.
+    pub fn invoke(&self, name: &str, args: &[AwwasmValue]) -> AwwasmCallResult {
+        self.submit(name, args.to_vec()).wait()
+    }
+
+    /// Run every call across the workers and return the results in
+    /// submission order.
+    pub fn invoke_all<I, N>(&self, calls: I) -> Vec<AwwasmCallResult>
+    where
+        I: IntoIterator<Item = (N, Vec<AwwasmValue>)>,
+        N: Into<String>,
+    {
+        let pending: Vec<AwwasmPoolCall> = calls.into_iter().map(|(name, args)| self.submit(name, args)).collect();
+        pending.into_iter().map(AwwasmPoolCall::wait).collect()
+    }
+}
+
+impl Drop for AwwasmStorePool {
+    /// Let the workers finish the queued calls, then stop them.
+    fn drop(&mut self) {
+        drop(self.jobs.take());
+        for worker in self.workers.drain(..) {
+            let _ = worker.join();
+        }
+    }
+}
+
+/// The worker serving a call stopped without answering.
+fn worker_lost() -> AwwasmRuntimeError {
+    AwwasmRuntimeError::Trap(AwwasmTrap::host(AwwasmHostPanic { message: Some(String::from("pool worker stopped")) }))
+}
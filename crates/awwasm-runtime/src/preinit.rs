@@ -0,0 +1,366 @@
+//! Wizer-style pre-initialization.
+//!
+//! `pre_initialize` instantiates a module, calls its initialization
+//! export once, and writes out a new module whose initial state is the
+//! state that call left behind:
+//!
+//! - every defined memory starts at its current size, with its contents
+//!   as active data segments (runs of zeroes are left out);
+//! - every defined global is initialized to its current value;
+//! - the original active data segments are emptied, since their bytes
+//!   are part of the image now. Passive segments keep their indices;
+//!   the ones the initializer dropped are emptied too;
+//! - the initialization export is removed, so the initializer cannot run
+//!   a second time over its own results.
+//!
+//! Everything else (types, imports, code, element segments, the other
+//! exports and custom sections) is copied unchanged, so the result
+//! validates and links like the original. Instantiating it skips the work the
+//! initializer did, which is what makes startup-heavy guests such as
+//! language runtimes boot instantly.
+//!
+//! Instances made in-process can skip the rewrite: after initializing
+//! one instance, `AwwasmStore::snapshot` / `restore` copy its state into
+//! fresh instances of the original module.
+//!
+//! Modules that import a memory are rejected: the memory is the
+//! embedder's, so its contents cannot become part of the module. So are
+//! 64-bit memories, whose image would not fit `i32.const` offsets.
+
+use alloc::format;
+use alloc::string::String;
+use alloc::vec::Vec;
+
+use awwasm_parser::components::module::AwwasmModule;
+
+use crate::artifact::{write_i64, write_u32};
+use crate::decode::{self, AwwasmReader, DecodeError};
+use crate::error::AwwasmInstantiationError;
+use crate::func::AwwasmHost;
+use crate::imports::AwwasmImports;
+use crate::instance::AwwasmModuleInst;
+use crate::runner::AwwasmRunError;
+use crate::store::AwwasmStore;
+use crate::type_convert;
+use crate::values::AwwasmValue;
+
+/// Export name `pre_initialize` callers conventionally use for the
+/// initialization function, as Wizer does.
+pub const PREINIT_EXPORT: &str = "wizer.initialize";
+
+const SECTION_MEMORY: u8 = 5;
+const SECTION_GLOBAL: u8 = 6;
+const SECTION_EXPORT: u8 = 7;
+const SECTION_CODE: u8 = 10;
+const SECTION_DATA: u8 = 11;
+const SECTION_DATA_COUNT: u8 = 12;
+
+/// Zero gaps shorter than this stay inside a data segment; splitting
+/// there would cost more in segment headers than it saves.
+const MIN_ZERO_GAP: usize = 16;
+
+/// Run the export `init` of the module in `bytes` and return an
+/// equivalent module that starts in the state `init` left behind.
+///
+/// `imports` and `host` are what the module needs to instantiate and
+/// run `init`; the rewritten module needs the same imports.
+pub fn pre_initialize<'a>(
+    bytes: &'a [u8],
+    init: &str,
+    imports: &mut AwwasmImports<'a>,
+    host: &mut dyn AwwasmHost,
+) -> Result<Vec<u8>, AwwasmRunError> {
+    let invalid = |e| AwwasmInstantiationError::InvalidModule(type_convert::parse_error(e));
+    let mut module = AwwasmModule::new(bytes).map_err(invalid)?;
+    if module.sections.is_some() {
+        module.resolve_all_sections().map_err(invalid)?;
+    }
+    if !decode::import_memory_types(&module)?.is_empty() {
+        return Err(unsupported("modules importing a memory cannot be pre-initialized").into());
+    }
+    let imported_globals = decode::import_global_types(&module)?.len();
+
+    let mut store = AwwasmStore::new();
+    let addr = store.store_init(&module, imports)?;
+    store.invoke_export(addr, init, &[], host)?;
+    let inst = store.module(addr).ok_or_else(|| unsupported("instance vanished"))?;
+
+    rewrite(bytes, &store, inst, imported_globals, init).map_err(AwwasmRunError::from)
+}
+
+fn unsupported(what: &str) -> AwwasmInstantiationError {
+    AwwasmInstantiationError::UnsupportedType { description: String::from(what) }
+}
+
+fn malformed(_: DecodeError) -> AwwasmInstantiationError {
+    unsupported("malformed module")
+}
+
+/// Copy `bytes` section by section, replacing the ones that hold initial
+/// state with the state of `inst` and leaving out the export `init`.
+fn rewrite(
+    bytes: &[u8],
+    store: &AwwasmStore<'_>,
+    inst: &AwwasmModuleInst<'_>,
+    imported_globals: usize,
+    init: &str,
+) -> Result<Vec<u8>, AwwasmInstantiationError> {
+    let image = memory_image(store, inst);
+    let mut out = Vec::with_capacity(bytes.len() + image.iter().map(|s| s.bytes.len()).sum::<usize>());
+    out.extend_from_slice(bytes.get(..8).ok_or_else(|| unsupported("malformed module"))?);
+
+    let mut sections = Vec::new();
+    let mut r = AwwasmReader::new(&bytes[8..]);
+    while !r.is_empty() {
+        let id = r.u8().map_err(malformed)?;
+        let len = r.u32().map_err(malformed)?;
+        sections.push((id, r.bytes(len as usize).map_err(malformed)?));
+    }
+    // Without a data section of its own, the image gets one after the
+    // code section (or at the end), where the binary format puts it.
+    let mut wrote_data = sections.iter().any(|&(id, _)| id == SECTION_DATA) || image.is_empty();
+    for (id, body) in sections {
+        let body = match id {
+            SECTION_MEMORY => memory_section(body, store, inst)?,
+            SECTION_GLOBAL => global_section(body, store, inst, imported_globals)?,
+            SECTION_EXPORT => export_section(body, init)?,
+            SECTION_DATA_COUNT => {
+                let mut count = Vec::new();
+                write_u32(&mut count, (inst.dataaddrs.len() + image.len()) as u32);
+                count
+            }
+            SECTION_DATA => data_section(body, store, inst, &image)?,
+            _ => body.to_vec(),
+        };
+        write_section(&mut out, id, &body);
+        if id == SECTION_CODE && !wrote_data {
+            wrote_data = true;
+            write_section(&mut out, SECTION_DATA, &data_section(&[0], store, inst, &image)?);
+        }
+    }
+    if !wrote_data {
+        write_section(&mut out, SECTION_DATA, &data_section(&[0], store, inst, &image)?);
+    }
+    Ok(out)
+}
+
+fn write_section(out: &mut Vec<u8>, id: u8, body: &[u8]) {
+    out.push(id);
+    write_u32(out, body.len() as u32);
+    out.extend_from_slice(body);
+}
+
+/// The memory section, with every minimum raised to the current size.
+fn memory_section(
+    body: &[u8],
+    store: &AwwasmStore<'_>,
+    inst: &AwwasmModuleInst<'_>,
+) -> Result<Vec<u8>, AwwasmInstantiationError> {
+    let mut r = AwwasmReader::new(body);
+    let count = r.u32().map_err(malformed)?;
+    let mut out = Vec::new();
+    write_u32(&mut out, count);
+    for i in 0..count as usize {
+        if r.clone().u8().map_err(malformed)? & 0x04 != 0 {
+            return Err(unsupported("64-bit memories cannot be pre-initialized"));
+        }
+        let ty = r.memory_type().map_err(malformed)?;
+        let addr = inst.memaddrs.get(i).ok_or_else(|| unsupported("memory count mismatch"))?;
+        let pages = store.mems[addr.0 as usize].size_pages();
+        let custom_page_size = ty.page_size_log2 != 16;
+        out.push(u8::from(ty.max.is_some()) | if ty.shared { 0x02 } else { 0 } | if custom_page_size { 0x08 } else { 0 });
+        write_u32(&mut out, pages);
+        if let Some(max) = ty.max {
+            write_u32(&mut out, max);
+        }
+        if custom_page_size {
+            write_u32(&mut out, ty.page_size_log2);
+        }
+    }
+    Ok(out)
+}
+
+/// The export section without the export `init`.
+fn export_section(body: &[u8], init: &str) -> Result<Vec<u8>, AwwasmInstantiationError> {
+    let mut r = AwwasmReader::new(body);
+    let count = r.u32().map_err(malformed)?;
+    let mut exports = Vec::new();
+    for _ in 0..count {
+        let name = r.name().map_err(malformed)?;
+        let kind = r.u8().map_err(malformed)?;
+        let index = r.u32().map_err(malformed)?;
+        if name != init.as_bytes() {
+            exports.push((name, kind, index));
+        }
+    }
+    let mut out = Vec::new();
+    write_u32(&mut out, exports.len() as u32);
+    for (name, kind, index) in exports {
+        write_u32(&mut out, name.len() as u32);
+        out.extend_from_slice(name);
+        out.push(kind);
+        write_u32(&mut out, index);
+    }
+    Ok(out)
+}
+
+/// The global section, with every initializer replaced by the global's
+/// current value.
+fn global_section(
+    body: &[u8],
+    store: &AwwasmStore<'_>,
+    inst: &AwwasmModuleInst<'_>,
+    imported_globals: usize,
+) -> Result<Vec<u8>, AwwasmInstantiationError> {
+    let mut r = AwwasmReader::new(body);
+    let count = r.u32().map_err(malformed)?;
+    let mut out = Vec::new();
+    write_u32(&mut out, count);
+    for i in 0..count as usize {
+        // value type and mutability
+        out.push(r.u8().map_err(malformed)?);
+        out.push(r.u8().map_err(malformed)?);
+        r.const_expr().map_err(malformed)?;
+        let addr = inst
+            .globaladdrs
+            .get(imported_globals + i)
+            .ok_or_else(|| unsupported("global count mismatch"))?;
+        write_const(&mut out, store.globals[addr.0 as usize].value, inst)?;
+    }
+    Ok(out)
+}
+
+/// Encode `value` as a constant expression, including its `end`.
+fn write_const(
+    out: &mut Vec<u8>,
+    value: AwwasmValue,
+    inst: &AwwasmModuleInst<'_>,
+) -> Result<(), AwwasmInstantiationError> {
+    match value {
+        AwwasmValue::I32(v) => {
+            out.push(0x41);
+            write_i64(out, i64::from(v));
+        }
+        AwwasmValue::I64(v) => {
+            out.push(0x42);
+            write_i64(out, v);
+        }
+        AwwasmValue::F32(v) => {
+            out.push(0x43);
+            out.extend_from_slice(&v.to_bits().to_le_bytes());
+        }
+        AwwasmValue::F64(v) => {
+            out.push(0x44);
+            out.extend_from_slice(&v.to_bits().to_le_bytes());
+        }
+        AwwasmValue::V128(v) => {
+            out.push(0xfd);
+            write_u32(out, 12);
+            out.extend_from_slice(&v.to_le_bytes());
+        }
+        AwwasmValue::FuncRef(None) => out.extend_from_slice(&[0xd0, 0x70]),
+        AwwasmValue::FuncRef(Some(addr)) => {
+            // Only the module's own function index space can be named.
+            let idx = inst
+                .funcaddrs
+                .iter()
+                .position(|&a| a == addr)
+                .ok_or_else(|| unsupported("a global refers to a function outside the module"))?;
+            out.push(0xd2);
+            write_u32(out, idx as u32);
+        }
+        AwwasmValue::ExternRef(None) => out.extend_from_slice(&[0xd0, 0x6f]),
+        AwwasmValue::ExternRef(Some(_)) => {
+            return Err(unsupported("a global holds a host reference"));
+        }
+    }
+    out.push(0x0b);
+    Ok(())
+}
+
+/// A run of memory contents to store as an active data segment.
+struct AwwasmImageSegment {
+    memory: u32,
+    offset: u32,
+    bytes: Vec<u8>,
+}
+
+/// The non-zero parts of every memory of `inst`.
+fn memory_image(store: &AwwasmStore<'_>, inst: &AwwasmModuleInst<'_>) -> Vec<AwwasmImageSegment> {
+    let mut image = Vec::new();
+    for (memory, addr) in inst.memaddrs.iter().enumerate() {
+        let data = store.mems[addr.0 as usize].data.to_vec();
+        let mut i = 0;
+        while let Some(start) = data[i..].iter().position(|&b| b != 0).map(|p| i + p) {
+            // Extend past zero gaps too short to be worth a new segment.
+            let mut end = start;
+            loop {
+                end += data[end..].iter().position(|&b| b == 0).unwrap_or(data.len() - end);
+                let gap = data[end..].iter().take(MIN_ZERO_GAP).take_while(|&&b| b == 0).count();
+                if gap == MIN_ZERO_GAP || end + gap == data.len() {
+                    break;
+                }
+                end += gap;
+            }
+            image.push(AwwasmImageSegment { memory: memory as u32, offset: start as u32, bytes: data[start..end].to_vec() });
+            i = end;
+        }
+    }
+    image
+}
+
+/// The data section: the original segments emptied or kept in place,
+/// followed by the memory image.
+fn data_section(
+    body: &[u8],
+    store: &AwwasmStore<'_>,
+    inst: &AwwasmModuleInst<'_>,
+    image: &[AwwasmImageSegment],
+) -> Result<Vec<u8>, AwwasmInstantiationError> {
+    let mut r = AwwasmReader::new(body);
+    let count = r.u32().map_err(malformed)?;
+    let mut out = Vec::new();
+    write_u32(&mut out, count + image.len() as u32);
+    for i in 0..count as usize {
+        match r.u32().map_err(malformed)? {
+            flags @ (0 | 2) => {
+                // Already applied: keep the segment index, drop the bytes.
+                let memory = if flags == 2 { r.u32().map_err(malformed)? } else { 0 };
+                r.const_expr().map_err(malformed)?;
+                let len = r.u32().map_err(malformed)?;
+                r.bytes(len as usize).map_err(malformed)?;
+                write_segment(&mut out, memory, 0, &[]);
+            }
+            1 => {
+                let len = r.u32().map_err(malformed)?;
+                let bytes = r.bytes(len as usize).map_err(malformed)?;
+                let dropped = inst.dataaddrs.get(i).map_or(false, |a| store.datas[a.0 as usize].dropped);
+                out.push(1);
+                let bytes = if dropped { &[][..] } else { bytes };
+                write_u32(&mut out, bytes.len() as u32);
+                out.extend_from_slice(bytes);
+            }
+            flags => return Err(unsupported(&format!("data segment flags {}", flags))),
+        }
+    }
+    for segment in image {
+        write_segment(&mut out, segment.memory, segment.offset, &segment.bytes);
+    }
+    Ok(out)
+}
+
+/// Encode an active data segment.
+fn write_segment(out: &mut Vec<u8>, memory: u32, offset: u32, bytes: &[u8]) {
+    if memory == 0 {
+        out.push(0);
+    } else {
+        out.push(2);
+        write_u32(out, memory);
+    }
+    out.push(0x41);
+    // i32.const takes the offset's bit pattern as a signed number.
+    write_i64(out, i64::from(offset as i32));
+    out.push(0x0b);
+    write_u32(out, bytes.len() as u32);
+    out.extend_from_slice(bytes);
+}
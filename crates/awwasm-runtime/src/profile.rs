@@ -0,0 +1,183 @@
+//! Profiling hooks and execution trace export.
+//!
+//! A store with an `AwwasmProfiler` (see `AwwasmStore::set_profiler`)
+//! reports every call into a guest or host function and every return
+//! from it, including returns by trap. `AwwasmTraceRecorder` timestamps
+//! those events with a platform clock and writes them in the Chrome
+//! trace-event JSON format, which `chrome://tracing`, Perfetto and
+//! speedscope all open as a flame chart.
+
+use core::fmt;
+
+#[cfg(feature = "std")]
+use std::fmt::Write as _;
+#[cfg(feature = "std")]
+use std::io;
+#[cfg(feature = "std")]
+use std::string::String;
+#[cfg(feature = "std")]
+use std::sync::{Arc, Mutex};
+#[cfg(feature = "std")]
+use std::vec::Vec;
+#[cfg(feature = "std")]
+use alloc::collections::BTreeMap;
+
+#[cfg(feature = "std")]
+use crate::platform::AwwasmHostPlatform;
+#[cfg(feature = "std")]
+use crate::store::AwwasmStore;
+use crate::values::AwwasmFuncAddr;
+
+/// Receives call and return events while a store executes.
+///
+/// Calls nest: every `enter` is matched by one `exit` of the same
+/// function, also when the call traps. Both are called on the thread
+/// running the guest, so they should be cheap.
+pub trait AwwasmProfiler: Send + Sync + fmt::Debug {
+    /// The function at `func` is about to run.
+    fn enter(&self, func: AwwasmFuncAddr);
+
+    /// The function at `func` returned or was unwound by a trap.
+    fn exit(&self, func: AwwasmFuncAddr);
+}
+
+/// Whether a trace event starts or ends a call.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AwwasmTracePhase {
+    Enter,
+    Exit,
+}
+
+/// One recorded call or return.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AwwasmTraceEvent {
+    pub func: AwwasmFuncAddr,
+    pub phase: AwwasmTracePhase,
+    /// Platform monotonic time of the event, in nanoseconds.
+    pub nanos: u64,
+}
+
+/// An `AwwasmProfiler` that keeps every event in memory.
+///
+/// ```ignore
+/// let recorder = Arc::new(AwwasmTraceRecorder::new(Arc::new(AwwasmStdPlatform::new())));
+/// store.set_profiler(recorder.clone());
+/// store.invoke_export(module, "run", &[], &mut host)?;
+/// recorder.write_chrome_trace(&store, File::create("run.trace.json")?)?;
+/// ```
+#[cfg(feature = "std")]
+#[derive(Debug)]
+pub struct AwwasmTraceRecorder {
+    platform: Arc<dyn AwwasmHostPlatform>,
+    events: Mutex<Vec<AwwasmTraceEvent>>,
+}
+
+#[cfg(feature = "std")]
+impl AwwasmTraceRecorder {
+    /// A recorder timestamping events with `platform`'s monotonic clock.
+    pub fn new(platform: Arc<dyn AwwasmHostPlatform>) -> Self {
+        Self { platform, events: Mutex::new(Vec::new()) }
+    }
+
+    /// The events recorded so far, oldest first.
+    pub fn events(&self) -> Vec<AwwasmTraceEvent> {
+        self.lock().clone()
+    }
+
+    /// Forget the events recorded so far.
+    pub fn clear(&self) {
+        self.lock().clear();
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, Vec<AwwasmTraceEvent>> {
+        self.events.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    fn record(&self, func: AwwasmFuncAddr, phase: AwwasmTracePhase) {
+        let nanos = self.platform.monotonic_nanos();
+        self.lock().push(AwwasmTraceEvent { func, phase, nanos });
+    }
+
+    /// The recorded events as a Chrome trace-event JSON document.
+    ///
+    /// Functions are named after their exports in `store`, the store
+    /// the events were recorded in; others are `func[ADDR]`.
+    pub fn to_chrome_trace(&self, store: &AwwasmStore<'_>) -> String {
+        let names = func_names(store);
+        let mut out = String::from("{\"traceEvents\":[");
+        for (i, event) in self.lock().iter().enumerate() {
+            if i > 0 {
+                out.push(',');
+            }
+            out.push_str("\n{\"name\":");
+            match names.get(&event.func.0) {
+                Some(name) => push_json_string(&mut out, name),
+                None => {
+                    let _ = write!(out, "\"func[{}]\"", event.func.0);
+                }
+            }
+            let phase = match event.phase {
+                AwwasmTracePhase::Enter => 'B',
+                AwwasmTracePhase::Exit => 'E',
+            };
+            // Timestamps are microseconds; keep the nanoseconds as decimals.
+            let _ = write!(
+                out,
+                ",\"cat\":\"wasm\",\"ph\":\"{}\",\"ts\":{}.{:03},\"pid\":1,\"tid\":1}}",
+                phase,
+                event.nanos / 1000,
+                event.nanos % 1000
+            );
+        }
+        out.push_str("\n],\"displayTimeUnit\":\"ns\"}\n");
+        out
+    }
+
+    /// Write `to_chrome_trace` to `out`.
+    pub fn write_chrome_trace(&self, store: &AwwasmStore<'_>, mut out: impl io::Write) -> io::Result<()> {
+        out.write_all(self.to_chrome_trace(store).as_bytes())
+    }
+}
+
+#[cfg(feature = "std")]
+impl AwwasmProfiler for AwwasmTraceRecorder {
+    fn enter(&self, func: AwwasmFuncAddr) {
+        self.record(func, AwwasmTracePhase::Enter);
+    }
+
+    fn exit(&self, func: AwwasmFuncAddr) {
+        self.record(func, AwwasmTracePhase::Exit);
+    }
+}
+
+/// The first export name of every exported function in `store`.
+#[cfg(feature = "std")]
+fn func_names(store: &AwwasmStore<'_>) -> BTreeMap<u32, String> {
+    let mut names = BTreeMap::new();
+    for module in &store.modules {
+        for (name, addr) in module.func_exports() {
+            names.entry(addr.0).or_insert_with(|| String::from_utf8_lossy(name).into_owned());
+        }
+    }
+    names
+}
+
+/// Append `s` as a JSON string literal.
+#[cfg(feature = "std")]
+fn push_json_string(out: &mut String, s: &str) {
+    out.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if (c as u32) < 0x20 => {
+                let _ = write!(out, "\\u{:04x}", c as u32);
+            }
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+}
@@ -0,0 +1,201 @@
+//! Interactive exploration of an instance.
+//!
+//! `AwwasmRepl` reads one command per line, calls exports with typed
+//! arguments and shows memory and globals between calls. `awwasm --repl`
+//! runs it on stdin; `eval` runs a single line, for embedding it
+//! elsewhere.
+//!
+//! ```text
+//! > exports
+//! (export "add" (func (param i32 i32) (result i32)))
+//! (export "memory" (memory 1 4)) ;; 1 pages
+//! > add 1 2
+//! 3
+//! > mem 0 16
+//! 00000000: 2a 00 00 00 00 00 00 00 00 00 00 00 00 00 00 00  |*...............|
+//! ```
+
+use std::fmt::Write as _;
+use std::io::{self, BufRead, Write};
+use std::string::String;
+use std::vec::Vec;
+
+use crate::func::AwwasmHost;
+use crate::store::AwwasmStore;
+use crate::values::{AwwasmExternAddr, AwwasmModuleAddr, AwwasmValue};
+
+const HELP: &str = "\
+commands:
+  exports                       list exports with their types
+  [call] <NAME> [ARGS...]       call a function export
+  mem [MEMORY] <OFFSET> [LEN]   dump LEN (default 64) bytes of a memory
+  global <NAME>                 show a global export's value
+  help                          show this help
+  quit                          leave";
+
+/// Bytes `mem` dumps when no length is given.
+const DEFAULT_DUMP_LEN: u32 = 64;
+
+/// A read-eval-print loop over one module instance.
+pub struct AwwasmRepl<'s, 'a> {
+    store: &'s mut AwwasmStore<'a>,
+    module: AwwasmModuleAddr,
+    host: &'s mut dyn AwwasmHost,
+}
+
+impl<'s, 'a> AwwasmRepl<'s, 'a> {
+    /// Explore `module`; `host` serves its host function calls.
+    pub fn new(store: &'s mut AwwasmStore<'a>, module: AwwasmModuleAddr, host: &'s mut dyn AwwasmHost) -> Self {
+        Self { store, module, host }
+    }
+
+    /// Prompt on `output` and evaluate lines from `input` until `quit`
+    /// or end of input.
+    pub fn run(&mut self, input: impl BufRead, mut output: impl Write) -> io::Result<()> {
+        write!(output, "> ")?;
+        output.flush()?;
+        for line in input.lines() {
+            let line = line?;
+            if matches!(line.trim(), "quit" | "exit") {
+                return Ok(());
+            }
+            match self.eval(&line) {
+                Ok(text) if text.is_empty() => {}
+                Ok(text) => writeln!(output, "{}", text)?,
+                Err(e) => writeln!(output, "error: {}", e)?,
+            }
+            write!(output, "> ")?;
+            output.flush()?;
+        }
+        writeln!(output)
+    }
+
+    /// Evaluate one command and return what it prints.
+    pub fn eval(&mut self, line: &str) -> Result<String, String> {
+        let mut words = line.split_whitespace();
+        let Some(command) = words.next() else {
+            return Ok(String::new());
+        };
+        let args: Vec<&str> = words.collect();
+        match command {
+            "help" => Ok(String::from(HELP)),
+            "exports" => Ok(self.exports()),
+            "mem" => self.mem(&args),
+            "global" => match args[..] {
+                [name] => self.global(name),
+                _ => Err(String::from("usage: global <NAME>")),
+            },
+            "call" => match args.split_first() {
+                Some((name, args)) => self.call(name, args),
+                None => Err(String::from("usage: call <NAME> [ARGS...]")),
+            },
+            name => self.call(name, &args),
+        }
+    }
+
+    fn exports(&self) -> String {
+        let mut out = String::new();
+        let Some(inst) = self.store.module(self.module) else {
+            return out;
+        };
+        for export in &inst.exports {
+            let name = String::from_utf8_lossy(export.name);
+            if !out.is_empty() {
+                out.push('\n');
+            }
+            let _ = match export.addr {
+                AwwasmExternAddr::Func(addr) => match self.store.func_type(addr) {
+                    Ok(ty) => write!(out, "(export {:?} {})", name, ty),
+                    Err(_) => write!(out, "(export {:?} (func))", name),
+                },
+                AwwasmExternAddr::Mem(addr) => match self.store.mem(addr) {
+                    Ok(mem) => write!(out, "(export {:?} {}) ;; {} pages", name, mem.type_, mem.size_pages()),
+                    Err(_) => write!(out, "(export {:?} (memory))", name),
+                },
+                AwwasmExternAddr::Table(addr) => match self.store.table(addr) {
+                    Ok(table) => write!(out, "(export {:?} {}) ;; {} elements", name, table.type_, table.elem.len()),
+                    Err(_) => write!(out, "(export {:?} (table))", name),
+                },
+                AwwasmExternAddr::Global(addr) => match self.store.global(addr) {
+                    Ok(global) => write!(out, "(export {:?} {}) ;; = {}", name, global.type_, global.value),
+                    Err(_) => write!(out, "(export {:?} (global))", name),
+                },
+            };
+        }
+        out
+    }
+
+    fn call(&mut self, name: &str, args: &[&str]) -> Result<String, String> {
+        let func = self
+            .store
+            .module(self.module)
+            .and_then(|m| m.get_func(name))
+            .ok_or_else(|| format!("no function export {:?} (try `help`)", name))?;
+        let ty = self.store.func_type(func).map_err(|e| e.to_string())?;
+        if ty.params.len() != args.len() {
+            return Err(format!("{} takes {} arguments: {}", name, ty.params.len(), ty));
+        }
+        let args = ty
+            .params
+            .iter()
+            .zip(args)
+            .map(|(&ty, arg)| AwwasmValue::parse(ty, arg).ok_or_else(|| format!("{:?} is not a valid {}", arg, ty)))
+            .collect::<Result<Vec<_>, _>>()?;
+        let results = self.store.invoke(func, &args, &mut *self.host).map_err(|e| e.to_string())?;
+        Ok(results.iter().map(|v| v.to_string()).collect::<Vec<_>>().join("\n"))
+    }
+
+    fn mem(&self, args: &[&str]) -> Result<String, String> {
+        let usage = || String::from("usage: mem [MEMORY] <OFFSET> [LEN]");
+        let inst = self.store.module(self.module).ok_or_else(usage)?;
+        // A leading word that is not a number names the memory.
+        let (addr, args) = match args.split_first() {
+            Some((name, rest)) if parse_u32(name).is_none() => {
+                (inst.get_memory(name).ok_or_else(|| format!("no memory export {:?}", name))?, rest)
+            }
+            _ => (inst.mem_exports().next().map(|(_, a)| a).ok_or("the instance exports no memory")?, args),
+        };
+        let (offset, len) = match args {
+            [offset] => (parse_u32(offset).ok_or_else(usage)?, DEFAULT_DUMP_LEN),
+            [offset, len] => (parse_u32(offset).ok_or_else(usage)?, parse_u32(len).ok_or_else(usage)?),
+            _ => return Err(usage()),
+        };
+        let bytes = self.store.mem(addr).map_err(|e| e.to_string())?.read(offset, len).map_err(|e| e.to_string())?;
+        Ok(hex_dump(offset, &bytes))
+    }
+
+    fn global(&self, name: &str) -> Result<String, String> {
+        let addr = self
+            .store
+            .module(self.module)
+            .and_then(|m| m.get_global(name))
+            .ok_or_else(|| format!("no global export {:?}", name))?;
+        self.store.get_global(addr).map(|v| v.to_string()).map_err(|e| e.to_string())
+    }
+}
+
+fn parse_u32(text: &str) -> Option<u32> {
+    match text.strip_prefix("0x") {
+        Some(hex) => u32::from_str_radix(hex, 16).ok(),
+        None => text.parse().ok(),
+    }
+}
+
+/// `xxd`-style lines of 16 bytes: address, hex, then printable ASCII.
+fn hex_dump(offset: u32, bytes: &[u8]) -> String {
+    let mut out = String::new();
+    for (i, line) in bytes.chunks(16).enumerate() {
+        if i > 0 {
+            out.push('\n');
+        }
+        let _ = write!(out, "{:08x}:", offset as usize + i * 16);
+        for b in line {
+            let _ = write!(out, " {:02x}", b);
+        }
+        out.push_str(&"   ".repeat(16 - line.len()));
+        out.push_str("  |");
+        out.extend(line.iter().map(|&b| if b.is_ascii_graphic() || b == b' ' { b as char } else { '.' }));
+        out.push('|');
+    }
+    out
+}
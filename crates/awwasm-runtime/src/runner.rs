@@ -0,0 +1,146 @@
+//! Runners for the standard application ABIs.
+//!
+//! A *command* exports `_start`, runs it once and is done. A *reactor*
+//! optionally exports `_initialize`, which is run once, and afterwards
+//! serves calls to its other exports.
+
+#[cfg(feature = "alloc")]
+use alloc::vec::Vec;
+
+use crate::error::{AwwasmInstantiationError, AwwasmRuntimeError};
+use crate::func::AwwasmHost;
+use crate::store::AwwasmStore;
+use crate::values::{AwwasmFuncAddr, AwwasmModuleAddr, AwwasmValue};
+
+#[cfg(feature = "wasi")]
+use awwasm_parser::components::module::AwwasmModule;
+#[cfg(feature = "wasi")]
+use crate::error::{AwwasmTrap, ExitStatus};
+#[cfg(feature = "wasi")]
+use crate::imports::AwwasmImports;
+#[cfg(feature = "wasi")]
+use crate::wasi::AwwasmWasiCtx;
+
+/// Errors from instantiating and running a module in one step.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum AwwasmRunError {
+    /// The module could not be instantiated
+    Instantiation(AwwasmInstantiationError),
+    /// The entry point is missing or execution failed
+    Runtime(AwwasmRuntimeError),
+}
+
+impl AwwasmRunError {
+    /// Stable numeric code of the underlying error (see
+    /// `AwwasmInstantiationError::code` and `AwwasmRuntimeError::code`).
+    pub fn code(&self) -> u32 {
+        match self {
+            AwwasmRunError::Instantiation(e) => e.code(),
+            AwwasmRunError::Runtime(e) => e.code(),
+        }
+    }
+}
+
+impl From<AwwasmInstantiationError> for AwwasmRunError {
+    fn from(e: AwwasmInstantiationError) -> Self {
+        AwwasmRunError::Instantiation(e)
+    }
+}
+
+impl From<AwwasmRuntimeError> for AwwasmRunError {
+    fn from(e: AwwasmRuntimeError) -> Self {
+        AwwasmRunError::Runtime(e)
+    }
+}
+
+impl core::fmt::Display for AwwasmRunError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            AwwasmRunError::Instantiation(e) => write!(f, "instantiation failed: {}", e),
+            AwwasmRunError::Runtime(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl core::error::Error for AwwasmRunError {
+    fn source(&self) -> Option<&(dyn core::error::Error + 'static)> {
+        match self {
+            AwwasmRunError::Instantiation(e) => e.source(),
+            AwwasmRunError::Runtime(e) => e.source(),
+        }
+    }
+}
+
+/// Look up a function export by name.
+fn func_export(store: &AwwasmStore<'_>, module: AwwasmModuleAddr, name: &str) -> Option<AwwasmFuncAddr> {
+    store.module(module)?.get_func(name)
+}
+
+/// Instantiate a WASI command module and run its `_start` export.
+///
+/// The module's imports are satisfied from `wasi`. A `proc_exit` call
+/// ends the run with the requested status; returning normally from
+/// `_start` is status 0.
+#[cfg(feature = "wasi")]
+pub fn run_command<'a>(
+    store: &mut AwwasmStore<'a>,
+    module: &AwwasmModule<'a>,
+    wasi: &mut AwwasmWasiCtx,
+) -> Result<ExitStatus, AwwasmRunError> {
+    let mut imports = AwwasmImports::new();
+    wasi.add_to_imports(&mut imports);
+    let addr = store.store_init(module, &mut imports)?;
+
+    // The start function runs first, and may exit before `_start` does.
+    let start = store.module(addr).and_then(|inst| inst.start);
+    let result = match start {
+        Some(start) => store.invoke(start, &[], wasi),
+        None => Ok(Vec::new()),
+    };
+    match result.and_then(|_| store.invoke_export(addr, "_start", &[], wasi)) {
+        Ok(_) => Ok(ExitStatus(0)),
+        Err(e) => match e.trap() {
+            Some(AwwasmTrap::Exit(status)) => Ok(*status),
+            _ => Err(e.into()),
+        },
+    }
+}
+
+/// An initialized reactor instance.
+///
+/// The instance stays in the store; the reactor just remembers its
+/// address so exports can be called by name.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AwwasmReactor {
+    module: AwwasmModuleAddr,
+}
+
+impl AwwasmReactor {
+    /// Run the module's `_initialize` export, if it has one.
+    pub fn initialize(
+        store: &mut AwwasmStore<'_>,
+        module: AwwasmModuleAddr,
+        host: &mut dyn AwwasmHost,
+    ) -> Result<Self, AwwasmRuntimeError> {
+        if let Some(init) = func_export(store, module, "_initialize") {
+            store.invoke(init, &[], host)?;
+        }
+        Ok(Self { module })
+    }
+
+    /// The module instance this reactor wraps.
+    pub fn module(&self) -> AwwasmModuleAddr {
+        self.module
+    }
+
+    /// Call the function export `name`.
+    pub fn call(
+        &self,
+        store: &mut AwwasmStore<'_>,
+        name: &str,
+        args: &[AwwasmValue],
+        host: &mut dyn AwwasmHost,
+    ) -> Result<Vec<AwwasmValue>, AwwasmRuntimeError> {
+        store.invoke_export(self.module, name, args, host)
+    }
+}
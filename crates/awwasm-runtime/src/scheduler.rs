@@ -0,0 +1,167 @@
+//! Interleaving long-running guest calls on one thread.
+//!
+//! `AwwasmScheduler` owns a set of tasks, each a guest call with its own
+//! store and host, and runs them round-robin: every task in turn executes
+//! one slice of `slice` units of fuel (instructions on a store without
+//! fuel), then pauses between two instructions while the others run. A
+//! plugin stuck in a long computation thus delays the rest by at most
+//! one slice per round.
+//!
+//! This is the synchronous counterpart of `async_call`, for hosts that
+//! have no async executor.
+
+use alloc::boxed::Box;
+use alloc::vec::Vec;
+use core::fmt;
+
+use crate::error::AwwasmRuntimeError;
+use crate::exec::{self, AwwasmPausedCall, AwwasmProgress};
+use crate::func::AwwasmHost;
+use crate::store::AwwasmStore;
+use crate::values::{AwwasmFuncAddr, AwwasmModuleAddr, AwwasmValue};
+
+/// Identifies a task of an `AwwasmScheduler`, in spawn order.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct AwwasmTaskId(pub usize);
+
+#[derive(Debug)]
+enum AwwasmTaskState {
+    /// Not run yet.
+    Start { addr: AwwasmFuncAddr, args: Vec<AwwasmValue> },
+    /// Waiting for its next slice.
+    Paused(AwwasmPausedCall),
+    /// Returned or trapped.
+    Done(Result<Vec<AwwasmValue>, AwwasmRuntimeError>),
+}
+
+struct AwwasmTask<'a> {
+    store: AwwasmStore<'a>,
+    host: Box<dyn AwwasmHost + 'a>,
+    state: AwwasmTaskState,
+    slices: u64,
+}
+
+/// Round-robin scheduler of guest calls; see the module docs.
+pub struct AwwasmScheduler<'a> {
+    slice: u64,
+    tasks: Vec<AwwasmTask<'a>>,
+}
+
+impl fmt::Debug for AwwasmScheduler<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("AwwasmScheduler")
+            .field("slice", &self.slice)
+            .field("tasks", &self.tasks.len())
+            .field("pending", &self.pending())
+            .finish()
+    }
+}
+
+impl<'a> AwwasmScheduler<'a> {
+    /// An empty scheduler giving each task `slice` units of fuel per
+    /// turn (at least 1).
+    pub fn new(slice: u64) -> Self {
+        Self { slice: slice.max(1), tasks: Vec::new() }
+    }
+
+    /// Add a task calling the function at `addr` of `store` with `args`,
+    /// running its host functions through `host`. It starts at the next
+    /// round.
+    pub fn spawn(
+        &mut self,
+        store: AwwasmStore<'a>,
+        host: impl AwwasmHost + 'a,
+        addr: AwwasmFuncAddr,
+        args: &[AwwasmValue],
+    ) -> AwwasmTaskId {
+        self.tasks.push(AwwasmTask {
+            store,
+            host: Box::new(host),
+            state: AwwasmTaskState::Start { addr, args: args.to_vec() },
+            slices: 0,
+        });
+        AwwasmTaskId(self.tasks.len() - 1)
+    }
+
+    /// Add a task calling the function export `name` of `module`.
+    pub fn spawn_export(
+        &mut self,
+        store: AwwasmStore<'a>,
+        host: impl AwwasmHost + 'a,
+        module: AwwasmModuleAddr,
+        name: &str,
+        args: &[AwwasmValue],
+    ) -> Result<AwwasmTaskId, AwwasmRuntimeError> {
+        let addr = store
+            .module(module)
+            .and_then(|inst| inst.get_func(name))
+            .ok_or_else(|| AwwasmRuntimeError::ExportNotFound(name.into()))?;
+        Ok(self.spawn(store, host, addr, args))
+    }
+
+    /// Run one slice of every unfinished task, in spawn order. Returns the
+    /// tasks that finished during the round.
+    pub fn round(&mut self) -> Vec<AwwasmTaskId> {
+        let budget = Some(self.slice);
+        let mut finished = Vec::new();
+        for (id, task) in self.tasks.iter_mut().enumerate() {
+            let progress = match core::mem::replace(&mut task.state, AwwasmTaskState::Done(Ok(Vec::new()))) {
+                AwwasmTaskState::Start { addr, args } => exec::start(&mut task.store, addr, &args, &mut *task.host, budget),
+                AwwasmTaskState::Paused(paused) => exec::resume(&mut task.store, paused, &mut *task.host, budget),
+                done @ AwwasmTaskState::Done(_) => {
+                    task.state = done;
+                    continue;
+                }
+            };
+            task.slices += 1;
+            task.state = match progress {
+                Ok(AwwasmProgress::Paused(paused)) => AwwasmTaskState::Paused(paused),
+                Ok(AwwasmProgress::Done(results)) => AwwasmTaskState::Done(Ok(results)),
+                Err(e) => AwwasmTaskState::Done(Err(e)),
+            };
+            if let AwwasmTaskState::Done(_) = task.state {
+                finished.push(AwwasmTaskId(id));
+            }
+        }
+        finished
+    }
+
+    /// Run rounds until every task has finished. Returns the tasks in the
+    /// order they finished.
+    pub fn run(&mut self) -> Vec<AwwasmTaskId> {
+        let mut finished = Vec::new();
+        while self.pending() > 0 {
+            finished.extend(self.round());
+        }
+        finished
+    }
+
+    /// Number of tasks that have not finished.
+    pub fn pending(&self) -> usize {
+        self.tasks.iter().filter(|t| !matches!(t.state, AwwasmTaskState::Done(_))).count()
+    }
+
+    /// What task `id` returned, once it has finished.
+    pub fn result(&self, id: AwwasmTaskId) -> Option<&Result<Vec<AwwasmValue>, AwwasmRuntimeError>> {
+        match &self.tasks.get(id.0)?.state {
+            AwwasmTaskState::Done(result) => Some(result),
+            _ => None,
+        }
+    }
+
+    /// Number of slices task `id` has run so far.
+    pub fn slices(&self, id: AwwasmTaskId) -> Option<u64> {
+        self.tasks.get(id.0).map(|t| t.slices)
+    }
+
+    /// The store of task `id`, e.g. to read its memory between rounds.
+    pub fn store(&self, id: AwwasmTaskId) -> Option<&AwwasmStore<'a>> {
+        self.tasks.get(id.0).map(|t| &t.store)
+    }
+
+    /// The store of task `id`, mutably. Changing its fuel between rounds
+    /// is fine; instances must stay as they are until the task finishes.
+    pub fn store_mut(&mut self, id: AwwasmTaskId) -> Option<&mut AwwasmStore<'a>> {
+        self.tasks.get_mut(id.0).map(|t| &mut t.store)
+    }
+}
@@ -0,0 +1,803 @@
+//! Snapshots of a store's mutable state.
+//!
+//! `AwwasmStore::snapshot` captures everything execution can change:
+//! memory contents, table elements, global values and which element and
+//! data segments were dropped. It also records the store's layout (every
+//! module instance's addresses and a hash of every function body) but
+//! not code, which comes back from the module bytes.
+//!
+//! To restore, instantiate the same modules from the same bytes, in the
+//! same order and with the same imports, into a fresh store, then call
+//! `AwwasmStore::restore`. The layout must match exactly.
+//!
+//! Snapshots serialize with serde (`serde` feature) or with
+//! `to_bytes` / `from_bytes` into this compact binary layout (integers
+//! are LEB128 as in the wasm binary format):
+//!
+//! - magic `\0aws`, `FORMAT_VERSION`, then an FNV-1a checksum (8 bytes,
+//!   little endian) of everything after it;
+//! - the layout: module address lists, then per function either its body
+//!   hash or its host function id, so a snapshot names the exact code it
+//!   belongs to;
+//! - memories as 64 KiB chunks, each stored all-zero (just a length),
+//!   run-length encoded, or raw, whichever is smallest;
+//! - table elements, global values and segment drop flags.
+//!
+//! Mostly-empty or repetitive memories shrink to a fraction of their
+//! size, which keeps frequent checkpoints of large memories cheap.
+//!
+//! To move a single instance to another store, perhaps in another
+//! process, use `AwwasmStore::extract_instance` and `rehydrate_instance`
+//! instead. An `AwwasmInstanceSnapshot` holds no store addresses, only
+//! the instance's own indices, so the target store may hold any other
+//! instances. Its binary form is the same with magic `\0awi` and a hash
+//! of the instance's code in place of the layout. Migration happens
+//! between calls: there is never a suspended guest call to carry over.
+
+use alloc::format;
+use alloc::string::String;
+use alloc::vec::Vec;
+
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+use crate::artifact::{self, write_i64, write_u32};
+use crate::decode::{AwwasmReader, DecodeError};
+use crate::error::AwwasmRuntimeError;
+use crate::exec;
+use crate::func::AwwasmFuncInst;
+use crate::instance::AwwasmModuleInst;
+use crate::memory::{self, AwwasmMemData, AwwasmMemInst};
+use crate::store::AwwasmStore;
+use crate::table::AwwasmTableInst;
+use crate::values::{AwwasmFuncAddr, AwwasmModuleAddr, AwwasmValue};
+
+/// A store's layout and mutable state, see the module docs.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct AwwasmStoreSnapshot {
+    /// Address spaces of every module instance, in instantiation order.
+    pub modules: Vec<AwwasmModuleLayout>,
+    /// What each function address holds.
+    pub funcs: Vec<AwwasmFuncId>,
+    /// Memory contents, by address.
+    pub memories: Vec<Vec<u8>>,
+    /// Table elements, by address.
+    pub tables: Vec<Vec<Option<AwwasmFuncAddr>>>,
+    /// Global values, by address.
+    pub globals: Vec<AwwasmValue>,
+    /// Whether each element segment was dropped.
+    pub elems_dropped: Vec<bool>,
+    /// Whether each data segment was dropped.
+    pub datas_dropped: Vec<bool>,
+}
+
+/// Store addresses of a module instance's index spaces.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct AwwasmModuleLayout {
+    pub funcs: Vec<u32>,
+    pub tables: Vec<u32>,
+    pub memories: Vec<u32>,
+    pub globals: Vec<u32>,
+    pub elems: Vec<u32>,
+    pub datas: Vec<u32>,
+}
+
+/// Identity of a function instance.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum AwwasmFuncId {
+    /// A guest function of `module`, with an FNV-1a hash of its body.
+    Wasm { module: u32, body_hash: u64 },
+    /// A host function.
+    Host { host_func_id: u32 },
+}
+
+const MAGIC: &[u8; 4] = b"\0aws";
+
+/// Bumped whenever the binary layout changes.
+const FORMAT_VERSION: u32 = 1;
+
+/// Memories are compressed in chunks of this many bytes.
+const CHUNK: usize = 64 * 1024;
+
+/// The largest memory a snapshot may restore: `MAX_PAGES` pages.
+const MAX_MEMORY: u64 = memory::MAX_PAGES as u64 * memory::PAGE_SIZE as u64;
+
+/// Chunk encodings.
+const CHUNK_ZERO: u8 = 0;
+const CHUNK_RLE: u8 = 1;
+const CHUNK_RAW: u8 = 2;
+
+/// Value tags.
+const VALUE_I32: u8 = 0;
+const VALUE_I64: u8 = 1;
+const VALUE_F32: u8 = 2;
+const VALUE_F64: u8 = 3;
+const VALUE_V128: u8 = 4;
+const VALUE_FUNCREF: u8 = 5;
+const VALUE_EXTERNREF: u8 = 6;
+
+/// State of one module instance, see the module docs.
+///
+/// Function references are function indices of the instance: table
+/// elements directly, and `FuncRef` globals as `AwwasmFuncAddr(funcidx)`.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct AwwasmInstanceSnapshot {
+    /// FNV-1a hash of the instance's function bodies, identifying its
+    /// module.
+    pub module_hash: u64,
+    /// Memory contents, by memidx.
+    pub memories: Vec<Vec<u8>>,
+    /// Table elements, by tableidx.
+    pub tables: Vec<Vec<Option<u32>>>,
+    /// Global values, by globalidx.
+    pub globals: Vec<AwwasmValue>,
+    /// Whether each element segment was dropped, by elemidx.
+    pub elems_dropped: Vec<bool>,
+    /// Whether each data segment was dropped, by dataidx.
+    pub datas_dropped: Vec<bool>,
+}
+
+const INSTANCE_MAGIC: &[u8; 4] = b"\0awi";
+
+impl AwwasmStoreSnapshot {
+    /// Encode in the binary layout described in the module docs.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut body = Vec::new();
+        write_u32(&mut body, self.modules.len() as u32);
+        for m in &self.modules {
+            for addrs in [&m.funcs, &m.tables, &m.memories, &m.globals, &m.elems, &m.datas] {
+                write_u32s(&mut body, addrs);
+            }
+        }
+        write_u32(&mut body, self.funcs.len() as u32);
+        for func in &self.funcs {
+            match *func {
+                AwwasmFuncId::Wasm { module, body_hash } => {
+                    body.push(0);
+                    write_u32(&mut body, module);
+                    body.extend_from_slice(&body_hash.to_le_bytes());
+                }
+                AwwasmFuncId::Host { host_func_id } => {
+                    body.push(1);
+                    write_u32(&mut body, host_func_id);
+                }
+            }
+        }
+        write_memories(&mut body, &self.memories);
+        write_u32(&mut body, self.tables.len() as u32);
+        for table in &self.tables {
+            write_u32(&mut body, table.len() as u32);
+            for elem in table {
+                write_u32(&mut body, elem.map_or(0, |f| f.0.wrapping_add(1)));
+            }
+        }
+        write_globals(&mut body, &self.globals);
+        write_flags(&mut body, &self.elems_dropped);
+        write_flags(&mut body, &self.datas_dropped);
+        seal(MAGIC, &body)
+    }
+
+    /// Decode the binary layout, failing with `InvalidSnapshot` if it is
+    /// malformed, from another format version, or fails its checksum.
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, AwwasmRuntimeError> {
+        let mut r = open(MAGIC, "a store", bytes)?;
+        let mut modules = Vec::new();
+        for _ in 0..r.u32().map_err(truncated)? {
+            modules.push(AwwasmModuleLayout {
+                funcs: read_u32s(&mut r)?,
+                tables: read_u32s(&mut r)?,
+                memories: read_u32s(&mut r)?,
+                globals: read_u32s(&mut r)?,
+                elems: read_u32s(&mut r)?,
+                datas: read_u32s(&mut r)?,
+            });
+        }
+        let mut funcs = Vec::new();
+        for _ in 0..r.u32().map_err(truncated)? {
+            funcs.push(match r.u8().map_err(truncated)? {
+                0 => AwwasmFuncId::Wasm { module: r.u32().map_err(truncated)?, body_hash: read_u64(&mut r)? },
+                1 => AwwasmFuncId::Host { host_func_id: r.u32().map_err(truncated)? },
+                tag => return Err(invalid(format!("bad function tag {}", tag))),
+            });
+        }
+        let memories = read_memories(&mut r)?;
+        let tables = read_tables(&mut r)?
+            .into_iter()
+            .map(|table| table.into_iter().map(|elem| elem.map(AwwasmFuncAddr)).collect())
+            .collect();
+        let globals = read_globals(&mut r)?;
+        let elems_dropped = read_flags(&mut r)?;
+        let datas_dropped = read_flags(&mut r)?;
+        if !r.is_empty() {
+            return Err(invalid(String::from("trailing bytes")));
+        }
+        Ok(Self { modules, funcs, memories, tables, globals, elems_dropped, datas_dropped })
+    }
+}
+
+impl AwwasmInstanceSnapshot {
+    /// Encode in the binary layout described in the module docs.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut body = Vec::new();
+        body.extend_from_slice(&self.module_hash.to_le_bytes());
+        write_memories(&mut body, &self.memories);
+        write_u32(&mut body, self.tables.len() as u32);
+        for table in &self.tables {
+            write_u32(&mut body, table.len() as u32);
+            for elem in table {
+                write_u32(&mut body, elem.map_or(0, |f| f.wrapping_add(1)));
+            }
+        }
+        write_globals(&mut body, &self.globals);
+        write_flags(&mut body, &self.elems_dropped);
+        write_flags(&mut body, &self.datas_dropped);
+        seal(INSTANCE_MAGIC, &body)
+    }
+
+    /// Decode the binary layout, failing with `InvalidSnapshot` if it is
+    /// malformed, from another format version, or fails its checksum.
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, AwwasmRuntimeError> {
+        let mut r = open(INSTANCE_MAGIC, "an instance", bytes)?;
+        let module_hash = read_u64(&mut r)?;
+        let memories = read_memories(&mut r)?;
+        let tables = read_tables(&mut r)?;
+        let globals = read_globals(&mut r)?;
+        let elems_dropped = read_flags(&mut r)?;
+        let datas_dropped = read_flags(&mut r)?;
+        if !r.is_empty() {
+            return Err(invalid(String::from("trailing bytes")));
+        }
+        Ok(Self { module_hash, memories, tables, globals, elems_dropped, datas_dropped })
+    }
+}
+
+/// Prefix `body` with `magic`, the format version and its checksum.
+fn seal(magic: &[u8; 4], body: &[u8]) -> Vec<u8> {
+    let mut out = Vec::with_capacity(body.len() + 16);
+    out.extend_from_slice(magic);
+    write_u32(&mut out, FORMAT_VERSION);
+    out.extend_from_slice(&artifact::body_hash(body).to_le_bytes());
+    out.extend_from_slice(body);
+    out
+}
+
+/// Check the header written by `seal` and return a reader over the body.
+fn open<'b>(magic: &[u8; 4], what: &str, bytes: &'b [u8]) -> Result<AwwasmReader<'b>, AwwasmRuntimeError> {
+    let mut r = AwwasmReader::new(bytes);
+    if r.bytes(4).map_err(truncated)? != magic {
+        return Err(invalid(format!("not {} snapshot", what)));
+    }
+    let format = r.u32().map_err(truncated)?;
+    if format != FORMAT_VERSION {
+        return Err(invalid(format!("format {} is not supported (this is format {})", format, FORMAT_VERSION)));
+    }
+    let checksum = read_u64(&mut r)?;
+    if artifact::body_hash(&bytes[r.pos()..]) != checksum {
+        return Err(invalid(String::from("checksum mismatch")));
+    }
+    Ok(r)
+}
+
+fn write_memories(out: &mut Vec<u8>, memories: &[Vec<u8>]) {
+    write_u32(out, memories.len() as u32);
+    for mem in memories {
+        write_u32(out, mem.chunks(CHUNK).len() as u32);
+        for chunk in mem.chunks(CHUNK) {
+            write_chunk(out, chunk);
+        }
+    }
+}
+
+fn read_memories(r: &mut AwwasmReader<'_>) -> Result<Vec<Vec<u8>>, AwwasmRuntimeError> {
+    let mut memories = Vec::new();
+    for _ in 0..r.u32().map_err(truncated)? {
+        let mut mem = Vec::new();
+        let chunks = r.u32().map_err(truncated)?;
+        if u64::from(chunks) > MAX_MEMORY / CHUNK as u64 {
+            return Err(invalid(format!("{} memory chunks exceed the memory size limit", chunks)));
+        }
+        for _ in 0..chunks {
+            read_chunk(r, &mut mem)?;
+        }
+        memories.push(mem);
+    }
+    Ok(memories)
+}
+
+/// Table elements, each written as 0 for null or the index plus one.
+fn read_tables(r: &mut AwwasmReader<'_>) -> Result<Vec<Vec<Option<u32>>>, AwwasmRuntimeError> {
+    let mut tables = Vec::new();
+    for _ in 0..r.u32().map_err(truncated)? {
+        let mut elems = Vec::new();
+        for _ in 0..r.u32().map_err(truncated)? {
+            elems.push(r.u32().map_err(truncated)?.checked_sub(1));
+        }
+        tables.push(elems);
+    }
+    Ok(tables)
+}
+
+fn write_globals(out: &mut Vec<u8>, globals: &[AwwasmValue]) {
+    write_u32(out, globals.len() as u32);
+    for value in globals {
+        write_value(out, value);
+    }
+}
+
+fn read_globals(r: &mut AwwasmReader<'_>) -> Result<Vec<AwwasmValue>, AwwasmRuntimeError> {
+    let mut globals = Vec::new();
+    for _ in 0..r.u32().map_err(truncated)? {
+        globals.push(read_value(r)?);
+    }
+    Ok(globals)
+}
+
+fn write_flags(out: &mut Vec<u8>, flags: &[bool]) {
+    write_u32(out, flags.len() as u32);
+    out.extend(flags.iter().map(|&d| u8::from(d)));
+}
+
+fn invalid(why: String) -> AwwasmRuntimeError {
+    AwwasmRuntimeError::InvalidSnapshot(why)
+}
+
+fn truncated(_: DecodeError) -> AwwasmRuntimeError {
+    invalid(String::from("truncated"))
+}
+
+fn write_u32s(out: &mut Vec<u8>, values: &[u32]) {
+    write_u32(out, values.len() as u32);
+    for &v in values {
+        write_u32(out, v);
+    }
+}
+
+fn read_u32s(r: &mut AwwasmReader<'_>) -> Result<Vec<u32>, AwwasmRuntimeError> {
+    let mut values = Vec::new();
+    for _ in 0..r.u32().map_err(truncated)? {
+        values.push(r.u32().map_err(truncated)?);
+    }
+    Ok(values)
+}
+
+fn read_u64(r: &mut AwwasmReader<'_>) -> Result<u64, AwwasmRuntimeError> {
+    let mut raw = [0; 8];
+    raw.copy_from_slice(r.bytes(8).map_err(truncated)?);
+    Ok(u64::from_le_bytes(raw))
+}
+
+fn read_flags(r: &mut AwwasmReader<'_>) -> Result<Vec<bool>, AwwasmRuntimeError> {
+    let mut flags = Vec::new();
+    for _ in 0..r.u32().map_err(truncated)? {
+        flags.push(match r.u8().map_err(truncated)? {
+            0 => false,
+            1 => true,
+            _ => return Err(invalid(String::from("bad drop flag"))),
+        });
+    }
+    Ok(flags)
+}
+
+/// Runs of equal bytes in `chunk`, as (length, byte).
+fn runs(chunk: &[u8]) -> Vec<(u32, u8)> {
+    let mut runs: Vec<(u32, u8)> = Vec::new();
+    for &b in chunk {
+        match runs.last_mut() {
+            Some((len, byte)) if *byte == b => *len += 1,
+            _ => runs.push((1, b)),
+        }
+    }
+    runs
+}
+
+fn write_chunk(out: &mut Vec<u8>, chunk: &[u8]) {
+    if chunk.iter().all(|&b| b == 0) {
+        out.push(CHUNK_ZERO);
+        write_u32(out, chunk.len() as u32);
+        return;
+    }
+    let mut rle = Vec::new();
+    let runs = runs(chunk);
+    write_u32(&mut rle, runs.len() as u32);
+    for (len, byte) in runs {
+        write_u32(&mut rle, len);
+        rle.push(byte);
+    }
+    if rle.len() < chunk.len() {
+        out.push(CHUNK_RLE);
+        out.extend_from_slice(&rle);
+    } else {
+        out.push(CHUNK_RAW);
+        write_u32(out, chunk.len() as u32);
+        out.extend_from_slice(chunk);
+    }
+}
+
+fn read_chunk(r: &mut AwwasmReader<'_>, mem: &mut Vec<u8>) -> Result<(), AwwasmRuntimeError> {
+    let start = mem.len();
+    match r.u8().map_err(truncated)? {
+        CHUNK_ZERO => {
+            let len = r.u32().map_err(truncated)? as usize;
+            if len <= CHUNK {
+                grow(mem, len)?;
+                mem.resize(start + len, 0);
+            }
+        }
+        CHUNK_RLE => {
+            for _ in 0..r.u32().map_err(truncated)? {
+                let len = r.u32().map_err(truncated)? as usize;
+                let byte = r.u8().map_err(truncated)?;
+                if len > CHUNK - (mem.len() - start) {
+                    return Err(invalid(String::from("oversized memory chunk")));
+                }
+                grow(mem, len)?;
+                mem.resize(mem.len() + len, byte);
+            }
+        }
+        CHUNK_RAW => {
+            let len = r.u32().map_err(truncated)? as usize;
+            if len <= CHUNK {
+                let bytes = r.bytes(len).map_err(truncated)?;
+                grow(mem, len)?;
+                mem.extend_from_slice(bytes);
+            }
+        }
+        tag => return Err(invalid(format!("bad chunk tag {}", tag))),
+    }
+    let len = mem.len() - start;
+    // Every chunk but a memory's last is full.
+    if len == 0 || len > CHUNK || start % CHUNK != 0 {
+        return Err(invalid(String::from("malformed memory chunk")));
+    }
+    Ok(())
+}
+
+/// Make room for `len` more bytes of `mem`, failing instead of aborting
+/// when the allocation cannot be made.
+fn grow(mem: &mut Vec<u8>, len: usize) -> Result<(), AwwasmRuntimeError> {
+    if (mem.len() as u64).saturating_add(len as u64) > MAX_MEMORY {
+        return Err(invalid(String::from("memory exceeds the memory size limit")));
+    }
+    mem.try_reserve(len)
+        .map_err(|_| invalid(format!("cannot allocate {} bytes of memory", mem.len() + len)))
+}
+
+fn write_value(out: &mut Vec<u8>, value: &AwwasmValue) {
+    match *value {
+        AwwasmValue::I32(v) => {
+            out.push(VALUE_I32);
+            write_i64(out, i64::from(v));
+        }
+        AwwasmValue::I64(v) => {
+            out.push(VALUE_I64);
+            write_i64(out, v);
+        }
+        AwwasmValue::F32(v) => {
+            out.push(VALUE_F32);
+            out.extend_from_slice(&v.to_bits().to_le_bytes());
+        }
+        AwwasmValue::F64(v) => {
+            out.push(VALUE_F64);
+            out.extend_from_slice(&v.to_bits().to_le_bytes());
+        }
+        AwwasmValue::V128(v) => {
+            out.push(VALUE_V128);
+            out.extend_from_slice(&v.to_le_bytes());
+        }
+        AwwasmValue::FuncRef(f) => {
+            out.push(VALUE_FUNCREF);
+            write_u32(out, f.map_or(0, |f| f.0.wrapping_add(1)));
+        }
+        AwwasmValue::ExternRef(e) => {
+            out.push(VALUE_EXTERNREF);
+            write_u32(out, e.map_or(0, |e| e.wrapping_add(1)));
+        }
+    }
+}
+
+fn read_value(r: &mut AwwasmReader<'_>) -> Result<AwwasmValue, AwwasmRuntimeError> {
+    Ok(match r.u8().map_err(truncated)? {
+        VALUE_I32 => AwwasmValue::I32(r.i32().map_err(truncated)?),
+        VALUE_I64 => AwwasmValue::I64(r.i64().map_err(truncated)?),
+        VALUE_F32 => AwwasmValue::F32(r.f32().map_err(truncated)?),
+        VALUE_F64 => AwwasmValue::F64(r.f64().map_err(truncated)?),
+        VALUE_V128 => {
+            let mut raw = [0; 16];
+            raw.copy_from_slice(r.bytes(16).map_err(truncated)?);
+            AwwasmValue::V128(u128::from_le_bytes(raw))
+        }
+        VALUE_FUNCREF => AwwasmValue::FuncRef(r.u32().map_err(truncated)?.checked_sub(1).map(AwwasmFuncAddr)),
+        VALUE_EXTERNREF => AwwasmValue::ExternRef(r.u32().map_err(truncated)?.checked_sub(1)),
+        tag => return Err(invalid(format!("bad value tag {}", tag))),
+    })
+}
+
+fn mismatch(why: String) -> AwwasmRuntimeError {
+    AwwasmRuntimeError::SnapshotMismatch(why)
+}
+
+/// Layout of `store`, the part a snapshot must agree on.
+fn layout(store: &AwwasmStore<'_>) -> Result<(Vec<AwwasmModuleLayout>, Vec<AwwasmFuncId>), AwwasmRuntimeError> {
+    let modules = store
+        .modules
+        .iter()
+        .map(|m| AwwasmModuleLayout {
+            funcs: m.funcaddrs.iter().map(|a| a.0).collect(),
+            tables: m.tableaddrs.iter().map(|a| a.0).collect(),
+            memories: m.memaddrs.iter().map(|a| a.0).collect(),
+            globals: m.globaladdrs.iter().map(|a| a.0).collect(),
+            elems: m.elemaddrs.iter().map(|a| a.0).collect(),
+            datas: m.dataaddrs.iter().map(|a| a.0).collect(),
+        })
+        .collect();
+    let funcs = store
+        .funcs
+        .iter()
+        .map(|f| match f {
+            AwwasmFuncInst::Wasm(f) => Ok(AwwasmFuncId::Wasm {
+                module: f.module.0,
+                body_hash: artifact::body_hash(exec::body(f)?.1),
+            }),
+            AwwasmFuncInst::Host(f) => Ok(AwwasmFuncId::Host { host_func_id: f.host_func_id }),
+        })
+        .collect::<Result<_, AwwasmRuntimeError>>()?;
+    Ok((modules, funcs))
+}
+
+pub(crate) fn capture(store: &AwwasmStore<'_>) -> Result<AwwasmStoreSnapshot, AwwasmRuntimeError> {
+    let (modules, funcs) = layout(store)?;
+    Ok(AwwasmStoreSnapshot {
+        modules,
+        funcs,
+        memories: store.mems.iter().map(|m| m.data.to_vec()).collect(),
+        tables: store.tables.iter().map(|t| t.elem.clone()).collect(),
+        globals: store.globals.iter().map(|g| g.value).collect(),
+        elems_dropped: store.elems.iter().map(|e| e.dropped).collect(),
+        datas_dropped: store.datas.iter().map(|d| d.dropped).collect(),
+    })
+}
+
+/// Check everything first so a mismatch leaves `store` untouched.
+fn check(store: &AwwasmStore<'_>, snapshot: &AwwasmStoreSnapshot) -> Result<(), AwwasmRuntimeError> {
+    let (modules, funcs) = layout(store)?;
+    if modules != snapshot.modules {
+        return Err(mismatch(String::from("module instances differ")));
+    }
+    if funcs != snapshot.funcs {
+        return Err(mismatch(String::from("functions differ")));
+    }
+    let counts = [
+        ("memories", store.mems.len(), snapshot.memories.len()),
+        ("tables", store.tables.len(), snapshot.tables.len()),
+        ("globals", store.globals.len(), snapshot.globals.len()),
+        ("element segments", store.elems.len(), snapshot.elems_dropped.len()),
+        ("data segments", store.datas.len(), snapshot.datas_dropped.len()),
+    ];
+    for (what, have, want) in counts {
+        if have != want {
+            return Err(mismatch(format!("store has {} {}, snapshot has {}", have, what, want)));
+        }
+    }
+    for (i, (mem, bytes)) in store.mems.iter().zip(&snapshot.memories).enumerate() {
+        if !mem_fits(mem, bytes.len()) {
+            return Err(mismatch(format!("memory {} cannot hold {} bytes", i, bytes.len())));
+        }
+    }
+    for (i, (table, elem)) in store.tables.iter().zip(&snapshot.tables).enumerate() {
+        if !table_fits(table, elem.len()) || elem.iter().flatten().any(|f| f.0 as usize >= store.funcs.len()) {
+            return Err(mismatch(format!("table {} does not fit the snapshot", i)));
+        }
+    }
+    for (i, (global, value)) in store.globals.iter().zip(&snapshot.globals).enumerate() {
+        if global.type_.value_type != value.value_type() {
+            return Err(mismatch(format!("global {} has another type", i)));
+        }
+    }
+    for (i, (elem, &dropped)) in store.elems.iter().zip(&snapshot.elems_dropped).enumerate() {
+        if elem.dropped && !dropped {
+            return Err(mismatch(format!("element segment {} was already dropped", i)));
+        }
+    }
+    Ok(())
+}
+
+pub(crate) fn restore(store: &mut AwwasmStore<'_>, snapshot: &AwwasmStoreSnapshot) -> Result<(), AwwasmRuntimeError> {
+    check(store, snapshot)?;
+    for (mem, bytes) in store.mems.iter_mut().zip(&snapshot.memories) {
+        set_mem(mem, bytes)?;
+    }
+    for (table, elem) in store.tables.iter_mut().zip(&snapshot.tables) {
+        table.elem.clone_from(elem);
+    }
+    for (global, value) in store.globals.iter_mut().zip(&snapshot.globals) {
+        global.value = *value;
+    }
+    for (elem, &dropped) in store.elems.iter_mut().zip(&snapshot.elems_dropped) {
+        if dropped && !elem.dropped {
+            elem.drop_elem();
+        }
+    }
+    for (data, &dropped) in store.datas.iter_mut().zip(&snapshot.datas_dropped) {
+        data.dropped = dropped;
+    }
+    Ok(())
+}
+
+/// Whether `mem` can take contents of `len` bytes.
+fn mem_fits(mem: &AwwasmMemInst, len: usize) -> bool {
+    let page_size = mem.type_.page_size();
+    let pages = len / page_size;
+    let fits = len % page_size == 0
+        && pages as u64 >= u64::from(mem.type_.min)
+        && pages as u64 <= u64::from(mem.type_.max.unwrap_or(mem.type_.max_pages()));
+    let fixed = matches!(mem.data, AwwasmMemData::Static(_) | AwwasmMemData::Shared(_)) && len != mem.data.len();
+    fits && !fixed
+}
+
+/// Whether `table` can take `len` elements.
+fn table_fits(table: &AwwasmTableInst, len: usize) -> bool {
+    let len = len as u64;
+    len >= u64::from(table.type_.min) && table.type_.max.is_none_or(|max| len <= u64::from(max))
+}
+
+fn set_mem(mem: &mut AwwasmMemInst, bytes: &[u8]) -> Result<(), AwwasmRuntimeError> {
+    match &mut mem.data {
+        AwwasmMemData::Heap(data) => {
+            data.clear();
+            data.try_reserve_exact(bytes.len())
+                .map_err(|_| AwwasmRuntimeError::OutOfMemory { requested_bytes: bytes.len() })?;
+            data.extend_from_slice(bytes);
+        }
+        AwwasmMemData::Static(data) => data.copy_from_slice(bytes),
+        AwwasmMemData::Shared(_) => {
+            mem.data.write(0, bytes);
+        }
+    }
+    Ok(())
+}
+
+/// Hash of the bodies of the functions `module` defines, in order.
+fn module_hash(store: &AwwasmStore<'_>, module: AwwasmModuleAddr) -> Result<u64, AwwasmRuntimeError> {
+    let inst = instance(store, module)?;
+    let mut hashes = Vec::new();
+    for &addr in &inst.funcaddrs {
+        if let AwwasmFuncInst::Wasm(f) = store.func(addr)? {
+            if f.module == module {
+                hashes.extend_from_slice(&artifact::body_hash(exec::body(f)?.1).to_le_bytes());
+            }
+        }
+    }
+    Ok(artifact::body_hash(&hashes))
+}
+
+fn instance<'s, 'a>(
+    store: &'s AwwasmStore<'a>,
+    module: AwwasmModuleAddr,
+) -> Result<&'s AwwasmModuleInst<'a>, AwwasmRuntimeError> {
+    store.module(module).ok_or_else(|| mismatch(format!("no module instance {}", module.0)))
+}
+
+/// Function index of `addr` in `inst`.
+fn func_idx(inst: &AwwasmModuleInst<'_>, addr: AwwasmFuncAddr) -> Result<u32, AwwasmRuntimeError> {
+    inst.funcaddrs
+        .iter()
+        .position(|&a| a == addr)
+        .map(|idx| idx as u32)
+        .ok_or_else(|| mismatch(format!("function {} is outside the instance", addr.0)))
+}
+
+pub(crate) fn capture_instance(
+    store: &AwwasmStore<'_>,
+    module: AwwasmModuleAddr,
+) -> Result<AwwasmInstanceSnapshot, AwwasmRuntimeError> {
+    let inst = instance(store, module)?;
+    let mut memories = Vec::new();
+    for &addr in &inst.memaddrs {
+        memories.push(store.mem(addr)?.data.to_vec());
+    }
+    let mut tables: Vec<Vec<Option<u32>>> = Vec::new();
+    for &addr in &inst.tableaddrs {
+        let elem = &store.table(addr)?.elem;
+        tables.push(elem.iter().map(|f| f.map(|f| func_idx(inst, f)).transpose()).collect::<Result<_, _>>()?);
+    }
+    let mut globals = Vec::new();
+    for &addr in &inst.globaladdrs {
+        globals.push(match store.get_global(addr)? {
+            AwwasmValue::FuncRef(Some(f)) => AwwasmValue::FuncRef(Some(AwwasmFuncAddr(func_idx(inst, f)?))),
+            value => value,
+        });
+    }
+    Ok(AwwasmInstanceSnapshot {
+        module_hash: module_hash(store, module)?,
+        memories,
+        tables,
+        globals,
+        elems_dropped: inst.elemaddrs.iter().map(|a| store.elems[a.0 as usize].dropped).collect(),
+        datas_dropped: inst.dataaddrs.iter().map(|a| store.datas[a.0 as usize].dropped).collect(),
+    })
+}
+
+/// Check everything first so a mismatch leaves `store` untouched.
+fn check_instance(
+    store: &AwwasmStore<'_>,
+    module: AwwasmModuleAddr,
+    snapshot: &AwwasmInstanceSnapshot,
+) -> Result<(), AwwasmRuntimeError> {
+    let inst = instance(store, module)?;
+    if module_hash(store, module)? != snapshot.module_hash {
+        return Err(mismatch(String::from("the instance runs other code")));
+    }
+    let counts = [
+        ("memories", inst.memaddrs.len(), snapshot.memories.len()),
+        ("tables", inst.tableaddrs.len(), snapshot.tables.len()),
+        ("globals", inst.globaladdrs.len(), snapshot.globals.len()),
+        ("element segments", inst.elemaddrs.len(), snapshot.elems_dropped.len()),
+        ("data segments", inst.dataaddrs.len(), snapshot.datas_dropped.len()),
+    ];
+    for (what, have, want) in counts {
+        if have != want {
+            return Err(mismatch(format!("instance has {} {}, snapshot has {}", have, what, want)));
+        }
+    }
+    for (i, (&addr, bytes)) in inst.memaddrs.iter().zip(&snapshot.memories).enumerate() {
+        if !mem_fits(store.mem(addr)?, bytes.len()) {
+            return Err(mismatch(format!("memory {} cannot hold {} bytes", i, bytes.len())));
+        }
+    }
+    for (i, (&addr, elem)) in inst.tableaddrs.iter().zip(&snapshot.tables).enumerate() {
+        let in_range = elem.iter().flatten().all(|&f| (f as usize) < inst.funcaddrs.len());
+        if !table_fits(store.table(addr)?, elem.len()) || !in_range {
+            return Err(mismatch(format!("table {} does not fit the snapshot", i)));
+        }
+    }
+    for (i, (&addr, value)) in inst.globaladdrs.iter().zip(&snapshot.globals).enumerate() {
+        let in_range = match value {
+            AwwasmValue::FuncRef(Some(f)) => (f.0 as usize) < inst.funcaddrs.len(),
+            _ => true,
+        };
+        if store.global(addr)?.type_.value_type != value.value_type() || !in_range {
+            return Err(mismatch(format!("global {} has another type", i)));
+        }
+    }
+    for (i, (addr, &dropped)) in inst.elemaddrs.iter().zip(&snapshot.elems_dropped).enumerate() {
+        if store.elems[addr.0 as usize].dropped && !dropped {
+            return Err(mismatch(format!("element segment {} was already dropped", i)));
+        }
+    }
+    Ok(())
+}
+
+pub(crate) fn restore_instance(
+    store: &mut AwwasmStore<'_>,
+    module: AwwasmModuleAddr,
+    snapshot: &AwwasmInstanceSnapshot,
+) -> Result<(), AwwasmRuntimeError> {
+    check_instance(store, module, snapshot)?;
+    let inst = instance(store, module)?.clone();
+    for (&addr, bytes) in inst.memaddrs.iter().zip(&snapshot.memories) {
+        set_mem(store.mem_mut(addr)?, bytes)?;
+    }
+    for (&addr, elem) in inst.tableaddrs.iter().zip(&snapshot.tables) {
+        store.table_mut(addr)?.elem = elem.iter().map(|f| f.map(|f| inst.funcaddrs[f as usize])).collect();
+    }
+    for (&addr, value) in inst.globaladdrs.iter().zip(&snapshot.globals) {
+        store.global_mut(addr)?.value = match *value {
+            AwwasmValue::FuncRef(Some(f)) => AwwasmValue::FuncRef(Some(inst.funcaddrs[f.0 as usize])),
+            value => value,
+        };
+    }
+    for (addr, &dropped) in inst.elemaddrs.iter().zip(&snapshot.elems_dropped) {
+        let elem = &mut store.elems[addr.0 as usize];
+        if dropped && !elem.dropped {
+            elem.drop_elem();
+        }
+    }
+    for (addr, &dropped) in inst.dataaddrs.iter().zip(&snapshot.datas_dropped) {
+        store.datas[addr.0 as usize].dropped = dropped;
+    }
+    Ok(())
+}
@@ -0,0 +1,366 @@
+//! Software IEEE 754 arithmetic for the `softfloat` feature.
+//!
+//! Every operation works on the bit patterns with integer arithmetic
+//! only, rounding to nearest-even, so results do not depend on the
+//! target's FPU (or on there being one). NaN results are always the
+//! canonical quiet NaN, which removes the one freedom the spec leaves to
+//! implementations and makes float code fully deterministic.
+
+/// Layout of an IEEE 754 binary format.
+trait Format {
+    /// Number of stored fraction bits.
+    const MANT: u32;
+    /// Number of exponent bits.
+    const EXP: u32;
+
+    const BIAS: i32 = (1 << (Self::EXP - 1)) - 1;
+    const SIGN: u64 = 1 << (Self::MANT + Self::EXP);
+    const EXP_MASK: u64 = ((1 << Self::EXP) - 1) << Self::MANT;
+    const FRAC_MASK: u64 = (1 << Self::MANT) - 1;
+    const CANONICAL_NAN: u64 = Self::EXP_MASK | (1 << (Self::MANT - 1));
+    const INF: u64 = Self::EXP_MASK;
+}
+
+struct Binary32;
+struct Binary64;
+
+impl Format for Binary32 {
+    const MANT: u32 = 23;
+    const EXP: u32 = 8;
+}
+
+impl Format for Binary64 {
+    const MANT: u32 = 52;
+    const EXP: u32 = 11;
+}
+
+/// A finite, non-zero value `sig * 2^exp`.
+#[derive(Clone, Copy)]
+struct Unpacked {
+    sig: u128,
+    exp: i32,
+}
+
+/// Classification of an operand.
+enum Class {
+    Nan,
+    Inf,
+    Zero,
+    Finite(Unpacked),
+}
+
+fn classify<F: Format>(bits: u64) -> Class {
+    let biased = ((bits & F::EXP_MASK) >> F::MANT) as i32;
+    let frac = bits & F::FRAC_MASK;
+    if biased == (1 << F::EXP) - 1 {
+        return if frac == 0 { Class::Inf } else { Class::Nan };
+    }
+    if biased == 0 {
+        if frac == 0 {
+            return Class::Zero;
+        }
+        return Class::Finite(Unpacked { sig: frac as u128, exp: 1 - F::BIAS - F::MANT as i32 });
+    }
+    Class::Finite(Unpacked {
+        sig: (frac | (1 << F::MANT)) as u128,
+        exp: biased - F::BIAS - F::MANT as i32,
+    })
+}
+
+/// Shift `v` so its leading bit sits at `F::MANT`.
+fn normalize<F: Format>(v: Unpacked) -> Unpacked {
+    let shift = (127 - v.sig.leading_zeros()) as i32 - F::MANT as i32;
+    if shift >= 0 {
+        v
+    } else {
+        Unpacked { sig: v.sig << -shift, exp: v.exp + shift }
+    }
+}
+
+/// Round `sig * 2^exp` to nearest-even and encode it.
+///
+/// When `sig` stands for an inexact value, its lowest bit must be set
+/// (sticky) and sit at least two bits below the rounding position.
+fn round_pack<F: Format>(negative: bool, sig: u128, exp: i32) -> u64 {
+    let sign = if negative { F::SIGN } else { 0 };
+    if sig == 0 {
+        return sign;
+    }
+    let emin = 1 - F::BIAS;
+    let top = exp + (127 - sig.leading_zeros()) as i32;
+    if top > F::BIAS {
+        return sign | F::INF;
+    }
+    let mut lsb = (top - F::MANT as i32).max(emin - F::MANT as i32);
+    let shift = lsb - exp;
+    let mut q = if shift <= 0 {
+        sig << -shift
+    } else if shift >= 128 {
+        // Everything is below the rounding position and below half of it.
+        0
+    } else {
+        let q = sig >> shift;
+        let rem = sig & ((1u128 << shift) - 1);
+        let half = 1u128 << (shift - 1);
+        if rem > half || (rem == half && q & 1 == 1) {
+            q + 1
+        } else {
+            q
+        }
+    };
+    if q >> (F::MANT + 1) != 0 {
+        q >>= 1;
+        lsb += 1;
+    }
+    if q >> F::MANT == 0 {
+        // Subnormal (or rounded to zero)
+        return sign | q as u64;
+    }
+    let biased = lsb + F::MANT as i32 + F::BIAS;
+    if biased >= (1 << F::EXP) - 1 {
+        return sign | F::INF;
+    }
+    sign | ((biased as u64) << F::MANT) | (q as u64 & F::FRAC_MASK)
+}
+
+fn add<F: Format>(a: u64, b: u64) -> u64 {
+    let (sa, sb) = (a & F::SIGN != 0, b & F::SIGN != 0);
+    let (x, y) = match (classify::<F>(a), classify::<F>(b)) {
+        (Class::Nan, _) | (_, Class::Nan) => return F::CANONICAL_NAN,
+        (Class::Inf, Class::Inf) if sa != sb => return F::CANONICAL_NAN,
+        (Class::Inf, _) => return a,
+        (_, Class::Inf) => return b,
+        (Class::Zero, Class::Zero) => return if sa && sb { F::SIGN } else { 0 },
+        (Class::Zero, _) => return b,
+        (_, Class::Zero) => return a,
+        (Class::Finite(x), Class::Finite(y)) => (x, y),
+    };
+    // `big` has the larger exponent; line both up 64 bits below its lsb.
+    let ((big, sbig), (small, ssmall)) = if x.exp >= y.exp { ((x, sa), (y, sb)) } else { ((y, sb), (x, sa)) };
+    let diff = (big.exp - small.exp) as u32;
+    let big_sig = big.sig << 64;
+    let small_sig = if diff <= 64 {
+        small.sig << (64 - diff)
+    } else if diff - 64 >= 128 {
+        1
+    } else {
+        let shifted = small.sig >> (diff - 64);
+        shifted | u128::from(shifted << (diff - 64) != small.sig)
+    };
+    let exp = big.exp - 64;
+    if sbig == ssmall {
+        return round_pack::<F>(sbig, big_sig + small_sig, exp);
+    }
+    match big_sig.cmp(&small_sig) {
+        core::cmp::Ordering::Equal => 0,
+        core::cmp::Ordering::Greater => round_pack::<F>(sbig, big_sig - small_sig, exp),
+        core::cmp::Ordering::Less => round_pack::<F>(ssmall, small_sig - big_sig, exp),
+    }
+}
+
+fn mul<F: Format>(a: u64, b: u64) -> u64 {
+    let negative = (a ^ b) & F::SIGN != 0;
+    let sign = if negative { F::SIGN } else { 0 };
+    match (classify::<F>(a), classify::<F>(b)) {
+        (Class::Nan, _) | (_, Class::Nan) => F::CANONICAL_NAN,
+        (Class::Inf, Class::Zero) | (Class::Zero, Class::Inf) => F::CANONICAL_NAN,
+        (Class::Inf, _) | (_, Class::Inf) => sign | F::INF,
+        (Class::Zero, _) | (_, Class::Zero) => sign,
+        (Class::Finite(x), Class::Finite(y)) => round_pack::<F>(negative, x.sig * y.sig, x.exp + y.exp),
+    }
+}
+
+fn div<F: Format>(a: u64, b: u64) -> u64 {
+    let negative = (a ^ b) & F::SIGN != 0;
+    let sign = if negative { F::SIGN } else { 0 };
+    match (classify::<F>(a), classify::<F>(b)) {
+        (Class::Nan, _) | (_, Class::Nan) => F::CANONICAL_NAN,
+        (Class::Inf, Class::Inf) | (Class::Zero, Class::Zero) => F::CANONICAL_NAN,
+        (Class::Inf, _) | (_, Class::Zero) => sign | F::INF,
+        (Class::Zero, _) | (_, Class::Inf) => sign,
+        (Class::Finite(x), Class::Finite(y)) => {
+            let (x, y) = (normalize::<F>(x), normalize::<F>(y));
+            // The quotient keeps at least `MANT + 3` bits.
+            let shift = F::MANT + 4;
+            let num = x.sig << shift;
+            let q = num / y.sig;
+            let sticky = u128::from(num % y.sig != 0);
+            round_pack::<F>(negative, q | sticky, x.exp - y.exp - shift as i32)
+        }
+    }
+}
+
+/// Integer square root: the largest `r` with `r * r <= n`.
+fn isqrt(n: u128) -> u128 {
+    let mut rem = n;
+    let mut root = 0u128;
+    let mut bit = 1u128 << ((127 - n.leading_zeros()) & !1);
+    while bit != 0 {
+        if rem >= root + bit {
+            rem -= root + bit;
+            root = (root >> 1) + bit;
+        } else {
+            root >>= 1;
+        }
+        bit >>= 2;
+    }
+    root
+}
+
+fn sqrt<F: Format>(a: u64) -> u64 {
+    match classify::<F>(a) {
+        Class::Nan => F::CANONICAL_NAN,
+        Class::Zero => a,
+        _ if a & F::SIGN != 0 => F::CANONICAL_NAN,
+        Class::Inf => a,
+        Class::Finite(x) => {
+            let x = normalize::<F>(x);
+            let (mut sig, mut exp) = (x.sig, x.exp);
+            if exp & 1 != 0 {
+                sig <<= 1;
+                exp -= 1;
+            }
+            // Scale by an even power of two so the root keeps at least
+            // `MANT + 3` bits.
+            let scale = (F::MANT / 2 + 4) * 2;
+            let n = sig << scale;
+            let r = isqrt(n);
+            let sticky = u128::from(r * r != n);
+            round_pack::<F>(false, r | sticky, (exp - scale as i32) / 2)
+        }
+    }
+}
+
+/// Convert between formats, rounding to nearest-even.
+fn convert<From: Format, To: Format>(a: u64) -> u64 {
+    let sign = if a & From::SIGN != 0 { To::SIGN } else { 0 };
+    match classify::<From>(a) {
+        Class::Nan => To::CANONICAL_NAN,
+        Class::Inf => sign | To::INF,
+        Class::Zero => sign,
+        Class::Finite(x) => round_pack::<To>(sign != 0, x.sig, x.exp),
+    }
+}
+
+/// Convert an integer of magnitude `mag` to the nearest float.
+fn from_int<F: Format>(negative: bool, mag: u64) -> u64 {
+    round_pack::<F>(negative, mag as u128, 0)
+}
+
+/// Round `a` to an integral value with `round`. The result is exact and
+/// libm computes it on the bits, so only a NaN's payload could depend on
+/// the target; it is made canonical like every other NaN result.
+fn round_f32(a: f32, round: fn(f32) -> f32) -> f32 {
+    if a.is_nan() {
+        return f32::from_bits(Binary32::CANONICAL_NAN as u32);
+    }
+    round(a)
+}
+
+/// `round_f32` for `f64`.
+fn round_f64(a: f64, round: fn(f64) -> f64) -> f64 {
+    if a.is_nan() {
+        return f64::from_bits(Binary64::CANONICAL_NAN);
+    }
+    round(a)
+}
+
+pub(crate) fn f32_add(a: f32, b: f32) -> f32 {
+    f32::from_bits(add::<Binary32>(a.to_bits() as u64, b.to_bits() as u64) as u32)
+}
+
+pub(crate) fn f32_sub(a: f32, b: f32) -> f32 {
+    f32::from_bits(add::<Binary32>(a.to_bits() as u64, (b.to_bits() ^ 0x8000_0000) as u64) as u32)
+}
+
+pub(crate) fn f32_mul(a: f32, b: f32) -> f32 {
+    f32::from_bits(mul::<Binary32>(a.to_bits() as u64, b.to_bits() as u64) as u32)
+}
+
+pub(crate) fn f32_div(a: f32, b: f32) -> f32 {
+    f32::from_bits(div::<Binary32>(a.to_bits() as u64, b.to_bits() as u64) as u32)
+}
+
+pub(crate) fn f32_sqrt(a: f32) -> f32 {
+    f32::from_bits(sqrt::<Binary32>(a.to_bits() as u64) as u32)
+}
+
+pub(crate) fn f32_ceil(a: f32) -> f32 {
+    round_f32(a, libm::ceilf)
+}
+
+pub(crate) fn f32_floor(a: f32) -> f32 {
+    round_f32(a, libm::floorf)
+}
+
+pub(crate) fn f32_trunc(a: f32) -> f32 {
+    round_f32(a, libm::truncf)
+}
+
+/// `f32.nearest`: ties to even.
+pub(crate) fn f32_nearest(a: f32) -> f32 {
+    round_f32(a, libm::rintf)
+}
+
+pub(crate) fn f64_add(a: f64, b: f64) -> f64 {
+    f64::from_bits(add::<Binary64>(a.to_bits(), b.to_bits()))
+}
+
+pub(crate) fn f64_sub(a: f64, b: f64) -> f64 {
+    f64::from_bits(add::<Binary64>(a.to_bits(), b.to_bits() ^ Binary64::SIGN))
+}
+
+pub(crate) fn f64_mul(a: f64, b: f64) -> f64 {
+    f64::from_bits(mul::<Binary64>(a.to_bits(), b.to_bits()))
+}
+
+pub(crate) fn f64_div(a: f64, b: f64) -> f64 {
+    f64::from_bits(div::<Binary64>(a.to_bits(), b.to_bits()))
+}
+
+pub(crate) fn f64_sqrt(a: f64) -> f64 {
+    f64::from_bits(sqrt::<Binary64>(a.to_bits()))
+}
+
+pub(crate) fn f64_ceil(a: f64) -> f64 {
+    round_f64(a, libm::ceil)
+}
+
+pub(crate) fn f64_floor(a: f64) -> f64 {
+    round_f64(a, libm::floor)
+}
+
+pub(crate) fn f64_trunc(a: f64) -> f64 {
+    round_f64(a, libm::trunc)
+}
+
+/// `f64.nearest`: ties to even.
+pub(crate) fn f64_nearest(a: f64) -> f64 {
+    round_f64(a, libm::rint)
+}
+
+/// `f32.demote_f64`
+pub(crate) fn f64_to_f32(a: f64) -> f32 {
+    f32::from_bits(convert::<Binary64, Binary32>(a.to_bits()) as u32)
+}
+
+/// `f64.promote_f32`
+pub(crate) fn f32_to_f64(a: f32) -> f64 {
+    f64::from_bits(convert::<Binary32, Binary64>(a.to_bits() as u64))
+}
+
+pub(crate) fn i64_to_f32(a: i64) -> f32 {
+    f32::from_bits(from_int::<Binary32>(a < 0, a.unsigned_abs()) as u32)
+}
+
+pub(crate) fn u64_to_f32(a: u64) -> f32 {
+    f32::from_bits(from_int::<Binary32>(false, a) as u32)
+}
+
+pub(crate) fn i64_to_f64(a: i64) -> f64 {
+    f64::from_bits(from_int::<Binary64>(a < 0, a.unsigned_abs()))
+}
+
+pub(crate) fn u64_to_f64(a: u64) -> f64 {
+    f64::from_bits(from_int::<Binary64>(false, a))
+}
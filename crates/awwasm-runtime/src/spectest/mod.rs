@@ -0,0 +1,228 @@
+//! The `spectest` host module and, with the `spectest` feature, a
+//! runner for `.wast` scripts.
+//!
+//! `AwwasmSpectest` provides the imports the official test suite expects
+//! from `spectest`: the `print*` functions, `global_i32`, `global_i64`,
+//! `global_f32`, `global_f64`, a 10..20 element funcref `table` and a
+//! 1..2 page `memory`. It serves the print calls through `AwwasmHost`,
+//! which also makes it a quick import set for examples.
+
+use alloc::vec::Vec;
+
+use crate::error::AwwasmTrap;
+use crate::func::{AwwasmFuncInst, AwwasmHost};
+use crate::global::{AwwasmGlobalInst, AwwasmGlobalType};
+use crate::imports::AwwasmImports;
+use crate::instance::{AwwasmExportInst, AwwasmModuleInst};
+use crate::memory::{AwwasmMemInst, AwwasmMemoryType};
+use crate::store::AwwasmStore;
+use crate::table::{AwwasmElemType, AwwasmTableInst, AwwasmTableType};
+use crate::values::{AwwasmExternAddr, AwwasmModuleAddr, AwwasmValue, AwwasmValueType};
+
+#[cfg(feature = "spectest")]
+mod script;
+
+#[cfg(feature = "spectest")]
+pub use script::{AwwasmWastError, AwwasmWastReport, AwwasmWastRunner};
+
+/// Import module name of the spec-test host module.
+pub const SPECTEST_MODULE: &[u8] = b"spectest";
+
+/// Default first `host_func_id` handed out to the print functions.
+pub const SPECTEST_HOST_FUNC_BASE: u32 = 0x5350_0000;
+
+/// The print functions served by `AwwasmSpectest`.
+///
+/// The discriminant is the offset of the function's `host_func_id`
+/// from the host's base id.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u32)]
+pub enum SpectestFunc {
+    Print,
+    PrintI32,
+    PrintI64,
+    PrintF32,
+    PrintF64,
+    PrintI32F32,
+    PrintF64F64,
+}
+
+impl SpectestFunc {
+    /// All print functions, in `host_func_id` order.
+    pub const ALL: &'static [SpectestFunc] = &[
+        SpectestFunc::Print,
+        SpectestFunc::PrintI32,
+        SpectestFunc::PrintI64,
+        SpectestFunc::PrintF32,
+        SpectestFunc::PrintF64,
+        SpectestFunc::PrintI32F32,
+        SpectestFunc::PrintF64F64,
+    ];
+
+    /// The import field name of this function.
+    pub fn name(self) -> &'static [u8] {
+        match self {
+            SpectestFunc::Print => b"print",
+            SpectestFunc::PrintI32 => b"print_i32",
+            SpectestFunc::PrintI64 => b"print_i64",
+            SpectestFunc::PrintF32 => b"print_f32",
+            SpectestFunc::PrintF64 => b"print_f64",
+            SpectestFunc::PrintI32F32 => b"print_i32_f32",
+            SpectestFunc::PrintF64F64 => b"print_f64_f64",
+        }
+    }
+
+    /// The parameter types; no print function returns anything.
+    pub fn params(self) -> &'static [AwwasmValueType] {
+        match self {
+            SpectestFunc::Print => &[],
+            SpectestFunc::PrintI32 => &[AwwasmValueType::I32],
+            SpectestFunc::PrintI64 => &[AwwasmValueType::I64],
+            SpectestFunc::PrintF32 => &[AwwasmValueType::F32],
+            SpectestFunc::PrintF64 => &[AwwasmValueType::F64],
+            SpectestFunc::PrintI32F32 => &[AwwasmValueType::I32, AwwasmValueType::F32],
+            SpectestFunc::PrintF64F64 => &[AwwasmValueType::F64, AwwasmValueType::F64],
+        }
+    }
+}
+
+/// The `spectest` host module.
+#[derive(Debug)]
+pub struct AwwasmSpectest {
+    base_id: u32,
+    /// Arguments of every print call not yet taken.
+    printed: Vec<Vec<AwwasmValue>>,
+    /// Also write print calls to stdout.
+    #[cfg(feature = "std")]
+    echo: bool,
+}
+
+impl AwwasmSpectest {
+    /// A host whose print functions use ids from
+    /// `SPECTEST_HOST_FUNC_BASE`.
+    pub fn new() -> Self {
+        Self::with_base_id(SPECTEST_HOST_FUNC_BASE)
+    }
+
+    /// A host whose print functions use ids from `base_id`, to keep
+    /// them apart from the embedder's own.
+    pub fn with_base_id(base_id: u32) -> Self {
+        Self {
+            base_id,
+            printed: Vec::new(),
+            #[cfg(feature = "std")]
+            echo: false,
+        }
+    }
+
+    /// Also write each print call to stdout, as the reference
+    /// interpreter does.
+    #[cfg(feature = "std")]
+    pub fn with_echo(mut self, echo: bool) -> Self {
+        self.echo = echo;
+        self
+    }
+
+    /// The `host_func_id` assigned to a print function.
+    pub fn host_func_id(&self, func: SpectestFunc) -> u32 {
+        self.base_id + func as u32
+    }
+
+    /// Map a `host_func_id` back to the print function it names.
+    pub fn lookup(&self, host_func_id: u32) -> Option<SpectestFunc> {
+        let offset = host_func_id.checked_sub(self.base_id)?;
+        SpectestFunc::ALL.get(offset as usize).copied()
+    }
+
+    /// Take the arguments of every print call so far.
+    pub fn take_printed(&mut self) -> Vec<Vec<AwwasmValue>> {
+        core::mem::take(&mut self.printed)
+    }
+
+    /// Register the whole module under `spectest`.
+    ///
+    /// The table is allocated in `store`, which the importing module
+    /// must be instantiated into; memory and globals are fresh for each
+    /// call.
+    pub fn add_to_imports<'a>(&self, store: &mut AwwasmStore<'a>, imports: &mut AwwasmImports<'a>) {
+        for &func in SpectestFunc::ALL {
+            imports.add_func(SPECTEST_MODULE, func.name(), AwwasmFuncInst::host(0, self.host_func_id(func)));
+        }
+        for (name, global) in globals() {
+            imports.add_global(SPECTEST_MODULE, name, global);
+        }
+        imports.add_table(SPECTEST_MODULE, b"table", store.alloc_table(table()));
+        imports.add_memory(SPECTEST_MODULE, b"memory", memory());
+    }
+
+    /// Add the module to `store` as an instance of its own, whose
+    /// exports other instances can be linked against (see
+    /// `AwwasmWastRunner::register`).
+    pub fn instantiate<'a>(&self, store: &mut AwwasmStore<'a>) -> AwwasmModuleAddr {
+        let mut inst = AwwasmModuleInst::new();
+        for &func in SpectestFunc::ALL {
+            let addr = store.alloc_func(AwwasmFuncInst::host(0, self.host_func_id(func)));
+            inst.funcaddrs.push(addr);
+            inst.push_export(AwwasmExportInst::new(func.name(), AwwasmExternAddr::Func(addr)));
+        }
+        for (name, global) in globals() {
+            let addr = store.alloc_global(global);
+            inst.globaladdrs.push(addr);
+            inst.push_export(AwwasmExportInst::new(name, AwwasmExternAddr::Global(addr)));
+        }
+        let table = store.alloc_table(table());
+        inst.tableaddrs.push(table);
+        inst.push_export(AwwasmExportInst::new(b"table", AwwasmExternAddr::Table(table)));
+        let mem = store.alloc_mem(memory());
+        inst.memaddrs.push(mem);
+        inst.push_export(AwwasmExportInst::new(b"memory", AwwasmExternAddr::Mem(mem)));
+        store.register_module(inst)
+    }
+}
+
+impl Default for AwwasmSpectest {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl AwwasmHost for AwwasmSpectest {
+    fn call(
+        &mut self,
+        host_func_id: u32,
+        args: &[AwwasmValue],
+        _mem: Option<&mut AwwasmMemInst>,
+    ) -> Result<Vec<AwwasmValue>, AwwasmTrap> {
+        let invalid = AwwasmTrap::InvalidHostCall { host_func_id };
+        let func = self.lookup(host_func_id).ok_or(invalid.clone())?;
+        if !args.iter().map(AwwasmValue::value_type).eq(func.params().iter().copied()) {
+            return Err(invalid);
+        }
+        #[cfg(feature = "std")]
+        if self.echo {
+            let values: Vec<std::string::String> = args.iter().map(|v| std::format!("{:?}", v)).collect();
+            std::println!("{}", values.join(" "));
+        }
+        self.printed.push(args.to_vec());
+        Ok(Vec::new())
+    }
+}
+
+/// `global_i32`, `global_i64`, `global_f32` and `global_f64`, all 666.
+fn globals() -> [(&'static [u8], AwwasmGlobalInst); 4] {
+    let global = |value_type, value| AwwasmGlobalInst::new(AwwasmGlobalType::immutable(value_type), value);
+    [
+        (b"global_i32", global(AwwasmValueType::I32, AwwasmValue::I32(666))),
+        (b"global_i64", global(AwwasmValueType::I64, AwwasmValue::I64(666))),
+        (b"global_f32", global(AwwasmValueType::F32, AwwasmValue::F32(666.6))),
+        (b"global_f64", global(AwwasmValueType::F64, AwwasmValue::F64(666.6))),
+    ]
+}
+
+fn table() -> AwwasmTableInst {
+    AwwasmTableInst::new(AwwasmTableType { min: 10, max: Some(20), elem_type: AwwasmElemType::FuncRef })
+}
+
+fn memory() -> AwwasmMemInst {
+    AwwasmMemInst::new(AwwasmMemoryType::new(1, Some(2)))
+}
@@ -0,0 +1,410 @@
+//! Runner for `.wast` spec-test scripts.
+//!
+//! `AwwasmWastRunner` parses a script and executes its directives
+//! against one store: `module`, `register`, `invoke`, `assert_return`,
+//! `assert_trap`, `assert_exhaustion`, `assert_invalid`,
+//! `assert_malformed` and `assert_unlinkable`. Other directives are
+//! counted as skipped.
+//!
+//! Trap assertions check that execution trapped, not the wording of the
+//! expected message. Registered memories and globals are imported the
+//! way `AwwasmImports` imports them, as copies.
+//!
+//! Module bytes and registered names live as long as the store, so they
+//! are leaked, as in `AwwasmStore::instantiate_wat`. Meant for test
+//! suites, not long-running processes.
+//!
+//! Only available with the `spectest` feature.
+
+use alloc::boxed::Box;
+use alloc::collections::BTreeMap;
+use alloc::format;
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+use core::fmt;
+
+use wast::core::{HeapType, NanPattern, V128Pattern, WastArgCore, WastRetCore};
+use wast::parser::{self, ParseBuffer};
+use wast::token::{Id, Span};
+use wast::{QuoteWat, Wast, WastArg, WastDirective, WastExecute, WastInvoke, WastRet, Wat};
+
+use crate::error::{AwwasmInstantiationError, AwwasmRuntimeError};
+use crate::func::AwwasmHost;
+use crate::imports::AwwasmImports;
+use crate::store::AwwasmStore;
+use super::AwwasmSpectest;
+use crate::values::{AwwasmExternAddr, AwwasmModuleAddr, AwwasmValue};
+
+/// A failed directive, or a script that could not be parsed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AwwasmWastError {
+    /// 1-based line of the directive.
+    pub line: usize,
+    /// What went wrong.
+    pub message: String,
+}
+
+impl fmt::Display for AwwasmWastError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "line {}: {}", self.line, self.message)
+    }
+}
+
+impl core::error::Error for AwwasmWastError {}
+
+/// Outcome of running a script.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct AwwasmWastReport {
+    /// Directives that behaved as the script expects.
+    pub passed: usize,
+    /// Directives the runner does not support.
+    pub skipped: usize,
+    /// Directives that did not.
+    pub failures: Vec<AwwasmWastError>,
+}
+
+impl AwwasmWastReport {
+    /// Whether no directive failed.
+    pub fn is_success(&self) -> bool {
+        self.failures.is_empty()
+    }
+}
+
+/// Why running a directive's action failed.
+enum ExecError {
+    /// Execution trapped, as `assert_trap` wants.
+    Trap(String),
+    /// Anything else.
+    Other(String),
+}
+
+impl ExecError {
+    fn message(self) -> String {
+        match self {
+            ExecError::Trap(m) => format!("unexpected trap: {}", m),
+            ExecError::Other(m) => m,
+        }
+    }
+}
+
+/// Executes `.wast` scripts against a store, with host functions run by
+/// `H`.
+#[derive(Debug)]
+pub struct AwwasmWastRunner<H> {
+    store: AwwasmStore<'static>,
+    host: H,
+    /// Names later modules can import from, with the instance behind
+    /// each.
+    registered: Vec<(&'static str, AwwasmModuleAddr)>,
+    /// Modules the script named with `$id`.
+    named: BTreeMap<String, AwwasmModuleAddr>,
+    /// The most recently defined module.
+    current: Option<AwwasmModuleAddr>,
+}
+
+impl AwwasmWastRunner<AwwasmSpectest> {
+    /// A runner whose scripts can import the `spectest` module, as the
+    /// official test suite does.
+    pub fn with_spectest() -> Self {
+        let mut runner = Self::new(AwwasmSpectest::new());
+        let addr = runner.host.instantiate(&mut runner.store);
+        runner.register("spectest", addr);
+        runner
+    }
+}
+
+impl<H: AwwasmHost> AwwasmWastRunner<H> {
+    /// A runner with an empty store.
+    pub fn new(host: H) -> Self {
+        Self {
+            store: AwwasmStore::new(),
+            host,
+            registered: Vec::new(),
+            named: BTreeMap::new(),
+            current: None,
+        }
+    }
+
+    /// The store modules are instantiated into.
+    pub fn store(&self) -> &AwwasmStore<'static> {
+        &self.store
+    }
+
+    /// The store, mutably, e.g. to instantiate host modules to
+    /// `register`.
+    pub fn store_mut(&mut self) -> &mut AwwasmStore<'static> {
+        &mut self.store
+    }
+
+    /// The host running imported host functions.
+    pub fn host_mut(&mut self) -> &mut H {
+        &mut self.host
+    }
+
+    /// Make the exports of `module` importable under `name`, like the
+    /// `register` directive.
+    pub fn register(&mut self, name: &str, module: AwwasmModuleAddr) {
+        let name: &'static str = Box::leak(name.into());
+        self.registered.push((name, module));
+    }
+
+    /// Run every directive of the script `wast`.
+    ///
+    /// Fails only if the script does not parse; failing directives are
+    /// collected in the report and the rest still run.
+    pub fn run(&mut self, wast: &str) -> Result<AwwasmWastReport, AwwasmWastError> {
+        let parse_error = |e: wast::Error| AwwasmWastError { line: line_of(e.span(), wast), message: e.message() };
+        let buf = ParseBuffer::new(wast).map_err(parse_error)?;
+        let script: Wast = parser::parse(&buf).map_err(parse_error)?;
+
+        let mut report = AwwasmWastReport::default();
+        for directive in script.directives {
+            let line = line_of(directive.span(), wast);
+            match self.directive(directive) {
+                Ok(true) => report.passed += 1,
+                Ok(false) => report.skipped += 1,
+                Err(message) => report.failures.push(AwwasmWastError { line, message }),
+            }
+        }
+        Ok(report)
+    }
+
+    /// Run one directive: `Ok(true)` if it passed, `Ok(false)` if it is
+    /// not supported.
+    fn directive(&mut self, directive: WastDirective<'_>) -> Result<bool, String> {
+        match directive {
+            WastDirective::Wat(mut module) => {
+                let id = quote_id(&module);
+                let bytes = module.encode().map_err(|e| e.to_string())?;
+                let addr = self.instantiate(bytes).map_err(|e| format!("module failed to instantiate: {}", e))?;
+                self.current = Some(addr);
+                if let Some(id) = id {
+                    self.named.insert(id.name().into(), addr);
+                }
+            }
+            WastDirective::Register { name, module, .. } => {
+                let addr = self.resolve(module)?;
+                self.register(name, addr);
+            }
+            WastDirective::Invoke(invoke) => {
+                self.invoke(&invoke).map_err(ExecError::message)?;
+            }
+            WastDirective::AssertReturn { exec, results, .. } => {
+                let values = self.execute(exec).map_err(ExecError::message)?;
+                if values.len() != results.len() {
+                    return Err(format!("expected {} results, got {:?}", results.len(), values));
+                }
+                for (value, expected) in values.iter().zip(&results) {
+                    if !matches_ret(value, expected)? {
+                        return Err(format!("expected {:?}, got {:?}", expected, values));
+                    }
+                }
+            }
+            WastDirective::AssertTrap { exec, message, .. } => match self.execute(exec) {
+                Err(ExecError::Trap(_)) => {}
+                Err(ExecError::Other(m)) => return Err(format!("expected trap \"{}\", got error: {}", message, m)),
+                Ok(values) => return Err(format!("expected trap \"{}\", got {:?}", message, values)),
+            },
+            WastDirective::AssertExhaustion { call, message, .. } => match self.invoke(&call) {
+                Err(ExecError::Trap(_)) => {}
+                Err(ExecError::Other(m)) => return Err(format!("expected \"{}\", got error: {}", message, m)),
+                Ok(values) => return Err(format!("expected \"{}\", got {:?}", message, values)),
+            },
+            WastDirective::AssertInvalid { mut module, message, .. }
+            | WastDirective::AssertMalformed { mut module, message, .. } => {
+                // Text that does not even encode is as malformed as it gets.
+                if let Ok(bytes) = module.encode() {
+                    if self.instantiate(bytes).is_ok() {
+                        return Err(format!("expected module to be rejected: \"{}\"", message));
+                    }
+                }
+            }
+            WastDirective::AssertUnlinkable { mut module, message, .. } => {
+                let bytes = module.encode().map_err(|e| e.to_string())?;
+                if self.instantiate(bytes).is_ok() {
+                    return Err(format!("expected module to fail to link: \"{}\"", message));
+                }
+            }
+            _ => return Ok(false),
+        }
+        Ok(true)
+    }
+
+    /// Run the action of an assertion.
+    fn execute(&mut self, exec: WastExecute<'_>) -> Result<Vec<AwwasmValue>, ExecError> {
+        match exec {
+            WastExecute::Invoke(invoke) => self.invoke(&invoke),
+            WastExecute::Wat(mut module) => {
+                let bytes = module.encode().map_err(|e| ExecError::Other(e.to_string()))?;
+                match self.instantiate(bytes) {
+                    Ok(_) => Ok(Vec::new()),
+                    // Start functions and segment initialization trap at
+                    // instantiation.
+                    Err(
+                        e @ (AwwasmInstantiationError::StartFunctionTrapped(_)
+                        | AwwasmInstantiationError::DataSegmentOutOfBounds { .. }
+                        | AwwasmInstantiationError::ElementSegmentOutOfBounds { .. }),
+                    ) => Err(ExecError::Trap(e.to_string())),
+                    Err(e) => Err(ExecError::Other(e.to_string())),
+                }
+            }
+            WastExecute::Get { module, global, .. } => {
+                let addr = self.resolve(module).map_err(ExecError::Other)?;
+                let global_addr = self
+                    .store
+                    .module(addr)
+                    .and_then(|m| m.get_global(global))
+                    .ok_or_else(|| ExecError::Other(format!("no global export \"{}\"", global)))?;
+                let value = self.store.get_global(global_addr).map_err(|e| ExecError::Other(e.to_string()))?;
+                Ok(alloc::vec![value])
+            }
+            _ => Err(ExecError::Other("unsupported action".into())),
+        }
+    }
+
+    fn invoke(&mut self, invoke: &WastInvoke<'_>) -> Result<Vec<AwwasmValue>, ExecError> {
+        let addr = self.resolve(invoke.module).map_err(ExecError::Other)?;
+        let args = invoke.args.iter().map(arg_value).collect::<Result<Vec<_>, _>>().map_err(ExecError::Other)?;
+        self.store.invoke_export(addr, invoke.name, &args, &mut self.host).map_err(|e: AwwasmRuntimeError| {
+            match e.trap() {
+                Some(trap) => ExecError::Trap(trap.to_string()),
+                None => ExecError::Other(e.to_string()),
+            }
+        })
+    }
+
+    /// The module `id` names, or the current one.
+    fn resolve(&self, id: Option<Id<'_>>) -> Result<AwwasmModuleAddr, String> {
+        match id {
+            Some(id) => self.named.get(id.name()).copied().ok_or_else(|| format!("unknown module ${}", id.name())),
+            None => self.current.ok_or_else(|| "no module defined yet".into()),
+        }
+    }
+
+    /// Instantiate `bytes` with the exports of every registered module
+    /// as imports.
+    fn instantiate(&mut self, bytes: Vec<u8>) -> Result<AwwasmModuleAddr, AwwasmInstantiationError> {
+        let mut imports = AwwasmImports::new();
+        for &(name, addr) in &self.registered {
+            let Some(inst) = self.store.module(addr) else { continue };
+            for export in &inst.exports {
+                match export.addr {
+                    AwwasmExternAddr::Func(f) => {
+                        if let Ok(func) = self.store.func(f) {
+                            imports.add_func(name, export.name, func.clone());
+                        }
+                    }
+                    AwwasmExternAddr::Table(t) => imports.add_table(name, export.name, t),
+                    AwwasmExternAddr::Mem(m) => {
+                        if let Ok(mem) = self.store.mem(m) {
+                            imports.add_memory(name, export.name, mem.clone());
+                        }
+                    }
+                    AwwasmExternAddr::Global(g) => {
+                        if let Ok(global) = self.store.global(g) {
+                            imports.add_global(name, export.name, global.clone());
+                        }
+                    }
+                }
+            }
+        }
+        self.store.instantiate_bytes(Vec::leak(bytes), &mut imports)
+    }
+}
+
+/// 1-based line of `span` in `text`.
+fn line_of(span: Span, text: &str) -> usize {
+    span.linecol_in(text).0 + 1
+}
+
+/// The `$id` of a module definition.
+fn quote_id<'a>(module: &QuoteWat<'a>) -> Option<Id<'a>> {
+    match module {
+        QuoteWat::Wat(Wat::Module(m)) => m.id,
+        _ => None,
+    }
+}
+
+fn arg_value(arg: &WastArg<'_>) -> Result<AwwasmValue, String> {
+    let WastArg::Core(arg) = arg else {
+        return Err("component arguments are not supported".into());
+    };
+    Ok(match arg {
+        WastArgCore::I32(v) => AwwasmValue::I32(*v),
+        WastArgCore::I64(v) => AwwasmValue::I64(*v),
+        WastArgCore::F32(v) => AwwasmValue::F32(f32::from_bits(v.bits)),
+        WastArgCore::F64(v) => AwwasmValue::F64(f64::from_bits(v.bits)),
+        WastArgCore::V128(v) => AwwasmValue::V128(u128::from_le_bytes(v.to_le_bytes())),
+        WastArgCore::RefNull(HeapType::Func) => AwwasmValue::FuncRef(None),
+        WastArgCore::RefNull(HeapType::Extern) => AwwasmValue::ExternRef(None),
+        WastArgCore::RefExtern(v) => AwwasmValue::ExternRef(Some(*v)),
+        other => return Err(format!("unsupported argument {:?}", other)),
+    })
+}
+
+/// Whether `value` is what `expected` describes.
+fn matches_ret(value: &AwwasmValue, expected: &WastRet<'_>) -> Result<bool, String> {
+    match expected {
+        WastRet::Core(expected) => matches_core(value, expected),
+        _ => Err("component results are not supported".into()),
+    }
+}
+
+fn matches_core(value: &AwwasmValue, expected: &WastRetCore<'_>) -> Result<bool, String> {
+    Ok(match (expected, value) {
+        (WastRetCore::I32(e), AwwasmValue::I32(v)) => e == v,
+        (WastRetCore::I64(e), AwwasmValue::I64(v)) => e == v,
+        (WastRetCore::F32(e), AwwasmValue::F32(v)) => f32_matches(e, *v),
+        (WastRetCore::F64(e), AwwasmValue::F64(v)) => f64_matches(e, *v),
+        (WastRetCore::V128(e), AwwasmValue::V128(v)) => v128_matches(e, *v),
+        (WastRetCore::RefNull(_), AwwasmValue::FuncRef(v)) => v.is_none(),
+        (WastRetCore::RefNull(_), AwwasmValue::ExternRef(v)) => v.is_none(),
+        (WastRetCore::RefFunc(_), AwwasmValue::FuncRef(v)) => v.is_some(),
+        (WastRetCore::Either(options), _) => {
+            for option in options {
+                if matches_core(value, option)? {
+                    return Ok(true);
+                }
+            }
+            false
+        }
+        (WastRetCore::I32(_) | WastRetCore::I64(_) | WastRetCore::F32(_) | WastRetCore::F64(_), _)
+        | (WastRetCore::V128(_) | WastRetCore::RefNull(_) | WastRetCore::RefFunc(_), _) => false,
+        (other, _) => return Err(format!("unsupported result {:?}", other)),
+    })
+}
+
+fn f32_matches(expected: &NanPattern<wast::token::Float32>, value: f32) -> bool {
+    let bits = value.to_bits();
+    match expected {
+        NanPattern::CanonicalNan => bits & 0x7fff_ffff == 0x7fc0_0000,
+        NanPattern::ArithmeticNan => value.is_nan() && bits & 0x0040_0000 != 0,
+        NanPattern::Value(e) => e.bits == bits,
+    }
+}
+
+fn f64_matches(expected: &NanPattern<wast::token::Float64>, value: f64) -> bool {
+    let bits = value.to_bits();
+    match expected {
+        NanPattern::CanonicalNan => bits & 0x7fff_ffff_ffff_ffff == 0x7ff8_0000_0000_0000,
+        NanPattern::ArithmeticNan => value.is_nan() && bits & 0x0008_0000_0000_0000 != 0,
+        NanPattern::Value(e) => e.bits == bits,
+    }
+}
+
+fn v128_matches(expected: &V128Pattern, value: u128) -> bool {
+    let bytes = value.to_le_bytes();
+    let lane = |i: usize, n: usize| {
+        let mut buf = [0; 8];
+        buf[..n].copy_from_slice(&bytes[i * n..(i + 1) * n]);
+        u64::from_le_bytes(buf)
+    };
+    match expected {
+        V128Pattern::I8(e) => e.iter().enumerate().all(|(i, &e)| lane(i, 1) as u8 == e as u8),
+        V128Pattern::I16(e) => e.iter().enumerate().all(|(i, &e)| lane(i, 2) as u16 == e as u16),
+        V128Pattern::I32(e) => e.iter().enumerate().all(|(i, &e)| lane(i, 4) as u32 == e as u32),
+        V128Pattern::I64(e) => e.iter().enumerate().all(|(i, &e)| lane(i, 8) == e as u64),
+        V128Pattern::F32(e) => e.iter().enumerate().all(|(i, e)| f32_matches(e, f32::from_bits(lane(i, 4) as u32))),
+        V128Pattern::F64(e) => e.iter().enumerate().all(|(i, e)| f64_matches(e, f64::from_bits(lane(i, 8)))),
+    }
+}
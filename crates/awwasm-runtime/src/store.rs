@@ -5,20 +5,144 @@
 
 #[cfg(feature = "alloc")]
 use alloc::vec::Vec;
+#[cfg(feature = "alloc")]
+use alloc::sync::Arc;
+#[cfg(feature = "alloc")]
+use alloc::collections::BTreeMap;
+#[cfg(feature = "alloc")]
+use alloc::string::{String, ToString};
+#[cfg(feature = "alloc")]
+use alloc::format;
+#[cfg(feature = "alloc")]
+use alloc::boxed::Box;
 
-use crate::values::{AwwasmFuncAddr, AwwasmTableAddr, AwwasmMemAddr, AwwasmGlobalAddr, AwwasmElemAddr, AwwasmDataAddr, AwwasmModuleAddr, AwwasmExternAddr};
-use crate::func::{AwwasmFuncInst, AwwasmElemInst, AwwasmDataInst};
-use crate::table::AwwasmTableInst;
-use crate::memory::AwwasmMemInst;
+use crate::values::{AwwasmFuncAddr, AwwasmTableAddr, AwwasmMemAddr, AwwasmGlobalAddr, AwwasmElemAddr, AwwasmDataAddr, AwwasmModuleAddr, AwwasmExternAddr, AwwasmExternKind};
+use crate::func::{AwwasmFuncInst, AwwasmFuncType, AwwasmWasmFuncInst, AwwasmElemInst, AwwasmDataInst, AwwasmHost, LazyResolvedCodeRef};
+use crate::table::{AwwasmTableInst, AwwasmTableType};
+use crate::memory::{AwwasmBoundsCheck, AwwasmMemInst};
 use crate::global::AwwasmGlobalInst;
-use crate::instance::{AwwasmModuleInst, AwwasmExportInst};
-use crate::error::{AwwasmRuntimeError, AwwasmInstantiationError};
+#[cfg(feature = "alloc")]
+use crate::interrupt::AwwasmInterruptHandle;
+#[cfg(feature = "std")]
+use crate::inspect::AwwasmInspector;
+#[cfg(feature = "alloc")]
+use crate::platform::AwwasmHostPlatform;
+#[cfg(feature = "alloc")]
+use crate::profile::AwwasmProfiler;
+#[cfg(feature = "alloc")]
+use crate::observer::{AwwasmInstantiationObserver, AwwasmInstantiationPhase};
+#[cfg(feature = "alloc")]
+use crate::fuel::AwwasmGasTable;
+use crate::fuel::AwwasmMetering;
+use crate::values::{AwwasmValue, AwwasmValueType};
+use crate::decode;
+use crate::exec;
+use crate::exec::{AwwasmExecArena, AwwasmExecutionMetrics, AwwasmStackLimits};
+use crate::artifact;
+use crate::async_call::AwwasmAsyncCall;
+use crate::audit::{AwwasmMemAccessor, AwwasmMemoryAuditor};
+use crate::host_trail::AwwasmHostCallAuditor;
+use crate::allowlist::{AwwasmModuleGate, AwwasmModuleHash};
+use crate::snapshot::{self, AwwasmInstanceSnapshot, AwwasmStoreSnapshot};
+#[cfg(feature = "alloc")]
+use crate::instance::{AwwasmModuleInst, AwwasmExportAlias, AwwasmExportInst};
+use crate::error::{AwwasmRuntimeError, AwwasmInstantiationCost, AwwasmInstantiationError, AwwasmModuleLimit, AwwasmNamePosition, AwwasmTrap};
 use crate::imports::{AwwasmImports, AwwasmImportValue};
 use crate::type_convert;
 
 use awwasm_parser::components::module::AwwasmModule;
 use awwasm_parser::components::types::{AwwasmImportKind, AwwasmExportKind};
 
+/// Bounds on the shape of modules a Store accepts, checked by
+/// `store_init` before anything is allocated; see
+/// `AwwasmStore::set_module_limits`.
+///
+/// Every size defaults to `u32::MAX`, i.e. no limit beyond the format's,
+/// and names need not be UTF-8.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AwwasmModuleLimits {
+    /// Maximum number of functions, imports included.
+    pub funcs: u32,
+    /// Maximum number of declared locals (parameters excluded) of any
+    /// one function.
+    pub locals_per_func: u32,
+    /// Maximum number of memories, imports included.
+    pub memories: u32,
+    /// Maximum number of tables, imports included.
+    pub tables: u32,
+    /// Maximum number of globals, imports included.
+    pub globals: u32,
+    /// Maximum size in bytes of any one data segment.
+    pub data_segment_bytes: u32,
+    /// Maximum length in bytes of any one export name.
+    pub export_name_bytes: u32,
+    /// Maximum length in bytes of any one import module or field name.
+    pub import_name_bytes: u32,
+    /// Whether import and export names must be valid UTF-8.
+    pub utf8_names: bool,
+    /// Maximum declared maximum, in pages, of any one shared memory the
+    /// module defines. Shared memories never move, so all of it is
+    /// allocated at instantiation.
+    pub shared_memory_pages: u32,
+}
+
+impl Default for AwwasmModuleLimits {
+    fn default() -> Self {
+        Self {
+            funcs: u32::MAX,
+            locals_per_func: u32::MAX,
+            memories: u32::MAX,
+            tables: u32::MAX,
+            globals: u32::MAX,
+            data_segment_bytes: u32::MAX,
+            export_name_bytes: u32::MAX,
+            import_name_bytes: u32::MAX,
+            utf8_names: false,
+            shared_memory_pages: u32::MAX,
+        }
+    }
+}
+
+/// Bounds on the work `store_init` does for one module before its first
+/// instruction runs, checked before anything is allocated; see
+/// `AwwasmStore::set_instantiation_limits`.
+///
+/// Every field defaults to `u64::MAX`, i.e. no limit.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AwwasmInstantiationLimits {
+    /// Maximum total bytes copied into memory by active data segments.
+    pub data_bytes: u64,
+    /// Maximum number of constant expressions evaluated: global
+    /// initializers and active segment offsets.
+    pub const_exprs: u64,
+    /// Maximum total entries of all element segments.
+    pub elements: u64,
+}
+
+impl Default for AwwasmInstantiationLimits {
+    fn default() -> Self {
+        Self { data_bytes: u64::MAX, const_exprs: u64::MAX, elements: u64::MAX }
+    }
+}
+
+/// Heap bytes held by a Store, see `AwwasmStore::heap_usage`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct AwwasmHeapUsage {
+    /// Linear memory contents.
+    pub memories: usize,
+    /// Table elements.
+    pub tables: usize,
+    /// Lowered function bodies.
+    pub code: usize,
+}
+
+impl AwwasmHeapUsage {
+    /// Sum of all categories.
+    pub fn total(&self) -> usize {
+        self.memories + self.tables + self.code
+    }
+}
+
 /// The Store - global runtime state for WebAssembly.
 ///
 /// Per the WebAssembly spec, the Store represents all global state that can
@@ -27,6 +151,9 @@ use awwasm_parser::components::types::{AwwasmImportKind, AwwasmExportKind};
 ///
 /// Multiple modules can share a Store, enabling cross-module calls and
 /// shared memories/tables.
+///
+/// A Store is `Send` and shares no state with other stores, so separate
+/// stores run on separate threads without locking; see the `pool` module.
 #[derive(Debug)]
 pub struct AwwasmStore<'a> {
     /// Function instances.
@@ -43,6 +170,71 @@ pub struct AwwasmStore<'a> {
     pub datas: Vec<AwwasmDataInst<'a>>,
     /// Module instances.
     pub modules: Vec<AwwasmModuleInst<'a>>,
+    /// Signature registry: every distinct function type seen by
+    /// `store_init`, once.
+    types: Vec<AwwasmFuncType>,
+    /// Registry ids of imported host functions, by function address.
+    /// Guest functions find theirs through their module's `type_ids`.
+    host_type_ids: BTreeMap<u32, u32>,
+    /// Module and field name each host function was first imported as,
+    /// by function address.
+    host_imports: BTreeMap<u32, (&'a [u8], &'a [u8])>,
+    /// Checked at calls and loop back-edges while executing.
+    #[cfg(feature = "alloc")]
+    interrupt: Option<AwwasmInterruptHandle>,
+    /// Read requests from other threads, served at the same points.
+    #[cfg(feature = "std")]
+    inspector: Option<AwwasmInspector>,
+    /// Time source for `deadline`.
+    #[cfg(feature = "alloc")]
+    platform: Option<Arc<dyn AwwasmHostPlatform>>,
+    /// Platform monotonic time at which running guests stop.
+    #[cfg(feature = "alloc")]
+    deadline: Option<u64>,
+    /// Told about every call and return while executing.
+    #[cfg(feature = "alloc")]
+    profiler: Option<Arc<dyn AwwasmProfiler>>,
+    /// Told about every phase of `store_init`.
+    #[cfg(feature = "alloc")]
+    instantiation_observer: Option<Arc<dyn AwwasmInstantiationObserver>>,
+    /// Told about every host-side access to this store's memories.
+    memory_auditor: Option<Arc<dyn AwwasmMemoryAuditor>>,
+    /// Who audited memory accesses are attributed to right now.
+    memory_accessor: AwwasmMemAccessor,
+    /// Told about every host function call while executing.
+    host_call_auditor: Option<Arc<dyn AwwasmHostCallAuditor>>,
+    /// Fuel left for guest code; `None` means unmetered.
+    fuel: Option<u64>,
+    /// What each instruction costs; `None` charges 1 for everything.
+    #[cfg(feature = "alloc")]
+    gas_table: Option<Arc<AwwasmGasTable>>,
+    /// Whether bodies lowered from now on charge their own fuel.
+    metering: AwwasmMetering,
+    /// Whether `invoke` collects `AwwasmExecutionMetrics`.
+    collect_metrics: bool,
+    /// What the last `invoke` that collected metrics did.
+    last_metrics: Option<AwwasmExecutionMetrics>,
+    /// Bounds on the shape of modules `store_init` accepts.
+    module_limits: AwwasmModuleLimits,
+    /// Bounds on the work of initializing one module.
+    instantiation_limits: AwwasmInstantiationLimits,
+    /// Decides which module binaries may be instantiated.
+    module_gate: Option<Arc<dyn AwwasmModuleGate>>,
+    /// Bounds on the interpreter's call and operand stacks.
+    stack_limits: AwwasmStackLimits,
+    /// Embedder-reserved interpreter stacks, lent to each call.
+    arena: Option<AwwasmExecArena>,
+    /// How guest loads and stores are kept inside their memory.
+    bounds_check: AwwasmBoundsCheck,
+    /// Whether guest state is wiped when the Store is dropped.
+    zeroize_on_drop: bool,
+    /// Most bytes of lowered bodies lazily lowered functions may keep.
+    code_cache_limit: Option<usize>,
+    /// Bytes of lowered bodies currently cached on function instances.
+    code_cache_bytes: usize,
+    /// Host functions currently calling back into guest code through an
+    /// `AwwasmCaller`.
+    pub(crate) reentry_depth: usize,
 }
 
 impl<'a> AwwasmStore<'a> {
@@ -56,7 +248,201 @@ impl<'a> AwwasmStore<'a> {
             elems: Vec::new(),
             datas: Vec::new(),
             modules: Vec::new(),
+            types: Vec::new(),
+            host_type_ids: BTreeMap::new(),
+            host_imports: BTreeMap::new(),
+            #[cfg(feature = "alloc")]
+            interrupt: None,
+            #[cfg(feature = "std")]
+            inspector: None,
+            #[cfg(feature = "alloc")]
+            platform: None,
+            #[cfg(feature = "alloc")]
+            deadline: None,
+            #[cfg(feature = "alloc")]
+            profiler: None,
+            #[cfg(feature = "alloc")]
+            instantiation_observer: None,
+            memory_auditor: None,
+            memory_accessor: AwwasmMemAccessor::Embedder,
+            host_call_auditor: None,
+            fuel: None,
+            #[cfg(feature = "alloc")]
+            gas_table: None,
+            metering: AwwasmMetering::default(),
+            collect_metrics: false,
+            last_metrics: None,
+            module_limits: AwwasmModuleLimits::default(),
+            instantiation_limits: AwwasmInstantiationLimits::default(),
+            module_gate: None,
+            stack_limits: AwwasmStackLimits::default(),
+            arena: None,
+            bounds_check: AwwasmBoundsCheck::default(),
+            zeroize_on_drop: false,
+            code_cache_limit: None,
+            code_cache_bytes: 0,
+            reentry_depth: 0,
+        }
+    }
+
+    /// Create a Store whose calls run on the stacks reserved in `arena`
+    /// instead of allocating their own.
+    ///
+    /// The stack limits become the arena's, so a call never grows it;
+    /// `set_stack_limits` may only lower them. A call started while
+    /// another holds the arena, such as one a host function makes
+    /// through its `AwwasmCaller`, allocates as usual.
+    pub fn with_arena(arena: AwwasmExecArena) -> Self {
+        let mut store = Self::new();
+        store.stack_limits = arena.limits();
+        store.arena = Some(arena);
+        store
+    }
+
+    /// Take the arena back, e.g. to hand it to another Store. Later calls
+    /// allocate their own stacks, still within the arena's limits unless
+    /// `set_stack_limits` raises them.
+    pub fn take_arena(&mut self) -> Option<AwwasmExecArena> {
+        self.arena.take()
+    }
+
+    /// Return the arena a finished call borrowed.
+    pub(crate) fn restore_arena(&mut self, arena: AwwasmExecArena) {
+        self.arena = Some(arena);
+    }
+
+    /// Reject modules whose shape exceeds `limits` from now on.
+    ///
+    /// Meant for hosts loading untrusted modules: a module asking for a
+    /// million functions or a gigabyte data segment is refused before
+    /// any of it is allocated.
+    pub fn set_module_limits(&mut self, limits: AwwasmModuleLimits) {
+        self.module_limits = limits;
+    }
+
+    /// The module limits in effect; unlimited unless set.
+    pub fn module_limits(&self) -> AwwasmModuleLimits {
+        self.module_limits
+    }
+
+    /// Bound the work of initializing each module from now on.
+    pub fn set_instantiation_limits(&mut self, limits: AwwasmInstantiationLimits) {
+        self.instantiation_limits = limits;
+    }
+
+    /// The current instantiation limits.
+    pub fn instantiation_limits(&self) -> AwwasmInstantiationLimits {
+        self.instantiation_limits
+    }
+
+    /// Fail if initializing `module` would take more work than the
+    /// instantiation limits allow.
+    fn check_instantiation_cost(&self, module: &AwwasmModule<'a>) -> Result<(), AwwasmInstantiationError> {
+        let limits = self.instantiation_limits;
+        if limits == AwwasmInstantiationLimits::default() {
+            return Ok(());
+        }
+        let check = |cost, max: u64, actual: u64| {
+            if actual > max {
+                return Err(AwwasmInstantiationError::InstantiationCostExceeded { cost, max, actual });
+            }
+            Ok(())
+        };
+        // Passive (flags 0x01) data segments are not copied until
+        // `memory.init` runs.
+        let active_data = module.data.as_deref().unwrap_or(&[]).iter().filter(|d| d.header.flags != 0x01);
+        let data_bytes = active_data.clone().map(|d| d.data_bytes.len() as u64).sum();
+        check(AwwasmInstantiationCost::DataBytes, limits.data_bytes, data_bytes)?;
+        let elems = decode::elems(module)?;
+        let elements = elems.iter().map(|e| e.funcs.len() as u64).sum();
+        check(AwwasmInstantiationCost::Elements, limits.elements, elements)?;
+        let active_elems = elems.iter().filter(|e| matches!(e.mode, decode::AwwasmElemMode::Active { .. })).count();
+        let const_exprs = decode::globals(module)?.len() + active_elems + active_data.count();
+        check(AwwasmInstantiationCost::ConstExprs, limits.const_exprs, const_exprs as u64)
+    }
+
+    /// Only instantiate modules `gate` permits; `None` permits all. See
+    /// the `allowlist` module.
+    pub fn set_module_gate(&mut self, gate: Option<Arc<dyn AwwasmModuleGate>>) {
+        self.module_gate = gate;
+    }
+
+    /// The module gate, if any.
+    pub fn module_gate(&self) -> Option<&Arc<dyn AwwasmModuleGate>> {
+        self.module_gate.as_ref()
+    }
+
+    /// Fail unless the module gate, if any, permits `bytes`.
+    fn check_module_gate(&self, bytes: &[u8]) -> Result<(), AwwasmInstantiationError> {
+        let Some(gate) = &self.module_gate else { return Ok(()) };
+        let hash = AwwasmModuleHash::of(bytes);
+        if !gate.permits(&hash, bytes) {
+            return Err(AwwasmInstantiationError::ModuleNotPermitted { hash: Some(hash.to_string()) });
         }
+        Ok(())
+    }
+
+    /// Fail if `module` exceeds the store's module limits.
+    fn check_module_shape(&self, module: &AwwasmModule<'a>) -> Result<(), AwwasmInstantiationError> {
+        let limits = self.module_limits;
+        let check = |limit, max: u32, actual: u64| {
+            if actual > u64::from(max) {
+                return Err(AwwasmInstantiationError::ModuleLimitExceeded { limit, max, actual });
+            }
+            Ok(())
+        };
+        let imports = module.imports.as_deref().unwrap_or(&[]);
+        let imported = |kind: fn(&AwwasmImportKind) -> bool| imports.iter().filter(|i| kind(&i.kind)).count() as u64;
+        let code_items = module.code.as_deref().unwrap_or(&[]);
+
+        let funcs = imported(|k| matches!(k, AwwasmImportKind::Function)) + code_items.len() as u64;
+        check(AwwasmModuleLimit::Funcs, limits.funcs, funcs)?;
+        let defined_memories = decode::memories(module)?;
+        let memories = imported(|k| matches!(k, AwwasmImportKind::Memory)) + defined_memories.len() as u64;
+        check(AwwasmModuleLimit::Memories, limits.memories, memories)?;
+        for mem_type in defined_memories.iter().filter(|mem_type| mem_type.shared) {
+            let pages = mem_type.max.unwrap_or(mem_type.min);
+            check(AwwasmModuleLimit::SharedMemoryPages, limits.shared_memory_pages, u64::from(pages))?;
+        }
+        let tables = imported(|k| matches!(k, AwwasmImportKind::Table)) + decode::tables(module)?.len() as u64;
+        check(AwwasmModuleLimit::Tables, limits.tables, tables)?;
+        let globals = imported(|k| matches!(k, AwwasmImportKind::Global)) + decode::globals(module)?.len() as u64;
+        check(AwwasmModuleLimit::Globals, limits.globals, globals)?;
+        for data_item in module.data.as_deref().unwrap_or(&[]) {
+            check(AwwasmModuleLimit::DataSegmentBytes, limits.data_segment_bytes, data_item.data_bytes.len() as u64)?;
+        }
+        // Lengths first, so a huge name is rejected before it is scanned.
+        let utf8 = |position, index: usize, name: &[u8]| {
+            if limits.utf8_names && core::str::from_utf8(name).is_err() {
+                return Err(AwwasmInstantiationError::InvalidName { position, index: index as u32 });
+            }
+            Ok(())
+        };
+        for (index, import_item) in imports.iter().enumerate() {
+            for (position, name) in [
+                (AwwasmNamePosition::ImportModule, import_item.module.bytes),
+                (AwwasmNamePosition::ImportField, import_item.name.bytes),
+            ] {
+                check(AwwasmModuleLimit::ImportNameBytes, limits.import_name_bytes, name.len() as u64)?;
+                utf8(position, index, name)?;
+            }
+        }
+        for (index, export_item) in module.exports.as_deref().unwrap_or(&[]).iter().enumerate() {
+            check(AwwasmModuleLimit::ExportNameBytes, limits.export_name_bytes, export_item.name.bytes.len() as u64)?;
+            utf8(AwwasmNamePosition::Export, index, export_item.name.bytes)?;
+        }
+        // Bodies stay unparsed until called; only their locals
+        // declarations are read, and only when locals are limited.
+        if limits.locals_per_func != u32::MAX {
+            for code_item in code_items {
+                let (locals, _) = exec::resolve_body(code_item.func_body).map_err(|e| {
+                    AwwasmInstantiationError::InvalidModule(crate::error::AwwasmParseError::new(e.to_string()))
+                })?;
+                let count = locals.iter().map(|decl| u64::from(decl.count)).sum();
+                check(AwwasmModuleLimit::Locals, limits.locals_per_func, count)?;
+            }
+        }
+        Ok(())
     }
 
     // ========================================================================
@@ -78,12 +464,63 @@ impl<'a> AwwasmStore<'a> {
     }
 
     /// Allocate a memory instance in the Store.
-    pub fn alloc_mem(&mut self, mem: AwwasmMemInst) -> AwwasmMemAddr {
+    pub fn alloc_mem(&mut self, mut mem: AwwasmMemInst) -> AwwasmMemAddr {
+        self.adopt_mem(&mut mem);
         let addr = AwwasmMemAddr(self.mems.len() as u32);
         self.mems.push(mem);
         addr
     }
 
+    /// Report host-side accesses to every memory of this Store, present
+    /// and future, to `auditor`; `None` stops auditing. See the `audit`
+    /// module.
+    pub fn set_memory_auditor(&mut self, auditor: Option<Arc<dyn AwwasmMemoryAuditor>>) {
+        self.memory_auditor = auditor;
+        let mut mems = core::mem::take(&mut self.mems);
+        mems.iter_mut().for_each(|mem| self.adopt_mem(mem));
+        self.mems = mems;
+    }
+
+    /// The memory auditor, if any.
+    pub fn memory_auditor(&self) -> Option<&Arc<dyn AwwasmMemoryAuditor>> {
+        self.memory_auditor.as_ref()
+    }
+
+    /// Attribute audited memory accesses to `accessor` from now on, and
+    /// return who they were attributed to before.
+    pub(crate) fn set_memory_accessor(&mut self, accessor: AwwasmMemAccessor) -> AwwasmMemAccessor {
+        let previous = core::mem::replace(&mut self.memory_accessor, accessor);
+        if self.memory_auditor.is_some() {
+            self.mems.iter_mut().for_each(|mem| mem.set_accessor(accessor));
+        }
+        previous
+    }
+
+    /// Report every host function call of later invocations to
+    /// `auditor`; `None` stops reporting. See the `host_trail` module.
+    pub fn set_host_call_auditor(&mut self, auditor: Option<Arc<dyn AwwasmHostCallAuditor>>) {
+        self.host_call_auditor = auditor;
+    }
+
+    /// The host call auditor, if any.
+    pub fn host_call_auditor(&self) -> Option<&Arc<dyn AwwasmHostCallAuditor>> {
+        self.host_call_auditor.as_ref()
+    }
+
+    /// Module and field name the host function at `addr` was first
+    /// imported as, if it was.
+    pub(crate) fn host_import(&self, addr: AwwasmFuncAddr) -> Option<(&'a [u8], &'a [u8])> {
+        self.host_imports.get(&addr.0).copied()
+    }
+
+    /// Give `mem` this Store's auditor, current accessor and zeroize
+    /// setting.
+    fn adopt_mem(&self, mem: &mut AwwasmMemInst) {
+        mem.set_auditor(self.memory_auditor.clone());
+        mem.set_accessor(self.memory_accessor);
+        mem.set_zeroize(self.zeroize_on_drop);
+    }
+
     /// Allocate a global instance in the Store.
     pub fn alloc_global(&mut self, global: AwwasmGlobalInst) -> AwwasmGlobalAddr {
         let addr = AwwasmGlobalAddr(self.globals.len() as u32);
@@ -112,105 +549,281 @@ impl<'a> AwwasmStore<'a> {
         addr
     }
 
+    /// Register a facade instance: one defining nothing itself, whose
+    /// exports are other instances' exports under new names. Embedders
+    /// and guests can then rely on the facade's names whichever helper
+    /// modules implement them.
+    ///
+    /// The aliases are resolved now; re-exporting one name twice keeps
+    /// the first. Fails with `ExportNotFound` if an aliased instance
+    /// does not export the name.
+    pub fn alias_exports(&mut self, aliases: &[AwwasmExportAlias<'a>]) -> Result<AwwasmModuleAddr, AwwasmRuntimeError> {
+        let mut facade = AwwasmModuleInst::new();
+        for alias in aliases {
+            let export = self.module(alias.module).and_then(|inst| inst.export(alias.export)).ok_or_else(|| {
+                AwwasmRuntimeError::ExportNotFound(String::from_utf8_lossy(alias.export).into_owned())
+            })?;
+            facade.push_export(AwwasmExportInst::new(alias.name, export.addr));
+        }
+        Ok(self.register_module(facade))
+    }
+
+    /// Offer the function and table exports of `module`, such as a
+    /// facade from `alias_exports`, to later instantiations as imports
+    /// from `import_module`.
+    ///
+    /// Memories and globals are left out: imports take those by value,
+    /// so the importer would get a copy rather than the instance's own.
+    /// Fails with `InvalidModuleAddr` if `module` is not in this store.
+    pub fn add_export_imports<M>(
+        &self,
+        module: AwwasmModuleAddr,
+        import_module: &'a M,
+        imports: &mut AwwasmImports<'a>,
+    ) -> Result<(), AwwasmRuntimeError>
+    where
+        M: AsRef<[u8]> + ?Sized,
+    {
+        let inst = self.module(module).ok_or(AwwasmRuntimeError::InvalidModuleAddr(module.0))?;
+        for export in &inst.exports {
+            match export.addr {
+                AwwasmExternAddr::Func(addr) => imports.add_func(import_module, export.name, self.func(addr)?.clone()),
+                AwwasmExternAddr::Table(addr) => imports.add_table(import_module, export.name, addr),
+                AwwasmExternAddr::Mem(_) | AwwasmExternAddr::Global(_) => {}
+            }
+        }
+        Ok(())
+    }
+
+    /// Parse `bytes` and instantiate the module into this Store.
+    ///
+    /// Runs `AwwasmModule::new`, `resolve_all_sections` and `store_init`
+    /// in one call. A module without any sections instantiates as empty.
+    ///
+    /// Function bodies and data segments keep pointing into `bytes`, so a
+    /// `&'static` module in flash executes in place; only lowered bodies
+    /// take RAM, bounded by `set_code_cache_limit`, and `with_arena`
+    /// keeps the interpreter's stacks in memory reserved up front.
+    ///
+    /// With a module gate set, `bytes` must pass it before they are
+    /// parsed.
+    pub fn instantiate_bytes(
+        &mut self,
+        bytes: &'a [u8],
+        imports: &mut AwwasmImports<'a>,
+    ) -> Result<AwwasmModuleAddr, AwwasmInstantiationError> {
+        self.check_module_gate(bytes)?;
+        let invalid = |e| AwwasmInstantiationError::InvalidModule(type_convert::parse_error(e));
+        let mut module = AwwasmModule::new(bytes).map_err(invalid)?;
+        // The parser has nothing to resolve without sections.
+        if module.sections.is_some() {
+            module.resolve_all_sections().map_err(invalid)?;
+        }
+        self.init_observed(&module, imports)
+    }
+
+    /// Compile WebAssembly text and instantiate it into this Store.
+    ///
+    /// The store borrows module bytes for its whole lifetime, so the
+    /// compiled binary is leaked. Meant for tests, examples and REPL-style
+    /// tools; use `instantiate_bytes` for anything long-running.
+    #[cfg(feature = "wat")]
+    pub fn instantiate_wat(
+        &mut self,
+        wat: &str,
+        imports: &mut AwwasmImports<'a>,
+    ) -> Result<AwwasmModuleAddr, AwwasmInstantiationError> {
+        let bytes = ::wat::parse_str(wat).map_err(|e| {
+            AwwasmInstantiationError::InvalidModule(crate::error::AwwasmParseError::new(e.to_string()))
+        })?;
+        self.instantiate_bytes(Vec::leak(bytes), imports)
+    }
+
     /// Instantiate a parsed `AwwasmModule` into this Store.
     ///
     /// Entry point for the runtime. It:
     /// 1. Resolves imports and allocates imported instances
     /// 2. Allocates module-defined functions (lazy — bodies stay as raw `&[u8]`)
-    /// 3. Allocates module-defined memories, globals
+    /// 3. Allocates module-defined memories, tables, globals
     /// 4. Allocates data segments (zero-copy `&'a [u8]` from parser)
     /// 5. Resolves exports
-    /// 6. Initializes active data segments (copies bytes into linear memory)
+    /// 6. Initializes active element and data segments
     /// 7. Registers and returns the `AwwasmModuleAddr`
+    ///
+    /// Each step is reported to the instantiation observer, if one is set.
+    ///
+    /// A store with a module gate cannot hash a parsed module, so it fails
+    /// with `ModuleNotPermitted`; use `instantiate_bytes` instead.
     pub fn store_init(
         &mut self,
         module: &AwwasmModule<'a>,
         imports: &mut AwwasmImports<'a>,
     ) -> Result<AwwasmModuleAddr, AwwasmInstantiationError> {
+        if self.module_gate.is_some() {
+            return Err(AwwasmInstantiationError::ModuleNotPermitted { hash: None });
+        }
+        self.init_observed(module, imports)
+    }
+
+    /// `store_init` without the module gate check.
+    fn init_observed(
+        &mut self,
+        module: &AwwasmModule<'a>,
+        imports: &mut AwwasmImports<'a>,
+    ) -> Result<AwwasmModuleAddr, AwwasmInstantiationError> {
+        let mut progress = AwwasmInitProgress::new(self);
+        let result = self.init_module(module, imports, &mut progress);
+        if let Err(ref e) = result {
+            progress.fail(e);
+        }
+        result
+    }
+
+    fn init_module(
+        &mut self,
+        module: &AwwasmModule<'a>,
+        imports: &mut AwwasmImports<'a>,
+        progress: &mut AwwasmInitProgress,
+    ) -> Result<AwwasmModuleAddr, AwwasmInstantiationError> {
+        self.check_module_shape(module)?;
+        self.check_instantiation_cost(module)?;
         let mut module_inst = AwwasmModuleInst::new();
+        module_inst.types = decode::func_types(module)?;
+        module_inst.custom_sections = decode::custom_sections(module)?;
+        module_inst.type_ids = module_inst.types.iter().map(|ty| self.intern_type(ty)).collect();
+        let import_func_types = decode::import_func_type_idxs(module)?;
+        let import_global_types = decode::import_global_types(module)?;
+        let import_table_types = decode::import_table_types(module)?;
+        let import_memory_types = decode::import_memory_types(module)?;
+        let mut import_func_count = 0;
+        let mut import_global_count = 0;
+        let mut import_table_count = 0;
+        let mut import_memory_count = 0;
 
         // Resolve imports
         if let Some(ref import_items) = module.imports {
             for import_item in import_items {
                 let mod_name = import_item.module.bytes;
                 let field_name = import_item.name.bytes;
+                let mismatch = |expected: String, got: String| AwwasmInstantiationError::ImportTypeMismatch {
+                    module: core::str::from_utf8(mod_name).unwrap_or("<invalid>").into(),
+                    name: core::str::from_utf8(field_name).unwrap_or("<invalid>").into(),
+                    expected,
+                    got,
+                };
 
-                match import_item.kind {
-                    AwwasmImportKind::Function => {
-                        let entry = imports.take(mod_name, field_name).ok_or_else(|| {
-                            AwwasmInstantiationError::MissingImport {
-                                module: core::str::from_utf8(mod_name).unwrap_or("<invalid>").into(),
-                                name: core::str::from_utf8(field_name).unwrap_or("<invalid>").into(),
-                            }
-                        })?;
-                        match entry.value {
-                            AwwasmImportValue::Func(func_inst) => {
-                                let addr = self.alloc_func(func_inst);
-                                module_inst.funcaddrs.push(addr);
+                if !imports.permits(mod_name, field_name) {
+                    return Err(AwwasmInstantiationError::ImportDenied {
+                        module: core::str::from_utf8(mod_name).unwrap_or("<invalid>").into(),
+                        name: core::str::from_utf8(field_name).unwrap_or("<invalid>").into(),
+                    });
+                }
+                let entry = imports.take(mod_name, field_name).ok_or_else(|| {
+                    AwwasmInstantiationError::MissingImport {
+                        module: core::str::from_utf8(mod_name).unwrap_or("<invalid>").into(),
+                        name: core::str::from_utf8(field_name).unwrap_or("<invalid>").into(),
+                    }
+                })?;
+
+                match (&import_item.kind, entry.value) {
+                    (AwwasmImportKind::Function, AwwasmImportValue::Func(mut func_inst)) => {
+                        let expected = import_func_types
+                            .get(import_func_count)
+                            .and_then(|&idx| module_inst.types.get(idx as usize).map(|ty| (idx, ty)));
+                        match func_inst {
+                            // Host functions take the signature the
+                            // importing module declares for them.
+                            AwwasmFuncInst::Host(ref mut host) => {
+                                if let Some((type_idx, _)) = expected {
+                                    host.type_idx = type_idx;
+                                }
                             }
-                            _ => {
-                                return Err(AwwasmInstantiationError::ImportTypeMismatch {
-                                    module: core::str::from_utf8(mod_name).unwrap_or("<invalid>").into(),
-                                    name: core::str::from_utf8(field_name).unwrap_or("<invalid>").into(),
-                                    expected: "function".into(),
-                                    got: "other".into(),
-                                });
+                            AwwasmFuncInst::Wasm(ref wasm) => {
+                                if let (Some((_, expected)), Some(got)) = (expected, self.wasm_func_type(wasm)) {
+                                    if expected != got {
+                                        return Err(mismatch(format!("{}", expected), format!("{}", got)));
+                                    }
+                                }
                             }
                         }
+                        import_func_count += 1;
+                        let host_type = match (&func_inst, expected) {
+                            (AwwasmFuncInst::Host(_), Some((type_idx, _))) => module_inst.type_ids.get(type_idx as usize).copied(),
+                            _ => None,
+                        };
+                        let addr = self.alloc_func(func_inst);
+                        if let Some(id) = host_type {
+                            self.host_type_ids.insert(addr.0, id);
+                        }
+                        if matches!(self.funcs[addr.0 as usize], AwwasmFuncInst::Host(_)) {
+                            self.host_imports.entry(addr.0).or_insert((mod_name, field_name));
+                        }
+                        module_inst.funcaddrs.push(addr);
                     }
-                    AwwasmImportKind::Memory => {
-                        let entry = imports.take(mod_name, field_name).ok_or_else(|| {
-                            AwwasmInstantiationError::MissingImport {
-                                module: core::str::from_utf8(mod_name).unwrap_or("<invalid>").into(),
-                                name: core::str::from_utf8(field_name).unwrap_or("<invalid>").into(),
-                            }
-                        })?;
-                        match entry.value {
-                            AwwasmImportValue::Memory(mem_inst) => {
-                                let addr = self.alloc_mem(mem_inst);
-                                module_inst.memaddrs.push(addr);
-                            }
-                            _ => {
-                                return Err(AwwasmInstantiationError::ImportTypeMismatch {
-                                    module: core::str::from_utf8(mod_name).unwrap_or("<invalid>").into(),
-                                    name: core::str::from_utf8(field_name).unwrap_or("<invalid>").into(),
-                                    expected: "memory".into(),
-                                    got: "other".into(),
-                                });
+                    (AwwasmImportKind::Memory, AwwasmImportValue::Memory(mem_inst)) => {
+                        // Accesses are scaled by the page size, so it has
+                        // to be the one the module was written for.
+                        // A shared memory only links where one is expected.
+                        if let Some(expected) = import_memory_types.get(import_memory_count) {
+                            if expected.page_size_log2 != mem_inst.type_.page_size_log2
+                                || expected.shared != mem_inst.type_.shared
+                            {
+                                return Err(mismatch(format!("{}", expected), format!("{}", mem_inst.type_)));
                             }
                         }
+                        import_memory_count += 1;
+                        let addr = self.alloc_mem(mem_inst);
+                        module_inst.memaddrs.push(addr);
                     }
-                    AwwasmImportKind::Global => {
-                        let entry = imports.take(mod_name, field_name).ok_or_else(|| {
-                            AwwasmInstantiationError::MissingImport {
-                                module: core::str::from_utf8(mod_name).unwrap_or("<invalid>").into(),
-                                name: core::str::from_utf8(field_name).unwrap_or("<invalid>").into(),
+                    (AwwasmImportKind::Global, AwwasmImportValue::Global(global_inst)) => {
+                        if let Some(expected) = import_global_types.get(import_global_count) {
+                            if *expected != global_inst.type_ {
+                                return Err(mismatch(format!("{}", expected), format!("{}", global_inst.type_)));
                             }
-                        })?;
-                        match entry.value {
-                            AwwasmImportValue::Global(global_inst) => {
-                                let addr = self.alloc_global(global_inst);
-                                module_inst.globaladdrs.push(addr);
-                            }
-                            _ => {
-                                return Err(AwwasmInstantiationError::ImportTypeMismatch {
-                                    module: core::str::from_utf8(mod_name).unwrap_or("<invalid>").into(),
-                                    name: core::str::from_utf8(field_name).unwrap_or("<invalid>").into(),
-                                    expected: "global".into(),
-                                    got: "other".into(),
-                                });
+                        }
+                        import_global_count += 1;
+                        let addr = self.alloc_global(global_inst);
+                        module_inst.globaladdrs.push(addr);
+                    }
+                    (AwwasmImportKind::Table, AwwasmImportValue::Table(addr)) => {
+                        let got = self.tables.get(addr.0 as usize).map(|t| AwwasmTableType { min: t.size(), ..t.type_ });
+                        let Some(got) = got else {
+                            return Err(mismatch("table".into(), format!("invalid table address {}", addr.0)));
+                        };
+                        if let Some(expected) = import_table_types.get(import_table_count) {
+                            if !got.matches(expected) {
+                                return Err(mismatch(format!("{}", expected), format!("{}", got)));
                             }
                         }
+                        import_table_count += 1;
+                        module_inst.tableaddrs.push(addr);
                     }
-                    // Table imports not yet supported
-                    AwwasmImportKind::Table => {
-                        return Err(AwwasmInstantiationError::UnsupportedType {
-                            description: "table imports not yet supported".into(),
-                        });
+                    (kind, value) => {
+                        let expected = match kind {
+                            AwwasmImportKind::Function => match import_func_types
+                                .get(import_func_count)
+                                .and_then(|&idx| module_inst.types.get(idx as usize))
+                            {
+                                Some(ty) => format!("{}", ty),
+                                None => "function".into(),
+                            },
+                            AwwasmImportKind::Global => match import_global_types.get(import_global_count) {
+                                Some(ty) => format!("{}", ty),
+                                None => "global".into(),
+                            },
+                            AwwasmImportKind::Table => match import_table_types.get(import_table_count) {
+                                Some(ty) => format!("{}", ty),
+                                None => "table".into(),
+                            },
+                            AwwasmImportKind::Memory => "memory".into(),
+                        };
+                        return Err(mismatch(expected, self.describe_import(&value)));
                     }
                 }
             }
         }
+        let count = module.imports.as_ref().map_or(0, |items| items.len() as u32);
+        progress.report(AwwasmInstantiationPhase::ImportsResolved { count });
 
         // Allocate module-defined functions
         let func_items = module.funcs.as_deref().unwrap_or(&[]);
@@ -226,23 +839,52 @@ impl<'a> AwwasmStore<'a> {
         // Pre-compute the module address
         let pending_module_addr = AwwasmModuleAddr(self.modules.len() as u32);
 
-        for code_item in code_items {
+        for (func_idx, code_item) in code_items.iter().enumerate() {
             // Store the raw func_body bytes — zero-copy from parser.
             // Resolution happens later (on-demand or via async batch).
-            let func = AwwasmFuncInst::wasm(0, pending_module_addr, code_item.func_body);
+            let type_idx = func_items.get(func_idx).map_or(0, |f| f.type_idx);
+            let func = AwwasmFuncInst::wasm(type_idx, pending_module_addr, code_item.func_body);
             let addr = self.alloc_func(func);
             module_inst.funcaddrs.push(addr);
         }
+        progress.report(AwwasmInstantiationPhase::FuncsAllocated { count: code_items.len() as u32 });
 
         // Allocate module-defined memories
-        if let Some(ref mem_items) = module.memories {
-            for mem_item in mem_items {
-                let mem_type = type_convert::memory_params_to_type(&mem_item.limits);
-                let mem = AwwasmMemInst::new(mem_type);
-                let addr = self.alloc_mem(mem);
-                module_inst.memaddrs.push(addr);
-            }
+        let (mut count, mut bytes) = (0, 0);
+        for mem_type in decode::memories(module)? {
+            let mem = AwwasmMemInst::try_new(mem_type)?;
+            count += 1;
+            bytes += mem.size_bytes() as u64;
+            let addr = self.alloc_mem(mem);
+            module_inst.memaddrs.push(addr);
+        }
+        progress.report(AwwasmInstantiationPhase::MemoriesAllocated { count, bytes });
+
+        // Allocate module-defined tables
+        let mut count = 0;
+        for table_type in decode::tables(module)? {
+            let addr = self.alloc_table(AwwasmTableInst::try_new(table_type)?);
+            module_inst.tableaddrs.push(addr);
+            count += 1;
         }
+        progress.report(AwwasmInstantiationPhase::TablesAllocated { count });
+
+        // Allocate module-defined globals. Initializers may only read
+        // imported globals, which are all allocated by now.
+        let imported_globals: Vec<AwwasmValue> = module_inst
+            .globaladdrs
+            .iter()
+            .map(|addr| self.globals[addr.0 as usize].value)
+            .collect();
+        let mut count = 0;
+        for (global_type, init) in decode::globals(module)? {
+            let value = decode::eval_const_value(init, &imported_globals, &module_inst.funcaddrs)?;
+            module_inst.refs.extend(decode::const_ref_func(init));
+            let addr = self.alloc_global(AwwasmGlobalInst::new(global_type, value));
+            module_inst.globaladdrs.push(addr);
+            count += 1;
+        }
+        progress.report(AwwasmInstantiationPhase::GlobalsInitialized { count });
 
         // Allocate data segments - zero-copy from parser
         if let Some(ref data_items) = module.data {
@@ -256,47 +898,94 @@ impl<'a> AwwasmStore<'a> {
         // Resolve exports
         if let Some(ref export_items) = module.exports {
             for export_item in export_items {
-                let addr = match export_item.kind {
-                    AwwasmExportKind::Function => {
-                        let func_addr = module_inst.funcaddrs.get(export_item.index as usize)
-                            .copied()
-                            .ok_or_else(|| AwwasmInstantiationError::MissingImport {
-                                module: "self".into(),
-                                name: core::str::from_utf8(export_item.name.bytes).unwrap_or("<invalid>").into(),
-                            })?;
-                        AwwasmExternAddr::Func(func_addr)
-                    }
-                    AwwasmExportKind::Memory => {
-                        let mem_addr = module_inst.memaddrs.get(export_item.index as usize)
-                            .copied()
-                            .ok_or_else(|| AwwasmInstantiationError::MissingImport {
-                                module: "self".into(),
-                                name: core::str::from_utf8(export_item.name.bytes).unwrap_or("<invalid>").into(),
-                            })?;
-                        AwwasmExternAddr::Mem(mem_addr)
-                    }
-                    AwwasmExportKind::Table => {
-                        let table_addr = module_inst.tableaddrs.get(export_item.index as usize)
-                            .copied()
-                            .ok_or_else(|| AwwasmInstantiationError::MissingImport {
-                                module: "self".into(),
-                                name: core::str::from_utf8(export_item.name.bytes).unwrap_or("<invalid>").into(),
-                            })?;
-                        AwwasmExternAddr::Table(table_addr)
-                    }
-                    AwwasmExportKind::Global => {
-                        let global_addr = module_inst.globaladdrs.get(export_item.index as usize)
-                            .copied()
-                            .ok_or_else(|| AwwasmInstantiationError::MissingImport {
-                                module: "self".into(),
-                                name: core::str::from_utf8(export_item.name.bytes).unwrap_or("<invalid>").into(),
-                            })?;
-                        AwwasmExternAddr::Global(global_addr)
-                    }
+                let index = export_item.index as usize;
+                let (kind, count, addr) = match export_item.kind {
+                    AwwasmExportKind::Function => (
+                        AwwasmExternKind::Func,
+                        module_inst.funcaddrs.len(),
+                        module_inst.funcaddrs.get(index).map(|&a| AwwasmExternAddr::Func(a)),
+                    ),
+                    AwwasmExportKind::Memory => (
+                        AwwasmExternKind::Mem,
+                        module_inst.memaddrs.len(),
+                        module_inst.memaddrs.get(index).map(|&a| AwwasmExternAddr::Mem(a)),
+                    ),
+                    AwwasmExportKind::Table => (
+                        AwwasmExternKind::Table,
+                        module_inst.tableaddrs.len(),
+                        module_inst.tableaddrs.get(index).map(|&a| AwwasmExternAddr::Table(a)),
+                    ),
+                    AwwasmExportKind::Global => (
+                        AwwasmExternKind::Global,
+                        module_inst.globaladdrs.len(),
+                        module_inst.globaladdrs.get(index).map(|&a| AwwasmExternAddr::Global(a)),
+                    ),
                 };
-                module_inst.exports.push(AwwasmExportInst::new(export_item.name.bytes, addr));
+                if let AwwasmExportKind::Function = export_item.kind {
+                    module_inst.refs.insert(export_item.index);
+                }
+                let addr = addr.ok_or_else(|| AwwasmInstantiationError::ExportIndexOutOfBounds {
+                    kind,
+                    name: core::str::from_utf8(export_item.name.bytes).unwrap_or("<invalid>").into(),
+                    index: export_item.index,
+                    count: count as u32,
+                })?;
+                module_inst.push_export(AwwasmExportInst::new(export_item.name.bytes, addr));
             }
         }
+        let count = module.exports.as_ref().map_or(0, |items| items.len() as u32);
+        progress.report(AwwasmInstantiationPhase::ExportsResolved { count });
+
+        // Record the start function for the embedder; it is not run here.
+        if let Some(index) = decode::start(module)? {
+            let addr = module_inst.func(index).ok_or(AwwasmInstantiationError::StartIndexOutOfBounds {
+                index,
+                count: module_inst.funcaddrs.len() as u32,
+            })?;
+            module_inst.start = Some(addr);
+        }
+
+        // Initialize active element segments (write function
+        // addresses into tables)
+        for (seg_idx, elem) in decode::elems(module)?.into_iter().enumerate() {
+            module_inst.refs.extend(elem.funcs.iter().copied());
+            // Declarative segments exist only to declare their functions.
+            let decode::AwwasmElemMode::Active { table, offset } = elem.mode else {
+                continue;
+            };
+            let offset = match decode::eval_const_value(offset, &imported_globals, &module_inst.funcaddrs)? {
+                AwwasmValue::I32(offset) => offset as u32,
+                _ => {
+                    return Err(AwwasmInstantiationError::InvalidConstExpr {
+                        description: format!("element segment {} offset is not i32", seg_idx),
+                        source: None,
+                    })
+                }
+            };
+            let table_size = module_inst
+                .table(table)
+                .and_then(|addr| self.tables.get(addr.0 as usize))
+                .map_or(0, |t| t.size());
+            let out_of_bounds = AwwasmInstantiationError::ElementSegmentOutOfBounds {
+                segment_idx: seg_idx as u32,
+                offset,
+                size: elem.funcs.len() as u32,
+                table_size,
+            };
+            let end = offset.checked_add(elem.funcs.len() as u32).ok_or(out_of_bounds.clone())?;
+            if end > table_size {
+                return Err(out_of_bounds);
+            }
+            let table_addr = module_inst.table(table).ok_or(out_of_bounds.clone())?;
+            for (i, func_idx) in elem.funcs.iter().enumerate() {
+                let func_addr = module_inst.func(*func_idx).ok_or(out_of_bounds.clone())?;
+                self.tables[table_addr.0 as usize].elem[offset as usize + i] = Some(func_addr);
+            }
+            progress.report(AwwasmInstantiationPhase::ElemSegmentInitialized {
+                index: seg_idx as u32,
+                len: elem.funcs.len() as u32,
+            });
+        }
 
         // Initialize active data segments
         // Active segments (flags 0x00 or 0x02) copy data into memory.
@@ -323,6 +1012,7 @@ impl<'a> AwwasmStore<'a> {
                 let offset_expr = data_item.header.offset.as_ref().ok_or_else(|| {
                     AwwasmInstantiationError::InvalidConstExpr {
                         description: format!("data segment {} missing offset expression", seg_idx),
+                        source: None,
                     }
                 })?;
 
@@ -359,16 +1049,636 @@ impl<'a> AwwasmStore<'a> {
                 }
 
                 // The actual memcpy — unavoidable per wasm spec
-                mem.data[offset..offset + data_bytes.len()].copy_from_slice(data_bytes);
+                mem.data.write(offset, data_bytes);
+                progress.report(AwwasmInstantiationPhase::DataSegmentInitialized {
+                    index: seg_idx as u32,
+                    bytes: data_bytes.len() as u32,
+                });
             }
         }
 
         // Register module instance
         let addr = self.register_module(module_inst);
+        progress.report(AwwasmInstantiationPhase::Done);
 
         Ok(addr)
     }
 
+    // ========================================================================
+    // Execution
+    // ========================================================================
+
+    /// Call a function and return its results.
+    ///
+    /// Guest code runs in the interpreter (see `exec`); calls to host
+    /// functions are handed to `host` together with the caller's default
+    /// memory.
+    pub fn invoke(
+        &mut self,
+        addr: AwwasmFuncAddr,
+        args: &[AwwasmValue],
+        host: &mut dyn AwwasmHost,
+    ) -> Result<Vec<AwwasmValue>, AwwasmRuntimeError> {
+        exec::invoke(self, addr, args, host)
+    }
+
+    /// Call the function at `addr` like `invoke` and also return what
+    /// the call did. Metrics are collected for this call only, unless
+    /// `set_collect_metrics` turned them on for every call.
+    pub fn invoke_with_metrics(
+        &mut self,
+        addr: AwwasmFuncAddr,
+        args: &[AwwasmValue],
+        host: &mut dyn AwwasmHost,
+    ) -> Result<(Vec<AwwasmValue>, AwwasmExecutionMetrics), AwwasmRuntimeError> {
+        let collect = core::mem::replace(&mut self.collect_metrics, true);
+        self.last_metrics = None;
+        let results = self.invoke(addr, args, host);
+        self.collect_metrics = collect;
+        Ok((results?, self.last_metrics.unwrap_or_default()))
+    }
+
+    /// Call the function export `name` of `module`.
+    ///
+    /// Looks the export up, checks that it is a function and calls it
+    /// like `invoke`, which checks `args` against its signature.
+    pub fn invoke_export(
+        &mut self,
+        module: AwwasmModuleAddr,
+        name: &str,
+        args: &[AwwasmValue],
+        host: &mut dyn AwwasmHost,
+    ) -> Result<Vec<AwwasmValue>, AwwasmRuntimeError> {
+        let func = self
+            .module(module)
+            .and_then(|inst| inst.get_func(name))
+            .ok_or_else(|| AwwasmRuntimeError::ExportNotFound(name.into()))?;
+        self.invoke(func, args, host)
+    }
+
+    /// Call the function at `addr` as a future that yields to its
+    /// executor every `yield_every` units of fuel (instructions on a
+    /// store without fuel); see the `async_call` module.
+    pub fn call_async<'s, H: AwwasmHost>(
+        &'s mut self,
+        addr: AwwasmFuncAddr,
+        args: &[AwwasmValue],
+        host: &'s mut H,
+        yield_every: u64,
+    ) -> AwwasmAsyncCall<'s, 'a, H> {
+        AwwasmAsyncCall::new(self, addr, args, host, yield_every)
+    }
+
+    /// Lower every function body `module` defines and serialize the
+    /// result, for `load_precompiled` to skip lowering on a later run.
+    ///
+    /// The artifact is tied to this runtime version, to the exact
+    /// function bodies of the module it was made from, and to the store's
+    /// metering and gas table.
+    #[cfg(feature = "alloc")]
+    pub fn precompile(&mut self, module: AwwasmModuleAddr) -> Result<Vec<u8>, AwwasmRuntimeError> {
+        let mut funcs = Vec::new();
+        for addr in self.defined_funcs(module)? {
+            let compiled = exec::compile(self, addr)?;
+            let AwwasmFuncInst::Wasm(f) = self.func(addr)? else {
+                return Err(AwwasmRuntimeError::HostFunctionNotExecutable);
+            };
+            funcs.push((artifact::body_hash(exec::body(f)?.1), compiled));
+        }
+        let refs: Vec<_> = funcs.iter().map(|(hash, code)| (*hash, &**code)).collect();
+        Ok(artifact::write(&refs, self.instrumentation().as_deref()))
+    }
+
+    /// Install the lowered bodies from a `precompile` artifact for
+    /// `module`, an instance of the same module binary.
+    ///
+    /// Fails with `InvalidArtifact`, leaving every function untouched, if
+    /// the artifact is malformed, from another runtime version, does not
+    /// match the module's function bodies, or was metered differently
+    /// from this store.
+    #[cfg(feature = "alloc")]
+    pub fn load_precompiled(&mut self, module: AwwasmModuleAddr, bytes: &[u8]) -> Result<(), AwwasmRuntimeError> {
+        let funcs = artifact::read(bytes, self.instrumentation().as_deref())?;
+        let addrs = self.defined_funcs(module)?;
+        if funcs.len() != addrs.len() {
+            return Err(AwwasmRuntimeError::InvalidArtifact(String::from("function count does not match the module")));
+        }
+        for (&addr, func) in addrs.iter().zip(&funcs) {
+            let AwwasmFuncInst::Wasm(f) = self.func(addr)? else {
+                return Err(AwwasmRuntimeError::HostFunctionNotExecutable);
+            };
+            let (locals, code) = exec::body(f)?;
+            if artifact::body_hash(code) != func.hash || locals != func.code.locals {
+                return Err(AwwasmRuntimeError::InvalidArtifact(String::from("function bodies do not match the module")));
+            }
+        }
+        let refs = self.module(module).map(|m| &m.refs);
+        let undeclared = funcs.iter().flat_map(|f| &f.code.ops).any(|op| {
+            matches!(op, crate::ir::Op::RefFunc(idx) if !refs.is_some_and(|refs| refs.contains(idx)))
+        });
+        if undeclared {
+            return Err(AwwasmRuntimeError::InvalidArtifact(String::from("ref.func names an undeclared function")));
+        }
+        for (addr, func) in addrs.into_iter().zip(funcs) {
+            exec::install(self, addr, Arc::new(func.code))?;
+        }
+        Ok(())
+    }
+
+    /// Capture the state of every instance in the store (see
+    /// `snapshot`).
+    pub fn snapshot(&self) -> Result<AwwasmStoreSnapshot, AwwasmRuntimeError> {
+        snapshot::capture(self)
+    }
+
+    /// Put back the state captured by `snapshot`.
+    ///
+    /// The store must hold the same modules, instantiated from the same
+    /// bytes in the same order, as the one the snapshot was taken from.
+    /// Fails with `SnapshotMismatch`, leaving the store untouched, if it
+    /// does not.
+    pub fn restore(&mut self, snapshot: &AwwasmStoreSnapshot) -> Result<(), AwwasmRuntimeError> {
+        snapshot::restore(self, snapshot)
+    }
+
+    /// Capture the state of one instance so it can move to another store
+    /// (see `snapshot`).
+    ///
+    /// Fails with `SnapshotMismatch` if a table or global of the instance
+    /// refers to a function outside its function index space.
+    pub fn extract_instance(&self, module: AwwasmModuleAddr) -> Result<AwwasmInstanceSnapshot, AwwasmRuntimeError> {
+        snapshot::capture_instance(self, module)
+    }
+
+    /// Put the state captured by `extract_instance` into `module`, which
+    /// must be a fresh instance of the same module bytes with the same
+    /// kind of imports. Imported memories, tables and globals are
+    /// overwritten too, since the guest's state lives there as well.
+    ///
+    /// Fails with `SnapshotMismatch`, leaving the store untouched, if the
+    /// instance does not fit the snapshot.
+    pub fn rehydrate_instance(
+        &mut self,
+        module: AwwasmModuleAddr,
+        snapshot: &AwwasmInstanceSnapshot,
+    ) -> Result<(), AwwasmRuntimeError> {
+        snapshot::restore_instance(self, module, snapshot)
+    }
+
+    /// Lower every function body `module` defines up front, instead of on
+    /// each function's first call.
+    ///
+    /// An invalid body fails with the error wrapped in `InFunction`.
+    #[cfg(feature = "alloc")]
+    pub fn resolve_all_functions(&mut self, module: AwwasmModuleAddr) -> Result<(), AwwasmRuntimeError> {
+        for addr in self.defined_funcs(module)? {
+            exec::compile(self, addr).map_err(|e| self.in_function(module, addr, e))?;
+        }
+        Ok(())
+    }
+
+    /// `resolve_all_functions`, lowering bodies across the Rayon thread
+    /// pool.
+    #[cfg(feature = "parallel")]
+    pub fn resolve_all_functions_parallel(&mut self, module: AwwasmModuleAddr) -> Result<(), AwwasmRuntimeError> {
+        use rayon::prelude::*;
+
+        let pending: Vec<(AwwasmFuncAddr, &'a [u8])> = self
+            .defined_funcs(module)?
+            .into_iter()
+            .filter_map(|addr| match self.func(addr) {
+                Ok(AwwasmFuncInst::Wasm(AwwasmWasmFuncInst { code: crate::func::LazyResolvedCodeRef::Unparsed { bytes }, .. })) => {
+                    Some((addr, *bytes))
+                }
+                _ => None,
+            })
+            .collect();
+        let inst = self.module(module).ok_or_else(|| AwwasmRuntimeError::InstructionParseError(String::from("unknown module")))?;
+        let meter = self.instrumentation();
+        let lowered = pending
+            .par_iter()
+            .map(|&(addr, bytes)| {
+                exec::lower_body(bytes, &inst.types, &inst.refs, meter.as_deref())
+                    .map(|code| (addr, code))
+                    .map_err(|e| (addr, e))
+            })
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|(addr, e)| self.in_function(module, addr, e))?;
+        for (addr, code) in lowered {
+            exec::install(self, addr, Arc::new(code))?;
+        }
+        Ok(())
+    }
+
+    /// Instantiate modules that do not import from each other
+    /// concurrently, then merge them into this Store.
+    ///
+    /// Each module is parsed and instantiated on the Rayon thread pool
+    /// into a shard, a fresh store with this one's module limits; the
+    /// shards are then appended to this Store one after another, so the
+    /// returned addresses follow the order of `modules`. If any module
+    /// fails, the first error in that order is returned and this Store
+    /// is left unchanged.
+    ///
+    /// Imports must not refer to this Store: a table or a guest function
+    /// taken from it fails with `ShardImport`. Memories, globals and host
+    /// functions carry their own state and are fine. Start functions are
+    /// not run, as with `store_init`, and the instantiation observer is
+    /// not told about shards.
+    #[cfg(feature = "parallel")]
+    pub fn instantiate_parallel(
+        &mut self,
+        modules: Vec<(&'a [u8], AwwasmImports<'a>)>,
+    ) -> Result<Vec<AwwasmModuleAddr>, AwwasmInstantiationError> {
+        use rayon::prelude::*;
+
+        for (_, imports) in &modules {
+            let foreign = imports.iter().find(|entry| {
+                matches!(entry.value, AwwasmImportValue::Table(_) | AwwasmImportValue::Func(AwwasmFuncInst::Wasm(_)))
+            });
+            if let Some(entry) = foreign {
+                return Err(AwwasmInstantiationError::ShardImport {
+                    module: String::from_utf8_lossy(entry.module).into_owned(),
+                    name: String::from_utf8_lossy(entry.name).into_owned(),
+                });
+            }
+        }
+        let module_limits = self.module_limits;
+        let instantiation_limits = self.instantiation_limits;
+        let module_gate = self.module_gate.clone();
+        let zeroize_on_drop = self.zeroize_on_drop;
+        let shards = modules
+            .into_par_iter()
+            .map(|(bytes, mut imports)| {
+                let mut shard = AwwasmStore::new();
+                shard.set_module_limits(module_limits);
+                shard.set_instantiation_limits(instantiation_limits);
+                shard.set_module_gate(module_gate.clone());
+                shard.set_zeroize_on_drop(zeroize_on_drop);
+                shard.instantiate_bytes(bytes, &mut imports).map(|_| shard)
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        Ok(shards.into_iter().map(|shard| self.merge_shard(shard)).collect())
+    }
+
+    /// Append the single module instantiated into `shard`, shifting every
+    /// address it holds past this Store's instances.
+    #[cfg(feature = "parallel")]
+    fn merge_shard(&mut self, mut shard: AwwasmStore<'a>) -> AwwasmModuleAddr {
+        let func = |addr: AwwasmFuncAddr, base: usize| AwwasmFuncAddr(addr.0 + base as u32);
+        let (funcs, tables, mems, globals) = (self.funcs.len(), self.tables.len(), self.mems.len(), self.globals.len());
+        let (elems, datas, modules) = (self.elems.len(), self.datas.len(), self.modules.len());
+        let type_ids: Vec<u32> = shard.types.iter().map(|ty| self.intern_type(ty)).collect();
+
+        // Take the instances rather than moving them out, since the
+        // shard has a destructor; it then wipes nothing.
+        for (addr, id) in core::mem::take(&mut shard.host_type_ids) {
+            self.host_type_ids.insert(addr + funcs as u32, type_ids[id as usize]);
+        }
+        for (addr, names) in core::mem::take(&mut shard.host_imports) {
+            self.host_imports.insert(addr + funcs as u32, names);
+        }
+        // `store_init` leaves bodies unlowered, so no call-site cache
+        // holds a shard address yet.
+        self.funcs.extend(core::mem::take(&mut shard.funcs).into_iter().map(|mut f| {
+            if let AwwasmFuncInst::Wasm(wasm) = &mut f {
+                wasm.module = AwwasmModuleAddr(wasm.module.0 + modules as u32);
+            }
+            f
+        }));
+        self.tables.extend(core::mem::take(&mut shard.tables).into_iter().map(|mut table| {
+            table.elem.iter_mut().flatten().for_each(|addr| *addr = func(*addr, funcs));
+            table
+        }));
+        for mut mem in core::mem::take(&mut shard.mems) {
+            self.adopt_mem(&mut mem);
+            self.mems.push(mem);
+        }
+        self.globals.extend(core::mem::take(&mut shard.globals).into_iter().map(|mut global| {
+            if let AwwasmValue::FuncRef(Some(addr)) = &mut global.value {
+                *addr = func(*addr, funcs);
+            }
+            global
+        }));
+        self.elems.extend(core::mem::take(&mut shard.elems).into_iter().map(|mut elem| {
+            elem.elem.iter_mut().flatten().for_each(|addr| *addr = func(*addr, funcs));
+            elem
+        }));
+        self.datas.append(&mut shard.datas);
+        for mut inst in core::mem::take(&mut shard.modules) {
+            inst.type_ids.iter_mut().for_each(|id| *id = type_ids[*id as usize]);
+            inst.funcaddrs.iter_mut().for_each(|addr| *addr = func(*addr, funcs));
+            inst.tableaddrs.iter_mut().for_each(|addr| addr.0 += tables as u32);
+            inst.memaddrs.iter_mut().for_each(|addr| addr.0 += mems as u32);
+            inst.globaladdrs.iter_mut().for_each(|addr| addr.0 += globals as u32);
+            inst.elemaddrs.iter_mut().for_each(|addr| addr.0 += elems as u32);
+            inst.dataaddrs.iter_mut().for_each(|addr| addr.0 += datas as u32);
+            for export in &mut inst.exports {
+                export.addr = match export.addr {
+                    AwwasmExternAddr::Func(addr) => AwwasmExternAddr::Func(func(addr, funcs)),
+                    AwwasmExternAddr::Table(addr) => AwwasmExternAddr::Table(AwwasmTableAddr(addr.0 + tables as u32)),
+                    AwwasmExternAddr::Mem(addr) => AwwasmExternAddr::Mem(AwwasmMemAddr(addr.0 + mems as u32)),
+                    AwwasmExternAddr::Global(addr) => AwwasmExternAddr::Global(AwwasmGlobalAddr(addr.0 + globals as u32)),
+                };
+            }
+            inst.start = inst.start.map(|addr| func(addr, funcs));
+            self.modules.push(inst);
+        }
+        self.code_cache_bytes = self.code_cache_bytes.saturating_add(shard.code_cache_bytes);
+        AwwasmModuleAddr(modules as u32)
+    }
+
+    /// Attach the module-local index of `addr` to an error about it.
+    fn in_function(&self, module: AwwasmModuleAddr, addr: AwwasmFuncAddr, error: AwwasmRuntimeError) -> AwwasmRuntimeError {
+        let func_idx = self
+            .module(module)
+            .and_then(|m| m.funcaddrs.iter().position(|&a| a == addr))
+            .unwrap_or(addr.0 as usize);
+        AwwasmRuntimeError::InFunction {
+            module: module.0,
+            func_idx: func_idx as u32,
+            source: Box::new(error),
+        }
+    }
+
+    /// Guest functions defined by (not imported into) `module`, in index
+    /// order.
+    fn defined_funcs(&self, module: AwwasmModuleAddr) -> Result<Vec<AwwasmFuncAddr>, AwwasmRuntimeError> {
+        let inst = self
+            .module(module)
+            .ok_or_else(|| AwwasmRuntimeError::InstructionParseError(String::from("unknown module")))?;
+        Ok(inst
+            .funcaddrs
+            .iter()
+            .copied()
+            .filter(|&addr| matches!(self.func(addr), Ok(AwwasmFuncInst::Wasm(f)) if f.module == module))
+            .collect())
+    }
+
+    /// Bound the interpreter's call, label and operand stacks of every
+    /// later `invoke`; exceeding them traps with `CallStackExhausted` or
+    /// `StackOverflow`. The native stack use of a call does not depend
+    /// on any of them.
+    ///
+    /// Fails with `StackLimitsExceedArena`, keeping the current limits,
+    /// if the store runs on an arena reserved for smaller ones.
+    pub fn set_stack_limits(&mut self, limits: AwwasmStackLimits) -> Result<(), AwwasmRuntimeError> {
+        if let Some(arena) = &self.arena {
+            if !limits.fits_within(&arena.limits()) {
+                return Err(AwwasmRuntimeError::StackLimitsExceedArena);
+            }
+        }
+        self.stack_limits = limits;
+        Ok(())
+    }
+
+    /// The interpreter stack bounds, `MAX_CALL_DEPTH`, `MAX_VALUE_STACK`
+    /// and `MAX_LABEL_DEPTH` unless changed.
+    pub fn stack_limits(&self) -> AwwasmStackLimits {
+        self.stack_limits
+    }
+
+    /// Keep guest loads and stores inside their memory with `strategy`
+    /// from now on; see `AwwasmBoundsCheck`.
+    pub fn set_bounds_check(&mut self, strategy: AwwasmBoundsCheck) {
+        self.bounds_check = strategy;
+    }
+
+    /// The bounds-check strategy, `Explicit` unless changed.
+    pub fn bounds_check(&self) -> AwwasmBoundsCheck {
+        self.bounds_check
+    }
+
+    /// Overwrite linear memories, table elements and global values with
+    /// zeros when this Store is dropped (off by default), so secrets a
+    /// guest handled do not linger in freed heap memory. Instances live
+    /// as long as their Store, so this covers them too. Memories also
+    /// wipe the old buffer when growing moves them. A shared memory is
+    /// only wiped if no other handle on it remains, and a memory backed
+    /// by an embedder's static buffer is wiped as well.
+    ///
+    /// This is a best effort, not a guarantee: the compiler is only
+    /// discouraged from removing the writes, and buffers left behind
+    /// while this was off are not reached.
+    pub fn set_zeroize_on_drop(&mut self, zeroize: bool) {
+        self.zeroize_on_drop = zeroize;
+        self.mems.iter_mut().for_each(|mem| mem.set_zeroize(zeroize));
+    }
+
+    /// Whether guest state is wiped on drop.
+    pub fn zeroize_on_drop(&self) -> bool {
+        self.zeroize_on_drop
+    }
+
+    /// Keep at most `limit` bytes of lowered function bodies (`None`: no
+    /// limit, the default).
+    ///
+    /// Bodies are lowered on their first call and normally cached; once
+    /// the cache is full, further bodies are lowered again on every call
+    /// instead. Trades speed for RAM on devices that execute modules
+    /// from flash. Bodies loaded with `load_precompiled` or
+    /// `resolve_all_functions_parallel` are always kept.
+    pub fn set_code_cache_limit(&mut self, limit: Option<usize>) {
+        self.code_cache_limit = limit;
+    }
+
+    /// Bytes of lowered function bodies currently cached.
+    pub fn code_cache_bytes(&self) -> usize {
+        self.code_cache_bytes
+    }
+
+    /// Whether `bytes` more of lowered code fit the cache limit.
+    pub(crate) fn code_cache_fits(&self, bytes: usize) -> bool {
+        self.code_cache_limit.is_none_or(|limit| self.code_cache_bytes.saturating_add(bytes) <= limit)
+    }
+
+    /// Account for `bytes` of lowered code cached on a function.
+    pub(crate) fn add_code_cache_bytes(&mut self, bytes: usize) {
+        self.code_cache_bytes = self.code_cache_bytes.saturating_add(bytes);
+    }
+
+    /// Make running guests stop with `AwwasmTrap::Interrupted` once
+    /// `handle.interrupt()` is called.
+    #[cfg(feature = "alloc")]
+    pub fn set_interrupt_handle(&mut self, handle: AwwasmInterruptHandle) {
+        self.interrupt = Some(handle);
+    }
+
+    /// The interrupt handle checked during execution, if any.
+    #[cfg(feature = "alloc")]
+    pub fn interrupt_handle(&self) -> Option<&AwwasmInterruptHandle> {
+        self.interrupt.as_ref()
+    }
+
+    /// Let other threads read this store's memories through `inspector`
+    /// while guest code runs.
+    #[cfg(feature = "std")]
+    pub fn set_inspector(&mut self, inspector: AwwasmInspector) {
+        self.inspector = Some(inspector);
+    }
+
+    /// The inspector served during execution, if any.
+    #[cfg(feature = "std")]
+    pub fn inspector(&self) -> Option<&AwwasmInspector> {
+        self.inspector.as_ref()
+    }
+
+    /// Use `platform` for time-based limits such as `set_deadline`.
+    #[cfg(feature = "alloc")]
+    pub fn set_platform(&mut self, platform: Arc<dyn AwwasmHostPlatform>) {
+        self.platform = Some(platform);
+    }
+
+    /// The platform set with `set_platform`, if any.
+    #[cfg(feature = "alloc")]
+    pub fn platform(&self) -> Option<&Arc<dyn AwwasmHostPlatform>> {
+        self.platform.as_ref()
+    }
+
+    /// Report calls and returns of later invocations to `profiler`;
+    /// `None` turns profiling off.
+    #[cfg(feature = "alloc")]
+    pub fn set_profiler(&mut self, profiler: Option<Arc<dyn AwwasmProfiler>>) {
+        self.profiler = profiler;
+    }
+
+    /// The profiler set with `set_profiler`, if any.
+    #[cfg(feature = "alloc")]
+    pub fn profiler(&self) -> Option<&Arc<dyn AwwasmProfiler>> {
+        self.profiler.as_ref()
+    }
+
+    /// Report the phases of later instantiations to `observer`; `None`
+    /// turns reporting off.
+    #[cfg(feature = "alloc")]
+    pub fn set_instantiation_observer(&mut self, observer: Option<Arc<dyn AwwasmInstantiationObserver>>) {
+        self.instantiation_observer = observer;
+    }
+
+    /// The observer set with `set_instantiation_observer`, if any.
+    #[cfg(feature = "alloc")]
+    pub fn instantiation_observer(&self) -> Option<&Arc<dyn AwwasmInstantiationObserver>> {
+        self.instantiation_observer.as_ref()
+    }
+
+    /// Meter guest code with `fuel` units from now on; `None` (the
+    /// default) turns metering off.
+    ///
+    /// Each executed instruction is charged as priced by the gas table.
+    /// Once the next instruction costs more than is left, execution traps
+    /// with `AwwasmTrap::OutOfFuel` and the remainder stays unspent. Fuel
+    /// carries over between calls; top it up with `set_fuel`.
+    pub fn set_fuel(&mut self, fuel: Option<u64>) {
+        self.fuel = fuel;
+    }
+
+    /// Fuel left, or `None` if execution is unmetered.
+    pub fn fuel(&self) -> Option<u64> {
+        self.fuel
+    }
+
+    /// Price instructions with `table` instead of charging 1 for each.
+    #[cfg(feature = "alloc")]
+    pub fn set_gas_table(&mut self, table: Arc<AwwasmGasTable>) {
+        self.gas_table = Some(table);
+    }
+
+    /// The gas table set with `set_gas_table`, if any.
+    #[cfg(feature = "alloc")]
+    pub fn gas_table(&self) -> Option<&Arc<AwwasmGasTable>> {
+        self.gas_table.as_ref()
+    }
+
+    /// Count instructions, host calls, stack depth and grown pages of
+    /// every later `invoke`, for `last_metrics`. Counting costs a little
+    /// on every instruction, so it is off by default. Only the built-in
+    /// interpreter collects metrics.
+    pub fn set_collect_metrics(&mut self, collect: bool) {
+        self.collect_metrics = collect;
+    }
+
+    /// Whether `invoke` collects metrics.
+    pub fn collect_metrics(&self) -> bool {
+        self.collect_metrics
+    }
+
+    /// What the most recent `invoke` that collected metrics did, also if
+    /// it trapped.
+    pub fn last_metrics(&self) -> Option<AwwasmExecutionMetrics> {
+        self.last_metrics
+    }
+
+    pub(crate) fn set_last_metrics(&mut self, metrics: AwwasmExecutionMetrics) {
+        self.last_metrics = Some(metrics);
+    }
+
+    /// Choose how fuel is charged for bodies lowered from now on.
+    ///
+    /// With `AwwasmMetering::Instrumented`, bodies are rewritten to
+    /// charge the gas table's price of each straight-line run of code on
+    /// entering it, which is cheaper than counting every instruction.
+    /// Set it, and the gas table, before the first call: bodies already
+    /// lowered keep the metering they were lowered with, and
+    /// `load_precompiled` only accepts artifacts metered the same way.
+    pub fn set_metering(&mut self, metering: AwwasmMetering) {
+        self.metering = metering;
+    }
+
+    /// How bodies lowered from now on are metered.
+    pub fn metering(&self) -> AwwasmMetering {
+        self.metering
+    }
+
+    /// The table to instrument newly lowered bodies with, if they are to
+    /// be instrumented.
+    #[cfg(feature = "alloc")]
+    pub(crate) fn instrumentation(&self) -> Option<Arc<AwwasmGasTable>> {
+        (self.metering == AwwasmMetering::Instrumented).then(|| self.gas_table.clone().unwrap_or_default())
+    }
+
+    /// Take `cost` units of fuel, if execution is metered.
+    #[inline]
+    pub(crate) fn charge_fuel(&mut self, cost: u64) -> Result<(), AwwasmTrap> {
+        if let Some(fuel) = &mut self.fuel {
+            *fuel = fuel.checked_sub(cost).ok_or(AwwasmTrap::OutOfFuel)?;
+        }
+        Ok(())
+    }
+
+    /// Make running guests stop with `AwwasmTrap::Interrupted` once the
+    /// platform's monotonic clock reaches `deadline` nanoseconds; `None`
+    /// removes the deadline. Checked at the same points as the interrupt
+    /// handle, and only once a platform is set.
+    #[cfg(feature = "alloc")]
+    pub fn set_deadline(&mut self, deadline: Option<u64>) {
+        self.deadline = deadline;
+    }
+
+    /// A point where guest code may pause or stop: serve pending
+    /// inspections, then report whether to stop.
+    #[cfg(feature = "alloc")]
+    pub(crate) fn safepoint(&self) -> bool {
+        #[cfg(feature = "std")]
+        if let Some(inspector) = &self.inspector {
+            inspector.serve(&self.mems);
+        }
+        self.should_interrupt()
+    }
+
+    /// Whether guest code should stop: the interrupt handle fired or the
+    /// deadline passed.
+    #[cfg(feature = "alloc")]
+    fn should_interrupt(&self) -> bool {
+        if self.interrupt.as_ref().is_some_and(|h| h.is_interrupted()) {
+            return true;
+        }
+        match (self.deadline, &self.platform) {
+            (Some(deadline), Some(platform)) => platform.monotonic_nanos() >= deadline,
+            _ => false,
+        }
+    }
+
     // ========================================================================
     // Access methods
     // ========================================================================
@@ -429,6 +1739,50 @@ impl<'a> AwwasmStore<'a> {
             .ok_or(AwwasmRuntimeError::InvalidGlobalAddr(addr.0))
     }
 
+    /// Current value of the global at `addr`.
+    pub fn get_global(&self, addr: AwwasmGlobalAddr) -> Result<AwwasmValue, AwwasmRuntimeError> {
+        Ok(self.global(addr)?.get())
+    }
+
+    /// Current value of an `i32` global.
+    pub fn get_global_i32(&self, addr: AwwasmGlobalAddr) -> Result<i32, AwwasmRuntimeError> {
+        let value = self.get_global(addr)?;
+        value.as_i32().ok_or_else(|| global_mismatch(AwwasmValueType::I32, value))
+    }
+
+    /// Current value of an `i64` global.
+    pub fn get_global_i64(&self, addr: AwwasmGlobalAddr) -> Result<i64, AwwasmRuntimeError> {
+        let value = self.get_global(addr)?;
+        value.as_i64().ok_or_else(|| global_mismatch(AwwasmValueType::I64, value))
+    }
+
+    /// Current value of an `f32` global.
+    pub fn get_global_f32(&self, addr: AwwasmGlobalAddr) -> Result<f32, AwwasmRuntimeError> {
+        let value = self.get_global(addr)?;
+        value.as_f32().ok_or_else(|| global_mismatch(AwwasmValueType::F32, value))
+    }
+
+    /// Current value of an `f64` global.
+    pub fn get_global_f64(&self, addr: AwwasmGlobalAddr) -> Result<f64, AwwasmRuntimeError> {
+        let value = self.get_global(addr)?;
+        value.as_f64().ok_or_else(|| global_mismatch(AwwasmValueType::F64, value))
+    }
+
+    /// Current value of a `v128` global.
+    pub fn get_global_v128(&self, addr: AwwasmGlobalAddr) -> Result<u128, AwwasmRuntimeError> {
+        let value = self.get_global(addr)?;
+        value.as_v128().ok_or_else(|| global_mismatch(AwwasmValueType::V128, value))
+    }
+
+    /// Set the global at `addr`.
+    ///
+    /// Fails with `ImmutableGlobal` (carrying the address) if the global
+    /// is immutable, and with `TypeMismatch` if `value` is not of the
+    /// global's declared type.
+    pub fn set_global(&mut self, addr: AwwasmGlobalAddr, value: impl Into<AwwasmValue>) -> Result<(), AwwasmRuntimeError> {
+        self.global_mut(addr)?.set(value.into()).map_err(|e| e.at(addr.0))
+    }
+
     /// Get an element instance by address.
     pub fn elem(&self, addr: AwwasmElemAddr) -> Option<&AwwasmElemInst<'a>> {
         self.elems.get(addr.0 as usize)
@@ -454,6 +1808,59 @@ impl<'a> AwwasmStore<'a> {
         self.modules.get(addr.0 as usize)
     }
 
+    /// Registry id of `ty`, adding it if it is new.
+    fn intern_type(&mut self, ty: &AwwasmFuncType) -> u32 {
+        match self.types.iter().position(|t| t == ty) {
+            Some(id) => id as u32,
+            None => {
+                self.types.push(ty.clone());
+                (self.types.len() - 1) as u32
+            }
+        }
+    }
+
+    /// Registry id of the signature of the function at `addr`.
+    pub(crate) fn func_type_id(&self, addr: AwwasmFuncAddr) -> Result<u32, AwwasmRuntimeError> {
+        let id = match self.func(addr)? {
+            AwwasmFuncInst::Wasm(f) => self.module(f.module).and_then(|m| m.type_ids.get(f.type_idx as usize)),
+            AwwasmFuncInst::Host(_) => self.host_type_ids.get(&addr.0),
+        };
+        id.copied().ok_or(AwwasmRuntimeError::UntypedFunction(addr.0))
+    }
+
+    /// Signature of the function at `addr`.
+    ///
+    /// Host functions get the signature of the import they were
+    /// instantiated for.
+    pub fn func_type(&self, addr: AwwasmFuncAddr) -> Result<&AwwasmFuncType, AwwasmRuntimeError> {
+        self.types
+            .get(self.func_type_id(addr)? as usize)
+            .ok_or(AwwasmRuntimeError::UntypedFunction(addr.0))
+    }
+
+    /// Signature of a wasm function, looked up in its owning module.
+    fn wasm_func_type(&self, func: &AwwasmWasmFuncInst<'_>) -> Option<&AwwasmFuncType> {
+        self.module(func.module)?.types.get(func.type_idx as usize)
+    }
+
+    /// Describe an import value for `ImportTypeMismatch`.
+    fn describe_import(&self, value: &AwwasmImportValue<'_>) -> String {
+        match value {
+            AwwasmImportValue::Func(AwwasmFuncInst::Wasm(f)) => match self.wasm_func_type(f) {
+                Some(ty) => format!("{}", ty),
+                None => "function".into(),
+            },
+            // Host functions are untyped until imported.
+            AwwasmImportValue::Func(AwwasmFuncInst::Host(_)) => "function".into(),
+            AwwasmImportValue::Memory(_) => "memory".into(),
+            AwwasmImportValue::Global(g) => format!("{}", g.type_),
+            AwwasmImportValue::Table(addr) => match self.tables.get(addr.0 as usize) {
+                Some(t) => format!("{}", AwwasmTableType { min: t.size(), ..t.type_ }),
+                None => "table".into(),
+            },
+        }
+    }
+
     /// Get a mutable module instance by address.
     pub fn module_mut(&mut self, addr: AwwasmModuleAddr) -> Option<&mut AwwasmModuleInst<'a>> {
         self.modules.get_mut(addr.0 as usize)
@@ -487,6 +1894,78 @@ impl<'a> AwwasmStore<'a> {
     pub fn module_count(&self) -> usize {
         self.modules.len()
     }
+
+    /// Heap bytes held by memories, tables and lowered bodies.
+    ///
+    /// Counts allocated capacity, not just what is in use. Memories and
+    /// tables grow and function bodies are lowered through fallible
+    /// allocation, so exhausting the heap surfaces as an error
+    /// (`MemoryAllocationFailed`, a failed `memory.grow`/`table.grow`,
+    /// `OutOfMemory`) rather than an abort.
+    pub fn heap_usage(&self) -> AwwasmHeapUsage {
+        let memories = self.mems.iter().map(|m| m.data.heap_bytes()).sum();
+        let tables = self
+            .tables
+            .iter()
+            .map(|t| t.elem.capacity() * core::mem::size_of::<Option<AwwasmFuncAddr>>())
+            .sum();
+        let code = self
+            .funcs
+            .iter()
+            .map(|f| match f {
+                AwwasmFuncInst::Wasm(w) => match &w.code {
+                    LazyResolvedCodeRef::Resolved { compiled, .. } => compiled.heap_bytes(),
+                    LazyResolvedCodeRef::Unparsed { .. } => 0,
+                },
+                AwwasmFuncInst::Host(_) => 0,
+            })
+            .sum();
+        AwwasmHeapUsage { memories, tables, code }
+    }
+}
+
+/// `TypeMismatch` for a global of type `expected` holding or given `got`.
+fn global_mismatch(expected: AwwasmValueType, got: AwwasmValue) -> AwwasmRuntimeError {
+    AwwasmRuntimeError::TypeMismatch {
+        expected: String::from(expected.name()),
+        got: String::from(got.value_type().name()),
+    }
+}
+
+/// Reports the phases of one `store_init` to the store's observer,
+/// timed with its platform clock. Does nothing without an observer.
+struct AwwasmInitProgress {
+    observer: Option<Arc<dyn AwwasmInstantiationObserver>>,
+    platform: Option<Arc<dyn AwwasmHostPlatform>>,
+    module: AwwasmModuleAddr,
+    /// Platform time of the previous report.
+    last: u64,
+}
+
+impl AwwasmInitProgress {
+    fn new(store: &AwwasmStore<'_>) -> Self {
+        let observer = store.instantiation_observer.clone();
+        let platform = observer.as_ref().and(store.platform.clone());
+        let last = platform.as_ref().map_or(0, |p| p.monotonic_nanos());
+        Self { observer, platform, module: AwwasmModuleAddr(store.modules.len() as u32), last }
+    }
+
+    fn elapsed(&mut self) -> Option<u64> {
+        let now = self.platform.as_ref()?.monotonic_nanos();
+        Some(now.saturating_sub(core::mem::replace(&mut self.last, now)))
+    }
+
+    fn report(&mut self, phase: AwwasmInstantiationPhase) {
+        if let Some(observer) = self.observer.clone() {
+            observer.phase(self.module, phase, self.elapsed());
+        }
+    }
+
+    fn fail(&mut self, error: &AwwasmInstantiationError) {
+        if let Some(observer) = self.observer.clone() {
+            observer.failed(self.module, error, self.elapsed());
+        }
+    }
 }
 
 impl<'a> Default for AwwasmStore<'a> {
@@ -495,3 +1974,18 @@ impl<'a> Default for AwwasmStore<'a> {
     }
 }
 
+impl Drop for AwwasmStore<'_> {
+    fn drop(&mut self) {
+        if !self.zeroize_on_drop {
+            return;
+        }
+        self.mems.iter_mut().for_each(AwwasmMemInst::wipe);
+        for table in &mut self.tables {
+            crate::memory::wipe(&mut table.elem[..], None);
+        }
+        for global in &mut self.globals {
+            crate::memory::wipe(core::slice::from_mut(&mut global.value), AwwasmValue::I32(0));
+        }
+    }
+}
+
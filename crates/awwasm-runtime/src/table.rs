@@ -5,9 +5,11 @@
 
 #[cfg(feature = "alloc")]
 use alloc::vec::Vec;
+#[cfg(feature = "alloc")]
+use alloc::collections::TryReserveError;
 
-use crate::values::AwwasmFuncAddr;
-use crate::error::AwwasmTrap;
+use crate::values::{AwwasmFuncAddr, AwwasmValue, AwwasmValueType};
+use crate::error::{AwwasmInstantiationError, AwwasmTrap, TableError};
 
 /// Table type - describes the limits and element type of a table.
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
@@ -29,6 +31,16 @@ pub enum AwwasmElemType {
     ExternRef,
 }
 
+impl AwwasmElemType {
+    /// The reference value type of the elements.
+    pub fn value_type(&self) -> AwwasmValueType {
+        match self {
+            AwwasmElemType::FuncRef => AwwasmValueType::FuncRef,
+            AwwasmElemType::ExternRef => AwwasmValueType::ExternRef,
+        }
+    }
+}
+
 impl AwwasmTableType {
     /// Create a new table type for function references.
     pub fn funcref(min: u32, max: Option<u32>) -> Self {
@@ -38,6 +50,45 @@ impl AwwasmTableType {
             elem_type: AwwasmElemType::FuncRef,
         }
     }
+
+    /// Whether a table of this type can be imported where `expected` is
+    /// declared: same element type, at least `expected.min` elements,
+    /// and a maximum within `expected.max` if that is set.
+    pub fn matches(&self, expected: &AwwasmTableType) -> bool {
+        let max_ok = match (self.max, expected.max) {
+            (_, None) => true,
+            (Some(max), Some(expected_max)) => max <= expected_max,
+            (None, Some(_)) => false,
+        };
+        self.elem_type == expected.elem_type && self.min >= expected.min && max_ok
+    }
+}
+
+/// Text-format type, e.g. `(table 1 10 funcref)`.
+impl core::fmt::Display for AwwasmTableType {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        write!(f, "(table {}", self.min)?;
+        if let Some(max) = self.max {
+            write!(f, " {}", max)?;
+        }
+        match self.elem_type {
+            AwwasmElemType::FuncRef => f.write_str(" funcref)"),
+            AwwasmElemType::ExternRef => f.write_str(" externref)"),
+        }
+    }
+}
+
+/// How `AwwasmTableInst::grow` reserves capacity.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum AwwasmTableGrowth {
+    /// Reserve exactly the new size, so capacity never exceeds what the
+    /// guest asked for.
+    #[default]
+    Exact,
+    /// At least double the capacity (up to the table's maximum), so a
+    /// guest growing one element at a time reallocates only
+    /// logarithmically often.
+    Geometric,
 }
 
 /// Table instance - runtime representation of a table.
@@ -50,20 +101,54 @@ pub struct AwwasmTableInst {
     pub type_: AwwasmTableType,
     /// The table elements (None = null reference).
     pub elem: Vec<Option<AwwasmFuncAddr>>,
+    /// How `grow` reserves capacity.
+    pub growth: AwwasmTableGrowth,
 }
 
 impl AwwasmTableInst {
     /// Create a new table instance with the given type.
     ///
     /// Initializes all elements to null.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the allocation fails. Use `try_new` for limits that come
+    /// from an untrusted module.
     pub fn new(type_: AwwasmTableType) -> Self {
-        let size = type_.min as usize;
-        Self {
-            type_,
-            elem: vec![None; size],
+        match Self::try_new(type_) {
+            Ok(table) => table,
+            Err(e) => panic!("{}", e),
         }
     }
 
+    /// Create a new table instance, reporting allocation failure instead
+    /// of aborting.
+    pub fn try_new(type_: AwwasmTableType) -> Result<Self, AwwasmInstantiationError> {
+        let size = type_.min as usize;
+        let mut elem = Vec::new();
+        elem.try_reserve_exact(size)
+            .map_err(|_| AwwasmInstantiationError::TableAllocationFailed { requested_elems: type_.min })?;
+        elem.resize(size, None);
+        Ok(Self { type_, elem, growth: AwwasmTableGrowth::default() })
+    }
+
+    /// Use `growth` for later `grow` calls.
+    pub fn with_growth(mut self, growth: AwwasmTableGrowth) -> Self {
+        self.growth = growth;
+        self
+    }
+
+    /// Number of elements the table can hold without reallocating.
+    pub fn capacity(&self) -> u32 {
+        self.elem.capacity().min(u32::MAX as usize) as u32
+    }
+
+    /// Reserve room for exactly `additional` more elements, so growing
+    /// by up to that much later does not reallocate.
+    pub fn reserve_exact(&mut self, additional: u32) -> Result<(), TryReserveError> {
+        self.elem.try_reserve_exact(additional as usize)
+    }
+
     /// Get the current size.
     #[inline]
     pub fn size(&self) -> u32 {
@@ -84,11 +169,15 @@ impl AwwasmTableInst {
     }
 
     /// Set an element at the given index.
+    ///
+    /// A function reference can only be stored in a `funcref` table;
+    /// `None` clears the slot in any table.
     #[inline]
-    pub fn set(&mut self, index: u32, value: Option<AwwasmFuncAddr>) -> Result<(), AwwasmTrap> {
+    pub fn set(&mut self, index: u32, value: Option<AwwasmFuncAddr>) -> Result<(), TableError> {
+        self.check_write(value)?;
         let idx = index as usize;
         if idx >= self.elem.len() {
-            return Err(AwwasmTrap::TableOutOfBounds {
+            return Err(TableError::OutOfBounds {
                 index,
                 table_size: self.elem.len() as u32,
             });
@@ -97,11 +186,36 @@ impl AwwasmTableInst {
         Ok(())
     }
 
+    /// Set an element from a reference value, which must be of the
+    /// table's element type.
+    pub fn set_value(&mut self, index: u32, value: AwwasmValue) -> Result<(), TableError> {
+        match value {
+            AwwasmValue::FuncRef(r) if self.type_.elem_type == AwwasmElemType::FuncRef => self.set(index, r),
+            other => Err(TableError::TypeMismatch {
+                expected: self.type_.elem_type.value_type(),
+                got: other.value_type(),
+            }),
+        }
+    }
+
+    /// Whether `value` may be written into this table.
+    fn check_write(&self, value: Option<AwwasmFuncAddr>) -> Result<(), TableError> {
+        if value.is_some() && self.type_.elem_type != AwwasmElemType::FuncRef {
+            return Err(TableError::TypeMismatch {
+                expected: self.type_.elem_type.value_type(),
+                got: AwwasmValueType::FuncRef,
+            });
+        }
+        Ok(())
+    }
+
     /// Grow the table by the given number of elements.
     ///
     /// Returns the previous size on success, or None if growth
-    /// would exceed the maximum.
+    /// would exceed the maximum, the host cannot allocate it, or `init`
+    /// cannot be stored in this table.
     pub fn grow(&mut self, delta: u32, init: Option<AwwasmFuncAddr>) -> Option<u32> {
+        self.check_write(init).ok()?;
         let old_size = self.size();
         let new_size = old_size.checked_add(delta)?;
 
@@ -113,21 +227,30 @@ impl AwwasmTableInst {
         }
 
         // Extend with the init value
+        let needed = new_size as usize - self.elem.len();
+        if self.growth == AwwasmTableGrowth::Geometric && needed > self.elem.capacity() - self.elem.len() {
+            let limit = self.type_.max.unwrap_or(u32::MAX) as usize;
+            let target = (self.elem.capacity() * 2).clamp(new_size as usize, limit.max(new_size as usize));
+            // Fall back to an exact reservation if the larger one fails.
+            let _ = self.elem.try_reserve_exact(target - self.elem.len());
+        }
+        self.elem.try_reserve_exact(needed).ok()?;
         self.elem.resize(new_size as usize, init);
         Some(old_size)
     }
 
     /// Fill a range of elements with a value.
-    pub fn fill(&mut self, offset: u32, value: Option<AwwasmFuncAddr>, count: u32) -> Result<(), AwwasmTrap> {
+    pub fn fill(&mut self, offset: u32, value: Option<AwwasmFuncAddr>, count: u32) -> Result<(), TableError> {
+        self.check_write(value)?;
         let start = offset as usize;
-        let end = start.checked_add(count as usize).ok_or(AwwasmTrap::TableOutOfBounds {
+        let end = start.checked_add(count as usize).ok_or(TableError::OutOfBounds {
             index: offset,
             table_size: self.elem.len() as u32,
         })?;
 
         if end > self.elem.len() {
-            return Err(AwwasmTrap::TableOutOfBounds {
-                index: offset + count - 1,
+            return Err(TableError::OutOfBounds {
+                index: offset.saturating_add(count).saturating_sub(1),
                 table_size: self.elem.len() as u32,
             });
         }
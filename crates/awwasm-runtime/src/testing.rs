@@ -0,0 +1,485 @@
+//! Model-based checkers for memory and table operations.
+//!
+//! `check_memory_ops` and `check_table_ops` run a sequence of operations
+//! on an implementation and on a plain `Vec` model of the reference
+//! semantics side by side, and report the first step where the two
+//! disagree: a different result, a different size, or different
+//! contents. `AwwasmMemInst` and `AwwasmTableInst` implement the backend
+//! traits; a fork or another engine implements them for its own memory
+//! and table types to validate them against the same model.
+//!
+//! With the `proptest` feature, the strategies below generate types and
+//! operation sequences biased towards the edges of the memory or table,
+//! where bounds checks go wrong:
+//!
+//! ```ignore
+//! proptest! {
+//!     #[test]
+//!     fn my_memory((ty, ops) in (testing::memory_type(), testing::memory_ops())) {
+//!         let mut mem = MyMemory::new(ty);
+//!         testing::check_memory_ops(&mut mem, ty, testing::MAX_MODEL_PAGES, &ops)
+//!             .map_err(|e| TestCaseError::fail(e.to_string()))?;
+//!     }
+//! }
+//! ```
+//!
+//! The `fuzz` feature derives `arbitrary::Arbitrary` for the operations,
+//! and `fuzz::memory_ops` checks `AwwasmMemInst` the same way.
+
+use core::fmt;
+use std::format;
+use std::string::String;
+use std::vec::Vec;
+
+use crate::memory::{AwwasmMemInst, AwwasmMemoryType};
+use crate::table::{AwwasmElemType, AwwasmTableInst, AwwasmTableType};
+use crate::values::AwwasmFuncAddr;
+
+/// Pages a memory may grow to before the checkers skip a grow; the
+/// strategies stay within it.
+pub const MAX_MODEL_PAGES: u32 = 16;
+
+/// Elements a table may grow to before the checkers skip a grow.
+pub const MAX_MODEL_TABLE: u32 = 1024;
+
+/// One memory operation. Lengths are small on purpose: the interesting
+/// cases are at the edges, not in bulk copies.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "fuzz", derive(arbitrary::Arbitrary))]
+pub enum AwwasmMemoryOp {
+    Grow(u8),
+    Read { offset: u32, len: u16 },
+    Write { offset: u32, bytes: Vec<u8> },
+    Fill { offset: u32, value: u8, len: u16 },
+    Copy { dst: u32, src: u32, len: u16 },
+    ReadI64(u32),
+    WriteI64(u32, i64),
+}
+
+/// One table operation.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "fuzz", derive(arbitrary::Arbitrary))]
+pub enum AwwasmTableOp {
+    Grow { delta: u8, init: Option<AwwasmFuncAddr> },
+    Get(u32),
+    Set { index: u32, value: Option<AwwasmFuncAddr> },
+    Fill { offset: u32, value: Option<AwwasmFuncAddr>, len: u16 },
+    Copy { dst: u32, src: u32, len: u16 },
+}
+
+/// A linear memory under test. Failed operations return `None` or
+/// `false` and must leave the memory unchanged.
+pub trait AwwasmMemoryBackend {
+    /// Current size in bytes.
+    fn size_bytes(&self) -> usize;
+
+    /// Grow by `delta` pages, returning the previous size in pages.
+    fn grow(&mut self, delta: u32) -> Option<u32>;
+
+    /// The `len` bytes at `offset`.
+    fn read(&self, offset: u32, len: u32) -> Option<Vec<u8>>;
+
+    fn write(&mut self, offset: u32, bytes: &[u8]) -> bool;
+
+    fn fill(&mut self, offset: u32, value: u8, len: u32) -> bool;
+
+    /// Copy `len` bytes from `src` to `dst`; the ranges may overlap.
+    fn copy_within(&mut self, dst: u32, src: u32, len: u32) -> bool;
+
+    fn read_i64(&self, offset: u32) -> Option<i64> {
+        let bytes = self.read(offset, 8)?;
+        Some(i64::from_le_bytes(bytes.try_into().ok()?))
+    }
+
+    fn write_i64(&mut self, offset: u32, value: i64) -> bool {
+        self.write(offset, &value.to_le_bytes())
+    }
+}
+
+impl AwwasmMemoryBackend for AwwasmMemInst {
+    fn size_bytes(&self) -> usize {
+        AwwasmMemInst::size_bytes(self)
+    }
+
+    fn grow(&mut self, delta: u32) -> Option<u32> {
+        AwwasmMemInst::grow(self, delta)
+    }
+
+    fn read(&self, offset: u32, len: u32) -> Option<Vec<u8>> {
+        AwwasmMemInst::read(self, offset, len).ok().map(|bytes| bytes.into_owned())
+    }
+
+    fn write(&mut self, offset: u32, bytes: &[u8]) -> bool {
+        AwwasmMemInst::write(self, offset, bytes).is_ok()
+    }
+
+    fn fill(&mut self, offset: u32, value: u8, len: u32) -> bool {
+        AwwasmMemInst::fill(self, offset, value, len).is_ok()
+    }
+
+    fn copy_within(&mut self, dst: u32, src: u32, len: u32) -> bool {
+        AwwasmMemInst::copy_within(self, dst, src, len).is_ok()
+    }
+
+    fn read_i64(&self, offset: u32) -> Option<i64> {
+        AwwasmMemInst::read_i64(self, offset).ok()
+    }
+
+    fn write_i64(&mut self, offset: u32, value: i64) -> bool {
+        AwwasmMemInst::write_i64(self, offset, value).is_ok()
+    }
+}
+
+/// A table under test. Failed operations return `None` or `false` and
+/// must leave the table unchanged.
+pub trait AwwasmTableBackend {
+    /// Current size in elements.
+    fn size(&self) -> u32;
+
+    /// Grow by `delta` elements set to `init`, returning the previous size.
+    fn grow(&mut self, delta: u32, init: Option<AwwasmFuncAddr>) -> Option<u32>;
+
+    fn get(&self, index: u32) -> Option<Option<AwwasmFuncAddr>>;
+
+    fn set(&mut self, index: u32, value: Option<AwwasmFuncAddr>) -> bool;
+
+    fn fill(&mut self, offset: u32, value: Option<AwwasmFuncAddr>, len: u32) -> bool;
+
+    /// Copy `len` elements from `src` to `dst`; the ranges may overlap.
+    fn copy_within(&mut self, dst: u32, src: u32, len: u32) -> bool;
+}
+
+impl AwwasmTableBackend for AwwasmTableInst {
+    fn size(&self) -> u32 {
+        AwwasmTableInst::size(self)
+    }
+
+    fn grow(&mut self, delta: u32, init: Option<AwwasmFuncAddr>) -> Option<u32> {
+        AwwasmTableInst::grow(self, delta, init)
+    }
+
+    fn get(&self, index: u32) -> Option<Option<AwwasmFuncAddr>> {
+        AwwasmTableInst::get(self, index).ok()
+    }
+
+    fn set(&mut self, index: u32, value: Option<AwwasmFuncAddr>) -> bool {
+        AwwasmTableInst::set(self, index, value).is_ok()
+    }
+
+    fn fill(&mut self, offset: u32, value: Option<AwwasmFuncAddr>, len: u32) -> bool {
+        AwwasmTableInst::fill(self, offset, value, len).is_ok()
+    }
+
+    fn copy_within(&mut self, dst: u32, src: u32, len: u32) -> bool {
+        AwwasmTableInst::copy_within(self, dst, src, len).is_ok()
+    }
+}
+
+/// Where an implementation first disagreed with the model.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AwwasmModelMismatch {
+    /// Index of the operation; the number of operations for a difference
+    /// in the final contents.
+    pub step: usize,
+    pub message: String,
+}
+
+impl fmt::Display for AwwasmModelMismatch {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "step {}: {}", self.step, self.message)
+    }
+}
+
+impl std::error::Error for AwwasmModelMismatch {}
+
+/// The range `[offset, offset + len)` if it lies within `size` items.
+fn range(size: usize, offset: u32, len: usize) -> Option<core::ops::Range<usize>> {
+    let start = offset as usize;
+    start.checked_add(len).filter(|&end| end <= size).map(|end| start..end)
+}
+
+/// Compare an implementation's answer with the model's.
+fn expect<T: PartialEq + fmt::Debug>(op: &dyn fmt::Debug, got: T, expected: T) -> Result<(), String> {
+    if got == expected {
+        Ok(())
+    } else {
+        Err(format!("{:?}: got {:?}, expected {:?}", op, got, expected))
+    }
+}
+
+/// The reference semantics of a linear memory: its bytes.
+#[derive(Debug, Clone)]
+pub struct AwwasmMemoryModel {
+    ty: AwwasmMemoryType,
+    /// Grows past this many pages are skipped rather than checked.
+    max_pages: u32,
+    bytes: Vec<u8>,
+}
+
+impl AwwasmMemoryModel {
+    /// A fresh zeroed memory of type `ty`.
+    pub fn new(ty: AwwasmMemoryType, max_pages: u32) -> Self {
+        Self { ty, max_pages, bytes: vec![0; ty.min as usize * ty.page_size()] }
+    }
+
+    /// The memory's contents.
+    pub fn bytes(&self) -> &[u8] {
+        &self.bytes
+    }
+
+    /// Apply `op` to both `mem` and the model and compare the outcome.
+    pub fn apply<M: AwwasmMemoryBackend + ?Sized>(&mut self, mem: &mut M, op: &AwwasmMemoryOp) -> Result<(), String> {
+        let size = self.bytes.len();
+        match *op {
+            AwwasmMemoryOp::Grow(delta) => {
+                let pages = (size / self.ty.page_size()) as u32;
+                let new_pages = pages + u32::from(delta);
+                // Whether a huge grow fails is up to the host's allocator.
+                if new_pages > self.max_pages {
+                    return Ok(());
+                }
+                let fits = new_pages <= self.ty.max.unwrap_or(self.ty.max_pages()).min(self.ty.max_pages());
+                expect(op, mem.grow(u32::from(delta)), fits.then_some(pages))?;
+                if fits {
+                    self.bytes.resize(new_pages as usize * self.ty.page_size(), 0);
+                }
+            }
+            AwwasmMemoryOp::Read { offset, len } => {
+                let expected = range(size, offset, usize::from(len)).map(|r| self.bytes[r].to_vec());
+                expect(op, mem.read(offset, u32::from(len)), expected)?;
+            }
+            AwwasmMemoryOp::Write { offset, ref bytes } => {
+                let to = range(size, offset, bytes.len());
+                expect(op, mem.write(offset, bytes), to.is_some())?;
+                if let Some(to) = to {
+                    self.bytes[to].copy_from_slice(bytes);
+                }
+            }
+            AwwasmMemoryOp::Fill { offset, value, len } => {
+                let to = range(size, offset, usize::from(len));
+                expect(op, mem.fill(offset, value, u32::from(len)), to.is_some())?;
+                if let Some(to) = to {
+                    self.bytes[to].fill(value);
+                }
+            }
+            AwwasmMemoryOp::Copy { dst, src, len } => {
+                let to = range(size, dst, usize::from(len));
+                let from = range(size, src, usize::from(len));
+                expect(op, mem.copy_within(dst, src, u32::from(len)), to.is_some() && from.is_some())?;
+                if let (Some(to), Some(from)) = (to, from) {
+                    self.bytes.copy_within(from, to.start);
+                }
+            }
+            AwwasmMemoryOp::ReadI64(offset) => {
+                let expected = range(size, offset, 8).map(|r| {
+                    let mut le = [0; 8];
+                    le.copy_from_slice(&self.bytes[r]);
+                    i64::from_le_bytes(le)
+                });
+                expect(op, mem.read_i64(offset), expected)?;
+            }
+            AwwasmMemoryOp::WriteI64(offset, value) => {
+                let to = range(size, offset, 8);
+                expect(op, mem.write_i64(offset, value), to.is_some())?;
+                if let Some(to) = to {
+                    self.bytes[to].copy_from_slice(&value.to_le_bytes());
+                }
+            }
+        }
+        expect(op, mem.size_bytes(), self.bytes.len()).map_err(|e| format!("size after {}", e))
+    }
+}
+
+/// The reference semantics of a table: its elements.
+#[derive(Debug, Clone)]
+pub struct AwwasmTableModel {
+    ty: AwwasmTableType,
+    /// Grows past this many elements are skipped rather than checked.
+    max_elems: u32,
+    elems: Vec<Option<AwwasmFuncAddr>>,
+}
+
+impl AwwasmTableModel {
+    /// A fresh table of type `ty` with every element null.
+    pub fn new(ty: AwwasmTableType, max_elems: u32) -> Self {
+        Self { ty, max_elems, elems: vec![None; ty.min as usize] }
+    }
+
+    /// The table's elements.
+    pub fn elems(&self) -> &[Option<AwwasmFuncAddr>] {
+        &self.elems
+    }
+
+    /// Only a `funcref` table holds function references.
+    fn accepts(&self, value: Option<AwwasmFuncAddr>) -> bool {
+        value.is_none() || self.ty.elem_type == AwwasmElemType::FuncRef
+    }
+
+    /// Apply `op` to both `table` and the model and compare the outcome.
+    pub fn apply<T: AwwasmTableBackend + ?Sized>(&mut self, table: &mut T, op: &AwwasmTableOp) -> Result<(), String> {
+        let size = self.elems.len();
+        match *op {
+            AwwasmTableOp::Grow { delta, init } => {
+                let new_size = size as u32 + u32::from(delta);
+                if new_size > self.max_elems {
+                    return Ok(());
+                }
+                let fits = self.accepts(init) && self.ty.max.map_or(true, |max| new_size <= max);
+                expect(op, table.grow(u32::from(delta), init), fits.then_some(size as u32))?;
+                if fits {
+                    self.elems.resize(new_size as usize, init);
+                }
+            }
+            AwwasmTableOp::Get(index) => {
+                expect(op, table.get(index), self.elems.get(index as usize).copied())?;
+            }
+            AwwasmTableOp::Set { index, value } => {
+                let ok = self.accepts(value) && (index as usize) < size;
+                expect(op, table.set(index, value), ok)?;
+                if ok {
+                    self.elems[index as usize] = value;
+                }
+            }
+            AwwasmTableOp::Fill { offset, value, len } => {
+                let to = range(size, offset, usize::from(len)).filter(|_| self.accepts(value));
+                expect(op, table.fill(offset, value, u32::from(len)), to.is_some())?;
+                if let Some(to) = to {
+                    self.elems[to].fill(value);
+                }
+            }
+            AwwasmTableOp::Copy { dst, src, len } => {
+                let to = range(size, dst, usize::from(len));
+                let from = range(size, src, usize::from(len));
+                expect(op, table.copy_within(dst, src, u32::from(len)), to.is_some() && from.is_some())?;
+                if let (Some(to), Some(from)) = (to, from) {
+                    self.elems.copy_within(from, to.start);
+                }
+            }
+        }
+        expect(op, table.size(), self.elems.len() as u32).map_err(|e| format!("size after {}", e))
+    }
+}
+
+/// Run `ops` on `mem`, a fresh memory of type `ty`, checking every step
+/// against `AwwasmMemoryModel` and the final contents at the end.
+pub fn check_memory_ops<M: AwwasmMemoryBackend + ?Sized>(
+    mem: &mut M,
+    ty: AwwasmMemoryType,
+    max_pages: u32,
+    ops: &[AwwasmMemoryOp],
+) -> Result<(), AwwasmModelMismatch> {
+    let mut model = AwwasmMemoryModel::new(ty, max_pages);
+    for (step, op) in ops.iter().enumerate() {
+        model.apply(mem, op).map_err(|message| AwwasmModelMismatch { step, message })?;
+    }
+    let contents = mem.read(0, model.bytes.len() as u32);
+    if contents.as_deref() != Some(model.bytes()) {
+        let message = String::from("final contents differ from the model");
+        return Err(AwwasmModelMismatch { step: ops.len(), message });
+    }
+    Ok(())
+}
+
+/// Run `ops` on `table`, a fresh table of type `ty`, checking every step
+/// against `AwwasmTableModel` and the final elements at the end.
+pub fn check_table_ops<T: AwwasmTableBackend + ?Sized>(
+    table: &mut T,
+    ty: AwwasmTableType,
+    max_elems: u32,
+    ops: &[AwwasmTableOp],
+) -> Result<(), AwwasmModelMismatch> {
+    let mut model = AwwasmTableModel::new(ty, max_elems);
+    for (step, op) in ops.iter().enumerate() {
+        model.apply(table, op).map_err(|message| AwwasmModelMismatch { step, message })?;
+    }
+    let elems: Vec<_> = (0..model.elems.len() as u32).map(|i| table.get(i)).collect();
+    if elems.iter().zip(model.elems()).any(|(got, &expected)| *got != Some(expected)) {
+        let message = String::from("final elements differ from the model");
+        return Err(AwwasmModelMismatch { step: ops.len(), message });
+    }
+    Ok(())
+}
+
+#[cfg(feature = "proptest")]
+pub use self::strategies::*;
+
+/// `proptest` strategies for the types and operations above.
+#[cfg(feature = "proptest")]
+mod strategies {
+    use proptest::collection::vec;
+    use proptest::option;
+    use proptest::prelude::*;
+    use std::vec::Vec;
+
+    use super::{AwwasmMemoryOp, AwwasmTableOp, MAX_MODEL_PAGES};
+    use crate::memory::{AwwasmMemoryType, DEFAULT_PAGE_SIZE_LOG2, PAGE_SIZE};
+    use crate::table::{AwwasmElemType, AwwasmTableType};
+    use crate::values::AwwasmFuncAddr;
+
+    /// Memories of up to a quarter of `MAX_MODEL_PAGES`, with or without
+    /// a maximum, mostly with 64 KiB pages.
+    pub fn memory_type() -> impl Strategy<Value = AwwasmMemoryType> {
+        let log2 = prop_oneof![3 => Just(DEFAULT_PAGE_SIZE_LOG2), 1 => Just(0)];
+        (0..=MAX_MODEL_PAGES / 4, option::of(0..=MAX_MODEL_PAGES / 2), log2)
+            .prop_map(|(min, extra, page_size_log2)| AwwasmMemoryType { min, max: extra.map(|e| min + e), page_size_log2, shared: false })
+    }
+
+    /// Offsets near the start or a page boundary, and the odd huge one
+    /// for overflowing `offset + len`.
+    fn offset() -> impl Strategy<Value = u32> {
+        prop_oneof![
+            2 => 0u32..1024,
+            2 => (0..=MAX_MODEL_PAGES, -64i32..64).prop_map(|(pages, d)| (pages * PAGE_SIZE as u32).wrapping_add_signed(d)),
+            1 => any::<u32>(),
+        ]
+    }
+
+    fn len() -> impl Strategy<Value = u16> {
+        prop_oneof![4 => 0u16..64, 1 => any::<u16>()]
+    }
+
+    pub fn memory_op() -> impl Strategy<Value = AwwasmMemoryOp> {
+        prop_oneof![
+            1 => (0u8..4).prop_map(AwwasmMemoryOp::Grow),
+            2 => (offset(), len()).prop_map(|(offset, len)| AwwasmMemoryOp::Read { offset, len }),
+            2 => (offset(), vec(any::<u8>(), 0..64)).prop_map(|(offset, bytes)| AwwasmMemoryOp::Write { offset, bytes }),
+            2 => (offset(), any::<u8>(), len()).prop_map(|(offset, value, len)| AwwasmMemoryOp::Fill { offset, value, len }),
+            2 => (offset(), offset(), len()).prop_map(|(dst, src, len)| AwwasmMemoryOp::Copy { dst, src, len }),
+            1 => offset().prop_map(AwwasmMemoryOp::ReadI64),
+            1 => (offset(), any::<i64>()).prop_map(|(offset, value)| AwwasmMemoryOp::WriteI64(offset, value)),
+        ]
+    }
+
+    pub fn memory_ops() -> impl Strategy<Value = Vec<AwwasmMemoryOp>> {
+        vec(memory_op(), 0..64)
+    }
+
+    /// Tables of up to 16 elements, with or without a maximum.
+    pub fn table_type() -> impl Strategy<Value = AwwasmTableType> {
+        let elem_type = prop_oneof![3 => Just(AwwasmElemType::FuncRef), 1 => Just(AwwasmElemType::ExternRef)];
+        (0u32..=16, option::of(0u32..=32), elem_type)
+            .prop_map(|(min, extra, elem_type)| AwwasmTableType { min, max: extra.map(|e| min + e), elem_type })
+    }
+
+    fn index() -> impl Strategy<Value = u32> {
+        prop_oneof![4 => 0u32..64, 1 => any::<u32>()]
+    }
+
+    fn elem() -> impl Strategy<Value = Option<AwwasmFuncAddr>> {
+        option::of((0u32..8).prop_map(AwwasmFuncAddr))
+    }
+
+    pub fn table_op() -> impl Strategy<Value = AwwasmTableOp> {
+        prop_oneof![
+            1 => (0u8..8, elem()).prop_map(|(delta, init)| AwwasmTableOp::Grow { delta, init }),
+            2 => index().prop_map(AwwasmTableOp::Get),
+            2 => (index(), elem()).prop_map(|(index, value)| AwwasmTableOp::Set { index, value }),
+            2 => (index(), elem(), len()).prop_map(|(offset, value, len)| AwwasmTableOp::Fill { offset, value, len }),
+            2 => (index(), index(), len()).prop_map(|(dst, src, len)| AwwasmTableOp::Copy { dst, src, len }),
+        ]
+    }
+
+    pub fn table_ops() -> impl Strategy<Value = Vec<AwwasmTableOp>> {
+        vec(table_op(), 0..64)
+    }
+}
@@ -1,9 +1,14 @@
 //! Conversion utilities: parser types → runtime types.
 
+#[cfg(feature = "alloc")]
+use alloc::string::{String, ToString};
+#[cfg(feature = "alloc")]
+use alloc::vec::Vec;
+
 use awwasm_parser::components::types::AwwasmMemoryParams;
 use awwasm_parser::components::instructions::eval_const_init_expr;
 
-use crate::error::AwwasmInstantiationError;
+use crate::error::{AwwasmInstantiationError, AwwasmParseError};
 use crate::values::AwwasmValueType;
 use crate::memory::AwwasmMemoryType;
 
@@ -26,16 +31,27 @@ pub fn memory_params_to_type(params: &AwwasmMemoryParams) -> AwwasmMemoryType {
 /// Evaluate a constant initializer expression using the parser.
 ///
 /// Delegates to `awwasm_parser::eval_const_init_expr` and converts
-/// the result/error to runtime types.
+/// the result/error to runtime types, keeping the parser's error chain
+/// as the `source()`.
 pub fn eval_const_expr(code: &[u8]) -> Result<u32, AwwasmInstantiationError> {
     let value = eval_const_init_expr(code).map_err(|e| {
         AwwasmInstantiationError::InvalidConstExpr {
             description: e.to_string(),
+            source: AwwasmParseError::from_chain(e.chain().map(|cause| cause.to_string())),
         }
     })?;
     Ok(value as u32)
 }
 
+/// Capture a parser error and its causes as an `AwwasmParseError`.
+pub fn parse_error(e: impl AsRef<dyn core::error::Error + Send + Sync>) -> AwwasmParseError {
+    let root: &dyn core::error::Error = e.as_ref();
+    let chain: Vec<String> = core::iter::successors(Some(root), |cause| cause.source())
+        .map(|cause| cause.to_string())
+        .collect();
+    AwwasmParseError::from_chain(chain).unwrap_or_else(|| AwwasmParseError::new(""))
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
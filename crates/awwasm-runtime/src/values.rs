@@ -6,8 +6,11 @@
 /// Runtime values that can appear on the stack or in globals.
 ///
 /// Per the WebAssembly spec, values are either numbers or references.
-/// Currently we support the four basic number types.
+/// Currently we support the four basic number types, `v128`, and the
+/// `funcref` / `externref` reference types.
 #[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "fuzz", derive(arbitrary::Arbitrary))]
 pub enum AwwasmValue {
     /// 32-bit integer
     I32(i32),
@@ -17,8 +20,12 @@ pub enum AwwasmValue {
     F32(f32),
     /// 64-bit IEEE 754 floating point
     F64(f64),
-    // Future: V128 for SIMD
-    // Future: FuncRef, ExternRef for reference types
+    /// 128-bit SIMD vector, lanes in little-endian order
+    V128(u128),
+    /// Function reference; `None` is `ref.null func`
+    FuncRef(Option<AwwasmFuncAddr>),
+    /// Opaque host reference; `None` is `ref.null extern`
+    ExternRef(Option<u32>),
 }
 
 impl AwwasmValue {
@@ -29,6 +36,9 @@ impl AwwasmValue {
             AwwasmValueType::I64 => AwwasmValue::I64(0),
             AwwasmValueType::F32 => AwwasmValue::F32(0.0),
             AwwasmValueType::F64 => AwwasmValue::F64(0.0),
+            AwwasmValueType::V128 => AwwasmValue::V128(0),
+            AwwasmValueType::FuncRef => AwwasmValue::FuncRef(None),
+            AwwasmValueType::ExternRef => AwwasmValue::ExternRef(None),
         }
     }
 
@@ -39,6 +49,9 @@ impl AwwasmValue {
             AwwasmValue::I64(_) => AwwasmValueType::I64,
             AwwasmValue::F32(_) => AwwasmValueType::F32,
             AwwasmValue::F64(_) => AwwasmValueType::F64,
+            AwwasmValue::V128(_) => AwwasmValueType::V128,
+            AwwasmValue::FuncRef(_) => AwwasmValueType::FuncRef,
+            AwwasmValue::ExternRef(_) => AwwasmValueType::ExternRef,
         }
     }
 
@@ -73,15 +86,189 @@ impl AwwasmValue {
             _ => None,
         }
     }
+
+    /// Try to get a v128 value.
+    pub fn as_v128(&self) -> Option<u128> {
+        match self {
+            AwwasmValue::V128(v) => Some(*v),
+            _ => None,
+        }
+    }
+
+    /// Whether this is a null reference; `None` for non-reference values.
+    pub fn is_null(&self) -> Option<bool> {
+        match self {
+            AwwasmValue::FuncRef(r) => Some(r.is_none()),
+            AwwasmValue::ExternRef(r) => Some(r.is_none()),
+            _ => None,
+        }
+    }
+
+    /// Parse `text` as a value of `value_type`, the way command lines and
+    /// REPLs spell values: integers in decimal or `0x` hex (`i32`/`i64`
+    /// also accept their unsigned range), floats as Rust parses them
+    /// (including `inf` and `nan`), `v128` as one 128-bit integer, and
+    /// references as `null` or `ref.null`, plus `ref.extern N` for
+    /// `externref`. The inverse of `Display`.
+    pub fn parse(value_type: AwwasmValueType, text: &str) -> Option<Self> {
+        let text = text.trim();
+        let (negative, digits) = match text.strip_prefix('-') {
+            Some(rest) => (true, rest),
+            None => (false, text.strip_prefix('+').unwrap_or(text)),
+        };
+        let magnitude = || match digits.strip_prefix("0x").or_else(|| digits.strip_prefix("0X")) {
+            Some(hex) => u128::from_str_radix(hex, 16).ok(),
+            None => digits.parse::<u128>().ok(),
+        };
+        // Two's complement within `bits`, accepting -2^(bits-1)..2^bits.
+        let int = |bits: u32| -> Option<u128> {
+            let m = magnitude()?;
+            let mask = u128::MAX >> (128 - bits);
+            match negative {
+                false if m <= mask => Some(m),
+                true if m <= 1u128 << (bits - 1) => Some(m.wrapping_neg() & mask),
+                _ => None,
+            }
+        };
+        let null = matches!(text, "null" | "ref.null" | "ref.null func" | "ref.null extern");
+        Some(match value_type {
+            AwwasmValueType::I32 => AwwasmValue::I32(int(32)? as u32 as i32),
+            AwwasmValueType::I64 => AwwasmValue::I64(int(64)? as u64 as i64),
+            AwwasmValueType::F32 => AwwasmValue::F32(text.parse().ok()?),
+            AwwasmValueType::F64 => AwwasmValue::F64(text.parse().ok()?),
+            AwwasmValueType::V128 => AwwasmValue::V128(int(128)?),
+            AwwasmValueType::FuncRef if null => AwwasmValue::FuncRef(None),
+            AwwasmValueType::ExternRef if null => AwwasmValue::ExternRef(None),
+            AwwasmValueType::ExternRef => {
+                AwwasmValue::ExternRef(Some(text.strip_prefix("ref.extern ")?.trim().parse().ok()?))
+            }
+            AwwasmValueType::FuncRef => return None,
+        })
+    }
+
+    /// Get an i32 value, panicking on any other type.
+    #[track_caller]
+    pub fn expect_i32(&self) -> i32 {
+        match self {
+            AwwasmValue::I32(v) => *v,
+            other => panic!("expected i32, got {:?}", other),
+        }
+    }
+
+    /// Get an i64 value, panicking on any other type.
+    #[track_caller]
+    pub fn expect_i64(&self) -> i64 {
+        match self {
+            AwwasmValue::I64(v) => *v,
+            other => panic!("expected i64, got {:?}", other),
+        }
+    }
+
+    /// Get an f32 value, panicking on any other type.
+    #[track_caller]
+    pub fn expect_f32(&self) -> f32 {
+        match self {
+            AwwasmValue::F32(v) => *v,
+            other => panic!("expected f32, got {:?}", other),
+        }
+    }
+
+    /// Get an f64 value, panicking on any other type.
+    #[track_caller]
+    pub fn expect_f64(&self) -> f64 {
+        match self {
+            AwwasmValue::F64(v) => *v,
+            other => panic!("expected f64, got {:?}", other),
+        }
+    }
+
+    /// Get a v128 value, panicking on any other type.
+    #[track_caller]
+    pub fn expect_v128(&self) -> u128 {
+        match self {
+            AwwasmValue::V128(v) => *v,
+            other => panic!("expected v128, got {:?}", other),
+        }
+    }
 }
 
+/// `From<T>` and `PartialEq<T>` between `AwwasmValue` and the primitive
+/// behind each variant. Comparing against a primitive of another type is
+/// simply unequal.
+macro_rules! primitive_value {
+    ($($ty:ty => $variant:ident),* $(,)?) => {$(
+        impl From<$ty> for AwwasmValue {
+            fn from(v: $ty) -> Self {
+                AwwasmValue::$variant(v)
+            }
+        }
+
+        impl PartialEq<$ty> for AwwasmValue {
+            fn eq(&self, other: &$ty) -> bool {
+                matches!(self, AwwasmValue::$variant(v) if v == other)
+            }
+        }
+    )*};
+}
+
+primitive_value!(i32 => I32, i64 => I64, f32 => F32, f64 => F64, u128 => V128);
+
 /// Value types in WebAssembly.
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "fuzz", derive(arbitrary::Arbitrary))]
 pub enum AwwasmValueType {
     I32,
     I64,
     F32,
     F64,
+    V128,
+    FuncRef,
+    ExternRef,
+}
+
+impl AwwasmValueType {
+    /// The type's name in the text format.
+    pub fn name(&self) -> &'static str {
+        match self {
+            AwwasmValueType::I32 => "i32",
+            AwwasmValueType::I64 => "i64",
+            AwwasmValueType::F32 => "f32",
+            AwwasmValueType::F64 => "f64",
+            AwwasmValueType::V128 => "v128",
+            AwwasmValueType::FuncRef => "funcref",
+            AwwasmValueType::ExternRef => "externref",
+        }
+    }
+
+    /// Whether this is a reference type.
+    pub fn is_ref(&self) -> bool {
+        matches!(self, AwwasmValueType::FuncRef | AwwasmValueType::ExternRef)
+    }
+}
+
+/// Numbers as `parse` reads them back; `v128` in hex; references in
+/// text-format style (`ref.null func`, `ref.func 3` with the store
+/// address, `ref.extern 7`).
+impl core::fmt::Display for AwwasmValue {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            AwwasmValue::I32(v) => write!(f, "{}", v),
+            AwwasmValue::I64(v) => write!(f, "{}", v),
+            AwwasmValue::F32(v) => write!(f, "{}", v),
+            AwwasmValue::F64(v) => write!(f, "{}", v),
+            AwwasmValue::V128(v) => write!(f, "{:#034x}", v),
+            AwwasmValue::FuncRef(None) => f.write_str("ref.null func"),
+            AwwasmValue::FuncRef(Some(addr)) => write!(f, "ref.func {}", addr.0),
+            AwwasmValue::ExternRef(None) => f.write_str("ref.null extern"),
+            AwwasmValue::ExternRef(Some(v)) => write!(f, "ref.extern {}", v),
+        }
+    }
+}
+
+impl core::fmt::Display for AwwasmValueType {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.write_str(self.name())
+    }
 }
 
 // ============================================================================
@@ -91,6 +278,8 @@ pub enum AwwasmValueType {
 
 /// Address of a function instance in the Store.
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "fuzz", derive(arbitrary::Arbitrary))]
 pub struct AwwasmFuncAddr(pub u32);
 
 /// Address of a table instance in the Store.
@@ -133,6 +322,39 @@ pub enum AwwasmExternAddr {
     Global(AwwasmGlobalAddr),
 }
 
+impl AwwasmExternAddr {
+    /// What kind of entity this address refers to.
+    pub fn kind(&self) -> AwwasmExternKind {
+        match self {
+            AwwasmExternAddr::Func(_) => AwwasmExternKind::Func,
+            AwwasmExternAddr::Table(_) => AwwasmExternKind::Table,
+            AwwasmExternAddr::Mem(_) => AwwasmExternKind::Mem,
+            AwwasmExternAddr::Global(_) => AwwasmExternKind::Global,
+        }
+    }
+}
+
+/// The kind of an importable/exportable entity.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum AwwasmExternKind {
+    Func,
+    Table,
+    Mem,
+    Global,
+}
+
+impl core::fmt::Display for AwwasmExternKind {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.write_str(match self {
+            AwwasmExternKind::Func => "function",
+            AwwasmExternKind::Table => "table",
+            AwwasmExternKind::Mem => "memory",
+            AwwasmExternKind::Global => "global",
+        })
+    }
+}
+
 impl From<AwwasmFuncAddr> for AwwasmExternAddr {
     fn from(addr: AwwasmFuncAddr) -> Self {
         AwwasmExternAddr::Func(addr)
@@ -0,0 +1,234 @@
+//! Builder for `AwwasmWasiCtx`.
+
+use std::fmt;
+use std::path::PathBuf;
+
+use crate::interrupt::AwwasmInterruptHandle;
+
+use super::{AwwasmWasiCtx, OsEntropy, WasiClock, WasiEntropy, WasiInput, WasiOutput};
+
+/// Default cap on the encoded size of argv, including NUL terminators.
+pub const DEFAULT_MAX_ARGS_SIZE: usize = 1024 * 1024;
+
+/// Default cap on the encoded size of the environment (`KEY=VALUE\0`).
+pub const DEFAULT_MAX_ENV_SIZE: usize = 1024 * 1024;
+
+/// Errors reported by `WasiCtxBuilder::build`.
+#[derive(Debug)]
+pub enum WasiCtxError {
+    /// Encoded argv exceeds the configured limit
+    ArgsTooLarge {
+        size: usize,
+        limit: usize,
+    },
+    /// Encoded environment exceeds the configured limit
+    EnvTooLarge {
+        size: usize,
+        limit: usize,
+    },
+    /// An argument or environment entry contains a NUL byte
+    InteriorNul(String),
+    /// An environment key is empty or contains `=`
+    InvalidEnvKey(String),
+    /// A preopened directory could not be opened
+    Preopen {
+        path: PathBuf,
+        error: std::io::Error,
+    },
+}
+
+impl fmt::Display for WasiCtxError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            WasiCtxError::ArgsTooLarge { size, limit } => {
+                write!(f, "arguments too large: {} bytes, limit {}", size, limit)
+            }
+            WasiCtxError::EnvTooLarge { size, limit } => {
+                write!(f, "environment too large: {} bytes, limit {}", size, limit)
+            }
+            WasiCtxError::InteriorNul(s) => write!(f, "string contains a NUL byte: {:?}", s),
+            WasiCtxError::InvalidEnvKey(key) => write!(f, "invalid environment key: {:?}", key),
+            WasiCtxError::Preopen { path, error } => {
+                write!(f, "cannot preopen {}: {}", path.display(), error)
+            }
+        }
+    }
+}
+
+impl std::error::Error for WasiCtxError {}
+
+/// Fluent builder for a WASI context.
+///
+/// Collects argv, environment, preopens and stdio routing, then checks
+/// them against the size limits in `build()`.
+#[derive(Debug)]
+pub struct WasiCtxBuilder {
+    args: Vec<String>,
+    env: Vec<(String, String)>,
+    preopens: Vec<(PathBuf, String)>,
+    stdin: WasiInput,
+    stdout: WasiOutput,
+    stderr: WasiOutput,
+    clock: WasiClock,
+    random: Box<dyn WasiEntropy>,
+    max_args_size: usize,
+    max_env_size: usize,
+    interrupt: Option<AwwasmInterruptHandle>,
+}
+
+impl WasiCtxBuilder {
+    /// Create a builder with no arguments, an empty environment and
+    /// inherited stdio.
+    pub fn new() -> Self {
+        Self {
+            args: Vec::new(),
+            env: Vec::new(),
+            preopens: Vec::new(),
+            stdin: WasiInput::Inherit,
+            stdout: WasiOutput::Inherit,
+            stderr: WasiOutput::Inherit,
+            clock: WasiClock::host(),
+            random: Box::new(OsEntropy),
+            max_args_size: DEFAULT_MAX_ARGS_SIZE,
+            max_env_size: DEFAULT_MAX_ENV_SIZE,
+            interrupt: None,
+        }
+    }
+
+    /// Append one argument. The first argument is the guest's `argv[0]`.
+    pub fn arg(mut self, arg: impl Into<String>) -> Self {
+        self.args.push(arg.into());
+        self
+    }
+
+    /// Append several arguments.
+    pub fn args<I, S>(mut self, args: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.args.extend(args.into_iter().map(Into::into));
+        self
+    }
+
+    /// Append one environment variable.
+    pub fn env(mut self, key: impl Into<String>, value: impl Into<String>) -> Self {
+        self.env.push((key.into(), value.into()));
+        self
+    }
+
+    /// Append several environment variables.
+    pub fn envs<I, K, V>(mut self, envs: I) -> Self
+    where
+        I: IntoIterator<Item = (K, V)>,
+        K: Into<String>,
+        V: Into<String>,
+    {
+        self.env.extend(envs.into_iter().map(|(k, v)| (k.into(), v.into())));
+        self
+    }
+
+    /// Preopen a host directory under the guest-visible name `guest_path`.
+    pub fn preopen_dir(mut self, host_path: impl Into<PathBuf>, guest_path: impl Into<String>) -> Self {
+        self.preopens.push((host_path.into(), guest_path.into()));
+        self
+    }
+
+    /// Route the guest's stdin.
+    pub fn stdin(mut self, input: WasiInput) -> Self {
+        self.stdin = input;
+        self
+    }
+
+    /// Route the guest's stdout.
+    pub fn stdout(mut self, output: WasiOutput) -> Self {
+        self.stdout = output;
+        self
+    }
+
+    /// Route the guest's stderr.
+    pub fn stderr(mut self, output: WasiOutput) -> Self {
+        self.stderr = output;
+        self
+    }
+
+    /// Use a custom clock source, e.g. `WasiClock::Virtual`.
+    pub fn clock(mut self, clock: WasiClock) -> Self {
+        self.clock = clock;
+        self
+    }
+
+    /// Use a custom entropy source, e.g. `SeededRng`.
+    pub fn random(mut self, random: impl WasiEntropy + 'static) -> Self {
+        self.random = Box::new(random);
+        self
+    }
+
+    /// Share an interrupt handle that can wake a sleeping guest.
+    pub fn interrupt_handle(mut self, handle: AwwasmInterruptHandle) -> Self {
+        self.interrupt = Some(handle);
+        self
+    }
+
+    /// Cap the encoded size of argv (default 1 MiB).
+    pub fn max_args_size(mut self, bytes: usize) -> Self {
+        self.max_args_size = bytes;
+        self
+    }
+
+    /// Cap the encoded size of the environment (default 1 MiB).
+    pub fn max_env_size(mut self, bytes: usize) -> Self {
+        self.max_env_size = bytes;
+        self
+    }
+
+    /// Validate the configuration and create the context.
+    pub fn build(self) -> Result<AwwasmWasiCtx, WasiCtxError> {
+        for arg in &self.args {
+            if arg.contains('\0') {
+                return Err(WasiCtxError::InteriorNul(arg.clone()));
+            }
+        }
+        for (key, value) in &self.env {
+            if key.is_empty() || key.contains('=') {
+                return Err(WasiCtxError::InvalidEnvKey(key.clone()));
+            }
+            if key.contains('\0') || value.contains('\0') {
+                return Err(WasiCtxError::InteriorNul(format!("{}={}", key, value)));
+            }
+        }
+
+        let args_size: usize = self.args.iter().map(|a| a.len() + 1).sum();
+        if args_size > self.max_args_size {
+            return Err(WasiCtxError::ArgsTooLarge { size: args_size, limit: self.max_args_size });
+        }
+        let env_size: usize = self.env.iter().map(|(k, v)| k.len() + v.len() + 2).sum();
+        if env_size > self.max_env_size {
+            return Err(WasiCtxError::EnvTooLarge { size: env_size, limit: self.max_env_size });
+        }
+
+        let mut ctx = AwwasmWasiCtx::new();
+        ctx.args = self.args;
+        ctx.env = self.env;
+        ctx.stdin = self.stdin;
+        ctx.stdout = self.stdout;
+        ctx.stderr = self.stderr;
+        ctx.clock = self.clock;
+        ctx.random = self.random;
+        if let Some(handle) = self.interrupt {
+            ctx.interrupt = handle;
+        }
+        for (path, guest_path) in self.preopens {
+            if let Err(error) = ctx.preopen_dir(path.clone(), &guest_path) {
+                return Err(WasiCtxError::Preopen { path, error });
+            }
+        }
+        Ok(ctx)
+    }
+}
+
+impl Default for WasiCtxBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -0,0 +1,111 @@
+//! Clock sources for `clock_time_get` / `clock_res_get`.
+
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use crate::platform::AwwasmHostPlatform;
+
+/// WASI clock ids.
+pub const CLOCK_REALTIME: i32 = 0;
+pub const CLOCK_MONOTONIC: i32 = 1;
+pub const CLOCK_PROCESS_CPUTIME: i32 = 2;
+pub const CLOCK_THREAD_CPUTIME: i32 = 3;
+
+/// Time source seen by the guest.
+#[derive(Debug, Clone)]
+pub enum WasiClock {
+    /// Host wall clock and a monotonic clock started with the context.
+    Host {
+        start: Instant,
+    },
+    /// Deterministic clock that only moves when the embedder says so.
+    Virtual(VirtualClock),
+    /// The embedder's platform clock. Realtime is unknown if the
+    /// platform has no wall clock.
+    Platform(Arc<dyn AwwasmHostPlatform>),
+}
+
+impl WasiClock {
+    /// Clock backed by the host's real time sources.
+    pub fn host() -> Self {
+        WasiClock::Host { start: Instant::now() }
+    }
+
+    /// Current time of clock `id` in nanoseconds, or `None` for an
+    /// unknown id. CPU-time clocks are approximated by the monotonic one.
+    pub fn now(&self, id: i32) -> Option<u64> {
+        match (self, id) {
+            (WasiClock::Host { .. }, CLOCK_REALTIME) => Some(
+                SystemTime::now()
+                    .duration_since(UNIX_EPOCH)
+                    .map(|d| d.as_nanos() as u64)
+                    .unwrap_or(0),
+            ),
+            (WasiClock::Host { start }, CLOCK_MONOTONIC | CLOCK_PROCESS_CPUTIME | CLOCK_THREAD_CPUTIME) => {
+                Some(start.elapsed().as_nanos() as u64)
+            }
+            (WasiClock::Virtual(clock), CLOCK_REALTIME) => Some(clock.realtime_base.saturating_add(clock.elapsed())),
+            (WasiClock::Virtual(clock), CLOCK_MONOTONIC | CLOCK_PROCESS_CPUTIME | CLOCK_THREAD_CPUTIME) => {
+                Some(clock.elapsed())
+            }
+            (WasiClock::Platform(platform), CLOCK_REALTIME) => platform.realtime_nanos(),
+            (WasiClock::Platform(platform), CLOCK_MONOTONIC | CLOCK_PROCESS_CPUTIME | CLOCK_THREAD_CPUTIME) => {
+                Some(platform.monotonic_nanos())
+            }
+            _ => None,
+        }
+    }
+
+    /// Resolution of clock `id` in nanoseconds.
+    pub fn resolution(&self, id: i32) -> Option<u64> {
+        match id {
+            CLOCK_REALTIME | CLOCK_MONOTONIC | CLOCK_PROCESS_CPUTIME | CLOCK_THREAD_CPUTIME => Some(1),
+            _ => None,
+        }
+    }
+}
+
+impl Default for WasiClock {
+    fn default() -> Self {
+        Self::host()
+    }
+}
+
+/// A manually driven clock for deterministic tests and replay.
+///
+/// Clones share the same time, so the embedder can keep one handle and
+/// advance it while the context owns another.
+#[derive(Debug, Clone)]
+pub struct VirtualClock {
+    realtime_base: u64,
+    elapsed: Arc<AtomicU64>,
+}
+
+impl VirtualClock {
+    /// Create a clock whose realtime reads `realtime_base` nanoseconds
+    /// since the epoch and whose monotonic reading starts at zero.
+    pub fn new(realtime_base: u64) -> Self {
+        Self {
+            realtime_base,
+            elapsed: Arc::new(AtomicU64::new(0)),
+        }
+    }
+
+    /// Nanoseconds elapsed since creation.
+    pub fn elapsed(&self) -> u64 {
+        self.elapsed.load(Ordering::SeqCst)
+    }
+
+    /// Move the clock forward, stopping at `u64::MAX` nanoseconds.
+    pub fn advance(&self, by: Duration) {
+        let by = u64::try_from(by.as_nanos()).unwrap_or(u64::MAX);
+        let _ = self.elapsed.fetch_update(Ordering::SeqCst, Ordering::SeqCst, |n| Some(n.saturating_add(by)));
+    }
+
+    /// Set the elapsed time directly. Going backwards is allowed, which
+    /// breaks monotonicity; only do it when replaying a recording.
+    pub fn set_elapsed(&self, nanos: u64) {
+        self.elapsed.store(nanos, Ordering::SeqCst);
+    }
+}
@@ -0,0 +1,77 @@
+//! Capability-based path resolution for preopened directories.
+//!
+//! Guests only ever name files relative to a directory fd they already
+//! hold. Resolution never leaves that directory: `..` cannot climb above
+//! it, absolute paths are refused, and symlinks are only honoured when
+//! their target exists and still lies inside the directory.
+
+use std::io;
+use std::path::{Component, Path, PathBuf};
+
+use super::errno;
+
+/// Resolve `guest_path` against the directory `root`.
+///
+/// Returns the canonical host path that was checked, or a WASI errno if
+/// the path would escape `root`. Callers open exactly that path, so a
+/// symlink swapped in after the check along the way is not followed;
+/// only the final component of a file being created is looked up again.
+pub(crate) fn resolve(root: &Path, guest_path: &str) -> Result<PathBuf, u16> {
+    if guest_path.is_empty() {
+        return Err(errno::NOENT);
+    }
+
+    // Lexical pass: `..` may only pop components we pushed ourselves
+    let mut parts: Vec<&str> = Vec::new();
+    for component in Path::new(guest_path).components() {
+        match component {
+            Component::Normal(part) => parts.push(part.to_str().ok_or(errno::ILSEQ)?),
+            Component::CurDir => {}
+            Component::ParentDir => {
+                parts.pop().ok_or(errno::NOTCAPABLE)?;
+            }
+            Component::RootDir | Component::Prefix(_) => return Err(errno::NOTCAPABLE),
+        }
+    }
+
+    let mut host_path = root.to_path_buf();
+    host_path.extend(&parts);
+
+    // Symlink pass: whatever exists on disk must still resolve inside root
+    let root = root.canonicalize().map_err(|e| io_errno(&e))?;
+    let checked = match host_path.canonicalize() {
+        Ok(resolved) => resolved,
+        // A dangling symlink: creating through it would land wherever it
+        // points, which can't be checked until it exists
+        Err(e) if e.kind() == io::ErrorKind::NotFound && is_symlink(&host_path) => {
+            return Err(errno::NOTCAPABLE);
+        }
+        // Not created yet: check the parent instead
+        Err(e) if e.kind() == io::ErrorKind::NotFound => match (host_path.parent(), host_path.file_name()) {
+            (Some(parent), Some(name)) => parent.canonicalize().map_err(|e| io_errno(&e))?.join(name),
+            _ => return Err(errno::NOENT),
+        },
+        Err(e) => return Err(io_errno(&e)),
+    };
+    if !checked.starts_with(&root) {
+        return Err(errno::NOTCAPABLE);
+    }
+
+    Ok(checked)
+}
+
+/// Whether `path` itself is a symlink, without following it.
+fn is_symlink(path: &Path) -> bool {
+    path.symlink_metadata().map(|m| m.file_type().is_symlink()).unwrap_or(false)
+}
+
+/// Map a host I/O error to the closest WASI errno.
+pub(crate) fn io_errno(err: &io::Error) -> u16 {
+    match err.kind() {
+        io::ErrorKind::NotFound => errno::NOENT,
+        io::ErrorKind::PermissionDenied => errno::ACCES,
+        io::ErrorKind::AlreadyExists => errno::EXIST,
+        io::ErrorKind::InvalidInput => errno::INVAL,
+        _ => errno::IO,
+    }
+}
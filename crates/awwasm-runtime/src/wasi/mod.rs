@@ -0,0 +1,1248 @@
+//! WASI snapshot_preview1 support.
+//!
+//! `AwwasmWasiCtx` implements the `wasi_snapshot_preview1` host functions
+//! on top of the standard library. It registers itself into an
+//! `AwwasmImports` set as host function instances and then serves those
+//! calls through the `AwwasmHost` trait.
+//!
+//! Only available with the `wasi` feature.
+
+use std::fs::{File, OpenOptions};
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::path::PathBuf;
+use std::time::Duration;
+
+use crate::error::{AwwasmTrap, ExitStatus};
+use crate::func::{AwwasmFuncInst, AwwasmHost};
+use crate::imports::AwwasmImports;
+use crate::interrupt::AwwasmInterruptHandle;
+use crate::memory::AwwasmMemInst;
+use crate::values::AwwasmValue;
+
+mod builder;
+mod clock;
+mod fs;
+mod random;
+mod stdio;
+mod threads;
+
+pub use builder::{WasiCtxBuilder, WasiCtxError, DEFAULT_MAX_ARGS_SIZE, DEFAULT_MAX_ENV_SIZE};
+pub use clock::{VirtualClock, WasiClock};
+pub use random::{OsEntropy, PlatformEntropy, SeededRng, WasiEntropy};
+pub use stdio::{WasiInput, WasiOutput};
+pub use threads::{AwwasmWasiThreads, THREAD_SPAWN, THREAD_START, WASI_THREADS_MODULE};
+
+/// Import module name used by WASI preview1 guests.
+pub const WASI_MODULE: &[u8] = b"wasi_snapshot_preview1";
+
+/// Default first `host_func_id` handed out to WASI functions.
+pub const WASI_HOST_FUNC_BASE: u32 = 0x5741_0000;
+
+/// WASI errno values returned to the guest.
+pub mod errno {
+    pub const SUCCESS: u16 = 0;
+    pub const ACCES: u16 = 2;
+    pub const AGAIN: u16 = 6;
+    pub const BADF: u16 = 8;
+    pub const EXIST: u16 = 20;
+    pub const FAULT: u16 = 21;
+    pub const ILSEQ: u16 = 25;
+    pub const INVAL: u16 = 28;
+    pub const IO: u16 = 29;
+    pub const ISDIR: u16 = 31;
+    pub const NAMETOOLONG: u16 = 37;
+    pub const NOENT: u16 = 44;
+    pub const NOSYS: u16 = 52;
+    pub const NOTDIR: u16 = 54;
+    pub const SPIPE: u16 = 70;
+    pub const NOTCAPABLE: u16 = 76;
+}
+
+/// The WASI functions served by `AwwasmWasiCtx`.
+///
+/// The discriminant is the offset of the function's `host_func_id`
+/// from the context's base id.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u32)]
+pub enum WasiFunc {
+    ArgsGet,
+    ArgsSizesGet,
+    EnvironGet,
+    EnvironSizesGet,
+    ClockResGet,
+    ClockTimeGet,
+    FdClose,
+    FdFdstatGet,
+    FdPrestatGet,
+    FdPrestatDirName,
+    FdRead,
+    FdSeek,
+    FdWrite,
+    PathOpen,
+    ProcExit,
+    RandomGet,
+    SchedYield,
+    PollOneoff,
+}
+
+impl WasiFunc {
+    /// All WASI functions, in `host_func_id` order.
+    pub const ALL: &'static [WasiFunc] = &[
+        WasiFunc::ArgsGet,
+        WasiFunc::ArgsSizesGet,
+        WasiFunc::EnvironGet,
+        WasiFunc::EnvironSizesGet,
+        WasiFunc::ClockResGet,
+        WasiFunc::ClockTimeGet,
+        WasiFunc::FdClose,
+        WasiFunc::FdFdstatGet,
+        WasiFunc::FdPrestatGet,
+        WasiFunc::FdPrestatDirName,
+        WasiFunc::FdRead,
+        WasiFunc::FdSeek,
+        WasiFunc::FdWrite,
+        WasiFunc::PathOpen,
+        WasiFunc::ProcExit,
+        WasiFunc::RandomGet,
+        WasiFunc::SchedYield,
+        WasiFunc::PollOneoff,
+    ];
+
+    /// The import field name of this function.
+    pub fn name(self) -> &'static [u8] {
+        match self {
+            WasiFunc::ArgsGet => b"args_get",
+            WasiFunc::ArgsSizesGet => b"args_sizes_get",
+            WasiFunc::EnvironGet => b"environ_get",
+            WasiFunc::EnvironSizesGet => b"environ_sizes_get",
+            WasiFunc::ClockResGet => b"clock_res_get",
+            WasiFunc::ClockTimeGet => b"clock_time_get",
+            WasiFunc::FdClose => b"fd_close",
+            WasiFunc::FdFdstatGet => b"fd_fdstat_get",
+            WasiFunc::FdPrestatGet => b"fd_prestat_get",
+            WasiFunc::FdPrestatDirName => b"fd_prestat_dir_name",
+            WasiFunc::FdRead => b"fd_read",
+            WasiFunc::FdSeek => b"fd_seek",
+            WasiFunc::FdWrite => b"fd_write",
+            WasiFunc::PathOpen => b"path_open",
+            WasiFunc::ProcExit => b"proc_exit",
+            WasiFunc::RandomGet => b"random_get",
+            WasiFunc::SchedYield => b"sched_yield",
+            WasiFunc::PollOneoff => b"poll_oneoff",
+        }
+    }
+}
+
+/// `path_open` flags and rights understood by the context.
+const OFLAGS_CREAT: i32 = 1 << 0;
+const OFLAGS_DIRECTORY: i32 = 1 << 1;
+const OFLAGS_EXCL: i32 = 1 << 2;
+const OFLAGS_TRUNC: i32 = 1 << 3;
+const FDFLAGS_APPEND: i32 = 1 << 0;
+const RIGHTS_FD_READ: i64 = 1 << 1;
+const RIGHTS_FD_WRITE: i64 = 1 << 6;
+
+/// `poll_oneoff` subscription and event layout.
+const SUBSCRIPTION_SIZE: u32 = 48;
+const EVENT_SIZE: u32 = 32;
+const EVENTTYPE_CLOCK: u8 = 0;
+const EVENTTYPE_FD_READ: u8 = 1;
+const EVENTTYPE_FD_WRITE: u8 = 2;
+const SUBCLOCKFLAGS_ABSTIME: u16 = 1 << 0;
+
+/// A completed `poll_oneoff` subscription.
+struct WasiEvent {
+    userdata: u64,
+    error: u16,
+    tag: u8,
+    nbytes: u64,
+}
+
+impl WasiEvent {
+    fn encode(&self) -> [u8; EVENT_SIZE as usize] {
+        let mut out = [0u8; EVENT_SIZE as usize];
+        out[0..8].copy_from_slice(&self.userdata.to_le_bytes());
+        out[8..10].copy_from_slice(&self.error.to_le_bytes());
+        out[10] = self.tag;
+        out[16..24].copy_from_slice(&self.nbytes.to_le_bytes());
+        out
+    }
+}
+
+/// An open WASI file descriptor.
+#[derive(Debug)]
+enum WasiFd {
+    Stdin,
+    Stdout,
+    Stderr,
+    /// A regular file opened through `path_open`.
+    File(File),
+    /// A directory. `preopen` holds the guest-visible name of
+    /// directories handed to the guest at startup.
+    Dir {
+        host_path: PathBuf,
+        preopen: Option<String>,
+    },
+}
+
+impl WasiFd {
+    /// The WASI `filetype` reported by `fd_fdstat_get`.
+    fn filetype(&self) -> u8 {
+        match self {
+            WasiFd::Stdin | WasiFd::Stdout | WasiFd::Stderr => 2,
+            WasiFd::Dir { .. } => 3,
+            WasiFd::File(_) => 4,
+        }
+    }
+}
+
+/// WASI preview1 host context.
+///
+/// Holds the guest-visible process state (arguments, environment,
+/// file descriptors) and implements the host side of each call.
+#[derive(Debug)]
+pub struct AwwasmWasiCtx {
+    base_id: u32,
+    args: Vec<String>,
+    env: Vec<(String, String)>,
+    fds: Vec<Option<WasiFd>>,
+    clock: WasiClock,
+    random: Box<dyn WasiEntropy>,
+    stdin: WasiInput,
+    stdout: WasiOutput,
+    stderr: WasiOutput,
+    interrupt: AwwasmInterruptHandle,
+}
+
+impl AwwasmWasiCtx {
+    /// Create a context with no arguments, an empty environment and
+    /// stdio wired to the host process.
+    pub fn new() -> Self {
+        Self {
+            base_id: WASI_HOST_FUNC_BASE,
+            args: Vec::new(),
+            env: Vec::new(),
+            fds: vec![Some(WasiFd::Stdin), Some(WasiFd::Stdout), Some(WasiFd::Stderr)],
+            clock: WasiClock::host(),
+            random: Box::new(OsEntropy),
+            stdin: WasiInput::Inherit,
+            stdout: WasiOutput::Inherit,
+            stderr: WasiOutput::Inherit,
+            interrupt: AwwasmInterruptHandle::new(),
+        }
+    }
+
+    /// Start configuring a context with `WasiCtxBuilder`.
+    pub fn builder() -> WasiCtxBuilder {
+        WasiCtxBuilder::new()
+    }
+
+    /// Use a different first `host_func_id`, to avoid clashing with
+    /// other host functions registered by the embedder.
+    pub fn with_base_id(mut self, base_id: u32) -> Self {
+        self.base_id = base_id;
+        self
+    }
+
+    /// Replace the clock source, e.g. with a `VirtualClock` for
+    /// deterministic runs.
+    pub fn set_clock(&mut self, clock: WasiClock) {
+        self.clock = clock;
+    }
+
+    /// Replace the entropy source behind `random_get`, e.g. with a
+    /// `SeededRng` for reproducible runs.
+    pub fn set_random(&mut self, random: impl WasiEntropy + 'static) {
+        self.random = Box::new(random);
+    }
+
+    /// Route the guest's stdin.
+    pub fn set_stdin(&mut self, input: WasiInput) {
+        self.stdin = input;
+    }
+
+    /// Route the guest's stdout.
+    pub fn set_stdout(&mut self, output: WasiOutput) {
+        self.stdout = output;
+    }
+
+    /// Route the guest's stderr.
+    pub fn set_stderr(&mut self, output: WasiOutput) {
+        self.stderr = output;
+    }
+
+    /// Handle that wakes a guest blocked in `poll_oneoff` and makes the
+    /// call fail with `AwwasmTrap::Interrupted`.
+    pub fn interrupt_handle(&self) -> AwwasmInterruptHandle {
+        self.interrupt.clone()
+    }
+
+    /// Share an existing interrupt handle, e.g. the one the embedder
+    /// already uses to stop the running guest.
+    pub fn set_interrupt_handle(&mut self, handle: AwwasmInterruptHandle) {
+        self.interrupt = handle;
+    }
+
+    /// Take everything captured from stdout so far.
+    ///
+    /// Only meaningful with `WasiOutput::Capture`; the buffer is left
+    /// empty so the context can be reused for the next request.
+    pub fn take_stdout(&mut self) -> Vec<u8> {
+        self.stdout.take()
+    }
+
+    /// Take everything captured from stderr so far.
+    pub fn take_stderr(&mut self) -> Vec<u8> {
+        self.stderr.take()
+    }
+
+    /// Grant the guest access to a host directory.
+    ///
+    /// The directory shows up as a preopened fd named `guest_path`.
+    /// Paths the guest opens through it are resolved inside `host_path`
+    /// only; see the `fs` module for the rules. Returns the new fd.
+    pub fn preopen_dir(&mut self, host_path: impl Into<PathBuf>, guest_path: &str) -> std::io::Result<u32> {
+        let host_path = host_path.into();
+        if !std::fs::metadata(&host_path)?.is_dir() {
+            return Err(std::io::Error::new(std::io::ErrorKind::InvalidInput, "preopen is not a directory"));
+        }
+        Ok(self.insert_fd(WasiFd::Dir {
+            host_path,
+            preopen: Some(guest_path.into()),
+        }))
+    }
+
+    /// The `host_func_id` assigned to a WASI function.
+    pub fn host_func_id(&self, func: WasiFunc) -> u32 {
+        self.base_id + func as u32
+    }
+
+    /// Map a `host_func_id` back to the WASI function it names.
+    pub fn lookup(&self, host_func_id: u32) -> Option<WasiFunc> {
+        let offset = host_func_id.checked_sub(self.base_id)?;
+        WasiFunc::ALL.get(offset as usize).copied()
+    }
+
+    /// Register every WASI function under `wasi_snapshot_preview1`.
+    pub fn add_to_imports<'a>(&self, imports: &mut AwwasmImports<'a>) {
+        for &func in WasiFunc::ALL {
+            imports.add_func(WASI_MODULE, func.name(), AwwasmFuncInst::host(0, self.host_func_id(func)));
+        }
+    }
+
+    fn dispatch(
+        &mut self,
+        func: WasiFunc,
+        args: &[AwwasmValue],
+        mem: &mut AwwasmMemInst,
+    ) -> Result<u16, ArgError> {
+        let errno = match func {
+            WasiFunc::ArgsGet => {
+                let (argv, buf) = (ptr(args, 0)?, ptr(args, 1)?);
+                let strings: Vec<&str> = self.args.iter().map(String::as_str).collect();
+                write_string_list(mem, &strings, argv, buf)
+            }
+            WasiFunc::ArgsSizesGet => {
+                let strings: Vec<&str> = self.args.iter().map(String::as_str).collect();
+                write_sizes(mem, &strings, ptr(args, 0)?, ptr(args, 1)?)
+            }
+            WasiFunc::EnvironGet => {
+                let (environ, buf) = (ptr(args, 0)?, ptr(args, 1)?);
+                let pairs = self.env_strings();
+                let strings: Vec<&str> = pairs.iter().map(String::as_str).collect();
+                write_string_list(mem, &strings, environ, buf)
+            }
+            WasiFunc::EnvironSizesGet => {
+                let pairs = self.env_strings();
+                let strings: Vec<&str> = pairs.iter().map(String::as_str).collect();
+                write_sizes(mem, &strings, ptr(args, 0)?, ptr(args, 1)?)
+            }
+            WasiFunc::ClockResGet => {
+                let (id, res_ptr) = (i32_arg(args, 0)?, ptr(args, 1)?);
+                match self.clock.resolution(id) {
+                    Some(res) => store_u64(mem, res_ptr, res),
+                    None => errno::INVAL,
+                }
+            }
+            WasiFunc::ClockTimeGet => {
+                let (id, _precision, time_ptr) = (i32_arg(args, 0)?, i64_arg(args, 1)?, ptr(args, 2)?);
+                match self.clock.now(id) {
+                    Some(now) => store_u64(mem, time_ptr, now),
+                    None => errno::INVAL,
+                }
+            }
+            WasiFunc::FdClose => {
+                let fd = i32_arg(args, 0)? as u32;
+                match self.fds.get_mut(fd as usize) {
+                    Some(slot @ Some(_)) => {
+                        *slot = None;
+                        errno::SUCCESS
+                    }
+                    _ => errno::BADF,
+                }
+            }
+            WasiFunc::FdFdstatGet => {
+                let (fd, buf) = (i32_arg(args, 0)? as u32, ptr(args, 1)?);
+                match self.fd(fd) {
+                    // No flags, all rights
+                    Some(entry) => {
+                        let mut stat = [0u8; 24];
+                        stat[0] = entry.filetype();
+                        stat[8..16].copy_from_slice(&u64::MAX.to_le_bytes());
+                        stat[16..24].copy_from_slice(&u64::MAX.to_le_bytes());
+                        store_bytes(mem, buf, &stat)
+                    }
+                    None => errno::BADF,
+                }
+            }
+            WasiFunc::FdPrestatGet => {
+                let (fd, buf) = (i32_arg(args, 0)? as u32, ptr(args, 1)?);
+                match self.fd(fd) {
+                    // tag = PREOPENTYPE_DIR, then u32 name length
+                    Some(WasiFd::Dir { preopen: Some(name), .. }) => {
+                        let mut prestat = [0u8; 8];
+                        prestat[4..8].copy_from_slice(&(name.len() as u32).to_le_bytes());
+                        store_bytes(mem, buf, &prestat)
+                    }
+                    _ => errno::BADF,
+                }
+            }
+            WasiFunc::FdPrestatDirName => {
+                let (fd, path, len) = (i32_arg(args, 0)? as u32, ptr(args, 1)?, ptr(args, 2)?);
+                match self.fd(fd) {
+                    Some(WasiFd::Dir { preopen: Some(name), .. }) => {
+                        if (len as usize) < name.len() {
+                            errno::NAMETOOLONG
+                        } else {
+                            store_bytes(mem, path, name.as_bytes())
+                        }
+                    }
+                    _ => errno::BADF,
+                }
+            }
+            WasiFunc::FdRead => {
+                let (fd, iovs, iovs_len, nread_ptr) =
+                    (i32_arg(args, 0)? as u32, ptr(args, 1)?, ptr(args, 2)?, ptr(args, 3)?);
+                let iovecs = match read_iovecs(mem, iovs, iovs_len) {
+                    Ok(iovecs) => iovecs,
+                    Err(e) => return Ok(e),
+                };
+                if iovecs.iter().any(|&(buf_ptr, buf_len)| !in_bounds(mem, buf_ptr, buf_len)) {
+                    return Ok(errno::FAULT);
+                }
+                let mut chunk = [0u8; COPY_CHUNK];
+                let mut total = 0u32;
+                'iovecs: for (buf_ptr, buf_len) in iovecs {
+                    let mut done = 0u32;
+                    while done < buf_len {
+                        let want = (buf_len - done).min(COPY_CHUNK as u32) as usize;
+                        let result = match self.fds.get_mut(fd as usize).and_then(Option::as_mut) {
+                            Some(WasiFd::Stdin) => self.stdin.read(&mut chunk[..want]),
+                            Some(WasiFd::File(file)) => file.read(&mut chunk[..want]),
+                            Some(WasiFd::Dir { .. }) => return Ok(errno::ISDIR),
+                            _ => return Ok(errno::BADF),
+                        };
+                        let n = match result {
+                            Ok(n) => n,
+                            Err(e) => return Ok(fs::io_errno(&e)),
+                        };
+                        if mem.write(buf_ptr + done, &chunk[..n]).is_err() {
+                            return Ok(errno::FAULT);
+                        }
+                        total += n as u32;
+                        done += n as u32;
+                        if n < want {
+                            break 'iovecs;
+                        }
+                    }
+                }
+                store_u32(mem, nread_ptr, total)
+            }
+            WasiFunc::FdSeek => {
+                let fd = i32_arg(args, 0)? as u32;
+                let (offset, whence, newoffset) = (i64_arg(args, 1)?, i32_arg(args, 2)?, ptr(args, 3)?);
+                let pos = match whence {
+                    0 => SeekFrom::Start(offset as u64),
+                    1 => SeekFrom::Current(offset),
+                    2 => SeekFrom::End(offset),
+                    _ => return Ok(errno::INVAL),
+                };
+                match self.fd_mut(fd) {
+                    Some(WasiFd::File(file)) => match file.seek(pos) {
+                        Ok(new_pos) => store_u64(mem, newoffset, new_pos),
+                        Err(e) => fs::io_errno(&e),
+                    },
+                    Some(WasiFd::Dir { .. }) => errno::ISDIR,
+                    Some(_) => errno::SPIPE,
+                    None => errno::BADF,
+                }
+            }
+            WasiFunc::FdWrite => {
+                let (fd, iovs, iovs_len, nwritten_ptr) =
+                    (i32_arg(args, 0)? as u32, ptr(args, 1)?, ptr(args, 2)?, ptr(args, 3)?);
+                let iovecs = match read_iovecs(mem, iovs, iovs_len) {
+                    Ok(iovecs) => iovecs,
+                    Err(e) => return Ok(e),
+                };
+                match self.fds.get(fd as usize).and_then(Option::as_ref) {
+                    Some(WasiFd::Dir { .. }) => return Ok(errno::ISDIR),
+                    Some(WasiFd::Stdin) | None => return Ok(errno::BADF),
+                    _ => {}
+                }
+                if iovecs.iter().any(|&(buf_ptr, buf_len)| !in_bounds(mem, buf_ptr, buf_len)) {
+                    return Ok(errno::FAULT);
+                }
+                // Iovecs may all alias one large region, so stream them in
+                // place rather than gathering; the count stops at u32::MAX.
+                let mut total = 0u32;
+                'iovecs: for (buf_ptr, buf_len) in iovecs {
+                    let mut done = 0u32;
+                    while done < buf_len {
+                        let n = (buf_len - done).min(COPY_CHUNK as u32).min(u32::MAX - total);
+                        if n == 0 {
+                            break 'iovecs;
+                        }
+                        let Ok(chunk) = mem.read(buf_ptr + done, n) else {
+                            return Ok(errno::FAULT);
+                        };
+                        let result = match self.fds.get_mut(fd as usize).and_then(Option::as_mut) {
+                            Some(WasiFd::Stdout) => self.stdout.write_all(&chunk, false),
+                            Some(WasiFd::Stderr) => self.stderr.write_all(&chunk, true),
+                            Some(WasiFd::File(file)) => file.write_all(&chunk),
+                            _ => return Ok(errno::BADF),
+                        };
+                        if let Err(e) = result {
+                            return Ok(fs::io_errno(&e));
+                        }
+                        total += n;
+                        done += n;
+                    }
+                }
+                store_u32(mem, nwritten_ptr, total)
+            }
+            WasiFunc::PathOpen => {
+                let (dirfd, _dirflags, path, path_len) =
+                    (i32_arg(args, 0)? as u32, i32_arg(args, 1)?, ptr(args, 2)?, ptr(args, 3)?);
+                let (oflags, rights, _inheriting, fdflags, fd_ptr) =
+                    (i32_arg(args, 4)?, i64_arg(args, 5)?, i64_arg(args, 6)?, i32_arg(args, 7)?, ptr(args, 8)?);
+                let guest_path = match mem.read(path, path_len) {
+                    Ok(bytes) => match core::str::from_utf8(&bytes) {
+                        Ok(s) => String::from(s),
+                        Err(_) => return Ok(errno::ILSEQ),
+                    },
+                    Err(_) => return Ok(errno::FAULT),
+                };
+                match self.path_open(dirfd, &guest_path, oflags, rights, fdflags) {
+                    Ok(fd) => store_u32(mem, fd_ptr, fd),
+                    Err(e) => e,
+                }
+            }
+            WasiFunc::ProcExit | WasiFunc::PollOneoff => unreachable!("handled before dispatch"),
+            WasiFunc::RandomGet => {
+                let (buf, len) = (ptr(args, 0)?, ptr(args, 1)?);
+                // The guest picks `len`; fill its buffer in place rather
+                // than allocating that much on the host.
+                if !in_bounds(mem, buf, len) {
+                    return Ok(errno::FAULT);
+                }
+                let mut chunk = [0u8; COPY_CHUNK];
+                let mut done = 0;
+                while done < len {
+                    let n = (len - done).min(COPY_CHUNK as u32) as usize;
+                    if let Err(e) = self.random.fill(&mut chunk[..n]) {
+                        return Ok(fs::io_errno(&e));
+                    }
+                    if mem.write(buf + done, &chunk[..n]).is_err() {
+                        return Ok(errno::FAULT);
+                    }
+                    done += n as u32;
+                }
+                errno::SUCCESS
+            }
+            WasiFunc::SchedYield => {
+                match &self.clock {
+                    WasiClock::Platform(platform) => platform.yield_now(),
+                    _ => std::thread::yield_now(),
+                }
+                errno::SUCCESS
+            }
+        };
+        Ok(errno)
+    }
+
+    /// Wait until at least one subscription is ready, then write its
+    /// events.
+    ///
+    /// Every open fd is always ready, so fd subscriptions complete
+    /// immediately. Otherwise the call sleeps until the earliest clock
+    /// deadline: on the host clock that blocks on the interrupt handle,
+    /// on a virtual clock it simply advances the clock.
+    fn poll_oneoff(
+        &mut self,
+        mem: &mut AwwasmMemInst,
+        subs: u32,
+        events: u32,
+        nsubscriptions: u32,
+        nevents_ptr: u32,
+    ) -> Result<u16, AwwasmTrap> {
+        if nsubscriptions == 0 {
+            return Ok(errno::INVAL);
+        }
+
+        let mut ready = Vec::new();
+        let mut timers = Vec::new();
+        for i in 0..nsubscriptions {
+            let Some(sub) = i
+                .checked_mul(SUBSCRIPTION_SIZE)
+                .and_then(|off| subs.checked_add(off))
+                .and_then(|base| mem.read(base, SUBSCRIPTION_SIZE).ok())
+            else {
+                return Ok(errno::FAULT);
+            };
+            let userdata = u64::from_le_bytes(sub[0..8].try_into().unwrap());
+            let tag = sub[8];
+            match tag {
+                EVENTTYPE_CLOCK => {
+                    let id = i32::from_le_bytes(sub[16..20].try_into().unwrap());
+                    let timeout = u64::from_le_bytes(sub[24..32].try_into().unwrap());
+                    let flags = u16::from_le_bytes(sub[40..42].try_into().unwrap());
+                    let Some(now) = self.clock.now(id) else {
+                        ready.push(WasiEvent { userdata, error: errno::INVAL, tag, nbytes: 0 });
+                        continue;
+                    };
+                    let delay = if flags & SUBCLOCKFLAGS_ABSTIME != 0 {
+                        timeout.saturating_sub(now)
+                    } else {
+                        timeout
+                    };
+                    timers.push((delay, userdata));
+                }
+                EVENTTYPE_FD_READ | EVENTTYPE_FD_WRITE => {
+                    let fd = u32::from_le_bytes(sub[16..20].try_into().unwrap());
+                    let error = if self.fd(fd).is_some() { errno::SUCCESS } else { errno::BADF };
+                    ready.push(WasiEvent { userdata, error, tag, nbytes: 0 });
+                }
+                _ => return Ok(errno::INVAL),
+            }
+        }
+
+        if ready.is_empty() {
+            let delay = timers.iter().map(|&(delay, _)| delay).min().unwrap_or(0);
+            match &self.clock {
+                WasiClock::Host { .. } => {
+                    if !self.interrupt.sleep(Duration::from_nanos(delay)) {
+                        return Err(AwwasmTrap::Interrupted);
+                    }
+                }
+                WasiClock::Virtual(clock) => clock.advance(Duration::from_nanos(delay)),
+                WasiClock::Platform(platform) => {
+                    platform.sleep(delay);
+                    if self.interrupt.is_interrupted() {
+                        return Err(AwwasmTrap::Interrupted);
+                    }
+                }
+            }
+            ready.extend(timers.iter().filter(|&&(d, _)| d <= delay).map(|&(_, userdata)| WasiEvent {
+                userdata,
+                error: errno::SUCCESS,
+                tag: EVENTTYPE_CLOCK,
+                nbytes: 0,
+            }));
+        }
+
+        for (i, event) in ready.iter().enumerate() {
+            let base = events.wrapping_add((i as u32).wrapping_mul(EVENT_SIZE));
+            let errno = store_bytes(mem, base, &event.encode());
+            if errno != errno::SUCCESS {
+                return Ok(errno);
+            }
+        }
+        Ok(store_u32(mem, nevents_ptr, ready.len() as u32))
+    }
+
+    fn fd(&self, fd: u32) -> Option<&WasiFd> {
+        self.fds.get(fd as usize).and_then(Option::as_ref)
+    }
+
+    fn fd_mut(&mut self, fd: u32) -> Option<&mut WasiFd> {
+        self.fds.get_mut(fd as usize).and_then(Option::as_mut)
+    }
+
+    /// Store `entry` in the lowest free fd slot.
+    fn insert_fd(&mut self, entry: WasiFd) -> u32 {
+        match self.fds.iter().position(Option::is_none) {
+            Some(idx) => {
+                self.fds[idx] = Some(entry);
+                idx as u32
+            }
+            None => {
+                self.fds.push(Some(entry));
+                (self.fds.len() - 1) as u32
+            }
+        }
+    }
+
+    fn path_open(&mut self, dirfd: u32, guest_path: &str, oflags: i32, rights: i64, fdflags: i32) -> Result<u32, u16> {
+        let root = match self.fd(dirfd) {
+            Some(WasiFd::Dir { host_path, .. }) => host_path.clone(),
+            Some(_) => return Err(errno::NOTDIR),
+            None => return Err(errno::BADF),
+        };
+        let host_path = fs::resolve(&root, guest_path)?;
+
+        let is_dir = host_path.is_dir();
+        if oflags & OFLAGS_DIRECTORY != 0 || is_dir {
+            if !is_dir {
+                return Err(if host_path.exists() { errno::NOTDIR } else { errno::NOENT });
+            }
+            return Ok(self.insert_fd(WasiFd::Dir { host_path, preopen: None }));
+        }
+
+        let read = rights & RIGHTS_FD_READ != 0;
+        let write = rights & RIGHTS_FD_WRITE != 0;
+        let file = OpenOptions::new()
+            .read(read || !write)
+            .write(write)
+            .append(fdflags & FDFLAGS_APPEND != 0)
+            .create(oflags & OFLAGS_CREAT != 0)
+            .create_new(oflags & OFLAGS_CREAT != 0 && oflags & OFLAGS_EXCL != 0)
+            .truncate(oflags & OFLAGS_TRUNC != 0)
+            .open(&host_path)
+            .map_err(|e| fs::io_errno(&e))?;
+        Ok(self.insert_fd(WasiFd::File(file)))
+    }
+
+    fn env_strings(&self) -> Vec<String> {
+        self.env.iter().map(|(k, v)| format!("{}={}", k, v)).collect()
+    }
+}
+
+impl Default for AwwasmWasiCtx {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl AwwasmHost for AwwasmWasiCtx {
+    fn call(
+        &mut self,
+        host_func_id: u32,
+        args: &[AwwasmValue],
+        mem: Option<&mut AwwasmMemInst>,
+    ) -> Result<Vec<AwwasmValue>, AwwasmTrap> {
+        let invalid = AwwasmTrap::InvalidHostCall { host_func_id };
+        let func = self.lookup(host_func_id).ok_or(invalid.clone())?;
+
+        if func == WasiFunc::ProcExit {
+            let code = i32_arg(args, 0).map_err(|_| invalid.clone())?;
+            return Err(AwwasmTrap::Exit(ExitStatus(code as u32)));
+        }
+
+        if func == WasiFunc::PollOneoff {
+            let (subs, events, nsubscriptions, nevents) =
+                match (ptr(args, 0), ptr(args, 1), ptr(args, 2), ptr(args, 3)) {
+                    (Ok(a), Ok(b), Ok(c), Ok(d)) => (a, b, c, d),
+                    _ => return Err(invalid),
+                };
+            let errno = match mem {
+                Some(mem) => self.poll_oneoff(mem, subs, events, nsubscriptions, nevents)?,
+                None => errno::FAULT,
+            };
+            return Ok(vec![AwwasmValue::I32(errno as i32)]);
+        }
+
+        let errno = match mem {
+            Some(mem) => self.dispatch(func, args, mem).map_err(|_| invalid)?,
+            None => errno::FAULT,
+        };
+        Ok(vec![AwwasmValue::I32(errno as i32)])
+    }
+}
+
+/// Argument of the wrong type or arity.
+struct ArgError;
+
+fn i32_arg(args: &[AwwasmValue], n: usize) -> Result<i32, ArgError> {
+    args.get(n).and_then(AwwasmValue::as_i32).ok_or(ArgError)
+}
+
+fn i64_arg(args: &[AwwasmValue], n: usize) -> Result<i64, ArgError> {
+    args.get(n).and_then(AwwasmValue::as_i64).ok_or(ArgError)
+}
+
+fn ptr(args: &[AwwasmValue], n: usize) -> Result<u32, ArgError> {
+    i32_arg(args, n).map(|v| v as u32)
+}
+
+fn store_u32(mem: &mut AwwasmMemInst, offset: u32, value: u32) -> u16 {
+    store_bytes(mem, offset, &value.to_le_bytes())
+}
+
+fn store_u64(mem: &mut AwwasmMemInst, offset: u32, value: u64) -> u16 {
+    store_bytes(mem, offset, &value.to_le_bytes())
+}
+
+/// Bytes copied from the host into guest memory at a time, so no host
+/// allocation is sized by a guest-chosen length.
+const COPY_CHUNK: usize = 4096;
+
+/// Whether the `len` bytes at `offset` lie inside `mem`.
+fn in_bounds(mem: &AwwasmMemInst, offset: u32, len: u32) -> bool {
+    u64::from(offset) + u64::from(len) <= mem.size_bytes() as u64
+}
+
+fn store_bytes(mem: &mut AwwasmMemInst, offset: u32, bytes: &[u8]) -> u16 {
+    match mem.write(offset, bytes) {
+        Ok(()) => errno::SUCCESS,
+        Err(_) => errno::FAULT,
+    }
+}
+
+/// Read an array of `(buf, buf_len)` iovecs from guest memory.
+fn read_iovecs(mem: &AwwasmMemInst, iovs: u32, iovs_len: u32) -> Result<Vec<(u32, u32)>, u16> {
+    (0..iovs_len)
+        .map(|i| {
+            let base = iovs.checked_add(i.checked_mul(8).ok_or(errno::FAULT)?).ok_or(errno::FAULT)?;
+            let buf = mem.read_i32(base).map_err(|_| errno::FAULT)? as u32;
+            let len = mem.read_i32(base.wrapping_add(4)).map_err(|_| errno::FAULT)? as u32;
+            Ok((buf, len))
+        })
+        .collect()
+}
+
+/// Write the count and total NUL-terminated size of a string list.
+fn write_sizes(mem: &mut AwwasmMemInst, strings: &[&str], count_ptr: u32, size_ptr: u32) -> u16 {
+    let size: usize = strings.iter().map(|s| s.len() + 1).sum();
+    match store_u32(mem, count_ptr, strings.len() as u32) {
+        errno::SUCCESS => store_u32(mem, size_ptr, size as u32),
+        e => e,
+    }
+}
+
+/// Write a string list as a pointer array plus NUL-terminated buffer.
+fn write_string_list(mem: &mut AwwasmMemInst, strings: &[&str], ptrs: u32, buf: u32) -> u16 {
+    let mut cursor = buf;
+    for (i, s) in strings.iter().enumerate() {
+        let slot = ptrs.wrapping_add(i as u32 * 4);
+        if store_u32(mem, slot, cursor) != errno::SUCCESS {
+            return errno::FAULT;
+        }
+        if store_bytes(mem, cursor, s.as_bytes()) != errno::SUCCESS
+            || store_bytes(mem, cursor.wrapping_add(s.len() as u32), &[0]) != errno::SUCCESS
+        {
+            return errno::FAULT;
+        }
+        cursor = cursor.wrapping_add(s.len() as u32 + 1);
+    }
+    errno::SUCCESS
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::memory::AwwasmMemoryType;
+    use crate::store::AwwasmStore;
+    use awwasm_parser::components::module::AwwasmModule;
+
+    fn call(ctx: &mut AwwasmWasiCtx, func: WasiFunc, args: &[AwwasmValue], mem: &mut AwwasmMemInst) -> i32 {
+        let id = ctx.host_func_id(func);
+        ctx.call(id, args, Some(mem)).unwrap()[0].as_i32().unwrap()
+    }
+
+    #[test]
+    fn test_args_roundtrip() {
+        let mut ctx = AwwasmWasiCtx::builder().args(["prog", "-v"]).build().unwrap();
+        let mut mem = AwwasmMemInst::new(AwwasmMemoryType::new(1, None));
+
+        let sizes = [AwwasmValue::I32(0), AwwasmValue::I32(4)];
+        assert_eq!(call(&mut ctx, WasiFunc::ArgsSizesGet, &sizes, &mut mem), 0);
+        assert_eq!(mem.read_i32(0).unwrap(), 2);
+        assert_eq!(mem.read_i32(4).unwrap(), 8);
+
+        let get = [AwwasmValue::I32(16), AwwasmValue::I32(64)];
+        assert_eq!(call(&mut ctx, WasiFunc::ArgsGet, &get, &mut mem), 0);
+        assert_eq!(mem.read_i32(16).unwrap(), 64);
+        assert_eq!(mem.read_i32(20).unwrap(), 69);
+        assert_eq!(&*mem.read(64, 8).unwrap(), b"prog\0-v\0");
+    }
+
+    #[test]
+    fn test_builder_env_and_limits() {
+        let mut ctx = AwwasmWasiCtx::builder()
+            .arg("prog")
+            .envs([("A", "1"), ("LANG", "C")])
+            .build()
+            .unwrap();
+        let mut mem = AwwasmMemInst::new(AwwasmMemoryType::new(1, None));
+
+        let sizes = [AwwasmValue::I32(0), AwwasmValue::I32(4)];
+        assert_eq!(call(&mut ctx, WasiFunc::EnvironSizesGet, &sizes, &mut mem), 0);
+        assert_eq!(mem.read_i32(0).unwrap(), 2);
+        assert_eq!(mem.read_i32(4).unwrap(), 11);
+        let get = [AwwasmValue::I32(16), AwwasmValue::I32(64)];
+        assert_eq!(call(&mut ctx, WasiFunc::EnvironGet, &get, &mut mem), 0);
+        assert_eq!(&*mem.read(64, 11).unwrap(), b"A=1\0LANG=C\0");
+
+        let too_big = AwwasmWasiCtx::builder().args(["abc", "de"]).max_args_size(6).build();
+        assert!(matches!(too_big, Err(WasiCtxError::ArgsTooLarge { size: 7, limit: 6 })));
+        let too_big = AwwasmWasiCtx::builder().env("K", "VV").max_env_size(4).build();
+        assert!(matches!(too_big, Err(WasiCtxError::EnvTooLarge { size: 5, limit: 4 })));
+        assert!(matches!(
+            AwwasmWasiCtx::builder().env("A=B", "1").build(),
+            Err(WasiCtxError::InvalidEnvKey(_))
+        ));
+        assert!(matches!(
+            AwwasmWasiCtx::builder().arg("a\0b").build(),
+            Err(WasiCtxError::InteriorNul(_))
+        ));
+    }
+
+    #[test]
+    fn test_virtual_clock() {
+        let clock = VirtualClock::new(1_000_000_000);
+        let mut ctx = AwwasmWasiCtx::builder().clock(WasiClock::Virtual(clock.clone())).build().unwrap();
+        let mut mem = AwwasmMemInst::new(AwwasmMemoryType::new(1, None));
+
+        let get = |id| [AwwasmValue::I32(id), AwwasmValue::I64(0), AwwasmValue::I32(0)];
+        assert_eq!(call(&mut ctx, WasiFunc::ClockTimeGet, &get(1), &mut mem), 0);
+        assert_eq!(mem.read_i64(0).unwrap(), 0);
+
+        clock.advance(std::time::Duration::from_millis(5));
+        assert_eq!(call(&mut ctx, WasiFunc::ClockTimeGet, &get(1), &mut mem), 0);
+        assert_eq!(mem.read_i64(0).unwrap(), 5_000_000);
+        assert_eq!(call(&mut ctx, WasiFunc::ClockTimeGet, &get(0), &mut mem), 0);
+        assert_eq!(mem.read_i64(0).unwrap(), 1_005_000_000);
+
+        assert_eq!(call(&mut ctx, WasiFunc::ClockTimeGet, &get(9), &mut mem), errno::INVAL as i32);
+        let res = [AwwasmValue::I32(1), AwwasmValue::I32(8)];
+        assert_eq!(call(&mut ctx, WasiFunc::ClockResGet, &res, &mut mem), 0);
+        assert_eq!(mem.read_i64(8).unwrap(), 1);
+
+        clock.advance(std::time::Duration::MAX);
+        assert_eq!(call(&mut ctx, WasiFunc::ClockTimeGet, &get(0), &mut mem), 0);
+        assert_eq!(mem.read_i64(0).unwrap(), -1);
+    }
+
+    /// Write a 48-byte subscription at `base`.
+    fn subscription(mem: &mut AwwasmMemInst, base: u32, userdata: u64, tag: u8, word: u32, timeout: u64) {
+        let mut sub = [0u8; 48];
+        sub[0..8].copy_from_slice(&userdata.to_le_bytes());
+        sub[8] = tag;
+        sub[16..20].copy_from_slice(&word.to_le_bytes());
+        sub[24..32].copy_from_slice(&timeout.to_le_bytes());
+        mem.write(base, &sub).unwrap();
+    }
+
+    #[test]
+    fn test_poll_oneoff() {
+        let clock = VirtualClock::new(0);
+        let mut ctx = AwwasmWasiCtx::builder().clock(WasiClock::Virtual(clock.clone())).build().unwrap();
+        let mut mem = AwwasmMemInst::new(AwwasmMemoryType::new(1, None));
+        let poll = |n| [AwwasmValue::I32(0), AwwasmValue::I32(512), AwwasmValue::I32(n), AwwasmValue::I32(1024)];
+
+        // Two sleeps: only the earlier one fires, and the virtual clock
+        // moves instead of the thread blocking.
+        subscription(&mut mem, 0, 7, 0, 1, 2_000_000);
+        subscription(&mut mem, 48, 8, 0, 1, 9_000_000);
+        assert_eq!(call(&mut ctx, WasiFunc::PollOneoff, &poll(2), &mut mem), 0);
+        assert_eq!(mem.read_i32(1024).unwrap(), 1);
+        assert_eq!(mem.read_i64(512).unwrap(), 7);
+        assert_eq!(clock.elapsed(), 2_000_000);
+
+        // A ready fd wins over a pending timer; a bad fd reports BADF.
+        subscription(&mut mem, 0, 1, 2, 1, 0);
+        subscription(&mut mem, 48, 2, 1, 99, 0);
+        subscription(&mut mem, 96, 3, 0, 1, 5_000_000);
+        assert_eq!(call(&mut ctx, WasiFunc::PollOneoff, &poll(3), &mut mem), 0);
+        assert_eq!(mem.read_i32(1024).unwrap(), 2);
+        assert_eq!(mem.read_i64(512).unwrap(), 1);
+        assert_eq!(&*mem.read(512 + 8, 2).unwrap(), &0u16.to_le_bytes());
+        assert_eq!(mem.read_i64(544).unwrap(), 2);
+        assert_eq!(&*mem.read(544 + 8, 2).unwrap(), &errno::BADF.to_le_bytes());
+        assert_eq!(clock.elapsed(), 2_000_000);
+
+        assert_eq!(call(&mut ctx, WasiFunc::PollOneoff, &poll(0), &mut mem), errno::INVAL as i32);
+    }
+
+    #[test]
+    fn test_poll_oneoff_interrupt() {
+        let mut ctx = AwwasmWasiCtx::new();
+        let handle = ctx.interrupt_handle();
+        let mut mem = AwwasmMemInst::new(AwwasmMemoryType::new(1, None));
+        subscription(&mut mem, 0, 1, 0, 1, 60_000_000_000);
+
+        let waker = std::thread::spawn(move || {
+            std::thread::sleep(std::time::Duration::from_millis(20));
+            handle.interrupt();
+        });
+        let args = [AwwasmValue::I32(0), AwwasmValue::I32(512), AwwasmValue::I32(1), AwwasmValue::I32(1024)];
+        let id = ctx.host_func_id(WasiFunc::PollOneoff);
+        assert_eq!(ctx.call(id, &args, Some(&mut mem)), Err(AwwasmTrap::Interrupted));
+        waker.join().unwrap();
+    }
+
+    #[test]
+    fn test_seeded_random() {
+        let run = |seed| {
+            let mut ctx = AwwasmWasiCtx::builder().random(SeededRng::new(seed)).build().unwrap();
+            let mut mem = AwwasmMemInst::new(AwwasmMemoryType::new(1, None));
+            let args = [AwwasmValue::I32(8), AwwasmValue::I32(13)];
+            assert_eq!(call(&mut ctx, WasiFunc::RandomGet, &args, &mut mem), 0);
+            mem.read(0, 32).unwrap().to_vec()
+        };
+
+        let a = run(42);
+        assert_eq!(a, run(42));
+        assert_ne!(a, run(43));
+        assert!(a[..8].iter().all(|&b| b == 0));
+        assert!(a[8..21].iter().any(|&b| b != 0));
+        assert!(a[21..].iter().all(|&b| b == 0));
+
+        let mut ctx = AwwasmWasiCtx::builder().random(SeededRng::new(42)).build().unwrap();
+        let mut mem = AwwasmMemInst::new(AwwasmMemoryType::new(1, None));
+        let args = [AwwasmValue::I32(8), AwwasmValue::I32(-1)];
+        assert_eq!(call(&mut ctx, WasiFunc::RandomGet, &args, &mut mem), errno::FAULT as i32);
+    }
+
+    #[test]
+    fn test_proc_exit_and_bad_calls() {
+        let mut ctx = AwwasmWasiCtx::new();
+        let id = ctx.host_func_id(WasiFunc::ProcExit);
+        let trap = ctx.call(id, &[AwwasmValue::I32(3)], None).unwrap_err();
+        assert_eq!(trap, AwwasmTrap::Exit(ExitStatus(3)));
+        assert_eq!(trap.exit_status().map(|s| s.code()), Some(3));
+        assert_eq!(AwwasmTrap::Unreachable.exit_status(), None);
+
+        let bad = ctx.call(1, &[], None);
+        assert_eq!(bad, Err(AwwasmTrap::InvalidHostCall { host_func_id: 1 }));
+
+        let mut mem = AwwasmMemInst::new(AwwasmMemoryType::new(1, None));
+        let id = ctx.host_func_id(WasiFunc::FdWrite);
+        assert!(ctx.call(id, &[AwwasmValue::I64(1)], Some(&mut mem)).is_err());
+    }
+
+    #[test]
+    fn test_stdio_capture_and_pipe() {
+        let mut ctx = AwwasmWasiCtx::new();
+        ctx.set_stdout(WasiOutput::capture());
+        ctx.set_stderr(WasiOutput::Null);
+        ctx.set_stdin(WasiInput::bytes("ping"));
+
+        let mut mem = AwwasmMemInst::new(AwwasmMemoryType::new(1, None));
+        mem.write(100, b"hello ").unwrap();
+        mem.write(200, b"world").unwrap();
+        // Two iovecs at offset 0: (100, 6), (200, 5)
+        for (i, v) in [100, 6, 200, 5].iter().enumerate() {
+            mem.write_i32(i as u32 * 4, *v).unwrap();
+        }
+
+        let write = |fd| [AwwasmValue::I32(fd), AwwasmValue::I32(0), AwwasmValue::I32(2), AwwasmValue::I32(32)];
+        assert_eq!(call(&mut ctx, WasiFunc::FdWrite, &write(1), &mut mem), 0);
+        assert_eq!(mem.read_i32(32).unwrap(), 11);
+        assert_eq!(call(&mut ctx, WasiFunc::FdWrite, &write(2), &mut mem), 0);
+        assert_eq!(ctx.take_stdout(), b"hello world");
+        assert!(ctx.take_stdout().is_empty());
+
+        // Aliasing iovecs are written once each, larger than one copy chunk
+        mem.write(0x1000, &[b'x'; 5000]).unwrap();
+        for i in 0..3 {
+            mem.write_i32(0x800 + i * 8, 0x1000).unwrap();
+            mem.write_i32(0x804 + i * 8, 5000).unwrap();
+        }
+        let alias = [AwwasmValue::I32(1), AwwasmValue::I32(0x800), AwwasmValue::I32(3), AwwasmValue::I32(32)];
+        assert_eq!(call(&mut ctx, WasiFunc::FdWrite, &alias, &mut mem), 0);
+        assert_eq!(mem.read_i32(32).unwrap(), 15000);
+        assert_eq!(ctx.take_stdout(), [b'x'; 15000]);
+        let bad_fd = [AwwasmValue::I32(0), AwwasmValue::I32(0x800), AwwasmValue::I32(0), AwwasmValue::I32(32)];
+        assert_eq!(call(&mut ctx, WasiFunc::FdWrite, &bad_fd, &mut mem), errno::BADF as i32);
+        assert!(ctx.take_stderr().is_empty());
+
+        // Read stdin into a single 16-byte iovec at 300
+        mem.write_i32(40, 300).unwrap();
+        mem.write_i32(44, 16).unwrap();
+        let read = [AwwasmValue::I32(0), AwwasmValue::I32(40), AwwasmValue::I32(1), AwwasmValue::I32(48)];
+        assert_eq!(call(&mut ctx, WasiFunc::FdRead, &read, &mut mem), 0);
+        assert_eq!(mem.read_i32(48).unwrap(), 4);
+        assert_eq!(&*mem.read(300, 4).unwrap(), b"ping");
+
+        // An iovec reaching past memory faults without consuming input
+        ctx.set_stdin(WasiInput::bytes("pong"));
+        mem.write_i32(44, -1).unwrap();
+        assert_eq!(call(&mut ctx, WasiFunc::FdRead, &read, &mut mem), errno::FAULT as i32);
+        mem.write_i32(44, 16).unwrap();
+        assert_eq!(call(&mut ctx, WasiFunc::FdRead, &read, &mut mem), 0);
+        assert_eq!(&*mem.read(300, 4).unwrap(), b"pong");
+    }
+
+    fn scratch_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("awwasm-wasi-{}-{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(dir.join("sandbox/sub")).unwrap();
+        dir
+    }
+
+    #[test]
+    fn test_preopen_prestat() {
+        let dir = scratch_dir("prestat");
+        let mut ctx = AwwasmWasiCtx::new();
+        let fd = ctx.preopen_dir(dir.join("sandbox"), "/data").unwrap();
+        assert_eq!(fd, 3);
+        assert!(ctx.preopen_dir(dir.join("missing"), "/x").is_err());
+
+        let mut mem = AwwasmMemInst::new(AwwasmMemoryType::new(1, None));
+        let get = [AwwasmValue::I32(3), AwwasmValue::I32(0)];
+        assert_eq!(call(&mut ctx, WasiFunc::FdPrestatGet, &get, &mut mem), 0);
+        assert_eq!(mem.read_i32(4).unwrap(), 5);
+
+        let name = [AwwasmValue::I32(3), AwwasmValue::I32(16), AwwasmValue::I32(5)];
+        assert_eq!(call(&mut ctx, WasiFunc::FdPrestatDirName, &name, &mut mem), 0);
+        assert_eq!(&*mem.read(16, 5).unwrap(), b"/data");
+
+        let stdout = [AwwasmValue::I32(1), AwwasmValue::I32(0)];
+        assert_eq!(call(&mut ctx, WasiFunc::FdPrestatGet, &stdout, &mut mem), errno::BADF as i32);
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_path_open_sandbox() {
+        let dir = scratch_dir("sandbox");
+        std::fs::write(dir.join("secret.txt"), b"top secret").unwrap();
+        let mut ctx = AwwasmWasiCtx::new();
+        let root = ctx.preopen_dir(dir.join("sandbox"), ".").unwrap();
+
+        let rw = RIGHTS_FD_READ | RIGHTS_FD_WRITE;
+        let fd = ctx.path_open(root, "sub/../note.txt", OFLAGS_CREAT, rw, 0).unwrap();
+        assert!(matches!(ctx.fd(fd), Some(WasiFd::File(_))));
+        assert!(dir.join("sandbox/note.txt").exists());
+
+        let sub = ctx.path_open(root, "sub", OFLAGS_DIRECTORY, 0, 0).unwrap();
+        assert!(matches!(ctx.fd(sub), Some(WasiFd::Dir { preopen: None, .. })));
+        assert_eq!(ctx.path_open(sub, "../note.txt", 0, RIGHTS_FD_READ, 0), Err(errno::NOTCAPABLE));
+
+        assert_eq!(ctx.path_open(root, "../secret.txt", 0, RIGHTS_FD_READ, 0), Err(errno::NOTCAPABLE));
+        assert_eq!(ctx.path_open(root, "sub/../../secret.txt", 0, RIGHTS_FD_READ, 0), Err(errno::NOTCAPABLE));
+        assert_eq!(ctx.path_open(root, "/etc/passwd", 0, RIGHTS_FD_READ, 0), Err(errno::NOTCAPABLE));
+        assert_eq!(ctx.path_open(root, "missing.txt", 0, RIGHTS_FD_READ, 0), Err(errno::NOENT));
+        assert_eq!(ctx.path_open(1, "note.txt", 0, RIGHTS_FD_READ, 0), Err(errno::NOTDIR));
+
+        #[cfg(unix)]
+        {
+            std::os::unix::fs::symlink(dir.join("secret.txt"), dir.join("sandbox/link")).unwrap();
+            assert_eq!(ctx.path_open(root, "link", 0, RIGHTS_FD_READ, 0), Err(errno::NOTCAPABLE));
+            std::os::unix::fs::symlink("note.txt", dir.join("sandbox/inside")).unwrap();
+            assert!(ctx.path_open(root, "inside", 0, RIGHTS_FD_READ, 0).is_ok());
+            // What gets opened is the checked target, not the link again.
+            let note = dir.join("sandbox/note.txt").canonicalize().unwrap();
+            assert_eq!(fs::resolve(&dir.join("sandbox"), "inside"), Ok(note.clone()));
+            assert_eq!(fs::resolve(&dir.join("sandbox"), "sub/new.txt"), Ok(note.with_file_name("sub").join("new.txt")));
+
+            // A dangling link out of the sandbox must not be created through
+            std::os::unix::fs::symlink(dir.join("planted.txt"), dir.join("sandbox/dangling")).unwrap();
+            assert_eq!(ctx.path_open(root, "dangling", OFLAGS_CREAT, rw, 0), Err(errno::NOTCAPABLE));
+            assert!(!dir.join("planted.txt").exists());
+        }
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_instantiate_with_wasi_imports() {
+        let wasm = wat::parse_str(r#"
+            (module
+                (import "wasi_snapshot_preview1" "fd_write"
+                    (func (param i32 i32 i32 i32) (result i32)))
+                (import "wasi_snapshot_preview1" "proc_exit" (func (param i32)))
+                (memory (export "memory") 1)
+            )
+        "#).unwrap();
+        let mut module = AwwasmModule::new(&wasm).unwrap();
+        module.resolve_all_sections().unwrap();
+
+        let ctx = AwwasmWasiCtx::new();
+        let mut imports = AwwasmImports::new();
+        ctx.add_to_imports(&mut imports);
+
+        let mut store = AwwasmStore::new();
+        let addr = store.store_init(&module, &mut imports).unwrap();
+        let inst = store.module(addr).unwrap();
+        assert_eq!(inst.funcaddrs.len(), 2);
+
+        match store.func(inst.funcaddrs[0]).unwrap() {
+            AwwasmFuncInst::Host(host) => {
+                assert_eq!(ctx.lookup(host.host_func_id), Some(WasiFunc::FdWrite));
+            }
+            _ => panic!("expected host function"),
+        }
+    }
+
+    #[test]
+    fn test_run_command() {
+        let wasm = wat::parse_str(r#"
+            (module
+                (import "wasi_snapshot_preview1" "fd_write"
+                    (func $fd_write (param i32 i32 i32 i32) (result i32)))
+                (import "wasi_snapshot_preview1" "proc_exit" (func $proc_exit (param i32)))
+                (memory (export "memory") 1)
+                (data (i32.const 16) "hello\n")
+                (func $init (i32.store8 (i32.const 16) (i32.const 72)))
+                (start $init)
+                (func (export "_start")
+                    (i32.store (i32.const 0) (i32.const 16))
+                    (i32.store (i32.const 4) (i32.const 6))
+                    (drop (call $fd_write (i32.const 1) (i32.const 0) (i32.const 1) (i32.const 8)))
+                    (call $proc_exit (i32.load (i32.const 8))))
+            )
+        "#).unwrap();
+        let mut module = AwwasmModule::new(&wasm).unwrap();
+        module.resolve_all_sections().unwrap();
+
+        let mut ctx = AwwasmWasiCtx::builder().stdout(WasiOutput::capture()).build().unwrap();
+        let mut store = AwwasmStore::new();
+        let status = crate::run_command(&mut store, &module, &mut ctx).unwrap();
+        // The start function ran before `_start`; the guest exits with
+        // the byte count fd_write reported.
+        assert_eq!(status, ExitStatus(6));
+        assert_eq!(ctx.take_stdout(), b"Hello\n");
+
+        let wasm = wat::parse_str("(module (func (export \"main\")))").unwrap();
+        let mut module = AwwasmModule::new(&wasm).unwrap();
+        module.resolve_all_sections().unwrap();
+        let mut store = AwwasmStore::new();
+        assert!(matches!(
+            crate::run_command(&mut store, &module, &mut ctx),
+            Err(crate::AwwasmRunError::Runtime(crate::AwwasmRuntimeError::ExportNotFound(_)))
+        ));
+    }
+
+    #[test]
+    fn test_wasi_threads_spawn() {
+        let wasm = wat::parse_str(r#"
+            (module
+                (import "env" "memory" (memory 1 1 shared))
+                (import "wasi" "thread-spawn" (func $spawn (param i32) (result i32)))
+                (global $started (mut i32) (i32.const 0))
+                (func $init (global.set $started (i32.const 1)))
+                (start $init)
+                (func (export "wasi_thread_start") (param $tid i32) (param $arg i32)
+                    (i32.store (i32.const 12) (global.get $started))
+                    (i32.store (i32.const 8) (local.get $tid))
+                    (i32.store (i32.const 0) (local.get $arg))
+                    (drop (memory.atomic.notify (i32.const 0) (i32.const 1))))
+                (func (export "_start")
+                    (i32.store (i32.const 4) (call $spawn (i32.const 42)))
+                    ;; Wait for the thread's store; "not-equal" if it already happened.
+                    (drop (memory.atomic.wait32 (i32.const 0) (i32.const 0) (i64.const -1))))
+            )
+        "#).unwrap();
+        let memory = AwwasmMemInst::new(AwwasmMemoryType::new(1, Some(1)).with_shared(true));
+        let mut threads = AwwasmWasiThreads::new(wasm, memory, AwwasmWasiCtx::new);
+        assert_eq!(threads.run().unwrap(), ExitStatus(0));
+
+        let mem = threads.memory();
+        assert_eq!(mem.read_i32(0).unwrap(), 42);
+        // The spawned thread saw the id `thread-spawn` returned.
+        assert_eq!(mem.read_i32(4).unwrap(), 1);
+        assert_eq!(mem.read_i32(8).unwrap(), 1);
+        // Its instance ran the start function first.
+        assert_eq!(mem.read_i32(12).unwrap(), 1);
+    }
+}
@@ -0,0 +1,73 @@
+//! Entropy sources for `random_get`.
+
+use std::fmt;
+use std::io;
+use std::sync::Arc;
+
+use crate::platform::AwwasmHostPlatform;
+
+/// Source of the bytes handed out by `random_get`.
+pub trait WasiEntropy: Send + fmt::Debug {
+    /// Fill `buf` completely with random bytes.
+    fn fill(&mut self, buf: &mut [u8]) -> io::Result<()>;
+}
+
+/// Operating-system entropy (the default).
+#[derive(Debug, Clone, Copy, Default)]
+pub struct OsEntropy;
+
+impl WasiEntropy for OsEntropy {
+    fn fill(&mut self, buf: &mut [u8]) -> io::Result<()> {
+        getrandom::getrandom(buf).map_err(io::Error::from)
+    }
+}
+
+/// Entropy from the embedder's platform.
+#[derive(Debug, Clone)]
+pub struct PlatformEntropy(pub Arc<dyn AwwasmHostPlatform>);
+
+impl WasiEntropy for PlatformEntropy {
+    fn fill(&mut self, buf: &mut [u8]) -> io::Result<()> {
+        if self.0.fill_entropy(buf) {
+            Ok(())
+        } else {
+            Err(io::Error::new(io::ErrorKind::Unsupported, "platform has no entropy source"))
+        }
+    }
+}
+
+/// Deterministic generator seeded by the embedder.
+///
+/// Two contexts created with the same seed hand out the same byte
+/// stream, which makes simulations and replicated executions
+/// reproducible. This is SplitMix64: fast and well distributed, but
+/// **not** cryptographically secure.
+#[derive(Debug, Clone)]
+pub struct SeededRng {
+    state: u64,
+}
+
+impl SeededRng {
+    /// Create a generator from a 64-bit seed.
+    pub fn new(seed: u64) -> Self {
+        Self { state: seed }
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        self.state = self.state.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        let mut z = self.state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^ (z >> 31)
+    }
+}
+
+impl WasiEntropy for SeededRng {
+    fn fill(&mut self, buf: &mut [u8]) -> io::Result<()> {
+        for chunk in buf.chunks_mut(8) {
+            let bytes = self.next_u64().to_le_bytes();
+            chunk.copy_from_slice(&bytes[..chunk.len()]);
+        }
+        Ok(())
+    }
+}
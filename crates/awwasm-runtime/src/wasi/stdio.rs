@@ -0,0 +1,103 @@
+//! Routing of the guest's stdin, stdout and stderr.
+
+use std::fmt;
+use std::io::{self, Cursor, Read, Write};
+
+/// Destination for a guest output stream (stdout or stderr).
+#[derive(Default)]
+pub enum WasiOutput {
+    /// Write to the corresponding stream of the host process.
+    #[default]
+    Inherit,
+    /// Collect into an in-memory buffer, retrieved with
+    /// `AwwasmWasiCtx::take_stdout` / `take_stderr`.
+    Capture(Vec<u8>),
+    /// Forward to an embedder-supplied writer.
+    Custom(Box<dyn Write + Send>),
+    /// Discard everything.
+    Null,
+}
+
+/// Source for the guest's stdin.
+#[derive(Default)]
+pub enum WasiInput {
+    /// Read from the host process's stdin.
+    #[default]
+    Inherit,
+    /// Serve a fixed byte buffer, then end-of-file.
+    Bytes(Cursor<Vec<u8>>),
+    /// Read from an embedder-supplied reader.
+    Custom(Box<dyn Read + Send>),
+    /// Always at end-of-file.
+    Null,
+}
+
+impl WasiOutput {
+    /// Start capturing into an empty buffer.
+    pub fn capture() -> Self {
+        WasiOutput::Capture(Vec::new())
+    }
+
+    /// Write all of `bytes` to the destination. `is_stderr` selects the
+    /// inherited host stream.
+    pub(crate) fn write_all(&mut self, bytes: &[u8], is_stderr: bool) -> io::Result<()> {
+        match self {
+            WasiOutput::Inherit if is_stderr => io::stderr().write_all(bytes),
+            WasiOutput::Inherit => io::stdout().write_all(bytes),
+            WasiOutput::Capture(buf) => {
+                buf.extend_from_slice(bytes);
+                Ok(())
+            }
+            WasiOutput::Custom(writer) => writer.write_all(bytes),
+            WasiOutput::Null => Ok(()),
+        }
+    }
+
+    /// Take the captured bytes, leaving the buffer empty.
+    ///
+    /// Returns an empty vector for non-capturing destinations.
+    pub(crate) fn take(&mut self) -> Vec<u8> {
+        match self {
+            WasiOutput::Capture(buf) => core::mem::take(buf),
+            _ => Vec::new(),
+        }
+    }
+}
+
+impl WasiInput {
+    /// Serve `bytes` as the guest's stdin.
+    pub fn bytes(bytes: impl Into<Vec<u8>>) -> Self {
+        WasiInput::Bytes(Cursor::new(bytes.into()))
+    }
+
+    pub(crate) fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        match self {
+            WasiInput::Inherit => io::stdin().read(buf),
+            WasiInput::Bytes(cursor) => cursor.read(buf),
+            WasiInput::Custom(reader) => reader.read(buf),
+            WasiInput::Null => Ok(0),
+        }
+    }
+}
+
+impl fmt::Debug for WasiOutput {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            WasiOutput::Inherit => write!(f, "Inherit"),
+            WasiOutput::Capture(buf) => write!(f, "Capture({} bytes)", buf.len()),
+            WasiOutput::Custom(_) => write!(f, "Custom"),
+            WasiOutput::Null => write!(f, "Null"),
+        }
+    }
+}
+
+impl fmt::Debug for WasiInput {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            WasiInput::Inherit => write!(f, "Inherit"),
+            WasiInput::Bytes(cursor) => write!(f, "Bytes({} bytes)", cursor.get_ref().len()),
+            WasiInput::Custom(_) => write!(f, "Custom"),
+            WasiInput::Null => write!(f, "Null"),
+        }
+    }
+}
@@ -0,0 +1,216 @@
+//! `wasi-threads`: guest threads on host threads.
+//!
+//! A guest built for wasi-threads (Rust's `wasm32-wasip1-threads`, or C
+//! with `-pthread`) imports a shared memory as `env.memory` and a spawn
+//! function as `wasi.thread-spawn`, and exports
+//! `wasi_thread_start(tid, start_arg)`. `AwwasmWasiThreads` serves the
+//! spawn import: each call instantiates the module again in a fresh store
+//! on a new host thread, with the same shared memory, and runs
+//! `wasi_thread_start` there.
+//!
+//! Every thread's instance is a full instantiation: active data segments
+//! are copied into the shared memory again and the start function runs
+//! again, before `wasi_thread_start`. The toolchains above emit passive
+//! segments for threaded guests and initialize memory from a start
+//! function guarded by a flag in the shared memory, so that happens only
+//! once; a module that initializes memory any other way will have it
+//! overwritten each time a thread is spawned.
+
+use std::fmt;
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread::JoinHandle;
+
+use crate::error::{AwwasmHostPanic, AwwasmRuntimeError, AwwasmTrap, ExitStatus};
+use crate::func::{AwwasmFuncInst, AwwasmHost};
+use crate::imports::AwwasmImports;
+use crate::memory::AwwasmMemInst;
+use crate::runner::AwwasmRunError;
+use crate::store::AwwasmStore;
+use crate::values::AwwasmValue;
+
+use super::{errno, AwwasmWasiCtx, WasiFunc};
+
+/// Import module of `thread-spawn`.
+pub const WASI_THREADS_MODULE: &[u8] = b"wasi";
+
+/// Import name of the spawn function: `(param i32) (result i32)`.
+pub const THREAD_SPAWN: &[u8] = b"thread-spawn";
+
+/// Export every spawned thread starts in: `(param i32 i32)`.
+pub const THREAD_START: &str = "wasi_thread_start";
+
+/// Largest thread id the convention allows.
+const MAX_TID: u32 = 0x1fff_ffff;
+
+/// State every thread of one guest process shares.
+struct AwwasmThreadGroup {
+    wasm: Arc<[u8]>,
+    memory: AwwasmMemInst,
+    new_ctx: Box<dyn Fn() -> AwwasmWasiCtx + Send + Sync>,
+    next_tid: AtomicU32,
+    threads: Mutex<Vec<JoinHandle<Result<(), AwwasmRunError>>>>,
+}
+
+/// A wasi-threads guest process.
+///
+/// Each thread, the main one included, gets its own `AwwasmWasiCtx`
+/// from the factory passed to `new`, so file descriptors opened by one
+/// thread are not visible to the others.
+pub struct AwwasmWasiThreads {
+    group: Arc<AwwasmThreadGroup>,
+    ctx: AwwasmWasiCtx,
+}
+
+impl fmt::Debug for AwwasmWasiThreads {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("AwwasmWasiThreads")
+            .field("memory", &self.group.memory)
+            .field("next_tid", &self.group.next_tid)
+            .field("ctx", &self.ctx)
+            .finish_non_exhaustive()
+    }
+}
+
+impl AwwasmWasiThreads {
+    /// A process running the module `wasm` over `memory`, which should
+    /// be shared (`AwwasmMemoryType::with_shared`) to match the guest's
+    /// import. `new_ctx` creates the WASI context of every thread.
+    pub fn new(
+        wasm: impl Into<Arc<[u8]>>,
+        memory: AwwasmMemInst,
+        new_ctx: impl Fn() -> AwwasmWasiCtx + Send + Sync + 'static,
+    ) -> Self {
+        let ctx = new_ctx();
+        let group = AwwasmThreadGroup {
+            wasm: wasm.into(),
+            memory,
+            new_ctx: Box::new(new_ctx),
+            next_tid: AtomicU32::new(1),
+            threads: Mutex::new(Vec::new()),
+        };
+        Self { group: Arc::new(group), ctx }
+    }
+
+    /// The shared memory every thread runs on.
+    pub fn memory(&self) -> &AwwasmMemInst {
+        &self.group.memory
+    }
+
+    /// This thread's WASI context, e.g. to take captured output.
+    pub fn ctx_mut(&mut self) -> &mut AwwasmWasiCtx {
+        &mut self.ctx
+    }
+
+    /// The `host_func_id` of `thread-spawn`: the first id after the
+    /// WASI functions.
+    pub fn spawn_func_id(&self) -> u32 {
+        self.ctx.base_id + WasiFunc::ALL.len() as u32
+    }
+
+    /// Register the WASI functions, `thread-spawn` and the shared memory.
+    pub fn add_to_imports<'a>(&self, imports: &mut AwwasmImports<'a>) {
+        self.ctx.add_to_imports(imports);
+        imports.add_func(WASI_THREADS_MODULE, THREAD_SPAWN, AwwasmFuncInst::host(0, self.spawn_func_id()));
+        imports.add_memory(b"env", b"memory", self.group.memory.clone());
+    }
+
+    /// Run the main thread's `_start`, then wait for every spawned thread.
+    ///
+    /// A `proc_exit` on the main thread ends the run with its status
+    /// once the other threads finish; otherwise the first thread to exit
+    /// or fail decides the outcome.
+    pub fn run(&mut self) -> Result<ExitStatus, AwwasmRunError> {
+        let main = self.run_export("_start", &[]);
+        let threads = self.join();
+        match exit_status(main)? {
+            ExitStatus(0) => threads.map(|status| status.unwrap_or(ExitStatus(0))),
+            status => Ok(status),
+        }
+    }
+
+    /// Wait for every spawned thread, including ones spawned while
+    /// waiting. Returns the status of the first thread that called
+    /// `proc_exit`, or the first failure.
+    pub fn join(&self) -> Result<Option<ExitStatus>, AwwasmRunError> {
+        let mut outcome = Ok(None);
+        loop {
+            let next = self.group.threads.lock().unwrap_or_else(|e| e.into_inner()).pop();
+            let Some(handle) = next else { break };
+            let result = handle
+                .join()
+                .unwrap_or_else(|payload| {
+                    let trap = AwwasmTrap::host(AwwasmHostPanic::from_payload(payload));
+                    Err(AwwasmRuntimeError::Trap(trap).into())
+                });
+            if let Ok(None) = outcome {
+                outcome = exit_status(result).map(|status| (status != ExitStatus(0)).then_some(status));
+            }
+        }
+        outcome
+    }
+
+    /// Instantiate the module in a fresh store and call `name`.
+    fn run_export(&mut self, name: &str, args: &[AwwasmValue]) -> Result<(), AwwasmRunError> {
+        let group = self.group.clone();
+        let mut imports = AwwasmImports::new();
+        self.add_to_imports(&mut imports);
+        let mut store = AwwasmStore::new();
+        let addr = store.instantiate_bytes(&group.wasm, &mut imports)?;
+        if let Some(start) = store.module(addr).and_then(|inst| inst.start) {
+            store.invoke(start, &[], self)?;
+        }
+        store.invoke_export(addr, name, args, self)?;
+        Ok(())
+    }
+
+    /// `thread-spawn`: the new thread's id, or a negated errno.
+    fn spawn(&self, start_arg: i32) -> i32 {
+        let tid = self.group.next_tid.fetch_add(1, Ordering::Relaxed);
+        if tid > MAX_TID {
+            return -i32::from(errno::AGAIN);
+        }
+        let mut child = AwwasmWasiThreads { group: self.group.clone(), ctx: (self.group.new_ctx)() };
+        let args = [AwwasmValue::I32(tid as i32), AwwasmValue::I32(start_arg)];
+        let spawned = std::thread::Builder::new()
+            .name(format!("wasi-thread-{}", tid))
+            .spawn(move || child.run_export(THREAD_START, &args));
+        match spawned {
+            Ok(handle) => {
+                self.group.threads.lock().unwrap_or_else(|e| e.into_inner()).push(handle);
+                tid as i32
+            }
+            Err(_) => -i32::from(errno::AGAIN),
+        }
+    }
+}
+
+impl AwwasmHost for AwwasmWasiThreads {
+    fn call(
+        &mut self,
+        host_func_id: u32,
+        args: &[AwwasmValue],
+        mem: Option<&mut AwwasmMemInst>,
+    ) -> Result<Vec<AwwasmValue>, AwwasmTrap> {
+        if host_func_id != self.spawn_func_id() {
+            return self.ctx.call(host_func_id, args, mem);
+        }
+        let start_arg = args
+            .first()
+            .and_then(AwwasmValue::as_i32)
+            .ok_or(AwwasmTrap::InvalidHostCall { host_func_id })?;
+        Ok(vec![AwwasmValue::I32(self.spawn(start_arg))])
+    }
+}
+
+/// Map a thread's outcome to an exit status, as `run_command` does.
+fn exit_status(result: Result<(), AwwasmRunError>) -> Result<ExitStatus, AwwasmRunError> {
+    match result {
+        Ok(()) => Ok(ExitStatus(0)),
+        Err(AwwasmRunError::Runtime(e)) => match e.trap() {
+            Some(AwwasmTrap::Exit(status)) => Ok(*status),
+            _ => Err(AwwasmRunError::Runtime(e)),
+        },
+        Err(e) => Err(e),
+    }
+}